@@ -0,0 +1,125 @@
+//! Throughput benchmarks: `cargo bench`, or `cargo bench -- <group>` for one group
+//!
+//! Workloads come from `payments_engine::workload` with fixed seeds, so runs
+//! are comparable across commits.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use payments_engine::concurrent_engine::ShardedEngine;
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::models::ClientId;
+use payments_engine::pipeline::{self, PipelineOptions};
+use payments_engine::read_transactions;
+use payments_engine::workload::{generate, generate_csv, WorkloadOptions};
+
+/// Transactions per benchmark iteration
+const TRANSACTIONS: usize = 100_000;
+const CLIENTS: ClientId = 1_000;
+/// Sharded runs pay a round trip through a shard task per transaction
+const SHARDED_TRANSACTIONS: usize = 20_000;
+
+/// Apply a mixed workload to one in-memory engine
+fn single_engine(c: &mut Criterion) {
+    let mut group = c.benchmark_group("single_engine");
+    group.throughput(Throughput::Elements(TRANSACTIONS as u64));
+
+    let workloads = [
+        ("deposits", WorkloadOptions::deposits(TRANSACTIONS, CLIENTS)),
+        (
+            "mixed",
+            WorkloadOptions {
+                transactions: TRANSACTIONS,
+                clients: CLIENTS,
+                ..WorkloadOptions::default()
+            },
+        ),
+    ];
+    for (name, options) in workloads {
+        let transactions = generate(&options);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut engine = PaymentsEngine::new();
+                for tx in transactions.iter().cloned() {
+                    engine.process_transaction(tx);
+                }
+                black_box(engine)
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Feed a mixed workload through `pipeline::ingest` into sharded engines
+fn sharded_engine(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sharded_engine");
+    group.throughput(Throughput::Elements(SHARDED_TRANSACTIONS as u64));
+    group.sample_size(20);
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let csv = generate_csv(&WorkloadOptions {
+        transactions: SHARDED_TRANSACTIONS,
+        clients: CLIENTS,
+        ..WorkloadOptions::default()
+    });
+
+    for shards in [1, 2, 4, 8] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(shards),
+            &shards,
+            |b, &shards| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let engine = ShardedEngine::new(shards);
+                        let stats = pipeline::ingest(&engine, &csv[..], PipelineOptions::default())
+                            .await
+                            .unwrap();
+                        black_box(stats)
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// A third of the stream is disputes and their follow-ups
+fn dispute_heavy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispute_heavy");
+    group.throughput(Throughput::Elements(TRANSACTIONS as u64));
+
+    let transactions = generate(&WorkloadOptions::dispute_heavy(TRANSACTIONS, CLIENTS));
+    group.bench_function("single_engine", |b| {
+        b.iter(|| {
+            let mut engine = PaymentsEngine::new();
+            for tx in transactions.iter().cloned() {
+                engine.process_transaction(tx);
+            }
+            black_box(engine)
+        })
+    });
+    group.finish();
+}
+
+/// Parse CSV into transactions without applying them
+fn csv_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("csv_parse");
+    let csv = generate_csv(&WorkloadOptions {
+        transactions: TRANSACTIONS,
+        clients: CLIENTS,
+        ..WorkloadOptions::default()
+    });
+    group.throughput(Throughput::Bytes(csv.len() as u64));
+
+    group.bench_function("read_transactions", |b| {
+        b.iter(|| black_box(read_transactions(&csv[..]).count()))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    single_engine,
+    sharded_engine,
+    dispute_heavy,
+    csv_parse
+);
+criterion_main!(benches);
@@ -0,0 +1,334 @@
+#[cfg(feature = "tolerant-amounts")]
+use alloc::string::String;
+use alloc::string::ToString;
+use core::fmt;
+use core::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use core::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+
+/// Monetary amount used throughout the engine
+///
+/// `rust_decimal::Decimal` by default. With the `fixed-point` feature it is
+/// `FixedAmount`, an `i64` count of 1/10000 units: cheaper arithmetic for
+/// very high volumes, at the cost of rejecting amounts with more than four
+/// significant decimal places and of a smaller range. Both serialize as
+/// decimal strings, so state files and snapshots work with either.
+#[cfg(not(feature = "fixed-point"))]
+pub type Amount = Decimal;
+
+/// Monetary amount used throughout the engine, see `FixedAmount`
+#[cfg(feature = "fixed-point")]
+pub type Amount = FixedAmount;
+
+/// Numeric representation of money that accounts and the rules in
+/// `transition` work with
+///
+/// Implemented for `Decimal` and `FixedAmount`. `Account`, `StoredTransaction`,
+/// `Hold` and the `transition` functions are generic over it, defaulting to
+/// `Amount`, so a simulation or embedded caller can pick a representation at
+/// compile time, or use both in one program, with the same rules. The
+/// engine's storage, snapshots and servers use `Amount`, chosen by the
+/// `fixed-point` feature.
+///
+/// Arithmetic is expected to panic on overflow, as both implementations do.
+pub trait Monetary:
+    Copy
+    + PartialOrd
+    + fmt::Debug
+    + fmt::Display
+    + FromStr<Err: fmt::Display>
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + AddAssign
+    + SubAssign
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+}
+
+impl Monetary for Decimal {
+    const ZERO: Self = Decimal::ZERO;
+}
+
+impl Monetary for FixedAmount {
+    const ZERO: Self = FixedAmount::ZERO;
+}
+
+/// Largest exponent `normalize` accepts either way, `Decimal`'s maximum scale
+#[cfg(feature = "tolerant-amounts")]
+const MAX_EXPONENT: i32 = 28;
+
+/// Rewrite an amount written with thousands separators or in scientific
+/// notation as a plain decimal, e.g. `+1,250.5` or `1.2505E3` as `1250.5`
+///
+/// Separators must be commas between groups of three digits before the
+/// decimal point. `None` if `text` is already plain or is malformed either
+/// way, which leaves it to the amount's own parsing to accept or reject.
+///
+/// ```
+/// use payments_engine_core::amount::normalize;
+///
+/// assert_eq!(normalize("-1,000,000.25").as_deref(), Some("-1000000.25"));
+/// assert_eq!(normalize("2.5e-3").as_deref(), Some("0.0025"));
+/// assert_eq!(normalize("12.5"), None);
+/// assert_eq!(normalize("1,00"), None);
+/// ```
+#[cfg(feature = "tolerant-amounts")]
+pub fn normalize(text: &str) -> Option<String> {
+    let (sign, unsigned) = match text.as_bytes().first() {
+        Some(b'-') => ("-", &text[1..]),
+        Some(b'+') => ("", &text[1..]),
+        _ => ("", text),
+    };
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => {
+            let exponent = exponent.parse::<i32>().ok()?;
+            (
+                mantissa,
+                Some(exponent).filter(|e| e.abs() <= MAX_EXPONENT)?,
+            )
+        }
+        None if unsigned.contains(',') => (unsigned, 0),
+        None => return None,
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let grouped = whole.contains(',');
+    let mut digits = String::with_capacity(whole.len() + fraction.len());
+    for (index, group) in whole.split(',').enumerate() {
+        let fits = match (grouped, index) {
+            (false, _) => true,
+            (true, 0) => (1..=3).contains(&group.len()),
+            (true, _) => group.len() == 3,
+        };
+        if !fits {
+            return None;
+        }
+        digits.push_str(group);
+    }
+    let whole_len = digits.len() as i32;
+    digits.push_str(fraction);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let point = whole_len + exponent;
+    let zeros = |count: i32| "0".repeat(count as usize);
+    let plain = if point <= 0 {
+        alloc::format!("0.{}{}", zeros(-point), digits)
+    } else if point as usize >= digits.len() {
+        alloc::format!("{}{}", digits, zeros(point - digits.len() as i32))
+    } else {
+        let (whole, fraction) = digits.split_at(point as usize);
+        alloc::format!("{}.{}", whole, fraction)
+    };
+    Some(alloc::format!("{}{}", sign, plain))
+}
+
+/// Amount stored as a whole number of 1/10000 units
+///
+/// Arithmetic panics on overflow, like `Decimal`'s; the range is about
+/// ±922 trillion. Parsing rejects amounts with a non-zero fifth decimal place
+/// rather than rounding them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FixedAmount(i64);
+
+impl FixedAmount {
+    /// Decimal places represented
+    pub const SCALE: u32 = 4;
+    pub const ZERO: Self = Self(0);
+    /// Minor units in one whole unit
+    const UNIT: i64 = 10_i64.pow(Self::SCALE);
+
+    /// Amount of `units` 1/10000ths
+    pub const fn from_minor_units(units: i64) -> Self {
+        Self(units)
+    }
+
+    /// Number of 1/10000ths in the amount
+    pub const fn minor_units(self) -> i64 {
+        self.0
+    }
+
+    /// Number of decimal places needed to write the amount, ignoring trailing zeros
+    pub fn decimal_places(self) -> u32 {
+        let mut fraction = (self.0 % Self::UNIT).abs();
+        if fraction == 0 {
+            return 0;
+        }
+        let mut places = Self::SCALE;
+        while fraction % 10 == 0 {
+            fraction /= 10;
+            places -= 1;
+        }
+        places
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+}
+
+impl Add for FixedAmount {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.checked_add(other).expect("amount overflow")
+    }
+}
+
+impl Sub for FixedAmount {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other).expect("amount overflow")
+    }
+}
+
+impl AddAssign for FixedAmount {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for FixedAmount {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for FixedAmount {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(self.0.checked_neg().expect("amount overflow"))
+    }
+}
+
+/// Written without trailing zeros, e.g. `1.5`, `-0.25`, `100`
+impl fmt::Display for FixedAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let unit = Self::UNIT.unsigned_abs();
+        let (whole, fraction) = (units / unit, units % unit);
+
+        let places = self.decimal_places();
+        if places == 0 {
+            return write!(f, "{}{}", sign, whole);
+        }
+        let fraction = fraction / 10_u64.pow(Self::SCALE - places);
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            whole,
+            fraction,
+            width = places as usize
+        )
+    }
+}
+
+/// Error parsing a `FixedAmount`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAmountError(&'static str);
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl core::error::Error for ParseAmountError {}
+
+impl FromStr for FixedAmount {
+    type Err = ParseAmountError;
+
+    /// Parse a plain decimal such as `12`, `-3.5` or `+0.0001`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+            return Err(ParseAmountError("invalid amount"));
+        }
+
+        let scale = Self::SCALE as usize;
+        let (kept, extra) = fraction.split_at(fraction.len().min(scale));
+        if extra.bytes().any(|b| b != b'0') {
+            return Err(ParseAmountError("amount has more than 4 decimal places"));
+        }
+
+        let overflow = ParseAmountError("amount out of range");
+        let whole: i64 = match whole {
+            "" => 0,
+            whole => whole.parse().map_err(|_| overflow.clone())?,
+        };
+        let fraction: i64 = match kept {
+            "" => 0,
+            kept => {
+                kept.parse::<i64>().expect("at most 4 digits")
+                    * 10_i64.pow((scale - kept.len()) as u32)
+            }
+        };
+
+        let units = whole
+            .checked_mul(Self::UNIT)
+            .and_then(|units| units.checked_add(fraction))
+            .ok_or(overflow)?;
+        Ok(Self(if negative { -units } else { units }))
+    }
+}
+
+impl From<FixedAmount> for Decimal {
+    fn from(amount: FixedAmount) -> Self {
+        Decimal::new(amount.0, FixedAmount::SCALE)
+    }
+}
+
+impl TryFrom<Decimal> for FixedAmount {
+    type Error = ParseAmountError;
+
+    /// Fails if `decimal` needs more than four decimal places or is out of range
+    fn try_from(decimal: Decimal) -> Result<Self, Self::Error> {
+        decimal.normalize().to_string().parse()
+    }
+}
+
+impl Serialize for FixedAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FixedAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(FixedAmountVisitor)
+    }
+}
+
+struct FixedAmountVisitor;
+
+impl Visitor<'_> for FixedAmountVisitor {
+    type Value = FixedAmount;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a decimal amount string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        value.trim().parse().map_err(E::custom)
+    }
+}
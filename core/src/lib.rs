@@ -0,0 +1,17 @@
+//! The engine's accounts, transactions and the rules for applying one to the
+//! other, without std
+//!
+//! Only `alloc` is needed unless the `schema` feature is on, so the models
+//! and `transition` can be reused on embedded targets or in deterministic
+//! simulations that drive the rules directly. `payments-engine` re-exports
+//! everything here and adds storage, duplicate detection, input and output on
+//! top.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod amount;
+pub mod models;
+pub mod outcome;
+pub mod transition;
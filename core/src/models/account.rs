@@ -0,0 +1,235 @@
+#[cfg(feature = "schema")]
+use alloc::borrow::Cow;
+
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+#[cfg(feature = "schema")]
+use utoipa::openapi::{RefOr, Schema};
+#[cfg(feature = "schema")]
+use utoipa::{PartialSchema, ToSchema};
+
+use crate::amount::{Amount, Monetary};
+use crate::models::transaction::{deserialize_optional_amount, ClientId};
+
+/// Account state, with balances in `A`
+#[derive(Debug, Clone)]
+pub struct Account<A = Amount> {
+    pub client_id: ClientId,
+    pub available: A,
+    pub held: A,
+    pub locked: bool,
+    /// Number of changes made to the account, starting at 0 when it opens
+    ///
+    /// Bumped by every applied deposit, withdrawal, dispute, resolve and
+    /// chargeback, so a caller that read the account can tell whether it has
+    /// changed since (see `PaymentsEngine::process_transaction_if_version`).
+    pub version: u64,
+}
+
+impl Account {
+    /// Create a new client account with zero balances
+    ///
+    /// For other amount representations, start from
+    /// `Account::with_balances(client_id, A::ZERO, A::ZERO, false)`.
+    pub fn new(client_id: ClientId) -> Self {
+        Self::with_balances(client_id, Amount::ZERO, Amount::ZERO, false)
+    }
+}
+
+impl<A: Monetary> Account<A> {
+    /// Account with the given balances, e.g. carried over from another ledger
+    ///
+    /// The version starts at 0 as for a new account.
+    pub fn with_balances(client_id: ClientId, available: A, held: A, locked: bool) -> Self {
+        Self {
+            client_id,
+            available,
+            held,
+            locked,
+            version: 0,
+        }
+    }
+
+    /// Get the total balance (available + held)
+    pub fn total(&self) -> A {
+        self.available + self.held
+    }
+
+    /// Deposit funds to available balance
+    /// Returns true if successful, false if account is locked
+    pub fn deposit(&mut self, amount: A) -> bool {
+        if self.locked {
+            return false;
+        }
+        self.available += amount;
+        self.version += 1;
+        true
+    }
+
+    /// Withdraw funds from available balance
+    /// Returns true if successful, false if insufficient funds or account is locked
+    pub fn withdraw(&mut self, amount: A) -> bool {
+        if self.locked {
+            return false;
+        }
+        if self.available < amount {
+            return false;
+        }
+        self.available -= amount;
+        self.version += 1;
+        true
+    }
+
+    /// Move funds from available to held (for dispute)
+    /// Returns true if successful, false if insufficient available funds
+    pub fn hold(&mut self, amount: A) -> bool {
+        if self.available < amount {
+            return false;
+        }
+        self.available -= amount;
+        self.held += amount;
+        self.version += 1;
+        true
+    }
+
+    /// Move funds from held back to available (for resolve)
+    /// Returns true if successful, false if insufficient held funds
+    pub fn release(&mut self, amount: A) -> bool {
+        if self.held < amount {
+            return false;
+        }
+        self.held -= amount;
+        self.available += amount;
+        self.version += 1;
+        true
+    }
+
+    /// Remove held funds and lock account (for chargeback)
+    /// Returns true if successful, false if insufficient held funds
+    pub fn chargeback(&mut self, amount: A) -> bool {
+        if self.held < amount {
+            return false;
+        }
+        self.held -= amount;
+        self.locked = true;
+        self.version += 1;
+        true
+    }
+
+    /// The account as a CSV output row, which leaves out the version
+    pub fn csv_row(&self) -> impl Serialize
+    where
+        A: Serialize,
+    {
+        AccountSerialized {
+            version: None,
+            ..AccountSerialized::from(self)
+        }
+    }
+}
+
+// Custom serialization to include computed total field for CSV output
+#[derive(Serialize)]
+#[cfg_attr(feature = "schema", derive(ToSchema), schema(bound = ""))]
+struct AccountSerialized<A> {
+    #[serde(rename = "client")]
+    #[cfg_attr(feature = "schema", schema(inline))]
+    client_id: ClientId,
+    #[cfg_attr(feature = "schema", schema(value_type = String))]
+    available: A,
+    #[cfg_attr(feature = "schema", schema(value_type = String))]
+    held: A,
+    #[cfg_attr(feature = "schema", schema(value_type = String))]
+    total: A,
+    locked: bool,
+    /// Left out of CSV output to keep its columns stable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u64>,
+}
+
+impl<A: Monetary> From<&Account<A>> for AccountSerialized<A> {
+    fn from(account: &Account<A>) -> Self {
+        Self {
+            client_id: account.client_id,
+            available: account.available,
+            held: account.held,
+            total: account.total(), // Compute on-the-fly
+            locked: account.locked,
+            version: Some(account.version),
+        }
+    }
+}
+
+impl<A: Monetary + Serialize> Serialize for Account<A> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        AccountSerialized::from(self).serialize(serializer)
+    }
+}
+
+/// Reads the serialized form back, e.g. a row of the output CSV
+///
+/// `total` may be left out, since it's derived; when present it must equal
+/// `available + held`. `version` defaults to 0 as in the CSV output, which
+/// leaves it out.
+impl<'de, A: Monetary> Deserialize<'de> for Account<A> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let record = AccountRecord::<A>::deserialize(deserializer)?;
+        let total = record.available + record.held;
+        if record.total.is_some_and(|given| given != total) {
+            return Err(de::Error::custom(format_args!(
+                "client {}: total doesn't equal available + held ({})",
+                record.client, total
+            )));
+        }
+        Ok(Self {
+            client_id: record.client,
+            available: record.available,
+            held: record.held,
+            locked: record.locked,
+            version: record.version,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "A: Monetary")]
+struct AccountRecord<A> {
+    client: ClientId,
+    #[serde(deserialize_with = "deserialize_amount")]
+    available: A,
+    #[serde(deserialize_with = "deserialize_amount")]
+    held: A,
+    #[serde(default, deserialize_with = "deserialize_optional_amount")]
+    total: Option<A>,
+    locked: bool,
+    #[serde(default)]
+    version: u64,
+}
+
+/// Parse an amount from its string form, so CSV never routes it through f64
+fn deserialize_amount<'de, A: Monetary, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<A, D::Error> {
+    deserialize_optional_amount(deserializer)?.ok_or_else(|| de::Error::custom("amount is empty"))
+}
+
+// API schema matches the serialized form rather than the struct fields
+#[cfg(feature = "schema")]
+impl PartialSchema for Account {
+    fn schema() -> RefOr<Schema> {
+        AccountSerialized::<Amount>::schema()
+    }
+}
+
+#[cfg(feature = "schema")]
+impl ToSchema for Account {
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("Account")
+    }
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+use super::transaction::{ClientId, TxId};
+use crate::amount::Amount;
+
+/// Funds held for one open dispute
+///
+/// A dispute places a hold for the disputed deposit's amount, and the
+/// resolve or chargeback settling it releases exactly that hold. A client's
+/// held balance is the sum of their holds, plus any held balance seeded from
+/// another ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hold<A = Amount> {
+    /// The disputed deposit
+    pub tx_id: TxId,
+    pub client_id: ClientId,
+    pub amount: A,
+    /// When the dispute happened, in Unix seconds, if its input said
+    #[serde(default)]
+    pub placed_at: Option<u64>,
+}
@@ -0,0 +1,12 @@
+pub mod account;
+pub mod hold;
+pub mod stored_tx;
+pub mod transaction;
+
+pub use account::Account;
+pub use hold::Hold;
+pub use stored_tx::StoredTransaction;
+pub use transaction::{
+    ClientId, Metadata, Transaction, TransactionBuildError, TransactionBuilder, TransactionType,
+    TxId,
+};
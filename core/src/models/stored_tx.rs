@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+use super::transaction::{ClientId, TransactionType, TxId};
+use crate::amount::Amount;
+
+/// Stored transaction for dispute reference
+/// Only deposits are stored as they are the only disputable transaction type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredTransaction<A = Amount> {
+    pub tx_id: TxId,
+    pub client_id: ClientId,
+    pub amount: A,
+    pub tx_type: TransactionType,
+    pub disputed: bool,
+    /// When the deposit happened, in Unix seconds, if its input said
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+impl<A> StoredTransaction<A> {
+    /// Create a new stored transaction
+    pub fn new(tx_id: TxId, client_id: ClientId, amount: A, tx_type: TransactionType) -> Self {
+        Self {
+            tx_id,
+            client_id,
+            amount,
+            tx_type,
+            disputed: false,
+            timestamp: None,
+        }
+    }
+}
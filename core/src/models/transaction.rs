@@ -0,0 +1,583 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeStruct};
+use serde::{Deserialize, Serialize, Serializer};
+#[cfg(feature = "schema")]
+use utoipa::ToSchema;
+
+use crate::amount::{Amount, Monetary};
+
+/// Type of transaction
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+}
+
+impl TransactionType {
+    /// Name used in CSV and JSON, e.g. `deposit`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Deposit => "deposit",
+            Self::Withdrawal => "withdrawal",
+            Self::Dispute => "dispute",
+            Self::Resolve => "resolve",
+            Self::Chargeback => "chargeback",
+        }
+    }
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Fields of a transaction the engine doesn't use, by name, e.g. a merchant
+/// ID or the acquirer's reference
+pub type Metadata = BTreeMap<String, String>;
+
+/// Transaction ID, unique across deposits and withdrawals
+///
+/// `u32` by default. With the `wide-tx-ids` feature it is `u64`, for
+/// upstreams that hand out 64-bit IDs such as snowflake IDs. IDs are plain
+/// numbers in CSV and JSON, so inputs, JSON state files and WALs whose IDs
+/// fit in 32 bits read the same either way.
+#[cfg(not(feature = "wide-tx-ids"))]
+pub type TxId = u32;
+
+/// Transaction ID, 64 bits wide with the `wide-tx-ids` feature
+#[cfg(feature = "wide-tx-ids")]
+pub type TxId = u64;
+
+/// Client ID, which names an account
+///
+/// `u16` by default. With the `wide-client-ids` feature it is `u64`, for
+/// deployments with more than 65,536 clients, or that key clients by IDs
+/// from another system. Like `TxId` it is a plain number in CSV and JSON.
+#[cfg(not(feature = "wide-client-ids"))]
+pub type ClientId = u16;
+
+/// Client ID, 64 bits wide with the `wide-client-ids` feature
+#[cfg(feature = "wide-client-ids")]
+pub type ClientId = u64;
+
+/// Transaction record from CSV (or JSON) input
+///
+/// Serializes back to the same shape, so a transaction written as JSON reads
+/// back unchanged. Fields beyond the five below are kept in `metadata`: JSON
+/// strings as they are, numbers and booleans written out, anything else
+/// dropped. Metadata is written back as top-level fields, except by
+/// serializers without maps, such as CSV, which can't take it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(ToSchema))]
+pub struct Transaction {
+    #[cfg_attr(feature = "schema", schema(rename = "type"))]
+    pub tx_type: TransactionType,
+    #[cfg_attr(feature = "schema", schema(inline))]
+    pub client: ClientId,
+    #[cfg_attr(feature = "schema", schema(inline))]
+    pub tx: TxId,
+    /// Required for deposits and withdrawals, ignored otherwise
+    #[cfg_attr(feature = "schema", schema(value_type = Option<String>))]
+    pub amount: Option<Amount>,
+    /// When the transaction happened, in Unix seconds, if the input says;
+    /// the engine applies transactions in input order regardless
+    pub timestamp: Option<u64>,
+    /// Extra fields, passed through to the WAL, history and rejections
+    /// untouched
+    #[cfg_attr(feature = "schema", schema(ignore))]
+    pub metadata: Metadata,
+}
+
+impl Transaction {
+    /// Names of the fields read into the struct; any others are metadata
+    pub const FIELDS: [&'static str; 5] = ["type", "client", "tx", "amount", "timestamp"];
+
+    /// Deposit of `amount` into `client`'s account
+    pub fn deposit(client: ClientId, tx: TxId, amount: Amount) -> Self {
+        Self::new(TransactionType::Deposit, client, tx, Some(amount))
+    }
+
+    /// Withdrawal of `amount` from `client`'s account
+    pub fn withdrawal(client: ClientId, tx: TxId, amount: Amount) -> Self {
+        Self::new(TransactionType::Withdrawal, client, tx, Some(amount))
+    }
+
+    /// Dispute of `client`'s deposit `tx`
+    pub fn dispute(client: ClientId, tx: TxId) -> Self {
+        Self::new(TransactionType::Dispute, client, tx, None)
+    }
+
+    /// Resolve of `client`'s disputed deposit `tx`
+    pub fn resolve(client: ClientId, tx: TxId) -> Self {
+        Self::new(TransactionType::Resolve, client, tx, None)
+    }
+
+    /// Chargeback of `client`'s disputed deposit `tx`
+    pub fn chargeback(client: ClientId, tx: TxId) -> Self {
+        Self::new(TransactionType::Chargeback, client, tx, None)
+    }
+
+    /// Start building a transaction field by field, see `TransactionBuilder`
+    pub fn builder() -> TransactionBuilder {
+        TransactionBuilder::default()
+    }
+
+    fn new(tx_type: TransactionType, client: ClientId, tx: TxId, amount: Option<Amount>) -> Self {
+        Self {
+            tx_type,
+            client,
+            tx,
+            amount,
+            timestamp: None,
+            metadata: Metadata::new(),
+        }
+    }
+}
+
+/// Builds a `Transaction`, checking that the fields fit together
+///
+/// Deposits and withdrawals need an amount; disputes, resolves and
+/// chargebacks must not have one, since they move the referenced deposit's
+/// amount. `build` reports the first problem instead of producing a
+/// transaction the engine would reject or misread.
+///
+/// ```
+/// # use payments_engine_core::models::{Transaction, TransactionBuildError};
+/// let deposit = Transaction::builder()
+///     .deposit()
+///     .client(1)
+///     .tx(7)
+///     .amount("2.5".parse().unwrap())
+///     .build()
+///     .unwrap();
+/// assert_eq!(deposit, Transaction::deposit(1, 7, "2.5".parse().unwrap()));
+///
+/// let missing = Transaction::builder().withdrawal().client(1).tx(8).build();
+/// assert_eq!(missing, Err(TransactionBuildError::MissingAmount));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TransactionBuilder {
+    tx_type: Option<TransactionType>,
+    client: Option<ClientId>,
+    tx: Option<TxId>,
+    amount: Option<Amount>,
+    timestamp: Option<u64>,
+    metadata: Metadata,
+}
+
+impl TransactionBuilder {
+    pub fn tx_type(mut self, tx_type: TransactionType) -> Self {
+        self.tx_type = Some(tx_type);
+        self
+    }
+
+    pub fn deposit(self) -> Self {
+        self.tx_type(TransactionType::Deposit)
+    }
+
+    pub fn withdrawal(self) -> Self {
+        self.tx_type(TransactionType::Withdrawal)
+    }
+
+    pub fn dispute(self) -> Self {
+        self.tx_type(TransactionType::Dispute)
+    }
+
+    pub fn resolve(self) -> Self {
+        self.tx_type(TransactionType::Resolve)
+    }
+
+    pub fn chargeback(self) -> Self {
+        self.tx_type(TransactionType::Chargeback)
+    }
+
+    pub fn client(mut self, client: ClientId) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// The transaction's own ID, or for disputes, resolves and chargebacks
+    /// the ID of the deposit they reference
+    pub fn tx(mut self, tx: TxId) -> Self {
+        self.tx = Some(tx);
+        self
+    }
+
+    pub fn amount(mut self, amount: Amount) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    /// Set when the transaction happened, in Unix seconds
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Add a `metadata` entry, replacing any earlier one named `key`
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Transaction, TransactionBuildError> {
+        let tx_type = self.tx_type.ok_or(TransactionBuildError::MissingType)?;
+        let client = self.client.ok_or(TransactionBuildError::MissingClient)?;
+        let tx = self.tx.ok_or(TransactionBuildError::MissingTransactionId)?;
+        match (tx_type, self.amount) {
+            (TransactionType::Deposit | TransactionType::Withdrawal, None) => {
+                Err(TransactionBuildError::MissingAmount)
+            }
+            (
+                TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback,
+                Some(_),
+            ) => Err(TransactionBuildError::UnexpectedAmount),
+            (tx_type, amount) => Ok(Transaction {
+                timestamp: self.timestamp,
+                metadata: self.metadata,
+                ..Transaction::new(tx_type, client, tx, amount)
+            }),
+        }
+    }
+}
+
+/// Why `TransactionBuilder::build` failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionBuildError {
+    MissingType,
+    MissingClient,
+    MissingTransactionId,
+    /// A deposit or withdrawal without an amount
+    MissingAmount,
+    /// A dispute, resolve or chargeback with an amount
+    UnexpectedAmount,
+}
+
+impl fmt::Display for TransactionBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::MissingType => "transaction type not set",
+            Self::MissingClient => "client not set",
+            Self::MissingTransactionId => "transaction ID not set",
+            Self::MissingAmount => "deposits and withdrawals need an amount",
+            Self::UnexpectedAmount => "disputes, resolves and chargebacks take no amount",
+        })
+    }
+}
+
+impl core::error::Error for TransactionBuildError {}
+
+impl Serialize for Transaction {
+    /// A struct without metadata, so CSV can write it; a map with it
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let amount = AmountField(self.amount);
+        if self.metadata.is_empty() {
+            let mut fields = serializer.serialize_struct("Transaction", Self::FIELDS.len())?;
+            fields.serialize_field("type", &self.tx_type)?;
+            fields.serialize_field("client", &self.client)?;
+            fields.serialize_field("tx", &self.tx)?;
+            fields.serialize_field("amount", &amount)?;
+            // Left out rather than null, so input without timestamps reads
+            // back the same
+            match self.timestamp {
+                Some(timestamp) => fields.serialize_field("timestamp", &timestamp)?,
+                None => fields.skip_field("timestamp")?,
+            }
+            return fields.end();
+        }
+
+        let known = Self::FIELDS.len() - usize::from(self.timestamp.is_none());
+        let mut fields = serializer.serialize_map(Some(known + self.metadata.len()))?;
+        fields.serialize_entry("type", &self.tx_type)?;
+        fields.serialize_entry("client", &self.client)?;
+        fields.serialize_entry("tx", &self.tx)?;
+        fields.serialize_entry("amount", &amount)?;
+        if let Some(timestamp) = self.timestamp {
+            fields.serialize_entry("timestamp", &timestamp)?;
+        }
+        // Entries named like a field would read back as that field
+        for (key, value) in &self.metadata {
+            if !Self::FIELDS.contains(&key.as_str()) {
+                fields.serialize_entry(key, value)?;
+            }
+        }
+        fields.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Transaction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Transaction", &Transaction::FIELDS, TransactionVisitor)
+    }
+}
+
+/// The amount as it appears in input and output: a decimal string, or
+/// nothing
+struct AmountField(Option<Amount>);
+
+impl Serialize for AmountField {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_optional_amount(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AmountField {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_optional_amount(deserializer).map(Self)
+    }
+}
+
+/// A field name in transaction input
+enum Field {
+    Type,
+    Client,
+    Tx,
+    Amount,
+    Timestamp,
+    /// An empty name, e.g. of a CSV column left out on purpose
+    Ignored,
+    Other(String),
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_identifier(FieldVisitor)
+    }
+}
+
+struct FieldVisitor;
+
+impl<'de> Visitor<'de> for FieldVisitor {
+    type Value = Field;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a field name")
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Field, E> {
+        Ok(match name {
+            "type" => Field::Type,
+            "client" => Field::Client,
+            "tx" => Field::Tx,
+            "amount" => Field::Amount,
+            "timestamp" => Field::Timestamp,
+            "" => Field::Ignored,
+            other => Field::Other(other.to_string()),
+        })
+    }
+
+    fn visit_bytes<E: de::Error>(self, name: &[u8]) -> Result<Field, E> {
+        match core::str::from_utf8(name) {
+            Ok(name) => self.visit_str(name),
+            Err(_) => Err(E::invalid_value(de::Unexpected::Bytes(name), &self)),
+        }
+    }
+}
+
+/// A metadata value: strings as they are, numbers and booleans written out,
+/// `None` for anything else
+struct MetadataValue(Option<String>);
+
+impl<'de> Deserialize<'de> for MetadataValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MetadataValueVisitor)
+    }
+}
+
+struct MetadataValueVisitor;
+
+impl<'de> Visitor<'de> for MetadataValueVisitor {
+    type Value = MetadataValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<MetadataValue, E> {
+        Ok(MetadataValue(Some(value.to_string())))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<MetadataValue, E> {
+        Ok(MetadataValue(Some(value)))
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<MetadataValue, E> {
+        Ok(MetadataValue(Some(value.to_string())))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<MetadataValue, E> {
+        Ok(MetadataValue(Some(value.to_string())))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<MetadataValue, E> {
+        Ok(MetadataValue(Some(value.to_string())))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<MetadataValue, E> {
+        Ok(MetadataValue(Some(value.to_string())))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<MetadataValue, E> {
+        Ok(MetadataValue(None))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<MetadataValue, E> {
+        Ok(MetadataValue(None))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<MetadataValue, D::Error> {
+        MetadataValue::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<MetadataValue, A::Error> {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(MetadataValue(None))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<MetadataValue, A::Error> {
+        while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+        Ok(MetadataValue(None))
+    }
+}
+
+struct TransactionVisitor;
+
+impl<'de> Visitor<'de> for TransactionVisitor {
+    type Value = Transaction;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a transaction")
+    }
+
+    /// Fields in `type,client,tx,amount,timestamp` order, e.g. a headerless
+    /// CSV row; the amount and timestamp may be left out
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Transaction, A::Error> {
+        let missing = |index| de::Error::invalid_length(index, &"3 to 5 fields");
+        let tx_type = seq.next_element()?.ok_or_else(|| missing(0))?;
+        let client = seq.next_element()?.ok_or_else(|| missing(1))?;
+        let tx = seq.next_element()?.ok_or_else(|| missing(2))?;
+        let amount = seq
+            .next_element::<AmountField>()?
+            .and_then(|amount| amount.0);
+        let timestamp = seq.next_element::<Option<u64>>()?.flatten();
+        Ok(Transaction {
+            timestamp,
+            ..Transaction::new(tx_type, client, tx, amount)
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Transaction, A::Error> {
+        let mut tx_type = None;
+        let mut client = None;
+        let mut tx = None;
+        let mut amount = None;
+        let mut timestamp = None;
+        let mut metadata = Metadata::new();
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Type => set(&mut tx_type, map.next_value()?, "type")?,
+                Field::Client => set(&mut client, map.next_value()?, "client")?,
+                Field::Tx => set(&mut tx, map.next_value()?, "tx")?,
+                Field::Amount => set(&mut amount, map.next_value::<AmountField>()?.0, "amount")?,
+                Field::Timestamp => set(&mut timestamp, map.next_value()?, "timestamp")?,
+                Field::Ignored => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+                Field::Other(name) => {
+                    if let MetadataValue(Some(value)) = map.next_value()? {
+                        metadata.insert(name, value);
+                    }
+                }
+            }
+        }
+
+        Ok(Transaction {
+            tx_type: tx_type.ok_or_else(|| de::Error::missing_field("type"))?,
+            client: client.ok_or_else(|| de::Error::missing_field("client"))?,
+            tx: tx.ok_or_else(|| de::Error::missing_field("tx"))?,
+            amount: amount.flatten(),
+            timestamp: timestamp.flatten(),
+            metadata,
+        })
+    }
+}
+
+/// Fill in a field read from a map, failing if it was already read
+fn set<T, E: de::Error>(slot: &mut Option<T>, value: T, name: &'static str) -> Result<(), E> {
+    if slot.is_some() {
+        return Err(E::duplicate_field(name));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Write the amount as a decimal string, or nothing without one
+///
+/// Nothing is an empty CSV field or a JSON `null`, both of which read back as
+/// `None`. Strings keep `Decimal`'s full precision in every format.
+fn serialize_optional_amount<S: Serializer>(
+    amount: &Option<Amount>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match amount {
+        Some(amount) => serializer.collect_str(amount),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Custom deserializer to handle empty strings (or a missing/null JSON value) as None for amount field
+///
+/// Parses the amount straight from the borrowed field text, so no `String`
+/// is allocated per row.
+pub(crate) fn deserialize_optional_amount<'de, A, D>(deserializer: D) -> Result<Option<A>, D::Error>
+where
+    A: Monetary,
+    D: serde::Deserializer<'de>,
+{
+    deserializer.deserialize_option(OptionalAmountVisitor(PhantomData))
+}
+
+struct OptionalAmountVisitor<A>(PhantomData<A>);
+
+impl<'de, A: Monetary> Visitor<'de> for OptionalAmountVisitor<A> {
+    type Value = Option<A>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a decimal amount string or nothing")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        let amount = value.trim();
+        if amount.is_empty() {
+            return Ok(None);
+        }
+        #[cfg(feature = "tolerant-amounts")]
+        if let Some(plain) = crate::amount::normalize(amount) {
+            return plain.parse::<A>().map(Some).map_err(E::custom);
+        }
+        amount.parse::<A>().map(Some).map_err(E::custom)
+    }
+}
@@ -0,0 +1,124 @@
+use core::fmt;
+
+/// Result of processing a single transaction
+///
+/// Rejections are business outcomes, not errors: the engine keeps going and
+/// the transaction simply has no effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The transaction changed engine state
+    Applied,
+    /// The transaction was ignored
+    Rejected(RejectReason),
+}
+
+impl Outcome {
+    /// True if the transaction was applied
+    pub fn is_applied(&self) -> bool {
+        matches!(self, Self::Applied)
+    }
+}
+
+/// Why a transaction was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// A deposit or withdrawal reused an already processed transaction ID
+    DuplicateTransaction,
+    /// A deposit or withdrawal had no amount
+    MissingAmount,
+    /// A deposit or withdrawal amount was zero or negative
+    NonPositiveAmount,
+    /// A deposit or withdrawal exceeded `EngineConfig::max_amount`
+    AmountAboveLimit,
+    /// An amount had more decimal places than `EngineConfig::max_decimal_places`
+    TooManyDecimalPlaces,
+    /// The client submitted transactions faster than its rate limit allows
+    RateLimited,
+    /// The client's account is locked after a chargeback
+    AccountLocked,
+    /// Not enough available funds for the withdrawal or dispute
+    InsufficientFunds,
+    /// Not enough held funds to resolve or charge back
+    InsufficientHeldFunds,
+    /// The client has no account
+    AccountNotFound,
+    /// The referenced transaction doesn't exist or isn't disputable
+    TransactionNotFound,
+    /// The referenced deposit was dropped under `EngineConfig::retention`
+    TransactionExpired,
+    /// The referenced transaction belongs to another client
+    ClientMismatch,
+    /// The referenced transaction is already under dispute
+    AlreadyDisputed,
+    /// The referenced transaction is not under dispute
+    NotDisputed,
+    /// The referenced transaction was spilled to disk and couldn't be read back
+    StorageUnavailable,
+    /// The account changed since the version the caller expected
+    VersionMismatch,
+    /// The client was erased and takes no more transactions
+    ClientErased,
+    /// A pre-commit hook refused the transaction
+    Vetoed,
+}
+
+impl RejectReason {
+    /// Every reason, in declaration order
+    pub const ALL: [Self; 19] = [
+        Self::DuplicateTransaction,
+        Self::MissingAmount,
+        Self::NonPositiveAmount,
+        Self::AmountAboveLimit,
+        Self::TooManyDecimalPlaces,
+        Self::RateLimited,
+        Self::AccountLocked,
+        Self::InsufficientFunds,
+        Self::InsufficientHeldFunds,
+        Self::AccountNotFound,
+        Self::TransactionNotFound,
+        Self::TransactionExpired,
+        Self::ClientMismatch,
+        Self::AlreadyDisputed,
+        Self::NotDisputed,
+        Self::StorageUnavailable,
+        Self::VersionMismatch,
+        Self::ClientErased,
+        Self::Vetoed,
+    ];
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::DuplicateTransaction => "duplicate transaction id",
+            Self::MissingAmount => "missing amount",
+            Self::NonPositiveAmount => "amount must be positive",
+            Self::AmountAboveLimit => "amount above limit",
+            Self::TooManyDecimalPlaces => "too many decimal places",
+            Self::RateLimited => "rate limit exceeded",
+            Self::AccountLocked => "account locked",
+            Self::InsufficientFunds => "insufficient available funds",
+            Self::InsufficientHeldFunds => "insufficient held funds",
+            Self::AccountNotFound => "account not found",
+            Self::TransactionNotFound => "referenced transaction not found",
+            Self::TransactionExpired => "referenced transaction expired",
+            Self::ClientMismatch => "transaction belongs to another client",
+            Self::AlreadyDisputed => "transaction already disputed",
+            Self::NotDisputed => "transaction not under dispute",
+            Self::StorageUnavailable => "stored transaction unavailable",
+            Self::VersionMismatch => "account version changed",
+            Self::ClientErased => "client erased",
+            Self::Vetoed => "vetoed",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Applied => f.write_str("applied"),
+            Self::Rejected(reason) => write!(f, "rejected: {}", reason),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! What each transaction type does to an account
+//!
+//! Each function checks the rules for one transaction type and applies it, or
+//! leaves everything untouched and returns why not. Finding the account and
+//! the referenced deposit is up to the caller, as are duplicate detection and
+//! amount limits; `PaymentsEngine::process_transaction` does all of that
+//! before calling in here.
+//!
+//! The functions are generic over the amount representation (see
+//! `Monetary`), which callers rarely need to spell out: it follows from the
+//! account and amounts passed in.
+
+use crate::amount::Monetary;
+use crate::models::{Account, ClientId, Hold, StoredTransaction};
+use crate::outcome::RejectReason;
+
+/// Credit `amount` to the available funds
+pub fn deposit<A: Monetary>(account: &mut Account<A>, amount: A) -> Result<(), RejectReason> {
+    if !account.deposit(amount) {
+        return Err(RejectReason::AccountLocked);
+    }
+    Ok(())
+}
+
+/// Debit `amount` from the available funds of the client's account, if any
+pub fn withdraw<A: Monetary>(
+    account: Option<&mut Account<A>>,
+    amount: A,
+) -> Result<(), RejectReason> {
+    let account = account.ok_or(RejectReason::AccountNotFound)?;
+    if !account.withdraw(amount) {
+        return Err(if account.locked {
+            RejectReason::AccountLocked
+        } else {
+            RejectReason::InsufficientFunds
+        });
+    }
+    Ok(())
+}
+
+/// Hold the funds of `deposit`, disputed by `client`, returning the hold
+///
+/// `account` is `client`'s account, if it has one. The hold has no
+/// `placed_at`; the caller knows when the dispute happened.
+pub fn dispute<A: Monetary>(
+    deposit: &mut StoredTransaction<A>,
+    client: ClientId,
+    account: Option<&mut Account<A>>,
+) -> Result<Hold<A>, RejectReason> {
+    if deposit.client_id != client {
+        return Err(RejectReason::ClientMismatch);
+    }
+    if deposit.disputed {
+        return Err(RejectReason::AlreadyDisputed);
+    }
+    let account = account.ok_or(RejectReason::AccountNotFound)?;
+    if !account.hold(deposit.amount) {
+        return Err(RejectReason::InsufficientFunds);
+    }
+    deposit.disputed = true;
+    Ok(Hold {
+        tx_id: deposit.tx_id,
+        client_id: client,
+        amount: deposit.amount,
+        placed_at: None,
+    })
+}
+
+/// Release the funds `hold` keeps for the disputed `deposit` back to available
+///
+/// `hold` is the hold the dispute placed, if there is one, and `account` is
+/// `client`'s account, if it has one.
+pub fn resolve<A: Monetary>(
+    deposit: &mut StoredTransaction<A>,
+    hold: Option<&Hold<A>>,
+    client: ClientId,
+    account: Option<&mut Account<A>>,
+) -> Result<(), RejectReason> {
+    let (hold, account) = disputed_account(deposit, hold, client, account)?;
+    if !account.release(hold.amount) {
+        return Err(RejectReason::InsufficientHeldFunds);
+    }
+    deposit.disputed = false;
+    Ok(())
+}
+
+/// Remove the funds `hold` keeps for the disputed `deposit` and lock the
+/// account
+///
+/// `hold` is the hold the dispute placed, if there is one, and `account` is
+/// `client`'s account, if it has one.
+pub fn chargeback<A: Monetary>(
+    deposit: &mut StoredTransaction<A>,
+    hold: Option<&Hold<A>>,
+    client: ClientId,
+    account: Option<&mut Account<A>>,
+) -> Result<(), RejectReason> {
+    let (hold, account) = disputed_account(deposit, hold, client, account)?;
+    if !account.chargeback(hold.amount) {
+        return Err(RejectReason::InsufficientHeldFunds);
+    }
+    // Charged back, so no longer under dispute
+    deposit.disputed = false;
+    Ok(())
+}
+
+/// Checks shared by resolves and chargebacks
+fn disputed_account<'a, 'h, A>(
+    deposit: &StoredTransaction<A>,
+    hold: Option<&'h Hold<A>>,
+    client: ClientId,
+    account: Option<&'a mut Account<A>>,
+) -> Result<(&'h Hold<A>, &'a mut Account<A>), RejectReason> {
+    if deposit.client_id != client {
+        return Err(RejectReason::ClientMismatch);
+    }
+    let hold = match hold {
+        Some(hold) if deposit.disputed && hold.tx_id == deposit.tx_id => hold,
+        _ => return Err(RejectReason::NotDisputed),
+    };
+    Ok((hold, account.ok_or(RejectReason::AccountNotFound)?))
+}
@@ -0,0 +1,74 @@
+use payments_engine_core::amount::Amount;
+use payments_engine_core::models::{Transaction, TransactionBuildError, TransactionType};
+
+fn amount(text: &str) -> Amount {
+    text.parse().unwrap()
+}
+
+#[test]
+fn test_constructors_set_amount_only_when_needed() {
+    let withdrawal = Transaction::withdrawal(2, 9, amount("1.25"));
+    assert_eq!(withdrawal.tx_type, TransactionType::Withdrawal);
+    assert_eq!((withdrawal.client, withdrawal.tx), (2, 9));
+    assert_eq!(withdrawal.amount, Some(amount("1.25")));
+
+    for (tx, tx_type) in [
+        (Transaction::dispute(2, 9), TransactionType::Dispute),
+        (Transaction::resolve(2, 9), TransactionType::Resolve),
+        (Transaction::chargeback(2, 9), TransactionType::Chargeback),
+    ] {
+        assert_eq!(tx.tx_type, tx_type);
+        assert_eq!(tx.amount, None);
+    }
+}
+
+#[test]
+fn test_builder_matches_constructors() {
+    let built = Transaction::builder()
+        .client(3)
+        .tx(4)
+        .amount(amount("10"))
+        .withdrawal()
+        .build();
+    assert_eq!(built, Ok(Transaction::withdrawal(3, 4, amount("10"))));
+
+    let built = Transaction::builder()
+        .tx_type(TransactionType::Resolve)
+        .client(3)
+        .tx(4)
+        .build();
+    assert_eq!(built, Ok(Transaction::resolve(3, 4)));
+}
+
+#[test]
+fn test_builder_rejects_invalid_combinations() {
+    let cases = [
+        (
+            Transaction::builder().client(1).tx(1),
+            TransactionBuildError::MissingType,
+        ),
+        (
+            Transaction::builder().deposit().tx(1),
+            TransactionBuildError::MissingClient,
+        ),
+        (
+            Transaction::builder().deposit().client(1),
+            TransactionBuildError::MissingTransactionId,
+        ),
+        (
+            Transaction::builder().deposit().client(1).tx(1),
+            TransactionBuildError::MissingAmount,
+        ),
+        (
+            Transaction::builder()
+                .chargeback()
+                .client(1)
+                .tx(1)
+                .amount(amount("5")),
+            TransactionBuildError::UnexpectedAmount,
+        ),
+    ];
+    for (builder, error) in cases {
+        assert_eq!(builder.build(), Err(error));
+    }
+}
@@ -0,0 +1,142 @@
+use payments_engine_core::amount::{Amount, FixedAmount, Monetary};
+use payments_engine_core::models::{
+    Account, ClientId, Hold, StoredTransaction, TransactionType, TxId,
+};
+use payments_engine_core::outcome::RejectReason;
+use payments_engine_core::transition;
+use rust_decimal::Decimal;
+
+fn amount(text: &str) -> Amount {
+    text.parse().unwrap()
+}
+
+fn stored_deposit(tx_id: TxId, client_id: ClientId, value: &str) -> StoredTransaction {
+    StoredTransaction::new(tx_id, client_id, amount(value), TransactionType::Deposit)
+}
+
+#[test]
+fn test_dispute_lifecycle() {
+    let mut account = Account::new(1);
+    transition::deposit(&mut account, amount("10")).unwrap();
+    let mut deposit = stored_deposit(1, 1, "10");
+
+    let hold = transition::dispute(&mut deposit, 1, Some(&mut account)).unwrap();
+    assert!(deposit.disputed);
+    assert_eq!(account.held, amount("10"));
+    assert_eq!((hold.tx_id, hold.client_id), (1, 1));
+    assert_eq!(hold.amount, amount("10"));
+    assert_eq!(
+        transition::dispute(&mut deposit, 1, Some(&mut account)),
+        Err(RejectReason::AlreadyDisputed)
+    );
+
+    transition::resolve(&mut deposit, Some(&hold), 1, Some(&mut account)).unwrap();
+    assert!(!deposit.disputed);
+    assert_eq!(account.available, amount("10"));
+
+    let hold = transition::dispute(&mut deposit, 1, Some(&mut account)).unwrap();
+    transition::chargeback(&mut deposit, Some(&hold), 1, Some(&mut account)).unwrap();
+    assert!(account.locked);
+    assert_eq!(account.total(), Amount::ZERO);
+    assert_eq!(account.version, 5);
+}
+
+#[test]
+fn test_rejections_leave_state_untouched() {
+    let mut account = Account::new(1);
+    transition::deposit(&mut account, amount("5")).unwrap();
+    let mut deposit = stored_deposit(1, 1, "5");
+
+    assert_eq!(
+        transition::withdraw(Some(&mut account), amount("6")),
+        Err(RejectReason::InsufficientFunds)
+    );
+    assert_eq!(
+        transition::withdraw(None, amount("1")),
+        Err(RejectReason::AccountNotFound)
+    );
+    assert_eq!(
+        transition::dispute(&mut deposit, 2, None),
+        Err(RejectReason::ClientMismatch)
+    );
+    assert_eq!(
+        transition::resolve(&mut deposit, None, 1, Some(&mut account)),
+        Err(RejectReason::NotDisputed)
+    );
+    assert_eq!(
+        transition::chargeback(&mut deposit, None, 1, Some(&mut account)),
+        Err(RejectReason::NotDisputed)
+    );
+
+    assert!(!deposit.disputed);
+    assert_eq!(account.available, amount("5"));
+    assert_eq!(account.version, 1);
+}
+
+#[test]
+fn test_settling_releases_the_hold_amount() {
+    let mut account = Account::new(1);
+    transition::deposit(&mut account, amount("10")).unwrap();
+    let mut deposit = stored_deposit(1, 1, "10");
+    let hold = transition::dispute(&mut deposit, 1, Some(&mut account)).unwrap();
+
+    // A hold for another deposit doesn't settle this one
+    let other = Hold { tx_id: 2, ..hold };
+    assert_eq!(
+        transition::resolve(&mut deposit, Some(&other), 1, Some(&mut account)),
+        Err(RejectReason::NotDisputed)
+    );
+
+    let partial = Hold {
+        amount: amount("4"),
+        ..hold
+    };
+    transition::resolve(&mut deposit, Some(&partial), 1, Some(&mut account)).unwrap();
+    assert_eq!(account.available, amount("4"));
+    assert_eq!(account.held, amount("6"));
+}
+
+#[test]
+fn test_locked_account_rejects_deposits_and_withdrawals() {
+    let mut account = Account::new(1);
+    account.locked = true;
+
+    assert_eq!(
+        transition::deposit(&mut account, amount("1")),
+        Err(RejectReason::AccountLocked)
+    );
+    assert_eq!(
+        transition::withdraw(Some(&mut account), amount("1")),
+        Err(RejectReason::AccountLocked)
+    );
+    assert_eq!(account.total(), Amount::ZERO);
+}
+
+/// Deposits 10.5, disputes and charges it back in any representation,
+/// returning the account
+fn charged_back<A: Monetary>() -> Account<A> {
+    let parse = |text: &str| text.parse::<A>().ok().unwrap();
+    let mut account = Account::with_balances(1, A::ZERO, A::ZERO, false);
+    transition::deposit(&mut account, parse("10.5")).unwrap();
+    transition::deposit(&mut account, parse("2")).unwrap();
+    let mut deposit = StoredTransaction::new(1, 1, parse("10.5"), TransactionType::Deposit);
+    let hold = transition::dispute(&mut deposit, 1, Some(&mut account)).unwrap();
+    transition::chargeback(&mut deposit, Some(&hold), 1, Some(&mut account)).unwrap();
+    account
+}
+
+#[test]
+fn test_rules_are_the_same_for_every_representation() {
+    let decimal = charged_back::<Decimal>();
+    let fixed = charged_back::<FixedAmount>();
+    assert_eq!(decimal.available, Decimal::new(2, 0));
+    assert_eq!(fixed.available, FixedAmount::from_minor_units(20_000));
+    for (decimal, fixed) in [
+        (decimal.available, fixed.available),
+        (decimal.held, fixed.held),
+        (decimal.total(), fixed.total()),
+    ] {
+        assert_eq!(decimal, Decimal::from(fixed));
+    }
+    assert!(decimal.locked && fixed.locked);
+}
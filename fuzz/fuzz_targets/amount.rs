@@ -0,0 +1,15 @@
+//! The amount parsers, for both amount representations
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use payments_engine::amount::{Amount, FixedAmount};
+
+fuzz_target!(|text: &str| {
+    // Display output parses back to the same amount
+    if let Ok(amount) = text.parse::<FixedAmount>() {
+        assert_eq!(amount.to_string().parse::<FixedAmount>().ok(), Some(amount));
+    }
+    if let Ok(amount) = text.parse::<Amount>() {
+        assert_eq!(amount.to_string().parse::<Amount>().ok(), Some(amount));
+    }
+});
@@ -0,0 +1,15 @@
+//! Whole CSV inputs, header included, as the CLI reads them
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use payments_engine::{read_transactions, write_transactions};
+
+fuzz_target!(|data: &[u8]| {
+    let transactions: Vec<_> = read_transactions(data).collect();
+
+    // Whatever parses reads back the same once written
+    let mut csv = Vec::new();
+    write_transactions(transactions.clone(), &mut csv).unwrap();
+    let reread: Vec<_> = read_transactions(csv.as_slice()).collect();
+    assert_eq!(reread, transactions);
+});
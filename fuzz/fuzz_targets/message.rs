@@ -0,0 +1,19 @@
+//! Broker message payloads in every supported encoding
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use payments_engine::connectors::format::MessageFormat;
+
+fuzz_target!(|payload: &[u8]| {
+    for format in [
+        MessageFormat::Json,
+        MessageFormat::Avro,
+        MessageFormat::Protobuf,
+    ] {
+        // A decoded message encodes to a payload that decodes the same
+        if let Ok(transaction) = format.decode(payload) {
+            let encoded = format.encode(&transaction);
+            assert_eq!(format.decode(&encoded).ok(), Some(transaction));
+        }
+    }
+});
@@ -0,0 +1,16 @@
+//! Write-ahead log lines, including ones torn by a crash
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use payments_engine::persistence::{decode_log_line, LogEntry};
+
+fuzz_target!(|line: &str| {
+    // A transaction read from the log is logged again unchanged
+    if let Ok(LogEntry::Transaction(transaction)) = decode_log_line(line) {
+        let logged = serde_json::to_string(&transaction).unwrap();
+        assert_eq!(
+            decode_log_line(&logged).ok(),
+            Some(LogEntry::Transaction(transaction))
+        );
+    }
+});
@@ -0,0 +1,86 @@
+/*
+ * C interface to the payments engine, built with `--features ffi`
+ *
+ * Link against the cdylib (libpayments_engine.so, .dylib or
+ * payments_engine.dll). Amounts are int64_t counts of 1/10000 units, so
+ * 12.5 is 125000. An engine must not be used from several threads at once.
+ * See src/ffi.rs for the details of each function.
+ */
+
+#ifndef PAYMENTS_ENGINE_H
+#define PAYMENTS_ENGINE_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes; pe_engine_submit returns 1 and up for rejections */
+#define PE_OK 0
+#define PE_INVALID_ARGUMENT (-1)
+#define PE_NOT_FOUND (-2)
+#define PE_OUT_OF_RANGE (-3)
+
+/* Rejection reasons returned by pe_engine_submit */
+#define PE_REJECT_DUPLICATE_TRANSACTION 1
+#define PE_REJECT_MISSING_AMOUNT 2
+#define PE_REJECT_NON_POSITIVE_AMOUNT 3
+#define PE_REJECT_AMOUNT_ABOVE_LIMIT 4
+#define PE_REJECT_TOO_MANY_DECIMAL_PLACES 5
+#define PE_REJECT_RATE_LIMITED 6
+#define PE_REJECT_ACCOUNT_LOCKED 7
+#define PE_REJECT_INSUFFICIENT_FUNDS 8
+#define PE_REJECT_INSUFFICIENT_HELD_FUNDS 9
+#define PE_REJECT_ACCOUNT_NOT_FOUND 10
+#define PE_REJECT_TRANSACTION_NOT_FOUND 11
+#define PE_REJECT_TRANSACTION_EXPIRED 12
+#define PE_REJECT_CLIENT_MISMATCH 13
+#define PE_REJECT_ALREADY_DISPUTED 14
+#define PE_REJECT_NOT_DISPUTED 15
+#define PE_REJECT_STORAGE_UNAVAILABLE 16
+#define PE_REJECT_VERSION_MISMATCH 17
+#define PE_REJECT_CLIENT_ERASED 18
+#define PE_REJECT_VETOED 19
+
+/* Transaction types for pe_engine_submit */
+#define PE_DEPOSIT 0
+#define PE_WITHDRAWAL 1
+#define PE_DISPUTE 2
+#define PE_RESOLVE 3
+#define PE_CHARGEBACK 4
+
+typedef struct PeEngine PeEngine;
+
+typedef struct PeAccount {
+    uint16_t client;
+    int64_t available;
+    int64_t held;
+    int64_t total;
+    bool locked;
+} PeAccount;
+
+PeEngine *pe_engine_new(void);
+void pe_engine_free(PeEngine *engine);
+
+/* amount is only read for deposits and withdrawals */
+int32_t pe_engine_submit(PeEngine *engine, uint8_t tx_type, uint16_t client,
+                         uint32_t tx, int64_t amount);
+
+/* Writes *out only when returning PE_OK */
+int32_t pe_engine_get_account(const PeEngine *engine, uint16_t client,
+                              PeAccount *out);
+
+/* Release the result with pe_string_free; NULL on failure */
+char *pe_engine_accounts_csv(const PeEngine *engine);
+void pe_string_free(char *string);
+
+/* Static string, never NULL; don't free it */
+const char *pe_status_message(int32_t status);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PAYMENTS_ENGINE_H */
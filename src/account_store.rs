@@ -0,0 +1,152 @@
+use std::collections::{btree_map, hash_map, BTreeMap, HashMap};
+
+use crate::memory;
+use crate::models::{Account, ClientId};
+
+/// How the engine keeps client accounts in memory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccountOrdering {
+    /// HashMap storage: O(1) lookups, output must be sorted at the end
+    #[default]
+    Unordered,
+    /// BTreeMap storage: O(log n) lookups, accounts iterate in client ID order
+    /// so results can be streamed out without collecting and sorting them
+    ByClientId,
+}
+
+/// Account storage backing `PaymentsEngine`
+pub(crate) enum AccountStore {
+    Unordered(HashMap<ClientId, Account>),
+    Ordered(BTreeMap<ClientId, Account>),
+}
+
+impl AccountStore {
+    pub(crate) fn new(ordering: AccountOrdering) -> Self {
+        match ordering {
+            AccountOrdering::Unordered => Self::Unordered(HashMap::new()),
+            AccountOrdering::ByClientId => Self::Ordered(BTreeMap::new()),
+        }
+    }
+
+    pub(crate) fn ordering(&self) -> AccountOrdering {
+        match self {
+            Self::Unordered(_) => AccountOrdering::Unordered,
+            Self::Ordered(_) => AccountOrdering::ByClientId,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Unordered(map) => map.len(),
+            Self::Ordered(map) => map.len(),
+        }
+    }
+
+    pub(crate) fn get(&self, client_id: &ClientId) -> Option<&Account> {
+        match self {
+            Self::Unordered(map) => map.get(client_id),
+            Self::Ordered(map) => map.get(client_id),
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, client_id: &ClientId) -> Option<&mut Account> {
+        match self {
+            Self::Unordered(map) => map.get_mut(client_id),
+            Self::Ordered(map) => map.get_mut(client_id),
+        }
+    }
+
+    /// Get the account for a client, creating an empty one if it doesn't exist
+    pub(crate) fn get_or_create(&mut self, client_id: ClientId) -> &mut Account {
+        match self {
+            Self::Unordered(map) => map
+                .entry(client_id)
+                .or_insert_with(|| Account::new(client_id)),
+            Self::Ordered(map) => map
+                .entry(client_id)
+                .or_insert_with(|| Account::new(client_id)),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, account: Account) {
+        match self {
+            Self::Unordered(map) => {
+                map.insert(account.client_id, account);
+            }
+            Self::Ordered(map) => {
+                map.insert(account.client_id, account);
+            }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, client_id: &ClientId) -> Option<Account> {
+        match self {
+            Self::Unordered(map) => map.remove(client_id),
+            Self::Ordered(map) => map.remove(client_id),
+        }
+    }
+
+    /// Make room for `additional` more accounts; ordered storage allocates
+    /// per node and has nothing to reserve
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        if let Self::Unordered(map) = self {
+            map.reserve(additional);
+        }
+    }
+
+    /// Estimated heap bytes held by the accounts
+    pub(crate) fn memory_footprint(&self) -> usize {
+        match self {
+            Self::Unordered(map) => memory::hash_map_bytes(map),
+            Self::Ordered(map) => memory::btree_map_bytes(map),
+        }
+    }
+
+    pub(crate) fn values(&self) -> Values<'_> {
+        match self {
+            Self::Unordered(map) => Values::Unordered(map.values()),
+            Self::Ordered(map) => Values::Ordered(map.values()),
+        }
+    }
+
+    pub(crate) fn into_values(self) -> IntoValues {
+        match self {
+            Self::Unordered(map) => IntoValues::Unordered(map.into_values()),
+            Self::Ordered(map) => IntoValues::Ordered(map.into_values()),
+        }
+    }
+}
+
+/// Borrowing iterator over stored accounts
+pub(crate) enum Values<'a> {
+    Unordered(hash_map::Values<'a, ClientId, Account>),
+    Ordered(btree_map::Values<'a, ClientId, Account>),
+}
+
+impl<'a> Iterator for Values<'a> {
+    type Item = &'a Account;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Unordered(iter) => iter.next(),
+            Self::Ordered(iter) => iter.next(),
+        }
+    }
+}
+
+/// Consuming iterator over stored accounts
+pub(crate) enum IntoValues {
+    Unordered(hash_map::IntoValues<ClientId, Account>),
+    Ordered(btree_map::IntoValues<ClientId, Account>),
+}
+
+impl Iterator for IntoValues {
+    type Item = Account;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Unordered(iter) => iter.next(),
+            Self::Ordered(iter) => iter.next(),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use crate::models::{Account, ClientId};
+
+/// Clients per page of an `AccountPages`
+const PAGE_SIZE: ClientId = 256;
+
+type Page = Vec<Option<Account>>;
+
+/// Page number and slot within the page of `client_id`
+fn locate(client_id: ClientId) -> (ClientId, usize) {
+    (client_id / PAGE_SIZE, (client_id % PAGE_SIZE) as usize)
+}
+
+/// Copy-on-write copy of a shard's accounts, addressed by client ID
+///
+/// Accounts sit in fixed pages of 256 clients behind `Arc`s, and only pages
+/// holding an account exist. Taking a view only clones the page pointers,
+/// one per 256 clients, never the accounts. Updating an account copies its
+/// page first if a view still holds it, and writes in place otherwise.
+#[derive(Clone)]
+pub(crate) struct AccountPages {
+    pages: BTreeMap<ClientId, Arc<Page>>,
+}
+
+impl AccountPages {
+    pub(crate) fn new<'a>(accounts: impl IntoIterator<Item = &'a Account>) -> Self {
+        let mut pages = Self {
+            pages: BTreeMap::new(),
+        };
+        for account in accounts {
+            pages.update(account);
+        }
+        pages
+    }
+
+    /// Record the current state of `account`
+    pub(crate) fn update(&mut self, account: &Account) {
+        let (page, slot) = locate(account.client_id);
+        let page = self
+            .pages
+            .entry(page)
+            .or_insert_with(|| Arc::new(vec![None; PAGE_SIZE as usize]));
+        Arc::make_mut(page)[slot] = Some(account.clone());
+    }
+
+    /// Drop `client_id`'s account, e.g. once the client has been erased
+    #[cfg(feature = "cluster")]
+    pub(crate) fn remove(&mut self, client_id: ClientId) {
+        let (page, slot) = locate(client_id);
+        if let Some(page) = self.pages.get_mut(&page) {
+            Arc::make_mut(page)[slot] = None;
+        }
+    }
+}
+
+/// Accounts of a `ShardedEngine` frozen at the moment the view was taken
+///
+/// The view shares unchanged memory with the live shards, so it is cheap to
+/// take, and it never changes afterwards: reading, filtering or serializing
+/// it takes as long as it needs without holding up transactions. Each shard's
+/// part reflects every transaction that shard acknowledged before the view
+/// was taken (see `ShardedEngine::accounts_view`).
+pub struct AccountsView {
+    shards: Vec<AccountPages>,
+}
+
+impl AccountsView {
+    pub(crate) fn new(shards: Vec<AccountPages>) -> Self {
+        Self { shards }
+    }
+
+    /// Look up a single client account
+    pub fn get(&self, client_id: ClientId) -> Option<&Account> {
+        let (page, slot) = locate(client_id);
+        self.shards
+            .iter()
+            .find_map(|shard| shard.pages.get(&page)?[slot].as_ref())
+    }
+
+    /// Iterate over all accounts in client ID order
+    pub fn iter(&self) -> impl Iterator<Item = &Account> {
+        let page_numbers: BTreeSet<ClientId> = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.pages.keys().copied())
+            .collect();
+        // A client lives on one shard, so at most one shard has each slot
+        page_numbers.into_iter().flat_map(move |page| {
+            let pages: Vec<&Page> = self
+                .shards
+                .iter()
+                .filter_map(|shard| shard.pages.get(&page).map(|page| &**page))
+                .collect();
+            (0..PAGE_SIZE as usize)
+                .filter_map(move |slot| pages.iter().find_map(|page| page[slot].as_ref()))
+        })
+    }
+
+    /// Number of accounts
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
@@ -0,0 +1,41 @@
+//! Monetary amounts, from `payments_engine_core::amount`, plus the helpers
+//! the engine's storage and limits need
+
+pub use payments_engine_core::amount::*;
+
+/// Number of decimal places an amount needs, ignoring trailing zeros
+#[cfg(not(feature = "fixed-point"))]
+pub(crate) fn decimal_places(amount: Amount) -> u32 {
+    amount.normalize().scale()
+}
+
+#[cfg(feature = "fixed-point")]
+pub(crate) fn decimal_places(amount: Amount) -> u32 {
+    amount.decimal_places()
+}
+
+/// Fixed-size binary form of an amount, for on-disk records
+#[cfg(not(feature = "fixed-point"))]
+pub(crate) fn to_bytes(amount: Amount) -> [u8; 16] {
+    amount.serialize()
+}
+
+#[cfg(feature = "fixed-point")]
+pub(crate) fn to_bytes(amount: Amount) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&amount.minor_units().to_le_bytes());
+    bytes
+}
+
+/// Inverse of `to_bytes`
+#[cfg(not(feature = "fixed-point"))]
+pub(crate) fn from_bytes(bytes: [u8; 16]) -> Amount {
+    rust_decimal::Decimal::deserialize(bytes)
+}
+
+#[cfg(feature = "fixed-point")]
+pub(crate) fn from_bytes(bytes: [u8; 16]) -> Amount {
+    let mut units = [0; 8];
+    units.copy_from_slice(&bytes[..8]);
+    FixedAmount::from_minor_units(i64::from_le_bytes(units))
+}
@@ -0,0 +1,121 @@
+//! Cold storage for deposits past the dispute window
+//!
+//! A `RetentionPolicy` drops stored deposits once they can no longer be
+//! disputed, which keeps the engine's memory bounded but loses the records.
+//! With an archive attached (`PaymentsEngine::set_archive`) each deposit is
+//! written to it before being dropped, so an audit can still fetch it with
+//! `PaymentsEngine::archived_transaction`. Disputes referencing an archived
+//! deposit are rejected as expired all the same. Deposits under dispute are
+//! never dropped, so only settled ones end up archived.
+//!
+//! `FileArchive` keeps the records in a local file. Other stores, such as an
+//! S3 bucket, plug in by implementing `ArchiveBackend`.
+//!
+//! ```
+//! use payments_engine::archive::FileArchive;
+//! use payments_engine::config::{EngineConfig, RetentionPolicy};
+//! use payments_engine::engine::PaymentsEngine;
+//! use payments_engine::models::Transaction;
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let mut engine = PaymentsEngine::with_config(EngineConfig {
+//!     retention: RetentionPolicy {
+//!         max_entries: Some(1),
+//!         max_age: None,
+//!     },
+//!     ..EngineConfig::default()
+//! });
+//! engine.set_archive(FileArchive::open(dir.path().join("archive")).unwrap());
+//!
+//! engine.process_transaction(Transaction::deposit(1, 1, "10".parse().unwrap()));
+//! engine.process_transaction(Transaction::deposit(1, 2, "5".parse().unwrap()));
+//! let archived = engine.archived_transaction(1).unwrap().unwrap();
+//! assert_eq!(archived.amount, "10".parse().unwrap());
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::error::Result;
+use crate::models::{StoredTransaction, TxId};
+use crate::tx_store::{self, RECORD_SIZE};
+
+/// Where archived deposits are kept
+///
+/// Stores are called from the thread applying transactions, right after the
+/// transaction that pushed the deposit out of the retention window.
+pub trait ArchiveBackend: Send {
+    /// Keep `stored_tx`, replacing any record with the same transaction ID
+    ///
+    /// Deposits are archived again when a WAL is replayed, so storing the
+    /// same record twice must be harmless. On `Err` the engine keeps the
+    /// deposit and tries again after a later transaction.
+    fn store(&mut self, stored_tx: &StoredTransaction) -> Result<()>;
+
+    /// The record kept for `tx_id`, `None` if there is none
+    fn fetch(&self, tx_id: TxId) -> Result<Option<StoredTransaction>>;
+
+    /// Make every stored record durable
+    ///
+    /// Called before a WAL checkpoint lets go of the entries behind them.
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An archive in a local file
+///
+/// Records are fixed-size and placed by transaction ID, like the spill file
+/// of `PaymentsEngine::spill_transactions`, so the file is its own index and
+/// takes little disk space when IDs are dense. Unlike the spill file it is
+/// kept across runs. Stores aren't synced one by one; `sync` does that.
+pub struct FileArchive {
+    file: File,
+}
+
+impl FileArchive {
+    /// Open the archive at `path`, creating it if it doesn't exist
+    ///
+    /// Several archives may be opened on the same file, e.g. one per shard:
+    /// transaction IDs are unique, so they never write the same record.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Ok(Self { file })
+    }
+}
+
+impl ArchiveBackend for FileArchive {
+    fn store(&mut self, stored_tx: &StoredTransaction) -> Result<()> {
+        self.file
+            .seek(SeekFrom::Start(tx_store::offset(stored_tx.tx_id)?))?;
+        self.file.write_all(&tx_store::encode_record(stored_tx))?;
+        Ok(())
+    }
+
+    fn fetch(&self, tx_id: TxId) -> Result<Option<StoredTransaction>> {
+        let mut record = [0u8; RECORD_SIZE as usize];
+        let Ok(offset) = tx_store::offset(tx_id) else {
+            // No record can be stored for it
+            return Ok(None);
+        };
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        match file.read_exact(&mut record) {
+            Ok(()) => Ok(tx_store::decode_record(tx_id, &record)),
+            // Past the end of the file, so never archived
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
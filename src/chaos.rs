@@ -0,0 +1,116 @@
+//! Fault injection for rehearsing failures in a running server
+//!
+//! Only built with the `chaos` feature, which production builds leave out.
+//! A server config's `[chaos]` section then turns on any mix of:
+//!
+//! - failed transactions, as if their WAL append had failed: the client gets
+//!   an error and nothing is applied or logged
+//! - artificial latency on the shards, which also backs up their queues
+//! - TCP and WebSocket connections closed right after a transaction was
+//!   processed, before it is acknowledged
+//!
+//! Faults are drawn from a seeded generator, so a rehearsal can be repeated.
+
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use crate::error::{EngineError, Result};
+use crate::workload::SplitMix64;
+
+/// Faults to inject, as read from a server config's `[chaos]` section
+///
+/// Rates are the share of transactions affected, from 0 to 1.
+///
+/// ```toml
+/// [chaos]
+/// persistence_failure_rate = 0.01
+/// max_shard_latency_ms = 20
+/// dropped_connection_rate = 0.005
+/// seed = 42
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChaosConfig {
+    /// Transactions failed as if their WAL append had failed
+    #[serde(default)]
+    pub persistence_failure_rate: f64,
+    /// Each transaction waits up to this long on its shard before it runs
+    #[serde(default)]
+    pub max_shard_latency_ms: u64,
+    /// Streamed transactions whose connection is closed instead of acknowledged
+    #[serde(default)]
+    pub dropped_connection_rate: f64,
+    /// Seed for drawing faults; taken from the clock if not set
+    pub seed: Option<u64>,
+}
+
+impl ChaosConfig {
+    /// Check every rate is between 0 and 1
+    pub fn validate(&self) -> Result<()> {
+        let rates = [
+            ("persistence_failure_rate", self.persistence_failure_rate),
+            ("dropped_connection_rate", self.dropped_connection_rate),
+        ];
+        for (name, rate) in rates {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(EngineError::InvalidConfig(format!(
+                    "chaos.{} must be between 0 and 1, got {}",
+                    name, rate
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The faults currently injected and the generator drawing them
+///
+/// Shared by every handle and shard of a `ShardedEngine`.
+#[derive(Default)]
+pub(crate) struct Chaos {
+    active: Option<(ChaosConfig, SplitMix64)>,
+}
+
+impl Chaos {
+    /// Start injecting `config`'s faults; `None` stops all of them
+    pub(crate) fn set(&mut self, config: Option<ChaosConfig>) {
+        self.active = config.map(|config| {
+            let seed = config.seed.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+            });
+            (config, SplitMix64::new(seed))
+        });
+    }
+
+    /// Whether the next transaction should fail as if persistence had
+    pub(crate) fn fails_persistence(&mut self) -> bool {
+        self.draw(|config| config.persistence_failure_rate)
+    }
+
+    /// How long the next transaction should wait on its shard
+    pub(crate) fn shard_latency(&mut self) -> Option<Duration> {
+        let (config, rng) = self.active.as_mut()?;
+        let max = config.max_shard_latency_ms;
+        (max > 0).then(|| Duration::from_millis(rng.next_u64() % (max + 1)))
+    }
+
+    /// Whether the connection that sent the last transaction should be closed
+    pub(crate) fn drops_connection(&mut self) -> bool {
+        self.draw(|config| config.dropped_connection_rate)
+    }
+
+    fn draw(&mut self, rate: impl FnOnce(&ChaosConfig) -> f64) -> bool {
+        self.active
+            .as_mut()
+            .is_some_and(|(config, rng)| rng.next_f64() < rate(config))
+    }
+}
+
+/// The error a transaction fails with when chaos fails its persistence
+pub(crate) fn injected_persistence_failure() -> EngineError {
+    io::Error::other("injected persistence failure (chaos mode)").into()
+}
@@ -1,9 +1,31 @@
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{broadcast, RwLock};
 
-use crate::models::{Account, Transaction};
-use crate::persistence::StubPersistence;
-use crate::persistent_engine::PersistentEngine;
+use crate::account_view::AccountsView;
+use crate::archive::{ArchiveBackend, FileArchive};
+#[cfg(feature = "chaos")]
+use crate::chaos::{Chaos, ChaosConfig};
+use crate::config::EngineConfig;
+use crate::engine::{Engine, EngineStats, FundsSummary, PaymentsEngine, Simulation};
+use crate::error::{EngineError, Result};
+use crate::events::AccountEvent;
+use crate::hooks::Hooks;
+use crate::models::{Account, ClientId, Hold, StoredTransaction, Transaction, TxId};
+use crate::outcome::{Outcome, RejectReason};
+#[cfg(feature = "cluster")]
+use crate::persistence::replicated::ReplicatedPersistence;
+#[cfg(feature = "cluster")]
+use crate::persistence::LogEntry;
+use crate::persistence::{BackgroundPersistence, PersistenceBackend, StubPersistence};
+use crate::persistent_engine::{CheckpointPolicy, IntegrityCheck, PersistentEngine};
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::shard::{Command, ShardHandle};
+use crate::state::EngineState;
 
 /// Thread-safe sharded engine for high-concurrency workloads
 ///
@@ -11,13 +33,14 @@ use crate::persistent_engine::PersistentEngine;
 ///
 /// 1. **Tokio async**: Handles many concurrent connections efficiently
 /// 2. **Sharding**: Partitions clients across N independent engines
-///    - Reduces lock contention
+///    - Each shard is a task with its own queue, so there are no locks
 ///    - Enables parallel processing on multiple cores
 ///    - Scales linearly with number of shards
 ///
 /// # Sharding Strategy
 ///
-/// Clients are distributed across shards by `client_id % num_shards`.
+/// Clients are distributed across shards by a `ShardKey` function,
+/// `client_id % num_shards` unless configured otherwise (see `ShardOptions`).
 /// This ensures:
 /// - Same client always goes to same shard (consistency)
 /// - Different clients can process in parallel (performance)
@@ -27,8 +50,7 @@ use crate::persistent_engine::PersistentEngine;
 ///
 /// ```no_run
 /// use payments_engine::concurrent_engine::ShardedEngine;
-/// use payments_engine::models::{Transaction, TransactionType};
-/// use rust_decimal_macros::dec;
+/// use payments_engine::models::Transaction;
 ///
 /// #[tokio::main]
 /// async fn main() {
@@ -40,12 +62,7 @@ use crate::persistent_engine::PersistentEngine;
 ///
 ///     // Process transactions concurrently
 ///     tokio::spawn(async move {
-///         let tx = Transaction {
-///             tx_type: TransactionType::Deposit,
-///             client: 1,
-///             tx: 1,
-///             amount: Some(dec!(100.0)),
-///         };
+///         let tx = Transaction::deposit(1, 1, "100.0".parse().unwrap());
 ///         // This will be routed to the appropriate shard
 ///         engine_clone.process_transaction(tx).await;
 ///     });
@@ -61,15 +78,161 @@ use crate::persistent_engine::PersistentEngine;
 ///
 /// # Architecture
 ///
-/// Each shard combines:
+/// Each shard is a tokio task (an actor) that owns:
 /// - **PersistentEngine** - WAL pattern for crash recovery
-/// - **StubPersistence** - Demonstrates persistence without file I/O
-/// - **Async RwLock** - Thread-safe concurrent access
+/// - **StubPersistence** - Demonstrates persistence without file I/O, or a
+///   **BackgroundPersistence** WAL in its own directory for engines built by
+///   `recover`
 ///
-/// This demonstrates both concurrency AND persistence working together.
+/// Handles talk to a shard through a bounded mpsc queue and get each result
+/// back on a oneshot channel. Only the shard's task touches its engine, so
+/// there are no locks to contend on, transactions for a client are applied in
+/// the order they were queued, and a full queue makes producers wait instead
+/// of piling up work in memory.
+///
+/// Shard tasks are spawned on the current tokio runtime, so engines must be
+/// created from within one. The tasks stop once every handle is dropped.
+///
+/// The shard count can change while the engine runs (see `resize`).
 pub struct ShardedEngine {
-    shards: Vec<Arc<RwLock<PersistentEngine<StubPersistence>>>>,
-    num_shards: usize,
+    /// Every transaction and query holds a read guard while it runs, so
+    /// `resize` and `shutdown` can wait for in-flight ones to drain
+    shards: Arc<RwLock<ShardSet>>,
+    shard_key: ShardKey,
+    /// Publishes an `AccountEvent` for every applied transaction
+    events: broadcast::Sender<AccountEvent>,
+    /// Per-client rate limiting, applied before transactions reach a shard
+    limiter: Arc<Mutex<RateLimiter>>,
+    /// Transactions the rate limiter turned away
+    rate_limited: Arc<AtomicU64>,
+    /// Faults injected into the shards and connections
+    #[cfg(feature = "chaos")]
+    chaos: Arc<Mutex<Chaos>>,
+}
+
+/// Activity of one shard, as reported by `ShardedEngine::shard_metrics`
+///
+/// Counts start from zero when the shard is created, including by `resize`.
+/// Transactions turned away by the rate limiter never reach a shard and are
+/// not counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardMetrics {
+    /// Index of the shard, as returned by the engine's `ShardKey`
+    pub shard: usize,
+    /// Transactions applied
+    pub applied: u64,
+    /// Transactions rejected by the engine
+    pub rejected: u64,
+    /// Transactions that failed with an error, e.g. from persistence
+    pub failed: u64,
+    /// Commands waiting in the shard's queue right now
+    pub queue_depth: usize,
+}
+
+impl ShardMetrics {
+    /// Transactions the shard has finished with, whatever the result
+    pub fn processed(&self) -> u64 {
+        self.applied + self.rejected + self.failed
+    }
+}
+
+/// The shards currently serving an engine's clients
+struct ShardSet {
+    handles: Vec<ShardHandle>,
+    /// Current configuration, given to shards created by `resize`
+    config: EngineConfig,
+    /// Current hooks, given to shards created by `resize`
+    hooks: Hooks,
+    /// File the shards archive deposits to, also given to shards created
+    /// by `resize`
+    archive: Option<PathBuf>,
+    /// Directory of per-shard WALs, for engines built by `recover`
+    wal_dir: Option<PathBuf>,
+    /// Whether new transactions are accepted
+    accepting: bool,
+    /// Whether transactions are refused with `EngineError::ReadOnly`
+    read_only: bool,
+}
+
+impl ShardSet {
+    /// Shard owning `client_id`, `None` once the shards have been finished
+    /// (see `ShardedEngine::into_accounts`)
+    fn shard(&self, shard_key: ShardKey, client_id: ClientId) -> Option<&ShardHandle> {
+        if self.handles.is_empty() {
+            return None;
+        }
+        Some(&self.handles[shard_key(client_id, self.handles.len())])
+    }
+}
+
+/// Number of account events buffered per subscriber before slow ones start missing events
+const EVENT_BUFFER: usize = 1024;
+
+/// Number of commands each shard queues before senders have to wait
+const SHARD_QUEUE_CAPACITY: usize = 1024;
+
+/// A shard task only stops when all handles are gone or it panicked
+const SHARD_STOPPED: &str = "shard task stopped";
+
+/// Name of the WAL file inside each shard's directory
+const WAL_FILE: &str = "wal.log";
+
+/// Maps a client ID to the index of the shard owning it, in `0..num_shards`
+///
+/// Must be deterministic: every transaction for a client has to reach the
+/// same shard.
+pub type ShardKey = fn(client_id: ClientId, num_shards: usize) -> usize;
+
+/// Shard by `client_id % num_shards`
+///
+/// Spreads contiguous client IDs perfectly, but skews when IDs share a
+/// stride with the shard count (e.g. only even IDs across an even number of
+/// shards).
+pub fn modulo_shard_key(client_id: ClientId, num_shards: usize) -> usize {
+    (client_id as usize) % num_shards
+}
+
+/// Shard by a multiplicative hash of the client ID
+///
+/// Scatters IDs that follow a pattern evenly across shards. The hash is fixed,
+/// so assignments are the same across runs.
+pub fn hashed_shard_key(client_id: ClientId, num_shards: usize) -> usize {
+    // Fibonacci hashing: the high bits of the product are well mixed
+    #[allow(clippy::useless_conversion)] // ClientId may already be u64
+    let hash = u64::from(client_id).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+    (hash as usize) % num_shards
+}
+
+/// Default shard count: one per available CPU
+pub fn default_shard_count() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// How a `ShardedEngine` partitions its clients
+#[derive(Debug, Clone, Copy)]
+pub struct ShardOptions {
+    /// Number of independent engine shards; must be at least 1
+    pub num_shards: usize,
+    /// Function picking each client's shard
+    pub shard_key: ShardKey,
+    /// When each shard checkpoints its WAL; only used by engines recovered
+    /// from a WAL directory
+    pub checkpoints: CheckpointPolicy,
+    /// What recovering from a WAL directory does when a shard's state
+    /// doesn't match a digest in its log
+    pub integrity: IntegrityCheck,
+}
+
+impl Default for ShardOptions {
+    /// One shard per CPU, sharded by `modulo_shard_key`
+    fn default() -> Self {
+        Self {
+            num_shards: default_shard_count(),
+            shard_key: modulo_shard_key,
+            checkpoints: CheckpointPolicy::default(),
+            integrity: IntegrityCheck::Off,
+        }
+    }
 }
 
 impl ShardedEngine {
@@ -82,80 +245,359 @@ impl ShardedEngine {
     ///   - Recommended: 2× number of CPU cores
     ///   - Typical: 8-16 shards
     ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero or if called outside a tokio runtime.
+    ///
     /// # Example
     ///
     /// ```
     /// use payments_engine::concurrent_engine::ShardedEngine;
     ///
+    /// # #[tokio::main]
+    /// # async fn main() {
     /// // Create engine with 8 shards
     /// let engine = ShardedEngine::new(8);
+    /// # }
     /// ```
     pub fn new(num_shards: usize) -> Self {
-        assert!(num_shards > 0, "num_shards must be at least 1");
+        Self::with_options(ShardOptions {
+            num_shards,
+            ..ShardOptions::default()
+        })
+    }
 
-        let shards = (0..num_shards)
-            .map(|_| {
-                let persistence = StubPersistence::new();
-                let persistent_engine = PersistentEngine::new(persistence);
-                Arc::new(RwLock::new(persistent_engine))
-            })
+    /// Create a new sharded engine with the given shard count and shard key
+    ///
+    /// # Panics
+    ///
+    /// Panics if `options.num_shards` is zero or if called outside a tokio
+    /// runtime.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use payments_engine::concurrent_engine::{hashed_shard_key, ShardOptions, ShardedEngine};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// // One shard per CPU, with clients scattered by hash
+    /// let engine = ShardedEngine::with_options(ShardOptions {
+    ///     shard_key: hashed_shard_key,
+    ///     ..ShardOptions::default()
+    /// });
+    /// # }
+    /// ```
+    pub fn with_options(options: ShardOptions) -> Self {
+        assert!(options.num_shards > 0, "num_shards must be at least 1");
+
+        let engines = (0..options.num_shards)
+            .map(|_| with_stub_persistence(PaymentsEngine::new()))
             .collect();
+        Self::from_engines(engines, options.shard_key, None)
+    }
+
+    /// Create a sharded engine that continues from previously saved state
+    ///
+    /// The state is split across shards by client (see `partition`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero or if called outside a tokio runtime.
+    pub fn from_state(num_shards: usize, state: EngineState) -> Self {
+        Self::from_state_with_options(
+            ShardOptions {
+                num_shards,
+                ..ShardOptions::default()
+            },
+            state,
+        )
+    }
+
+    /// Create a sharded engine from saved state with the given shard count
+    /// and shard key
+    ///
+    /// The state doesn't depend on how it was sharded before, so both may
+    /// differ from the run that saved it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `options.num_shards` is zero or if called outside a tokio
+    /// runtime.
+    pub fn from_state_with_options(options: ShardOptions, state: EngineState) -> Self {
+        assert!(options.num_shards > 0, "num_shards must be at least 1");
 
-        Self { shards, num_shards }
+        let engines = partition(state, options.num_shards, options.shard_key)
+            .into_iter()
+            .map(|shard_state| with_stub_persistence(PaymentsEngine::from_state(shard_state)))
+            .collect();
+        Self::from_engines(engines, options.shard_key, None)
     }
 
-    /// Determine which shard handles this client
+    /// Recover a sharded engine from per-shard WALs in `dir`
+    ///
+    /// Each shard `i` logs to `dir/shard-<i>/wal.log`. Those logs are replayed
+    /// on one thread per shard, and the shards keep appending to them, so the
+    /// engine survives a restart by calling this again with the same `dir`.
+    /// A missing or empty `dir` starts a fresh engine.
+    ///
+    /// Each log is synced by its own writer thread. A shard goes on to the
+    /// next transaction while the previous ones are being synced, but a
+    /// transaction is only acknowledged once its entry is on disk.
     ///
-    /// Uses modulo to distribute clients evenly across shards
-    fn shard_for_client(&self, client_id: u16) -> usize {
-        (client_id as usize) % self.num_shards
+    /// Uses `modulo_shard_key`; see `recover_with_options` for other keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_shards` is zero or if called outside a tokio runtime.
+    pub fn recover(dir: impl AsRef<Path>, num_shards: usize) -> Result<Self> {
+        Self::recover_with_options(
+            dir,
+            ShardOptions {
+                num_shards,
+                ..ShardOptions::default()
+            },
+        )
+    }
+
+    /// Recover a sharded engine from per-shard WALs in `dir` (see `recover`)
+    ///
+    /// Each log only holds its own shard's clients, so the shard count and
+    /// shard key must be the ones the logs were written with. A different
+    /// shard count is refused with `EngineError::InvalidConfig`; a different
+    /// shard key can't be detected and would split clients' histories.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `options.num_shards` is zero or if called outside a tokio
+    /// runtime.
+    pub fn recover_with_options(dir: impl AsRef<Path>, options: ShardOptions) -> Result<Self> {
+        Self::recover_shards(dir.as_ref(), options, |_, path| {
+            BackgroundPersistence::open(path)
+        })
+    }
+
+    /// Recover a sharded engine from per-shard WALs in `dir` (see
+    /// `recover_with_options`), replicating every entry to `followers`
+    ///
+    /// Each follower address is a `persistence::replicated::Follower`, and a
+    /// transaction is only acknowledged once a majority of this node and the
+    /// followers hold it. A follower's directory can be recovered with the
+    /// same options if this node is lost.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `options.num_shards` is zero or if called outside a tokio
+    /// runtime.
+    #[cfg(feature = "cluster")]
+    pub fn recover_replicated(
+        dir: impl AsRef<Path>,
+        options: ShardOptions,
+        followers: &[String],
+    ) -> Result<Self> {
+        Self::recover_shards(dir.as_ref(), options, |shard, path| {
+            ReplicatedPersistence::new(
+                BackgroundPersistence::open(path)?,
+                shard,
+                followers.to_vec(),
+            )
+        })
+    }
+
+    /// Recover each shard from its WAL in `dir`, logging through the backend
+    /// `open` returns for a shard and its WAL's path
+    fn recover_shards<P, F>(dir: &Path, options: ShardOptions, open: F) -> Result<Self>
+    where
+        P: PersistenceBackend + 'static,
+        F: Fn(usize, PathBuf) -> Result<P> + Sync,
+    {
+        assert!(options.num_shards > 0, "num_shards must be at least 1");
+        fs::create_dir_all(dir)?;
+
+        let existing = existing_shard_dirs(dir);
+        if existing != 0 && existing != options.num_shards {
+            return Err(EngineError::InvalidConfig(format!(
+                "{} holds WALs for {} shards, not {}",
+                dir.display(),
+                existing,
+                options.num_shards
+            )));
+        }
+
+        let engines = std::thread::scope(|scope| {
+            let open = &open;
+            let replays: Vec<_> = (0..options.num_shards)
+                .map(|shard| {
+                    scope.spawn(move || {
+                        fs::create_dir_all(shard_dir(dir, shard))?;
+                        let mut engine = PersistentEngine::recover_checked(
+                            open(shard, shard_wal_path(dir, shard))?,
+                            options.integrity,
+                        )?;
+                        engine.set_checkpoint_policy(options.checkpoints);
+                        Ok(engine)
+                    })
+                })
+                .collect();
+
+            replays
+                .into_iter()
+                .map(|replay| replay.join().expect("WAL replay panicked"))
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        Ok(Self::from_engines(
+            engines,
+            options.shard_key,
+            Some(dir.to_path_buf()),
+        ))
+    }
+
+    /// Run each engine, with its own persistence backend, as one shard
+    ///
+    /// For backends other than the built-in ones, e.g. an in-memory log in
+    /// tests. Engine `i` serves the clients `shard_key` maps to shard `i` of
+    /// `engines.len()`, so each should only hold state for those clients;
+    /// `PersistentEngine::recover` on a log written by the same shard does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `engines` is empty or if called outside a tokio runtime.
+    pub fn from_shards<P: PersistenceBackend + 'static>(
+        engines: Vec<PersistentEngine<P>>,
+        shard_key: ShardKey,
+    ) -> Self {
+        assert!(!engines.is_empty(), "num_shards must be at least 1");
+        Self::from_engines(engines, shard_key, None)
+    }
+
+    /// Run each engine as a shard of a new sharded engine
+    fn from_engines<P: PersistenceBackend + 'static>(
+        engines: Vec<PersistentEngine<P>>,
+        shard_key: ShardKey,
+        wal_dir: Option<PathBuf>,
+    ) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        #[cfg(feature = "chaos")]
+        let chaos = Arc::new(Mutex::new(Chaos::default()));
+        let handles = spawn_shards(
+            engines,
+            &events,
+            #[cfg(feature = "chaos")]
+            &chaos,
+        );
+
+        Self {
+            shards: Arc::new(RwLock::new(ShardSet {
+                handles,
+                config: EngineConfig::default(),
+                hooks: Hooks::new(),
+                archive: None,
+                wal_dir,
+                accepting: true,
+                read_only: false,
+            })),
+            shard_key,
+            events,
+            limiter: Arc::new(Mutex::new(RateLimiter::default())),
+            rate_limited: Arc::new(AtomicU64::new(0)),
+            #[cfg(feature = "chaos")]
+            chaos,
+        }
+    }
+
+    /// Index `client_id` would have among `num_shards` shards under this
+    /// engine's shard key
+    pub(crate) fn shard_for(&self, client_id: ClientId, num_shards: usize) -> usize {
+        (self.shard_key)(client_id, num_shards)
     }
 
     /// Process a transaction asynchronously
     ///
-    /// Routes the transaction to the appropriate shard based on client_id.
-    /// Multiple transactions on different shards can process in parallel.
+    /// Routes the transaction to the appropriate shard based on client_id and
+    /// waits for the shard to acknowledge it. Multiple transactions on
+    /// different shards can process in parallel.
     ///
     /// # Arguments
     ///
     /// * `tx` - Transaction to process
     ///
+    /// # Returns
+    ///
+    /// Whether the transaction was applied or rejected, `Err` if persistence
+    /// fails, the engine has been shut down or the shard task has stopped
+    ///
     /// # Example
     ///
     /// ```no_run
     /// # use payments_engine::concurrent_engine::ShardedEngine;
-    /// # use payments_engine::models::{Transaction, TransactionType};
-    /// # use rust_decimal_macros::dec;
+    /// # use payments_engine::models::Transaction;
     /// # #[tokio::main]
     /// # async fn main() {
     /// let engine = ShardedEngine::new(8);
     ///
-    /// let tx = Transaction {
-    ///     tx_type: TransactionType::Deposit,
-    ///     client: 1,
-    ///     tx: 1,
-    ///     amount: Some(dec!(100.0)),
-    /// };
+    /// let tx = Transaction::deposit(1, 1, "100.0".parse().unwrap());
     ///
     /// engine.process_transaction(tx).await;
     /// # }
     /// ```
-    pub async fn process_transaction(&self, tx: Transaction) -> crate::error::Result<()> {
-        let shard_id = self.shard_for_client(tx.client);
+    pub async fn process_transaction(&self, tx: Transaction) -> Result<Outcome> {
+        self.process(tx, None).await
+    }
 
-        // Acquire write lock for this shard only
-        // Other shards can process concurrently
-        let mut engine = self.shards[shard_id].write().await;
+    /// Process a transaction only if the client's account is still at
+    /// `expected_version`
+    ///
+    /// The check runs on the client's shard right before the transaction, so
+    /// nothing can change the account in between. A stale version is
+    /// rejected with `RejectReason::VersionMismatch` without touching the WAL.
+    /// See `PaymentsEngine::process_transaction_if_version`.
+    pub async fn process_transaction_if_version(
+        &self,
+        tx: Transaction,
+        expected_version: u64,
+    ) -> Result<Outcome> {
+        self.process(tx, Some(expected_version)).await
+    }
 
-        // Process with persistence (WAL pattern)
-        engine.process_transaction(tx)?;
+    async fn process(&self, tx: Transaction, expected_version: Option<u64>) -> Result<Outcome> {
+        // Held until the transaction is done so resize and shutdown can wait for it
+        let shards = self.shards.read().await;
+        if !shards.accepting {
+            return Err(EngineError::ShuttingDown);
+        }
+        if shards.read_only {
+            return Err(EngineError::ReadOnly);
+        }
 
-        Ok(())
+        let allowed = self
+            .limiter
+            .lock()
+            .expect("rate limiter lock poisoned")
+            .try_acquire(tx.client, Instant::now());
+        if !allowed {
+            self.rate_limited.fetch_add(1, Ordering::Relaxed);
+            return Ok(Outcome::Rejected(RejectReason::RateLimited));
+        }
+
+        // Queue on this shard only; other shards process concurrently
+        let Some(shard) = shards.shard(self.shard_key, tx.client) else {
+            return Err(EngineError::ShuttingDown);
+        };
+        shard
+            .request(|reply| Command::Process {
+                tx,
+                expected_version,
+                reply,
+            })
+            .await
+            .unwrap_or(Err(EngineError::ShardStopped))
     }
 
     /// Get account balance for a client (read-only query)
     ///
-    /// Uses read lock - allows multiple concurrent reads on the same shard
+    /// Queued behind the shard's pending transactions, so it reflects every
+    /// transaction acknowledged before the call.
     ///
     /// # Arguments
     ///
@@ -178,24 +620,42 @@ impl ShardedEngine {
     /// }
     /// # }
     /// ```
-    pub async fn get_account(&self, client_id: u16) -> Option<Account> {
-        let shard_id = self.shard_for_client(client_id);
-
-        // Read lock - doesn't block other readers
-        let persistent_engine = self.shards[shard_id].read().await;
+    /// What processing `tx` would do, without changing anything (see
+    /// `PaymentsEngine::simulate`)
+    ///
+    /// Worked out on the client's shard after the transactions already
+    /// queued there. The rate limit is neither checked nor charged, so a
+    /// transaction that simulates as applied can still be rejected with
+    /// `RejectReason::RateLimited`.
+    pub async fn simulate(&self, tx: Transaction) -> Result<Simulation> {
+        let shards = self.shards.read().await;
+        let shard = shards
+            .shard(self.shard_key, tx.client)
+            .ok_or(EngineError::ShuttingDown)?;
+        Ok(shard
+            .request(|reply| Command::Simulate { tx, reply })
+            .await
+            .expect(SHARD_STOPPED))
+    }
 
-        persistent_engine
-            .engine()
-            .get_accounts()
-            .iter()
-            .find(|acc| acc.client_id == client_id)
-            .map(|acc| (*acc).clone())
+    pub async fn get_account(&self, client_id: ClientId) -> Option<Account> {
+        let shards = self.shards.read().await;
+        shards
+            .shard(self.shard_key, client_id)?
+            .request(|reply| Command::GetAccount { client_id, reply })
+            .await
+            .expect(SHARD_STOPPED)
     }
 
     /// Get all accounts from all shards
     ///
     /// Reads from all shards and combines results, sorted by client_id
     ///
+    /// Transactions keep flowing while the shards are read, so one shard's
+    /// accounts may be from before a transaction and another's from after a
+    /// later one. Use `snapshot` when the accounts must all be from the same
+    /// moment.
+    ///
     /// # Returns
     ///
     /// Vector of all accounts across all shards
@@ -215,38 +675,111 @@ impl ShardedEngine {
     /// # }
     /// ```
     pub async fn get_all_accounts(&self) -> Vec<Account> {
-        let mut all_accounts = Vec::new();
+        self.accounts_view().await.iter().cloned().collect()
+    }
 
-        // Read from all shards concurrently using join_all
-        let futures: Vec<_> = self
-            .shards
+    /// Take a frozen view of all accounts for expensive reads
+    ///
+    /// Each shard hands over a copy-on-write view of its accounts, which costs
+    /// the same however many accounts it holds, and goes straight back to
+    /// processing. Dumping, filtering or serializing the view afterwards
+    /// doesn't hold up any shard, and later transactions don't show up in it.
+    /// Each shard's part reflects the transactions that shard acknowledged
+    /// before the view; use `snapshot` for a single point in time across
+    /// shards.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use payments_engine::concurrent_engine::ShardedEngine;
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let engine = ShardedEngine::new(8);
+    /// let view = engine.accounts_view().await;
+    /// for account in view.iter().filter(|a| a.locked) {
+    ///     println!("Client {} is locked", account.client_id);
+    /// }
+    /// # }
+    /// ```
+    pub async fn accounts_view(&self) -> AccountsView {
+        let shards = self.shards.read().await;
+        collect_views(&shards.handles).await
+    }
+
+    /// Get all accounts as of a single point in time, sorted by client_id
+    ///
+    /// Intake pauses briefly: transactions in flight finish, every shard
+    /// hands over a view of its accounts while no new ones can start, and
+    /// then intake resumes. The result reflects exactly the transactions
+    /// acknowledged before the snapshot, on every shard. The accounts are
+    /// copied out of the views after intake resumes, so the pause doesn't
+    /// grow with the number of accounts.
+    pub async fn snapshot(&self) -> Vec<Account> {
+        let view = {
+            // Exclusive access: no transaction runs while the views are taken
+            let shards = self.shards.write().await;
+            collect_views(&shards.handles).await
+        };
+        view.iter().cloned().collect()
+    }
+
+    /// Capture the whole engine state as of a single point in time, e.g. to
+    /// save as a state file while the engine keeps running
+    ///
+    /// Isolated the same way as `snapshot`: transactions in flight finish and
+    /// no new ones start until every shard has flushed its persistence and
+    /// handed over its state. Unlike `snapshot`, the pause includes copying
+    /// each shard's state. Fails with `EngineError::ShuttingDown` after
+    /// `shutdown`.
+    pub async fn snapshot_state(&self) -> Result<EngineState> {
+        let shards = self.shards.write().await;
+        if !shards.accepting {
+            return Err(EngineError::ShuttingDown);
+        }
+        checkpoint(&shards.handles).await
+    }
+
+    /// Get all deposits currently under dispute, across all shards
+    ///
+    /// # Returns
+    ///
+    /// Disputed transactions sorted by transaction ID
+    pub async fn get_open_disputes(&self) -> Vec<StoredTransaction> {
+        let shards = self.shards.read().await;
+        let futures: Vec<_> = shards
+            .handles
             .iter()
-            .map(|shard| async move {
-                let persistent_engine = shard.read().await;
-                persistent_engine
-                    .engine()
-                    .get_accounts()
-                    .iter()
-                    .map(|acc| (*acc).clone())
-                    .collect::<Vec<_>>()
-            })
+            .map(|shard| shard.request(|reply| Command::OpenDisputes { reply }))
             .collect();
 
-        for accounts in futures::future::join_all(futures).await {
-            all_accounts.extend(accounts);
-        }
+        let mut disputes: Vec<StoredTransaction> = futures::future::join_all(futures)
+            .await
+            .into_iter()
+            .flat_map(|disputes| disputes.expect(SHARD_STOPPED))
+            .collect();
+        disputes.sort_by_key(|d| d.tx_id);
 
-        // Sort by client_id for deterministic output
-        all_accounts.sort_by_key(|a| a.client_id);
+        disputes
+    }
 
-        all_accounts
+    /// Holds making up a client's held balance, sorted by the disputed
+    /// deposit's ID (see `PaymentsEngine::holds`)
+    pub async fn holds(&self, client_id: ClientId) -> Vec<Hold> {
+        let shards = self.shards.read().await;
+        let Some(shard) = shards.shard(self.shard_key, client_id) else {
+            return Vec::new();
+        };
+        shard
+            .request(|reply| Command::Holds { client_id, reply })
+            .await
+            .expect(SHARD_STOPPED)
     }
 
     /// Clone handle for sharing across tasks
     ///
     /// Creates a new handle to the same underlying shards.
-    /// This is cheap (just clones Arcs) and allows sharing the engine
-    /// across multiple tokio tasks.
+    /// This is cheap (just clones channel senders and Arcs) and allows
+    /// sharing the engine across multiple tokio tasks.
     ///
     /// # Example
     ///
@@ -272,19 +805,534 @@ impl ShardedEngine {
     pub fn clone_handle(&self) -> Self {
         Self {
             shards: self.shards.clone(),
-            num_shards: self.num_shards,
+            shard_key: self.shard_key,
+            events: self.events.clone(),
+            limiter: self.limiter.clone(),
+            rate_limited: self.rate_limited.clone(),
+            #[cfg(feature = "chaos")]
+            chaos: self.chaos.clone(),
+        }
+    }
+
+    /// Replace the configuration of every shard (see `PaymentsEngine::set_config`)
+    ///
+    /// Waits for in-flight transactions, then switches every shard over; no
+    /// state is rebuilt, so this is cheap enough to run on every config file
+    /// change.
+    pub async fn set_config(&self, config: EngineConfig) {
+        let mut shards = self.shards.write().await;
+        shards.config = config.clone();
+
+        for shard in &shards.handles {
+            let config = config.clone();
+            shard
+                .request(|reply| Command::SetConfig { config, reply })
+                .await
+                .expect(SHARD_STOPPED);
+        }
+    }
+
+    /// Replace the hooks of every shard (see `PaymentsEngine::set_hooks`)
+    ///
+    /// Waits for in-flight transactions, then switches every shard over.
+    /// Shards run their hooks concurrently, each for its own clients. Like
+    /// `PersistentEngine::set_hooks`, post-commit hooks run before the
+    /// transaction's WAL entry is written, and transactions the rate limiter
+    /// turns away never reach them.
+    pub async fn set_hooks(&self, hooks: Hooks) {
+        let mut shards = self.shards.write().await;
+        shards.hooks = hooks.clone();
+
+        for shard in &shards.handles {
+            let hooks = hooks.clone();
+            shard
+                .request(|reply| Command::SetHooks { hooks, reply })
+                .await
+                .expect(SHARD_STOPPED);
+        }
+    }
+
+    /// Have every shard archive deposits to the file at `path` before the
+    /// retention policy drops them (see `archive`)
+    ///
+    /// All shards share the file, so it stays valid across `resize`.
+    pub async fn archive_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut shards = self.shards.write().await;
+        for shard in &shards.handles {
+            let archive = FileArchive::open(path)?;
+            shard
+                .request(|reply| Command::SetArchive { archive, reply })
+                .await
+                .expect(SHARD_STOPPED);
+        }
+        shards.archive = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// A deposit archived by any shard, for audits; `None` if it wasn't
+    /// archived or there is no archive
+    pub async fn archived_transaction(&self, tx_id: TxId) -> Result<Option<StoredTransaction>> {
+        let path = self.shards.read().await.archive.clone();
+        match path {
+            Some(path) => FileArchive::open(path)?.fetch(tx_id),
+            None => Ok(None),
+        }
+    }
+
+    /// Limit how fast each client may submit transactions; `None` removes the limit
+    ///
+    /// Transactions over the limit are rejected with `RejectReason::RateLimited`
+    /// without reaching the WAL.
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        self.limiter
+            .lock()
+            .expect("rate limiter lock poisoned")
+            .set_limit(limit);
+    }
+
+    /// Inject `config`'s faults from now on; `None` stops injecting them
+    ///
+    /// See the `chaos` module. Shard latency and failed persistence apply to
+    /// every transaction; dropped connections are up to the servers, through
+    /// `chaos_drops_connection`.
+    #[cfg(feature = "chaos")]
+    pub fn set_chaos(&self, config: Option<ChaosConfig>) {
+        self.chaos.lock().expect("chaos lock poisoned").set(config);
+    }
+
+    /// Whether a server should close the connection of the transaction it
+    /// just processed instead of acknowledging it
+    #[cfg(feature = "chaos")]
+    pub fn chaos_drops_connection(&self) -> bool {
+        self.chaos
+            .lock()
+            .expect("chaos lock poisoned")
+            .drops_connection()
+    }
+
+    /// Stop accepting transactions and capture the final state
+    ///
+    /// New transactions (from any handle) fail with `EngineError::ShuttingDown`
+    /// from the moment this is called. Transactions already in flight finish
+    /// first, then every shard's persistence is flushed and the combined state
+    /// of all shards is returned so it can be saved as a final snapshot.
+    /// Queries keep working afterwards.
+    pub async fn shutdown(&self) -> Result<EngineState> {
+        // Waits for in-flight transactions to release their read guards
+        let mut shards = self.shards.write().await;
+        shards.accepting = false;
+
+        checkpoint(&shards.handles).await
+    }
+
+    /// Shut down and move every account out of the shards, sorted by client ID
+    ///
+    /// The consuming counterpart of `get_all_accounts`, like
+    /// `PaymentsEngine::into_accounts`: transactions in flight finish, each
+    /// shard's persistence is flushed, and the shard tasks hand over their
+    /// accounts and stop. Other handles of this engine see no shards from
+    /// then on: transactions fail with `EngineError::ShuttingDown` and queries
+    /// come back empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use payments_engine::concurrent_engine::ShardedEngine;
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let engine = ShardedEngine::new(8);
+    /// // ... process transactions ...
+    /// let accounts = engine.into_accounts().await.unwrap();
+    /// # }
+    /// ```
+    pub async fn into_accounts(self) -> Result<Vec<Account>> {
+        let mut shards = self.shards.write().await;
+        shards.accepting = false;
+
+        let futures: Vec<_> = std::mem::take(&mut shards.handles)
+            .into_iter()
+            .map(|shard| async move { shard.request(|reply| Command::Finish { reply }).await })
+            .collect();
+
+        let mut accounts = Vec::new();
+        for shard_accounts in futures::future::join_all(futures).await {
+            accounts.extend(shard_accounts.ok_or(EngineError::ShardStopped)??);
+        }
+        accounts.sort_by_key(|a| a.client_id);
+
+        Ok(accounts)
+    }
+
+    /// Change the number of shards without restarting
+    ///
+    /// Intake pauses while this runs: transactions in flight finish, the
+    /// state of every shard is captured, and each client's account and
+    /// disputable transactions move to its shard under the new count. New
+    /// transactions wait meanwhile rather than fail. Handles cloned earlier
+    /// see the new shards too.
+    ///
+    /// Fails with `EngineError::ShuttingDown` after `shutdown`, and with
+    /// `EngineError::InvalidConfig` if `num_shards` is zero or the engine
+    /// logs to per-shard WALs (see `recover`), whose contents are tied to the
+    /// shard count.
+    pub async fn resize(&self, num_shards: usize) -> Result<()> {
+        if num_shards == 0 {
+            return Err(EngineError::InvalidConfig(
+                "num_shards must be at least 1".to_string(),
+            ));
+        }
+
+        let mut shards = self.shards.write().await;
+        if !shards.accepting {
+            return Err(EngineError::ShuttingDown);
+        }
+        if let Some(wal_dir) = &shards.wal_dir {
+            return Err(EngineError::InvalidConfig(format!(
+                "cannot resize an engine logging to per-shard WALs in {}",
+                wal_dir.display()
+            )));
+        }
+        if shards.handles.len() == num_shards {
+            return Ok(());
+        }
+
+        let state = checkpoint(&shards.handles).await?;
+        let engines = partition(state, num_shards, self.shard_key)
+            .into_iter()
+            .map(|shard_state| {
+                let mut engine = PaymentsEngine::from_state(shard_state);
+                engine.set_config(shards.config.clone());
+                engine.set_hooks(shards.hooks.clone());
+                if let Some(path) = &shards.archive {
+                    engine.set_archive(FileArchive::open(path)?);
+                }
+                Ok(with_stub_persistence(engine))
+            })
+            .collect::<Result<_>>()?;
+
+        // The old shard tasks exit once their handles are dropped here
+        shards.handles = spawn_shards(
+            engines,
+            &self.events,
+            #[cfg(feature = "chaos")]
+            &self.chaos,
+        );
+        Ok(())
+    }
+
+    /// Subscribe to account changes
+    ///
+    /// The receiver gets an `AccountEvent` for every transaction applied after
+    /// this call, from any handle of this engine. Subscribers that fall more
+    /// than a buffer's worth of events behind skip ahead (`RecvError::Lagged`).
+    pub fn subscribe(&self) -> broadcast::Receiver<AccountEvent> {
+        self.events.subscribe()
+    }
+
+    /// Summary counts across all shards (see `PaymentsEngine::stats`)
+    pub async fn stats(&self) -> EngineStats {
+        let shards = self.shards.read().await;
+        let futures: Vec<_> = shards
+            .handles
+            .iter()
+            .map(|shard| shard.request(|reply| Command::Stats { reply }))
+            .collect();
+
+        let mut stats = EngineStats::default();
+        for shard_stats in futures::future::join_all(futures).await {
+            let shard_stats = shard_stats.expect(SHARD_STOPPED);
+            stats.accounts += shard_stats.accounts;
+            stats.locked_accounts += shard_stats.locked_accounts;
+            stats.open_disputes += shard_stats.open_disputes;
         }
+        stats
+    }
+
+    /// Funds across all shards (see `PaymentsEngine::funds`)
+    pub async fn funds(&self) -> FundsSummary {
+        let shards = self.shards.read().await;
+        let futures: Vec<_> = shards
+            .handles
+            .iter()
+            .map(|shard| shard.request(|reply| Command::Funds { reply }))
+            .collect();
+
+        let mut funds = FundsSummary::default();
+        for shard_funds in futures::future::join_all(futures).await {
+            let shard_funds = shard_funds.expect(SHARD_STOPPED);
+            funds.available += shard_funds.available;
+            funds.held += shard_funds.held;
+            funds.charged_back += shard_funds.charged_back;
+            funds.open_disputes += shard_funds.open_disputes;
+        }
+        funds
+    }
+
+    /// Per-shard counters and queue depths, in shard order
+    ///
+    /// Read without queueing behind the shards, so a backed-up shard shows up
+    /// immediately. A shard with a much higher count or a persistently deep
+    /// queue than the rest is hot; a different `ShardKey` may spread the load
+    /// better.
+    pub async fn shard_metrics(&self) -> Vec<ShardMetrics> {
+        let shards = self.shards.read().await;
+        shards
+            .handles
+            .iter()
+            .enumerate()
+            .map(|(shard, handle)| {
+                let counters = handle.counters();
+                ShardMetrics {
+                    shard,
+                    applied: counters.applied.load(Ordering::Relaxed),
+                    rejected: counters.rejected.load(Ordering::Relaxed),
+                    failed: counters.failed.load(Ordering::Relaxed),
+                    queue_depth: handle.queue_depth(),
+                }
+            })
+            .collect()
+    }
+
+    /// Rejected transactions by reason, most frequent first
+    ///
+    /// Reasons nothing was rejected for are left out. Like `shard_metrics`,
+    /// shard counts start from zero when a shard is created; rate-limited
+    /// transactions, which never reach a shard, count from the engine's
+    /// creation.
+    pub async fn rejection_counts(&self) -> Vec<(RejectReason, u64)> {
+        let shards = self.shards.read().await;
+        let mut counts: Vec<_> = RejectReason::ALL
+            .iter()
+            .map(|&reason| {
+                let rejected: u64 = shards
+                    .handles
+                    .iter()
+                    .map(|handle| {
+                        handle.counters().rejections[reason as usize].load(Ordering::Relaxed)
+                    })
+                    .sum();
+                (reason, rejected)
+            })
+            .collect();
+        counts[RejectReason::RateLimited as usize].1 += self.rate_limited.load(Ordering::Relaxed);
+        counts.retain(|&(_, rejected)| rejected > 0);
+        // Stable, so ties stay in declaration order
+        counts.sort_by_key(|&(_, rejected)| std::cmp::Reverse(rejected));
+        counts
     }
 
     /// Get number of shards
-    pub fn num_shards(&self) -> usize {
-        self.num_shards
+    pub async fn num_shards(&self) -> usize {
+        self.shards.read().await.handles.len()
+    }
+
+    /// Refuse new transactions with `EngineError::ReadOnly`, or take them
+    /// again
+    ///
+    /// Transactions in flight finish first. Queries keep working. A replica
+    /// (see `persistence::shipping`) is read-only until it is promoted.
+    pub async fn set_read_only(&self, read_only: bool) {
+        self.shards.write().await.read_only = read_only;
+    }
+
+    /// Whether new transactions are refused (see `set_read_only`)
+    pub async fn is_read_only(&self) -> bool {
+        self.shards.read().await.read_only
+    }
+
+    /// Apply an entry of shard `shard`'s log on a primary to the same shard
+    /// here, read-only or not and without rate limiting, so the shard's own
+    /// log stays a copy of the primary's
+    #[cfg(feature = "cluster")]
+    pub(crate) async fn apply_shipped(&self, shard: usize, entry: LogEntry) -> Result<()> {
+        let shards = self.shards.read().await;
+        if !shards.accepting {
+            return Err(EngineError::ShuttingDown);
+        }
+        let handle = shards.handles.get(shard).ok_or(EngineError::ShuttingDown)?;
+        let result = match entry {
+            LogEntry::Transaction(tx) => {
+                handle
+                    .request(|reply| Command::Process {
+                        tx,
+                        expected_version: None,
+                        reply,
+                    })
+                    .await
+            }
+            LogEntry::Redaction(client_id) => {
+                handle
+                    .request(|reply| Command::Erase { client_id, reply })
+                    .await
+            }
+            // Followers check their own state, not the leader's
+            LogEntry::Digest(_) => return Ok(()),
+        };
+        result.unwrap_or(Err(EngineError::ShardStopped)).map(drop)
     }
 }
 
+impl Engine for ShardedEngine {
+    fn process(&mut self, tx: Transaction) -> impl Future<Output = Result<Outcome>> + Send {
+        self.process_transaction(tx)
+    }
+
+    fn get_account(&self, client_id: ClientId) -> impl Future<Output = Option<Account>> + Send {
+        ShardedEngine::get_account(self, client_id)
+    }
+
+    fn accounts(&self) -> impl Future<Output = Vec<Account>> + Send {
+        self.get_all_accounts()
+    }
+
+    fn stats(&self) -> impl Future<Output = EngineStats> + Send {
+        ShardedEngine::stats(self)
+    }
+
+    fn funds(&self) -> impl Future<Output = FundsSummary> + Send {
+        ShardedEngine::funds(self)
+    }
+
+    fn simulate(&self, tx: Transaction) -> impl Future<Output = Result<Simulation>> + Send {
+        ShardedEngine::simulate(self, tx)
+    }
+}
+
+/// Split `state` into one state per shard
+///
+/// Accounts and disputable transactions go to the shard owning their client.
+/// Processed transaction IDs don't record their client, so every shard gets
+/// the full set for duplicate detection.
+fn partition(state: EngineState, num_shards: usize, shard_key: ShardKey) -> Vec<EngineState> {
+    let mut shard_states = vec![EngineState::default(); num_shards];
+    for shard_state in shard_states.iter_mut() {
+        shard_state.processed_tx_ids = state.processed_tx_ids.clone();
+    }
+    for account in state.accounts {
+        shard_states[shard_key(account.client, num_shards)]
+            .accounts
+            .push(account);
+    }
+    for stored_tx in state.disputable_transactions {
+        shard_states[shard_key(stored_tx.client_id, num_shards)]
+            .disputable_transactions
+            .push(stored_tx);
+    }
+    for hold in state.holds {
+        shard_states[shard_key(hold.client_id, num_shards)]
+            .holds
+            .push(hold);
+    }
+    for (client_id, held) in state.seeded_held {
+        shard_states[shard_key(client_id, num_shards)]
+            .seeded_held
+            .push((client_id, held));
+    }
+    for (client_id, amount) in state.charged_back {
+        shard_states[shard_key(client_id, num_shards)]
+            .charged_back
+            .push((client_id, amount));
+    }
+    shard_states
+}
+
+/// Spawn one shard task per engine, each publishing to `events`
+fn spawn_shards<P: PersistenceBackend + 'static>(
+    engines: Vec<PersistentEngine<P>>,
+    events: &broadcast::Sender<AccountEvent>,
+    #[cfg(feature = "chaos")] chaos: &Arc<Mutex<Chaos>>,
+) -> Vec<ShardHandle> {
+    engines
+        .into_iter()
+        .map(|engine| {
+            ShardHandle::spawn(
+                engine,
+                SHARD_QUEUE_CAPACITY,
+                events.clone(),
+                #[cfg(feature = "chaos")]
+                chaos.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Wrap an engine for a shard that keeps no WAL
+fn with_stub_persistence(engine: PaymentsEngine) -> PersistentEngine<StubPersistence> {
+    PersistentEngine::with_engine(engine, StubPersistence::new())
+}
+
+/// Directory holding shard `shard`'s WAL
+fn shard_dir(dir: &Path, shard: usize) -> PathBuf {
+    dir.join(format!("shard-{}", shard))
+}
+
+/// Shard `shard`'s WAL
+pub(crate) fn shard_wal_path(dir: &Path, shard: usize) -> PathBuf {
+    shard_dir(dir, shard).join(WAL_FILE)
+}
+
+/// Logs of the shards with WALs in `dir`, shard 0 first
+pub(crate) fn shard_wal_paths(dir: &Path) -> Vec<PathBuf> {
+    (0..existing_shard_dirs(dir))
+        .map(|shard| shard_wal_path(dir, shard))
+        .collect()
+}
+
+/// Number of consecutive shard directories, from `shard-0`, in `dir`
+fn existing_shard_dirs(dir: &Path) -> usize {
+    let mut count = 0;
+    while shard_dir(dir, count).is_dir() {
+        count += 1;
+    }
+    count
+}
+
+/// Take a view of every shard's accounts concurrently
+async fn collect_views(handles: &[ShardHandle]) -> AccountsView {
+    let futures: Vec<_> = handles
+        .iter()
+        .map(|shard| shard.request(|reply| Command::View { reply }))
+        .collect();
+
+    let views = futures::future::join_all(futures)
+        .await
+        .into_iter()
+        .map(|view| view.expect(SHARD_STOPPED))
+        .collect();
+    AccountsView::new(views)
+}
+
+/// Flush every shard and combine their states into one
+async fn checkpoint(handles: &[ShardHandle]) -> Result<EngineState> {
+    let mut state = EngineState::default();
+    for shard in handles {
+        let shard_state = shard
+            .request(|reply| Command::Checkpoint { reply })
+            .await
+            .ok_or(EngineError::ShardStopped)??;
+        state.merge(shard_state);
+    }
+
+    // Keep the same ordering as `PaymentsEngine::to_state`
+    state.accounts.sort_by_key(|a| a.client);
+    state.disputable_transactions.sort_by_key(|t| t.tx_id);
+    state.processed_tx_ids.sort_unstable();
+    state.processed_tx_ids.dedup();
+    state.holds.sort_unstable_by_key(|hold| hold.tx_id);
+    state
+        .seeded_held
+        .sort_unstable_by_key(|(client_id, _)| *client_id);
+    state
+        .charged_back
+        .sort_unstable_by_key(|(client_id, _)| *client_id);
+
+    Ok(state)
+}
+
 // ShardedEngine is automatically Send + Sync because:
-// - Arc is Send + Sync
-// - RwLock is Send + Sync
-// - PaymentsEngine contains only Send + Sync types
+// - Shard handles are just mpsc senders
+// - Arc, RwLock and Mutex are Send + Sync
+// - Each PaymentsEngine is owned by its shard task and never shared
 //
 // This allows sharing across tokio tasks safely
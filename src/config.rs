@@ -0,0 +1,84 @@
+use serde::Deserialize;
+
+use crate::account_store::AccountOrdering;
+use crate::amount::{self, Amount};
+use crate::outcome::RejectReason;
+
+/// Engine configuration
+#[derive(Debug, Clone, Default)]
+pub struct EngineConfig {
+    /// How accounts are stored, see `AccountOrdering`
+    pub account_ordering: AccountOrdering,
+    /// Whether negative available/held balances are acceptable
+    ///
+    /// The engine never produces them itself, but balances seeded from another
+    /// ledger may legitimately be overdrawn. When false, `check_invariants`
+    /// reports any negative balance as a violation.
+    pub allow_negative_balances: bool,
+    /// Largest deposit or withdrawal accepted; larger ones are rejected
+    pub max_amount: Option<Amount>,
+    /// Most decimal places a deposit or withdrawal amount may have
+    ///
+    /// Trailing zeros don't count, so `1.50` has one decimal place.
+    pub max_decimal_places: Option<u32>,
+    /// How long deposits stay disputable, see `RetentionPolicy`
+    pub retention: RetentionPolicy,
+    /// Run `check_invariants` after every this many applied transactions,
+    /// raising any violations through the alert hooks (see `Hooks::alert`)
+    ///
+    /// Off by default; 0 also turns it off. Each check walks every account
+    /// and open dispute, so small intervals cost throughput on large ledgers.
+    pub invariant_check_every: Option<u64>,
+}
+
+impl EngineConfig {
+    /// Check a deposit or withdrawal amount against the configured limits
+    pub(crate) fn check_amount(&self, amount: Option<Amount>) -> Result<(), RejectReason> {
+        let Some(amount) = amount else {
+            return Err(RejectReason::MissingAmount);
+        };
+        // Reject negative or zero amounts for deposits/withdrawals
+        if amount <= Amount::ZERO {
+            return Err(RejectReason::NonPositiveAmount);
+        }
+        if self.max_amount.is_some_and(|max| amount > max) {
+            return Err(RejectReason::AmountAboveLimit);
+        }
+        if self
+            .max_decimal_places
+            .is_some_and(|places| amount::decimal_places(amount) > places)
+        {
+            return Err(RejectReason::TooManyDecimalPlaces);
+        }
+        Ok(())
+    }
+}
+
+/// Limits on how many deposits the engine keeps for later disputes
+///
+/// By default every deposit stays disputable forever. Deposits dropped under
+/// a limit can no longer be disputed: disputes, resolves and chargebacks
+/// referencing them are rejected as expired. Deposits under dispute are never
+/// dropped; one that reaches a limit while disputed starts a fresh retention
+/// period instead.
+///
+/// Ages are measured on a logical clock, like `HistoryEntry::timestamp`: the
+/// number of transactions the engine (each shard, for `ShardedEngine`) has
+/// applied since the deposit. Limits apply to deposits stored before the
+/// policy was set as if they had just been stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionPolicy {
+    /// Most deposits kept; the oldest are dropped beyond this
+    pub max_entries: Option<usize>,
+    /// Deposits are dropped once more than this many transactions have been
+    /// applied after them
+    pub max_age: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// True if any limit is set
+    pub fn is_limited(&self) -> bool {
+        self.max_entries.is_some() || self.max_age.is_some()
+    }
+}
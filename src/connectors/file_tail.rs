@@ -0,0 +1,376 @@
+//! Following a CSV file other systems append transactions to
+//!
+//! Needs the `file-tail` feature.
+
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures::FutureExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::concurrent_engine::ShardedEngine;
+use crate::connectors::offsets::OffsetTracker;
+use crate::error::{EngineError, Result};
+use crate::models::Transaction;
+use crate::pipeline::{parse_csv_record, IngestStats};
+use crate::CsvColumns;
+
+/// What to follow and how
+#[derive(Debug, Clone)]
+pub struct TailSourceOptions {
+    /// CSV file to follow; waited for if it doesn't exist yet
+    pub path: PathBuf,
+    /// File recording how far into `path` every transaction has been
+    /// processed, where following resumes after a restart. Without one the
+    /// whole file is read on every start.
+    pub offset_file: Option<PathBuf>,
+    /// Transactions each shard's lane holds before reading waits
+    pub lane_capacity: usize,
+    /// How long to wait for new lines once the end of the file is reached,
+    /// in milliseconds; also bounds how long shutdown waits
+    pub poll_ms: u64,
+}
+
+impl TailSourceOptions {
+    /// The whole file on every start, lanes of 1024, looking for new lines
+    /// every 250 milliseconds
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            offset_file: None,
+            lane_capacity: 1024,
+            poll_ms: 250,
+        }
+    }
+}
+
+/// A transaction read from the file and the offset of its line
+struct Delivery {
+    tx: Transaction,
+    start: u64,
+}
+
+/// Apply transactions appended to a CSV file to `engine` until `shutdown`
+/// resolves, like `tail -f`
+///
+/// The file is read to its end, then checked for new lines every
+/// `options.poll_ms`. A line is only read once its newline has been
+/// written, so a writer caught partway through a row isn't misread. The
+/// first non-blank line is the header; rows that aren't valid transactions
+/// are counted as malformed and skipped. Transactions are routed to
+/// per-shard lanes like `pipeline::ingest`, so each client's stay in file
+/// order.
+///
+/// With `options.offset_file`, the offset below which every transaction
+/// has been processed is recorded each time reading catches up with the
+/// file and on shutdown, and reading resumes there. Lines processed after
+/// the last record and before a crash are read again (at-least-once);
+/// repeated deposits and withdrawals are rejected as duplicates. A file
+/// that shrinks is taken to have been truncated and is read again from the
+/// start once the transactions in flight have finished. A file replaced by
+/// renaming another over it isn't noticed.
+///
+/// On shutdown, transactions already read are finished before returning.
+/// Fails if the file or offset file can't be read or written or the engine
+/// can't process a transaction.
+///
+/// # Panics
+///
+/// Panics if `options.lane_capacity` is zero.
+pub async fn consume(
+    engine: &ShardedEngine,
+    options: TailSourceOptions,
+    shutdown: impl Future<Output = ()>,
+) -> Result<IngestStats> {
+    let start = match &options.offset_file {
+        Some(path) => load_offset(path)?,
+        None => 0,
+    };
+    let mut tail = Tail::new(options.path.clone(), start);
+
+    let num_lanes = engine.num_shards().await.max(1);
+    let (done_sender, mut done) = mpsc::unbounded_channel::<u64>();
+    let mut lanes = Vec::with_capacity(num_lanes);
+    let mut workers = JoinSet::new();
+    for _ in 0..num_lanes {
+        let (sender, mut receiver) = mpsc::channel::<Delivery>(options.lane_capacity);
+        let engine = engine.clone_handle();
+        let done = done_sender.clone();
+        workers.spawn(async move {
+            let mut processed = 0;
+            while let Some(delivery) = receiver.recv().await {
+                engine.process_transaction(delivery.tx).await?;
+                processed += 1;
+                let _ = done.send(delivery.start);
+            }
+            Ok::<u64, EngineError>(processed)
+        });
+        lanes.push(sender);
+    }
+    drop(done_sender);
+
+    let mut stats = IngestStats::default();
+    // A single partition whose offsets are the lines' byte offsets
+    let mut tracker = OffsetTracker::new();
+    let mut recorded = start;
+    let poll = Duration::from_millis(options.poll_ms);
+    tokio::pin!(shutdown);
+
+    let followed = loop {
+        if (&mut shutdown).now_or_never().is_some() {
+            break Ok(());
+        }
+        // Workers only stop early when the engine fails
+        if let Some(worker) = workers.try_join_next() {
+            break worker.expect("tail worker panicked").map(|processed| {
+                stats.processed += processed;
+            });
+        }
+        while let Ok(start) = done.try_recv() {
+            tracker.finish(0, start as i64);
+        }
+
+        let deliveries = match tail.read() {
+            Ok(Appended::Transactions(deliveries)) => deliveries,
+            Ok(Appended::Truncated) => {
+                log::warn!(
+                    "'{}' was truncated; reading it again from the start",
+                    options.path.display()
+                );
+                while tracker.has_in_flight() {
+                    let Some(start) = done.recv().await else {
+                        break;
+                    };
+                    tracker.finish(0, start as i64);
+                }
+                tail.rewind();
+                continue;
+            }
+            Err(e) => break Err(e),
+        };
+
+        if deliveries.is_empty() {
+            let watermark = watermark(&tracker, &tail);
+            if watermark != recorded {
+                if let Some(path) = &options.offset_file {
+                    if let Err(e) = save_offset(path, watermark) {
+                        break Err(e);
+                    }
+                }
+                recorded = watermark;
+            }
+            tokio::select! {
+                () = &mut shutdown => break Ok(()),
+                () = tokio::time::sleep(poll) => continue,
+            }
+        }
+
+        let mut stopped = false;
+        for delivery in deliveries {
+            tracker.start(0, delivery.start as i64);
+            let lane = &lanes[engine.shard_for(delivery.tx.client, num_lanes)];
+            if lane.send(delivery).await.is_err() {
+                // The worker failed; its error is reported below
+                stopped = true;
+                break;
+            }
+        }
+        if stopped {
+            break Ok(());
+        }
+    };
+    stats.malformed = tail.malformed;
+
+    // Finish what was read, then record how far that got
+    drop(lanes);
+    let mut finished = Ok(());
+    while let Some(worker) = workers.join_next().await {
+        match worker.expect("tail worker panicked") {
+            Ok(processed) => stats.processed += processed,
+            Err(e) => finished = Err(e),
+        }
+    }
+    while let Ok(start) = done.try_recv() {
+        tracker.finish(0, start as i64);
+    }
+    let watermark = watermark(&tracker, &tail);
+    let recorded = match &options.offset_file {
+        Some(path) if watermark != recorded => save_offset(path, watermark),
+        _ => Ok(()),
+    };
+
+    followed.and(finished).and(recorded).map(|()| stats)
+}
+
+/// Offset below which every line read has been processed
+fn watermark(tracker: &OffsetTracker, tail: &Tail) -> u64 {
+    match tracker.committable(0) {
+        Some(offset) if tracker.has_in_flight() => offset as u64,
+        _ => tail.position,
+    }
+}
+
+/// What reading the file found since the last read
+enum Appended {
+    /// Transactions from new complete lines, none at the end of the file
+    Transactions(Vec<Delivery>),
+    /// The file is now shorter than what was read of it
+    Truncated,
+}
+
+/// The reading side of `consume`
+struct Tail {
+    path: PathBuf,
+    /// Opened once the file exists
+    reader: Option<BufReader<File>>,
+    /// Offset just past the last complete line read
+    position: u64,
+    /// The start of a line whose newline hasn't been written yet
+    partial: Vec<u8>,
+    columns: Option<CsvColumns>,
+    /// Lines read so far
+    line: u64,
+    /// Rows skipped so far
+    malformed: u64,
+}
+
+impl Tail {
+    fn new(path: PathBuf, position: u64) -> Self {
+        Self {
+            path,
+            reader: None,
+            position,
+            partial: Vec::new(),
+            columns: None,
+            line: 0,
+            malformed: 0,
+        }
+    }
+
+    /// Read the lines completed since the last read
+    fn read(&mut self) -> Result<Appended> {
+        if self.reader.is_none() {
+            self.open()?;
+        }
+        let Some(reader) = &mut self.reader else {
+            // Not created yet
+            return Ok(Appended::Transactions(Vec::new()));
+        };
+
+        let mut deliveries = Vec::new();
+        loop {
+            if reader.read_until(b'\n', &mut self.partial)? == 0 {
+                break;
+            }
+            if !self.partial.ends_with(b"\n") {
+                continue;
+            }
+            let start = self.position;
+            self.position += self.partial.len() as u64;
+            self.line += 1;
+            let line = String::from_utf8_lossy(&self.partial).into_owned();
+            self.partial.clear();
+            let Some(record) = parse_csv_record(&line) else {
+                continue;
+            };
+            let record = record.into_byte_record();
+            let Some(columns) = &self.columns else {
+                self.columns = Some(CsvColumns::new(&record));
+                continue;
+            };
+            match columns.parse(&record, self.line) {
+                Ok(tx) => deliveries.push(Delivery { tx, start }),
+                Err(error) => {
+                    log::debug!("Skipped malformed row at {}", error);
+                    self.malformed += 1;
+                }
+            }
+            // Hand over what was read before catching up with a busy file
+            if deliveries.len() >= READ_BATCH {
+                break;
+            }
+        }
+
+        let read = self.position + self.partial.len() as u64;
+        if deliveries.is_empty() && reader.get_ref().metadata()?.len() < read {
+            return Ok(Appended::Truncated);
+        }
+        Ok(Appended::Transactions(deliveries))
+    }
+
+    /// Open the file if it exists, finding the header and counting the
+    /// lines before `position`
+    fn open(&mut self) -> Result<()> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut reader = BufReader::new(file);
+        if reader.get_ref().metadata()?.len() < self.position {
+            log::warn!(
+                "'{}' is shorter than its recorded offset; reading it from the start",
+                self.path.display()
+            );
+            self.position = 0;
+        }
+
+        let mut skipped = 0;
+        let mut line = Vec::new();
+        while skipped < self.position {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            skipped += read as u64;
+            self.line += 1;
+            if self.columns.is_none() {
+                if let Some(record) = parse_csv_record(&String::from_utf8_lossy(&line)) {
+                    self.columns = Some(CsvColumns::new(&record.into_byte_record()));
+                }
+            }
+        }
+        reader.seek(SeekFrom::Start(self.position))?;
+        self.reader = Some(reader);
+        Ok(())
+    }
+
+    /// Start over at the beginning of the file, keeping the count of
+    /// malformed rows
+    fn rewind(&mut self) {
+        *self = Self {
+            malformed: self.malformed,
+            ..Self::new(std::mem::take(&mut self.path), 0)
+        };
+    }
+}
+
+/// Transactions read before they're handed to the lanes
+const READ_BATCH: usize = 1024;
+
+/// The offset recorded in `path`, 0 if there is none yet
+fn load_offset(path: &Path) -> Result<u64> {
+    match fs::read_to_string(path) {
+        Ok(text) => text.trim().parse().map_err(|_| {
+            EngineError::InvalidConfig(format!(
+                "offset file '{}' doesn't hold an offset",
+                path.display()
+            ))
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Record `offset` in `path`, replacing it atomically
+fn save_offset(path: &Path, offset: u64) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, format!("{}\n", offset))?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,301 @@
+//! Consuming transactions from and publishing account events to Kafka
+//!
+//! Needs the `kafka` feature, which builds librdkafka.
+
+use std::collections::VecDeque;
+use std::future::Future;
+
+use futures::channel::oneshot::Canceled;
+use futures::FutureExt;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::producer::future_producer::OwnedDeliveryResult;
+use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::concurrent_engine::ShardedEngine;
+use crate::connectors::format::MessageFormat;
+use crate::connectors::offsets::OffsetTracker;
+use crate::connectors::publish::{event_json, EventSink};
+use crate::error::{EngineError, Result};
+use crate::events::AccountEvent;
+use crate::models::Transaction;
+use crate::pipeline::IngestStats;
+
+/// Where and how to consume transactions
+#[derive(Debug, Clone)]
+pub struct KafkaSourceOptions {
+    /// Bootstrap brokers, e.g. `localhost:9092`
+    pub brokers: String,
+    pub topic: String,
+    /// Consumer group whose committed offsets say where to resume
+    pub group_id: String,
+    /// Encoding of the message payloads
+    pub format: MessageFormat,
+    /// Messages each shard's lane holds before the consumer waits
+    pub lane_capacity: usize,
+    /// Further librdkafka settings, e.g. `security.protocol`; these override
+    /// the consumer's own defaults
+    pub config: Vec<(String, String)>,
+}
+
+impl KafkaSourceOptions {
+    /// JSON messages, lanes of 1024 and no extra settings
+    pub fn new(
+        brokers: impl Into<String>,
+        topic: impl Into<String>,
+        group_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            brokers: brokers.into(),
+            topic: topic.into(),
+            group_id: group_id.into(),
+            format: MessageFormat::Json,
+            lane_capacity: 1024,
+            config: Vec::new(),
+        }
+    }
+}
+
+/// A consumed transaction and where it came from
+struct Delivery {
+    tx: Transaction,
+    partition: i32,
+    offset: i64,
+}
+
+/// Consume transactions from Kafka into `engine` until `shutdown` resolves
+///
+/// Messages are routed to per-shard lanes like `pipeline::ingest`, so shards
+/// work in parallel while each client's transactions from a partition stay
+/// in order. A message's offset becomes committable only once the engine
+/// has acknowledged it, which with a write-ahead log means it is durable,
+/// and only once every earlier message of its partition has been
+/// acknowledged too. A crash therefore redelivers transactions rather than
+/// losing them (at-least-once); redelivered deposits and withdrawals are
+/// rejected as duplicates. Messages that can't be decoded are counted as
+/// malformed and skipped.
+///
+/// With no committed offsets for the group, consumption starts at the
+/// beginning of the topic. On shutdown, transactions already consumed are
+/// finished and their offsets committed before returning. Fails if the
+/// consumer can't be set up or the engine can't process a transaction, in
+/// which case that transaction's offset is never committed.
+///
+/// # Panics
+///
+/// Panics if `options.lane_capacity` is zero.
+pub async fn consume(
+    engine: &ShardedEngine,
+    options: KafkaSourceOptions,
+    shutdown: impl Future<Output = ()>,
+) -> Result<IngestStats> {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", &options.brokers)
+        .set("group.id", &options.group_id)
+        .set("auto.offset.reset", "earliest")
+        // Offsets are stored by hand once processed, then committed in the background
+        .set("enable.auto.commit", "true")
+        .set("enable.auto.offset.store", "false");
+    for (key, value) in &options.config {
+        config.set(key, value);
+    }
+    let consumer: StreamConsumer = config.create()?;
+    consumer.subscribe(&[&options.topic])?;
+
+    let num_lanes = engine.num_shards().await.max(1);
+    let (done_sender, mut done) = mpsc::unbounded_channel::<(i32, i64)>();
+    let mut lanes = Vec::with_capacity(num_lanes);
+    let mut workers = JoinSet::new();
+    for _ in 0..num_lanes {
+        let (sender, mut receiver) = mpsc::channel::<Delivery>(options.lane_capacity);
+        let engine = engine.clone_handle();
+        let done = done_sender.clone();
+        workers.spawn(async move {
+            let mut processed = 0;
+            while let Some(delivery) = receiver.recv().await {
+                engine.process_transaction(delivery.tx).await?;
+                processed += 1;
+                let _ = done.send((delivery.partition, delivery.offset));
+            }
+            Ok::<u64, EngineError>(processed)
+        });
+        lanes.push(sender);
+    }
+    drop(done_sender);
+
+    let mut stats = IngestStats::default();
+    let mut tracker = OffsetTracker::new();
+    tokio::pin!(shutdown);
+
+    let consumed = loop {
+        tokio::select! {
+            biased;
+            _ = &mut shutdown => break Ok(()),
+            Some((partition, offset)) = done.recv() => {
+                let watermark = tracker.finish(partition, offset);
+                store_offset(&consumer, &options.topic, partition, watermark);
+            }
+            // Workers only stop early when the engine fails
+            Some(worker) = workers.join_next() => {
+                break worker.expect("Kafka worker panicked").map(|processed| {
+                    stats.processed += processed;
+                });
+            }
+            message = consumer.recv() => {
+                let (partition, offset, decoded) = match message {
+                    Ok(message) => (
+                        message.partition(),
+                        message.offset(),
+                        message.payload().map(|payload| options.format.decode(payload)),
+                    ),
+                    Err(e) => break Err(e.into()),
+                };
+                tracker.start(partition, offset);
+
+                let Some(Ok(tx)) = decoded else {
+                    stats.malformed += 1;
+                    let watermark = tracker.finish(partition, offset);
+                    store_offset(&consumer, &options.topic, partition, watermark);
+                    continue;
+                };
+
+                let lane = &lanes[engine.shard_for(tx.client, num_lanes)];
+                let delivery = Delivery { tx, partition, offset };
+                if lane.send(delivery).await.is_err() {
+                    // The worker failed; its error is reported below
+                    break Ok(());
+                }
+            }
+        }
+    };
+
+    // Finish what was consumed, recording offsets as transactions complete
+    drop(lanes);
+    let mut finished = Ok(());
+    while let Some(worker) = workers.join_next().await {
+        match worker.expect("Kafka worker panicked") {
+            Ok(processed) => stats.processed += processed,
+            Err(e) => finished = Err(e),
+        }
+    }
+    while let Ok((partition, offset)) = done.try_recv() {
+        tracker.finish(partition, offset);
+    }
+
+    // Partitions reassigned in the meantime are left to their new owner
+    let assigned = consumer.assignment()?;
+    let mut offsets = TopicPartitionList::new();
+    for (partition, watermark) in tracker.watermarks() {
+        if assigned.find_partition(&options.topic, partition).is_some() {
+            offsets.add_partition_offset(&options.topic, partition, Offset::Offset(watermark))?;
+        }
+    }
+    if offsets.count() > 0 {
+        consumer.commit(&offsets, CommitMode::Sync)?;
+    }
+
+    consumed.and(finished).map(|()| stats)
+}
+
+/// Store a partition's watermark for the next automatic commit
+fn store_offset(consumer: &StreamConsumer, topic: &str, partition: i32, watermark: Option<i64>) {
+    let Some(watermark) = watermark else {
+        return;
+    };
+    let mut offsets = TopicPartitionList::new();
+    if offsets
+        .add_partition_offset(topic, partition, Offset::Offset(watermark))
+        .is_ok()
+    {
+        // Fails only if the partition was reassigned since, in which case
+        // its new owner redelivers from the last commit
+        let _ = consumer.store_offsets(&offsets);
+    }
+}
+
+/// Events awaiting delivery confirmation before `publish` waits for the oldest
+const MAX_UNCONFIRMED: usize = 10_000;
+
+/// Publishes account events as JSON to a Kafka topic
+///
+/// Messages are keyed by client ID, so each client's events land on one
+/// partition in order. Deliveries are confirmed in the background; a failed
+/// delivery fails the next `publish` or `flush`.
+pub struct KafkaEventSink {
+    producer: FutureProducer,
+    topic: String,
+    unconfirmed: VecDeque<DeliveryFuture>,
+}
+
+impl KafkaEventSink {
+    /// Create a producer for `topic`; `config` holds further librdkafka
+    /// settings, as for `KafkaSourceOptions::config`
+    pub fn new(
+        brokers: &str,
+        topic: impl Into<String>,
+        config: &[(String, String)],
+    ) -> Result<Self> {
+        let mut client_config = ClientConfig::new();
+        client_config
+            .set("bootstrap.servers", brokers)
+            // Retries must not reorder a client's events
+            .set("enable.idempotence", "true");
+        for (key, value) in config {
+            client_config.set(key, value);
+        }
+        Ok(Self {
+            producer: client_config.create()?,
+            topic: topic.into(),
+            unconfirmed: VecDeque::new(),
+        })
+    }
+
+    /// Wait for the oldest unconfirmed delivery
+    async fn confirm_oldest(&mut self) -> Result<()> {
+        match self.unconfirmed.pop_front() {
+            Some(delivery) => delivered(delivery.await),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Outcome of a delivery as reported by the producer
+fn delivered(result: std::result::Result<OwnedDeliveryResult, Canceled>) -> Result<()> {
+    match result {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err((e, _))) => Err(e.into()),
+        // The producer dropped the message without reporting on it
+        Err(Canceled) => Err(KafkaError::Canceled.into()),
+    }
+}
+
+impl EventSink for KafkaEventSink {
+    async fn publish(&mut self, event: &AccountEvent) -> Result<()> {
+        // Surface failures of deliveries that have already completed
+        while let Some(result) = self.unconfirmed.front_mut().and_then(|d| d.now_or_never()) {
+            self.unconfirmed.pop_front();
+            delivered(result)?;
+        }
+        if self.unconfirmed.len() >= MAX_UNCONFIRMED {
+            self.confirm_oldest().await?;
+        }
+
+        let key = event.account.client_id.to_string();
+        let payload = event_json(event);
+        let record = FutureRecord::to(&self.topic).key(&key).payload(&payload);
+        let delivery = self.producer.send_result(record).map_err(|(e, _)| e)?;
+        self.unconfirmed.push_back(delivery);
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        while !self.unconfirmed.is_empty() {
+            self.confirm_oldest().await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,19 @@
+//! Sources and sinks connecting the engine to message brokers and files
+//!
+//! Broker clients sit behind cargo features so the default build doesn't
+//! pull them in; message decoding and offset bookkeeping are always built,
+//! the `EventSink` layer with the `async` feature.
+
+#[cfg(feature = "file-tail")]
+pub mod file_tail;
+pub mod format;
+pub mod iso8583;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod offsets;
+#[cfg(feature = "async")]
+pub mod publish;
+#[cfg(feature = "redis")]
+pub mod redis_streams;
@@ -0,0 +1,46 @@
+//! Publishing account events to NATS
+//!
+//! Needs the `nats` feature.
+
+use crate::connectors::publish::{event_json, EventSink};
+use crate::error::{EngineError, Result};
+use crate::events::AccountEvent;
+
+/// Publishes account events as JSON to `<subject>.<client>`
+///
+/// Subscribers pick clients with subject wildcards: `<subject>.>` for all of
+/// them, `<subject>.42` for one. Core NATS delivers at most once; events
+/// published while a subscriber is disconnected are lost to it.
+pub struct NatsEventSink {
+    client: async_nats::Client,
+    subject: String,
+}
+
+impl NatsEventSink {
+    /// Connect to the NATS server at `url`, e.g. `nats://localhost:4222`
+    pub async fn connect(url: &str, subject: impl Into<String>) -> Result<Self> {
+        let client = async_nats::connect(url).await.map_err(nats_error)?;
+        Ok(Self {
+            client,
+            subject: subject.into(),
+        })
+    }
+}
+
+impl EventSink for NatsEventSink {
+    async fn publish(&mut self, event: &AccountEvent) -> Result<()> {
+        let subject = format!("{}.{}", self.subject, event.account.client_id);
+        self.client
+            .publish(subject, event_json(event).into())
+            .await
+            .map_err(nats_error)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.client.flush().await.map_err(nats_error)
+    }
+}
+
+fn nats_error(error: impl std::error::Error + Send + Sync + 'static) -> EngineError {
+    EngineError::Nats(Box::new(error))
+}
@@ -0,0 +1,64 @@
+use std::collections::{BTreeSet, HashMap};
+
+/// Commit watermarks for messages processed out of order
+///
+/// Consumers hand messages to different shards, which finish them in any
+/// order, but a partition's committed offset means "everything before this
+/// is done". The tracker remembers which offsets are still in flight and
+/// reports, per partition, the offset below which every message has
+/// finished: the next offset a restarted consumer should read. Committing
+/// only that keeps delivery at-least-once.
+#[derive(Debug, Default)]
+pub struct OffsetTracker {
+    partitions: HashMap<i32, PartitionOffsets>,
+}
+
+#[derive(Debug, Default)]
+struct PartitionOffsets {
+    in_flight: BTreeSet<i64>,
+    /// One past the highest offset started
+    next: i64,
+}
+
+impl OffsetTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that processing of `offset` started
+    pub fn start(&mut self, partition: i32, offset: i64) {
+        let offsets = self.partitions.entry(partition).or_default();
+        offsets.in_flight.insert(offset);
+        offsets.next = offsets.next.max(offset + 1);
+    }
+
+    /// Record that `offset` finished and return the partition's watermark
+    pub fn finish(&mut self, partition: i32, offset: i64) -> Option<i64> {
+        self.partitions
+            .get_mut(&partition)?
+            .in_flight
+            .remove(&offset);
+        self.committable(partition)
+    }
+
+    /// Offset below which every started message of `partition` has finished,
+    /// `None` if nothing was started on it
+    pub fn committable(&self, partition: i32) -> Option<i64> {
+        let offsets = self.partitions.get(&partition)?;
+        Some(offsets.in_flight.first().copied().unwrap_or(offsets.next))
+    }
+
+    /// Watermarks of every partition seen so far
+    pub fn watermarks(&self) -> impl Iterator<Item = (i32, i64)> + '_ {
+        self.partitions
+            .keys()
+            .filter_map(|&partition| Some((partition, self.committable(partition)?)))
+    }
+
+    /// True while any message is still being processed
+    pub fn has_in_flight(&self) -> bool {
+        self.partitions
+            .values()
+            .any(|offsets| !offsets.in_flight.is_empty())
+    }
+}
@@ -0,0 +1,97 @@
+use std::future::Future;
+
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+use crate::error::Result;
+use crate::events::AccountEvent;
+
+/// Destination outside the process for account events, e.g. a broker topic
+///
+/// Implementations may buffer: `publish` returns once the event is accepted
+/// for delivery, `flush` once everything accepted has been delivered.
+pub trait EventSink: Send {
+    /// Hand one event to the destination
+    fn publish(&mut self, event: &AccountEvent) -> impl Future<Output = Result<()>> + Send;
+
+    /// Wait until every published event has been delivered
+    fn flush(&mut self) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// What happened to the events seen by `publish_events`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PublishStats {
+    /// Events handed to the sink
+    pub published: u64,
+    /// Events skipped because the sink fell too far behind the engine
+    pub missed: u64,
+}
+
+/// Publish account events from `events` to `sink` until `shutdown` resolves
+///
+/// `events` comes from `ShardedEngine::subscribe`; every event emitted after
+/// subscribing is published, in the order the engine emitted them, so each
+/// client's events arrive in order. Publishing is best effort: a sink that
+/// can't keep up skips events rather than stalling the engine, counted in
+/// `PublishStats::missed`. Consumers can spot the gap from the account
+/// `version`, which goes up by one per event, and fetch the account again.
+///
+/// On shutdown, events already emitted are still published and everything
+/// is flushed before returning. Fails as soon as the sink does.
+///
+/// # Example
+///
+/// ```no_run
+/// # use payments_engine::concurrent_engine::ShardedEngine;
+/// # use payments_engine::connectors::publish::{publish_events, EventSink};
+/// # use std::future::Future;
+/// # async fn run(engine: ShardedEngine, sink: impl EventSink, shutdown: impl Future<Output = ()>) {
+/// let stats = publish_events(engine.subscribe(), sink, shutdown).await.unwrap();
+/// eprintln!("{} events published, {} missed", stats.published, stats.missed);
+/// # }
+/// ```
+pub async fn publish_events<S: EventSink>(
+    mut events: broadcast::Receiver<AccountEvent>,
+    mut sink: S,
+    shutdown: impl Future<Output = ()>,
+) -> Result<PublishStats> {
+    let mut stats = PublishStats::default();
+    tokio::pin!(shutdown);
+
+    loop {
+        let event = tokio::select! {
+            biased;
+            _ = &mut shutdown => break,
+            event = events.recv() => event,
+        };
+        match event {
+            Ok(event) => {
+                sink.publish(&event).await?;
+                stats.published += 1;
+            }
+            Err(RecvError::Lagged(missed)) => stats.missed += missed,
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    // Events emitted before shutdown are still owed to the sink
+    loop {
+        match events.try_recv() {
+            Ok(event) => {
+                sink.publish(&event).await?;
+                stats.published += 1;
+            }
+            Err(TryRecvError::Lagged(missed)) => stats.missed += missed,
+            Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+        }
+    }
+
+    sink.flush().await?;
+    Ok(stats)
+}
+
+/// Encode an event as the JSON published by the broker sinks
+#[cfg(any(feature = "kafka", feature = "nats"))]
+pub(crate) fn event_json(event: &AccountEvent) -> Vec<u8> {
+    serde_json::to_vec(event).expect("account events serialize to JSON")
+}
@@ -0,0 +1,257 @@
+//! Consuming transactions from a Redis Stream
+//!
+//! Needs the `redis` feature.
+
+use std::future::Future;
+
+use futures::FutureExt;
+use redis::aio::MultiplexedConnection;
+use redis::streams::{StreamId, StreamReadOptions, StreamReadReply};
+use redis::{AsyncCommands, RedisResult};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::concurrent_engine::ShardedEngine;
+use crate::connectors::format::{self, MessageFormat};
+use crate::error::{EngineError, Result};
+use crate::models::Transaction;
+use crate::pipeline::IngestStats;
+
+/// Where and how to consume transactions
+#[derive(Debug, Clone)]
+pub struct RedisSourceOptions {
+    /// Server URL, e.g. `redis://localhost:6379`
+    pub url: String,
+    /// Key of the stream; created if missing
+    pub stream: String,
+    /// Consumer group; created at the start of the stream if missing
+    pub group: String,
+    /// Name of this consumer within the group. Entries it read but didn't
+    /// acknowledge before stopping are read again when it restarts under the
+    /// same name.
+    pub consumer: String,
+    /// `None` reads the transaction from the entry's `type`, `client`, `tx`
+    /// and `amount` fields; a format decodes it from a single `payload` field
+    pub payload_format: Option<MessageFormat>,
+    /// Entries each shard's lane holds before the consumer waits
+    pub lane_capacity: usize,
+    /// Entries fetched per read
+    pub count: usize,
+    /// How long a read waits for new entries, in milliseconds; also bounds
+    /// how long shutdown and acknowledgements wait
+    pub block_ms: usize,
+}
+
+impl RedisSourceOptions {
+    /// Transactions as entry fields, consumer `payments-engine`, lanes of
+    /// 1024, reads of up to 512 entries waiting up to a second
+    pub fn new(
+        url: impl Into<String>,
+        stream: impl Into<String>,
+        group: impl Into<String>,
+    ) -> Self {
+        Self {
+            url: url.into(),
+            stream: stream.into(),
+            group: group.into(),
+            consumer: "payments-engine".to_string(),
+            payload_format: None,
+            lane_capacity: 1024,
+            count: 512,
+            block_ms: 1000,
+        }
+    }
+}
+
+/// A consumed transaction and the entry it came from
+struct Delivery {
+    tx: Transaction,
+    id: String,
+}
+
+/// Consume transactions from a Redis Stream into `engine` until `shutdown`
+/// resolves
+///
+/// Entries are read through a consumer group and routed to per-shard lanes
+/// like `pipeline::ingest`, so shards work in parallel while each client's
+/// transactions stay in stream order. An entry is acknowledged with `XACK`
+/// only once the engine has acknowledged its transaction, which with a
+/// write-ahead log means it is durable. Entries left unacknowledged by a
+/// crash stay pending for this consumer and are read again first when it
+/// restarts (at-least-once); redelivered deposits and withdrawals are
+/// rejected as duplicates. Entries that can't be decoded are counted as
+/// malformed and acknowledged.
+///
+/// On shutdown, transactions already read are finished and acknowledged
+/// before returning. Fails if Redis can't be reached or the engine can't
+/// process a transaction, in which case that entry stays pending.
+///
+/// # Panics
+///
+/// Panics if `options.lane_capacity` is zero.
+pub async fn consume(
+    engine: &ShardedEngine,
+    options: RedisSourceOptions,
+    shutdown: impl Future<Output = ()>,
+) -> Result<IngestStats> {
+    let client = redis::Client::open(options.url.as_str())?;
+    // Reads block their connection, so acknowledgements get their own
+    let mut reader = client.get_multiplexed_async_connection().await?;
+    let mut acker = client.get_multiplexed_async_connection().await?;
+    create_group(&mut reader, &options).await?;
+
+    let num_lanes = engine.num_shards().await.max(1);
+    let (done_sender, mut done) = mpsc::unbounded_channel::<String>();
+    let mut lanes = Vec::with_capacity(num_lanes);
+    let mut workers = JoinSet::new();
+    for _ in 0..num_lanes {
+        let (sender, mut receiver) = mpsc::channel::<Delivery>(options.lane_capacity);
+        let engine = engine.clone_handle();
+        let done = done_sender.clone();
+        workers.spawn(async move {
+            let mut processed = 0;
+            while let Some(delivery) = receiver.recv().await {
+                engine.process_transaction(delivery.tx).await?;
+                processed += 1;
+                let _ = done.send(delivery.id);
+            }
+            Ok::<u64, EngineError>(processed)
+        });
+        lanes.push(sender);
+    }
+    drop(done_sender);
+
+    let mut stats = IngestStats::default();
+    let mut malformed = Vec::new();
+    // Entries delivered before but never acknowledged come first; once
+    // they run out, only new entries are read
+    let mut pending_after = Some("0".to_string());
+    tokio::pin!(shutdown);
+
+    let consumed = loop {
+        if (&mut shutdown).now_or_never().is_some() {
+            break Ok(());
+        }
+        // Workers only stop early when the engine fails
+        if let Some(worker) = workers.try_join_next() {
+            break worker.expect("Redis worker panicked").map(|processed| {
+                stats.processed += processed;
+            });
+        }
+        if let Err(e) = acknowledge(&mut acker, &options, &mut done, &mut malformed).await {
+            break Err(e);
+        }
+
+        let mut read_options = StreamReadOptions::default()
+            .group(&options.group, &options.consumer)
+            .count(options.count);
+        let id = match &pending_after {
+            Some(id) => id.as_str(),
+            None => {
+                read_options = read_options.block(options.block_ms);
+                ">"
+            }
+        };
+        // A read that times out with nothing new replies nil
+        let reply: RedisResult<Option<StreamReadReply>> = reader
+            .xread_options(&[&options.stream], &[id], &read_options)
+            .await;
+        let entries: Vec<StreamId> = match reply {
+            Ok(reply) => reply
+                .into_iter()
+                .flat_map(|reply| reply.keys)
+                .flat_map(|key| key.ids)
+                .collect(),
+            Err(e) => break Err(e.into()),
+        };
+
+        if pending_after.is_some() {
+            pending_after = entries.last().map(|entry| entry.id.clone());
+        }
+
+        let mut stopped = false;
+        for entry in entries {
+            let Ok(tx) = decode_entry(&entry, options.payload_format) else {
+                stats.malformed += 1;
+                malformed.push(entry.id);
+                continue;
+            };
+            let lane = &lanes[engine.shard_for(tx.client, num_lanes)];
+            let delivery = Delivery { tx, id: entry.id };
+            if lane.send(delivery).await.is_err() {
+                // The worker failed; its error is reported below
+                stopped = true;
+                break;
+            }
+        }
+        if stopped {
+            break Ok(());
+        }
+    };
+
+    // Finish what was read, then acknowledge it
+    drop(lanes);
+    let mut finished = Ok(());
+    while let Some(worker) = workers.join_next().await {
+        match worker.expect("Redis worker panicked") {
+            Ok(processed) => stats.processed += processed,
+            Err(e) => finished = Err(e),
+        }
+    }
+    let acknowledged = acknowledge(&mut acker, &options, &mut done, &mut malformed).await;
+
+    consumed.and(finished).and(acknowledged).map(|()| stats)
+}
+
+/// Create the consumer group, and the stream with it, unless it exists
+async fn create_group(
+    conn: &mut MultiplexedConnection,
+    options: &RedisSourceOptions,
+) -> Result<()> {
+    let created: RedisResult<()> = conn
+        .xgroup_create_mkstream(&options.stream, &options.group, "0")
+        .await;
+    match created {
+        Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+        created => created.map_err(EngineError::from),
+    }
+}
+
+/// Acknowledge the entries processed so far, plus the `malformed` ones
+async fn acknowledge(
+    conn: &mut MultiplexedConnection,
+    options: &RedisSourceOptions,
+    done: &mut mpsc::UnboundedReceiver<String>,
+    malformed: &mut Vec<String>,
+) -> Result<()> {
+    let mut ids = std::mem::take(malformed);
+    while let Ok(id) = done.try_recv() {
+        ids.push(id);
+    }
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let _: u64 = conn.xack(&options.stream, &options.group, &ids).await?;
+    Ok(())
+}
+
+/// Read the transaction carried by a stream entry
+fn decode_entry(entry: &StreamId, payload_format: Option<MessageFormat>) -> Result<Transaction> {
+    match payload_format {
+        Some(payload_format) => {
+            let payload: Vec<u8> = entry
+                .get("payload")
+                .ok_or_else(|| missing_field("payload"))?;
+            payload_format.decode(&payload)
+        }
+        None => {
+            let field = |name| entry.get::<String>(name).ok_or_else(|| missing_field(name));
+            let amount = entry.get::<String>("amount").unwrap_or_default();
+            format::from_fields(&field("type")?, &field("client")?, &field("tx")?, &amount)
+        }
+    }
+}
+
+fn missing_field(name: &str) -> EngineError {
+    EngineError::InvalidMessage(format!("entry has no '{}' field", name))
+}
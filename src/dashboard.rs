@@ -0,0 +1,334 @@
+//! Live terminal dashboard for a server or a large batch run
+//!
+//! Built with the `tui` feature. A `Dashboard` keeps the recent history of
+//! `Sample`s taken while transactions are processed and renders it with
+//! ratatui: throughput, per-shard queue depths, rejections by reason and the
+//! accounts holding the most funds. `Screen` takes over the terminal to show
+//! it.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, RenderDirection, Row, Sparkline, Table};
+use ratatui::{Frame, Terminal};
+
+use crate::amount::Amount;
+use crate::concurrent_engine::{ShardMetrics, ShardedEngine};
+use crate::models::Account;
+use crate::outcome::{Outcome, RejectReason};
+
+/// How often the dashboard takes a sample and redraws
+pub const TICK: Duration = Duration::from_millis(250);
+/// Accounts shown under "Top held"
+pub const TOP_ACCOUNTS: usize = 10;
+/// Throughput readings kept for the sparkline, a minute's worth of ticks
+const HISTORY: usize = 240;
+
+/// Counters and accounts at one moment of a run
+#[derive(Debug, Clone, Default)]
+pub struct Sample {
+    /// Transactions applied so far
+    pub applied: u64,
+    /// Transactions rejected so far
+    pub rejected: u64,
+    /// Transactions that failed with an error so far
+    pub failed: u64,
+    /// Per-shard counters; empty for a batch run, which has no shards
+    pub shards: Vec<ShardMetrics>,
+    /// Rejections so far by reason, in any order
+    pub rejections: Vec<(RejectReason, u64)>,
+    /// Accounts with the most held funds, most first (see `top_held`)
+    pub top_held: Vec<Account>,
+}
+
+impl Sample {
+    /// Take a sample of a running `ShardedEngine`
+    pub async fn of(engine: &ShardedEngine) -> Self {
+        let shards = engine.shard_metrics().await;
+        let view = engine.accounts_view().await;
+        Self {
+            applied: shards.iter().map(|shard| shard.applied).sum(),
+            rejected: shards.iter().map(|shard| shard.rejected).sum(),
+            failed: shards.iter().map(|shard| shard.failed).sum(),
+            rejections: engine.rejection_counts().await,
+            top_held: top_held(view.iter(), TOP_ACCOUNTS),
+            shards,
+        }
+    }
+
+    /// Transactions finished with so far, whatever the result
+    pub fn processed(&self) -> u64 {
+        self.applied + self.rejected + self.failed
+    }
+
+    /// Count the outcome of a transaction processed outside a
+    /// `ShardedEngine`, e.g. by a batch run
+    pub fn record(&mut self, outcome: Outcome) {
+        let Outcome::Rejected(reason) = outcome else {
+            self.applied += 1;
+            return;
+        };
+        self.rejected += 1;
+        match self.rejections.iter_mut().find(|(r, _)| *r == reason) {
+            Some((_, count)) => *count += 1,
+            None => self.rejections.push((reason, 1)),
+        }
+    }
+}
+
+/// The `count` accounts with the most held funds, most first
+///
+/// Accounts holding nothing are left out; ties go to the lower client ID.
+pub fn top_held<'a>(accounts: impl IntoIterator<Item = &'a Account>, count: usize) -> Vec<Account> {
+    let mut holding: Vec<&Account> = accounts
+        .into_iter()
+        .filter(|account| account.held > Amount::ZERO)
+        .collect();
+    holding.sort_unstable_by(|a, b| b.held.cmp(&a.held).then(a.client_id.cmp(&b.client_id)));
+    holding.into_iter().take(count).cloned().collect()
+}
+
+/// What a dashboard shows, built up from successive samples
+pub struct Dashboard {
+    title: String,
+    started: Instant,
+    latest: Sample,
+    /// When `latest` was taken
+    sampled: Instant,
+    /// Overall transactions per second at each update, oldest first
+    throughput: VecDeque<u64>,
+    /// Transactions per second of each shard at the last update
+    shard_throughput: Vec<u64>,
+}
+
+impl Dashboard {
+    /// A dashboard for a run that started at `started`
+    pub fn new(title: impl Into<String>, started: Instant) -> Self {
+        Self {
+            title: title.into(),
+            started,
+            latest: Sample::default(),
+            sampled: started,
+            throughput: VecDeque::with_capacity(HISTORY),
+            shard_throughput: Vec::new(),
+        }
+    }
+
+    /// Take in a sample taken at `now`
+    ///
+    /// Throughput is measured since the previous sample. Shards that didn't
+    /// exist then, e.g. after a resize, count from zero.
+    pub fn update(&mut self, sample: Sample, now: Instant) {
+        let seconds = now.duration_since(self.sampled).as_secs_f64();
+        let rate = |now: u64, before: u64| -> u64 {
+            if seconds > 0.0 {
+                (now.saturating_sub(before) as f64 / seconds).round() as u64
+            } else {
+                0
+            }
+        };
+
+        if self.throughput.len() == HISTORY {
+            self.throughput.pop_front();
+        }
+        self.throughput
+            .push_back(rate(sample.processed(), self.latest.processed()));
+        self.shard_throughput = sample
+            .shards
+            .iter()
+            .map(|shard| {
+                let before = self
+                    .latest
+                    .shards
+                    .get(shard.shard)
+                    .map_or(0, ShardMetrics::processed);
+                rate(shard.processed(), before)
+            })
+            .collect();
+
+        self.latest = sample;
+        self.sampled = now;
+    }
+
+    /// Transactions per second at the last update
+    pub fn throughput(&self) -> u64 {
+        self.throughput.back().copied().unwrap_or(0)
+    }
+
+    /// The last sample taken in
+    pub fn latest(&self) -> &Sample {
+        &self.latest
+    }
+
+    /// Draw the dashboard over the whole frame
+    pub fn render(&self, frame: &mut Frame) {
+        let [summary, sparkline, panels, help] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(5),
+            Constraint::Min(6),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.render_summary(frame, summary);
+        // Newest at the right edge, older readings scrolling off the left
+        let throughput = self.throughput.iter().rev().copied().collect::<Vec<_>>();
+        frame.render_widget(
+            Sparkline::default()
+                .block(Block::bordered().title("Throughput (tx/s)"))
+                .direction(RenderDirection::RightToLeft)
+                .data(&throughput),
+            sparkline,
+        );
+
+        if self.latest.shards.is_empty() {
+            let [rejections, top_held] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(panels);
+            self.render_rejections(frame, rejections);
+            self.render_top_held(frame, top_held);
+        } else {
+            let [shards, rejections, top_held] = Layout::horizontal([
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+            ])
+            .areas(panels);
+            self.render_shards(frame, shards);
+            self.render_rejections(frame, rejections);
+            self.render_top_held(frame, top_held);
+        }
+
+        frame.render_widget(
+            Line::from("q: quit").style(Style::new().add_modifier(Modifier::DIM)),
+            help,
+        );
+    }
+
+    fn render_summary(&self, frame: &mut Frame, area: Rect) {
+        let latest = &self.latest;
+        let elapsed = self.sampled.duration_since(self.started).as_secs();
+        let text = format!(
+            "{} tx/s | processed {} | applied {} | rejected {} | failed {} | up {}m {:02}s",
+            self.throughput(),
+            latest.processed(),
+            latest.applied,
+            latest.rejected,
+            latest.failed,
+            elapsed / 60,
+            elapsed % 60
+        );
+        frame.render_widget(
+            Paragraph::new(text).block(Block::bordered().title(self.title.as_str())),
+            area,
+        );
+    }
+
+    fn render_shards(&self, frame: &mut Frame, area: Rect) {
+        let rows = self.latest.shards.iter().map(|shard| {
+            let throughput = self.shard_throughput.get(shard.shard).copied().unwrap_or(0);
+            Row::new([
+                shard.shard.to_string(),
+                shard.queue_depth.to_string(),
+                throughput.to_string(),
+                shard.processed().to_string(),
+            ])
+        });
+        let table = Table::new(rows, [Constraint::Fill(1); 4])
+            .header(header(["shard", "queue", "tx/s", "processed"]))
+            .block(Block::bordered().title("Shards"));
+        frame.render_widget(table, area);
+    }
+
+    fn render_rejections(&self, frame: &mut Frame, area: Rect) {
+        let mut rejections = self.latest.rejections.clone();
+        // Stable, so ties keep the order they came in
+        rejections.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        let rows = rejections
+            .iter()
+            .map(|(reason, count)| Row::new([reason.to_string(), count.to_string()]));
+        let table = Table::new(rows, [Constraint::Fill(3), Constraint::Fill(1)])
+            .header(header(["reason", "count"]))
+            .block(Block::bordered().title("Rejections"));
+        frame.render_widget(table, area);
+    }
+
+    fn render_top_held(&self, frame: &mut Frame, area: Rect) {
+        let rows = self.latest.top_held.iter().map(|account| {
+            Row::new([
+                account.client_id.to_string(),
+                account.held.to_string(),
+                account.total().to_string(),
+                if account.locked { "locked" } else { "" }.to_string(),
+            ])
+        });
+        let table = Table::new(rows, [Constraint::Fill(1); 4])
+            .header(header(["client", "held", "total", ""]))
+            .block(Block::bordered().title("Top held"));
+        frame.render_widget(table, area);
+    }
+}
+
+fn header<const N: usize>(cells: [&'static str; N]) -> Row<'static> {
+    Row::new(cells).style(Style::new().add_modifier(Modifier::BOLD))
+}
+
+/// A terminal, taken over to show a dashboard until dropped
+///
+/// Switches `W`, stdout or stderr, to the alternate screen in raw mode, so
+/// key presses reach `quit_requested` instead of raising signals; Ctrl-C is
+/// one of the keys that quit.
+pub struct Screen<W: Write> {
+    terminal: Terminal<CrosstermBackend<W>>,
+}
+
+impl<W: Write> Screen<W> {
+    pub fn open(out: W) -> io::Result<Self> {
+        let mut screen = Self {
+            terminal: Terminal::new(CrosstermBackend::new(out))?,
+        };
+        // Undone by `drop` from here on
+        enable_raw_mode()?;
+        execute!(screen.terminal.backend_mut(), EnterAlternateScreen)?;
+        screen.terminal.clear()?;
+        Ok(screen)
+    }
+
+    pub fn draw(&mut self, dashboard: &Dashboard) -> io::Result<()> {
+        self.terminal.draw(|frame| dashboard.render(frame))?;
+        Ok(())
+    }
+
+    /// Whether q, Esc or Ctrl-C was pressed since the last call; never waits
+    pub fn quit_requested(&mut self) -> io::Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) || ctrl_c;
+                if quit && key.kind == KeyEventKind::Press {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<W: Write> Drop for Screen<W> {
+    fn drop(&mut self) {
+        // Nothing to do about a terminal that can't be restored
+        let _ = execute!(self.terminal.backend_mut(), LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+        let _ = self.terminal.show_cursor();
+    }
+}
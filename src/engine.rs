@@ -1,221 +1,1268 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::future::{self, Future};
+use std::path::Path;
 
-use rust_decimal::Decimal;
+pub use crate::account_store::AccountOrdering;
+use crate::account_store::AccountStore;
+use crate::amount::Amount;
+use crate::archive::ArchiveBackend;
+use crate::config::EngineConfig;
+use crate::error::Result;
+use crate::events::AccountEvent;
+use crate::history::{AnonymizedEntry, Balance, History, HistoryEntry, RejectedWithdrawal};
+use crate::hooks::Hooks;
+use crate::invariants::{InvariantReport, InvariantViolation};
+use crate::memory::{self, MemoryFootprint};
+use crate::models::{
+    Account, ClientId, Hold, Metadata, StoredTransaction, Transaction, TransactionType, TxId,
+};
+use crate::outcome::{Outcome, RejectReason};
+use crate::resume;
+use crate::settlement::{self, SettlementSummary};
+use crate::state::{AccountState, EngineState, Tombstone};
+use crate::statement::Statement;
+use crate::suspicious::{self, ReportRules, SuspiciousActivity};
+use crate::transition;
+use crate::tx_store::{TransactionStore, TxIdSet};
 
-use crate::models::{Account, StoredTransaction, Transaction, TransactionType};
+/// Result of a single processing step: `Err` carries the rejection reason
+type StepResult = std::result::Result<(), RejectReason>;
+
+/// Operations shared by every execution model
+///
+/// Implemented by `PaymentsEngine`, `PersistentEngine` and `ShardedEngine`,
+/// so code that feeds transactions or reads accounts can be written once and
+/// run against any of them. Methods return futures so the sharded engine can
+/// wait on its shards; the single-threaded engines answer immediately.
+///
+/// Inherent methods with the same names take precedence when the concrete
+/// type is known; these are for code generic over `E: Engine`.
+pub trait Engine {
+    /// Process one transaction; `Err` only for system failures such as
+    /// persistence errors, never for rejected transactions
+    fn process(&mut self, tx: Transaction) -> impl Future<Output = Result<Outcome>> + Send;
+
+    /// Current state of one client's account
+    fn get_account(&self, client_id: ClientId) -> impl Future<Output = Option<Account>> + Send;
+
+    /// All accounts, sorted by client ID
+    fn accounts(&self) -> impl Future<Output = Vec<Account>> + Send;
+
+    /// Summary counts over the whole engine
+    fn stats(&self) -> impl Future<Output = EngineStats> + Send;
+
+    /// Funds held across the whole engine
+    fn funds(&self) -> impl Future<Output = FundsSummary> + Send;
+
+    /// What processing `tx` would do, without changing anything; `Err` only
+    /// for system failures, as with `process`
+    fn simulate(&self, tx: Transaction) -> impl Future<Output = Result<Simulation>> + Send;
+}
+
+/// What a transaction would do, as worked out by `Engine::simulate`
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    /// Whether the transaction would be applied, and if not why
+    pub outcome: Outcome,
+    /// The client's account as it would be afterwards: unchanged if the
+    /// transaction would be rejected, `None` if there is no account and the
+    /// transaction wouldn't open one
+    pub account: Option<Account>,
+}
+
+/// Summary counts reported by `Engine::stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EngineStats {
+    /// Client accounts
+    pub accounts: usize,
+    /// Accounts frozen by a chargeback
+    pub locked_accounts: usize,
+    /// Deposits currently under dispute
+    pub open_disputes: usize,
+}
+
+/// Funds across all clients, reported by `Engine::funds`, e.g. to reconcile
+/// the ledger against the bank account holding the clients' money
+///
+/// Balances of erased clients (see `PaymentsEngine::erase_client`) still
+/// count, since their money didn't leave with their data.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FundsSummary {
+    /// Sum of every available balance
+    pub available: Amount,
+    /// Sum of every held balance
+    pub held: Amount,
+    /// Sum of every deposit charged back
+    pub charged_back: Amount,
+    /// Deposits currently under dispute
+    pub open_disputes: usize,
+}
+
+impl FundsSummary {
+    /// Funds the clients hold in total (available + held)
+    pub fn total(&self) -> Amount {
+        self.available + self.held
+    }
+}
 
 /// Transaction processing engine
 pub struct PaymentsEngine {
-    /// Map of client ID to account
-    accounts: HashMap<u16, Account>,
-    /// Map of transaction ID to stored disputable transactions (deposits only)
-    disputable_transactions: HashMap<u32, StoredTransaction>,
+    /// Client accounts keyed by client ID
+    accounts: AccountStore,
+    /// Stored disputable transactions keyed by ID (deposits only), optionally
+    /// spilling older ones to disk
+    disputable_transactions: TransactionStore,
     /// Set of all processed transaction IDs (for duplicate detection)
-    processed_tx_ids: HashSet<u32>,
+    ///
+    /// A compressed bitmap: dense runs of IDs take a few bits each, where a
+    /// hash set would spend tens of bytes per ID.
+    processed_tx_ids: TxIdSet,
+    /// Log of applied transactions, only kept when history is enabled
+    history: Option<History>,
+    /// Funds held for each open dispute, keyed by the disputed deposit's ID
+    holds: HashMap<TxId, Hold>,
+    /// Held balances seeded via `with_initial_accounts`, not backed by disputes
+    seeded_held: HashMap<ClientId, Amount>,
+    /// Clients removed by `erase_client`, with the balances they left behind
+    erased: HashMap<ClientId, Tombstone>,
+    /// Amount charged back per client
+    charged_back: HashMap<ClientId, Amount>,
+    config: EngineConfig,
+    /// Callbacks run around each transaction
+    hooks: Hooks,
+    /// Transactions applied since the invariant watchdog last ran
+    unchecked: u64,
 }
 
 impl PaymentsEngine {
     /// Create a new payments engine
     pub fn new() -> Self {
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// Create a new payments engine with the given configuration
+    pub fn with_config(config: EngineConfig) -> Self {
         Self {
-            accounts: HashMap::new(),
-            disputable_transactions: HashMap::new(),
-            processed_tx_ids: HashSet::new(),
+            accounts: AccountStore::new(config.account_ordering),
+            disputable_transactions: TransactionStore::new(),
+            processed_tx_ids: TxIdSet::new(),
+            history: None,
+            holds: HashMap::new(),
+            seeded_held: HashMap::new(),
+            erased: HashMap::new(),
+            charged_back: HashMap::new(),
+            config,
+            hooks: Hooks::new(),
+            unchecked: 0,
         }
     }
 
-    /// Process a single transaction
-    pub fn process_transaction(&mut self, tx: Transaction) {
-        // Check for duplicate transaction ID for deposits and withdrawals only
-        // (dispute/resolve/chargeback reference existing transaction IDs)
-        if matches!(
-            tx.tx_type,
-            TransactionType::Deposit | TransactionType::Withdrawal
-        ) && self.processed_tx_ids.contains(&tx.tx)
-        {
-            return;
+    /// Create a new payments engine sized for `clients` accounts and
+    /// `transactions` stored deposits
+    ///
+    /// Pre-allocating avoids rehashing the account and deposit tables over
+    /// and over as a large batch fills them. The numbers are hints: the
+    /// engine still grows past them, and overestimating only costs the
+    /// unused memory. See `reserve` to size an engine built another way.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use payments_engine::engine::PaymentsEngine;
+    ///
+    /// let engine = PaymentsEngine::with_capacity(10_000, 1_000_000);
+    /// ```
+    pub fn with_capacity(clients: usize, transactions: usize) -> Self {
+        let mut engine = Self::new();
+        engine.reserve(clients, transactions);
+        engine
+    }
+
+    /// Make room for `clients` more accounts and `transactions` more stored
+    /// deposits without reallocating
+    ///
+    /// Only deposits are stored, so withdrawals and dispute traffic in a
+    /// transaction count can be left out. With `AccountOrdering::ByClientId`
+    /// accounts aren't pre-allocated, and with a spill file the deposits
+    /// reserved are capped at its in-memory limit.
+    pub fn reserve(&mut self, clients: usize, transactions: usize) {
+        self.accounts.reserve(clients);
+        self.disputable_transactions.reserve(transactions);
+    }
+
+    /// Create a new payments engine with the given account storage ordering
+    ///
+    /// `AccountOrdering::ByClientId` lets `into_sorted_accounts` stream results
+    /// in client order without collecting and sorting them first.
+    pub fn with_account_ordering(ordering: AccountOrdering) -> Self {
+        Self::with_config(EngineConfig {
+            account_ordering: ordering,
+            ..EngineConfig::default()
+        })
+    }
+
+    /// Get the engine configuration
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Replace the engine configuration, e.g. after the config file changed
+    ///
+    /// Takes effect from the next transaction. `account_ordering` is fixed when
+    /// the engine is created, so the current ordering is kept.
+    pub fn set_config(&mut self, config: EngineConfig) {
+        self.config = EngineConfig {
+            account_ordering: self.config.account_ordering,
+            ..config
+        };
+    }
+
+    /// Get the hooks run around each transaction
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// Replace the hooks run around each transaction (see `Hooks`)
+    ///
+    /// Takes effect from the next transaction. Hooks only see transactions
+    /// processed from here on: state loaded with `from_state` and events
+    /// applied with `apply_event` don't go through them.
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.hooks = hooks;
+    }
+
+    /// Keep a per-client history of applied transactions
+    ///
+    /// Required for point-in-time queries such as `balance_at`. History grows
+    /// with every applied transaction, so it is off by default.
+    pub fn retain_history(mut self) -> Self {
+        self.history.get_or_insert_with(History::default);
+        self
+    }
+
+    /// Bound the memory used by stored deposits by spilling older ones to disk
+    ///
+    /// Once more than `max_in_memory` deposits are held, the oldest ones not
+    /// under dispute are moved to a file at `path` (truncated if it exists)
+    /// and read back when a dispute, resolve or chargeback references them.
+    /// The file is sparse, indexed by transaction ID, so it takes little disk
+    /// space when IDs are dense. It is scratch space only: state and
+    /// snapshots still carry every deposit.
+    pub fn spill_transactions(
+        mut self,
+        path: impl AsRef<Path>,
+        max_in_memory: usize,
+    ) -> Result<Self> {
+        self.disputable_transactions
+            .attach_spill_file(path.as_ref(), max_in_memory)?;
+        Ok(self)
+    }
+
+    /// Write deposits to `archive` before the retention policy drops them
+    /// (see `archive`)
+    ///
+    /// Replaces any archive attached before. Without a retention policy
+    /// nothing is dropped, so nothing is archived either.
+    pub fn set_archive(&mut self, archive: impl ArchiveBackend + 'static) {
+        self.disputable_transactions
+            .attach_archive(Box::new(archive));
+    }
+
+    /// A deposit the archive holds, for audits; `None` if it holds none or
+    /// no archive is attached
+    pub fn archived_transaction(&self, tx_id: TxId) -> Result<Option<StoredTransaction>> {
+        self.disputable_transactions.archived(tx_id)
+    }
+
+    /// Make the deposits archived so far durable (see `ArchiveBackend::sync`)
+    pub fn sync_archive(&mut self) -> Result<()> {
+        self.disputable_transactions.sync_archive()
+    }
+
+    /// Create an engine seeded with existing account balances
+    ///
+    /// Used when migrating from another ledger system: accounts start with the
+    /// given available/held balances and locked status. Held funds seeded this
+    /// way have no associated dispute, so they can't be resolved or charged back.
+    pub fn with_initial_accounts<I: IntoIterator<Item = Account>>(accounts: I) -> Self {
+        let mut engine = Self::new();
+        engine.seed_accounts(accounts);
+        engine
+    }
+
+    /// Seed account balances into this engine, replacing any existing accounts
+    /// for the same clients
+    ///
+    /// See `with_initial_accounts`; this variant works with engines built
+    /// through `with_config`.
+    pub fn seed_accounts<I: IntoIterator<Item = Account>>(&mut self, accounts: I) {
+        for account in accounts {
+            if account.held != Amount::ZERO {
+                self.seeded_held.insert(account.client_id, account.held);
+            } else {
+                self.seeded_held.remove(&account.client_id);
+            }
+            self.accounts.insert(account);
         }
+    }
 
-        // Validate amount for deposit/withdrawal
-        if matches!(
-            tx.tx_type,
-            TransactionType::Deposit | TransactionType::Withdrawal
-        ) {
-            if let Some(amount) = tx.amount {
-                // Reject negative or zero amounts for deposits/withdrawals
-                if amount <= Decimal::ZERO {
-                    return;
+    /// Restore an engine from previously saved state
+    pub fn from_state(state: EngineState) -> Self {
+        Self::from_state_with_config(state, EngineConfig::default())
+    }
+
+    /// Restore an engine from saved state, like `from_state`, with `config`
+    /// instead of the default configuration
+    pub fn from_state_with_config(state: EngineState, config: EngineConfig) -> Self {
+        let mut accounts = AccountStore::new(config.account_ordering);
+        for account in state.accounts {
+            accounts.insert(account.into());
+        }
+
+        let mut holds: HashMap<TxId, Hold> = state
+            .holds
+            .into_iter()
+            .map(|hold| (hold.tx_id, hold))
+            .collect();
+        // State saved before holds were tracked only flags the deposits
+        for stored_tx in &state.disputable_transactions {
+            if stored_tx.disputed {
+                holds.entry(stored_tx.tx_id).or_insert(Hold {
+                    tx_id: stored_tx.tx_id,
+                    client_id: stored_tx.client_id,
+                    amount: stored_tx.amount,
+                    placed_at: None,
+                });
+            }
+        }
+
+        Self {
+            accounts,
+            disputable_transactions: state.disputable_transactions.into_iter().collect(),
+            processed_tx_ids: state.processed_tx_ids.into_iter().collect(),
+            history: None,
+            holds,
+            seeded_held: state.seeded_held.into_iter().collect(),
+            erased: state
+                .erased
+                .into_iter()
+                .map(|tombstone| (tombstone.client, tombstone))
+                .collect(),
+            charged_back: state.charged_back.into_iter().collect(),
+            config,
+            hooks: Hooks::new(),
+            unchecked: 0,
+        }
+    }
+
+    /// Capture the engine state so it can be saved and restored later
+    ///
+    /// Entries are sorted by ID so the same state always serializes identically.
+    ///
+    /// # Panics
+    ///
+    /// Panics if deposits spilled by `spill_transactions` can't be read back.
+    pub fn to_state(&self) -> EngineState {
+        let mut accounts: Vec<AccountState> = self.accounts.values().map(Into::into).collect();
+        accounts.sort_by_key(|a| a.client);
+
+        let mut disputable_transactions = self
+            .disputable_transactions
+            .all()
+            .expect("failed to read spilled transactions");
+        disputable_transactions.sort_by_key(|t| t.tx_id);
+
+        // The bitmap iterates in ascending order
+        let processed_tx_ids: Vec<_> = self.processed_tx_ids.iter().collect();
+
+        let mut holds: Vec<Hold> = self.holds.values().copied().collect();
+        holds.sort_unstable_by_key(|hold| hold.tx_id);
+
+        let mut seeded_held: Vec<_> = self
+            .seeded_held
+            .iter()
+            .map(|(client_id, held)| (*client_id, *held))
+            .collect();
+        seeded_held.sort_unstable_by_key(|(client_id, _)| *client_id);
+
+        let mut erased: Vec<_> = self.erased.values().copied().collect();
+        erased.sort_unstable_by_key(|tombstone| tombstone.client);
+
+        let mut charged_back: Vec<_> = self
+            .charged_back
+            .iter()
+            .map(|(client_id, amount)| (*client_id, *amount))
+            .collect();
+        charged_back.sort_unstable_by_key(|(client_id, _)| *client_id);
+
+        EngineState {
+            accounts,
+            disputable_transactions,
+            processed_tx_ids,
+            holds,
+            seeded_held,
+            erased,
+            charged_back,
+            // Inputs are tracked by whoever applies them
+            sources: Vec::new(),
+        }
+    }
+
+    /// Export the full engine state as a compact binary snapshot
+    ///
+    /// Covers accounts, disputable transactions, processed IDs and the
+    /// tombstones of erased clients, so the snapshot can be moved between
+    /// hosts or archived independently of the WAL.
+    pub fn export_snapshot(&self) -> Result<Vec<u8>> {
+        self.to_state().to_snapshot_bytes()
+    }
+
+    /// Rebuild an engine from a snapshot produced by `export_snapshot`
+    pub fn import_snapshot(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::from_state(EngineState::from_snapshot_bytes(bytes)?))
+    }
+
+    /// Rebuild an engine from the account events another engine emitted,
+    /// e.g. the stream from `ShardedEngine::subscribe`
+    ///
+    /// Events the engine can't apply are skipped; see `apply_event`.
+    pub fn from_events<I: IntoIterator<Item = AccountEvent>>(events: I) -> Self {
+        let mut engine = Self::new();
+        for event in events {
+            engine.apply_event(event);
+        }
+        engine
+    }
+
+    /// Apply an account event as if the transaction behind it were processed
+    ///
+    /// The account takes the state in the event, and the deposit store,
+    /// processed IDs and history are updated to match, so a projection or
+    /// replica fed the event stream ends up where the emitting engine was.
+    /// Amounts aren't in events: a deposit or withdrawal moved the
+    /// difference in available funds since the client's previous event.
+    /// Events must therefore start from this engine's state (empty, or
+    /// restored with `from_state` from the point the stream picks up) and
+    /// come in order for each client; different clients may interleave.
+    ///
+    /// Events are checked against the account version. An event no newer
+    /// than the account is rejected with `DuplicateTransaction`, so an
+    /// overlapping replay is harmless, and one that skips a version with
+    /// `VersionMismatch`, since a missing event would throw off the amounts.
+    /// An event referencing a deposit the engine doesn't have is rejected as
+    /// `process_transaction` would reject the transaction.
+    pub fn apply_event(&mut self, event: AccountEvent) -> Outcome {
+        let client_id = event.account.client_id;
+        if self.erased.contains_key(&client_id) {
+            return Outcome::Rejected(RejectReason::ClientErased);
+        }
+
+        let current = self.accounts.get(&client_id);
+        let version = current.map_or(0, |account| account.version);
+        if event.account.version <= version {
+            return Outcome::Rejected(RejectReason::DuplicateTransaction);
+        }
+        if event.account.version != version + 1 {
+            return Outcome::Rejected(RejectReason::VersionMismatch);
+        }
+        let moved = event.account.available - current.map_or(Amount::ZERO, |a| a.available);
+        let released = current.map_or(Amount::ZERO, |a| a.held) - event.account.held;
+
+        let amount = match event.tx_type {
+            TransactionType::Deposit => {
+                self.disputable_transactions.insert(StoredTransaction::new(
+                    event.tx,
+                    client_id,
+                    moved,
+                    TransactionType::Deposit,
+                ));
+                self.processed_tx_ids.insert(event.tx);
+                Some(moved)
+            }
+            TransactionType::Withdrawal => {
+                self.processed_tx_ids.insert(event.tx);
+                Some(-moved)
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                let stored_tx =
+                    match referenced_deposit(&mut self.disputable_transactions, event.tx) {
+                        Ok(stored_tx) => stored_tx,
+                        Err(reason) => return Outcome::Rejected(reason),
+                    };
+                if stored_tx.client_id != client_id {
+                    return Outcome::Rejected(RejectReason::ClientMismatch);
                 }
-            } else {
-                return;
+                stored_tx.disputed = event.tx_type == TransactionType::Dispute;
+                if stored_tx.disputed {
+                    self.holds.insert(
+                        event.tx,
+                        Hold {
+                            tx_id: event.tx,
+                            client_id,
+                            amount: stored_tx.amount,
+                            placed_at: None,
+                        },
+                    );
+                } else {
+                    self.holds.remove(&event.tx);
+                }
+                if event.tx_type == TransactionType::Chargeback {
+                    *self.charged_back.entry(client_id).or_default() += released;
+                }
+                None
             }
+        };
+
+        self.accounts.insert(event.account);
+        // Events don't carry the transaction's timestamp or metadata
+        self.record_history(
+            event.tx,
+            event.tx_type,
+            client_id,
+            amount,
+            None,
+            &Metadata::new(),
+        );
+        self.disputable_transactions.tick(&self.config.retention);
+        Outcome::Applied
+    }
+
+    /// Process a transaction only if the client's account is still at
+    /// `expected_version`
+    ///
+    /// Compare-and-swap for callers that decided on the transaction after
+    /// reading the account: if anything changed the account since, the
+    /// transaction is rejected with `RejectReason::VersionMismatch` and the
+    /// caller can read the account again and reconsider. A client without an
+    /// account is at version 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use payments_engine::engine::PaymentsEngine;
+    /// # use payments_engine::models::Transaction;
+    /// # use payments_engine::outcome::{Outcome, RejectReason};
+    /// # let deposit = |tx, amount: &str| Transaction::deposit(1, tx, amount.parse().unwrap());
+    /// let mut engine = PaymentsEngine::new();
+    /// engine.process_transaction(deposit(1, "10"));
+    /// let reviewed = engine.get_account(1).unwrap().version;
+    ///
+    /// // Someone else changes the account in the meantime
+    /// engine.process_transaction(deposit(2, "5"));
+    ///
+    /// assert_eq!(
+    ///     engine.process_transaction_if_version(deposit(3, "1"), reviewed),
+    ///     Outcome::Rejected(RejectReason::VersionMismatch)
+    /// );
+    /// ```
+    pub fn process_transaction_if_version(
+        &mut self,
+        tx: Transaction,
+        expected_version: u64,
+    ) -> Outcome {
+        if self.account_version(tx.client) != expected_version {
+            return Outcome::Rejected(RejectReason::VersionMismatch);
+        }
+        self.process_transaction(tx)
+    }
+
+    /// Process a single transaction
+    ///
+    /// Returns whether the transaction was applied or why it was rejected.
+    /// Pre-commit hooks run once the transaction has passed the checks for
+    /// duplicates and amounts, post-commit hooks once it is applied.
+    pub fn process_transaction(&mut self, tx: Transaction) -> Outcome {
+        if let Err(reason) = self.precheck(&tx) {
+            return self.reject(&tx, reason);
+        }
+        if let Err(reason) = self.hooks.before(&tx, self.accounts.get(&tx.client)) {
+            return self.reject(&tx, reason);
         }
 
         let tx_id = tx.tx;
         let tx_type = tx.tx_type;
+        let client_id = tx.client;
+        let tx_amount = tx.amount;
 
-        match tx_type {
+        let result = match tx_type {
             TransactionType::Deposit => {
-                self.process_deposit(tx);
+                let result = self.process_deposit(&tx);
                 // Mark deposit transaction ID as processed
                 self.processed_tx_ids.insert(tx_id);
+                result
             }
             TransactionType::Withdrawal => {
-                self.process_withdrawal(tx);
+                let result = self.process_withdrawal(&tx);
                 // Mark withdrawal transaction ID as processed
                 self.processed_tx_ids.insert(tx_id);
+                result
             }
-            TransactionType::Dispute => self.process_dispute(tx),
-            TransactionType::Resolve => self.process_resolve(tx),
-            TransactionType::Chargeback => self.process_chargeback(tx),
+            TransactionType::Dispute => self.process_dispute(&tx),
+            TransactionType::Resolve => self.process_resolve(&tx),
+            TransactionType::Chargeback => self.process_chargeback(&tx),
+        };
+
+        match result {
+            Ok(()) => {
+                self.record_history(
+                    tx_id,
+                    tx_type,
+                    client_id,
+                    tx_amount,
+                    tx.timestamp,
+                    &tx.metadata,
+                );
+                self.disputable_transactions.tick(&self.config.retention);
+                if let Some(account) = self.accounts.get(&client_id) {
+                    self.hooks.after(&tx, account);
+                }
+                self.watch();
+                Outcome::Applied
+            }
+            Err(reason) => self.reject(&tx, reason),
         }
     }
 
-    /// Process a deposit transaction
-    fn process_deposit(&mut self, tx: Transaction) {
-        let amount = tx.amount.expect("amount validated by process_transaction");
-
-        // Get or create account
-        let account = self
-            .accounts
-            .entry(tx.client)
-            .or_insert_with(|| Account::new(tx.client));
-
-        // Process deposit (returns false if account is locked)
-        if !account.deposit(amount) {
+    /// Count an applied transaction, checking the invariants once
+    /// `EngineConfig::invariant_check_every` have been applied since the
+    /// last check and raising any violations through the alert hooks
+    fn watch(&mut self) {
+        let Some(every) = self.config.invariant_check_every.filter(|&every| every > 0) else {
+            return;
+        };
+        self.unchecked += 1;
+        if self.unchecked < every {
             return;
         }
+        self.unchecked = 0;
+        let report = self.check_invariants();
+        if !report.is_ok() {
+            self.hooks.raise(&report);
+        }
+    }
 
-        // Store transaction for potential dispute
-        self.disputable_transactions.insert(
+    /// Reject `tx`, noting it in the history if it is a failed withdrawal
+    ///
+    /// Resubmitted withdrawals and those of erased clients aren't attempts
+    /// to take money out, so they aren't noted.
+    fn reject(&mut self, tx: &Transaction, reason: RejectReason) -> Outcome {
+        let attempted = !matches!(
+            reason,
+            RejectReason::DuplicateTransaction | RejectReason::ClientErased
+        );
+        if let Some(history) = self.history.as_mut() {
+            if tx.tx_type == TransactionType::Withdrawal && attempted {
+                history.record_rejected_withdrawal(
+                    tx.client,
+                    tx.tx,
+                    tx.amount,
+                    tx.timestamp,
+                    reason,
+                );
+            }
+        }
+        log::trace!(
+            "Rejected {} {} for client {}: {}",
+            tx.tx_type,
             tx.tx,
-            StoredTransaction::new(tx.tx, tx.client, amount, TransactionType::Deposit),
+            tx.client,
+            reason
         );
+        Outcome::Rejected(reason)
     }
 
-    /// Process a withdrawal transaction
-    fn process_withdrawal(&mut self, tx: Transaction) {
-        let amount = tx.amount.expect("amount validated by process_transaction");
-
-        // Get account (ignore if doesn't exist)
-        let account = match self.accounts.get_mut(&tx.client) {
-            Some(acc) => acc,
-            None => return,
+    /// What processing `tx` would do, without changing anything
+    ///
+    /// Runs the same checks and transitions as `process_transaction`, on
+    /// copies of the account and of the deposit it refers to, so a frontend
+    /// can warn that a withdrawal will fail before it is submitted. A spilled
+    /// deposit is read from the spill file and left there. Pre-commit hooks
+    /// are asked too, so they should only decide, not act; post-commit hooks
+    /// aren't run.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use payments_engine::engine::PaymentsEngine;
+    /// # use payments_engine::models::Transaction;
+    /// # use payments_engine::outcome::{Outcome, RejectReason};
+    /// let mut engine = PaymentsEngine::new();
+    /// engine.process_transaction(Transaction::deposit(1, 1, "10".parse().unwrap()));
+    ///
+    /// let simulation = engine.simulate(Transaction::withdrawal(1, 2, "25".parse().unwrap()));
+    /// assert_eq!(simulation.outcome, Outcome::Rejected(RejectReason::InsufficientFunds));
+    ///
+    /// let simulation = engine.simulate(Transaction::withdrawal(1, 2, "4".parse().unwrap()));
+    /// assert_eq!(simulation.outcome, Outcome::Applied);
+    /// assert_eq!(simulation.account.unwrap().available, "6".parse().unwrap());
+    /// assert_eq!(engine.get_account(1).unwrap().available, "10".parse().unwrap());
+    /// ```
+    pub fn simulate(&self, tx: Transaction) -> Simulation {
+        let mut account = self.accounts.get(&tx.client).cloned();
+        let outcome = match self.simulate_step(&tx, &mut account) {
+            Ok(()) => Outcome::Applied,
+            Err(reason) => Outcome::Rejected(reason),
         };
-
-        // Process withdrawal (returns false if insufficient funds or account is locked)
-        // Silently ignore if withdrawal fails
-        account.withdraw(amount);
+        Simulation { outcome, account }
     }
 
-    /// Process a dispute transaction
-    fn process_dispute(&mut self, tx: Transaction) {
-        // Look up the referenced transaction
-        let stored_tx = match self.disputable_transactions.get_mut(&tx.tx) {
-            Some(t) => t,
-            None => return, // Transaction doesn't exist, ignore
-        };
+    /// `process_transaction` against a copy of the client's account
+    fn simulate_step(&self, tx: &Transaction, account: &mut Option<Account>) -> StepResult {
+        self.precheck(tx)?;
+        self.hooks.before(tx, account.as_ref())?;
+        match tx.tx_type {
+            TransactionType::Deposit => {
+                let amount = tx.amount.expect("amount validated by precheck");
+                let account = account.get_or_insert_with(|| Account::new(tx.client));
+                transition::deposit(account, amount)
+            }
+            TransactionType::Withdrawal => {
+                let amount = tx.amount.expect("amount validated by precheck");
+                transition::withdraw(account.as_mut(), amount)
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                if self.disputable_transactions.is_evicted(tx.tx) {
+                    return Err(RejectReason::TransactionExpired);
+                }
+                let mut stored_tx = match self.disputable_transactions.peek(tx.tx) {
+                    Ok(Some(stored_tx)) => stored_tx,
+                    Ok(None) => return Err(RejectReason::TransactionNotFound),
+                    Err(_) => return Err(RejectReason::StorageUnavailable),
+                };
+                let hold = self.holds.get(&tx.tx);
+                match tx.tx_type {
+                    TransactionType::Dispute => {
+                        transition::dispute(&mut stored_tx, tx.client, account.as_mut()).map(drop)
+                    }
+                    TransactionType::Resolve => {
+                        transition::resolve(&mut stored_tx, hold, tx.client, account.as_mut())
+                    }
+                    _ => transition::chargeback(&mut stored_tx, hold, tx.client, account.as_mut()),
+                }
+            }
+        }
+    }
 
-        // Verify client ID matches (security check)
-        if stored_tx.client_id != tx.client {
-            return;
+    /// Checks that come before a transaction touches any account: erased
+    /// clients, duplicate IDs and amounts
+    fn precheck(&self, tx: &Transaction) -> StepResult {
+        if self.erased.contains_key(&tx.client) {
+            return Err(RejectReason::ClientErased);
         }
 
-        // Check if already disputed
-        if stored_tx.disputed {
-            return;
+        // Only deposits and withdrawals bring new IDs and amounts
+        // (dispute/resolve/chargeback reference existing transaction IDs)
+        if matches!(
+            tx.tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        ) {
+            if self.processed_tx_ids.contains(tx.tx) {
+                return Err(RejectReason::DuplicateTransaction);
+            }
+            self.config.check_amount(tx.amount)?;
         }
+        Ok(())
+    }
 
-        // Get the account
-        let account = match self.accounts.get_mut(&tx.client) {
-            Some(acc) => acc,
-            None => return, // Account doesn't exist, should not happen but handle gracefully
+    /// Append an applied transaction to the history, if history is retained
+    fn record_history(
+        &mut self,
+        tx_id: TxId,
+        tx_type: TransactionType,
+        client_id: ClientId,
+        tx_amount: Option<Amount>,
+        tx_timestamp: Option<u64>,
+        metadata: &Metadata,
+    ) {
+        let Some(history) = self.history.as_mut() else {
+            return;
         };
 
-        // Move funds from available to held (returns false if insufficient available)
-        if !account.hold(stored_tx.amount) {
+        // Dispute-related transactions move the referenced deposit's amount
+        let amount = match tx_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => tx_amount,
+            _ => self.disputable_transactions.get(&tx_id).map(|t| t.amount),
+        };
+        let Some(amount) = amount else {
             return;
+        };
+
+        if let Some(account) = self.accounts.get(&client_id) {
+            history.record(tx_id, tx_type, amount, tx_timestamp, account, metadata);
         }
+    }
 
-        // Mark transaction as disputed
-        stored_tx.disputed = true;
+    /// Process a deposit transaction
+    fn process_deposit(&mut self, tx: &Transaction) -> StepResult {
+        let amount = tx.amount.expect("amount validated by process_transaction");
+
+        // Get or create account
+        let account = self.accounts.get_or_create(tx.client);
+        transition::deposit(account, amount)?;
+
+        // Store transaction for potential dispute
+        self.disputable_transactions.insert(StoredTransaction {
+            timestamp: tx.timestamp,
+            ..StoredTransaction::new(tx.tx, tx.client, amount, TransactionType::Deposit)
+        });
+        Ok(())
     }
 
-    /// Process a resolve transaction
-    fn process_resolve(&mut self, tx: Transaction) {
-        // Look up the referenced transaction
-        let stored_tx = match self.disputable_transactions.get_mut(&tx.tx) {
-            Some(t) => t,
-            None => return, // Transaction doesn't exist, ignore
-        };
+    /// Process a withdrawal transaction
+    fn process_withdrawal(&mut self, tx: &Transaction) -> StepResult {
+        let amount = tx.amount.expect("amount validated by process_transaction");
+        transition::withdraw(self.accounts.get_mut(&tx.client), amount)
+    }
 
-        // Verify client ID matches (security check)
-        if stored_tx.client_id != tx.client {
-            return;
+    /// Process a dispute transaction
+    fn process_dispute(&mut self, tx: &Transaction) -> StepResult {
+        let stored_tx = referenced_deposit(&mut self.disputable_transactions, tx.tx)?;
+        let hold = transition::dispute(stored_tx, tx.client, self.accounts.get_mut(&tx.client))?;
+        self.holds.insert(
+            tx.tx,
+            Hold {
+                placed_at: tx.timestamp,
+                ..hold
+            },
+        );
+        Ok(())
+    }
+
+    /// Process a resolve transaction, releasing the dispute's hold
+    fn process_resolve(&mut self, tx: &Transaction) -> StepResult {
+        let stored_tx = referenced_deposit(&mut self.disputable_transactions, tx.tx)?;
+        transition::resolve(
+            stored_tx,
+            self.holds.get(&tx.tx),
+            tx.client,
+            self.accounts.get_mut(&tx.client),
+        )?;
+        self.holds.remove(&tx.tx);
+        Ok(())
+    }
+
+    /// Process a chargeback transaction, charging back the dispute's hold
+    fn process_chargeback(&mut self, tx: &Transaction) -> StepResult {
+        let stored_tx = referenced_deposit(&mut self.disputable_transactions, tx.tx)?;
+        transition::chargeback(
+            stored_tx,
+            self.holds.get(&tx.tx),
+            tx.client,
+            self.accounts.get_mut(&tx.client),
+        )?;
+        let hold = self
+            .holds
+            .remove(&tx.tx)
+            .expect("hold checked by chargeback");
+        *self.charged_back.entry(tx.client).or_default() += hold.amount;
+        Ok(())
+    }
+
+    /// Erase a client's personal data, e.g. for a GDPR erasure request
+    ///
+    /// The account, the client's stored deposits and their history entries
+    /// are removed. What they contributed to the ledger stays: the balances
+    /// become a `Tombstone` (see `erased_clients`), history entries are kept
+    /// as `AnonymizedEntry`s and their transaction IDs still count as
+    /// processed. Later transactions for the client are rejected with
+    /// `RejectReason::ClientErased`, and `to_state` carries the tombstone,
+    /// so state files and snapshots taken afterwards hold no more than that.
+    ///
+    /// Rejected with `AccountNotFound` if the client has no account and
+    /// `AlreadyDisputed` while one of their deposits is under dispute, since
+    /// the dispute still has to be settled against the account. With a spill
+    /// file (see `spill_transactions`) every spilled deposit is read back to
+    /// find the client's; if that fails the erasure is rejected with
+    /// `StorageUnavailable` and can be retried.
+    pub fn erase_client(&mut self, client_id: ClientId) -> Outcome {
+        if self.erased.contains_key(&client_id) {
+            return Outcome::Rejected(RejectReason::ClientErased);
+        }
+        if self.accounts.get(&client_id).is_none() {
+            return Outcome::Rejected(RejectReason::AccountNotFound);
+        }
+        if self
+            .open_disputes()
+            .any(|stored_tx| stored_tx.client_id == client_id)
+        {
+            return Outcome::Rejected(RejectReason::AlreadyDisputed);
+        }
+        if self
+            .disputable_transactions
+            .remove_client(client_id)
+            .is_err()
+        {
+            return Outcome::Rejected(RejectReason::StorageUnavailable);
         }
 
-        // Check if under dispute
-        if !stored_tx.disputed {
-            return; // Not under dispute, ignore
+        let account = self
+            .accounts
+            .remove(&client_id)
+            .expect("account checked above");
+        self.seeded_held.remove(&client_id);
+        if let Some(history) = self.history.as_mut() {
+            history.erase(client_id);
         }
+        self.erased.insert(client_id, Tombstone::from(&account));
+        Outcome::Applied
+    }
+
+    /// Tombstones of every client removed by `erase_client`, in no particular
+    /// order
+    pub fn erased_clients(&self) -> impl Iterator<Item = &Tombstone> {
+        self.erased.values()
+    }
+
+    /// History entries of erased clients in the order they happened
+    ///
+    /// Empty if history isn't retained.
+    pub fn anonymized_history(&self) -> &[AnonymizedEntry] {
+        self.history
+            .as_ref()
+            .map(History::anonymized)
+            .unwrap_or_default()
+    }
+
+    /// Get all client accounts
+    pub fn get_accounts(&self) -> Vec<&Account> {
+        self.accounts.values().collect()
+    }
+
+    /// Look up a single client account
+    pub fn get_account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+
+    /// Current `Account::version` of a client, 0 if it has no account
+    pub fn account_version(&self, client_id: ClientId) -> u64 {
+        self.get_account(client_id)
+            .map_or(0, |account| account.version)
+    }
 
-        // Get the account
-        let account = match self.accounts.get_mut(&tx.client) {
-            Some(acc) => acc,
-            None => return, // Account doesn't exist, should not happen but handle gracefully
+    /// Summary counts over all accounts and transactions
+    pub fn stats(&self) -> EngineStats {
+        EngineStats {
+            accounts: self.accounts.len(),
+            locked_accounts: self.accounts_iter().filter(|a| a.locked).count(),
+            open_disputes: self.holds.len(),
+        }
+    }
+
+    /// Funds across all clients, including what erased clients left behind
+    ///
+    /// Like `stats`, this walks every account.
+    pub fn funds(&self) -> FundsSummary {
+        let mut funds = FundsSummary {
+            open_disputes: self.holds.len(),
+            ..FundsSummary::default()
         };
+        for amount in self.charged_back.values() {
+            funds.charged_back += *amount;
+        }
+        for account in self.accounts_iter() {
+            funds.available += account.available;
+            funds.held += account.held;
+        }
+        for tombstone in self.erased.values() {
+            funds.available += tombstone.available;
+            funds.held += tombstone.held;
+        }
+        funds
+    }
 
-        // Move funds from held back to available (returns false if insufficient held)
-        if !account.release(stored_tx.amount) {
-            return;
+    /// A hash of every balance, lock, erased client and open dispute
+    ///
+    /// Engines that applied the same transactions to the same state have the
+    /// same digest whatever order they keep clients in, and so does an engine
+    /// restored from their state, which is how recovery checks what it
+    /// rebuilt (see `persistent_engine::IntegrityCheck`). Like `stats`, this
+    /// walks every account.
+    pub fn state_digest(&self) -> u64 {
+        let mut accounts: Vec<&Account> = self.accounts_iter().collect();
+        accounts.sort_unstable_by_key(|account| account.client_id);
+        let mut erased: Vec<&Tombstone> = self.erased.values().collect();
+        erased.sort_unstable_by_key(|tombstone| tombstone.client);
+        let mut holds: Vec<&Hold> = self.holds.values().collect();
+        holds.sort_unstable_by_key(|hold| hold.tx_id);
+
+        let mut text = String::new();
+        for account in accounts {
+            let _ = write!(
+                text,
+                "{},{},{},{};",
+                account.client_id, account.available, account.held, account.locked
+            );
+        }
+        text.push('|');
+        for tombstone in erased {
+            let _ = write!(
+                text,
+                "{},{},{},{};",
+                tombstone.client, tombstone.available, tombstone.held, tombstone.locked
+            );
         }
+        text.push('|');
+        for hold in holds {
+            let _ = write!(text, "{},{},{};", hold.tx_id, hold.client_id, hold.amount);
+        }
+        resume::fingerprint(text.as_bytes())
+    }
+
+    /// Estimated heap bytes held by the engine's state
+    ///
+    /// Grows with clients, stored deposits and processed transaction IDs.
+    /// Stored deposits spilled to disk (see `spill_transactions`) only count
+    /// for their bookkeeping, so comparing `stored_transactions` with the
+    /// total shows how much a spill file would save. Cost is proportional to
+    /// the number of clients with history when history is retained, and
+    /// constant otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use payments_engine::engine::PaymentsEngine;
+    /// use payments_engine::models::Transaction;
+    ///
+    /// let mut engine = PaymentsEngine::new();
+    /// let before = engine.memory_footprint().total();
+    /// engine.process_transaction(Transaction::deposit(1, 1, "100.0".parse().unwrap()));
+    /// assert!(engine.memory_footprint().total() > before);
+    /// ```
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        MemoryFootprint {
+            accounts: self.accounts.memory_footprint()
+                + memory::hash_map_bytes(&self.holds)
+                + memory::hash_map_bytes(&self.seeded_held)
+                + memory::hash_map_bytes(&self.erased)
+                + memory::hash_map_bytes(&self.charged_back),
+            stored_transactions: self.disputable_transactions.memory_footprint(),
+            processed_tx_ids: memory::bitmap_bytes(&self.processed_tx_ids),
+            history: self.history.as_ref().map_or(0, History::memory_footprint),
+        }
+    }
+
+    /// Iterate over deposits that are currently under dispute
+    pub fn open_disputes(&self) -> impl Iterator<Item = &StoredTransaction> {
+        // Disputed deposits are never spilled, so memory has all of them
+        self.disputable_transactions
+            .in_memory_values()
+            .filter(|stored_tx| stored_tx.disputed)
+    }
 
-        // Mark transaction as no longer disputed
-        stored_tx.disputed = false;
+    /// Holds making up a client's held balance, one per open dispute, sorted
+    /// by the disputed deposit's ID
+    ///
+    /// Any held balance the client was seeded with (see
+    /// `with_initial_accounts`) isn't backed by a dispute, so it has no hold.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use payments_engine::engine::PaymentsEngine;
+    /// use payments_engine::models::Transaction;
+    ///
+    /// let mut engine = PaymentsEngine::new();
+    /// engine.process_transaction(Transaction::deposit(1, 1, "10".parse().unwrap()));
+    /// engine.process_transaction(Transaction::deposit(1, 2, "5".parse().unwrap()));
+    /// engine.process_transaction(Transaction::dispute(1, 2));
+    ///
+    /// let holds = engine.holds(1);
+    /// assert_eq!(holds.len(), 1);
+    /// assert_eq!((holds[0].tx_id, holds[0].amount), (2, "5".parse().unwrap()));
+    /// ```
+    pub fn holds(&self, client_id: ClientId) -> Vec<Hold> {
+        let mut holds: Vec<Hold> = self
+            .holds
+            .values()
+            .filter(|hold| hold.client_id == client_id)
+            .copied()
+            .collect();
+        holds.sort_unstable_by_key(|hold| hold.tx_id);
+        holds
     }
 
-    /// Process a chargeback transaction
-    fn process_chargeback(&mut self, tx: Transaction) {
-        // Look up the referenced transaction
-        let stored_tx = match self.disputable_transactions.get_mut(&tx.tx) {
-            Some(t) => t,
-            None => return, // Transaction doesn't exist, ignore
+    /// Balance of a client as it stood at `timestamp`
+    ///
+    /// Reflects every transaction applied at or before `timestamp` (see
+    /// `HistoryEntry::timestamp`). Returns `None` if history isn't retained or
+    /// the client had no applied transactions by then.
+    pub fn balance_at(&self, client_id: ClientId, timestamp: u64) -> Option<Balance> {
+        self.history.as_ref()?.balance_at(client_id, timestamp)
+    }
+
+    /// Applied transactions for a client in the order they happened
+    ///
+    /// Empty if history isn't retained.
+    pub fn client_history(&self, client_id: ClientId) -> &[HistoryEntry] {
+        self.history
+            .as_ref()
+            .map(|history| history.client_entries(client_id))
+            .unwrap_or_default()
+    }
+
+    /// Withdrawals of a client the engine rejected, in the order they came
+    ///
+    /// Empty if history isn't retained. Resubmitted withdrawals aren't
+    /// included.
+    pub fn rejected_withdrawals(&self, client_id: ClientId) -> &[RejectedWithdrawal] {
+        self.history
+            .as_ref()
+            .map(|history| history.client_rejected_withdrawals(client_id))
+            .unwrap_or_default()
+    }
+
+    /// Statement of a client's applied transactions, for the exporters in
+    /// `statement`
+    ///
+    /// Returns `None` if history isn't retained or the client has no applied
+    /// transactions.
+    pub fn statement(&self, client_id: ClientId) -> Option<Statement<'_>> {
+        Statement::new(client_id, self.client_history(client_id))
+    }
+
+    /// Statements of every client with applied transactions, sorted by client ID
+    ///
+    /// Empty if history isn't retained.
+    pub fn statements(&self) -> Vec<Statement<'_>> {
+        let Some(history) = &self.history else {
+            return Vec::new();
         };
+        let mut statements: Vec<_> = history
+            .iter()
+            .filter_map(|(client_id, entries)| Statement::new(client_id, entries))
+            .collect();
+        statements.sort_by_key(|statement| statement.client_id);
+        statements
+    }
 
-        // Verify client ID matches (security check)
-        if stored_tx.client_id != tx.client {
-            return;
+    /// Per-client totals for each settlement period of `period_length` timestamp units
+    ///
+    /// Built from the retained history, so it is empty if history isn't retained.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period_length` is zero.
+    pub fn settlement_summaries(&self, period_length: u64) -> Vec<SettlementSummary> {
+        match &self.history {
+            Some(history) => settlement::summarize(history, period_length),
+            None => Vec::new(),
         }
+    }
 
-        // Check if under dispute
-        if !stored_tx.disputed {
-            return; // Not under dispute, ignore
+    /// Activity matching the patterns of `rules`, for a suspicious-activity
+    /// report (see `suspicious`)
+    ///
+    /// Built from the retained history, so it is empty if history isn't retained.
+    pub fn suspicious_activity(&self, rules: &ReportRules) -> Vec<SuspiciousActivity> {
+        match &self.history {
+            Some(history) => suspicious::detect(history, rules),
+            None => Vec::new(),
         }
+    }
 
-        // Get the account
-        let account = match self.accounts.get_mut(&tx.client) {
-            Some(acc) => acc,
-            None => return, // Account doesn't exist, should not happen but handle gracefully
-        };
+    /// Verify accounting invariants across all accounts
+    ///
+    /// Checks that each account's held balance equals the sum of its holds
+    /// (plus any held balance it was seeded with), and that no balance is
+    /// negative unless `EngineConfig::allow_negative_balances` is set.
+    pub fn check_invariants(&self) -> InvariantReport {
+        let mut disputed: HashMap<ClientId, Amount> = HashMap::new();
+        for hold in self.holds.values() {
+            *disputed.entry(hold.client_id).or_default() += hold.amount;
+        }
 
-        // Remove held funds and lock account (returns false if insufficient held)
-        if !account.chargeback(stored_tx.amount) {
-            return;
+        let mut report = InvariantReport::default();
+        let mut accounts: Vec<&Account> = self.accounts.values().collect();
+        accounts.sort_by_key(|a| a.client_id);
+
+        for account in accounts {
+            report.accounts_checked += 1;
+            let client_id = account.client_id;
+
+            let expected = disputed.get(&client_id).copied().unwrap_or_default()
+                + self
+                    .seeded_held
+                    .get(&client_id)
+                    .copied()
+                    .unwrap_or_default();
+            if account.held != expected {
+                report.violations.push(InvariantViolation::HeldMismatch {
+                    client_id,
+                    held: account.held,
+                    expected,
+                });
+            }
+
+            if !self.config.allow_negative_balances {
+                if account.available < Amount::ZERO {
+                    report
+                        .violations
+                        .push(InvariantViolation::NegativeAvailable {
+                            client_id,
+                            available: account.available,
+                        });
+                }
+                if account.held < Amount::ZERO {
+                    report.violations.push(InvariantViolation::NegativeHeld {
+                        client_id,
+                        held: account.held,
+                    });
+                }
+            }
         }
 
-        // Mark transaction as no longer disputed (it's been charged back)
-        stored_tx.disputed = false;
+        report
     }
 
-    /// Get all client accounts
-    pub fn get_accounts(&self) -> Vec<&Account> {
-        self.accounts.values().collect()
+    /// Iterate over all client accounts without allocating
+    pub fn accounts_iter(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.values()
     }
 
     /// Consume the engine and return all accounts
     pub fn into_accounts(self) -> Vec<Account> {
         self.accounts.into_values().collect()
     }
+
+    /// Consume the engine and iterate over all accounts in storage order
+    pub fn into_accounts_iter(self) -> impl Iterator<Item = Account> {
+        self.accounts.into_values()
+    }
+
+    /// Consume the engine and iterate over all accounts sorted by client ID
+    ///
+    /// With `AccountOrdering::ByClientId` accounts are streamed straight out of
+    /// storage; otherwise they are collected and sorted first.
+    pub fn into_sorted_accounts(self) -> impl Iterator<Item = Account> {
+        let mut buffered = Vec::new();
+
+        let streamed = match self.accounts.ordering() {
+            AccountOrdering::ByClientId => Some(self.accounts.into_values()),
+            AccountOrdering::Unordered => {
+                buffered.extend(self.accounts.into_values());
+                buffered.sort_by_key(|a| a.client_id);
+                None
+            }
+        };
+
+        streamed.into_iter().flatten().chain(buffered)
+    }
+}
+
+/// Look up the deposit a dispute, resolve or chargeback refers to
+fn referenced_deposit(
+    store: &mut TransactionStore,
+    tx_id: TxId,
+) -> std::result::Result<&mut StoredTransaction, RejectReason> {
+    if store.is_evicted(tx_id) {
+        return Err(RejectReason::TransactionExpired);
+    }
+    match store.get_mut(&tx_id) {
+        Ok(Some(stored_tx)) => Ok(stored_tx),
+        Ok(None) => Err(RejectReason::TransactionNotFound),
+        Err(_) => Err(RejectReason::StorageUnavailable),
+    }
 }
 
 impl Default for PaymentsEngine {
@@ -223,3 +1270,31 @@ impl Default for PaymentsEngine {
         Self::new()
     }
 }
+
+impl Engine for PaymentsEngine {
+    fn process(&mut self, tx: Transaction) -> impl Future<Output = Result<Outcome>> + Send {
+        future::ready(Ok(self.process_transaction(tx)))
+    }
+
+    fn get_account(&self, client_id: ClientId) -> impl Future<Output = Option<Account>> + Send {
+        future::ready(PaymentsEngine::get_account(self, client_id).cloned())
+    }
+
+    fn accounts(&self) -> impl Future<Output = Vec<Account>> + Send {
+        let mut accounts: Vec<Account> = self.accounts_iter().cloned().collect();
+        accounts.sort_by_key(|a| a.client_id);
+        future::ready(accounts)
+    }
+
+    fn stats(&self) -> impl Future<Output = EngineStats> + Send {
+        future::ready(PaymentsEngine::stats(self))
+    }
+
+    fn funds(&self) -> impl Future<Output = FundsSummary> + Send {
+        future::ready(PaymentsEngine::funds(self))
+    }
+
+    fn simulate(&self, tx: Transaction) -> impl Future<Output = Result<Simulation>> + Send {
+        future::ready(Ok(PaymentsEngine::simulate(self, tx)))
+    }
+}
@@ -9,6 +9,49 @@ pub enum EngineError {
 
     #[error("CSV parsing error: {0}")]
     Csv(#[from] csv::Error),
+
+    #[error("State file error: {0}")]
+    State(#[from] serde_json::Error),
+
+    #[error("Snapshot encoding error: {0}")]
+    Snapshot(#[from] bincode::Error),
+
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+
+    #[cfg(feature = "kafka")]
+    #[error("Kafka error: {0}")]
+    Kafka(#[from] rdkafka::error::KafkaError),
+
+    #[cfg(feature = "nats")]
+    #[error("NATS error: {0}")]
+    Nats(async_nats::Error),
+
+    #[cfg(feature = "redis")]
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+
+    #[cfg(feature = "cluster")]
+    #[error("Replication failed: {0}")]
+    Replication(String),
+
+    #[error("Engine is read-only")]
+    ReadOnly,
+
+    #[error("Engine is shutting down")]
+    ShuttingDown,
+
+    #[error("Shard task stopped")]
+    ShardStopped,
+
+    #[error("Recovered state doesn't match the log: digest {found:016x}, logged {expected:016x}")]
+    StateMismatch { expected: u64, found: u64 },
 }
 
 pub type Result<T> = std::result::Result<T, EngineError>;
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::{Account, TransactionType, TxId};
+
+/// Notification that a transaction changed a client's account
+///
+/// Serializes as the account row (`client`, `available`, `held`, `total`,
+/// `locked`, `version`) plus the `tx` that caused the change and its `type`,
+/// so a chargeback, say, can be told apart from a deposit. Deserializes from
+/// the same shape, for rebuilding an engine with `PaymentsEngine::from_events`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AccountEvent {
+    /// Transaction that produced this account state
+    #[schema(inline)]
+    pub tx: TxId,
+    #[serde(rename = "type")]
+    pub tx_type: TransactionType,
+    #[serde(flatten)]
+    pub account: Account,
+}
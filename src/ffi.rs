@@ -0,0 +1,285 @@
+//! C interface for embedding the engine in other languages
+//!
+//! Built with the `ffi` feature into the crate's cdylib; the declarations
+//! are in `include/payments_engine.h`. An engine is an opaque pointer from
+//! `pe_engine_new` that must be released with `pe_engine_free`. Amounts
+//! cross the boundary as `int64_t` counts of 1/10000 units, so `12.5` is
+//! `125000`. Functions return `PE_OK` (0) on success, a negative `PE_*`
+//! error code, or for `pe_engine_submit` a positive code naming the
+//! `RejectReason`; `pe_status_message` describes any of them.
+//!
+//! An engine isn't thread-safe: callers must not use one from several
+//! threads at once. An amount overflowing the engine's range aborts the
+//! process, since a panic can't unwind into C.
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+use std::sync::OnceLock;
+
+use crate::amount::{Amount, FixedAmount};
+use crate::engine::{AccountOrdering, PaymentsEngine};
+use crate::models::{ClientId, Metadata, Transaction, TransactionType, TxId};
+use crate::outcome::{Outcome, RejectReason};
+use crate::output::{CsvSink, OutputSink};
+
+/// Success, or the transaction was applied
+pub const PE_OK: i32 = 0;
+/// A null pointer or unknown transaction type was passed
+pub const PE_INVALID_ARGUMENT: i32 = -1;
+/// The client has no account
+pub const PE_NOT_FOUND: i32 = -2;
+/// A balance doesn't fit in `int64_t` minor units
+pub const PE_OUT_OF_RANGE: i32 = -3;
+
+/// Transaction types for `pe_engine_submit`
+pub const PE_DEPOSIT: u8 = 0;
+pub const PE_WITHDRAWAL: u8 = 1;
+pub const PE_DISPUTE: u8 = 2;
+pub const PE_RESOLVE: u8 = 3;
+pub const PE_CHARGEBACK: u8 = 4;
+
+/// Rejection reasons in the order of their status codes, starting at 1
+///
+/// Only ever append: the codes are part of the C ABI.
+const REJECT_REASONS: [RejectReason; 19] = [
+    RejectReason::DuplicateTransaction,
+    RejectReason::MissingAmount,
+    RejectReason::NonPositiveAmount,
+    RejectReason::AmountAboveLimit,
+    RejectReason::TooManyDecimalPlaces,
+    RejectReason::RateLimited,
+    RejectReason::AccountLocked,
+    RejectReason::InsufficientFunds,
+    RejectReason::InsufficientHeldFunds,
+    RejectReason::AccountNotFound,
+    RejectReason::TransactionNotFound,
+    RejectReason::TransactionExpired,
+    RejectReason::ClientMismatch,
+    RejectReason::AlreadyDisputed,
+    RejectReason::NotDisputed,
+    RejectReason::StorageUnavailable,
+    RejectReason::VersionMismatch,
+    RejectReason::ClientErased,
+    RejectReason::Vetoed,
+];
+
+/// A client account as C sees it, amounts in 1/10000 units
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeAccount {
+    pub client: u16,
+    pub available: i64,
+    pub held: i64,
+    pub total: i64,
+    pub locked: bool,
+}
+
+/// Create an empty engine
+#[no_mangle]
+pub extern "C" fn pe_engine_new() -> *mut PaymentsEngine {
+    let engine = PaymentsEngine::with_account_ordering(AccountOrdering::ByClientId);
+    Box::into_raw(Box::new(engine))
+}
+
+/// Release an engine; null is ignored
+///
+/// # Safety
+///
+/// `engine` must be null or come from `pe_engine_new` and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn pe_engine_free(engine: *mut PaymentsEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Apply a transaction
+///
+/// `amount` is only read for deposits and withdrawals. Returns `PE_OK` if
+/// the transaction was applied, the positive code of its `RejectReason` if
+/// it was rejected, or `PE_INVALID_ARGUMENT`.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine from `pe_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn pe_engine_submit(
+    engine: *mut PaymentsEngine,
+    tx_type: u8,
+    client: u16,
+    tx: u32,
+    amount: i64,
+) -> i32 {
+    let Some(engine) = engine.as_mut() else {
+        return PE_INVALID_ARGUMENT;
+    };
+    let tx_type = match tx_type {
+        PE_DEPOSIT => TransactionType::Deposit,
+        PE_WITHDRAWAL => TransactionType::Withdrawal,
+        PE_DISPUTE => TransactionType::Dispute,
+        PE_RESOLVE => TransactionType::Resolve,
+        PE_CHARGEBACK => TransactionType::Chargeback,
+        _ => return PE_INVALID_ARGUMENT,
+    };
+    // The C API takes 16-bit client and 32-bit transaction IDs whatever the
+    // width of `ClientId` and `TxId`
+    #[allow(clippy::useless_conversion)]
+    let (client, tx) = (ClientId::from(client), TxId::from(tx));
+    let amount = matches!(
+        tx_type,
+        TransactionType::Deposit | TransactionType::Withdrawal
+    )
+    .then(|| from_minor_units(amount));
+
+    match engine.process_transaction(Transaction {
+        tx_type,
+        client,
+        tx,
+        amount,
+        timestamp: None,
+        metadata: Metadata::new(),
+    }) {
+        Outcome::Applied => PE_OK,
+        Outcome::Rejected(reason) => reject_code(reason),
+    }
+}
+
+/// Copy a client's account into `out`
+///
+/// Returns `PE_OK`, `PE_NOT_FOUND` if the client has no account,
+/// `PE_OUT_OF_RANGE` or `PE_INVALID_ARGUMENT`; `out` is only written on
+/// success.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine from `pe_engine_new`, and `out`
+/// null or valid for writing a `PeAccount`.
+#[no_mangle]
+pub unsafe extern "C" fn pe_engine_get_account(
+    engine: *const PaymentsEngine,
+    client: u16,
+    out: *mut PeAccount,
+) -> i32 {
+    let (Some(engine), false) = (engine.as_ref(), out.is_null()) else {
+        return PE_INVALID_ARGUMENT;
+    };
+    #[allow(clippy::useless_conversion)]
+    let Some(account) = engine.get_account(ClientId::from(client)) else {
+        return PE_NOT_FOUND;
+    };
+    let balances = (
+        minor_units(account.available),
+        minor_units(account.held),
+        minor_units(account.total()),
+    );
+    let (Some(available), Some(held), Some(total)) = balances else {
+        return PE_OUT_OF_RANGE;
+    };
+    out.write(PeAccount {
+        client,
+        available,
+        held,
+        total,
+        locked: account.locked,
+    });
+    PE_OK
+}
+
+/// All accounts as CSV sorted by client ID, in the CLI's output format
+///
+/// Returns a string to release with `pe_string_free`, or null if `engine`
+/// is null or the output couldn't be written.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine from `pe_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn pe_engine_accounts_csv(engine: *const PaymentsEngine) -> *mut c_char {
+    let Some(engine) = engine.as_ref() else {
+        return ptr::null_mut();
+    };
+    let mut output = Vec::new();
+    let mut sink = CsvSink::new(&mut output);
+    // Accounts are stored by client ID, so no sort is needed
+    let written = engine
+        .accounts_iter()
+        .try_for_each(|account| sink.write_account(account))
+        .and_then(|()| sink.finish());
+    drop(sink);
+    if written.is_err() {
+        return ptr::null_mut();
+    }
+    CString::new(output).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Release a string returned by this library; null is ignored
+///
+/// # Safety
+///
+/// `string` must be null or come from this library and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn pe_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Static description of a status code, e.g. `insufficient available funds`
+///
+/// Never null; unknown codes are described as such. The string must not be
+/// freed.
+#[no_mangle]
+pub extern "C" fn pe_status_message(status: i32) -> *const c_char {
+    static MESSAGES: OnceLock<Vec<CString>> = OnceLock::new();
+    let messages = MESSAGES.get_or_init(|| {
+        REJECT_REASONS
+            .iter()
+            .map(|reason| CString::new(reason.to_string()).expect("no NUL in messages"))
+            .collect()
+    });
+
+    let message: &CStr = match status {
+        PE_OK => c"ok",
+        PE_INVALID_ARGUMENT => c"invalid argument",
+        PE_NOT_FOUND => c"no account for client",
+        PE_OUT_OF_RANGE => c"amount out of range",
+        code => usize::try_from(code)
+            .ok()
+            .and_then(|code| messages.get(code.checked_sub(1)?))
+            .map_or(c"unknown status", CString::as_c_str),
+    };
+    message.as_ptr()
+}
+
+/// Status code of a rejection, 1 and up
+fn reject_code(reason: RejectReason) -> i32 {
+    let index = REJECT_REASONS
+        .iter()
+        .position(|&known| known == reason)
+        .expect("every reason has a code");
+    index as i32 + 1
+}
+
+/// An amount as 1/10000 units, if it has at most four decimal places and fits
+#[cfg(not(feature = "fixed-point"))]
+fn minor_units(amount: Amount) -> Option<i64> {
+    FixedAmount::try_from(amount)
+        .ok()
+        .map(FixedAmount::minor_units)
+}
+
+#[cfg(feature = "fixed-point")]
+fn minor_units(amount: Amount) -> Option<i64> {
+    Some(amount.minor_units())
+}
+
+/// Inverse of `minor_units`
+#[cfg(not(feature = "fixed-point"))]
+fn from_minor_units(units: i64) -> Amount {
+    FixedAmount::from_minor_units(units).into()
+}
+
+#[cfg(feature = "fixed-point")]
+fn from_minor_units(units: i64) -> Amount {
+    FixedAmount::from_minor_units(units)
+}
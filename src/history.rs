@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::amount::Amount;
+use crate::memory;
+use crate::models::{Account, ClientId, Metadata, TransactionType, TxId};
+use crate::outcome::RejectReason;
+
+/// Record of one applied transaction and the balances it produced
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    /// When the transaction was applied
+    ///
+    /// Input transactions carry no time, so this is a logical clock: the
+    /// engine-wide sequence number of applied transactions, starting at 1.
+    pub timestamp: u64,
+    pub tx_id: TxId,
+    pub tx_type: TransactionType,
+    /// Amount moved by the transaction (the referenced deposit's amount for
+    /// dispute/resolve/chargeback)
+    pub amount: Amount,
+    /// When the transaction happened, in Unix seconds, if its input said
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_timestamp: Option<u64>,
+    pub available: Amount,
+    pub held: Amount,
+    pub locked: bool,
+    /// The transaction's pass-through `metadata`
+    #[serde(skip_serializing_if = "Metadata::is_empty")]
+    pub metadata: Metadata,
+}
+
+/// A `HistoryEntry` of an erased client, with nothing left linking it to them
+///
+/// Kept so the amounts moved still show in audits of the ledger as a whole.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnonymizedEntry {
+    pub timestamp: u64,
+    pub tx_type: TransactionType,
+    pub amount: Amount,
+}
+
+/// A withdrawal the engine rejected, kept for suspicious-activity reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectedWithdrawal {
+    /// `HistoryEntry::timestamp` of the last transaction applied before it
+    pub timestamp: u64,
+    pub tx_id: TxId,
+    pub amount: Option<Amount>,
+    /// When the withdrawal was attempted, in Unix seconds, if its input said
+    pub tx_timestamp: Option<u64>,
+    pub reason: RejectReason,
+}
+
+/// Account balances at a point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Balance {
+    pub available: Amount,
+    pub held: Amount,
+    pub locked: bool,
+}
+
+impl Balance {
+    /// Get the total balance (available + held)
+    pub fn total(&self) -> Amount {
+        self.available + self.held
+    }
+}
+
+impl From<&HistoryEntry> for Balance {
+    fn from(entry: &HistoryEntry) -> Self {
+        Self {
+            available: entry.available,
+            held: entry.held,
+            locked: entry.locked,
+        }
+    }
+}
+
+/// Per-client log of applied transactions, and of rejected withdrawals
+///
+/// Entries are appended in timestamp order, so point-in-time lookups are a
+/// binary search over the client's entries.
+#[derive(Debug, Default)]
+pub(crate) struct History {
+    clock: u64,
+    entries: HashMap<ClientId, Vec<HistoryEntry>>,
+    /// Entries of erased clients, in timestamp order
+    anonymized: Vec<AnonymizedEntry>,
+    rejected_withdrawals: HashMap<ClientId, Vec<RejectedWithdrawal>>,
+}
+
+impl History {
+    /// Estimated heap bytes held by the recorded entries
+    pub(crate) fn memory_footprint(&self) -> usize {
+        memory::hash_map_bytes(&self.entries)
+            + self.entries.values().map(memory::vec_bytes).sum::<usize>()
+            + memory::vec_bytes(&self.anonymized)
+            + memory::hash_map_bytes(&self.rejected_withdrawals)
+            + self
+                .rejected_withdrawals
+                .values()
+                .map(memory::vec_bytes)
+                .sum::<usize>()
+    }
+
+    /// Record an applied transaction along with the account state it produced
+    pub(crate) fn record(
+        &mut self,
+        tx_id: TxId,
+        tx_type: TransactionType,
+        amount: Amount,
+        tx_timestamp: Option<u64>,
+        account: &Account,
+        metadata: &Metadata,
+    ) {
+        self.clock += 1;
+        self.entries
+            .entry(account.client_id)
+            .or_default()
+            .push(HistoryEntry {
+                timestamp: self.clock,
+                tx_id,
+                tx_type,
+                amount,
+                tx_timestamp,
+                available: account.available,
+                held: account.held,
+                locked: account.locked,
+                metadata: metadata.clone(),
+            });
+    }
+
+    /// Record a withdrawal that was rejected; it doesn't move the clock
+    pub(crate) fn record_rejected_withdrawal(
+        &mut self,
+        client_id: ClientId,
+        tx_id: TxId,
+        amount: Option<Amount>,
+        tx_timestamp: Option<u64>,
+        reason: RejectReason,
+    ) {
+        self.rejected_withdrawals
+            .entry(client_id)
+            .or_default()
+            .push(RejectedWithdrawal {
+                timestamp: self.clock,
+                tx_id,
+                amount,
+                tx_timestamp,
+                reason,
+            });
+    }
+
+    pub(crate) fn client_rejected_withdrawals(&self, client_id: ClientId) -> &[RejectedWithdrawal] {
+        self.rejected_withdrawals
+            .get(&client_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn client_entries(&self, client_id: ClientId) -> &[HistoryEntry] {
+        self.entries
+            .get(&client_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Drop a client's entries, keeping only their anonymized form
+    pub(crate) fn erase(&mut self, client_id: ClientId) {
+        self.rejected_withdrawals.remove(&client_id);
+        let Some(entries) = self.entries.remove(&client_id) else {
+            return;
+        };
+        self.anonymized
+            .extend(entries.into_iter().map(|entry| AnonymizedEntry {
+                timestamp: entry.timestamp,
+                tx_type: entry.tx_type,
+                amount: entry.amount,
+            }));
+        self.anonymized.sort_by_key(|entry| entry.timestamp);
+    }
+
+    pub(crate) fn anonymized(&self) -> &[AnonymizedEntry] {
+        &self.anonymized
+    }
+
+    /// Clients with entries or rejected withdrawals, in no particular order
+    pub(crate) fn clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        let rejected_only = self
+            .rejected_withdrawals
+            .keys()
+            .filter(|client_id| !self.entries.contains_key(client_id));
+        self.entries.keys().chain(rejected_only).copied()
+    }
+
+    /// Iterate over each client's entries
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (ClientId, &[HistoryEntry])> {
+        self.entries
+            .iter()
+            .map(|(client_id, entries)| (*client_id, entries.as_slice()))
+    }
+
+    /// Balance after the last transaction applied at or before `timestamp`
+    pub(crate) fn balance_at(&self, client_id: ClientId, timestamp: u64) -> Option<Balance> {
+        let entries = self.client_entries(client_id);
+        let applied = entries.partition_point(|entry| entry.timestamp <= timestamp);
+
+        if applied == 0 {
+            return None;
+        }
+        Some(Balance::from(&entries[applied - 1]))
+    }
+}
@@ -0,0 +1,149 @@
+//! Callbacks run around each transaction, for embedders to extend the engine
+//!
+//! A pre-commit hook sees every transaction that passed the engine's own
+//! checks for duplicates and amounts, along with the client's account as it
+//! stands, and can refuse it with a `RejectReason`, typically
+//! `RejectReason::Vetoed`. A post-commit hook sees every applied transaction
+//! with the account it left behind, e.g. to mirror the ledger elsewhere.
+//! An alert hook is told when the invariant watchdog (see
+//! `EngineConfig::invariant_check_every`) finds the ledger broken.
+//!
+//! ```
+//! use std::sync::{Arc, Mutex};
+//!
+//! use payments_engine::engine::PaymentsEngine;
+//! use payments_engine::hooks::Hooks;
+//! use payments_engine::models::{Transaction, TransactionType};
+//! use payments_engine::outcome::{Outcome, RejectReason};
+//!
+//! let mirrored = Arc::new(Mutex::new(Vec::new()));
+//! let mirror = mirrored.clone();
+//! let mut engine = PaymentsEngine::new();
+//! engine.set_hooks(
+//!     Hooks::new()
+//!         .pre_commit(|tx, _| match tx.tx_type {
+//!             TransactionType::Withdrawal if tx.client == 7 => Err(RejectReason::Vetoed),
+//!             _ => Ok(()),
+//!         })
+//!         .post_commit(move |tx, account| {
+//!             mirror.lock().unwrap().push((tx.tx, account.available));
+//!         }),
+//! );
+//!
+//! engine.process_transaction(Transaction::deposit(7, 1, "10".parse().unwrap()));
+//! assert_eq!(
+//!     engine.process_transaction(Transaction::withdrawal(7, 2, "1".parse().unwrap())),
+//!     Outcome::Rejected(RejectReason::Vetoed)
+//! );
+//! assert_eq!(*mirrored.lock().unwrap(), vec![(1, "10".parse().unwrap())]);
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::invariants::InvariantReport;
+use crate::models::{Account, Transaction};
+use crate::outcome::RejectReason;
+
+/// Decides whether a transaction may go ahead, given the client's account
+/// before it (`None` if the client has none yet)
+pub type PreCommitHook =
+    Arc<dyn Fn(&Transaction, Option<&Account>) -> Result<(), RejectReason> + Send + Sync>;
+
+/// Told about an applied transaction and the client's account after it
+pub type PostCommitHook = Arc<dyn Fn(&Transaction, &Account) + Send + Sync>;
+
+/// Told about a failed invariant check, with every violation it found
+pub type AlertHook = Arc<dyn Fn(&InvariantReport) + Send + Sync>;
+
+/// The hooks registered on an engine, run in the order they were added
+///
+/// Cheap to clone: a `ShardedEngine` gives every shard the same hooks, so
+/// they may be called from several threads at once.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pre_commit: Vec<PreCommitHook>,
+    post_commit: Vec<PostCommitHook>,
+    alert: Vec<AlertHook>,
+}
+
+impl Hooks {
+    /// No hooks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a hook run before each transaction is applied
+    ///
+    /// The first hook to return `Err` rejects the transaction with that
+    /// reason; later hooks aren't asked. A vetoed deposit or withdrawal
+    /// doesn't use up its transaction ID, so it can be submitted again.
+    pub fn pre_commit(
+        mut self,
+        hook: impl Fn(&Transaction, Option<&Account>) -> Result<(), RejectReason>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.pre_commit.push(Arc::new(hook));
+        self
+    }
+
+    /// Add a hook run after each transaction is applied
+    pub fn post_commit(
+        mut self,
+        hook: impl Fn(&Transaction, &Account) + Send + Sync + 'static,
+    ) -> Self {
+        self.post_commit.push(Arc::new(hook));
+        self
+    }
+
+    /// Add a hook run when the invariant watchdog finds a violation
+    ///
+    /// Called from inside `process_transaction`, right after the transaction
+    /// that completed the check interval, so it should be quick.
+    pub fn alert(mut self, hook: impl Fn(&InvariantReport) + Send + Sync + 'static) -> Self {
+        self.alert.push(Arc::new(hook));
+        self
+    }
+
+    /// Whether no hooks are registered
+    pub fn is_empty(&self) -> bool {
+        self.pre_commit.is_empty() && self.post_commit.is_empty() && self.alert.is_empty()
+    }
+
+    /// Run the pre-commit hooks until one vetoes
+    pub(crate) fn before(
+        &self,
+        tx: &Transaction,
+        account: Option<&Account>,
+    ) -> Result<(), RejectReason> {
+        self.pre_commit
+            .iter()
+            .try_for_each(|hook| hook(tx, account))
+    }
+
+    /// Run every post-commit hook
+    pub(crate) fn after(&self, tx: &Transaction, account: &Account) {
+        for hook in &self.post_commit {
+            hook(tx, account);
+        }
+    }
+
+    /// Run every alert hook
+    pub(crate) fn raise(&self, report: &InvariantReport) {
+        for hook in &self.alert {
+            hook(report);
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("pre_commit", &self.pre_commit.len())
+            .field("post_commit", &self.post_commit.len())
+            .field("alert", &self.alert.len())
+            .finish()
+    }
+}
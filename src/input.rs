@@ -0,0 +1,92 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+/// Transaction input for the batch path: a memory-mapped file or a buffered stream
+///
+/// Mapping a large file lets the CSV parser read straight out of the page
+/// cache instead of issuing a `read` syscall per buffer. Pipes, stdin and
+/// other inputs that can't be mapped are read through a buffer as usual.
+pub enum Input {
+    /// A regular file mapped into memory
+    Mapped {
+        map: Mmap,
+        /// Bytes already read
+        pos: usize,
+    },
+    /// Anything that can't be mapped, e.g. a pipe or stdin
+    Buffered(Box<dyn BufRead>),
+}
+
+impl Input {
+    /// Open `path` for buffered reads; `-` means stdin
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if path == Path::new("-") {
+            return Ok(Self::Buffered(Box::new(io::stdin().lock())));
+        }
+        Ok(Self::Buffered(Box::new(BufReader::new(File::open(path)?))))
+    }
+
+    /// Open `path` memory-mapped if it is a non-empty regular file, otherwise
+    /// the same way as `open`
+    ///
+    /// The file must not be truncated or modified while it is being read:
+    /// the mapping would see the change, and reading past a truncated end
+    /// kills the process with `SIGBUS`.
+    pub fn open_mapped(path: &Path) -> io::Result<Self> {
+        if path == Path::new("-") {
+            return Self::open(path);
+        }
+
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        // Empty files can't be mapped on every platform
+        if !metadata.is_file() || metadata.len() == 0 {
+            return Ok(Self::Buffered(Box::new(BufReader::new(file))));
+        }
+
+        // SAFETY: the mapping is read-only and callers are told not to modify
+        // the file while it is mapped; see the doc comment above.
+        match unsafe { Mmap::map(&file) } {
+            Ok(map) => Ok(Self::Mapped { map, pos: 0 }),
+            Err(_) => Ok(Self::Buffered(Box::new(BufReader::new(file)))),
+        }
+    }
+
+    /// True if the input is memory-mapped
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, Self::Mapped { .. })
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Mapped { .. } => {
+                let n = self.fill_buf()?.read(buf)?;
+                self.consume(n);
+                Ok(n)
+            }
+            Self::Buffered(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl BufRead for Input {
+    /// The unread rest of a mapped file, without copying
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Self::Mapped { map, pos } => Ok(&map[*pos..]),
+            Self::Buffered(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amount: usize) {
+        match self {
+            Self::Mapped { map, pos } => *pos = (*pos + amount).min(map.len()),
+            Self::Buffered(reader) => reader.consume(amount),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+
+use crate::amount::Amount;
+use crate::models::ClientId;
+
+/// A single broken accounting invariant
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvariantViolation {
+    /// Held funds don't match the open disputes (plus any seeded held balance)
+    HeldMismatch {
+        client_id: ClientId,
+        held: Amount,
+        expected: Amount,
+    },
+    /// Available balance is negative and the config doesn't permit it
+    NegativeAvailable {
+        client_id: ClientId,
+        available: Amount,
+    },
+    /// Held balance is negative and the config doesn't permit it
+    NegativeHeld { client_id: ClientId, held: Amount },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HeldMismatch {
+                client_id,
+                held,
+                expected,
+            } => write!(
+                f,
+                "client {}: held {} does not match open disputes totalling {}",
+                client_id, held, expected
+            ),
+            Self::NegativeAvailable {
+                client_id,
+                available,
+            } => write!(f, "client {}: negative available {}", client_id, available),
+            Self::NegativeHeld { client_id, held } => {
+                write!(f, "client {}: negative held {}", client_id, held)
+            }
+        }
+    }
+}
+
+/// Result of `PaymentsEngine::check_invariants`
+///
+/// `total == available + held` is not checked: `total` is always derived from
+/// the two balances, so it cannot drift inside the engine.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InvariantReport {
+    /// Number of accounts checked
+    pub accounts_checked: usize,
+    /// Violations found, sorted by client ID
+    pub violations: Vec<InvariantViolation>,
+}
+
+impl InvariantReport {
+    /// True if no invariant was violated
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for InvariantReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} violation(s) across {} account(s)",
+            self.violations.len(),
+            self.accounts_checked
+        )?;
+        for violation in &self.violations {
+            write!(f, "\n  {}", violation)?;
+        }
+        Ok(())
+    }
+}
@@ -1,53 +1,603 @@
+mod account_store;
+#[cfg(feature = "async")]
+pub mod account_view;
+pub mod amount;
+pub mod archive;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "async")]
 pub mod concurrent_engine;
+pub mod config;
+pub mod connectors;
+#[cfg(feature = "tui")]
+pub mod dashboard;
 pub mod engine;
 pub mod error;
-pub mod models;
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod history;
+pub mod hooks;
+pub mod input;
+pub mod invariants;
+pub mod memory;
+pub mod output;
+pub mod parallel;
+#[cfg(feature = "async")]
 pub mod persistence;
+#[cfg(feature = "async")]
 pub mod persistent_engine;
+#[cfg(feature = "async")]
+pub mod pipeline;
+#[cfg(feature = "async")]
+pub mod rate_limit;
+pub mod repl;
+#[cfg(feature = "async")]
+pub mod replay;
+pub mod resume;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod settlement;
+#[cfg(feature = "async")]
+mod shard;
+pub mod state;
+pub mod statement;
+pub mod suspicious;
+pub mod tenant;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod tx_store;
+pub mod validation;
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod workload;
+
+pub use payments_engine_core::{models, outcome, transition};
 
 use std::io::{Read, Write};
+use std::path::Path;
 
-use engine::PaymentsEngine;
+#[cfg(feature = "async")]
+use concurrent_engine::{ShardOptions, ShardedEngine};
+#[cfg(feature = "async")]
+use engine::Engine;
+use engine::{AccountOrdering, PaymentsEngine};
 use error::Result;
+use models::{Account, Transaction};
+use output::{CsvSink, OutputSink};
+#[cfg(feature = "async")]
+use pipeline::{CsvTransactions, PipelineOptions};
+use resume::ResumableRows;
+use state::EngineState;
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use validation::{HeaderReport, RowError};
 
 /// Process transactions from a CSV reader and write results to a CSV writer
 pub fn process_transactions<R: Read, W: Write>(reader: R, writer: W) -> Result<()> {
+    // Ordered storage lets results stream out without a final sort
+    let engine = PaymentsEngine::with_account_ordering(AccountOrdering::ByClientId);
+    process_transactions_with_engine(engine, reader, writer)
+}
+
+/// Process transactions on top of an existing engine and write results to a CSV writer
+///
+/// Useful together with `PaymentsEngine::with_initial_accounts` to start from
+/// balances migrated from another system.
+pub fn process_transactions_with_engine<R: Read, W: Write>(
+    mut engine: PaymentsEngine,
+    reader: R,
+    writer: W,
+) -> Result<()> {
+    apply_transactions(&mut engine, reader);
+
+    // Write results
+    write_accounts(engine, writer)?;
+
+    Ok(())
+}
+
+/// Async counterpart of `process_transactions`, running on a `ShardedEngine`
+///
+/// Reads CSV transactions from `reader`, applies them with one shard per CPU
+/// through the bounded `pipeline` and writes the resulting accounts as CSV,
+/// sorted by client ID, once the input is exhausted. Must be called within a
+/// tokio runtime.
+#[cfg(feature = "async")]
+pub async fn process_transactions_async<R, W>(reader: R, mut writer: W) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let engine = ShardedEngine::with_options(ShardOptions::default());
+    pipeline::ingest(&engine, reader, PipelineOptions::default()).await?;
+
+    let mut output = Vec::new();
+    let mut sink = CsvSink::new(&mut output);
+    for account in engine.into_accounts().await? {
+        sink.write_account(&account)?;
+    }
+    sink.finish()?;
+    drop(sink);
+
+    writer.write_all(&output).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Process transactions on top of the state saved by a previous run
+///
+/// Loads the state file (starting fresh if it doesn't exist), applies the new
+/// batch, saves the updated state back to the same path, then writes the
+/// resulting accounts. Duplicate detection and open disputes carry over
+/// between runs, so each run only needs the new transactions. Rows of an
+/// input an earlier run already applied are skipped (see `resume`).
+pub fn process_transactions_with_state<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    state_path: &Path,
+) -> Result<()> {
+    let mut state = EngineState::load(state_path)?;
+    let mut sources = std::mem::take(&mut state.sources);
+    let mut engine = PaymentsEngine::from_state(state);
+
+    let mut rows = ResumableRows::new(reader, &sources)?;
+    for transaction in rows.by_ref() {
+        engine.process_transaction(transaction);
+    }
+    rows.record(&mut sources);
+
+    // Save state before writing output so a failed write doesn't lose the batch
+    let mut state = engine.to_state();
+    state.sources = sources;
+    state.save(state_path)?;
+
+    write_accounts(engine, writer)?;
+
+    Ok(())
+}
+
+/// Read accounts from a CSV in the same format the engine writes
+///
+/// Unlike transaction input, malformed rows are an error: silently dropping a
+/// starting balance would corrupt the migrated ledger. So is a `total` that
+/// doesn't equal `available + held`; the column may also be left out.
+pub fn read_accounts<R: Read>(reader: R) -> Result<Vec<Account>> {
     let mut csv_reader = csv::ReaderBuilder::new()
         .trim(csv::Trim::All)
         .from_reader(reader);
 
-    let mut engine = PaymentsEngine::new();
+    csv_reader.deserialize().map(|result| Ok(result?)).collect()
+}
+
+/// Apply every transaction from a CSV reader to the engine
+///
+/// Malformed rows are skipped. Large inputs are parsed in chunks on other
+/// threads while the engine applies what is already parsed; see
+/// `parallel::apply_transactions_chunked`. On wasm32, which has no threads,
+/// rows are parsed and applied one by one.
+pub fn apply_transactions<R: Read>(engine: &mut PaymentsEngine, reader: R) {
+    if cfg!(target_arch = "wasm32") {
+        for transaction in CsvRows::new(reader) {
+            engine.process_transaction(transaction);
+        }
+    } else {
+        parallel::apply_transactions_chunked(engine, reader);
+    }
+}
+
+/// Apply the transactions from a CSV reader that happened at or before
+/// `as_of`, in Unix seconds, e.g. for the balances at an end-of-day cutoff
+///
+/// Later transactions are skipped wherever they appear, so the input needn't
+/// be sorted. Transactions without a timestamp are applied. Rows are parsed
+/// and applied one by one.
+pub fn apply_transactions_as_of<R: Read>(engine: &mut PaymentsEngine, reader: R, as_of: u64) {
+    for transaction in CsvRows::new(reader) {
+        if transaction
+            .timestamp
+            .is_none_or(|timestamp| timestamp <= as_of)
+        {
+            engine.process_transaction(transaction);
+        }
+    }
+}
+
+/// Parse transactions from a CSV reader without processing them
+///
+/// Malformed rows are skipped and iteration stops at the first I/O error,
+/// the same as the input side of `apply_transactions`.
+pub fn read_transactions<R: Read>(reader: R) -> impl Iterator<Item = Transaction> {
+    CsvRows::new(reader)
+}
+
+/// Parse transactions from a CSV reader, reporting what can't be read
+///
+/// The header row is checked before any row is read; see
+/// `CheckedTransactions::header`. Without a required column every row fails,
+/// so callers usually stop there. Each row then yields its transaction or a
+/// `RowError` with its line and, where one is at fault, the column and the
+/// value as written. An I/O error is yielded as a row error and ends the
+/// iteration.
+///
+/// ```
+/// use payments_engine::read_transactions_checked;
+///
+/// let input = "type,client,tx,amount\ndeposit,1,1,ten\ndeposit,1,2,10\n";
+/// let mut transactions = read_transactions_checked(input.as_bytes());
+/// assert!(transactions.header().is_valid());
+///
+/// let error = transactions.next().unwrap().unwrap_err();
+/// assert_eq!(error.line, 2);
+/// assert_eq!(error.column.as_deref(), Some("amount"));
+/// assert_eq!(error.value.as_deref(), Some("ten"));
+/// assert!(transactions.next().unwrap().is_ok());
+/// ```
+pub fn read_transactions_checked<R: Read>(reader: R) -> CheckedTransactions<R> {
+    let rows = CsvRows::new(reader);
+    let header = validation::check_header(rows.headers().iter().map(|name| {
+        // Names the reader can't decode can't be known columns either
+        std::str::from_utf8(name).unwrap_or("\u{fffd}")
+    }));
+    CheckedTransactions { rows, header }
+}
+
+/// Parse one headerless CSV row in `type,client,tx,amount` order, e.g.
+/// `deposit, 1, 7, 2.5`
+///
+/// The amount column may be left out for disputes, resolves and
+/// chargebacks. Fields are validated as in `read_transactions`; `None` if
+/// the row is malformed. Only the first line of `row` is read.
+pub fn parse_csv_row(row: &str) -> Option<Transaction> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(row.as_bytes());
+
+    let mut record = reader.records().next()?.ok()?;
+    // Allow `dispute,1,1` without a trailing empty amount column
+    if record.len() == 3 {
+        record.push_field("");
+    }
+
+    record.deserialize(None).ok()
+}
+
+/// Write transactions as CSV, the format `read_transactions` reads
+///
+/// Writes a header row even without transactions. Amounts are written as
+/// given and a transaction without one gets an empty `amount` field, the
+/// same for `timestamp`, so reading the output back yields the same
+/// transactions, less their `metadata`, which isn't written.
+pub fn write_transactions<W: Write>(
+    transactions: impl IntoIterator<Item = Transaction>,
+    writer: W,
+) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    writer.write_record(Transaction::FIELDS)?;
+    for tx in transactions {
+        // Every column on every row, unlike `Transaction`'s own serialization
+        writer.serialize((tx.tx_type, tx.client, tx.tx, tx.amount, tx.timestamp))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A CSV header row, split into the columns `Transaction` reads and the
+/// extra ones kept as its `metadata`
+///
+/// Extra values are copied from the row as they are: left to serde, the CSV
+/// deserializer would guess their types and turn `007` into `7`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CsvColumns {
+    /// The header row with extra columns renamed to `""`, which
+    /// `Transaction` skips
+    fields: csv::ByteRecord,
+    /// Position and name of each extra column
+    extra: Vec<(usize, String)>,
+}
 
-    // Process each transaction
-    for result in csv_reader.deserialize() {
-        match result {
-            Ok(transaction) => {
-                engine.process_transaction(transaction);
+impl CsvColumns {
+    pub(crate) fn new(headers: &csv::ByteRecord) -> Self {
+        let mut columns = Self::default();
+        for (index, name) in headers.iter().enumerate() {
+            let known = Transaction::FIELDS
+                .iter()
+                .any(|field| field.as_bytes() == name);
+            if known || name.is_empty() {
+                columns.fields.push_field(name);
+            } else {
+                columns.fields.push_field(b"");
+                columns
+                    .extra
+                    .push((index, String::from_utf8_lossy(name).into_owned()));
             }
-            Err(_) => {
-                // Silently skip malformed transactions
+        }
+        columns
+    }
+
+    /// Where in `record` deserializing it went wrong
+    fn row_error(&self, line: u64, record: &csv::ByteRecord, error: &csv::Error) -> RowError {
+        let csv::ErrorKind::Deserialize { err, .. } = error.kind() else {
+            return RowError {
+                line,
+                column: None,
+                value: None,
+                message: error.to_string(),
+            };
+        };
+        // Errors raised by `Transaction`'s own parsing, such as an unknown
+        // type, don't say which field they came from
+        let field = err
+            .field()
+            .map(|index| index as usize)
+            .or_else(|| self.invalid_field(record));
+        let text = |record: &csv::ByteRecord| {
+            let value = record.get(field?)?;
+            Some(String::from_utf8_lossy(value).into_owned())
+        };
+        RowError {
+            line,
+            column: text(&self.fields),
+            value: text(record),
+            message: err.kind().to_string(),
+        }
+    }
+
+    /// Position of the first column of `record` that `Transaction` can't
+    /// read, found by reading each value on its own among valid ones
+    fn invalid_field(&self, record: &csv::ByteRecord) -> Option<usize> {
+        // A valid value for each of `Transaction::FIELDS`
+        const VALID: [&[u8]; 5] = [b"deposit", b"1", b"1", b"1", b""];
+        let headers = csv::ByteRecord::from(Transaction::FIELDS.to_vec());
+        (0..record.len()).find(|&index| {
+            let Some(known) = Transaction::FIELDS
+                .iter()
+                .position(|field| field.as_bytes() == &self.fields[index])
+            else {
+                return false;
+            };
+            let mut values = VALID;
+            values[known] = &record[index];
+            csv::ByteRecord::from(values.to_vec())
+                .deserialize::<Transaction>(Some(&headers))
+                .is_err()
+        })
+    }
+
+    /// Number of columns, extra ones included
+    pub(crate) fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Parse a row found on `line`, or say why it isn't a valid transaction
+    ///
+    /// Rows must be as long as the headers. Empty extra values are left out
+    /// of the metadata.
+    pub(crate) fn parse(
+        &self,
+        record: &csv::ByteRecord,
+        line: u64,
+    ) -> std::result::Result<Transaction, RowError> {
+        if record.len() != self.len() {
+            return Err(RowError {
+                line,
+                column: None,
+                value: None,
+                message: format!("expected {} fields, found {}", self.len(), record.len()),
+            });
+        }
+        let mut transaction: Transaction = record
+            .deserialize(Some(&self.fields))
+            .map_err(|e| self.row_error(line, record, &e))?;
+        for (index, name) in &self.extra {
+            if let Some(value) = record.get(*index).filter(|value| !value.is_empty()) {
+                let value = String::from_utf8_lossy(value).into_owned();
+                transaction.metadata.insert(name.clone(), value);
             }
         }
+        Ok(transaction)
     }
+}
 
-    // Write results
-    write_accounts(engine, writer)?;
+/// Transactions parsed from CSV input, skipping malformed rows
+///
+/// Every row is read into the same `ByteRecord` and deserialized straight
+/// from its bytes, so parsing doesn't allocate per row unless the input has
+/// extra columns for `metadata`. Iteration ends at the end of the input or
+/// the first I/O error.
+pub(crate) struct CsvRows<R> {
+    reader: csv::Reader<R>,
+    headers: csv::ByteRecord,
+    columns: CsvColumns,
+    record: csv::ByteRecord,
+    /// Set by an I/O error, after which nothing more is read
+    failed: bool,
+}
 
-    Ok(())
+impl<R: Read> CsvRows<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        let mut reader = Self::builder().from_reader(reader);
+        // Unreadable headers leave nothing to match rows against, so every row is skipped
+        let headers = reader.byte_headers().cloned().unwrap_or_default();
+        Self {
+            reader,
+            columns: CsvColumns::new(&headers),
+            headers,
+            record: csv::ByteRecord::new(),
+            failed: false,
+        }
+    }
+
+    /// Rows from input without a header line, e.g. a later chunk of a file,
+    /// matched against `headers` taken from elsewhere
+    pub(crate) fn with_headers(reader: R, headers: csv::ByteRecord) -> Self {
+        Self {
+            reader: Self::builder().has_headers(false).from_reader(reader),
+            columns: CsvColumns::new(&headers),
+            headers,
+            record: csv::ByteRecord::new(),
+            failed: false,
+        }
+    }
+
+    /// Header row the rows are matched against
+    pub(crate) fn headers(&self) -> &csv::ByteRecord {
+        &self.headers
+    }
+
+    /// Row the last transaction returned was parsed from
+    pub(crate) fn record(&self) -> &csv::ByteRecord {
+        &self.record
+    }
+
+    /// Bytes of the input read so far, up to the end of the last row
+    pub(crate) fn position(&self) -> u64 {
+        self.reader.position().byte()
+    }
+
+    /// The next row's transaction or why it isn't one, `None` at the end of
+    /// the input
+    ///
+    /// An I/O error is returned as a row error without a column and ends the
+    /// input.
+    pub(crate) fn next_checked(&mut self) -> Option<std::result::Result<Transaction, RowError>> {
+        if self.failed {
+            return None;
+        }
+        match self.reader.read_byte_record(&mut self.record) {
+            Ok(true) => {
+                // The record's own position points at any blank lines
+                // skipped before it, so count back from the line break
+                // ending it, unless it is the last line and has none
+                let start = self.record.position().map_or(0, csv::Position::line);
+                let line = start.max(self.reader.position().line() - 1);
+                Some(self.columns.parse(&self.record, line))
+            }
+            Ok(false) => None,
+            Err(e) => {
+                self.failed = e.is_io_error();
+                Some(Err(RowError {
+                    line: e.position().map_or(0, csv::Position::line),
+                    column: None,
+                    value: None,
+                    message: e.to_string(),
+                }))
+            }
+        }
+    }
+
+    fn builder() -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        // Row lengths are checked against the headers in `next`, so a chunk
+        // starting with a bad row doesn't set the length for the rest
+        builder.trim(csv::Trim::All).flexible(true);
+        builder
+    }
+}
+
+impl<R: Read> Iterator for CsvRows<R> {
+    type Item = Transaction;
+
+    fn next(&mut self) -> Option<Transaction> {
+        // Skip malformed rows; an I/O error ends the input
+        loop {
+            match self.next_checked()? {
+                Ok(transaction) => return Some(transaction),
+                Err(error) => log::debug!("Skipped malformed row at {}", error),
+            }
+        }
+    }
 }
 
-/// Write client accounts to CSV
-fn write_accounts<W: Write>(engine: PaymentsEngine, writer: W) -> Result<()> {
-    let mut csv_writer = csv::Writer::from_writer(writer);
+/// Transactions parsed from CSV input, with a report of each row that isn't
+/// one, from `read_transactions_checked`
+pub struct CheckedTransactions<R> {
+    rows: CsvRows<R>,
+    header: HeaderReport,
+}
+
+impl<R> CheckedTransactions<R> {
+    /// How the header row compares to the input format
+    pub fn header(&self) -> &HeaderReport {
+        &self.header
+    }
+}
 
-    let mut accounts = engine.into_accounts();
-    // Sort by client ID for consistent output
-    accounts.sort_by_key(|a| a.client_id);
+impl<R: Read> Iterator for CheckedTransactions<R> {
+    type Item = std::result::Result<Transaction, RowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next_checked()
+    }
+}
 
-    for account in accounts {
-        csv_writer.serialize(account)?;
+/// Apply every transaction from an async CSV reader to any `Engine`
+///
+/// The async counterpart of `apply_transactions`: rows are read one line at
+/// a time and malformed ones are skipped. Each transaction is acknowledged
+/// before the next is read, so every client's transactions apply in input
+/// order. Fields spanning several lines (quoted newlines) are not supported.
+/// See `pipeline::ingest` to process shards in parallel.
+///
+/// Fails if reading fails or the engine can't process a transaction, e.g.
+/// because it is shutting down; rejected transactions are not errors.
+#[cfg(feature = "async")]
+pub async fn apply_transactions_async<E: Engine, R: AsyncRead + Unpin>(
+    engine: &mut E,
+    reader: R,
+) -> Result<()> {
+    let mut transactions = CsvTransactions::new(reader);
+    while let Some(transaction) = transactions.next().await? {
+        engine.process(transaction).await?;
     }
 
-    csv_writer.flush()?;
     Ok(())
 }
+
+/// Apply transactions from a CSV reader, emitting account updates as they happen
+///
+/// After every applied transaction the affected account's new state is
+/// written to `sink` and flushed, giving continuous output for long-running
+/// streams instead of a single dump at the end. Rejected and malformed
+/// transactions produce no output.
+pub fn apply_transactions_streaming<R: Read, S: OutputSink + ?Sized>(
+    engine: &mut PaymentsEngine,
+    reader: R,
+    sink: &mut S,
+) -> Result<()> {
+    for transaction in CsvRows::new(reader) {
+        let client_id = transaction.client;
+        if !engine.process_transaction(transaction).is_applied() {
+            continue;
+        }
+
+        if let Some(account) = engine.get_account(client_id) {
+            sink.write_account(account)?;
+            sink.flush()?;
+        }
+    }
+
+    sink.finish()
+}
+
+/// Write client accounts to CSV, sorted by client ID for consistent output
+pub fn write_accounts<W: Write>(engine: PaymentsEngine, writer: W) -> Result<()> {
+    write_accounts_to(engine, &mut CsvSink::new(writer))
+}
+
+/// Write client accounts to an output sink, sorted by client ID
+pub fn write_accounts_to<S: OutputSink + ?Sized>(
+    engine: PaymentsEngine,
+    sink: &mut S,
+) -> Result<()> {
+    for account in engine.into_sorted_accounts() {
+        sink.write_account(&account)?;
+    }
+
+    sink.finish()
+}
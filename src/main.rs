@@ -1,26 +1,2073 @@
-use std::env;
-use std::fs::File;
-use std::io;
+use std::fmt;
+use std::fs::{self, File};
+#[cfg(any(feature = "kafka", feature = "nats"))]
+use std::future::Future;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use payments_engine::process_transactions;
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use payments_engine::amount::Amount;
+use payments_engine::concurrent_engine::{
+    default_shard_count, hashed_shard_key, modulo_shard_key, ShardKey, ShardOptions, ShardedEngine,
+};
+use payments_engine::config::EngineConfig;
+use payments_engine::connectors::file_tail::{self, TailSourceOptions};
+#[cfg(any(feature = "kafka", feature = "redis"))]
+use payments_engine::connectors::format::MessageFormat;
+use payments_engine::connectors::iso8583::{self, Iso8583Format};
+#[cfg(feature = "kafka")]
+use payments_engine::connectors::kafka::{self, KafkaEventSink, KafkaSourceOptions};
+#[cfg(feature = "nats")]
+use payments_engine::connectors::nats::NatsEventSink;
+#[cfg(any(feature = "kafka", feature = "nats"))]
+use payments_engine::connectors::publish::{publish_events, EventSink};
+#[cfg(feature = "redis")]
+use payments_engine::connectors::redis_streams::{self, RedisSourceOptions};
+#[cfg(feature = "tui")]
+use payments_engine::dashboard::{self, Dashboard, Sample, Screen};
+use payments_engine::engine::{AccountOrdering, PaymentsEngine};
+use payments_engine::error::EngineError;
+use payments_engine::hooks::Hooks;
+use payments_engine::input::Input;
+use payments_engine::models::ClientId;
+use payments_engine::output::{CsvSink, JsonLinesSink, OutputSink};
+use payments_engine::parallel::{apply_transactions_parallel, merge_accounts};
+use payments_engine::persistence::replicated::{self, Follower};
+use payments_engine::persistence::shipping::{self, Replica, ReplicaExit, WalShipper};
+use payments_engine::persistence::{self, Migration};
+use payments_engine::persistent_engine::{CheckpointPolicy, IntegrityCheck};
+#[cfg(feature = "tui")]
+use payments_engine::read_transactions;
+use payments_engine::repl::{Repl, Reply};
+use payments_engine::replay::{self, Breakpoint, Replay, Step};
+use payments_engine::resume::ResumableRows;
+use payments_engine::server;
+use payments_engine::server::auth::ApiKeys;
+use payments_engine::server::config::ServerConfig;
+use payments_engine::server::snapshots::{Schedule, SnapshotOptions, Snapshotter};
+use payments_engine::state::{EngineState, SourceOffset, SNAPSHOT_VERSION};
+use payments_engine::statement::{camt053, mt940, ofx, qif, Statement, StatementOptions};
+use payments_engine::suspicious::{self, ReportRules};
+use payments_engine::tenant::{self, MultiTenantEngine};
+use payments_engine::verify::verify_accounts;
+use payments_engine::workload::{self, AmountDistribution, Workload, WorkloadOptions};
+use payments_engine::{
+    apply_transactions, apply_transactions_as_of, apply_transactions_streaming, read_accounts,
+    read_transactions_checked, write_accounts_to,
+};
+use tokio::sync::watch;
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+/// Process a CSV of transactions and print the resulting client accounts
+#[derive(Parser)]
+#[command(
+    name = "payments-engine",
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 
+    /// Only report problems on stderr, not progress notes
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also report each skipped row and how long each stage took; twice
+    /// also reports every rejected transaction
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    #[command(flatten)]
+    batch: BatchArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run as a server, processing transactions streamed over the network
+    Serve(Box<ServeArgs>),
+    /// Write a synthetic transactions CSV for load testing
+    Generate(GenerateArgs),
+    /// Process a CSV of transactions and write per-client account statements
+    Statement(StatementArgs),
+    /// Process a CSV of transactions and write a suspicious-activity report
+    Report(ReportArgs),
+    /// Step through a server's write-ahead log and show or save accounts at a point
+    Replay(ReplayArgs),
+    /// Check an accounts file for malformed rows, totals that don't add
+    /// up, negative balances and duplicate clients
+    Verify(VerifyArgs),
+    /// Rewrite write-ahead logs and snapshots written by earlier versions in
+    /// the current format
+    MigrateWal(MigrateWalArgs),
+    /// Type transactions and commands into an engine interactively
+    Repl,
+    /// Keep a copy of a server's write-ahead logs, sent with --replicate-to
+    Follow(FollowArgs),
+    /// Make a server running with --replica-of take transactions
+    Promote(PromoteArgs),
+}
+
+#[derive(Args)]
+struct GenerateArgs {
+    /// Number of transactions to write
+    #[arg(long, short = 'n', default_value_t = 100_000)]
+    transactions: usize,
+
+    /// Number of clients, IDs 1 to N
+    #[arg(long, default_value_t = 1_000, value_parser = clap::value_parser!(ClientId).range(1..))]
+    clients: ClientId,
+
+    /// Fraction of transactions that are withdrawals
+    #[arg(long, default_value_t = 0.25, value_parser = parse_ratio)]
+    withdrawal_ratio: f64,
+
+    /// Fraction of transactions that are disputes, resolves or chargebacks
+    #[arg(long, default_value_t = 0.05, value_parser = parse_ratio)]
+    dispute_ratio: f64,
+
+    /// Fraction of settled disputes that are charged back instead of resolved
+    #[arg(long, default_value_t = 0.1, value_parser = parse_ratio)]
+    chargeback_rate: f64,
+
+    /// Fraction of transactions that repeat an earlier deposit or withdrawal
+    #[arg(long, default_value_t = 0.0, value_parser = parse_ratio)]
+    duplicate_ratio: f64,
+
+    /// How amounts are distributed
+    #[arg(long, value_enum, default_value_t = AmountsArg::Uniform)]
+    amounts: AmountsArg,
+
+    /// Largest amount generated
+    #[arg(long, default_value_t = 1000.0)]
+    max_amount: f64,
+
+    /// Median amount for --amounts log-normal
+    #[arg(long, default_value_t = 25.0)]
+    median_amount: f64,
+
+    /// Seed; the same seed and options always give the same file
+    #[arg(long, default_value_t = 0x5eed)]
+    seed: u64,
+
+    /// File to write instead of stdout
+    #[arg(long, short = 'o', value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct StatementArgs {
+    /// Input transactions CSV; `-` reads stdin
+    input: PathBuf,
+
+    /// Client to write a statement for; repeat for several [default: every
+    /// client with applied transactions]
+    #[arg(long = "client", value_name = "ID")]
+    clients: Vec<ClientId>,
+
+    /// Statement format
+    #[arg(long, value_enum, default_value_t = StatementFormat::Camt053)]
+    format: StatementFormat,
+
+    /// ISO 4217 code of the currency amounts are in; XXX means none
+    #[arg(long, default_value = "XXX")]
+    currency: String,
+
+    /// File to write instead of stdout
+    #[arg(long, short = 'o', value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    /// Input transactions CSV; `-` reads stdin
+    input: PathBuf,
+
+    /// Report format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Csv)]
+    format: ReportFormat,
+
+    /// Longest time from a deposit to its chargeback that is reported, in the
+    /// input's timestamps (or applied transactions, if it has none)
+    #[arg(long, value_name = "TIME", default_value_t = ReportRules::default().cycle_window)]
+    cycle_window: u64,
+
+    /// Rejected withdrawals within --failed-withdrawal-window that are reported
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = ReportRules::default().failed_withdrawals,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    failed_withdrawals: usize,
+
+    /// Time within which --failed-withdrawals rejected withdrawals are reported
+    #[arg(long, value_name = "TIME", default_value_t = ReportRules::default().failed_withdrawal_window)]
+    failed_withdrawal_window: u64,
+
+    /// Amount a single deposit would be reported at anyway; deposits under it
+    /// that add up to it within --structuring-window are reported
+    #[arg(long, value_name = "AMOUNT", default_value_t = ReportRules::default().reporting_threshold)]
+    reporting_threshold: Amount,
+
+    /// Smallest deposit counted as kept under --reporting-threshold
+    #[arg(long, value_name = "AMOUNT", default_value_t = ReportRules::default().structuring_floor)]
+    structuring_floor: Amount,
+
+    /// Longest time over which structured deposits are added up
+    #[arg(long, value_name = "TIME", default_value_t = ReportRules::default().structuring_window)]
+    structuring_window: u64,
+
+    /// File to write instead of stdout
+    #[arg(long, short = 'o', value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    /// One row per client and pattern
+    Csv,
+    /// One object with when and how the report was made
+    Json,
+}
+
+#[derive(Args)]
+struct ReplayArgs {
+    /// Log file, or a --wal directory to replay every shard's log; replayed
+    /// from its checkpoint if it has one
+    log: PathBuf,
+
+    /// Point to stop at: tx:<id>, time:<n>, lsn:<n>, at:<seconds> or
+    /// locked:<client> [default: the end of the log]
+    #[arg(long, value_name = "POINT")]
+    to: Option<Breakpoint>,
+
+    /// Earlier point to diff accounts at --to against, instead of printing
+    /// them
+    #[arg(long, value_name = "POINT")]
+    from: Option<Breakpoint>,
+
+    /// Client to show; repeat for several [default: every client]
+    #[arg(long = "client", value_name = "ID")]
+    clients: Vec<ClientId>,
+
+    /// Print every replayed entry for the shown clients to stderr
+    #[arg(long)]
+    trace: bool,
+
+    /// File to write instead of stdout
+    #[arg(long, short = 'o', value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Also save every account's state at --to to FILE, in the --state
+    /// format a batch run can carry on from
+    #[arg(long, value_name = "FILE")]
+    save_state: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// Accounts CSV written by a batch run
+    accounts: PathBuf,
+}
+
+#[derive(Args)]
+struct MigrateWalArgs {
+    /// Log file, --wal directory to migrate every shard's log, or binary
+    /// snapshot file; the server must be stopped
+    path: PathBuf,
+}
+
+#[derive(Args)]
+struct FollowArgs {
+    /// Address to accept the leader's connections on, e.g. 0.0.0.0:7900
+    #[arg(long, value_name = "ADDR")]
+    listen: String,
+
+    /// Directory to keep the copies in; `serve --wal` on it takes over from
+    /// the leader
+    #[arg(long, value_name = "DIR")]
+    wal: PathBuf,
+}
+
+#[derive(Args)]
+struct PromoteArgs {
+    /// The replica's --wal directory
+    wal: PathBuf,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StatementFormat {
+    /// ISO 20022 camt.053 XML
+    Camt053,
+    /// SWIFT MT940
+    Mt940,
+    /// Open Financial Exchange 2.2
+    Ofx,
+    /// Quicken Interchange Format
+    Qif,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AmountsArg {
+    /// Every amount up to --max-amount equally likely
+    Uniform,
+    /// Mostly small amounts around --median-amount with a long tail
+    LogNormal,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("listeners").required(true).multiple(true)))]
+#[command(group(
+    ArgGroup::new("checkpoints")
+        .multiple(true)
+        .requires("wal")
+        .conflicts_with_all(["replicate_to", "ship_wal", "replica_of"])
+))]
+struct ServeArgs {
+    /// Address to accept newline-delimited transaction streams on, e.g. 127.0.0.1:7878
+    #[arg(long, value_name = "ADDR", group = "listeners")]
+    tcp: Option<String>,
+
+    /// Address to serve the HTTP REST API on, e.g. 127.0.0.1:8080
+    #[arg(long, value_name = "ADDR", group = "listeners")]
+    http: Option<String>,
+
+    /// Number of engine shards [default: number of CPUs]
+    #[arg(long)]
+    shards: Option<usize>,
+
+    /// How clients are assigned to shards
+    #[arg(long, value_enum, default_value_t = ShardKeyArg::Modulo)]
+    shard_key: ShardKeyArg,
+
+    /// File of API keys and the clients each may act on; enables authentication
+    #[arg(long, value_name = "FILE")]
+    api_keys: Option<PathBuf>,
+
+    /// State file loaded at startup and written on shutdown (SIGINT/SIGTERM)
+    #[arg(long, value_name = "FILE")]
+    state: Option<PathBuf>,
+
+    /// Directory of per-shard write-ahead logs, replayed at startup; needs the
+    /// same --shards and --shard-key on every run
+    #[arg(long, value_name = "DIR", conflicts_with = "state")]
+    wal: Option<PathBuf>,
+
+    /// Follower to replicate the --wal logs to before acknowledging
+    /// transactions; repeat or separate with commas for several
+    #[arg(long, value_name = "ADDR", value_delimiter = ',', requires = "wal")]
+    replicate_to: Vec<String>,
+
+    /// Address to stream the --wal logs to replicas on
+    #[arg(long, value_name = "ADDR", requires = "wal")]
+    ship_wal: Option<String>,
+
+    /// Follow the primary shipping its logs on ADDR (its --ship-wal), serving
+    /// reads only until `payments-engine promote` is run on --wal
+    #[arg(
+        long,
+        value_name = "ADDR",
+        requires = "wal",
+        conflicts_with = "replicate_to"
+    )]
+    replica_of: Option<String>,
+
+    /// Checkpoint a shard's --wal log, saving a snapshot next to it and
+    /// starting it over, once it holds this many bytes
+    #[arg(
+        long,
+        value_name = "BYTES",
+        group = "checkpoints",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    checkpoint_bytes: Option<u64>,
+
+    /// Checkpoint a shard's --wal log once this many entries were logged to
+    /// it since its last checkpoint
+    #[arg(
+        long,
+        value_name = "N",
+        group = "checkpoints",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    checkpoint_entries: Option<u64>,
+
+    /// Checkpoint a shard's --wal log once this many seconds went by since
+    /// its last checkpoint
+    #[arg(
+        long,
+        value_name = "SECS",
+        group = "checkpoints",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    checkpoint_interval: Option<u64>,
+
+    /// Compare each shard's recovered state to the digests logged at its
+    /// checkpoints and on shutdown, and warn or refuse to start on a mismatch
+    #[arg(long, value_enum, value_name = "ACTION", requires = "wal")]
+    verify_recovery: Option<VerifyRecoveryArg>,
+
+    /// Directory to save state files in, on --snapshot-schedule and when
+    /// `POST /admin/snapshot` asks for one
+    #[arg(long, value_name = "DIR")]
+    snapshot_dir: Option<PathBuf>,
+
+    /// When to save a state file to --snapshot-dir: five crontab fields in
+    /// UTC, e.g. "0 */6 * * *", or @hourly, @daily or @weekly
+    #[arg(long, value_name = "CRON", requires = "snapshot_dir")]
+    snapshot_schedule: Option<Schedule>,
+
+    /// Newest state files to keep in --snapshot-dir, deleting older ones
+    /// [default: keep all]
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "snapshot_dir",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    snapshot_keep: Option<usize>,
+
+    /// TOML file with limits, rate limits and validation rules; reloaded on SIGHUP
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Archive deposits dropped under the config's `[retention]` policy to
+    /// this file, where `GET /archive/{tx}` finds them
+    #[arg(long, value_name = "FILE")]
+    archive: Option<PathBuf>,
+
+    /// Show a live dashboard on the terminal; q, Esc or Ctrl-C shuts the
+    /// server down
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    dashboard: bool,
+
+    /// Kafka brokers to consume transactions from, e.g. localhost:9092
+    #[cfg(feature = "kafka")]
+    #[arg(
+        long,
+        value_name = "LIST",
+        group = "listeners",
+        requires = "kafka_topic"
+    )]
+    kafka_brokers: Option<String>,
+
+    /// Kafka topic to consume transactions from
+    #[cfg(feature = "kafka")]
+    #[arg(long, value_name = "TOPIC", requires = "kafka_brokers")]
+    kafka_topic: Option<String>,
+
+    /// Kafka consumer group to commit offsets under
+    #[cfg(feature = "kafka")]
+    #[arg(long, value_name = "GROUP", default_value = "payments-engine")]
+    kafka_group: String,
+
+    /// Encoding of Kafka messages: json, avro or protobuf
+    #[cfg(feature = "kafka")]
+    #[arg(long, value_name = "FORMAT", default_value = "json")]
+    kafka_format: MessageFormat,
+
+    /// Redis server to consume transactions from, e.g. redis://localhost:6379
+    #[cfg(feature = "redis")]
+    #[arg(
+        long,
+        value_name = "URL",
+        group = "listeners",
+        requires = "redis_stream"
+    )]
+    redis_url: Option<String>,
+
+    /// Redis stream to consume transactions from
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "KEY", requires = "redis_url")]
+    redis_stream: Option<String>,
+
+    /// Redis consumer group to acknowledge entries under
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "GROUP", default_value = "payments-engine")]
+    redis_group: String,
+
+    /// Name of this consumer in the Redis consumer group; keep it stable
+    /// across restarts so unacknowledged entries are read again
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "NAME", default_value = "payments-engine")]
+    redis_consumer: String,
+
+    /// Decode each Redis entry's `payload` field as json, avro or protobuf
+    /// instead of reading type, client, tx and amount fields
+    #[cfg(feature = "redis")]
+    #[arg(long, value_name = "FORMAT")]
+    redis_format: Option<MessageFormat>,
+
+    /// CSV file to follow like `tail -f`, applying transactions as lines are
+    /// appended to it
+    #[arg(long, value_name = "FILE", group = "listeners")]
+    follow: Option<PathBuf>,
+
+    /// File recording how far --follow got, so a restart resumes there
+    /// instead of reading the whole file again
+    #[arg(long, value_name = "FILE", requires = "follow")]
+    follow_offset: Option<PathBuf>,
+
+    /// Kafka brokers to publish account events to
+    #[cfg(feature = "kafka")]
+    #[arg(long, value_name = "LIST", requires = "events_kafka_topic")]
+    events_kafka_brokers: Option<String>,
+
+    /// Kafka topic to publish account events to, keyed by client ID
+    #[cfg(feature = "kafka")]
+    #[arg(long, value_name = "TOPIC", requires = "events_kafka_brokers")]
+    events_kafka_topic: Option<String>,
+
+    /// NATS server to publish account events to, e.g. nats://localhost:4222
+    #[cfg(feature = "nats")]
+    #[arg(long, value_name = "URL")]
+    events_nats_url: Option<String>,
+
+    /// Subject prefix for NATS account events, published as <SUBJECT>.<client>
+    #[cfg(feature = "nats")]
+    #[arg(long, value_name = "SUBJECT", default_value = "payments.accounts")]
+    events_nats_subject: String,
+}
+
+/// Batch mode: process one CSV file and print the resulting accounts
+#[derive(Args)]
+#[command(group(ArgGroup::new("resumable").args(["state", "checkpoint"])))]
+struct BatchArgs {
+    /// Input transactions CSV; `-` reads stdin
+    #[arg(required = true)]
+    input: Option<PathBuf>,
+
+    /// Format of the input
+    #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+    input_format: InputFormat,
+
+    /// Memory-map the input instead of reading it into buffers; pipes and
+    /// stdin are still read normally. The file must not change while mapped
+    #[arg(long)]
+    mmap: bool,
+
+    /// State file from a previous run; loaded before processing and updated afterwards
+    #[arg(long, value_name = "FILE")]
+    state: Option<PathBuf>,
+
+    /// Save --state or --checkpoint every N transactions, so a rerun after a
+    /// crash resumes from the last save
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "resumable",
+        conflicts_with_all = ["stream_updates", "as_of"],
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    checkpoint_every: Option<u64>,
+
+    /// Save a binary snapshot of the engine, along with how far the input
+    /// was read, to FILE every --checkpoint-every transactions (default
+    /// 1000000). Rerun with the same FILE and input after a crash to resume
+    /// from the last one; FILE is removed once the run completes
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["state", "stream_updates", "parallel", "tenants", "as_of", "input_errors", "strict"]
+    )]
+    checkpoint: Option<PathBuf>,
+
+    /// Accounts CSV (same format as the output) used to seed starting balances
+    #[arg(long, value_name = "FILE", conflicts_with = "state")]
+    accounts: Option<PathBuf>,
+
+    /// Verify accounting invariants after processing and fail if any are violated
+    #[arg(long)]
+    check_invariants: bool,
+
+    /// Also check accounting invariants after every N applied transactions,
+    /// reporting violations as they are found and failing the run
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["parallel", "tenants"],
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    watchdog: Option<u64>,
+
+    /// Emit each affected account as transactions are applied instead of a final dump
+    #[arg(long)]
+    stream_updates: bool,
+
+    /// Split the input by client and process the parts on every CPU; reads
+    /// the whole input into memory
+    #[arg(long, conflicts_with_all = ["state", "accounts", "stream_updates"])]
+    parallel: bool,
+
+    /// Output format for the resulting accounts
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    format: OutputFormat,
+
+    /// Output the accounts as they stood at this Unix time, skipping
+    /// transactions timestamped later; untimed ones are still applied
+    #[arg(
+        long,
+        value_name = "TIMESTAMP",
+        conflicts_with_all = ["stream_updates", "parallel", "tenants"]
+    )]
+    as_of: Option<u64>,
+
+    /// Roughly how many deposits the input holds; sizes the deposit table up
+    /// front instead of growing it while processing
+    #[arg(long, value_name = "N", conflicts_with = "parallel")]
+    expected_deposits: Option<usize>,
+
+    /// Read a `tenant` column and keep each tenant's clients and transaction
+    /// IDs apart; the output gains a `tenant` column
+    #[arg(
+        long,
+        conflicts_with_all = ["state", "accounts", "stream_updates", "parallel", "expected_deposits"]
+    )]
+    tenants: bool,
+
+    /// Only output accounts of this tenant; may be repeated
+    #[arg(long = "tenant", value_name = "ID", requires = "tenants")]
+    only_tenants: Vec<String>,
+
+    /// Check the input's header before processing, failing if it lacks a
+    /// required column, and write each row that isn't a valid transaction to
+    /// FILE as CSV (line, column, value, message) instead of skipping it
+    /// silently
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["state", "stream_updates", "parallel", "tenants", "as_of"]
+    )]
+    input_errors: Option<PathBuf>,
+
+    /// Fail on a row that isn't a valid transaction, or a header lacking a
+    /// required column, instead of skipping it: at the first one, or with
+    /// --input-errors once every one is listed
+    #[arg(
+        long,
+        conflicts_with_all = ["state", "stream_updates", "parallel", "tenants", "as_of"]
+    )]
+    strict: bool,
+
+    /// Show a live dashboard on the terminal while processing; q, Esc or
+    /// Ctrl-C abandons the run
+    #[cfg(feature = "tui")]
+    #[arg(
+        long,
+        conflicts_with_all = ["stream_updates", "parallel", "tenants", "as_of", "checkpoint_every", "checkpoint", "input_errors", "strict"]
+    )]
+    dashboard: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum InputFormat {
+    /// CSV with a `type,client,tx,amount` header row
+    Csv,
+    /// ISO 8583 messages, each after a 2-byte big-endian length, decoded
+    /// with the default `Iso8583Format`
+    Iso8583,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// CSV with a header row
+    Csv,
+    /// Newline-delimited JSON objects
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ShardKeyArg {
+    /// Client ID modulo the shard count
+    Modulo,
+    /// Hash of the client ID, for IDs that follow a pattern
+    Hash,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum VerifyRecoveryArg {
+    /// Log a warning and start with the state as recovered
+    Warn,
+    /// Exit without starting
+    Refuse,
+}
+
+impl ShardKeyArg {
+    fn shard_key(self) -> ShardKey {
+        match self {
+            ShardKeyArg::Modulo => modulo_shard_key,
+            ShardKeyArg::Hash => hashed_shard_key,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    log::set_logger(&StderrLogger).expect("no logger set yet");
+    log::set_max_level(match (cli.quiet, cli.verbose) {
+        (true, _) => log::LevelFilter::Warn,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    });
+
+    let result = match cli.command {
+        Some(Command::Serve(args)) => serve(*args),
+        Some(Command::Generate(args)) => generate(args),
+        Some(Command::Statement(args)) => statement(args),
+        Some(Command::Report(args)) => report(args),
+        Some(Command::Replay(args)) => replay(args),
+        Some(Command::Verify(args)) => verify(args),
+        Some(Command::MigrateWal(args)) => migrate_wal(args),
+        Some(Command::Repl) => repl(),
+        Some(Command::Follow(args)) => follow(args),
+        Some(Command::Promote(args)) => promote(args),
+        None => run_batch(cli.batch),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {:?}", error);
+            let failure = error
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<Classified>())
+                .map(|classified| classified.failure);
+            ExitCode::from(failure.map_or(1, |failure| failure as u8))
+        }
+    }
+}
+
+/// Writes the engine's log records to stderr: progress notes as they are,
+/// everything else prefixed with its level
+///
+/// Dependencies only get through with warnings and errors, so `-vv` shows
+/// the engine's records rather than every library's.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && (metadata.level() <= log::Level::Warn
+                || metadata.target().starts_with("payments_engine"))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            log::Level::Info => eprintln!("{}", record.args()),
+            level => eprintln!("{}: {}", level.as_str().to_lowercase(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Report at debug level how long `stage` took since `clock`, and restart it
+fn lap(clock: &mut Instant, stage: &str) {
+    log::debug!("{} took {:.2?}", stage, clock.elapsed());
+    *clock = Instant::now();
+}
+
+/// Classes of failure with an exit code of their own, for scripts and
+/// orchestrators to branch on
+///
+/// Other failures exit with 1, and invalid arguments with 2, as clap does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    /// An input file is missing or can't be opened
+    InputNotFound = 3,
+    /// Input rejected under `--strict` or `--input-errors`, or an accounts
+    /// file that doesn't parse
+    MalformedInput = 4,
+    /// `--check-invariants` or `--watchdog` found a broken invariant
+    InvariantViolation = 5,
+    /// A state file, WAL or archive couldn't be read or written
+    Persistence = 6,
+}
+
+impl Failure {
+    /// An error saying `message` that exits with this failure's code
+    fn error(self, message: impl fmt::Display) -> Classified {
+        Classified {
+            failure: self,
+            error: anyhow::anyhow!("{}", message),
+        }
+    }
+}
+
+/// An error tagged with its `Failure`, shown as the error it wraps
+#[derive(Debug)]
+struct Classified {
+    failure: Failure,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for Classified {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.chain().nth(1)
+    }
+}
+
+/// Tag the error of a result with the `Failure` it exits with
+trait Classify<T> {
+    fn classify(self, failure: Failure) -> Result<T>;
+}
+
+impl<T> Classify<T> for Result<T> {
+    fn classify(self, failure: Failure) -> Result<T> {
+        self.map_err(|error| Classified { failure, error }.into())
+    }
+}
+
+/// Run the configured servers until one of them fails or a shutdown signal arrives
+///
+/// On SIGINT/SIGTERM the engine stops taking transactions, lets in-flight
+/// ones finish and, with `--state`, saves its final state before exiting.
+/// SIGHUP reloads `--config`; a config that fails to load is reported and
+/// the previous one stays in effect.
+fn serve(args: ServeArgs) -> Result<()> {
+    let shard_options = ShardOptions {
+        num_shards: args.shards.unwrap_or_else(default_shard_count),
+        shard_key: args.shard_key.shard_key(),
+        checkpoints: CheckpointPolicy {
+            max_log_bytes: args.checkpoint_bytes,
+            max_entries: args.checkpoint_entries,
+            max_interval: args.checkpoint_interval.map(Duration::from_secs),
+        },
+        integrity: match args.verify_recovery {
+            None => IntegrityCheck::Off,
+            Some(VerifyRecoveryArg::Warn) => IntegrityCheck::Warn,
+            Some(VerifyRecoveryArg::Refuse) => IntegrityCheck::Refuse,
+        },
+    };
+    anyhow::ensure!(shard_options.num_shards > 0, "--shards must be at least 1");
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")?;
+
+    let api_keys = args
+        .api_keys
+        .as_ref()
+        .map(|path| {
+            ApiKeys::load(path)
+                .with_context(|| format!("Failed to load API keys '{}'", path.display()))
+        })
+        .transpose()?;
+
+    // Shard tasks are spawned as the engine is built
+    let _runtime_guard = runtime.enter();
+
+    let engine = match (&args.state, &args.wal) {
+        (Some(state_path), _) => {
+            let state = EngineState::load(state_path)
+                .with_context(|| format!("Failed to load state file '{}'", state_path.display()))
+                .classify(Failure::Persistence)?;
+            ShardedEngine::from_state_with_options(shard_options, state)
+        }
+        (None, Some(wal_dir)) if args.replicate_to.is_empty() => {
+            ShardedEngine::recover_with_options(wal_dir, shard_options)
+                .with_context(|| format!("Failed to recover from '{}'", wal_dir.display()))
+                .classify(Failure::Persistence)?
+        }
+        (None, Some(wal_dir)) => {
+            log::info!(
+                "Replicating to {}; transactions need {} of {} nodes",
+                args.replicate_to.join(", "),
+                replicated::majority(args.replicate_to.len() + 1),
+                args.replicate_to.len() + 1
+            );
+            ShardedEngine::recover_replicated(wal_dir, shard_options, &args.replicate_to)
+                .with_context(|| format!("Failed to recover from '{}'", wal_dir.display()))
+                .classify(Failure::Persistence)?
+        }
+        (None, None) => ShardedEngine::with_options(shard_options),
+    };
+
+    let config = args
+        .config
+        .as_ref()
+        .map(|path| load_config(path))
+        .transpose()?;
+
+    runtime.block_on(async {
+        // Only fires once a config enables the watchdog, possibly on reload
+        engine
+            .set_hooks(Hooks::new().alert(|report| {
+                log::warn!("Invariant watchdog: {}", report);
+            }))
+            .await;
+        if let Some(path) = &args.archive {
+            engine
+                .archive_to(path)
+                .await
+                .with_context(|| format!("Failed to open archive '{}'", path.display()))
+                .classify(Failure::Persistence)?;
+        }
+        if let Some(config) = &config {
+            config.apply(&engine).await;
+            #[cfg(feature = "chaos")]
+            announce_chaos(config);
+        }
+
+        // Publishers subscribe before anything can change an account; they
+        // stop after the sources, once the last events have been emitted
+        let (stop_publishers, publishers_stopped) = watch::channel(false);
+        let mut publishers: tokio::task::JoinSet<Result<()>> = tokio::task::JoinSet::new();
+
+        #[cfg(feature = "kafka")]
+        if let (Some(brokers), Some(topic)) = (&args.events_kafka_brokers, &args.events_kafka_topic)
+        {
+            let sink = KafkaEventSink::new(brokers, topic.as_str(), &[])
+                .context("Failed to create Kafka producer")?;
+            log::info!("Publishing account events to Kafka topic '{}'", topic);
+            let stopped = until_stopped(publishers_stopped.clone());
+            publishers.spawn(publish(&engine, "Kafka", sink, stopped));
+        }
+
+        #[cfg(feature = "nats")]
+        if let Some(url) = &args.events_nats_url {
+            let sink = NatsEventSink::connect(url, args.events_nats_subject.as_str())
+                .await
+                .with_context(|| format!("Failed to connect to NATS at '{}'", url))?;
+            log::info!(
+                "Publishing account events to NATS subjects '{}.<client>'",
+                args.events_nats_subject
+            );
+            let stopped = until_stopped(publishers_stopped.clone());
+            publishers.spawn(publish(&engine, "NATS", sink, stopped));
+        }
+        drop(publishers_stopped);
+
+        // Read-only before any server takes a transaction
+        if args.replica_of.is_some() {
+            engine.set_read_only(true).await;
+        }
+
+        let snapshots = args
+            .snapshot_dir
+            .as_ref()
+            .map(|dir| {
+                let options = SnapshotOptions {
+                    dir: dir.clone(),
+                    keep: args.snapshot_keep,
+                };
+                Snapshotter::new(&engine, options)
+                    .with_context(|| format!("Failed to create '{}'", dir.display()))
+                    .classify(Failure::Persistence)
+            })
+            .transpose()?;
+
+        let mut servers = tokio::task::JoinSet::new();
+
+        if let (Some(addr), Some(wal_dir)) = (&args.ship_wal, &args.wal) {
+            let shipper = WalShipper::bind(addr.as_str(), wal_dir)
+                .await
+                .with_context(|| format!("Failed to bind '{}'", addr))?;
+            log::info!("Shipping logs to replicas on {}", shipper.local_addr()?);
+            servers.spawn(async move { shipper.run().await.context("WAL shipping failed") });
+        }
+
+        if let Some(addr) = &args.tcp {
+            let listener = bind(addr).await?;
+            log::info!(
+                "Listening for transaction streams on {}",
+                listener.local_addr()?
+            );
+            let engine = engine.clone_handle();
+            let api_keys = api_keys.clone();
+            servers.spawn(async move {
+                server::tcp::serve(listener, engine, api_keys)
+                    .await
+                    .context("TCP server failed")
+            });
+        }
+
+        if let Some(addr) = &args.http {
+            let listener = bind(addr).await?;
+            log::info!("Serving HTTP API on {}", listener.local_addr()?);
+            let engine = engine.clone_handle();
+            let snapshots = snapshots.clone();
+            servers.spawn(async move {
+                server::http::serve(listener, engine, api_keys, snapshots)
+                    .await
+                    .context("HTTP server failed")
+            });
+        }
+
+        // Sources finish the messages they consumed when told to stop
+        let (stop_sources, sources_stopped) = watch::channel(false);
+        let mut sources: tokio::task::JoinSet<Result<()>> = tokio::task::JoinSet::new();
+
+        #[cfg(feature = "kafka")]
+        if let (Some(brokers), Some(topic)) = (&args.kafka_brokers, &args.kafka_topic) {
+            let mut options = KafkaSourceOptions::new(brokers, topic, &args.kafka_group);
+            options.format = args.kafka_format;
+            log::info!("Consuming transactions from Kafka topic '{}'", topic);
+            let engine = engine.clone_handle();
+            let stopped = until_stopped(sources_stopped.clone());
+            sources.spawn(async move {
+                let stats = kafka::consume(&engine, options, stopped)
+                    .await
+                    .context("Kafka source failed")?;
+                log::info!(
+                    "Kafka source stopped: {} processed, {} malformed",
+                    stats.processed,
+                    stats.malformed
+                );
+                Ok(())
+            });
+        }
+        #[cfg(feature = "redis")]
+        if let (Some(url), Some(stream)) = (&args.redis_url, &args.redis_stream) {
+            let mut options = RedisSourceOptions::new(url, stream, &args.redis_group);
+            options.consumer = args.redis_consumer.clone();
+            options.payload_format = args.redis_format;
+            log::info!("Consuming transactions from Redis stream '{}'", stream);
+            let engine = engine.clone_handle();
+            let stopped = until_stopped(sources_stopped.clone());
+            sources.spawn(async move {
+                let stats = redis_streams::consume(&engine, options, stopped)
+                    .await
+                    .context("Redis source failed")?;
+                log::info!(
+                    "Redis source stopped: {} processed, {} malformed",
+                    stats.processed,
+                    stats.malformed
+                );
+                Ok(())
+            });
+        }
+        if let Some(path) = &args.follow {
+            let mut options = TailSourceOptions::new(path);
+            options.offset_file = args.follow_offset.clone();
+            log::info!("Following transactions appended to '{}'", path.display());
+            let engine = engine.clone_handle();
+            let stopped = until_stopped(sources_stopped.clone());
+            sources.spawn(async move {
+                let stats = file_tail::consume(&engine, options, stopped)
+                    .await
+                    .context("Following the transaction file failed")?;
+                log::info!(
+                    "Stopped following: {} processed, {} malformed",
+                    stats.processed,
+                    stats.malformed
+                );
+                Ok(())
+            });
+        }
+        if let (Some(primary), Some(wal_dir)) = (&args.replica_of, &args.wal) {
+            log::info!("Replicating from {}; read-only until promoted", primary);
+            let replica = Replica::new(&engine, primary.as_str(), wal_dir);
+            let stopped = sources_stopped.clone();
+            sources.spawn(async move {
+                let exit = replica
+                    .run(until_stopped(stopped.clone()))
+                    .await
+                    .context("Replication failed")?;
+                if exit == ReplicaExit::Promoted {
+                    log::info!("Promoted: taking transactions");
+                    until_stopped(stopped).await;
+                }
+                Ok(())
+            });
+        }
+        // Stops with the sources, so no snapshot starts during shutdown
+        if let (Some(snapshots), Some(schedule)) = (&snapshots, &args.snapshot_schedule) {
+            log::info!(
+                "Saving snapshots to '{}' on schedule '{}'",
+                snapshots.dir().display(),
+                schedule
+            );
+            let snapshots = snapshots.clone();
+            let schedule = schedule.clone();
+            let stopped = until_stopped(sources_stopped.clone());
+            sources.spawn(async move {
+                snapshots.run(&schedule, stopped).await;
+                Ok(())
+            });
+        }
+        drop(sources_stopped);
+
+        // Started last, so the messages above aren't drawn over
+        let mut dashboard: tokio::task::JoinSet<Result<()>> = tokio::task::JoinSet::new();
+        #[cfg(feature = "tui")]
+        if args.dashboard {
+            let engine = engine.clone_handle();
+            dashboard.spawn(async move { show_dashboard(&engine).await });
+        }
+
+        let mut reload = ReloadSignal::new().context("Failed to listen for SIGHUP")?;
+
+        loop {
+            // Servers only return on failure, so the first one to finish ends the process
+            tokio::select! {
+                Some(result) = servers.join_next() => {
+                    result.context("Server task panicked")??;
+                    break;
+                }
+                Some(result) = sources.join_next() => {
+                    result.context("Source task panicked")??;
+                    break;
+                }
+                Some(result) = publishers.join_next() => {
+                    result.context("Publisher task panicked")??;
+                    break;
+                }
+                // Only returns once the operator quits it
+                Some(result) = dashboard.join_next() => {
+                    result.context("Dashboard task panicked")??;
+                    break;
+                }
+                signal = shutdown_signal() => {
+                    signal.context("Failed to listen for shutdown signals")?;
+                    break;
+                }
+                _ = reload.recv(), if args.config.is_some() => {
+                    let path = args.config.as_ref().expect("checked by the select guard");
+                    match load_config(path) {
+                        Ok(config) => {
+                            config.apply(&engine).await;
+                            log::info!("Reloaded config from '{}'", path.display());
+                            #[cfg(feature = "chaos")]
+                            announce_chaos(&config);
+                        }
+                        Err(e) => log::warn!("Keeping previous config: {:#}", e),
+                    }
+                }
+            }
+        }
+
+        // Gives the terminal back before anything else is printed
+        dashboard.shutdown().await;
+        log::info!("Shutting down");
+        servers.abort_all();
+        let _ = stop_sources.send(true);
+        while let Some(result) = sources.join_next().await {
+            result.context("Source task panicked")??;
+        }
+        let _ = stop_publishers.send(true);
+        while let Some(result) = publishers.join_next().await {
+            result.context("Publisher task panicked")??;
+        }
+        let state = engine
+            .shutdown()
+            .await
+            .context("Failed to shut down engine")?;
+
+        if let Some(state_path) = &args.state {
+            state
+                .save(state_path)
+                .with_context(|| format!("Failed to save state file '{}'", state_path.display()))
+                .classify(Failure::Persistence)?;
+        }
+
+        Ok(())
+    })
+}
+
+fn load_config(path: &Path) -> Result<ServerConfig> {
+    ServerConfig::load(path)
+        .with_context(|| format!("Failed to load config file '{}'", path.display()))
+}
+
+/// Make sure injected faults aren't mistaken for real ones
+#[cfg(feature = "chaos")]
+fn announce_chaos(config: &ServerConfig) {
+    if let Some(chaos) = &config.chaos {
+        log::info!("Chaos mode: injecting faults ({:?})", chaos);
+    }
+}
+
+/// Show a dashboard of `engine` until the operator quits it
+#[cfg(feature = "tui")]
+async fn show_dashboard(engine: &ShardedEngine) -> Result<()> {
+    // Log messages go to stderr, so the dashboard takes stdout
+    anyhow::ensure!(
+        io::stdout().is_terminal(),
+        "--dashboard needs stdout to be a terminal"
+    );
+    let mut screen = Screen::open(io::stdout()).context("Failed to set up the terminal")?;
+    let mut dashboard = Dashboard::new("payments-engine serve", std::time::Instant::now());
+    let mut ticks = tokio::time::interval(dashboard::TICK);
+    loop {
+        ticks.tick().await;
+        if screen.quit_requested()? {
+            return Ok(());
+        }
+        dashboard.update(Sample::of(engine).await, std::time::Instant::now());
+        screen.draw(&dashboard)?;
+    }
+}
+
+/// Apply CSV transactions one at a time, showing a dashboard as they go
+#[cfg(feature = "tui")]
+fn apply_with_dashboard(engine: &mut PaymentsEngine, file: Input) -> Result<()> {
+    /// Transactions between checks of the clock
+    const CHECK_EVERY: u64 = 1024;
+
+    // Accounts go to stdout, so the dashboard takes stderr
+    anyhow::ensure!(
+        io::stderr().is_terminal(),
+        "--dashboard needs stderr to be a terminal"
+    );
+    let mut screen = Screen::open(io::stderr()).context("Failed to set up the terminal")?;
+    let started = std::time::Instant::now();
+    let mut dashboard = Dashboard::new("payments-engine", started);
+    let mut sample = Sample::default();
+    let mut next_tick = started;
+
+    let mut show = |engine: &PaymentsEngine, sample: &Sample| -> Result<()> {
+        let sample = Sample {
+            top_held: dashboard::top_held(engine.accounts_iter(), dashboard::TOP_ACCOUNTS),
+            ..sample.clone()
+        };
+        dashboard.update(sample, std::time::Instant::now());
+        screen.draw(&dashboard)?;
+        anyhow::ensure!(!screen.quit_requested()?, "Stopped from the dashboard");
+        Ok(())
+    };
+
+    for tx in read_transactions(file) {
+        sample.record(engine.process_transaction(tx));
+        if sample.processed() % CHECK_EVERY == 0 && std::time::Instant::now() >= next_tick {
+            show(engine, &sample)?;
+            next_tick = std::time::Instant::now() + dashboard::TICK;
+        }
+    }
+    show(engine, &sample)
+}
+
+/// Config reload requests (SIGHUP); never fires on platforms without it
+struct ReloadSignal {
+    #[cfg(unix)]
+    hangup: tokio::signal::unix::Signal,
+}
+
+impl ReloadSignal {
+    fn new() -> io::Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            hangup: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.hangup.recv().await;
+
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Resolve when the process receives SIGINT (Ctrl-C) or, on Unix, SIGTERM
+async fn shutdown_signal() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await
+}
+
+/// Publish `engine`'s account events to `sink` until `stopped` resolves
+#[cfg(any(feature = "kafka", feature = "nats"))]
+fn publish<S: EventSink + 'static>(
+    engine: &ShardedEngine,
+    name: &'static str,
+    sink: S,
+    stopped: impl Future<Output = ()> + Send + 'static,
+) -> impl Future<Output = Result<()>> + Send + 'static {
+    let events = engine.subscribe();
+    async move {
+        let stats = publish_events(events, sink, stopped)
+            .await
+            .with_context(|| format!("{} event publisher failed", name))?;
+        log::info!(
+            "{} event publisher stopped: {} published, {} missed",
+            name,
+            stats.published,
+            stats.missed
+        );
+        Ok(())
+    }
+}
+
+/// Resolves once `stop` is set
+async fn until_stopped(mut stop: watch::Receiver<bool>) {
+    // An error means the sender is gone, which also means stop
+    let _ = stop.wait_for(|stop| *stop).await;
+}
+
+async fn bind(addr: &str) -> Result<tokio::net::TcpListener> {
+    tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on '{}'", addr))
+}
+
+/// Write the workload described by `args` as CSV
+fn generate(args: GenerateArgs) -> Result<()> {
     anyhow::ensure!(
-        args.len() == 2,
-        "Usage: {} <input.csv>",
-        args.first().unwrap_or(&"payments-engine".to_string())
+        args.withdrawal_ratio + args.dispute_ratio + args.duplicate_ratio <= 1.0,
+        "--withdrawal-ratio, --dispute-ratio and --duplicate-ratio add up to more than 1"
     );
+    anyhow::ensure!(args.max_amount > 0.0, "--max-amount must be positive");
+
+    let amounts = match args.amounts {
+        AmountsArg::Uniform => AmountDistribution::Uniform {
+            max: args.max_amount,
+        },
+        AmountsArg::LogNormal => AmountDistribution::LogNormal {
+            median: args.median_amount,
+            sigma: 1.0,
+            max: args.max_amount,
+        },
+    };
+    let transactions = Workload::new(WorkloadOptions {
+        transactions: args.transactions,
+        clients: args.clients,
+        withdrawal_ratio: args.withdrawal_ratio,
+        dispute_ratio: args.dispute_ratio,
+        chargeback_rate: args.chargeback_rate,
+        duplicate_ratio: args.duplicate_ratio,
+        amounts,
+        seed: args.seed,
+    });
+
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create '{}'", path.display()))?;
+            workload::write_csv(transactions, io::BufWriter::new(file))
+        }
+        None => workload::write_csv(transactions, io::BufWriter::new(io::stdout().lock())),
+    }
+    .context("Failed to write transactions")
+}
+
+/// Write statements for the clients in `args` built from its input
+fn statement(args: StatementArgs) -> Result<()> {
+    let file = Input::open(&args.input)
+        .with_context(|| format!("Failed to open input file '{}'", args.input.display()))
+        .classify(Failure::InputNotFound)?;
+    let mut engine = PaymentsEngine::new().retain_history();
+    apply_transactions(&mut engine, file);
+
+    let statements = if args.clients.is_empty() {
+        engine.statements()
+    } else {
+        // Clients without applied transactions have nothing to report
+        args.clients
+            .iter()
+            .filter_map(|&client_id| engine.statement(client_id))
+            .collect()
+    };
+    let options = StatementOptions::new(args.currency);
 
-    let filename = &args[1];
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create '{}'", path.display()))?;
+            write_statements(args.format, &statements, &options, io::BufWriter::new(file))
+        }
+        None => write_statements(
+            args.format,
+            &statements,
+            &options,
+            io::BufWriter::new(io::stdout().lock()),
+        ),
+    }
+    .context("Failed to write statements")
+}
+
+fn write_statements<W: io::Write>(
+    format: StatementFormat,
+    statements: &[Statement],
+    options: &StatementOptions,
+    writer: W,
+) -> payments_engine::error::Result<()> {
+    match format {
+        StatementFormat::Camt053 => camt053::write(statements, options, writer),
+        StatementFormat::Mt940 => mt940::write(statements, options, writer),
+        StatementFormat::Ofx => ofx::write(statements, options, writer),
+        StatementFormat::Qif => qif::write(statements, options, writer),
+    }
+}
+
+/// Write a suspicious-activity report on the input in `args`
+fn report(args: ReportArgs) -> Result<()> {
+    let file = Input::open(&args.input)
+        .with_context(|| format!("Failed to open input file '{}'", args.input.display()))
+        .classify(Failure::InputNotFound)?;
+    let mut engine = PaymentsEngine::new().retain_history();
+    apply_transactions(&mut engine, file);
+
+    let rules = ReportRules {
+        cycle_window: args.cycle_window,
+        failed_withdrawals: args.failed_withdrawals,
+        failed_withdrawal_window: args.failed_withdrawal_window,
+        reporting_threshold: args.reporting_threshold,
+        structuring_floor: args.structuring_floor,
+        structuring_window: args.structuring_window,
+    };
+    let report = engine.suspicious_activity(&rules);
+    let write = |writer: &mut dyn io::Write| match args.format {
+        ReportFormat::Csv => suspicious::write_csv(&report, writer),
+        ReportFormat::Json => {
+            suspicious::write_json(&report, &rules, std::time::SystemTime::now(), writer)
+        }
+    };
 
-    let file = File::open(filename)
-        .with_context(|| format!("Failed to open input file '{}'", filename))?;
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create '{}'", path.display()))?;
+            write(&mut io::BufWriter::new(file))
+        }
+        None => write(&mut io::BufWriter::new(io::stdout().lock())),
+    }
+    .context("Failed to write report")
+}
+
+/// Replay a log to the points in `args` and print accounts or their diff
+fn replay(args: ReplayArgs) -> Result<()> {
+    let mut replay = Replay::open(&args.log)
+        .with_context(|| format!("Failed to read log '{}'", args.log.display()))
+        .classify(Failure::Persistence)?;
+    let shown = |client: &ClientId| args.clients.is_empty() || args.clients.contains(client);
+    let run_to = |replay: &mut Replay, point: Option<Breakpoint>| -> Result<()> {
+        let trace = |step: &Step| {
+            if args.trace && shown(&step.client()) {
+                eprintln!("{}", step);
+            }
+        };
+        match point {
+            Some(point) => anyhow::ensure!(
+                replay.run_to(point, trace),
+                "The log never reaches {}",
+                point
+            ),
+            None => replay.run_to_end(trace),
+        }
+        log::info!(
+            "Stopped at entry {} of {}, time {}",
+            replay.position(),
+            replay.len(),
+            replay.time()
+        );
+        Ok(())
+    };
+
+    let before = match args.from {
+        Some(from) => {
+            run_to(&mut replay, Some(from))?;
+            Some(replay.snapshot())
+        }
+        None => None,
+    };
+    run_to(&mut replay, args.to)?;
 
-    process_transactions(file, io::stdout())
-        .context("Failed to process transactions and write output")?;
+    if let Some(path) = &args.save_state {
+        replay
+            .engine()
+            .to_state()
+            .save(path)
+            .with_context(|| format!("Failed to save state to '{}'", path.display()))
+            .classify(Failure::Persistence)?;
+    }
 
+    let write = |writer: &mut dyn io::Write| -> Result<()> {
+        match &before {
+            Some(before) => {
+                for change in replay::diff(before, &replay.snapshot()) {
+                    if shown(&change.client) {
+                        writeln!(writer, "{}", change)?;
+                    }
+                }
+                writer.flush()?;
+            }
+            None => {
+                let mut sink = CsvSink::new(writer);
+                for account in replay.engine().accounts_iter() {
+                    if shown(&account.client_id) {
+                        sink.write_account(account)?;
+                    }
+                }
+                sink.finish()?;
+            }
+        }
+        Ok(())
+    };
+
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create '{}'", path.display()))?;
+            write(&mut io::BufWriter::new(file))
+        }
+        None => write(&mut io::BufWriter::new(io::stdout().lock())),
+    }
+    .context("Failed to write accounts")
+}
+
+/// Check the accounts file in `args`, failing with every problem found
+fn verify(args: VerifyArgs) -> Result<()> {
+    let file = File::open(&args.accounts)
+        .with_context(|| format!("Failed to open accounts file '{}'", args.accounts.display()))
+        .classify(Failure::InputNotFound)?;
+    let report = verify_accounts(io::BufReader::new(file))
+        .with_context(|| format!("Failed to read accounts file '{}'", args.accounts.display()))
+        .classify(Failure::MalformedInput)?;
+    let failure = if report.is_malformed() {
+        Failure::MalformedInput
+    } else {
+        Failure::InvariantViolation
+    };
+    anyhow::ensure!(
+        report.is_ok(),
+        failure.error(format!("Verification failed: {}", report))
+    );
+    log::info!("{} account(s) verified", report.accounts_checked);
     Ok(())
 }
+
+/// Bring a log, a WAL directory or a snapshot up to the current format
+fn migrate_wal(args: MigrateWalArgs) -> Result<()> {
+    anyhow::ensure!(
+        args.path.exists(),
+        Failure::InputNotFound.error(format!("'{}' doesn't exist", args.path.display()))
+    );
+    if is_snapshot_file(&args.path)? {
+        return migrate_snapshot(&args.path);
+    }
+
+    let Migration {
+        logs,
+        snapshots,
+        lines,
+    } = persistence::migrate_log(&args.path)
+        .with_context(|| format!("Failed to migrate '{}'", args.path.display()))
+        .classify(Failure::Persistence)?;
+    log::info!(
+        "Migrated {} log(s): {} snapshot(s) upgraded, {} line(s) rewritten",
+        logs,
+        snapshots,
+        lines
+    );
+    Ok(())
+}
+
+/// Whether `path` is a binary snapshot file rather than a log or directory
+fn is_snapshot_file(path: &Path) -> Result<bool> {
+    if path.is_dir() {
+        return Ok(false);
+    }
+    let mut header = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(6).read_to_end(&mut header))
+        .with_context(|| format!("Failed to read '{}'", path.display()))
+        .classify(Failure::Persistence)?;
+    Ok(EngineState::snapshot_version(&header).is_ok())
+}
+
+/// Rewrite a snapshot file saved by an earlier version
+fn migrate_snapshot(path: &Path) -> Result<()> {
+    let bytes = fs::read(path)
+        .with_context(|| format!("Failed to read snapshot '{}'", path.display()))
+        .classify(Failure::Persistence)?;
+    let (version, state) = EngineState::from_any_snapshot_bytes(&bytes)
+        .with_context(|| format!("Failed to read snapshot '{}'", path.display()))
+        .classify(Failure::Persistence)?;
+    if version == SNAPSHOT_VERSION {
+        log::info!("'{}' is already in the current format", path.display());
+        return Ok(());
+    }
+    state
+        .save_snapshot(path)
+        .with_context(|| format!("Failed to save snapshot '{}'", path.display()))
+        .classify(Failure::Persistence)?;
+    log::info!(
+        "Upgraded snapshot '{}' from version {}",
+        path.display(),
+        version
+    );
+    Ok(())
+}
+
+/// Keep copies of a leader's logs until the listener fails
+fn follow(args: FollowArgs) -> Result<()> {
+    let follower = Follower::bind(&args.listen, &args.wal)
+        .with_context(|| format!("Failed to listen on '{}'", args.listen))?;
+    log::info!(
+        "Following on {}, keeping logs in '{}'",
+        follower.local_addr()?,
+        args.wal.display()
+    );
+    follower.run()?;
+    Ok(())
+}
+
+/// Ask the replica following into a directory to take transactions
+fn promote(args: PromoteArgs) -> Result<()> {
+    shipping::request_promotion(&args.wal)
+        .with_context(|| format!("Failed to promote '{}'", args.wal.display()))?;
+    log::info!(
+        "Asked the replica on '{}' to take transactions",
+        args.wal.display()
+    );
+    Ok(())
+}
+
+/// Run an interactive session on stdin until `quit` or end of input
+fn repl() -> Result<()> {
+    let mut session = Repl::new();
+    let mut stdout = io::stdout().lock();
+    // Piped input, e.g. a script of commands, gets no prompts
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        writeln!(stdout, "Type transactions or commands; 'help' lists them")?;
+    }
+    let mut lines = io::stdin().lines();
+    loop {
+        if interactive {
+            write!(stdout, "> ")?;
+            stdout.flush()?;
+        }
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        match session.execute(&line.context("Failed to read input")?) {
+            Reply::Output(output) if output.is_empty() => {}
+            Reply::Output(output) => writeln!(stdout, "{}", output)?,
+            Reply::Quit => return Ok(()),
+        }
+    }
+}
+
+/// Parse a fraction between 0 and 1
+fn parse_ratio(value: &str) -> std::result::Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        Ok(_) => Err("must be between 0 and 1".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn run_batch(cli: BatchArgs) -> Result<()> {
+    let input = cli
+        .input
+        .as_ref()
+        .expect("input is required without a subcommand");
+    let file = if cli.mmap {
+        Input::open_mapped(input)
+    } else {
+        Input::open(input)
+    }
+    .with_context(|| format!("Failed to open input file '{}'", input.display()))
+    .classify(Failure::InputNotFound)?;
+
+    let mut sink: Box<dyn OutputSink> = match cli.format {
+        OutputFormat::Csv => Box::new(CsvSink::new(io::stdout())),
+        OutputFormat::Json => Box::new(JsonLinesSink::new(io::stdout())),
+    };
+
+    anyhow::ensure!(
+        cli.input_format == InputFormat::Csv
+            || !(cli.parallel
+                || cli.stream_updates
+                || cli.as_of.is_some()
+                || cli.checkpoint_every.is_some()
+                || cli.checkpoint.is_some()
+                || cli.input_errors.is_some()
+                || cli.strict),
+        "--parallel, --stream-updates, --as-of, --checkpoint-every, --checkpoint, --input-errors and --strict need CSV input"
+    );
+    #[cfg(feature = "tui")]
+    anyhow::ensure!(
+        cli.input_format == InputFormat::Csv || !cli.dashboard,
+        "--dashboard needs CSV input"
+    );
+    if cli.parallel {
+        return run_parallel_batch(&cli, file, sink.as_mut());
+    }
+    if cli.tenants {
+        return run_tenant_batch(&cli, file);
+    }
+
+    let mut clock = Instant::now();
+    let (mut engine, mut sources) = build_engine(&cli)?;
+    if let Some(deposits) = cli.expected_deposits {
+        engine.reserve(0, deposits);
+    }
+    let alerts = cli
+        .watchdog
+        .map(|every| watch_invariants(&mut engine, every));
+    lap(&mut clock, "Setting up the engine");
+
+    if cli.stream_updates {
+        apply_transactions_streaming(&mut engine, file, sink.as_mut())
+            .context("Failed to write account updates")?;
+    } else if cli.input_format == InputFormat::Iso8583 {
+        apply_iso8583(&mut engine, file)?;
+    } else if let Some(as_of) = cli.as_of {
+        apply_transactions_as_of(&mut engine, file, as_of);
+    } else if cli.strict || cli.input_errors.is_some() {
+        apply_checked(&mut engine, file, cli.input_errors.as_deref(), cli.strict)?;
+    } else if let Some(state_path) = &cli.state {
+        let rows = ResumableRows::new(file, &sources).context("Failed to read input")?;
+        apply_resuming(
+            &mut engine,
+            rows,
+            &mut sources,
+            cli.checkpoint_every,
+            |engine, sources| save_state(engine, sources, state_path),
+        )?;
+    } else if let Some(checkpoint_path) = &cli.checkpoint {
+        let rows = ResumableRows::new(file, &sources).context("Failed to read input")?;
+        // Applying the input from the start on top of the checkpoint would
+        // apply its first part twice
+        anyhow::ensure!(
+            sources.is_empty() || rows.resume_from() > 0,
+            "Checkpoint '{}' was taken on a different input",
+            checkpoint_path.display()
+        );
+        apply_resuming(
+            &mut engine,
+            rows,
+            &mut sources,
+            Some(cli.checkpoint_every.unwrap_or(DEFAULT_CHECKPOINT_EVERY)),
+            |engine, sources| save_checkpoint(engine, sources, checkpoint_path),
+        )?;
+    } else {
+        #[cfg(feature = "tui")]
+        if cli.dashboard {
+            apply_with_dashboard(&mut engine, file)?;
+        } else {
+            apply_transactions(&mut engine, file);
+        }
+        #[cfg(not(feature = "tui"))]
+        apply_transactions(&mut engine, file);
+    }
+    lap(&mut clock, "Applying the input");
+
+    if let Some(alerts) = alerts {
+        let raised = alerts.load(Ordering::Relaxed);
+        anyhow::ensure!(
+            raised == 0,
+            Failure::InvariantViolation
+                .error(format!("Invariant watchdog raised {} alert(s)", raised))
+        );
+    }
+    if cli.check_invariants {
+        let report = engine.check_invariants();
+        anyhow::ensure!(
+            report.is_ok(),
+            Failure::InvariantViolation.error(format!("Invariant check failed: {}", report))
+        );
+        lap(&mut clock, "Checking invariants");
+    }
+
+    if let Some(state_path) = &cli.state {
+        save_state(&engine, &sources, state_path)?;
+        lap(&mut clock, "Saving state");
+    }
+
+    // Streamed updates already include every account's final state
+    if !cli.stream_updates {
+        write_accounts_to(engine, sink.as_mut()).context("Failed to write output")?;
+        lap(&mut clock, "Writing output");
+    }
+
+    // The run is complete, so a later one starts over
+    if let Some(checkpoint_path) = cli.checkpoint.as_ref().filter(|path| path.exists()) {
+        fs::remove_file(checkpoint_path)
+            .with_context(|| {
+                format!(
+                    "Failed to remove checkpoint '{}'",
+                    checkpoint_path.display()
+                )
+            })
+            .classify(Failure::Persistence)?;
+    }
+
+    Ok(())
+}
+
+/// Transactions between checkpoints when `--checkpoint` is given without
+/// `--checkpoint-every`
+const DEFAULT_CHECKPOINT_EVERY: u64 = 1_000_000;
+
+/// Apply the `rows` no earlier run applied, handing the engine and how far
+/// the input was read to `save` every `checkpoint_every` transactions
+fn apply_resuming(
+    engine: &mut PaymentsEngine,
+    mut rows: ResumableRows<Input>,
+    sources: &mut Vec<SourceOffset>,
+    checkpoint_every: Option<u64>,
+    save: impl Fn(&PaymentsEngine, &[SourceOffset]) -> Result<()>,
+) -> Result<()> {
+    if rows.resume_from() > 0 {
+        log::info!(
+            "Resuming the input after byte {}, where an earlier run stopped",
+            rows.resume_from()
+        );
+    }
+
+    let mut applied = 0;
+    while let Some(transaction) = rows.next() {
+        engine.process_transaction(transaction);
+        applied += 1;
+        if checkpoint_every.is_some_and(|every| applied % every == 0) {
+            rows.record(sources);
+            save(engine, sources)?;
+        }
+    }
+    rows.record(sources);
+    Ok(())
+}
+
+/// Save a binary checkpoint of the engine along with how far the input was
+/// read
+fn save_checkpoint(engine: &PaymentsEngine, sources: &[SourceOffset], path: &Path) -> Result<()> {
+    let mut state = engine.to_state();
+    state.sources = sources.to_vec();
+    state
+        .save_snapshot(path)
+        .with_context(|| format!("Failed to save checkpoint '{}'", path.display()))
+        .classify(Failure::Persistence)
+}
+
+/// Save the engine's state along with how far each input was read
+fn save_state(engine: &PaymentsEngine, sources: &[SourceOffset], path: &Path) -> Result<()> {
+    let mut state = engine.to_state();
+    state.sources = sources.to_vec();
+    state
+        .save(path)
+        .with_context(|| format!("Failed to save state file '{}'", path.display()))
+        .classify(Failure::Persistence)
+}
+
+/// Have `engine` check its invariants every `every` transactions, reporting
+/// violations on stderr; returns how many checks failed so far
+fn watch_invariants(engine: &mut PaymentsEngine, every: u64) -> Arc<AtomicUsize> {
+    engine.set_config(EngineConfig {
+        invariant_check_every: Some(every),
+        ..engine.config().clone()
+    });
+    let alerts = Arc::new(AtomicUsize::new(0));
+    let raised = alerts.clone();
+    engine.set_hooks(Hooks::new().alert(move |report| {
+        log::warn!("Invariant watchdog: {}", report);
+        raised.fetch_add(1, Ordering::Relaxed);
+    }));
+    alerts
+}
+
+/// Apply the transactions of `file`, writing the rows that aren't valid
+/// transactions to `errors_path` if given; fails before applying anything if
+/// the header lacks a required column
+///
+/// With `strict` malformed rows fail the run too: the first one if there is
+/// no `errors_path`, or all of them once they are written to it.
+fn apply_checked(
+    engine: &mut PaymentsEngine,
+    file: Input,
+    errors_path: Option<&Path>,
+    strict: bool,
+) -> Result<()> {
+    let transactions = read_transactions_checked(file);
+    let header = transactions.header();
+    anyhow::ensure!(
+        header.is_valid(),
+        Failure::MalformedInput.error(format!("Invalid input header: {}", header))
+    );
+    if !header.unknown.is_empty() {
+        log::info!(
+            "Passing unknown columns through as metadata: {}",
+            header.unknown.join(", ")
+        );
+    }
+
+    let Some(errors_path) = errors_path else {
+        for transaction in transactions {
+            let transaction = transaction.map_err(|error| {
+                Failure::MalformedInput.error(format!("Malformed row at {}", error))
+            })?;
+            engine.process_transaction(transaction);
+        }
+        return Ok(());
+    };
+
+    let mut errors = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_path(errors_path)
+        .with_context(|| format!("Failed to create '{}'", errors_path.display()))?;
+    errors.write_record(["line", "column", "value", "message"])?;
+    let mut malformed = 0;
+    for transaction in transactions {
+        match transaction {
+            Ok(transaction) => {
+                engine.process_transaction(transaction);
+            }
+            Err(error) => {
+                errors.serialize(&error)?;
+                malformed += 1;
+            }
+        }
+    }
+    errors.flush()?;
+    anyhow::ensure!(
+        !strict || malformed == 0,
+        Failure::MalformedInput.error(format!(
+            "{} malformed row(s), listed in '{}'",
+            malformed,
+            errors_path.display()
+        ))
+    );
+    if malformed > 0 {
+        log::info!(
+            "Skipped {} malformed row(s), listed in '{}'",
+            malformed,
+            errors_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Apply length-prefixed ISO 8583 messages, skipping ones that don't decode
+fn apply_iso8583(engine: &mut PaymentsEngine, file: Input) -> Result<()> {
+    let format = Iso8583Format::default();
+    for transaction in iso8583::read_transactions(file, &format) {
+        match transaction {
+            Ok(transaction) => {
+                engine.process_transaction(transaction);
+            }
+            Err(EngineError::InvalidMessage(message)) => {
+                log::debug!("Skipped undecodable message: {}", message);
+            }
+            Err(e) => return Err(e).context("Failed to read ISO 8583 messages"),
+        }
+    }
+    Ok(())
+}
+
+/// Batch mode with `--parallel`: one engine per partition of the clients
+fn run_parallel_batch(cli: &BatchArgs, file: Input, sink: &mut dyn OutputSink) -> Result<()> {
+    let mut clock = Instant::now();
+    let engines = apply_transactions_parallel(file, default_shard_count());
+    lap(&mut clock, "Applying the input");
+
+    if cli.check_invariants {
+        for engine in &engines {
+            let report = engine.check_invariants();
+            anyhow::ensure!(
+                report.is_ok(),
+                Failure::InvariantViolation.error(format!("Invariant check failed: {}", report))
+            );
+        }
+        lap(&mut clock, "Checking invariants");
+    }
+
+    for account in merge_accounts(engines) {
+        sink.write_account(&account)
+            .context("Failed to write output")?;
+    }
+    sink.finish().context("Failed to write output")?;
+    lap(&mut clock, "Writing output");
+    Ok(())
+}
+
+/// Batch mode with `--tenants`: one engine per tenant
+fn run_tenant_batch(cli: &BatchArgs, file: Input) -> Result<()> {
+    anyhow::ensure!(
+        cli.input_format == InputFormat::Csv && matches!(cli.format, OutputFormat::Csv),
+        "--tenants needs CSV input and output"
+    );
+    let mut clock = Instant::now();
+    let mut engine = MultiTenantEngine::new();
+    tenant::apply_tenant_transactions(&mut engine, file);
+    lap(&mut clock, "Applying the input");
+
+    if cli.check_invariants {
+        for id in engine.tenants() {
+            let report = engine.tenant(id).expect("listed tenant").check_invariants();
+            anyhow::ensure!(
+                report.is_ok(),
+                Failure::InvariantViolation.error(format!(
+                    "Invariant check failed for tenant '{}': {}",
+                    id, report
+                ))
+            );
+        }
+        lap(&mut clock, "Checking invariants");
+    }
+
+    tenant::write_tenant_accounts(&engine, &cli.only_tenants, io::stdout())
+        .context("Failed to write output")?;
+    lap(&mut clock, "Writing output");
+    Ok(())
+}
+
+/// Create the engine, starting from a checkpoint, seeded accounts or saved
+/// state if requested, along with how far earlier runs on the state read
+/// their inputs
+fn build_engine(cli: &BatchArgs) -> Result<(PaymentsEngine, Vec<SourceOffset>)> {
+    if let Some(checkpoint_path) = &cli.checkpoint {
+        let mut state = EngineState::load_snapshot(checkpoint_path)
+            .with_context(|| format!("Failed to load checkpoint '{}'", checkpoint_path.display()))
+            .classify(Failure::Persistence)?;
+        // Seeded accounts are part of the checkpoint
+        if !state.sources.is_empty() {
+            let sources = std::mem::take(&mut state.sources);
+            return Ok((PaymentsEngine::from_state(state), sources));
+        }
+    }
+
+    if let Some(accounts_path) = &cli.accounts {
+        let accounts_file = File::open(accounts_path)
+            .with_context(|| format!("Failed to open accounts file '{}'", accounts_path.display()))
+            .classify(Failure::InputNotFound)?;
+        let accounts = read_accounts(accounts_file)
+            .with_context(|| format!("Failed to read accounts file '{}'", accounts_path.display()))
+            .classify(Failure::MalformedInput)?;
+        return Ok((PaymentsEngine::with_initial_accounts(accounts), Vec::new()));
+    }
+
+    if let Some(state_path) = &cli.state {
+        let mut state = EngineState::load(state_path)
+            .with_context(|| format!("Failed to load state file '{}'", state_path.display()))
+            .classify(Failure::Persistence)?;
+        let sources = std::mem::take(&mut state.sources);
+        return Ok((PaymentsEngine::from_state(state), sources));
+    }
+
+    // Ordered storage lets results stream out without a final sort
+    Ok((
+        PaymentsEngine::with_account_ordering(AccountOrdering::ByClientId),
+        Vec::new(),
+    ))
+}
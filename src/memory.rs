@@ -0,0 +1,64 @@
+//! Memory use estimates for capacity planning
+//!
+//! Figures are computed from collection capacities and element sizes, not
+//! measured from the allocator, so they leave out allocator overhead and the
+//! engine's fixed-size fields. They track real usage closely enough to size
+//! machines and pick spill thresholds.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::mem::size_of;
+
+use crate::tx_store::TxIdSet;
+
+/// Estimated heap bytes held by a `PaymentsEngine`, by what holds them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryFootprint {
+    /// Client accounts, including seeded held balances
+    pub accounts: usize,
+    /// Stored deposits kept for disputes, with their index; entries spilled
+    /// to disk only count for their bookkeeping
+    pub stored_transactions: usize,
+    /// Set of processed transaction IDs used for duplicate detection
+    pub processed_tx_ids: usize,
+    /// Per-client transaction history, zero unless history is retained
+    pub history: usize,
+}
+
+impl MemoryFootprint {
+    /// Sum of all parts
+    pub fn total(&self) -> usize {
+        self.accounts + self.stored_transactions + self.processed_tx_ids + self.history
+    }
+}
+
+/// Bytes behind a `HashMap`: one slot plus one control byte per bucket
+pub(crate) fn hash_map_bytes<K, V>(map: &HashMap<K, V>) -> usize {
+    if map.capacity() == 0 {
+        return 0;
+    }
+    // Tables keep at most 7/8 of their buckets full
+    let buckets = (map.capacity() * 8 / 7).next_power_of_two();
+    buckets * (size_of::<(K, V)>() + 1)
+}
+
+/// Bytes behind a `BTreeMap`, assuming nodes are about two thirds full
+pub(crate) fn btree_map_bytes<K, V>(map: &BTreeMap<K, V>) -> usize {
+    map.len() * (size_of::<K>() + size_of::<V>()) * 3 / 2
+}
+
+pub(crate) fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * size_of::<T>()
+}
+
+pub(crate) fn vec_deque_bytes<T>(deque: &VecDeque<T>) -> usize {
+    deque.capacity() * size_of::<T>()
+}
+
+/// Bytes behind a set of transaction IDs, approximated by its serialized
+/// size, which lays its containers out the same way
+pub(crate) fn bitmap_bytes(bitmap: &TxIdSet) -> usize {
+    if bitmap.is_empty() {
+        return 0;
+    }
+    bitmap.serialized_size()
+}
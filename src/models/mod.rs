@@ -1,7 +0,0 @@
-pub mod account;
-pub mod stored_tx;
-pub mod transaction;
-
-pub use account::Account;
-pub use stored_tx::StoredTransaction;
-pub use transaction::{Transaction, TransactionType};
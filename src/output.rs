@@ -0,0 +1,84 @@
+use std::io::Write;
+
+use crate::error::Result;
+use crate::models::Account;
+
+/// Destination for account results
+///
+/// Decouples result emission from the CSV format so embedders can deliver
+/// accounts wherever they need them (a JSON stream, a database upsert, a
+/// message broker) without re-serializing the engine's CSV output.
+pub trait OutputSink {
+    /// Emit one account
+    fn write_account(&mut self, account: &Account) -> Result<()>;
+
+    /// Push buffered output through to the destination
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Complete the output once all accounts have been written
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+/// Writes accounts as CSV with a `client,available,held,total,locked` header
+pub struct CsvSink<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(writer),
+        }
+    }
+}
+
+impl<W: Write> OutputSink for CsvSink<W> {
+    fn write_account(&mut self, account: &Account) -> Result<()> {
+        self.writer.serialize(account.csv_row())?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes accounts as newline-delimited JSON objects
+///
+/// Uses the same field names as the CSV output plus the account `version`;
+/// amounts are strings to keep full decimal precision.
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> OutputSink for JsonLinesSink<W> {
+    fn write_account(&mut self, account: &Account) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, account)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Collects accounts in memory, mainly useful for tests and embedding
+impl OutputSink for Vec<Account> {
+    fn write_account(&mut self, account: &Account) -> Result<()> {
+        self.push(account.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::{Read, Write};
+use std::sync::mpsc;
+use std::thread;
+
+use rayon::prelude::*;
+
+use crate::config::EngineConfig;
+use crate::engine::{AccountOrdering, PaymentsEngine};
+use crate::error::Result;
+use crate::models::{Account, Transaction, TransactionType};
+use crate::output::{CsvSink, OutputSink};
+use crate::tx_store::TxIdSet;
+use crate::CsvRows;
+
+/// Apply CSV transactions on a rayon thread pool, one engine per partition
+///
+/// The input is read in full and split by client into `partitions` lists
+/// (client ID modulo `partitions`), keeping each client's transactions in
+/// input order. Every list is then processed by its own `PaymentsEngine` on
+/// the global rayon pool. Clients never share a partition, so the engines
+/// together hold the same accounts a single engine would.
+///
+/// Transaction IDs are unique across the whole input, not per partition:
+/// deposits and withdrawals reusing an ID are dropped while splitting, just
+/// as a single engine would reject them. Malformed rows are skipped.
+///
+/// Meant for offline batches that fit in memory; see `pipeline::ingest` for
+/// streams.
+///
+/// # Panics
+///
+/// Panics if `partitions` is zero.
+pub fn apply_transactions_parallel<R: Read>(reader: R, partitions: usize) -> Vec<PaymentsEngine> {
+    assert!(partitions > 0, "at least one partition is required");
+
+    let config = EngineConfig::default();
+    let mut lists: Vec<Vec<Transaction>> = vec![Vec::new(); partitions];
+    let mut claimed_tx_ids = TxIdSet::new();
+
+    for tx in CsvRows::new(reader) {
+        // A single engine uses up an ID once the amount passes validation
+        if matches!(
+            tx.tx_type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        ) {
+            if claimed_tx_ids.contains(tx.tx) {
+                continue;
+            }
+            if config.check_amount(tx.amount).is_ok() {
+                claimed_tx_ids.insert(tx.tx);
+            }
+        }
+        lists[tx.client as usize % partitions].push(tx);
+    }
+
+    lists
+        .into_par_iter()
+        .map(|transactions| {
+            let mut engine = PaymentsEngine::with_account_ordering(AccountOrdering::ByClientId);
+            let deposits = transactions
+                .iter()
+                .filter(|tx| tx.tx_type == TransactionType::Deposit)
+                .count();
+            engine.reserve(0, deposits);
+            for tx in transactions {
+                engine.process_transaction(tx);
+            }
+            engine
+        })
+        .collect()
+}
+
+/// Parallel counterpart of `process_transactions`
+///
+/// Applies the transactions with `apply_transactions_parallel` and writes
+/// the resulting accounts as CSV, sorted by client ID.
+///
+/// # Panics
+///
+/// Panics if `partitions` is zero.
+pub fn process_transactions_parallel<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    partitions: usize,
+) -> Result<()> {
+    let engines = apply_transactions_parallel(reader, partitions);
+
+    let mut sink = CsvSink::new(writer);
+    for account in merge_accounts(engines) {
+        sink.write_account(&account)?;
+    }
+    sink.finish()
+}
+
+/// All accounts held by partitioned engines, sorted by client ID
+pub fn merge_accounts(engines: Vec<PaymentsEngine>) -> Vec<Account> {
+    let mut accounts: Vec<Account> = engines
+        .into_iter()
+        .flat_map(PaymentsEngine::into_accounts_iter)
+        .collect();
+    accounts.sort_by_key(|a| a.client_id);
+    accounts
+}
+
+/// Input bytes parsed as one unit by `apply_transactions_chunked`
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Parsed rounds of chunks waiting for the engine; bounds the memory the
+/// parser can get ahead by
+const PARSED_ROUNDS_QUEUED: usize = 2;
+
+/// Apply CSV transactions to `engine`, parsing on other threads while the
+/// engine applies
+///
+/// The input is cut into chunks of about a megabyte at line boundaries. The
+/// calling thread reads a round of chunks, one per rayon thread, and parses
+/// them in parallel on the rayon pool, while a separate thread applies the
+/// previous rounds to the engine in input order. Parsing is usually the
+/// larger share of a batch's work, so overlapping it with the single-threaded
+/// apply roughly doubles throughput on multi-core machines.
+///
+/// The result is the same as applying the rows one at a time: malformed rows
+/// are skipped and reading stops at the first I/O error. Inputs that fit in
+/// one chunk are handled inline without starting any threads. Rows are split
+/// at newlines, so quoted fields spanning several lines are not supported;
+/// transaction fields never contain newlines.
+pub fn apply_transactions_chunked<R: Read>(engine: &mut PaymentsEngine, reader: R) {
+    let mut chunks = Chunks {
+        reader,
+        carry: Vec::new(),
+        done: false,
+    };
+    let Some(first) = chunks.next() else {
+        return;
+    };
+
+    // The header line comes with the first chunk; later chunks borrow it
+    let first_rows = CsvRows::new(&first[..]);
+    if chunks.done {
+        for tx in first_rows {
+            engine.process_transaction(tx);
+        }
+        return;
+    }
+    let headers = first_rows.headers().clone();
+    let first_batch: Vec<Transaction> = first_rows.collect();
+
+    let chunks_per_round = rayon::current_num_threads().max(1);
+    let (parsed, rounds) = mpsc::sync_channel::<Vec<Vec<Transaction>>>(PARSED_ROUNDS_QUEUED);
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for round in rounds {
+                for tx in round.into_iter().flatten() {
+                    engine.process_transaction(tx);
+                }
+            }
+        });
+
+        // Dropping the sender at the end of this block lets the applier finish
+        let parsed = parsed;
+        if parsed.send(vec![first_batch]).is_err() {
+            return;
+        }
+        loop {
+            let round: Vec<Vec<u8>> = chunks.by_ref().take(chunks_per_round).collect();
+            if round.is_empty() {
+                break;
+            }
+            let batches = round
+                .into_par_iter()
+                .map(|chunk| CsvRows::with_headers(&chunk[..], headers.clone()).collect())
+                .collect();
+            if parsed.send(batches).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Input split into chunks of about `CHUNK_SIZE` bytes ending at a newline
+struct Chunks<R> {
+    reader: R,
+    /// Start of the next chunk: bytes read past the last newline of the previous one
+    carry: Vec<u8>,
+    /// Whether the input is exhausted
+    done: bool,
+}
+
+impl<R: Read> Iterator for Chunks<R> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let mut chunk = std::mem::take(&mut self.carry);
+        while !self.done {
+            let wanted = CHUNK_SIZE.saturating_sub(chunk.len()).max(8 * 1024) as u64;
+            match (&mut self.reader).take(wanted).read_to_end(&mut chunk) {
+                Ok(read) if (read as u64) < wanted => self.done = true,
+                Ok(_) => {}
+                Err(_) => {
+                    // Like the CSV reader, stop at an I/O error and drop the
+                    // partial line it cut short
+                    self.done = true;
+                    let complete = chunk.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+                    chunk.truncate(complete);
+                }
+            }
+
+            if !self.done && chunk.len() >= CHUNK_SIZE {
+                if let Some(newline) = chunk.iter().rposition(|&b| b == b'\n') {
+                    self.carry = chunk.split_off(newline + 1);
+                    break;
+                }
+                // One line longer than a chunk: keep reading until it ends
+            }
+        }
+        (!chunk.is_empty()).then_some(chunk)
+    }
+}
@@ -1,6 +1,23 @@
+use crate::concurrent_engine::shard_wal_paths;
 use crate::error::Result;
-use crate::models::Transaction;
+use crate::models::{ClientId, Transaction};
+use crate::state::EngineState;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use tokio::sync::oneshot;
+
+mod checkpoint;
+#[cfg(feature = "cluster")]
+pub mod replicated;
+#[cfg(feature = "cluster")]
+pub mod shipping;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
 
 /// Persistence backend for crash recovery
 ///
@@ -29,17 +46,11 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 ///
 /// ```no_run
 /// use payments_engine::persistence::{PersistenceBackend, StubPersistence};
-/// use payments_engine::models::{Transaction, TransactionType};
-/// use rust_decimal_macros::dec;
+/// use payments_engine::models::Transaction;
 ///
 /// let mut persistence = StubPersistence::new();
 ///
-/// let tx = Transaction {
-///     tx_type: TransactionType::Deposit,
-///     client: 1,
-///     tx: 1,
-///     amount: Some(dec!(100.0)),
-/// };
+/// let tx = Transaction::deposit(1, 1, "100.0".parse().unwrap());
 ///
 /// // In production, this would write to disk + fsync
 /// persistence.append(&tx).unwrap();
@@ -80,6 +91,174 @@ pub trait PersistenceBackend: Send + Sync {
     ///
     /// Vector of all transactions in the log, in order
     fn replay(&self) -> Result<Vec<Transaction>>;
+
+    /// Append a redaction record for a client erased with
+    /// `PersistentEngine::erase_client`
+    ///
+    /// Replaying the log erases the client again at the same point, so a
+    /// recovered engine doesn't bring their data back. The default fails
+    /// with `Unsupported`, for backends that can't store redactions.
+    fn append_redaction(&mut self, client_id: ClientId) -> Result<()> {
+        let _ = client_id;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "persistence backend can't store redaction records",
+        )
+        .into())
+    }
+
+    /// Append a digest of the engine's state at this point in the log, from
+    /// `PaymentsEngine::state_digest`
+    ///
+    /// Recovery can compare it to the state it rebuilt up to here; see
+    /// `persistent_engine::IntegrityCheck`. The default drops it, for
+    /// backends that can't store digests.
+    fn append_digest(&mut self, digest: u64) -> Result<()> {
+        let _ = digest;
+        Ok(())
+    }
+
+    /// Replay all entries, redaction records included, in log order
+    ///
+    /// The default wraps every transaction from `replay`, for backends
+    /// without redaction records.
+    fn replay_entries(&self) -> Result<Vec<LogEntry>> {
+        Ok(self
+            .replay()?
+            .into_iter()
+            .map(LogEntry::Transaction)
+            .collect())
+    }
+
+    /// Append a transaction without waiting for it to become durable
+    ///
+    /// The transaction is queued in log order before this returns, so the
+    /// caller can go on to process it; the returned handle resolves once it
+    /// is as durable as the backend makes it. The default appends in place
+    /// and hands back an already completed handle.
+    fn append_pipelined(&mut self, tx: &Transaction) -> Result<CommitHandle> {
+        self.append(tx)?;
+        Ok(CommitHandle::completed())
+    }
+
+    /// Make sure every appended transaction has reached durable storage
+    ///
+    /// Called on shutdown. Backends that sync on every append have nothing to do.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Bytes in the live log, for `CheckpointPolicy::max_log_bytes`
+    ///
+    /// `None` for backends that can't tell, which never reach that limit.
+    fn log_size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Save `state`, which reflects every entry appended so far, and drop
+    /// those entries from the log
+    ///
+    /// From then on `load_checkpoint` returns `state` and `replay_entries`
+    /// only what is appended afterwards. The default fails with
+    /// `Unsupported`, for backends that can't store snapshots.
+    fn checkpoint(&mut self, state: &EngineState) -> Result<()> {
+        let _ = state;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "persistence backend can't store checkpoints",
+        )
+        .into())
+    }
+
+    /// State saved by the latest `checkpoint`, `None` if there was none
+    fn load_checkpoint(&self) -> Result<Option<EngineState>> {
+        Ok(None)
+    }
+}
+
+/// One entry of a write-ahead log
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogEntry {
+    Transaction(Transaction),
+    /// The client was erased from here on
+    Redaction(ClientId),
+    /// The engine's state up to here had this `state_digest`
+    Digest(u64),
+}
+
+/// A redaction record as written to a log file, e.g. `{"redact":7}`
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RedactionLine {
+    redact: ClientId,
+}
+
+/// A state digest record as written to a log file, e.g. `{"digest":42}`
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DigestLine {
+    digest: u64,
+}
+
+/// Result a background writer reports for a batch
+type CommitResult = std::result::Result<(), Arc<io::Error>>;
+
+/// Completion handle for a pipelined append (see
+/// `PersistenceBackend::append_pipelined`)
+///
+/// Dropping it doesn't cancel the append, it only stops waiting for it.
+#[must_use = "dropping a CommitHandle doesn't wait for durability"]
+pub struct CommitHandle {
+    receiver: Option<oneshot::Receiver<CommitResult>>,
+}
+
+impl CommitHandle {
+    /// Handle for an append that is already as durable as it will get
+    pub fn completed() -> Self {
+        Self { receiver: None }
+    }
+
+    /// Whether the append was already complete when the handle was made
+    pub fn is_completed(&self) -> bool {
+        self.receiver.is_none()
+    }
+
+    /// Wait until the append is durable
+    ///
+    /// `Err` if writing or syncing the batch holding it failed, or if the
+    /// writer stopped before getting to it.
+    pub async fn wait(self) -> Result<()> {
+        match self.receiver {
+            None => Ok(()),
+            Some(receiver) => commit_result(receiver.await.ok()),
+        }
+    }
+
+    /// Blocking counterpart of `wait`
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within an async context; use `wait` there.
+    pub fn wait_blocking(self) -> Result<()> {
+        match self.receiver {
+            None => Ok(()),
+            Some(receiver) => commit_result(receiver.blocking_recv().ok()),
+        }
+    }
+}
+
+/// Turn a writer's report, or its absence, into the caller's result
+fn commit_result(result: Option<CommitResult>) -> Result<()> {
+    match result {
+        Some(Ok(())) => Ok(()),
+        // Every waiter on a failed batch gets its own copy of the error
+        Some(Err(e)) => Err(io::Error::new(e.kind(), e.to_string()).into()),
+        None => Err(writer_stopped().into()),
+    }
+}
+
+fn writer_stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "WAL writer stopped")
 }
 
 /// Stub persistence implementation for demonstration
@@ -117,17 +296,11 @@ pub trait PersistenceBackend: Send + Sync {
 ///
 /// ```
 /// use payments_engine::persistence::{PersistenceBackend, StubPersistence};
-/// use payments_engine::models::{Transaction, TransactionType};
-/// use rust_decimal_macros::dec;
+/// use payments_engine::models::Transaction;
 ///
 /// let mut persistence = StubPersistence::new();
 ///
-/// let tx = Transaction {
-///     tx_type: TransactionType::Deposit,
-///     client: 1,
-///     tx: 1,
-///     amount: Some(dec!(100.0)),
-/// };
+/// let tx = Transaction::deposit(1, 1, "100.0".parse().unwrap());
 ///
 /// // Logs what would be persisted
 /// persistence.append(&tx).unwrap();
@@ -200,4 +373,599 @@ impl PersistenceBackend for StubPersistence {
 
         Ok(Vec::new()) // Stub returns empty - simulates fresh start
     }
+
+    fn append_redaction(&mut self, client_id: ClientId) -> Result<()> {
+        // Production would write a redaction line, see `FilePersistence`
+        let _ = client_id;
+        Ok(())
+    }
+}
+
+/// Write-ahead log in a file, one JSON transaction per line
+///
+/// A client erased later gets a `{"redact":<client>}` line. The log is
+/// append-only, so their earlier lines stay in the file until it is
+/// replaced, e.g. by starting a new log after saving a snapshot. State
+/// digests are `{"digest":<hash>}` lines.
+///
+/// Each append reaches the operating system before the transaction is
+/// processed, so the log survives the process crashing; `flush` syncs it to
+/// disk to survive the machine going down too.
+///
+/// A crash mid-append can leave a partial last line. `open` cuts it off, so
+/// the transaction it held counts as never having been accepted.
+///
+/// `checkpoint` saves a snapshot next to the log, in `<path>.snapshot`, and
+/// starts the log over; see `CheckpointPolicy` for having `PersistentEngine`
+/// do it on its own.
+///
+/// # Example
+///
+/// ```no_run
+/// use payments_engine::persistence::FilePersistence;
+/// use payments_engine::persistent_engine::PersistentEngine;
+///
+/// let persistence = FilePersistence::open("transactions.log").unwrap();
+/// let engine = PersistentEngine::recover(persistence).unwrap();
+/// ```
+pub struct FilePersistence {
+    path: PathBuf,
+    file: File,
+    /// Bytes in the live log
+    len: u64,
+}
+
+impl FilePersistence {
+    /// Open the log at `path`, creating it if it doesn't exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = open_log(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { path, file, len })
+    }
+
+    /// Path of the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl PersistenceBackend for FilePersistence {
+    fn append(&mut self, tx: &Transaction) -> Result<()> {
+        let mut line = serde_json::to_vec(tx)?;
+        line.push(b'\n');
+        // One write per entry, so a crash can only tear the last line
+        self.file.write_all(&line)?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    fn replay(&self) -> Result<Vec<Transaction>> {
+        replay_transactions(&self.path)
+    }
+
+    fn append_redaction(&mut self, client_id: ClientId) -> Result<()> {
+        let line = redaction_line(client_id)?;
+        self.file.write_all(&line)?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    fn append_digest(&mut self, digest: u64) -> Result<()> {
+        let line = digest_line(digest)?;
+        self.file.write_all(&line)?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    fn replay_entries(&self) -> Result<Vec<LogEntry>> {
+        checkpoint::uncovered_entries(&self.path)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    fn log_size(&self) -> Option<u64> {
+        Some(self.len)
+    }
+
+    fn checkpoint(&mut self, state: &EngineState) -> Result<()> {
+        self.file.sync_data()?;
+        let segment = checkpoint::next_segment(&self.path)?;
+        checkpoint::seal(&self.path, segment)?;
+        self.file = open_log(&self.path)?;
+        self.len = 0;
+        checkpoint::save_snapshot(&self.path, segment, state)
+    }
+
+    fn load_checkpoint(&self) -> Result<Option<EngineState>> {
+        Ok(checkpoint::load_snapshot(&self.path)?.map(|(_, state)| state))
+    }
+}
+
+/// Most entries a background writer puts in one write and sync
+const MAX_BATCH: usize = 1024;
+
+/// Work queued for a background writer
+enum WriterRequest {
+    /// Append a serialized entry, reporting once it is synced
+    Append {
+        line: Vec<u8>,
+        done: oneshot::Sender<CommitResult>,
+    },
+    /// Report once everything queued before has been synced
+    Sync { done: mpsc::Sender<CommitResult> },
+}
+
+/// Write-ahead log in a file, same format as `FilePersistence`, written and
+/// synced by a background thread
+///
+/// Appends are queued for the writer thread, which takes whatever has piled
+/// up since its last sync, writes it in one go and syncs it with a single
+/// `fsync`. `append_pipelined` hands back a `CommitHandle` for the entry, so
+/// the caller can process the transaction while it is being written and only
+/// acknowledge it once the handle resolves. Under load many appends share one
+/// sync, and the disk latency of one shard overlaps with processing on
+/// others.
+///
+/// The engine can see a transaction before its entry is durable. If the log
+/// can't be written or synced, the handles of that batch fail and the writer
+/// stops, so every later append fails before its transaction is processed.
+///
+/// With the `io-uring` feature on Linux, each batch's write and the sync
+/// linked after it go to the kernel as one io_uring submission. Kernels that
+/// refuse to set up a ring get the plain `write` and `fdatasync` path.
+///
+/// Checkpoints are kept as for `FilePersistence`. `checkpoint` waits for
+/// everything queued before it and starts a new writer on the new log.
+///
+/// # Example
+///
+/// ```no_run
+/// use payments_engine::persistence::{BackgroundPersistence, PersistenceBackend};
+/// use payments_engine::models::Transaction;
+///
+/// let mut persistence = BackgroundPersistence::open("transactions.log").unwrap();
+/// let tx = Transaction::deposit(1, 1, "100.0".parse().unwrap());
+///
+/// let commit = persistence.append_pipelined(&tx).unwrap();
+/// // ... process the transaction ...
+/// commit.wait_blocking().unwrap();
+/// ```
+pub struct BackgroundPersistence {
+    path: PathBuf,
+    requests: Option<mpsc::Sender<WriterRequest>>,
+    writer: Option<JoinHandle<()>>,
+    uses_io_uring: bool,
+    /// Bytes queued for the live log
+    len: u64,
+}
+
+impl BackgroundPersistence {
+    /// Open the log at `path`, creating it if it doesn't exist, and start its
+    /// writer thread
+    ///
+    /// A partial last line is dropped as in `FilePersistence::open`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut persistence = Self {
+            path: path.as_ref().to_path_buf(),
+            requests: None,
+            writer: None,
+            uses_io_uring: false,
+            len: 0,
+        };
+        persistence.start_writer()?;
+        Ok(persistence)
+    }
+
+    /// Open the live log and start a writer thread on it
+    fn start_writer(&mut self) -> Result<()> {
+        let file = open_log(&self.path)?;
+        self.len = file.metadata()?.len();
+        let sink = BatchSink::new(file);
+        self.uses_io_uring = sink.uses_io_uring();
+        let (requests, queue) = mpsc::channel();
+        let writer = thread::Builder::new()
+            .name("wal-writer".to_string())
+            .spawn(move || write_batches(sink, queue))?;
+
+        self.requests = Some(requests);
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    /// Let the writer finish what is queued, then wait for it to exit
+    fn stop_writer(&mut self) {
+        drop(self.requests.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+
+    /// Path of the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether batches go through io_uring rather than plain system calls
+    ///
+    /// Always `false` without the `io-uring` feature.
+    pub fn uses_io_uring(&self) -> bool {
+        self.uses_io_uring
+    }
+
+    fn queue_line(&mut self, line: Vec<u8>) -> Result<CommitHandle> {
+        let (done, receiver) = oneshot::channel();
+        let len = line.len() as u64;
+        self.send(WriterRequest::Append { line, done })?;
+        self.len += len;
+        Ok(CommitHandle {
+            receiver: Some(receiver),
+        })
+    }
+
+    fn send(&self, request: WriterRequest) -> Result<()> {
+        self.requests
+            .as_ref()
+            .and_then(|requests| requests.send(request).ok())
+            .ok_or_else(|| writer_stopped().into())
+    }
+}
+
+impl PersistenceBackend for BackgroundPersistence {
+    /// Queue the entry without waiting for it; `flush` waits for everything
+    fn append(&mut self, tx: &Transaction) -> Result<()> {
+        // The writer still syncs it, nobody waits for the report
+        drop(self.append_pipelined(tx)?);
+        Ok(())
+    }
+
+    fn append_pipelined(&mut self, tx: &Transaction) -> Result<CommitHandle> {
+        let mut line = serde_json::to_vec(tx)?;
+        line.push(b'\n');
+        self.queue_line(line)
+    }
+
+    fn replay(&self) -> Result<Vec<Transaction>> {
+        replay_transactions(&self.path)
+    }
+
+    /// Queue the record and wait until it is synced
+    fn append_redaction(&mut self, client_id: ClientId) -> Result<()> {
+        self.queue_line(redaction_line(client_id)?)?.wait_blocking()
+    }
+
+    /// Queue the record without waiting for it, like a transaction
+    fn append_digest(&mut self, digest: u64) -> Result<()> {
+        drop(self.queue_line(digest_line(digest)?)?);
+        Ok(())
+    }
+
+    fn replay_entries(&self) -> Result<Vec<LogEntry>> {
+        checkpoint::uncovered_entries(&self.path)
+    }
+
+    /// Block until every entry appended so far is synced
+    fn flush(&mut self) -> Result<()> {
+        let (done, report) = mpsc::channel();
+        self.send(WriterRequest::Sync { done })?;
+        commit_result(report.recv().ok())
+    }
+
+    fn log_size(&self) -> Option<u64> {
+        Some(self.len)
+    }
+
+    fn checkpoint(&mut self, state: &EngineState) -> Result<()> {
+        self.flush()?;
+        self.stop_writer();
+        let segment = checkpoint::next_segment(&self.path)?;
+        checkpoint::seal(&self.path, segment)?;
+        self.start_writer()?;
+        checkpoint::save_snapshot(&self.path, segment, state)
+    }
+
+    fn load_checkpoint(&self) -> Result<Option<EngineState>> {
+        Ok(checkpoint::load_snapshot(&self.path)?.map(|(_, state)| state))
+    }
+}
+
+impl Drop for BackgroundPersistence {
+    fn drop(&mut self) {
+        self.stop_writer();
+    }
+}
+
+/// Where a background writer puts its batches
+enum BatchSink {
+    File(File),
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    Uring(Box<uring::UringWriter>),
+}
+
+impl BatchSink {
+    #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+    fn new(file: File) -> Self {
+        Self::File(file)
+    }
+
+    /// Use io_uring if the kernel lets us set up a ring
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    fn new(file: File) -> Self {
+        match uring::UringWriter::new(file) {
+            Ok(writer) => Self::Uring(Box::new(writer)),
+            Err(file) => Self::File(file),
+        }
+    }
+
+    fn uses_io_uring(&self) -> bool {
+        !matches!(self, Self::File(_))
+    }
+
+    /// Append `batch` to the log and sync it
+    fn write_and_sync(&mut self, batch: &[u8]) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.write_all(batch).and_then(|()| file.sync_data()),
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Self::Uring(writer) => writer.write_and_sync(batch),
+        }
+    }
+}
+
+/// Background writer loop: write and sync queued entries a batch at a time
+/// until the queue closes or a write fails
+fn write_batches(mut sink: BatchSink, queue: mpsc::Receiver<WriterRequest>) {
+    let mut buffer = Vec::new();
+    let mut appended = Vec::new();
+    let mut synced = Vec::new();
+
+    while let Ok(first) = queue.recv() {
+        // Take whatever else is already waiting, up to a batch
+        for request in std::iter::once(first).chain(queue.try_iter().take(MAX_BATCH - 1)) {
+            match request {
+                WriterRequest::Append { line, done } => {
+                    buffer.extend_from_slice(&line);
+                    appended.push(done);
+                }
+                WriterRequest::Sync { done } => synced.push(done),
+            }
+        }
+
+        // Entries are whole lines, so a crash can only tear the last one
+        let result = sink.write_and_sync(&buffer).map_err(Arc::new);
+        buffer.clear();
+
+        // Senders whose handle was dropped just aren't waited on
+        for done in appended.drain(..) {
+            let _ = done.send(result.clone());
+        }
+        for done in synced.drain(..) {
+            let _ = done.send(result.clone());
+        }
+
+        if result.is_err() {
+            // What reached the file is unknown, so nothing more may follow
+            return;
+        }
+    }
+}
+
+/// Open a log for appending and cut off a partial line left by a crash
+/// mid-append
+fn open_log(path: &Path) -> Result<File> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)?;
+
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    if !contents.is_empty() && !contents.ends_with(b"\n") {
+        let complete = contents
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        file.set_len(complete as u64)?;
+    }
+
+    Ok(file)
+}
+
+/// A redaction record as a log line
+fn redaction_line(client_id: ClientId) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(&RedactionLine { redact: client_id })?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// A state digest record as a log line
+fn digest_line(digest: u64) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(&DigestLine { digest })?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Read every entry from a log of JSON lines
+fn replay_log(path: &Path) -> Result<Vec<LogEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        entries.push(decode_log_line(&line?)?);
+    }
+    Ok(entries)
+}
+
+/// Read every entry of the log at `path` without opening it for writing
+///
+/// A partial last line, left by a crash mid-append, is ignored, as opening
+/// the log for recovery would cut it off.
+pub fn read_log(path: impl AsRef<Path>) -> Result<Vec<LogEntry>> {
+    let contents = std::fs::read(path)?;
+    let complete = contents
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    String::from_utf8_lossy(&contents[..complete])
+        .lines()
+        .map(decode_log_line)
+        .collect()
+}
+
+/// Read the log at `path` the way recovery would, without opening it for
+/// writing: the state its last checkpoint saved, if it was checkpointed,
+/// and every entry logged after that
+///
+/// A partial last line is ignored, as in `read_log`.
+pub fn read_checkpointed_log(
+    path: impl AsRef<Path>,
+) -> Result<(Option<EngineState>, Vec<LogEntry>)> {
+    checkpoint::read(path.as_ref())
+}
+
+/// What `migrate_log` rewrote
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Migration {
+    /// Logs looked at
+    pub logs: usize,
+    /// Checkpoint snapshots written by an earlier version, now in the
+    /// current format
+    pub snapshots: usize,
+    /// Log lines that read back differently when encoded again, now written
+    /// the current way
+    pub lines: usize,
+}
+
+/// Rewrite a write-ahead log written by an earlier version in the current
+/// format, so this and later versions keep reading it
+///
+/// `path` is a log file or a `ShardedEngine` WAL directory, whose shards'
+/// logs are each migrated. A checkpointed log's snapshot is upgraded from
+/// any earlier version (see `EngineState::from_any_snapshot_bytes`); every
+/// line of the live log and its sealed segments is decoded and encoded
+/// again. Files already in the current format are left alone, so migrating
+/// twice changes nothing. A partial last line, which recovery would cut
+/// off, is dropped.
+///
+/// Nothing may be writing to the logs meanwhile. Every log line is decoded
+/// before anything is written, so a line that doesn't decode fails the
+/// migration with the files untouched; each file is then replaced
+/// atomically.
+pub fn migrate_log(path: impl AsRef<Path>) -> Result<Migration> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        return migrate_one_log(path);
+    }
+    let mut migration = Migration::default();
+    for log in shard_wal_paths(path) {
+        let shard = migrate_one_log(&log)?;
+        migration.logs += shard.logs;
+        migration.snapshots += shard.snapshots;
+        migration.lines += shard.lines;
+    }
+    Ok(migration)
+}
+
+fn migrate_one_log(path: &Path) -> Result<Migration> {
+    let mut migration = Migration {
+        logs: 1,
+        ..Migration::default()
+    };
+    let mut rewrites = Vec::new();
+    for file in checkpoint::segment_paths(path)?
+        .into_iter()
+        .chain([path.to_path_buf()])
+    {
+        let contents = match std::fs::read(&file) {
+            Ok(contents) => contents,
+            // A log that was just checkpointed may not have been created again
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let complete = contents
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let mut encoded = Vec::with_capacity(complete);
+        let mut changed = 0;
+        for line in contents[..complete].split_inclusive(|&b| b == b'\n') {
+            let line = &line[..line.len() - 1];
+            let entry = decode_log_line(&String::from_utf8_lossy(line))?;
+            let start = encoded.len();
+            encoded.extend(encode_log_line(&entry)?);
+            if encoded[start..encoded.len() - 1] != *line {
+                changed += 1;
+            }
+        }
+        if complete < contents.len() {
+            log::warn!("Dropping the partial last line of '{}'", file.display());
+        } else if changed == 0 {
+            continue;
+        }
+        migration.lines += changed;
+        rewrites.push((file, encoded));
+    }
+
+    if checkpoint::upgrade_snapshot(path)?.is_some() {
+        migration.snapshots += 1;
+    }
+    for (file, contents) in rewrites {
+        replace_log(&file, &contents)?;
+    }
+    Ok(migration)
+}
+
+/// Replace the log file at `path` with `contents` through a temporary
+/// sibling file
+fn replace_log(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Encode a log entry as a line of a log file
+fn encode_log_line(entry: &LogEntry) -> Result<Vec<u8>> {
+    match entry {
+        LogEntry::Transaction(tx) => {
+            let mut line = serde_json::to_vec(tx)?;
+            line.push(b'\n');
+            Ok(line)
+        }
+        LogEntry::Redaction(client_id) => redaction_line(*client_id),
+        LogEntry::Digest(digest) => digest_line(*digest),
+    }
+}
+
+/// Decode one line of a log file: a JSON transaction, redaction or digest
+/// record
+///
+/// Fails on anything else, such as a line torn by a crash mid-write.
+pub fn decode_log_line(line: &str) -> Result<LogEntry> {
+    if let Ok(redaction) = serde_json::from_str::<RedactionLine>(line) {
+        return Ok(LogEntry::Redaction(redaction.redact));
+    }
+    if let Ok(digest) = serde_json::from_str::<DigestLine>(line) {
+        return Ok(LogEntry::Digest(digest.digest));
+    }
+    Ok(LogEntry::Transaction(serde_json::from_str(line)?))
+}
+
+/// Read every transaction from a log of JSON lines that its checkpoint
+/// doesn't cover, skipping redaction and digest records
+fn replay_transactions(path: &Path) -> Result<Vec<Transaction>> {
+    Ok(checkpoint::uncovered_entries(path)?
+        .into_iter()
+        .filter_map(|entry| match entry {
+            LogEntry::Transaction(tx) => Some(tx),
+            LogEntry::Redaction(_) | LogEntry::Digest(_) => None,
+        })
+        .collect())
 }
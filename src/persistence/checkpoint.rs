@@ -0,0 +1,190 @@
+//! Snapshots that let a log start over
+//!
+//! Checkpointing a log at `wal.log` goes in three steps:
+//!
+//! 1. The log so far is sealed: renamed to `wal.log.<n>`, with a new, empty
+//!    `wal.log` taking the entries that follow.
+//! 2. The engine's state, which covers everything up to the end of the sealed
+//!    segment, is written to `wal.log.snapshot` along with `n`.
+//! 3. The sealed segment, and any earlier one left behind, is deleted.
+//!
+//! A crash between any two steps leaves files recovery can still make sense
+//! of: a snapshot covers the segments numbered up to its own, and the rest
+//! are replayed in order before the live log.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{EngineError, Result};
+use crate::state::{EngineState, SNAPSHOT_VERSION};
+
+use super::{read_log, LogEntry};
+
+/// Bytes before the encoded state: the number of the last covered segment
+const HEADER_LEN: usize = 8;
+
+/// Path of the snapshot kept next to the log at `path`
+fn snapshot_path(path: &Path) -> PathBuf {
+    sibling(path, "snapshot")
+}
+
+/// Path of sealed segment `segment` of the log at `path`
+fn segment_path(path: &Path, segment: u64) -> PathBuf {
+    sibling(path, &segment.to_string())
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.file_name().unwrap_or_default());
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Numbers of the sealed segments of the log at `path`, in order
+fn sealed_segments(path: &Path) -> Result<Vec<u64>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Some(prefix) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(Vec::new());
+    };
+
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let segment = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(prefix))
+            .and_then(|rest| rest.strip_prefix('.'))
+            .and_then(|number| number.parse::<u64>().ok());
+        segments.extend(segment);
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// Paths of the sealed segments of the log at `path`, in order
+pub(super) fn segment_paths(path: &Path) -> Result<Vec<PathBuf>> {
+    Ok(sealed_segments(path)?
+        .into_iter()
+        .map(|segment| segment_path(path, segment))
+        .collect())
+}
+
+/// The latest snapshot of the log at `path` and the last segment it covers,
+/// `None` if it was never checkpointed
+pub(super) fn load_snapshot(path: &Path) -> Result<Option<(u64, EngineState)>> {
+    let Some((segment, bytes)) = read_snapshot(path)? else {
+        return Ok(None);
+    };
+    Ok(Some((segment, EngineState::from_snapshot_bytes(&bytes)?)))
+}
+
+/// Rewrite the snapshot of the log at `path` in the current format if an
+/// earlier version wrote it, returning that version
+pub(super) fn upgrade_snapshot(path: &Path) -> Result<Option<u16>> {
+    let Some((segment, bytes)) = read_snapshot(path)? else {
+        return Ok(None);
+    };
+    let (version, state) = EngineState::from_any_snapshot_bytes(&bytes)?;
+    if version == SNAPSHOT_VERSION {
+        return Ok(None);
+    }
+    write_snapshot(path, segment, &state)?;
+    Ok(Some(version))
+}
+
+/// The last segment the snapshot of the log at `path` covers and its encoded
+/// state, `None` if there is no snapshot
+fn read_snapshot(path: &Path) -> Result<Option<(u64, Vec<u8>)>> {
+    let mut bytes = match fs::read(snapshot_path(path)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if bytes.len() < HEADER_LEN {
+        return Err(EngineError::InvalidSnapshot(
+            "checkpoint is truncated".to_string(),
+        ));
+    }
+
+    let state = bytes.split_off(HEADER_LEN);
+    let segment = u64::from_le_bytes(bytes.try_into().expect("header is 8 bytes"));
+    Ok(Some((segment, state)))
+}
+
+/// Number the next sealed segment of the log at `path` gets
+pub(super) fn next_segment(path: &Path) -> Result<u64> {
+    let covered = load_snapshot(path)?.map_or(0, |(segment, _)| segment);
+    let sealed = sealed_segments(path)?.last().copied().unwrap_or(0);
+    Ok(covered.max(sealed) + 1)
+}
+
+/// Entries the snapshot of the log at `path` doesn't cover: those of later
+/// sealed segments, then those of the live log
+pub(super) fn uncovered_entries(path: &Path) -> Result<Vec<LogEntry>> {
+    let covered = load_snapshot(path)?.map_or(0, |(segment, _)| segment);
+    let mut entries = sealed_entries(path, covered)?;
+    entries.extend(super::replay_log(path)?);
+    Ok(entries)
+}
+
+/// The snapshot of the log at `path`, if it has one, and the entries it
+/// doesn't cover, reading the live log without opening it for writing
+pub(super) fn read(path: &Path) -> Result<(Option<EngineState>, Vec<LogEntry>)> {
+    let snapshot = load_snapshot(path)?;
+    let covered = snapshot.as_ref().map_or(0, |(segment, _)| *segment);
+    let mut entries = sealed_entries(path, covered)?;
+    entries.extend(read_log(path)?);
+    Ok((snapshot.map(|(_, state)| state), entries))
+}
+
+/// Entries of the sealed segments of the log at `path` numbered after
+/// `covered`, in order
+fn sealed_entries(path: &Path, covered: u64) -> Result<Vec<LogEntry>> {
+    let mut entries = Vec::new();
+    for segment in sealed_segments(path)? {
+        if segment > covered {
+            entries.extend(read_log(segment_path(path, segment))?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Seal the live log at `path` as segment `segment`
+///
+/// The caller must have synced the log and opens a new one afterwards.
+pub(super) fn seal(path: &Path, segment: u64) -> Result<()> {
+    fs::rename(path, segment_path(path, segment))?;
+    Ok(())
+}
+
+/// Save `state`, covering the log at `path` up to the end of sealed segment
+/// `segment`, then delete the segments it covers
+pub(super) fn save_snapshot(path: &Path, segment: u64, state: &EngineState) -> Result<()> {
+    write_snapshot(path, segment, state)?;
+
+    for sealed in sealed_segments(path)? {
+        if sealed <= segment {
+            fs::remove_file(segment_path(path, sealed))?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace the snapshot of the log at `path` with `state`, covering it up to
+/// the end of sealed segment `segment`
+fn write_snapshot(path: &Path, segment: u64, state: &EngineState) -> Result<()> {
+    let snapshot = snapshot_path(path);
+    let tmp_path = sibling(&snapshot, "tmp");
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(&segment.to_le_bytes())?;
+    file.write_all(&state.to_snapshot_bytes()?)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, &snapshot)?;
+    Ok(())
+}
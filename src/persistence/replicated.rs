@@ -0,0 +1,439 @@
+//! Quorum replication of write-ahead logs across nodes
+//!
+//! A cluster is one leader, the server taking transactions, and any number
+//! of followers, each keeping a copy of the leader's per-shard WALs. The
+//! leader's shards log through `ReplicatedPersistence`, which sends every
+//! entry to the followers and only lets its transaction be processed once a
+//! majority of the cluster, the leader included, holds it. Three nodes keep
+//! taking transactions through the loss of any one of them, and lose no
+//! acknowledged transaction if it is the leader.
+//!
+//! A `Follower` lays its copies out the way `ShardedEngine::recover` reads
+//! them, so it takes over as leader by starting a server on its directory.
+//! That step is manual: there is no leader election, only the log
+//! replication half of Raft. Run a single leader at a time.
+//!
+//! # Protocol
+//!
+//! Newline-delimited over TCP. The leader opens one connection per shard and
+//! follower, saying which shard it is for and how many entries its log
+//! holds, e.g. `{"shard":0,"entries":12}`. The follower answers with how
+//! many it holds, `{"entries":10}`, or `{"error":...}`, and the leader sends
+//! the ones it is missing. From then on every entry is a line in the WAL
+//! format, answered with `ok` once the follower has synced it. A follower
+//! holding entries the leader doesn't has diverged and refuses the leader;
+//! its copy of that shard has to be removed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    decode_log_line, digest_line, open_log, read_log, redaction_line, CommitHandle, LogEntry,
+    PersistenceBackend,
+};
+use crate::concurrent_engine::shard_wal_path;
+use crate::error::{EngineError, Result};
+use crate::models::{ClientId, Transaction};
+
+/// How long a follower gets to connect or answer before it counts as down
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// First line on a connection, from the leader
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    shard: usize,
+    entries: u64,
+}
+
+/// The follower's answer to `Hello`
+#[derive(Serialize, Deserialize)]
+struct Progress {
+    entries: u64,
+}
+
+/// The follower's answer to `Hello` if it can't follow
+#[derive(Serialize)]
+struct Refusal {
+    error: String,
+}
+
+/// A shard's local log, replicated to followers before each entry counts as
+/// committed (see the module docs)
+///
+/// Every append waits for the followers' answers, so each transaction costs
+/// a network round trip on top of the local write. Followers that are down
+/// or too slow are skipped, and reconnected and brought up to date by a
+/// later append. If fewer than `quorum` nodes hold an entry, the append
+/// fails with `EngineError::Replication` and the transaction isn't
+/// processed. The entry stays in the logs that got it, though, and is
+/// applied if the engine is recovered from one of them; resubmitting the
+/// transaction under the same ID is safe either way, as the copy applied
+/// second is rejected as a duplicate.
+///
+/// # Example
+///
+/// ```no_run
+/// use payments_engine::persistence::replicated::ReplicatedPersistence;
+/// use payments_engine::persistence::FilePersistence;
+/// use payments_engine::persistent_engine::PersistentEngine;
+///
+/// let local = FilePersistence::open("wal.log").unwrap();
+/// let followers = vec!["10.0.0.2:7900".to_string(), "10.0.0.3:7900".to_string()];
+/// let persistence = ReplicatedPersistence::new(local, 0, followers).unwrap();
+/// let engine = PersistentEngine::recover(persistence).unwrap();
+/// ```
+pub struct ReplicatedPersistence<P> {
+    inner: P,
+    shard: usize,
+    followers: Vec<Link>,
+    /// Entries in the local log
+    entries: u64,
+    timeout: Duration,
+}
+
+/// The leader's side of one follower, for one shard
+struct Link {
+    addr: String,
+    /// `None` until connected, and again after the follower failed to answer
+    connection: Option<Connection>,
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl<P: PersistenceBackend> ReplicatedPersistence<P> {
+    /// Replicate `inner`, the local log of shard `shard`, to the followers at
+    /// `followers`
+    ///
+    /// Doesn't connect yet: the first append connects to each follower and
+    /// sends it whatever it is missing.
+    pub fn new(inner: P, shard: usize, followers: Vec<String>) -> Result<Self> {
+        let entries = inner.replay_entries()?.len() as u64;
+        Ok(Self {
+            inner,
+            shard,
+            followers: followers
+                .into_iter()
+                .map(|addr| Link {
+                    addr,
+                    connection: None,
+                })
+                .collect(),
+            entries,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Give followers `timeout` to connect or answer instead of
+    /// `DEFAULT_TIMEOUT`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Nodes, the leader included, that must hold an entry for it to count
+    /// as committed: a majority of the cluster
+    pub fn quorum(&self) -> usize {
+        majority(self.followers.len() + 1)
+    }
+
+    /// The local log
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Send an entry already in the local log to the followers and wait for
+    /// a quorum to hold it
+    fn replicate(&mut self, line: &[u8]) -> Result<()> {
+        let before = self.entries;
+        self.entries += 1;
+
+        // Send to every follower before waiting for any, so the round trips
+        // overlap
+        let mut sent = Vec::with_capacity(self.followers.len());
+        for index in 0..self.followers.len() {
+            match self.send(index, before, line) {
+                Ok(()) => sent.push(index),
+                Err(_) => self.followers[index].connection = None,
+            }
+        }
+
+        let mut holders = 1;
+        for index in sent {
+            let link = &mut self.followers[index];
+            let acked = link.connection.as_mut().map(Connection::expect_ack);
+            match acked {
+                Some(Ok(())) => holders += 1,
+                _ => link.connection = None,
+            }
+        }
+
+        if holders >= self.quorum() {
+            Ok(())
+        } else {
+            Err(EngineError::Replication(format!(
+                "entry {} of shard {} reached {} of {} nodes, {} needed",
+                before + 1,
+                self.shard,
+                holders,
+                self.followers.len() + 1,
+                self.quorum()
+            )))
+        }
+    }
+
+    /// Write a line to follower `index`, connecting first if needed; the
+    /// follower's log is brought up to `before` entries
+    fn send(&mut self, index: usize, before: u64, line: &[u8]) -> Result<()> {
+        if self.followers[index].connection.is_none() {
+            let connection = self.connect(&self.followers[index].addr.clone(), before)?;
+            self.followers[index].connection = Some(connection);
+        }
+        let connection = self.followers[index]
+            .connection
+            .as_mut()
+            .expect("connected above");
+        connection.writer.write_all(line)?;
+        Ok(())
+    }
+
+    /// Connect to the follower at `addr` and send it the first `entries`
+    /// entries it doesn't hold yet
+    fn connect(&mut self, addr: &str, entries: u64) -> Result<Connection> {
+        let socket = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no address for {}", addr),
+            )
+        })?;
+        let stream = TcpStream::connect_timeout(&socket, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+
+        let hello = Hello {
+            shard: self.shard,
+            entries,
+        };
+        connection.write_json(&hello)?;
+        let reply = connection.read_line()?;
+        let progress: Progress = serde_json::from_str(&reply).map_err(|_| {
+            EngineError::Replication(format!(
+                "{} refused shard {}: {}",
+                addr,
+                self.shard,
+                reply.trim_end()
+            ))
+        })?;
+
+        if progress.entries < entries {
+            // Everything queued must be in the file before it is read back
+            self.inner.flush()?;
+            let log = self.inner.replay_entries()?;
+            let missing = &log[progress.entries as usize..entries as usize];
+            let mut lines = Vec::new();
+            for entry in missing {
+                lines.extend(entry_line(entry)?);
+            }
+            connection.writer.write_all(&lines)?;
+            for _ in missing {
+                connection.expect_ack()?;
+            }
+        }
+        Ok(connection)
+    }
+}
+
+impl<P: PersistenceBackend> PersistenceBackend for ReplicatedPersistence<P> {
+    fn append(&mut self, tx: &Transaction) -> Result<()> {
+        self.inner.append(tx)?;
+        self.replicate(&transaction_line(tx)?)
+    }
+
+    fn replay(&self) -> Result<Vec<Transaction>> {
+        self.inner.replay()
+    }
+
+    fn append_redaction(&mut self, client_id: ClientId) -> Result<()> {
+        self.inner.append_redaction(client_id)?;
+        self.replicate(&redaction_line(client_id)?)
+    }
+
+    fn replay_entries(&self) -> Result<Vec<LogEntry>> {
+        self.inner.replay_entries()
+    }
+
+    /// Queue the entry locally and replicate it; the handle resolves once
+    /// the local copy is durable, the followers' already are
+    fn append_pipelined(&mut self, tx: &Transaction) -> Result<CommitHandle> {
+        let commit = self.inner.append_pipelined(tx)?;
+        self.replicate(&transaction_line(tx)?)?;
+        Ok(commit)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Connection {
+    fn write_json(&mut self, value: &impl Serialize) -> Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        Ok(())
+    }
+
+    /// Read a whole line; a connection closed mid-line is an error
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        if !line.ends_with('\n') {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(line)
+    }
+
+    fn expect_ack(&mut self) -> Result<()> {
+        let reply = self.read_line()?;
+        if reply.trim_end() == "ok" {
+            Ok(())
+        } else {
+            Err(EngineError::Replication(format!(
+                "follower failed: {}",
+                reply.trim_end()
+            )))
+        }
+    }
+}
+
+/// Keeps copies of a leader's per-shard WALs in a directory
+///
+/// Shard `i`'s copy is `dir/shard-<i>/wal.log`, as `ShardedEngine::recover`
+/// reads it.
+///
+/// ```no_run
+/// use payments_engine::persistence::replicated::Follower;
+///
+/// let follower = Follower::bind("0.0.0.0:7900", "wal").unwrap();
+/// follower.run().unwrap();
+/// ```
+pub struct Follower {
+    listener: TcpListener,
+    dir: PathBuf,
+    /// Held by the connection writing each shard's copy, so a leader that
+    /// reconnects waits for its old connection to wind down
+    shards: Mutex<HashMap<usize, Arc<Mutex<()>>>>,
+}
+
+impl Follower {
+    /// Listen for a leader on `addr`, keeping the copies in `dir`
+    pub fn bind(addr: impl ToSocketAddrs, dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            dir,
+            shards: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Take connections until accepting fails, each on its own thread
+    ///
+    /// A connection that breaks only ends itself; the leader reconnects.
+    pub fn run(self) -> Result<()> {
+        let follower = Arc::new(self);
+        for stream in follower.listener.incoming() {
+            let stream = stream?;
+            let follower = Arc::clone(&follower);
+            thread::spawn(move || follower.serve(stream));
+        }
+        Ok(())
+    }
+
+    /// Follow one shard over one connection until it closes
+    fn serve(&self, stream: TcpStream) -> Result<()> {
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        let hello: Hello = serde_json::from_str(&connection.read_line()?)?;
+
+        let lock = Arc::clone(
+            self.shards
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entry(hello.shard)
+                .or_default(),
+        );
+        let _writing = lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let path = shard_wal_path(&self.dir, hello.shard);
+        fs::create_dir_all(path.parent().expect("shard logs are in a directory"))?;
+        let mut file = open_log(&path)?;
+        let entries = read_log(&path)?.len() as u64;
+        if entries > hello.entries {
+            let error = format!(
+                "copy of shard {} holds {} entries, more than the leader's {}",
+                hello.shard, entries, hello.entries
+            );
+            connection.write_json(&Refusal {
+                error: error.clone(),
+            })?;
+            return Err(EngineError::Replication(error));
+        }
+        connection.write_json(&Progress { entries })?;
+
+        loop {
+            let line = match connection.read_line() {
+                Ok(line) => line,
+                // The leader went away, possibly mid-line
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            // Only ever write what reads back as a log entry
+            decode_log_line(line.trim_end())?;
+            file.write_all(line.as_bytes())?;
+            file.sync_data()?;
+            connection.writer.write_all(b"ok\n")?;
+        }
+    }
+}
+
+/// Nodes that make up a majority of a cluster of `nodes`
+pub fn majority(nodes: usize) -> usize {
+    nodes / 2 + 1
+}
+
+/// A transaction as a log line
+fn transaction_line(tx: &Transaction) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(tx)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn entry_line(entry: &LogEntry) -> Result<Vec<u8>> {
+    match entry {
+        LogEntry::Transaction(tx) => transaction_line(tx),
+        LogEntry::Redaction(client_id) => redaction_line(*client_id),
+        LogEntry::Digest(digest) => digest_line(*digest),
+    }
+}
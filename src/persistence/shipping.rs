@@ -0,0 +1,378 @@
+//! Asynchronous WAL shipping from a primary to read-only replicas
+//!
+//! The simpler alternative to `replicated`: the primary doesn't wait for
+//! anyone. A `WalShipper` next to it streams each shard's log to replicas as
+//! it is written, and a `Replica` applies the entries to its own engine,
+//! whose shards log them to a directory laid out like the primary's. The
+//! replica serves reads, a little behind the primary, and refuses
+//! transactions until it is promoted (see `request_promotion`). Entries the
+//! primary acknowledged but hadn't shipped yet are lost if it fails.
+//!
+//! # Protocol
+//!
+//! Newline-delimited over TCP. A replica opens one connection per shard and
+//! says which shard it follows and how many entries it holds, e.g.
+//! `{"shard":0,"entries":10}`. The shipper answers with the primary's shard
+//! count and entries so far, `{"shards":4,"entries":12}`, or `{"error":...}`,
+//! then sends every entry from the replica's on, as lines in the WAL format,
+//! and keeps sending new ones as they are written. An empty line is sent
+//! whenever the log has been idle for `HEARTBEAT_INTERVAL`, so each side
+//! notices if the other is gone. A replica holding entries the primary
+//! doesn't has diverged, e.g. a former primary, and is refused.
+
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{self, BufRead, BufReader};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, Lines};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::task::JoinSet;
+
+use super::{decode_log_line, read_log};
+use crate::concurrent_engine::{shard_wal_path, shard_wal_paths, ShardedEngine};
+use crate::error::{EngineError, Result};
+
+/// How long a log may be idle before the shipper sends an empty line
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long either side waits for a line before giving up on the connection
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the shipper looks for new entries in an idle log
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long a replica waits before reconnecting to the primary
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a replica checks whether it has been promoted
+const PROMOTION_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// File in a replica's directory asking it to become writable
+const PROMOTE_FILE: &str = "promote";
+
+/// First line on a connection, from the replica
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    shard: usize,
+    entries: u64,
+}
+
+/// The shipper's answer to `Hello`
+#[derive(Serialize, Deserialize)]
+struct Progress {
+    shards: usize,
+    entries: u64,
+}
+
+/// The shipper's answer to `Hello` if it can't ship to the replica
+#[derive(Serialize)]
+struct Refusal {
+    error: String,
+}
+
+/// Streams the per-shard WALs in a directory to replicas
+///
+/// Runs next to a primary started with `ShardedEngine::recover` on the same
+/// directory. It only reads the logs, so it can be started and stopped
+/// without touching the engine.
+///
+/// ```no_run
+/// use payments_engine::persistence::shipping::WalShipper;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let shipper = WalShipper::bind("0.0.0.0:7901", "wal").await.unwrap();
+/// shipper.run().await.unwrap();
+/// # }
+/// ```
+pub struct WalShipper {
+    listener: TcpListener,
+    dir: PathBuf,
+}
+
+impl WalShipper {
+    /// Listen for replicas on `addr`, shipping the logs in `dir`
+    pub async fn bind(addr: impl ToSocketAddrs, dir: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Ship to replicas until accepting a connection fails
+    ///
+    /// Each replica connection is served by its own task; one that breaks
+    /// only ends itself, and the replica reconnects.
+    pub async fn run(self) -> Result<()> {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            tokio::spawn(ship(stream, self.dir.clone()));
+        }
+    }
+}
+
+/// Ship one shard's log over one connection until it breaks
+async fn ship(stream: TcpStream, dir: PathBuf) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+    let hello: Hello = serde_json::from_str(&next_line(&mut lines).await?)?;
+
+    let shards = shard_wal_paths(&dir).len();
+    let path = shard_wal_path(&dir, hello.shard);
+    let entries = if hello.shard < shards {
+        read_log(&path)?.len() as u64
+    } else {
+        0
+    };
+    let refusal = if hello.shard >= shards {
+        Some(format!(
+            "no shard {}, the primary has {}",
+            hello.shard, shards
+        ))
+    } else if hello.entries > entries {
+        Some(format!(
+            "replica of shard {} holds {} entries, more than the primary's {}",
+            hello.shard, hello.entries, entries
+        ))
+    } else {
+        None
+    };
+    if let Some(error) = refusal {
+        writer.write_all(&json_line(&Refusal { error })?).await?;
+        return Ok(());
+    }
+    writer
+        .write_all(&json_line(&Progress { shards, entries })?)
+        .await?;
+
+    // Tail the log: whole lines are shipped as soon as they are written
+    let mut log = BufReader::new(File::open(&path)?);
+    let mut line = String::new();
+    let mut skipped = 0;
+    let mut idle = Duration::ZERO;
+    loop {
+        log.read_line(&mut line)?;
+        if line.ends_with('\n') {
+            if skipped < hello.entries {
+                skipped += 1;
+            } else {
+                writer.write_all(line.as_bytes()).await?;
+                idle = Duration::ZERO;
+            }
+            line.clear();
+            continue;
+        }
+
+        // At the end of the log, possibly partway through a line
+        if idle >= HEARTBEAT_INTERVAL {
+            writer.write_all(b"\n").await?;
+            idle = Duration::ZERO;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+        idle += POLL_INTERVAL;
+    }
+}
+
+/// How `Replica::run` ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicaExit {
+    /// The replica was promoted and takes transactions now
+    Promoted,
+    /// The replica was stopped and is still read-only
+    Stopped,
+}
+
+/// Keeps a sharded engine a copy of a primary's by applying the entries its
+/// `WalShipper` sends
+///
+/// The engine must have been recovered from `dir` with the primary's shard
+/// count and shard key, e.g. `ShardedEngine::recover_with_options`, so its
+/// shards log what they apply to a copy of the primary's logs. Each entry is
+/// applied to the shard it came from, bypassing rate limits, but limits in
+/// the engine's config still decide whether it is applied or rejected: give
+/// replicas the primary's config.
+///
+/// ```no_run
+/// use payments_engine::concurrent_engine::ShardedEngine;
+/// use payments_engine::persistence::shipping::Replica;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let engine = ShardedEngine::recover("wal", 4).unwrap();
+/// let replica = Replica::new(&engine, "10.0.0.1:7901", "wal");
+/// replica.run(std::future::pending()).await.unwrap();
+/// // Promoted: `engine` takes transactions now
+/// # }
+/// ```
+pub struct Replica {
+    engine: ShardedEngine,
+    primary: String,
+    dir: PathBuf,
+}
+
+impl Replica {
+    /// Follow the shipper at `primary` with `engine`, recovered from `dir`
+    pub fn new(engine: &ShardedEngine, primary: impl Into<String>, dir: impl AsRef<Path>) -> Self {
+        Self {
+            engine: engine.clone_handle(),
+            primary: primary.into(),
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Make the engine read-only and apply the primary's entries until
+    /// promoted or `stop` completes
+    ///
+    /// Lost connections are retried for as long as it runs. It fails if the
+    /// primary refuses it, e.g. because it has diverged, or if applying an
+    /// entry fails; the engine then stays read-only. Once promoted, the
+    /// engine takes transactions again. A promotion requested before this is
+    /// called takes effect at once.
+    pub async fn run(self, stop: impl Future<Output = ()>) -> Result<ReplicaExit> {
+        if !take_promotion(&self.dir)? {
+            self.engine.set_read_only(true).await;
+
+            let mut shards = JoinSet::new();
+            let num_shards = self.engine.num_shards().await;
+            for shard in 0..num_shards {
+                let entries = read_log(shard_wal_path(&self.dir, shard))?.len() as u64;
+                let follower = ShardFollower {
+                    engine: self.engine.clone_handle(),
+                    primary: self.primary.clone(),
+                    shard,
+                    num_shards,
+                    entries,
+                };
+                shards.spawn(follower.run());
+            }
+
+            tokio::pin!(stop);
+            loop {
+                tokio::select! {
+                    Some(error) = shards.join_next() => {
+                        return Err(error.expect("replica shard task panicked"));
+                    }
+                    () = &mut stop => return Ok(ReplicaExit::Stopped),
+                    () = tokio::time::sleep(PROMOTION_POLL_INTERVAL) => {
+                        if take_promotion(&self.dir)? {
+                            break;
+                        }
+                    }
+                }
+            }
+            // Dropping the tasks stops them between entries
+            shards.shutdown().await;
+        }
+
+        self.engine.set_read_only(false).await;
+        Ok(ReplicaExit::Promoted)
+    }
+}
+
+/// One shard of a `Replica`
+struct ShardFollower {
+    engine: ShardedEngine,
+    primary: String,
+    shard: usize,
+    num_shards: usize,
+    /// Entries in the shard's log here
+    entries: u64,
+}
+
+impl ShardFollower {
+    /// Follow the primary's shard, reconnecting as needed, until an error
+    /// that retrying can't fix
+    async fn run(mut self) -> EngineError {
+        loop {
+            match self.follow().await {
+                Ok(()) | Err(EngineError::Io(_)) => {
+                    tokio::time::sleep(RETRY_INTERVAL).await;
+                }
+                Err(e) => return e,
+            }
+        }
+    }
+
+    /// Apply the primary's entries over one connection until it closes
+    async fn follow(&mut self) -> Result<()> {
+        let stream = TcpStream::connect(&self.primary).await?;
+        stream.set_nodelay(true)?;
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+
+        let hello = Hello {
+            shard: self.shard,
+            entries: self.entries,
+        };
+        writer.write_all(&json_line(&hello)?).await?;
+        let reply = next_line(&mut lines).await?;
+        let progress: Progress = serde_json::from_str(&reply).map_err(|_| {
+            EngineError::Replication(format!(
+                "{} refused shard {}: {}",
+                self.primary, self.shard, reply
+            ))
+        })?;
+        if progress.shards != self.num_shards {
+            return Err(EngineError::Replication(format!(
+                "{} has {} shards, not {}",
+                self.primary, progress.shards, self.num_shards
+            )));
+        }
+
+        loop {
+            let line = next_line(&mut lines).await?;
+            // Heartbeat
+            if line.is_empty() {
+                continue;
+            }
+            let entry = decode_log_line(&line)?;
+            self.engine.apply_shipped(self.shard, entry).await?;
+            self.entries += 1;
+        }
+    }
+}
+
+/// Ask the replica following into `dir` to become writable
+///
+/// The replica notices within a fraction of a second; one that isn't
+/// running is promoted when it next starts.
+pub fn request_promotion(dir: impl AsRef<Path>) -> Result<()> {
+    fs::write(dir.as_ref().join(PROMOTE_FILE), b"")?;
+    Ok(())
+}
+
+/// Whether promotion was requested, clearing the request
+fn take_promotion(dir: &Path) -> Result<bool> {
+    match fs::remove_file(dir.join(PROMOTE_FILE)) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read a line, failing if none comes within `READ_TIMEOUT` or the
+/// connection closes
+async fn next_line(lines: &mut Lines<tokio::io::BufReader<OwnedReadHalf>>) -> io::Result<String> {
+    match tokio::time::timeout(READ_TIMEOUT, lines.next_line()).await {
+        Ok(Ok(Some(line))) => Ok(line),
+        Ok(Ok(None)) => Err(io::ErrorKind::UnexpectedEof.into()),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err(io::ErrorKind::TimedOut.into()),
+    }
+}
+
+fn json_line(value: &impl Serialize) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    Ok(line)
+}
@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+use io_uring::{opcode, squeue, types, IoUring};
+
+/// Submission queue entries per ring: one write and its linked sync
+const RING_ENTRIES: u32 = 2;
+
+/// `user_data` tags telling the two completions apart
+const WRITE: u64 = 0;
+const SYNC: u64 = 1;
+
+/// Writes WAL batches through an io_uring
+///
+/// Each batch is a write at the end of the file with an `fdatasync` linked
+/// behind it, submitted together so the kernel starts the sync as soon as the
+/// write lands, with one system call for both.
+pub(super) struct UringWriter {
+    ring: IoUring,
+    file: File,
+}
+
+impl UringWriter {
+    /// Set up a ring for `file`, handing the file back if the kernel won't
+    pub(super) fn new(file: File) -> Result<Self, File> {
+        match IoUring::new(RING_ENTRIES) {
+            Ok(ring) => Ok(Self { ring, file }),
+            Err(_) => Err(file),
+        }
+    }
+
+    /// Append `batch` to the file and sync it
+    pub(super) fn write_and_sync(&mut self, mut batch: &[u8]) -> io::Result<()> {
+        let fd = types::Fd(self.file.as_raw_fd());
+        loop {
+            let len = batch.len().min(u32::MAX as usize);
+            // An offset of -1 writes at the file position, which for a file
+            // opened to append is always its end
+            let write = opcode::Write::new(fd, batch.as_ptr(), len as u32)
+                .offset(u64::MAX)
+                .build()
+                .flags(squeue::Flags::IO_LINK)
+                .user_data(WRITE);
+            let sync = opcode::Fsync::new(fd)
+                .flags(types::FsyncFlags::DATASYNC)
+                .build()
+                .user_data(SYNC);
+
+            // SAFETY: both entries only refer to `batch` and `self.file`,
+            // which outlive the submission because we wait below for both
+            // completions before returning or touching the ring again.
+            unsafe {
+                let mut submission = self.ring.submission();
+                submission
+                    .push(&write)
+                    .and_then(|()| submission.push(&sync))
+                    .map_err(|_| io::Error::other("io_uring submission queue full"))?;
+            }
+            self.wait_for(2)?;
+
+            let (mut written, mut synced) = (0, 0);
+            for completion in self.ring.completion() {
+                match completion.user_data() {
+                    WRITE => written = completion.result(),
+                    _ => synced = completion.result(),
+                }
+            }
+
+            if written < 0 {
+                return Err(io::Error::from_raw_os_error(-written));
+            }
+            let written = written as usize;
+            if written == 0 && !batch.is_empty() {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            batch = &batch[written..];
+
+            // A short write cancels the linked sync; go again with the rest
+            if batch.is_empty() {
+                return match synced {
+                    result if result < 0 => Err(io::Error::from_raw_os_error(-result)),
+                    _ => Ok(()),
+                };
+            }
+        }
+    }
+
+    /// Submit what is queued and wait for `completions` entries to finish
+    fn wait_for(&mut self, completions: usize) -> io::Result<()> {
+        loop {
+            match self.ring.submit_and_wait(completions) {
+                Ok(_) => return Ok(()),
+                // Entries already submitted stay in flight; just wait again
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
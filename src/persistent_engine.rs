@@ -1,7 +1,60 @@
-use crate::engine::PaymentsEngine;
-use crate::error::Result;
-use crate::models::Transaction;
-use crate::persistence::PersistenceBackend;
+use std::future::{self, Future};
+use std::time::{Duration, Instant};
+
+use crate::archive::ArchiveBackend;
+use crate::config::EngineConfig;
+use crate::engine::{Engine, EngineStats, FundsSummary, PaymentsEngine, Simulation};
+use crate::error::{EngineError, Result};
+use crate::hooks::Hooks;
+use crate::models::{Account, ClientId, Transaction};
+use crate::outcome::Outcome;
+use crate::persistence::{CommitHandle, LogEntry, PersistenceBackend};
+
+/// When `PersistentEngine` checkpoints its WAL on its own
+///
+/// A checkpoint saves the engine's state with the backend (see
+/// `PersistenceBackend::checkpoint`) and drops the log entries it covers, so
+/// the log, and the time recovery takes to replay it, stop growing. Each
+/// limit left at `None` never triggers one; the default never checkpoints.
+///
+/// Limits are checked before each transaction or redaction is logged, so a
+/// quiet engine doesn't checkpoint just because `max_interval` went by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckpointPolicy {
+    /// Checkpoint once the live log holds this many bytes
+    pub max_log_bytes: Option<u64>,
+    /// Checkpoint once this many entries were logged since the last checkpoint
+    pub max_entries: Option<u64>,
+    /// Checkpoint once this much time went by since the last checkpoint, or
+    /// since the engine started
+    pub max_interval: Option<Duration>,
+}
+
+/// What recovery does when the state it rebuilt doesn't match a digest
+/// recorded in the log
+///
+/// `checkpoint()` and `record_digest()` log a digest of the engine's state
+/// (see `PaymentsEngine::state_digest`), so a snapshot or log entry that was
+/// damaged or edited since shows up as a mismatch when the next digest is
+/// replayed. Logs without digests, and backends that don't store them,
+/// recover as before.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntegrityCheck {
+    /// Skip digests
+    #[default]
+    Off,
+    /// Log a warning and go on with the state as rebuilt
+    Warn,
+    /// Fail with `EngineError::StateMismatch`
+    Refuse,
+}
+
+impl CheckpointPolicy {
+    /// Whether any limit is set
+    pub fn is_enabled(&self) -> bool {
+        self.max_log_bytes.is_some() || self.max_entries.is_some() || self.max_interval.is_some()
+    }
+}
 
 /// Engine with persistence support for crash recovery
 ///
@@ -22,24 +75,25 @@ use crate::persistence::PersistenceBackend;
 /// 2. Rebuilds in-memory state
 /// 3. Continues normal operation
 ///
+/// # Checkpoints
+///
+/// `checkpoint()` saves the engine's state with the backend and starts the
+/// log over; recovery then loads that state and only replays what was
+/// logged after it. `set_checkpoint_policy()` has the engine do it on its
+/// own once the log grows past a size or entry count, or after a while.
+///
 /// # Example
 ///
 /// ```no_run
 /// use payments_engine::persistent_engine::PersistentEngine;
 /// use payments_engine::persistence::StubPersistence;
-/// use payments_engine::models::{Transaction, TransactionType};
-/// use rust_decimal_macros::dec;
+/// use payments_engine::models::Transaction;
 ///
 /// // Normal startup (fresh state)
 /// let mut engine = PersistentEngine::new(StubPersistence::new());
 ///
 /// // Process transactions
-/// let tx = Transaction {
-///     tx_type: TransactionType::Deposit,
-///     client: 1,
-///     tx: 1,
-///     amount: Some(dec!(100.0)),
-/// };
+/// let tx = Transaction::deposit(1, 1, "100.0".parse().unwrap());
 /// engine.process_transaction(tx).unwrap();
 ///
 /// // After crash, recover from persistent storage
@@ -57,6 +111,12 @@ pub struct PersistentEngine<P: PersistenceBackend> {
     engine: PaymentsEngine,
     /// Persistence backend (WAL)
     persistence: P,
+    /// When to checkpoint without being asked
+    checkpoints: CheckpointPolicy,
+    /// Entries logged since the last checkpoint
+    logged: u64,
+    /// When the last checkpoint was taken, or the engine started
+    last_checkpoint: Instant,
 }
 
 impl<P: PersistenceBackend> PersistentEngine<P> {
@@ -77,9 +137,20 @@ impl<P: PersistenceBackend> PersistentEngine<P> {
     /// let engine = PersistentEngine::new(StubPersistence::new());
     /// ```
     pub fn new(persistence: P) -> Self {
+        Self::with_engine(PaymentsEngine::new(), persistence)
+    }
+
+    /// Wrap an existing engine, e.g. one restored from a saved state
+    ///
+    /// Transactions already reflected in `engine` are not in the WAL, so
+    /// `recover()` alone won't rebuild them; keep the state that produced it.
+    pub fn with_engine(engine: PaymentsEngine, persistence: P) -> Self {
         Self {
-            engine: PaymentsEngine::new(),
+            engine,
             persistence,
+            checkpoints: CheckpointPolicy::default(),
+            logged: 0,
+            last_checkpoint: Instant::now(),
         }
     }
 
@@ -87,8 +158,8 @@ impl<P: PersistenceBackend> PersistentEngine<P> {
     ///
     /// # Recovery Steps
     ///
-    /// 1. Create fresh engine
-    /// 2. Replay all transactions from persistent storage
+    /// 1. Create fresh engine, or load the state of the latest checkpoint
+    /// 2. Replay all transactions from persistent storage logged after it
     /// 3. Rebuild in-memory state
     /// 4. Return recovered engine ready for normal operation
     ///
@@ -111,19 +182,96 @@ impl<P: PersistenceBackend> PersistentEngine<P> {
     /// // Engine state is now restored from WAL
     /// ```
     pub fn recover(persistence: P) -> Result<Self> {
-        let mut engine = PaymentsEngine::new();
-        let transactions = persistence.replay()?;
+        Self::recover_checked(persistence, IntegrityCheck::Off)
+    }
+
+    /// Recover like `recover`, comparing the state rebuilt at each digest in
+    /// the log to it
+    ///
+    /// With `IntegrityCheck::Refuse`, fails on the first mismatch.
+    pub fn recover_checked(persistence: P, check: IntegrityCheck) -> Result<Self> {
+        let mut engine = match persistence.load_checkpoint()? {
+            Some(state) => PaymentsEngine::from_state(state),
+            None => PaymentsEngine::new(),
+        };
 
-        for tx in transactions.iter() {
-            engine.process_transaction(tx.clone());
+        let mut logged = 0;
+        for entry in persistence.replay_entries()? {
+            match entry {
+                LogEntry::Transaction(tx) => {
+                    engine.process_transaction(tx);
+                }
+                LogEntry::Redaction(client_id) => {
+                    engine.erase_client(client_id);
+                }
+                LogEntry::Digest(expected) => {
+                    check_digest(&engine, expected, logged, check)?;
+                    continue;
+                }
+            }
+            logged += 1;
         }
 
         Ok(Self {
-            engine,
-            persistence,
+            logged,
+            ..Self::with_engine(engine, persistence)
         })
     }
 
+    /// Checkpoint on its own according to `policy` from now on
+    ///
+    /// The backend must support checkpoints (see
+    /// `PersistenceBackend::checkpoint`), or the first transaction to reach a
+    /// limit fails.
+    pub fn set_checkpoint_policy(&mut self, policy: CheckpointPolicy) {
+        self.checkpoints = policy;
+    }
+
+    /// Save the engine's state with the backend and drop the log entries it
+    /// covers
+    ///
+    /// Everything processed so far is in the checkpoint once this returns.
+    /// On `Err` the checkpoint may not have been taken, but whatever the
+    /// backend kept still recovers to the current state. Deposits archived
+    /// so far are synced first, since the state no longer has them.
+    ///
+    /// The new log starts with a digest of the state, which recovery can
+    /// check the checkpoint against.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.engine.sync_archive()?;
+        self.persistence.checkpoint(&self.engine.to_state())?;
+        self.logged = 0;
+        self.last_checkpoint = Instant::now();
+        self.record_digest()
+    }
+
+    /// Log a digest of the engine's current state
+    ///
+    /// Recovery with an `IntegrityCheck` compares the state it rebuilt up to
+    /// here with it. Walks every account, so it's meant for checkpoints and
+    /// shutdown rather than every transaction.
+    pub fn record_digest(&mut self) -> Result<()> {
+        self.persistence.append_digest(self.engine.state_digest())
+    }
+
+    /// Checkpoint if the policy says it's time, then count the entry about
+    /// to be logged
+    fn checkpoint_if_due(&mut self) -> Result<()> {
+        let policy = self.checkpoints;
+        let due = policy.max_entries.is_some_and(|max| self.logged >= max)
+            || policy
+                .max_log_bytes
+                .is_some_and(|max| self.persistence.log_size().is_some_and(|size| size >= max))
+            || policy
+                .max_interval
+                .is_some_and(|max| self.last_checkpoint.elapsed() >= max);
+        if due {
+            self.checkpoint()?;
+        }
+        self.logged += 1;
+        Ok(())
+    }
+
     /// Process a transaction with durability guarantee
     ///
     /// # WAL Pattern Implementation
@@ -139,36 +287,69 @@ impl<P: PersistenceBackend> PersistentEngine<P> {
     ///
     /// # Returns
     ///
-    /// `Ok(())` if persisted and processed, `Err` if persistence fails
+    /// `Ok(outcome)` if persisted and processed (the outcome says whether it
+    /// was applied or rejected), `Err` if persistence fails, including a
+    /// checkpoint the policy called for
     ///
     /// # Example
     ///
     /// ```no_run
     /// use payments_engine::persistent_engine::PersistentEngine;
     /// use payments_engine::persistence::StubPersistence;
-    /// use payments_engine::models::{Transaction, TransactionType};
-    /// use rust_decimal_macros::dec;
+    /// use payments_engine::models::Transaction;
     ///
     /// let mut engine = PersistentEngine::new(StubPersistence::new());
     ///
-    /// let tx = Transaction {
-    ///     tx_type: TransactionType::Deposit,
-    ///     client: 1,
-    ///     tx: 1,
-    ///     amount: Some(dec!(100.0)),
-    /// };
+    /// let tx = Transaction::deposit(1, 1, "100.0".parse().unwrap());
     ///
     /// engine.process_transaction(tx).unwrap();
     /// ```
-    pub fn process_transaction(&mut self, tx: Transaction) -> Result<()> {
+    pub fn process_transaction(&mut self, tx: Transaction) -> Result<Outcome> {
         // CRITICAL: Persist BEFORE processing (WAL pattern)
         // This ensures we can recover if we crash after this point
+        self.checkpoint_if_due()?;
         self.persistence.append(&tx)?;
 
         // Safe to process now - if we crash, transaction is in WAL
-        self.engine.process_transaction(tx);
+        Ok(self.engine.process_transaction(tx))
+    }
 
-        Ok(())
+    /// Process a transaction without waiting for its WAL entry to be durable
+    ///
+    /// Like `process_transaction`, the entry is queued in the WAL before the
+    /// transaction is processed, but with a pipelined backend (see
+    /// `PersistenceBackend::append_pipelined`) the write and sync happen in
+    /// the background. Acknowledge the outcome only once the returned handle
+    /// resolves; until then a crash can lose the transaction.
+    ///
+    /// # Returns
+    ///
+    /// The outcome and the entry's `CommitHandle`, `Err` if the entry could
+    /// not be queued, in which case the transaction was not processed
+    pub fn process_transaction_pipelined(
+        &mut self,
+        tx: Transaction,
+    ) -> Result<(Outcome, CommitHandle)> {
+        self.checkpoint_if_due()?;
+        let commit = self.persistence.append_pipelined(&tx)?;
+        Ok((self.engine.process_transaction(tx), commit))
+    }
+
+    /// Erase a client (see `PaymentsEngine::erase_client`), recording it in
+    /// the WAL first
+    ///
+    /// The redaction record is durable before the client is erased, so a
+    /// recovered engine erases them again instead of rebuilding their data
+    /// from earlier entries.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(outcome)` if the record was persisted, `Err` if persistence fails
+    /// or the backend can't store redaction records
+    pub fn erase_client(&mut self, client_id: ClientId) -> Result<Outcome> {
+        self.checkpoint_if_due()?;
+        self.persistence.append_redaction(client_id)?;
+        Ok(self.engine.erase_client(client_id))
     }
 
     /// Get reference to inner engine for queries
@@ -188,10 +369,103 @@ impl<P: PersistenceBackend> PersistentEngine<P> {
         &self.engine
     }
 
+    /// Archive the inner engine's deposits to `archive` (see
+    /// `PaymentsEngine::set_archive`)
+    pub fn set_archive(&mut self, archive: impl ArchiveBackend + 'static) {
+        self.engine.set_archive(archive);
+    }
+
+    /// Replace the inner engine's configuration (see `PaymentsEngine::set_config`)
+    pub fn set_config(&mut self, config: EngineConfig) {
+        self.engine.set_config(config);
+    }
+
+    /// Replace the inner engine's hooks (see `PaymentsEngine::set_hooks`)
+    ///
+    /// Post-commit hooks run once a transaction is applied in memory, before
+    /// its WAL entry is written. Transactions replayed by `recover` don't go
+    /// through hooks registered afterwards.
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.engine.set_hooks(hooks);
+    }
+
+    /// Consume the wrapper and return the inner engine
+    pub fn into_engine(self) -> PaymentsEngine {
+        self.engine
+    }
+
+    /// Flush the persistence backend so every processed transaction is
+    /// durable, and sync the deposits archived so far
+    pub fn flush(&mut self) -> Result<()> {
+        self.engine.sync_archive()?;
+        self.persistence.flush()
+    }
+
     /// Get mutable reference to persistence backend
     ///
-    /// Advanced use cases like triggering snapshots.
+    /// Advanced use cases; checkpoint through `checkpoint()` instead, so
+    /// the engine's state goes with it.
     pub fn persistence_mut(&mut self) -> &mut P {
         &mut self.persistence
     }
 }
+
+/// Compare the state recovery rebuilt after `replayed` entries with a
+/// digest logged at that point
+fn check_digest(
+    engine: &PaymentsEngine,
+    expected: u64,
+    replayed: u64,
+    check: IntegrityCheck,
+) -> Result<()> {
+    if check == IntegrityCheck::Off {
+        return Ok(());
+    }
+    let found = engine.state_digest();
+    if found == expected {
+        return Ok(());
+    }
+    if check == IntegrityCheck::Refuse {
+        return Err(EngineError::StateMismatch { expected, found });
+    }
+    log::warn!(
+        "Recovered state doesn't match the digest logged after {} entries: {:016x}, logged {:016x}",
+        replayed,
+        found,
+        expected
+    );
+    Ok(())
+}
+
+impl<P: PersistenceBackend> Engine for PersistentEngine<P> {
+    /// Resolves once the transaction's WAL entry is durable
+    fn process(&mut self, tx: Transaction) -> impl Future<Output = Result<Outcome>> + Send {
+        let result = self.process_transaction_pipelined(tx);
+        async move {
+            let (outcome, commit) = result?;
+            commit.wait().await?;
+            Ok(outcome)
+        }
+    }
+
+    fn get_account(&self, client_id: ClientId) -> impl Future<Output = Option<Account>> + Send {
+        Engine::get_account(&self.engine, client_id)
+    }
+
+    fn accounts(&self) -> impl Future<Output = Vec<Account>> + Send {
+        self.engine.accounts()
+    }
+
+    fn stats(&self) -> impl Future<Output = EngineStats> + Send {
+        future::ready(self.engine.stats())
+    }
+
+    fn funds(&self) -> impl Future<Output = FundsSummary> + Send {
+        future::ready(self.engine.funds())
+    }
+
+    /// Writes nothing to the WAL
+    fn simulate(&self, tx: Transaction) -> impl Future<Output = Result<Simulation>> + Send {
+        future::ready(Ok(self.engine.simulate(tx)))
+    }
+}
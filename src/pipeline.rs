@@ -0,0 +1,186 @@
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader, Lines};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinSet;
+
+use crate::concurrent_engine::ShardedEngine;
+use crate::error::{EngineError, Result};
+use crate::models::Transaction;
+use crate::CsvColumns;
+
+/// What the parser does when a shard's lane is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for room, slowing the parser down to the pace of the slowest shard
+    #[default]
+    Block,
+    /// Discard the transaction and keep parsing; counted in `IngestStats::dropped`
+    Drop,
+}
+
+/// Tuning for `ingest`
+#[derive(Debug, Clone, Copy)]
+pub struct PipelineOptions {
+    /// Parsed transactions each shard's lane holds before `overflow` applies
+    pub lane_capacity: usize,
+    /// What to do with a transaction whose lane is full
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for PipelineOptions {
+    /// Lanes of 1024 transactions that block when full
+    fn default() -> Self {
+        Self {
+            lane_capacity: 1024,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// What happened to the rows read by `ingest`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestStats {
+    /// Transactions handed to the engine, whether applied or rejected
+    pub processed: u64,
+    /// Transactions discarded under `OverflowPolicy::Drop`
+    pub dropped: u64,
+    /// Rows that weren't valid transactions
+    pub malformed: u64,
+}
+
+/// Feed CSV transactions from `reader` through bounded lanes into `engine`
+///
+/// The parser runs on the calling task and routes each transaction to the
+/// lane of the shard owning its client. Every lane is a bounded channel
+/// drained by its own worker task, so shards process in parallel while each
+/// client's transactions stay in input order. When a shard falls behind its
+/// lane fills up and `options.overflow` decides whether the parser waits or
+/// drops the transaction, so memory use is bounded either way.
+///
+/// The lanes are laid out for the engine's shard count when ingestion starts.
+/// Fails if reading fails or the engine can't process a transaction;
+/// rejected transactions are not errors. Must be called within a tokio
+/// runtime.
+///
+/// # Panics
+///
+/// Panics if `options.lane_capacity` is zero.
+pub async fn ingest<R: AsyncRead + Unpin>(
+    engine: &ShardedEngine,
+    reader: R,
+    options: PipelineOptions,
+) -> Result<IngestStats> {
+    let num_lanes = engine.num_shards().await.max(1);
+    let mut lanes = Vec::with_capacity(num_lanes);
+    let mut workers = JoinSet::new();
+
+    for _ in 0..num_lanes {
+        let (sender, mut receiver) = mpsc::channel::<Transaction>(options.lane_capacity);
+        let engine = engine.clone_handle();
+        workers.spawn(async move {
+            let mut processed = 0;
+            while let Some(tx) = receiver.recv().await {
+                engine.process_transaction(tx).await?;
+                processed += 1;
+            }
+            Ok::<u64, EngineError>(processed)
+        });
+        lanes.push(sender);
+    }
+
+    let mut stats = IngestStats::default();
+    let mut transactions = CsvTransactions::new(reader);
+
+    let parsed = loop {
+        let tx = match transactions.next().await {
+            Ok(Some(tx)) => tx,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        };
+        let lane = &lanes[engine.shard_for(tx.client, num_lanes)];
+
+        // A closed lane means its worker failed; its error is reported below
+        let delivered = match options.overflow {
+            OverflowPolicy::Block => lane.send(tx).await.is_ok(),
+            OverflowPolicy::Drop => match lane.try_send(tx) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    stats.dropped += 1;
+                    true
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+        };
+        if !delivered {
+            break Ok(());
+        }
+    };
+    stats.malformed = transactions.malformed;
+
+    // Closing the lanes lets the workers finish what's queued and exit
+    drop(lanes);
+    while let Some(worker) = workers.join_next().await {
+        stats.processed += worker.expect("ingest worker panicked")?;
+    }
+
+    parsed.map(|()| stats)
+}
+
+/// Transactions parsed one line at a time from async CSV input
+///
+/// The first non-blank line is the header. Rows that aren't valid
+/// transactions are counted and skipped. Fields spanning several lines
+/// (quoted newlines) are not supported.
+pub(crate) struct CsvTransactions<R> {
+    lines: Lines<BufReader<R>>,
+    /// Lines read so far
+    line: u64,
+    columns: Option<CsvColumns>,
+    /// Rows skipped so far
+    pub(crate) malformed: u64,
+}
+
+impl<R: AsyncRead + Unpin> CsvTransactions<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            lines: BufReader::new(reader).lines(),
+            line: 0,
+            columns: None,
+            malformed: 0,
+        }
+    }
+
+    /// The next valid transaction, `None` at the end of the input
+    pub(crate) async fn next(&mut self) -> Result<Option<Transaction>> {
+        while let Some(line) = self.lines.next_line().await? {
+            self.line += 1;
+            let Some(record) = parse_csv_record(&line) else {
+                continue;
+            };
+            let record = record.into_byte_record();
+            let Some(columns) = &self.columns else {
+                self.columns = Some(CsvColumns::new(&record));
+                continue;
+            };
+
+            match columns.parse(&record, self.line) {
+                Ok(transaction) => return Ok(Some(transaction)),
+                Err(error) => {
+                    log::debug!("Skipped malformed row at {}", error);
+                    self.malformed += 1;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Parse one line as a trimmed CSV record; `None` for blank or unparseable lines
+pub(crate) fn parse_csv_record(line: &str) -> Option<csv::StringRecord> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(line.as_bytes());
+    reader.records().next()?.ok()
+}
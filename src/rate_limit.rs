@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use crate::models::ClientId;
+
+/// Per-client transaction rate limit (token bucket)
+///
+/// Each client may submit `burst` transactions at once, refilled at
+/// `per_second` transactions per second.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+/// Token bucket for one client
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Tracks each client's token bucket against the current `RateLimit`
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    limit: Option<RateLimit>,
+    buckets: HashMap<ClientId, Bucket>,
+}
+
+impl RateLimiter {
+    /// Replace the limit; `None` lets every transaction through
+    ///
+    /// Buckets start full again under the new limit.
+    pub(crate) fn set_limit(&mut self, limit: Option<RateLimit>) {
+        self.limit = limit;
+        self.buckets.clear();
+    }
+
+    /// Take a token for `client_id`, returning false if its bucket is empty
+    pub(crate) fn try_acquire(&mut self, client_id: ClientId, now: Instant) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+
+        let capacity = f64::from(limit.burst);
+        let bucket = self.buckets.entry(client_id).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * f64::from(limit.per_second)).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Interactive sessions against a real engine, for `payments-engine repl`
+//!
+//! Each line an operator types is either a transaction, in the CSV or JSON
+//! the TCP server accepts, or a command to inspect the engine. Snapshots
+//! mark points to `undo` back to: the session keeps what it applied and
+//! rebuilds the engine from the part before the snapshot, so history and
+//! erasures are undone exactly.
+
+use std::fmt::Write;
+
+use crate::engine::{AccountOrdering, PaymentsEngine};
+use crate::models::{Account, ClientId, Transaction};
+use crate::outcome::Outcome;
+use crate::parse_csv_row;
+
+/// Commands, as shown by `help`
+pub const HELP: &str = "\
+<type>,<client>,<tx>[,<amount>]  apply a transaction, e.g. deposit,1,1,100.0
+{\"type\":...}                     apply a JSON transaction
+account <client>                 show one account
+accounts                         show every account
+disputes                         list deposits under dispute
+history <client>                 list a client's applied transactions
+erase <client>                   erase a client's account and history
+check                            check the ledger invariants
+snapshot [<name>]                mark this point to undo back to
+snapshots                        list snapshots
+undo [<name>]                    go back to a snapshot [default: the latest]
+help                             show this help
+quit                             leave";
+
+/// Name of the snapshot every session starts with
+pub const START: &str = "start";
+
+const ACCOUNT_HEADER: &str = "client,available,held,total,locked";
+
+/// What the session does with a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// Text to show, possibly several lines; empty for blank input
+    Output(String),
+    /// The operator asked to leave
+    Quit,
+}
+
+/// Something applied to the engine, kept to rebuild it on `undo`
+#[derive(Debug, Clone)]
+enum Action {
+    Transaction(Transaction),
+    Erase(ClientId),
+}
+
+/// An interactive session
+pub struct Repl {
+    engine: PaymentsEngine,
+    /// Everything applied so far, rejected transactions included, so that
+    /// replaying it rejects them again
+    actions: Vec<Action>,
+    /// Snapshot names with how many actions they cover, oldest first
+    snapshots: Vec<(String, usize)>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    /// A session on an empty engine, with the `start` snapshot
+    pub fn new() -> Self {
+        Self {
+            engine: new_engine(),
+            actions: Vec::new(),
+            snapshots: vec![(START.to_string(), 0)],
+        }
+    }
+
+    /// The engine in its current state
+    pub fn engine(&self) -> &PaymentsEngine {
+        &self.engine
+    }
+
+    /// Run one line of input
+    ///
+    /// Problems come back as output starting with `error:`, so a session
+    /// carries on after a typo.
+    pub fn execute(&mut self, line: &str) -> Reply {
+        let line = line.trim();
+        let mut words = line.split_whitespace();
+        let output = match (words.next(), words.next(), words.next()) {
+            (None, _, _) => Ok(String::new()),
+            (Some("quit" | "exit"), None, _) => return Reply::Quit,
+            (Some("help"), None, _) => Ok(HELP.to_string()),
+            (Some("accounts"), None, _) => Ok(self.accounts()),
+            (Some("account"), Some(client), None) => {
+                parse_client(client).and_then(|client| self.account(client))
+            }
+            (Some("disputes"), None, _) => Ok(self.disputes()),
+            (Some("history"), Some(client), None) => {
+                parse_client(client).map(|client| self.history(client))
+            }
+            (Some("erase"), Some(client), None) => {
+                parse_client(client).map(|client| self.apply(Action::Erase(client)).to_string())
+            }
+            (Some("check"), None, _) => Ok(self.engine.check_invariants().to_string()),
+            (Some("snapshot"), name, None) => self.snapshot(name),
+            (Some("snapshots"), None, _) => Ok(self.list_snapshots()),
+            (Some("undo"), name, None) => self.undo(name),
+            (Some(command), ..) if is_command(command) => {
+                Err(format!("wrong arguments for '{}'; try 'help'", command))
+            }
+            _ => parse_transaction(line).map(|tx| self.apply(Action::Transaction(tx)).to_string()),
+        };
+        Reply::Output(output.unwrap_or_else(|message| format!("error: {}", message)))
+    }
+
+    fn apply(&mut self, action: Action) -> Outcome {
+        let outcome = apply_to(&mut self.engine, action.clone());
+        self.actions.push(action);
+        outcome
+    }
+
+    fn accounts(&self) -> String {
+        let mut output = ACCOUNT_HEADER.to_string();
+        for account in self.engine.accounts_iter() {
+            output.push('\n');
+            output.push_str(&account_row(account));
+        }
+        output
+    }
+
+    fn account(&self, client: ClientId) -> Result<String, String> {
+        let account = self
+            .engine
+            .get_account(client)
+            .ok_or_else(|| format!("no account for client {}", client))?;
+        Ok(format!("{}\n{}", ACCOUNT_HEADER, account_row(account)))
+    }
+
+    fn disputes(&self) -> String {
+        let mut disputes: Vec<_> = self.engine.open_disputes().collect();
+        disputes.sort_unstable_by_key(|stored_tx| stored_tx.tx_id);
+        let mut output = "client,tx,amount".to_string();
+        for stored_tx in disputes {
+            let _ = write!(
+                output,
+                "\n{},{},{}",
+                stored_tx.client_id, stored_tx.tx_id, stored_tx.amount
+            );
+        }
+        output
+    }
+
+    fn history(&self, client: ClientId) -> String {
+        let mut output = "time,type,tx,amount,available,held,locked".to_string();
+        for entry in self.engine.client_history(client) {
+            let _ = write!(
+                output,
+                "\n{},{},{},{},{},{},{}",
+                entry.timestamp,
+                entry.tx_type,
+                entry.tx_id,
+                entry.amount,
+                entry.available,
+                entry.held,
+                entry.locked
+            );
+        }
+        output
+    }
+
+    fn snapshot(&mut self, name: Option<&str>) -> Result<String, String> {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => (1..)
+                .map(|n| n.to_string())
+                .find(|name| self.snapshot_position(name).is_none())
+                .expect("some number is free"),
+        };
+        if self.snapshot_position(&name).is_some() {
+            return Err(format!("snapshot '{}' already exists", name));
+        }
+        self.snapshots.push((name.clone(), self.actions.len()));
+        Ok(format!("saved snapshot '{}'", name))
+    }
+
+    fn list_snapshots(&self) -> String {
+        self.snapshots
+            .iter()
+            .map(|(name, actions)| format!("{} ({} entries)", name, actions))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Rebuild the engine from the actions before a snapshot; later
+    /// snapshots are dropped, the snapshot itself is kept
+    fn undo(&mut self, name: Option<&str>) -> Result<String, String> {
+        let index = match name {
+            Some(name) => self
+                .snapshot_position(name)
+                .ok_or_else(|| format!("no snapshot named '{}'", name))?,
+            None => self.snapshots.len() - 1,
+        };
+        self.snapshots.truncate(index + 1);
+        let (name, actions) = &self.snapshots[index];
+
+        let undone = self.actions.len() - actions;
+        self.actions.truncate(*actions);
+        self.engine = new_engine();
+        for action in self.actions.clone() {
+            apply_to(&mut self.engine, action);
+        }
+        Ok(format!(
+            "back at snapshot '{}', undid {} entries",
+            name, undone
+        ))
+    }
+
+    fn snapshot_position(&self, name: &str) -> Option<usize> {
+        self.snapshots.iter().position(|(n, _)| n == name)
+    }
+}
+
+fn new_engine() -> PaymentsEngine {
+    PaymentsEngine::with_account_ordering(AccountOrdering::ByClientId).retain_history()
+}
+
+fn apply_to(engine: &mut PaymentsEngine, action: Action) -> Outcome {
+    match action {
+        Action::Transaction(tx) => engine.process_transaction(tx),
+        Action::Erase(client) => engine.erase_client(client),
+    }
+}
+
+fn is_command(word: &str) -> bool {
+    matches!(
+        word,
+        "quit"
+            | "exit"
+            | "help"
+            | "accounts"
+            | "account"
+            | "disputes"
+            | "history"
+            | "erase"
+            | "check"
+            | "snapshot"
+            | "snapshots"
+            | "undo"
+    )
+}
+
+fn parse_client(word: &str) -> Result<ClientId, String> {
+    word.parse()
+        .map_err(|_| format!("invalid client ID '{}'", word))
+}
+
+fn parse_transaction(line: &str) -> Result<Transaction, String> {
+    if line.starts_with('{') {
+        return serde_json::from_str(line).map_err(|e| format!("invalid JSON transaction: {}", e));
+    }
+    parse_csv_row(line)
+        .ok_or_else(|| format!("unknown command or transaction '{}'; try 'help'", line))
+}
+
+fn account_row(account: &Account) -> String {
+    format!(
+        "{},{},{},{},{}",
+        account.client_id,
+        account.available,
+        account.held,
+        account.total(),
+        account.locked
+    )
+}
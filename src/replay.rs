@@ -0,0 +1,417 @@
+//! Stepping through a write-ahead log to see how accounts got where they are
+//!
+//! A `Replay` applies a log's entries to a fresh engine one at a time, so it
+//! can stop at a `Breakpoint`, show every account as it was there, and
+//! `diff` that against another point. Answering "how did this client end up
+//! locked" is a replay to `locked:<client>` with a trace of the client's
+//! entries.
+//!
+//! A checkpointed log is replayed from its snapshot: the engine starts in
+//! the snapshot's state and only the entries logged since are stepped
+//! through. Positions count those entries from 1, the log sequence number
+//! of each. Time is the logical clock history entries carry: the number of
+//! transactions applied so far, so rejected entries don't advance it.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::concurrent_engine::shard_wal_paths;
+use crate::config::EngineConfig;
+use crate::engine::{AccountOrdering, PaymentsEngine};
+use crate::error::Result;
+use crate::history::Balance;
+use crate::models::{Account, ClientId, TxId};
+use crate::outcome::Outcome;
+use crate::persistence::{read_checkpointed_log, LogEntry};
+use crate::state::EngineState;
+
+/// Where a replay should stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// After the first entry with this transaction ID, written `tx:<id>`
+    Tx(TxId),
+    /// Once this many transactions have been applied, written `time:<n>`
+    Time(u64),
+    /// After the entry at this position, written `lsn:<n>`
+    Lsn(usize),
+    /// Before the first transaction timestamped later than this many Unix
+    /// seconds, written `at:<seconds>`; entries without a timestamp don't
+    /// stop it, and the end of the log does
+    At(u64),
+    /// After the entry that locks this client's account, written
+    /// `locked:<client>`
+    Locked(ClientId),
+}
+
+impl Breakpoint {
+    /// Whether `step` reached this breakpoint
+    fn is_hit(&self, step: &Step) -> bool {
+        match *self {
+            Self::Tx(tx) => matches!(&step.entry, LogEntry::Transaction(entry) if entry.tx == tx),
+            Self::Time(time) => step.time >= time,
+            Self::Lsn(position) => step.position >= position,
+            // Checked before each step instead
+            Self::At(_) => false,
+            Self::Locked(client) => {
+                step.outcome.is_applied()
+                    && step.client() == client
+                    && step.account.as_ref().is_some_and(|account| account.locked)
+            }
+        }
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let usage = || {
+            format!(
+                "invalid breakpoint '{}': use tx:<id>, time:<n>, lsn:<n>, at:<seconds> or locked:<client>",
+                s
+            )
+        };
+        let (kind, value) = s.split_once(':').ok_or_else(usage)?;
+        match kind {
+            "tx" => value.parse().map(Self::Tx).map_err(|_| usage()),
+            "time" => value.parse().map(Self::Time).map_err(|_| usage()),
+            "lsn" => value.parse().map(Self::Lsn).map_err(|_| usage()),
+            "at" => value.parse().map(Self::At).map_err(|_| usage()),
+            "locked" => value.parse().map(Self::Locked).map_err(|_| usage()),
+            _ => Err(usage()),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tx(tx) => write!(f, "tx:{}", tx),
+            Self::Time(time) => write!(f, "time:{}", time),
+            Self::Lsn(position) => write!(f, "lsn:{}", position),
+            Self::At(seconds) => write!(f, "at:{}", seconds),
+            Self::Locked(client) => write!(f, "locked:{}", client),
+        }
+    }
+}
+
+/// One replayed log entry and what it did
+#[derive(Debug, Clone)]
+pub struct Step {
+    /// Position of the entry in the log, from 1
+    pub position: usize,
+    /// Transactions applied up to and including this entry
+    pub time: u64,
+    pub entry: LogEntry,
+    pub outcome: Outcome,
+    /// The entry's client's account afterwards; `None` if it has none
+    pub account: Option<Account>,
+}
+
+impl Step {
+    /// Client the entry is for
+    pub fn client(&self) -> ClientId {
+        match &self.entry {
+            LogEntry::Transaction(tx) => tx.client,
+            LogEntry::Redaction(client) => *client,
+            LogEntry::Digest(_) => unreachable!("replays drop state digests"),
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    /// E.g. `#12 time 9: chargeback client 7 tx 3: applied`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} time {}: ", self.position, self.time)?;
+        match &self.entry {
+            LogEntry::Transaction(tx) => {
+                write!(f, "{} client {} tx {}", tx.tx_type, tx.client, tx.tx)?;
+                if let Some(amount) = tx.amount {
+                    write!(f, " amount {}", amount)?;
+                }
+            }
+            LogEntry::Redaction(client) => write!(f, "erase client {}", client)?,
+            LogEntry::Digest(digest) => write!(f, "state digest {:016x}", digest)?,
+        }
+        write!(f, ": {}", self.outcome)
+    }
+}
+
+/// Every account's balances at one point of a replay, by client
+pub type Snapshot = BTreeMap<ClientId, Balance>;
+
+/// A log being replayed
+pub struct Replay {
+    entries: Vec<LogEntry>,
+    /// State the first entry applies to: the log's checkpoint, if any
+    base: EngineState,
+    engine: PaymentsEngine,
+    /// Entries applied so far
+    position: usize,
+    /// Transactions applied so far
+    time: u64,
+}
+
+impl Replay {
+    /// Replay `entries`, starting before the first
+    pub fn new(entries: Vec<LogEntry>) -> Self {
+        Self::from_state(EngineState::default(), entries)
+    }
+
+    /// Replay `entries` on top of `base`, starting before the first
+    ///
+    /// State digests are dropped; they change nothing, so they aren't steps.
+    pub fn from_state(base: EngineState, mut entries: Vec<LogEntry>) -> Self {
+        entries.retain(|entry| !matches!(entry, LogEntry::Digest(_)));
+        Self {
+            entries,
+            engine: new_engine(&base),
+            base,
+            position: 0,
+            time: 0,
+        }
+    }
+
+    /// Replay the log at `path`, or every shard's log in a `--wal` directory,
+    /// from its checkpoint if it has one
+    ///
+    /// A directory's logs are interleaved by transaction timestamp, so
+    /// positions and time follow that order. Entries without a timestamp
+    /// stay right behind the entry before them in their log, so logs without
+    /// any are replayed one shard after another. Each client only has
+    /// entries in one shard, so their order is unaffected.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            let (base, entries) = read_checkpointed_log(path)?;
+            return Ok(Self::from_state(base.unwrap_or_default(), entries));
+        }
+        let mut base = EngineState::default();
+        let mut logs = Vec::new();
+        for log in shard_wal_paths(path) {
+            let (checkpoint, entries) = read_checkpointed_log(log)?;
+            base.merge(checkpoint.unwrap_or_default());
+            logs.push(entries);
+        }
+        Ok(Self::from_state(base, interleave(logs)))
+    }
+
+    /// Number of entries in the log
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of entries replayed so far
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Number of transactions applied so far
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// The engine in its state at the current position, with history
+    pub fn engine(&self) -> &PaymentsEngine {
+        &self.engine
+    }
+
+    /// Apply the next entry; `None` at the end of the log
+    pub fn step(&mut self) -> Option<Step> {
+        let entry = self.entries.get(self.position)?.clone();
+        self.position += 1;
+
+        let outcome = match &entry {
+            LogEntry::Transaction(tx) => self.engine.process_transaction(tx.clone()),
+            LogEntry::Redaction(client) => self.engine.erase_client(*client),
+            LogEntry::Digest(_) => unreachable!("replays drop state digests"),
+        };
+        if outcome.is_applied() && matches!(entry, LogEntry::Transaction(_)) {
+            self.time += 1;
+        }
+
+        let mut step = Step {
+            position: self.position,
+            time: self.time,
+            entry,
+            outcome,
+            account: None,
+        };
+        step.account = self.engine.get_account(step.client()).cloned();
+        Some(step)
+    }
+
+    /// Step until `breakpoint` is hit, passing every step to `on_step`
+    ///
+    /// Returns whether it was hit; if not, the replay is at the end of the
+    /// log. A `Breakpoint::Time` or `Breakpoint::Lsn` already reached stops
+    /// without a step.
+    pub fn run_to(&mut self, breakpoint: Breakpoint, mut on_step: impl FnMut(&Step)) -> bool {
+        match breakpoint {
+            Breakpoint::Time(time) if self.time >= time => return true,
+            Breakpoint::Lsn(position) if self.position >= position => return true,
+            _ => {}
+        }
+        loop {
+            if let Breakpoint::At(seconds) = breakpoint {
+                let next = self.entries.get(self.position);
+                if next.is_none_or(|entry| timestamp(entry).is_some_and(|at| at > seconds)) {
+                    return true;
+                }
+            }
+            let Some(step) = self.step() else {
+                return false;
+            };
+            on_step(&step);
+            if breakpoint.is_hit(&step) {
+                return true;
+            }
+        }
+    }
+
+    /// Step to the end of the log, passing every step to `on_step`
+    pub fn run_to_end(&mut self, mut on_step: impl FnMut(&Step)) {
+        while let Some(step) = self.step() {
+            on_step(&step);
+        }
+    }
+
+    /// Go back to before the first entry
+    pub fn rewind(&mut self) {
+        self.engine = new_engine(&self.base);
+        self.position = 0;
+        self.time = 0;
+    }
+
+    /// Every account's balances at the current position
+    pub fn snapshot(&self) -> Snapshot {
+        self.engine
+            .accounts_iter()
+            .map(|account| {
+                let balance = Balance {
+                    available: account.available,
+                    held: account.held,
+                    locked: account.locked,
+                };
+                (account.client_id, balance)
+            })
+            .collect()
+    }
+}
+
+/// How one account differs between two snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountChange {
+    pub client: ClientId,
+    /// `None` if the account didn't exist yet
+    pub before: Option<Balance>,
+    /// `None` if the client was erased
+    pub after: Option<Balance>,
+}
+
+impl fmt::Display for AccountChange {
+    /// E.g. `client 7: available 10 -> 0, held 0 -> 10`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "client {}: ", self.client)?;
+        let (before, after) = match (self.before, self.after) {
+            (Some(before), Some(after)) => (before, after),
+            (None, Some(after)) => {
+                return write!(
+                    f,
+                    "opened with available {}, held {}, locked {}",
+                    after.available, after.held, after.locked
+                )
+            }
+            (Some(_), None) => return f.write_str("erased"),
+            (None, None) => return f.write_str("unchanged"),
+        };
+
+        let mut changes = Vec::new();
+        if before.available != after.available {
+            changes.push(format!(
+                "available {} -> {}",
+                before.available, after.available
+            ));
+        }
+        if before.held != after.held {
+            changes.push(format!("held {} -> {}", before.held, after.held));
+        }
+        if before.locked != after.locked {
+            changes.push(format!("locked {} -> {}", before.locked, after.locked));
+        }
+        if changes.is_empty() {
+            return f.write_str("unchanged");
+        }
+        f.write_str(&changes.join(", "))
+    }
+}
+
+/// Accounts that differ between `before` and `after`, by client
+pub fn diff(before: &Snapshot, after: &Snapshot) -> Vec<AccountChange> {
+    let clients: std::collections::BTreeSet<ClientId> =
+        before.keys().chain(after.keys()).copied().collect();
+    clients
+        .into_iter()
+        .filter_map(|client| {
+            let (before, after) = (before.get(&client).copied(), after.get(&client).copied());
+            (before != after).then_some(AccountChange {
+                client,
+                before,
+                after,
+            })
+        })
+        .collect()
+}
+
+fn new_engine(base: &EngineState) -> PaymentsEngine {
+    let config = EngineConfig {
+        account_ordering: AccountOrdering::ByClientId,
+        ..EngineConfig::default()
+    };
+    PaymentsEngine::from_state_with_config(base.clone(), config).retain_history()
+}
+
+/// The timestamp of a transaction entry, if it has one
+fn timestamp(entry: &LogEntry) -> Option<u64> {
+    match entry {
+        LogEntry::Transaction(tx) => tx.timestamp,
+        LogEntry::Redaction(_) | LogEntry::Digest(_) => None,
+    }
+}
+
+/// Merge several logs into one ordered by transaction timestamp, keeping
+/// each log's own order
+///
+/// An entry counts as happening at the latest timestamp its log has shown
+/// so far, so entries without one stay right behind the entry before them.
+/// Ties go to the earlier log.
+fn interleave(logs: Vec<Vec<LogEntry>>) -> Vec<LogEntry> {
+    let mut entries = Vec::with_capacity(logs.iter().map(Vec::len).sum());
+    let mut logs: Vec<_> = logs
+        .into_iter()
+        .map(|log| log.into_iter().peekable())
+        .collect();
+    let mut clocks = vec![0; logs.len()];
+    loop {
+        let next = logs
+            .iter_mut()
+            .zip(&clocks)
+            .enumerate()
+            .filter_map(|(index, (log, &clock))| {
+                let at = timestamp(log.peek()?).map_or(clock, |at| at.max(clock));
+                Some((at, index))
+            })
+            .min();
+        let Some((at, index)) = next else {
+            return entries;
+        };
+        clocks[index] = at;
+        entries.extend(logs[index].next());
+    }
+}
@@ -0,0 +1,164 @@
+//! Picking up inputs where an earlier run left them, so re-running an input
+//! never applies a transaction twice
+//!
+//! Rejecting reused transaction IDs keeps deposits and withdrawals from
+//! being applied twice, but a dispute, resolve or chargeback read again
+//! still moves funds. So a state file also records how far each input
+//! applied on top of it was read, along with a fingerprint of the input's
+//! start (`SourceOffset`). A later run that is given the same input
+//! recognises it by its start, skips the rows up to the recorded position
+//! and goes on from there, whether the earlier run finished, crashed after
+//! saving a checkpoint or the input has grown since. Rows past the
+//! fingerprinted start are skipped without being parsed, so resuming a long
+//! input near its end costs little more than reading it.
+//!
+//! ```
+//! use payments_engine::engine::PaymentsEngine;
+//! use payments_engine::resume::ResumableRows;
+//!
+//! let input = "type,client,tx,amount\ndeposit,1,1,10\ndispute,1,1,\nresolve,1,1,\n";
+//! let mut engine = PaymentsEngine::new();
+//! let mut sources = Vec::new();
+//!
+//! // The first run only gets as far as the deposit
+//! let mut rows = ResumableRows::new(&input.as_bytes()[..37], &sources).unwrap();
+//! for tx in rows.by_ref() {
+//!     engine.process_transaction(tx);
+//! }
+//! rows.record(&mut sources);
+//!
+//! // The second run gets all of it and only applies the rows after that
+//! let rows = ResumableRows::new(input.as_bytes(), &sources).unwrap();
+//! assert_eq!(rows.count(), 2);
+//! ```
+
+use std::io::{self, Cursor, Read};
+
+use crate::models::Transaction;
+use crate::state::SourceOffset;
+use crate::CsvRows;
+
+/// Leading bytes of an input its fingerprint covers
+pub const FINGERPRINT_BYTES: usize = 4096;
+
+type Rows<R> = CsvRows<io::Chain<Cursor<Vec<u8>>, R>>;
+
+/// Transactions of a CSV input that no earlier run applied
+///
+/// Reads and parses like `read_transactions`. Which rows to skip is decided
+/// up front from the `SourceOffset`s of earlier runs; `offset` says how far
+/// this one has got.
+pub struct ResumableRows<R> {
+    rows: Rows<R>,
+    /// The input's first bytes, up to `FINGERPRINT_BYTES`
+    head: Vec<u8>,
+    /// Where an earlier run stopped reading this input, 0 if none did
+    resume_from: u64,
+    /// Bytes skipped unparsed before `rows` started reading
+    skipped: u64,
+}
+
+impl<R: Read> ResumableRows<R> {
+    /// Read the start of `reader` and find where the earlier runs described
+    /// by `sources` stopped reading it
+    pub fn new(mut reader: R, sources: &[SourceOffset]) -> io::Result<Self> {
+        let mut head = Vec::with_capacity(FINGERPRINT_BYTES);
+        (&mut reader)
+            .take(FINGERPRINT_BYTES as u64)
+            .read_to_end(&mut head)?;
+        let resume_from = sources
+            .iter()
+            .filter(|source| starts(&head, source))
+            .map(|source| source.position)
+            .max()
+            .unwrap_or(0);
+
+        // The earlier run stopped at the end of a row, so everything up to
+        // there can be skipped unparsed once the header row is known
+        let head_len = head.len() as u64;
+        let (rows, skipped) = if resume_from > head_len && head.contains(&b'\n') {
+            let headers = CsvRows::new(&head[..]).headers().clone();
+            io::copy(
+                &mut (&mut reader).take(resume_from - head_len),
+                &mut io::sink(),
+            )?;
+            let rest = Cursor::new(Vec::new()).chain(reader);
+            (CsvRows::with_headers(rest, headers), resume_from)
+        } else {
+            (CsvRows::new(Cursor::new(head.clone()).chain(reader)), 0)
+        };
+
+        Ok(Self {
+            rows,
+            head,
+            resume_from,
+            skipped,
+        })
+    }
+
+    /// Byte position an earlier run stopped reading this input at, 0 if it
+    /// is new
+    pub fn resume_from(&self) -> u64 {
+        self.resume_from
+    }
+
+    /// How far the input has been read, to save with the engine's state
+    pub fn offset(&self) -> SourceOffset {
+        let position = self.bytes_read().max(self.resume_from);
+        SourceOffset {
+            fingerprint: fingerprint(&self.head[..fingerprinted(position, &self.head)]),
+            position,
+        }
+    }
+
+    /// Bytes of the input read or skipped so far
+    fn bytes_read(&self) -> u64 {
+        self.skipped + self.rows.position()
+    }
+
+    /// Record how far the input has been read in `sources`, replacing what
+    /// earlier runs or checkpoints recorded for it
+    pub fn record(&self, sources: &mut Vec<SourceOffset>) {
+        let offset = self.offset();
+        sources
+            .retain(|source| !(source.position <= offset.position && starts(&self.head, source)));
+        sources.push(offset);
+    }
+}
+
+impl<R: Read> Iterator for ResumableRows<R> {
+    type Item = Transaction;
+
+    fn next(&mut self) -> Option<Transaction> {
+        loop {
+            let transaction = self.rows.next()?;
+            // The row ends where the reader is now
+            if self.bytes_read() > self.resume_from {
+                return Some(transaction);
+            }
+        }
+    }
+}
+
+/// Whether `head` could be the start of the input `source` was recorded for
+fn starts(head: &[u8], source: &SourceOffset) -> bool {
+    let len = fingerprinted(source.position, head);
+    len as u64 == source.position.min(FINGERPRINT_BYTES as u64)
+        && fingerprint(&head[..len]) == source.fingerprint
+}
+
+/// Leading bytes a fingerprint covers for an input read up to `position`,
+/// of which `head` holds the first
+fn fingerprinted(position: u64, head: &[u8]) -> usize {
+    position.min(head.len() as u64) as usize
+}
+
+/// A 64-bit FNV-1a hash; fixed, so state files stay valid across versions
+pub(crate) fn fingerprint(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
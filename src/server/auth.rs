@@ -0,0 +1,98 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::{EngineError, Result};
+use crate::models::ClientId;
+
+/// Clients an API key may act on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientScope {
+    /// Every client
+    All,
+    /// Only the listed clients
+    Clients(HashSet<ClientId>),
+}
+
+impl ClientScope {
+    /// Whether this scope covers `client_id`
+    pub fn allows(&self, client_id: ClientId) -> bool {
+        match self {
+            Self::All => true,
+            Self::Clients(clients) => clients.contains(&client_id),
+        }
+    }
+}
+
+/// API keys accepted by the server, each scoped to a set of clients
+///
+/// Keys are loaded from a text file with one key per line:
+///
+/// ```text
+/// # key        clients
+/// partner-a    1,2,3
+/// operator     *
+/// ```
+///
+/// Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    keys: HashMap<String, Arc<ClientScope>>,
+}
+
+impl ApiKeys {
+    /// Create an empty key set (rejects every request)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Authorize `key` for `scope`, replacing any previous scope for that key
+    pub fn insert(&mut self, key: impl Into<String>, scope: ClientScope) {
+        self.keys.insert(key.into(), Arc::new(scope));
+    }
+
+    /// Scope granted to `key`, or `None` if the key is unknown
+    pub fn scope(&self, key: &str) -> Option<Arc<ClientScope>> {
+        self.keys.get(key).cloned()
+    }
+
+    /// Parse keys from the text format described above
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut keys = Self::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |reason: &str| {
+                EngineError::InvalidConfig(format!("API keys line {}: {}", index + 1, reason))
+            };
+
+            let (key, clients) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| invalid("expected '<key> <clients>'"))?;
+
+            let scope = match clients.trim() {
+                "*" => ClientScope::All,
+                clients => ClientScope::Clients(
+                    clients
+                        .split(',')
+                        .map(|id| id.trim().parse::<ClientId>())
+                        .collect::<std::result::Result<_, _>>()
+                        .map_err(|_| invalid("client IDs must be '*' or a comma-separated list"))?,
+                ),
+            };
+            keys.insert(key, scope);
+        }
+
+        Ok(keys)
+    }
+
+    /// Load keys from a file in the text format described above
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::amount::Amount;
+#[cfg(feature = "chaos")]
+use crate::chaos::ChaosConfig;
+use crate::concurrent_engine::ShardedEngine;
+use crate::config::{EngineConfig, RetentionPolicy};
+use crate::error::{EngineError, Result};
+use crate::rate_limit::RateLimit;
+
+/// Server policy loaded from a TOML config file
+///
+/// Every section is optional; a missing setting means "no limit".
+///
+/// ```toml
+/// [limits]
+/// max_amount = "10000.00"
+///
+/// [rate_limit]
+/// per_second = 50
+/// burst = 100
+///
+/// [validation]
+/// max_decimal_places = 4
+///
+/// [retention]
+/// max_entries = 1000000
+/// max_age = 5000000
+///
+/// [watchdog]
+/// check_every = 100000
+/// ```
+///
+/// Builds with the `chaos` feature also accept a `[chaos]` section (see
+/// `ChaosConfig`); other builds refuse it as an unknown field.
+///
+/// The file can be reloaded while the server runs (see `apply`), so policy
+/// changes don't need a restart.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub limits: Limits,
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub validation: ValidationRules,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    #[serde(default)]
+    pub watchdog: Watchdog,
+    /// Faults to inject; none if the section is missing
+    #[cfg(feature = "chaos")]
+    pub chaos: Option<ChaosConfig>,
+}
+
+/// Per-transaction amount limits
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// Largest deposit or withdrawal accepted
+    pub max_amount: Option<Amount>,
+}
+
+/// Structural checks applied to incoming transactions
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidationRules {
+    /// Most decimal places an amount may have
+    pub max_decimal_places: Option<u32>,
+}
+
+/// Periodic invariant checks, see `EngineConfig::invariant_check_every`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Watchdog {
+    /// Transactions each shard applies between checks
+    pub check_every: Option<u64>,
+}
+
+impl ServerConfig {
+    /// Parse a config from TOML text
+    pub fn parse(text: &str) -> Result<Self> {
+        let config: Self =
+            toml::from_str(text).map_err(|e| EngineError::InvalidConfig(e.to_string()))?;
+        #[cfg(feature = "chaos")]
+        if let Some(chaos) = &config.chaos {
+            chaos.validate()?;
+        }
+        Ok(config)
+    }
+
+    /// Load a config from a TOML file
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Engine configuration carrying this config's limits, validation rules,
+    /// retention policy and watchdog interval
+    pub fn engine_config(&self) -> EngineConfig {
+        EngineConfig {
+            max_amount: self.limits.max_amount,
+            max_decimal_places: self.validation.max_decimal_places,
+            retention: self.retention,
+            invariant_check_every: self.watchdog.check_every,
+            ..EngineConfig::default()
+        }
+    }
+
+    /// Apply this config to a running engine
+    ///
+    /// Only policy changes; accounts, disputes and the WAL are untouched.
+    pub async fn apply(&self, engine: &ShardedEngine) {
+        engine.set_config(self.engine_config()).await;
+        engine.set_rate_limit(self.rate_limit);
+        #[cfg(feature = "chaos")]
+        engine.set_chaos(self.chaos);
+    }
+}
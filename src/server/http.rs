@@ -0,0 +1,707 @@
+use std::io;
+use std::sync::Arc;
+
+use axum::extract::{FromRequestParts, Path, State};
+use axum::http::header::{ETAG, IF_MATCH};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use tokio::net::TcpListener;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::amount::Amount;
+use crate::concurrent_engine::ShardedEngine;
+use crate::engine::{FundsSummary, Simulation};
+use crate::error::EngineError;
+use crate::events::AccountEvent;
+use crate::models::{Account, ClientId, Hold, Metadata, StoredTransaction, Transaction, TxId};
+use crate::outcome::{Outcome, RejectReason};
+use crate::server::auth::{ApiKeys, ClientScope};
+use crate::server::snapshots::{SnapshotInfo, Snapshotter};
+use crate::server::{sse, ws};
+use crate::statement::UtcDateTime;
+
+/// Header carrying the caller's API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Shared state handed to every request handler
+pub(crate) struct AppState {
+    pub(crate) engine: ShardedEngine,
+    /// Accepted keys; `None` disables authentication
+    api_keys: Option<Arc<ApiKeys>>,
+    /// Takes `POST /admin/snapshot`; `None` disables it
+    snapshots: Option<Snapshotter>,
+}
+
+impl Clone for AppState {
+    fn clone(&self) -> Self {
+        Self {
+            engine: self.engine.clone_handle(),
+            api_keys: self.api_keys.clone(),
+            snapshots: self.snapshots.clone(),
+        }
+    }
+}
+
+/// Clients the caller of a request is authorized for
+///
+/// Extracting this rejects the request with `401` unless it carries a known
+/// API key (when authentication is enabled).
+pub(crate) struct Caller(pub(crate) Arc<ClientScope>);
+
+impl Caller {
+    /// Fail unless the caller may act on `client_id`
+    pub(crate) fn authorize(&self, client_id: ClientId) -> Result<(), Forbidden> {
+        if self.0.allows(client_id) {
+            Ok(())
+        } else {
+            Err(Forbidden(client_id))
+        }
+    }
+}
+
+/// Rejection for a request touching a client outside the caller's scope (`403`)
+pub(crate) struct Forbidden(ClientId);
+
+impl IntoResponse for Forbidden {
+    fn into_response(self) -> Response {
+        error_response(
+            StatusCode::FORBIDDEN,
+            format!("API key not authorized for client {}", self.0),
+        )
+    }
+}
+
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Response> {
+        let Some(api_keys) = &state.api_keys else {
+            return Ok(Caller(Arc::new(ClientScope::All)));
+        };
+
+        parts
+            .headers
+            .get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|key| api_keys.scope(key))
+            .map(Caller)
+            .ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "missing or unknown API key"))
+    }
+}
+
+/// Acknowledgement returned for a submitted transaction
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TransactionAck {
+    /// `applied` or `rejected`
+    #[schema(value_type = String, example = "applied")]
+    pub status: &'static str,
+    /// Why the transaction was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// The rejected transaction's metadata, to trace the rejection back to
+    /// its source
+    #[serde(skip_serializing_if = "Metadata::is_empty")]
+    #[schema(value_type = BTreeMap<String, String>)]
+    pub metadata: Metadata,
+}
+
+impl TransactionAck {
+    /// Acknowledge a transaction with `metadata`, which is echoed back if it
+    /// was rejected
+    pub fn new(outcome: &Outcome, metadata: Metadata) -> Self {
+        let mut ack = Self::from(outcome);
+        if !outcome.is_applied() {
+            ack.metadata = metadata;
+        }
+        ack
+    }
+}
+
+impl From<&Outcome> for TransactionAck {
+    fn from(outcome: &Outcome) -> Self {
+        match outcome {
+            Outcome::Applied => Self {
+                status: "applied",
+                reason: None,
+                metadata: Metadata::new(),
+            },
+            Outcome::Rejected(reason) => Self {
+                status: "rejected",
+                reason: Some(reason.to_string()),
+                metadata: Metadata::new(),
+            },
+        }
+    }
+}
+
+/// What a transaction would do, returned by `POST /transactions/simulate`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SimulationResult {
+    /// `applied` or `rejected`, as the transaction would be
+    #[schema(value_type = String, example = "rejected")]
+    pub status: &'static str,
+    /// Why the transaction would be rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// The client's account as it would be afterwards; absent if there is no
+    /// account and the transaction wouldn't open one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<Account>,
+}
+
+impl From<Simulation> for SimulationResult {
+    fn from(simulation: Simulation) -> Self {
+        let TransactionAck { status, reason, .. } = TransactionAck::from(&simulation.outcome);
+        Self {
+            status,
+            reason,
+            account: simulation.account,
+        }
+    }
+}
+
+/// A deposit currently under dispute
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OpenDispute {
+    pub client: ClientId,
+    #[schema(inline)]
+    pub tx: TxId,
+    #[schema(value_type = String)]
+    pub amount: Amount,
+    /// When the deposit happened, in Unix seconds, if its input said
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+impl From<StoredTransaction> for OpenDispute {
+    fn from(stored_tx: StoredTransaction) -> Self {
+        Self {
+            client: stored_tx.client_id,
+            tx: stored_tx.tx_id,
+            amount: stored_tx.amount,
+            timestamp: stored_tx.timestamp,
+        }
+    }
+}
+
+/// Funds held for one open dispute, returned by `GET /accounts/{client}/holds`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HoldEntry {
+    /// The disputed deposit
+    #[schema(inline)]
+    pub tx: TxId,
+    #[schema(value_type = String)]
+    pub amount: Amount,
+    /// When the dispute happened, in Unix seconds, if its input said
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub placed_at: Option<u64>,
+}
+
+impl From<Hold> for HoldEntry {
+    fn from(hold: Hold) -> Self {
+        Self {
+            tx: hold.tx_id,
+            amount: hold.amount,
+            placed_at: hold.placed_at,
+        }
+    }
+}
+
+/// Funds across all clients, returned by `GET /funds`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FundsTotals {
+    #[schema(value_type = String)]
+    pub available: Amount,
+    #[schema(value_type = String)]
+    pub held: Amount,
+    /// `available + held`, what the clients' bank account should hold
+    #[schema(value_type = String)]
+    pub total: Amount,
+    /// Sum of every deposit charged back
+    #[schema(value_type = String)]
+    pub charged_back: Amount,
+    /// Deposits currently under dispute
+    pub open_disputes: usize,
+}
+
+impl From<FundsSummary> for FundsTotals {
+    fn from(funds: FundsSummary) -> Self {
+        Self {
+            available: funds.available,
+            held: funds.held,
+            total: funds.total(),
+            charged_back: funds.charged_back,
+            open_disputes: funds.open_disputes,
+        }
+    }
+}
+
+/// A deposit archived after it left the dispute window, returned by
+/// `GET /archive/{tx}`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ArchivedTransaction {
+    pub client: ClientId,
+    #[schema(inline)]
+    pub tx: TxId,
+    #[schema(value_type = String)]
+    pub amount: Amount,
+    /// When the deposit happened, in Unix seconds, if its input said
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+impl From<StoredTransaction> for ArchivedTransaction {
+    fn from(stored_tx: StoredTransaction) -> Self {
+        Self {
+            client: stored_tx.client_id,
+            tx: stored_tx.tx_id,
+            amount: stored_tx.amount,
+            timestamp: stored_tx.timestamp,
+        }
+    }
+}
+
+/// A snapshot saved by `POST /admin/snapshot`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SnapshotTaken {
+    /// The state file, on the server
+    pub path: String,
+    /// When the state was captured, e.g. `2024-03-01T09:30:00Z`
+    pub taken_at: String,
+    pub accounts: usize,
+}
+
+impl From<SnapshotInfo> for SnapshotTaken {
+    fn from(snapshot: SnapshotInfo) -> Self {
+        Self {
+            path: snapshot.path.display().to_string(),
+            taken_at: UtcDateTime::from_system_time(snapshot.taken_at).iso_date_time(),
+            accounts: snapshot.accounts,
+        }
+    }
+}
+
+/// JSON error body
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ErrorBody {
+    error: String,
+}
+
+pub(crate) fn error_response(status: StatusCode, message: impl Into<String>) -> Response {
+    let body = ErrorBody {
+        error: message.into(),
+    };
+    (status, Json(body)).into_response()
+}
+
+/// OpenAPI description of the REST API, served at `/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "payments-engine",
+        description = "Transaction submission and account queries"
+    ),
+    paths(
+        submit_transaction,
+        simulate_transaction,
+        get_account,
+        list_accounts,
+        list_disputes,
+        list_holds,
+        get_funds,
+        get_archived,
+        take_snapshot,
+        sse::account_events
+    ),
+    components(schemas(
+        Transaction,
+        Account,
+        TransactionAck,
+        SimulationResult,
+        OpenDispute,
+        HoldEntry,
+        FundsTotals,
+        ArchivedTransaction,
+        SnapshotTaken,
+        AccountEvent,
+        ErrorBody
+    )),
+    modifiers(&ApiKeyAuth),
+    security(("api_key" = []))
+)]
+pub struct ApiDoc;
+
+/// Registers the `x-api-key` header security scheme
+struct ApiKeyAuth;
+
+impl Modify for ApiKeyAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+            );
+        }
+    }
+}
+
+/// Build the REST API router
+///
+/// With `api_keys`, every route except the API docs requires an
+/// `x-api-key` header naming a known key (`401` otherwise), and requests
+/// touching a client outside the key's scope get `403`. Listings and event
+/// streams only include the key's clients.
+///
+/// Routes (all bodies are JSON, amounts are strings):
+///
+/// - `POST /transactions` - submit a transaction; `200` with
+///   `{"status":"applied"}` or `422` with `{"status":"rejected","reason":...}`
+/// - `POST /transactions/simulate` - what a transaction would do, without
+///   applying it; `200` with the status, reason and resulting account
+/// - `GET /accounts/{client}` - one account, `404` if the client is unknown
+/// - `GET /accounts` - all accounts sorted by client ID
+/// - `GET /disputes` - deposits currently under dispute, sorted by transaction ID
+/// - `GET /funds` - funds across all clients, for reconciliation; `403` for
+///   keys scoped to some clients
+/// - `GET /archive/{tx}` - a deposit archived after leaving the dispute
+///   window, `404` if it wasn't archived
+/// - `POST /admin/snapshot` - save the engine's state as a state file now
+///   (see `router_with_snapshots`); `403` for keys scoped to some clients
+/// - `GET /ws` - WebSocket stream of transactions with per-transaction acks
+///   (see `ws::upgrade`)
+/// - `GET /events[?client=<id>]` - server-sent account change events (see
+///   `sse::account_events`)
+/// - `GET /openapi.json` - OpenAPI document for the routes above (see `ApiDoc`)
+/// - `GET /docs` - Swagger UI for the OpenAPI document
+pub fn router(engine: ShardedEngine, api_keys: Option<ApiKeys>) -> Router {
+    router_with_snapshots(engine, api_keys, None)
+}
+
+/// Build the REST API router, with `POST /admin/snapshot` taking snapshots
+/// through `snapshots`
+///
+/// Without `snapshots` that route answers `404`.
+pub fn router_with_snapshots(
+    engine: ShardedEngine,
+    api_keys: Option<ApiKeys>,
+    snapshots: Option<Snapshotter>,
+) -> Router {
+    Router::new()
+        .route("/transactions", post(submit_transaction))
+        .route("/transactions/simulate", post(simulate_transaction))
+        .route("/accounts", get(list_accounts))
+        .route("/accounts/{client}", get(get_account))
+        .route("/accounts/{client}/holds", get(list_holds))
+        .route("/disputes", get(list_disputes))
+        .route("/funds", get(get_funds))
+        .route("/archive/{tx}", get(get_archived))
+        .route("/admin/snapshot", post(take_snapshot))
+        .route("/ws", get(ws::upgrade))
+        .route("/events", get(sse::account_events))
+        .with_state(AppState {
+            engine,
+            api_keys: api_keys.map(Arc::new),
+            snapshots,
+        })
+        .merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()))
+}
+
+/// Serve the REST API on `listener` until the server fails
+pub async fn serve(
+    listener: TcpListener,
+    engine: ShardedEngine,
+    api_keys: Option<ApiKeys>,
+    snapshots: Option<Snapshotter>,
+) -> io::Result<()> {
+    axum::serve(listener, router_with_snapshots(engine, api_keys, snapshots)).await
+}
+
+/// Submit a transaction
+///
+/// With an `If-Match` header carrying an account version (as returned in the
+/// account's `ETag`), the transaction only applies if the client's account is
+/// still at that version.
+#[utoipa::path(
+    post,
+    path = "/transactions",
+    request_body = Transaction,
+    params(("If-Match" = Option<String>, Header, description = "Expected account version")),
+    responses(
+        (status = 200, description = "Transaction applied", body = TransactionAck),
+        (status = 400, description = "Body is not valid JSON or If-Match is not a version"),
+        (status = 401, description = "Missing or unknown API key", body = ErrorBody),
+        (status = 403, description = "API key not authorized for the client", body = ErrorBody),
+        (status = 412, description = "Account changed since the If-Match version", body = TransactionAck),
+        (status = 422, description = "Transaction rejected by the engine", body = TransactionAck),
+        (status = 500, description = "Transaction could not be persisted", body = ErrorBody),
+        (status = 503, description = "Server is a read-only replica", body = ErrorBody)
+    )
+)]
+async fn submit_transaction(
+    State(state): State<AppState>,
+    caller: Caller,
+    headers: HeaderMap,
+    Json(tx): Json<Transaction>,
+) -> Response {
+    if let Err(forbidden) = caller.authorize(tx.client) {
+        return forbidden.into_response();
+    }
+
+    let metadata = tx.metadata.clone();
+    let result = match headers.get(IF_MATCH) {
+        None => state.engine.process_transaction(tx).await,
+        Some(value) => match parse_version(value.to_str().unwrap_or_default()) {
+            Some(version) => {
+                state
+                    .engine
+                    .process_transaction_if_version(tx, version)
+                    .await
+            }
+            None => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    "If-Match must be an account version",
+                )
+            }
+        },
+    };
+
+    match result {
+        Ok(outcome) => {
+            let status = match outcome {
+                Outcome::Applied => StatusCode::OK,
+                Outcome::Rejected(RejectReason::VersionMismatch) => StatusCode::PRECONDITION_FAILED,
+                Outcome::Rejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            (status, Json(TransactionAck::new(&outcome, metadata))).into_response()
+        }
+        Err(e @ EngineError::ReadOnly) => {
+            error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string())
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Check what a transaction would do without applying it
+///
+/// Answers `200` whether or not the transaction would be applied, so a
+/// frontend can warn before submitting. Nothing is written; the rate limit
+/// isn't checked.
+#[utoipa::path(
+    post,
+    path = "/transactions/simulate",
+    request_body = Transaction,
+    responses(
+        (status = 200, description = "What the transaction would do", body = SimulationResult),
+        (status = 400, description = "Body is not valid JSON"),
+        (status = 401, description = "Missing or unknown API key", body = ErrorBody),
+        (status = 403, description = "API key not authorized for the client", body = ErrorBody),
+        (status = 503, description = "Server is shutting down", body = ErrorBody)
+    )
+)]
+async fn simulate_transaction(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(tx): Json<Transaction>,
+) -> Response {
+    if let Err(forbidden) = caller.authorize(tx.client) {
+        return forbidden.into_response();
+    }
+
+    match state.engine.simulate(tx).await {
+        Ok(simulation) => Json(SimulationResult::from(simulation)).into_response(),
+        Err(e) => error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string()),
+    }
+}
+
+/// Get one client's account
+#[utoipa::path(
+    get,
+    path = "/accounts/{client}",
+    params(("client" = inline(ClientId), Path, description = "Client ID")),
+    responses(
+        (status = 200, description = "The client's account, with its version as the ETag", body = Account),
+        (status = 401, description = "Missing or unknown API key", body = ErrorBody),
+        (status = 403, description = "API key not authorized for the client", body = ErrorBody),
+        (status = 404, description = "Unknown client", body = ErrorBody)
+    )
+)]
+async fn get_account(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(client_id): Path<ClientId>,
+) -> Response {
+    if let Err(forbidden) = caller.authorize(client_id) {
+        return forbidden.into_response();
+    }
+
+    match state.engine.get_account(client_id).await {
+        Some(account) => {
+            let etag = format!("\"{}\"", account.version);
+            ([(ETAG, etag)], Json(account)).into_response()
+        }
+        None => error_response(StatusCode::NOT_FOUND, "client not found"),
+    }
+}
+
+/// Parse an `If-Match` account version, quoted as in an `ETag` or bare
+fn parse_version(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    value.parse().ok()
+}
+
+/// List all accounts the caller may see, sorted by client ID
+#[utoipa::path(
+    get,
+    path = "/accounts",
+    responses(
+        (status = 200, description = "All accounts", body = Vec<Account>),
+        (status = 401, description = "Missing or unknown API key", body = ErrorBody)
+    )
+)]
+async fn list_accounts(State(state): State<AppState>, caller: Caller) -> Json<Vec<Account>> {
+    let view = state.engine.accounts_view().await;
+    Json(
+        view.iter()
+            .filter(|account| caller.0.allows(account.client_id))
+            .cloned()
+            .collect(),
+    )
+}
+
+/// List open disputes for the clients the caller may see, sorted by transaction ID
+#[utoipa::path(
+    get,
+    path = "/disputes",
+    responses(
+        (status = 200, description = "Open disputes", body = Vec<OpenDispute>),
+        (status = 401, description = "Missing or unknown API key", body = ErrorBody)
+    )
+)]
+async fn list_disputes(State(state): State<AppState>, caller: Caller) -> Json<Vec<OpenDispute>> {
+    let disputes = state.engine.get_open_disputes().await;
+    Json(
+        disputes
+            .into_iter()
+            .filter(|dispute| caller.0.allows(dispute.client_id))
+            .map(OpenDispute::from)
+            .collect(),
+    )
+}
+
+/// List the holds making up one client's held balance, sorted by transaction ID
+#[utoipa::path(
+    get,
+    path = "/accounts/{client}/holds",
+    params(("client" = inline(ClientId), Path, description = "Client ID")),
+    responses(
+        (status = 200, description = "One hold per open dispute", body = Vec<HoldEntry>),
+        (status = 401, description = "Missing or unknown API key", body = ErrorBody),
+        (status = 403, description = "API key not authorized for the client", body = ErrorBody)
+    )
+)]
+async fn list_holds(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(client_id): Path<ClientId>,
+) -> Response {
+    if let Err(forbidden) = caller.authorize(client_id) {
+        return forbidden.into_response();
+    }
+    let holds = state.engine.holds(client_id).await;
+    Json(holds.into_iter().map(HoldEntry::from).collect::<Vec<_>>()).into_response()
+}
+
+/// Funds across all clients: balances, chargebacks and open disputes
+///
+/// Only for keys covering every client, since the totals include all of them.
+#[utoipa::path(
+    get,
+    path = "/funds",
+    responses(
+        (status = 200, description = "Funds across all clients", body = FundsTotals),
+        (status = 401, description = "Missing or unknown API key", body = ErrorBody),
+        (status = 403, description = "API key scoped to some clients", body = ErrorBody)
+    )
+)]
+async fn get_funds(State(state): State<AppState>, caller: Caller) -> Response {
+    if !matches!(*caller.0, ClientScope::All) {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "API key not authorized for all clients",
+        );
+    }
+    Json(FundsTotals::from(state.engine.funds().await)).into_response()
+}
+
+/// Fetch an archived deposit, e.g. for an audit
+#[utoipa::path(
+    get,
+    path = "/archive/{tx}",
+    params(("tx" = inline(TxId), Path, description = "Transaction ID")),
+    responses(
+        (status = 200, description = "The archived deposit", body = ArchivedTransaction),
+        (status = 401, description = "Missing or unknown API key", body = ErrorBody),
+        (status = 403, description = "API key not authorized for the client", body = ErrorBody),
+        (status = 404, description = "Deposit not archived", body = ErrorBody)
+    )
+)]
+async fn get_archived(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(tx): Path<TxId>,
+) -> Response {
+    match state.engine.archived_transaction(tx).await {
+        Ok(Some(stored_tx)) => match caller.authorize(stored_tx.client_id) {
+            Ok(()) => Json(ArchivedTransaction::from(stored_tx)).into_response(),
+            Err(forbidden) => forbidden.into_response(),
+        },
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "transaction not archived"),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Save the engine's state as a state file now, between scheduled snapshots
+///
+/// Intake pauses while the state is captured; see
+/// `ShardedEngine::snapshot_state`.
+#[utoipa::path(
+    post,
+    path = "/admin/snapshot",
+    responses(
+        (status = 200, description = "Snapshot saved", body = SnapshotTaken),
+        (status = 401, description = "Missing or unknown API key", body = ErrorBody),
+        (status = 403, description = "API key scoped to some clients", body = ErrorBody),
+        (status = 404, description = "Snapshots aren't enabled", body = ErrorBody),
+        (status = 500, description = "Snapshot could not be saved", body = ErrorBody),
+        (status = 503, description = "Server is shutting down", body = ErrorBody)
+    )
+)]
+async fn take_snapshot(State(state): State<AppState>, caller: Caller) -> Response {
+    if !matches!(*caller.0, ClientScope::All) {
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "API key not authorized for all clients",
+        );
+    }
+    let Some(snapshots) = &state.snapshots else {
+        return error_response(StatusCode::NOT_FOUND, "snapshots aren't enabled");
+    };
+    match snapshots.take().await {
+        Ok(snapshot) => Json(SnapshotTaken::from(snapshot)).into_response(),
+        Err(e @ EngineError::ShuttingDown) => {
+            error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string())
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
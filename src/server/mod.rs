@@ -0,0 +1,8 @@
+pub mod auth;
+pub mod config;
+pub mod http;
+pub mod protocol;
+pub mod snapshots;
+pub mod sse;
+pub mod tcp;
+pub mod ws;
@@ -0,0 +1,102 @@
+use crate::engine::FundsSummary;
+use crate::models::{Account, ClientId, Transaction};
+use crate::parse_csv_row;
+
+/// A single request line received from a client
+///
+/// Lines are either transactions, as CSV (`deposit,1,1,100.0`) or JSON
+/// (`{"type":"deposit","client":1,"tx":1,"amount":"100.0"}`), or commands:
+///
+/// - `query <client>` - fetch one account
+/// - `accounts` - fetch all accounts
+/// - `funds` - fetch the funds across all clients
+/// - `auth <key>` - authenticate the session with an API key
+/// - `bind <client>[,<client>...]` - restrict the session to these clients
+#[derive(Debug)]
+pub enum Request {
+    Transaction(Transaction),
+    Query(ClientId),
+    Accounts,
+    Funds,
+    Auth(String),
+    Bind(Vec<ClientId>),
+    /// Blank lines and CSV header rows, which need no response
+    Ignore,
+}
+
+/// CSV header row that clients may send before their transactions
+const CSV_HEADER: &str = "type,client,tx,amount";
+
+/// Parse one request line
+///
+/// Returns a human-readable message describing the problem on failure.
+pub fn parse_line(line: &str) -> Result<Request, String> {
+    let line = line.trim();
+
+    if line.is_empty() || line.replace(' ', "") == CSV_HEADER {
+        return Ok(Request::Ignore);
+    }
+
+    if line.starts_with('{') {
+        return serde_json::from_str(line)
+            .map(Request::Transaction)
+            .map_err(|e| format!("invalid JSON transaction: {}", e));
+    }
+
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some("accounts") if words.next().is_none() => return Ok(Request::Accounts),
+        Some("funds") if words.next().is_none() => return Ok(Request::Funds),
+        Some("query") => {
+            return match (words.next().map(str::parse::<ClientId>), words.next()) {
+                (Some(Ok(client_id)), None) => Ok(Request::Query(client_id)),
+                _ => Err("usage: query <client>".to_string()),
+            };
+        }
+        Some("auth") => {
+            return match (words.next(), words.next()) {
+                (Some(key), None) => Ok(Request::Auth(key.to_string())),
+                _ => Err("usage: auth <key>".to_string()),
+            };
+        }
+        Some("bind") => {
+            let clients: String = words.collect();
+            return clients
+                .split(',')
+                .map(|id| id.parse::<ClientId>())
+                .collect::<Result<Vec<_>, _>>()
+                .map(Request::Bind)
+                .map_err(|_| "usage: bind <client>[,<client>...]".to_string());
+        }
+        _ => {}
+    }
+
+    parse_csv_row(line)
+        .map(Request::Transaction)
+        .ok_or_else(|| format!("invalid transaction: {}", line))
+}
+
+/// Format an account as a CSV row in the output column order
+pub fn format_account(account: &Account) -> String {
+    format!(
+        "{},{},{},{},{}",
+        account.client_id,
+        account.available,
+        account.held,
+        account.total(),
+        account.locked
+    )
+}
+
+/// Format funds as a CSV row: available, held, total, charged back and open
+/// disputes
+pub fn format_funds(funds: &FundsSummary) -> String {
+    format!(
+        "{},{},{},{},{}",
+        funds.available,
+        funds.held,
+        funds.total(),
+        funds.charged_back,
+        funds.open_disputes
+    )
+}
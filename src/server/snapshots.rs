@@ -0,0 +1,344 @@
+//! State files taken while the server runs, on a schedule or on demand
+//!
+//! A `Snapshotter` saves the engine's state as of a single point in time
+//! (see `ShardedEngine::snapshot_state`) to a timestamped state file, which
+//! `serve --state` or a batch run's `--state` can start from. `run` takes
+//! them on a cron-like `Schedule`; `POST /admin/snapshot` takes one
+//! whenever it's asked (see `http::router_with_snapshots`).
+
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::sync::Mutex;
+
+use crate::concurrent_engine::ShardedEngine;
+use crate::error::{EngineError, Result};
+use crate::statement::UtcDateTime;
+
+/// When scheduled snapshots are taken, in UTC
+///
+/// Parsed from the five fields of a crontab line: minute (0-59), hour
+/// (0-23), day of month (1-31), month (1-12) and day of week (0-7, where
+/// both 0 and 7 are Sunday). Each field is `*`, a value, a range `a-b` or a
+/// comma-separated list of them, and any but a single value may have a step,
+/// e.g. `*/15` or `9-17/2`. As in cron, when both day fields are restricted
+/// a day matching either counts. `@hourly`, `@daily` and `@weekly` stand for
+/// `0 * * * *`, `0 0 * * *` and `0 0 * * 0`.
+///
+/// ```
+/// use payments_engine::server::snapshots::Schedule;
+///
+/// // Every 15 minutes during working hours on weekdays
+/// let schedule: Schedule = "*/15 9-17 * * 1-5".parse().unwrap();
+/// assert!("61 * * * *".parse::<Schedule>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month or day of week field is `*`
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// The first minute matching the schedule after `time`, `None` if
+    /// none does within the next five years (e.g. `0 0 31 2 *`)
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let mut minute = seconds / 60 + 1;
+        let limit = minute + 5 * 366 * 24 * 60;
+
+        while minute < limit {
+            let start = UNIX_EPOCH + Duration::from_secs(minute * 60);
+            let date = UtcDateTime::from_system_time(start);
+            // Sunday is 0; the epoch was a Thursday
+            let weekday = (minute / (24 * 60) + 4) % 7;
+            if !self.on_day(date.month, date.day, weekday as u32) {
+                minute = (minute / (24 * 60) + 1) * 24 * 60;
+            } else if !has(self.hours, date.hour) {
+                minute = (minute / 60 + 1) * 60;
+            } else if !has(self.minutes, date.minute) {
+                minute += 1;
+            } else {
+                return Some(start);
+            }
+        }
+        None
+    }
+
+    fn on_day(&self, month: u32, day: u32, weekday: u32) -> bool {
+        if !has(self.months, month) {
+            return false;
+        }
+        let day_matches = has(self.days, day);
+        let weekday_matches = has(self.weekdays, weekday);
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day_matches || weekday_matches,
+            _ => day_matches && weekday_matches,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = EngineError;
+
+    fn from_str(expression: &str) -> Result<Self> {
+        let fields = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            fields => fields,
+        };
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid_schedule(
+                expression,
+                format!("has {} fields, not 5", fields.len()),
+            ));
+        };
+        let field = |text: &str, name: &str, min: u32, max: u32| {
+            parse_field(text, min, max)
+                .ok_or_else(|| invalid_schedule(expression, format!("bad {} '{}'", name, text)))
+        };
+
+        let mut weekdays = field(weekday, "day of week", 0, 7)?;
+        if has(weekdays, 7) {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            expression: expression.trim().to_string(),
+            minutes: field(minute, "minute", 0, 59)?,
+            hours: field(hour, "hour", 0, 23)?,
+            days: field(day, "day of month", 1, 31)?,
+            months: field(month, "month", 1, 12)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn invalid_schedule(expression: &str, problem: String) -> EngineError {
+    EngineError::InvalidConfig(format!("schedule '{}' {}", expression, problem))
+}
+
+fn has(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// The values a field allows as bits of a set, `None` if it isn't valid
+fn parse_field(text: &str, min: u32, max: u32) -> Option<u64> {
+    let mut set = 0;
+    for item in text.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>().ok()?)),
+            None => (item, None),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+            // A single value with a step runs to the end of the field
+            None if step.is_some() => (range.parse().ok()?, max),
+            None => {
+                let value = range.parse().ok()?;
+                (value, value)
+            }
+        };
+        let step = step.unwrap_or(1);
+        if first < min || last > max || first > last || step == 0 {
+            return None;
+        }
+        for value in (first..=last).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Some(set)
+}
+
+/// Where snapshots go
+#[derive(Debug, Clone)]
+pub struct SnapshotOptions {
+    /// Directory for the state files, created if it doesn't exist
+    pub dir: PathBuf,
+    /// Snapshots kept in `dir`, at least 1; older ones are deleted after
+    /// each new one. `None` keeps them all.
+    pub keep: Option<usize>,
+}
+
+impl SnapshotOptions {
+    /// Keep every snapshot in `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            keep: None,
+        }
+    }
+}
+
+/// A snapshot that was saved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    /// The state file
+    pub path: PathBuf,
+    /// When the engine's state was captured
+    pub taken_at: SystemTime,
+    /// Accounts in the state
+    pub accounts: usize,
+}
+
+/// Saves the engine's state to timestamped files in a directory
+///
+/// Files are named `state-<UTC time>.json`, e.g.
+/// `state-2024-03-01T09-30-00.000Z.json`, so sorting their names sorts
+/// them by age. Handles are cheap to clone and share one directory;
+/// snapshots through any of them are taken one at a time.
+pub struct Snapshotter {
+    engine: ShardedEngine,
+    options: SnapshotOptions,
+    /// Held while a snapshot is written
+    writing: Arc<Mutex<()>>,
+}
+
+impl Clone for Snapshotter {
+    fn clone(&self) -> Self {
+        Self {
+            engine: self.engine.clone_handle(),
+            options: self.options.clone(),
+            writing: self.writing.clone(),
+        }
+    }
+}
+
+impl Snapshotter {
+    /// Snapshot `engine` into `options.dir`, creating it if needed
+    pub fn new(engine: &ShardedEngine, options: SnapshotOptions) -> Result<Self> {
+        fs::create_dir_all(&options.dir)?;
+        Ok(Self {
+            engine: engine.clone_handle(),
+            options,
+            writing: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Directory the snapshots are saved in
+    pub fn dir(&self) -> &Path {
+        &self.options.dir
+    }
+
+    /// Capture the engine's state and save it as a new state file
+    ///
+    /// Intake only pauses while the state is captured, not while it's
+    /// written. Fails with `EngineError::ShuttingDown` once the engine shuts
+    /// down, or if the file can't be written; a snapshot that fails leaves
+    /// no file behind, only possibly a `.tmp` one.
+    pub async fn take(&self) -> Result<SnapshotInfo> {
+        let _writing = self.writing.lock().await;
+        let state = self.engine.snapshot_state().await?;
+        let taken_at = SystemTime::now();
+        let options = self.options.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let path = free_path(&options, taken_at);
+            state.save(&path)?;
+            if let Some(keep) = options.keep {
+                prune(&options, keep.max(1))?;
+            }
+            Ok(SnapshotInfo {
+                path,
+                taken_at,
+                accounts: state.accounts.len(),
+            })
+        })
+        .await
+        .expect("snapshot writer panicked")
+    }
+
+    /// Take a snapshot at every time `schedule` matches, until `shutdown`
+    /// resolves
+    ///
+    /// A snapshot that fails is logged and the schedule goes on. A snapshot
+    /// already being written when `shutdown` resolves is finished first.
+    /// Only returns once `shutdown` resolves, even if the schedule never
+    /// matches again.
+    pub async fn run(&self, schedule: &Schedule, shutdown: impl Future<Output = ()>) {
+        tokio::pin!(shutdown);
+        loop {
+            let Some(next) = schedule.next_after(SystemTime::now()) else {
+                log::warn!("Snapshot schedule '{}' never matches again", schedule);
+                return shutdown.await;
+            };
+            let wait = next
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO);
+            tokio::select! {
+                () = &mut shutdown => return,
+                () = tokio::time::sleep(wait) => {}
+            }
+            match self.take().await {
+                Ok(snapshot) => log::info!(
+                    "Saved snapshot '{}' of {} accounts",
+                    snapshot.path.display(),
+                    snapshot.accounts
+                ),
+                Err(EngineError::ShuttingDown) => return,
+                Err(e) => log::warn!("Scheduled snapshot failed: {}", e),
+            }
+        }
+    }
+}
+
+/// A file name for a snapshot taken at `time` that isn't taken yet
+fn free_path(options: &SnapshotOptions, mut time: SystemTime) -> PathBuf {
+    loop {
+        let millis = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_millis());
+        let date = UtcDateTime::from_system_time(time);
+        let path = options.dir.join(format!(
+            "state-{}T{:02}-{:02}-{:02}.{:03}Z.json",
+            date.iso_date(),
+            date.hour,
+            date.minute,
+            date.second,
+            millis
+        ));
+        if !path.exists() {
+            return path;
+        }
+        time += Duration::from_millis(1);
+    }
+}
+
+/// Delete all but the newest `keep` snapshots in `options.dir`
+fn prune(options: &SnapshotOptions, keep: usize) -> Result<()> {
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(&options.dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with("state-") && name.ends_with(".json") {
+            snapshots.push(options.dir.join(&*name));
+        }
+    }
+    snapshots.sort();
+    let stale = snapshots.len().saturating_sub(keep);
+    for path in &snapshots[..stale] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
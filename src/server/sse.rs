@@ -0,0 +1,78 @@
+use std::convert::Infallible;
+
+use axum::extract::{Query, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::Stream;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::IntoParams;
+
+use crate::events::AccountEvent;
+use crate::models::ClientId;
+use crate::server::http::{AppState, Caller, ErrorBody, Forbidden};
+
+/// Query parameters for `GET /events`
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct EventFilter {
+    /// Only stream changes to this client's account
+    client: Option<ClientId>,
+}
+
+/// `GET /events` - server-sent stream of account changes
+///
+/// Each change is an `account` event whose data is an `AccountEvent` as JSON.
+/// `?client=<id>` limits the stream to one client; with authentication the
+/// stream only ever includes the caller's clients. Subscribers that fall too
+/// far behind skip the events they missed rather than stalling the engine.
+#[utoipa::path(
+    get,
+    path = "/events",
+    params(EventFilter),
+    responses((
+        status = 200,
+        description = "Stream of `account` events",
+        body = AccountEvent,
+        content_type = "text/event-stream"
+    ), (
+        status = 401, description = "Missing or unknown API key", body = ErrorBody
+    ), (
+        status = 403, description = "API key not authorized for the client", body = ErrorBody
+    ))
+)]
+pub(crate) async fn account_events(
+    State(state): State<AppState>,
+    caller: Caller,
+    Query(filter): Query<EventFilter>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Forbidden> {
+    if let Some(client_id) = filter.client {
+        caller.authorize(client_id)?;
+    }
+
+    let scope = caller.0;
+    let receiver = state.engine.subscribe();
+
+    let stream = futures::stream::unfold(receiver, move |mut receiver| {
+        let scope = scope.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event)
+                        if scope.allows(event.account.client_id)
+                            && filter.client.is_none_or(|c| c == event.account.client_id) =>
+                    {
+                        let sse_event = Event::default()
+                            .event("account")
+                            .json_data(&event)
+                            .expect("account events serialize to JSON");
+                        return Some((Ok(sse_event), receiver));
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
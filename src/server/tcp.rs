@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+use std::io;
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+use crate::concurrent_engine::ShardedEngine;
+use crate::models::ClientId;
+use crate::server::auth::{ApiKeys, ClientScope};
+use crate::server::protocol::{self, Request};
+
+/// Accept TCP connections and serve each on its own task
+///
+/// Each connection streams newline-delimited requests (see
+/// `protocol::Request`) and receives one response line per request:
+///
+/// - transactions: `ok`, `rejected: <reason>` or `error: <message>`
+/// - `query <client>`: the account as `client,available,held,total,locked`
+/// - `accounts`: one line per account followed by `end`
+/// - `auth <key>` / `bind <clients>`: `ok` or `error: <message>`
+///
+/// With `api_keys`, a connection must `auth` before anything else and is then
+/// limited to the key's clients. Any connection may `bind` once to narrow the
+/// clients it can act on; requests for other clients are refused with
+/// `error: client <id> not bound to this session`.
+///
+/// Runs until accepting a connection fails.
+pub async fn serve(
+    listener: TcpListener,
+    engine: ShardedEngine,
+    api_keys: Option<ApiKeys>,
+) -> io::Result<()> {
+    let api_keys = api_keys.map(Arc::new);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let engine = engine.clone_handle();
+        let api_keys = api_keys.clone();
+
+        tokio::spawn(async move {
+            // A broken connection only affects its own client
+            let _ = handle_connection(stream, engine, api_keys).await;
+        });
+    }
+}
+
+/// Which clients a connection may act on
+struct Session {
+    api_keys: Option<Arc<ApiKeys>>,
+    /// Scope granted by `auth`, or by default when authentication is off
+    scope: Option<Arc<ClientScope>>,
+    /// Clients named by `bind`
+    bound: Option<HashSet<ClientId>>,
+}
+
+impl Session {
+    fn new(api_keys: Option<Arc<ApiKeys>>) -> Self {
+        let scope = match api_keys {
+            Some(_) => None,
+            None => Some(Arc::new(ClientScope::All)),
+        };
+        Self {
+            api_keys,
+            scope,
+            bound: None,
+        }
+    }
+
+    fn authenticate(&mut self, key: &str) -> Result<(), String> {
+        let api_keys = self
+            .api_keys
+            .as_ref()
+            .ok_or("authentication is not enabled")?;
+        let scope = api_keys.scope(key).ok_or("unknown API key")?;
+        self.scope = Some(scope);
+        Ok(())
+    }
+
+    fn bind(&mut self, clients: Vec<ClientId>) -> Result<(), String> {
+        let scope = self.scope.as_ref().ok_or("not authenticated")?;
+        if self.bound.is_some() {
+            return Err("session already bound".to_string());
+        }
+        if let Some(client_id) = clients.iter().find(|c| !scope.allows(**c)) {
+            return Err(format!("API key not authorized for client {}", client_id));
+        }
+        self.bound = Some(clients.into_iter().collect());
+        Ok(())
+    }
+
+    /// Whether the session has authenticated (always true without API keys)
+    fn is_authenticated(&self) -> bool {
+        self.scope.is_some()
+    }
+
+    /// Whether the session may see every client, as aggregates need
+    fn allows_all(&self) -> bool {
+        self.scope
+            .as_ref()
+            .is_some_and(|s| matches!(**s, ClientScope::All))
+            && self.bound.is_none()
+    }
+
+    fn allows(&self, client_id: ClientId) -> bool {
+        self.scope.as_ref().is_some_and(|s| s.allows(client_id))
+            && self.bound.as_ref().is_none_or(|b| b.contains(&client_id))
+    }
+}
+
+fn not_bound(client_id: ClientId) -> String {
+    format!("error: client {} not bound to this session", client_id)
+}
+
+/// Serve requests from a single connection until it closes
+pub async fn handle_connection<S>(
+    stream: S,
+    engine: ShardedEngine,
+    api_keys: Option<Arc<ApiKeys>>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let mut session = Session::new(api_keys);
+
+    while let Some(line) = lines.next_line().await? {
+        let response = match protocol::parse_line(&line) {
+            Ok(Request::Ignore) => continue,
+            Ok(Request::Auth(key)) => match session.authenticate(&key) {
+                Ok(()) => "ok".to_string(),
+                Err(message) => format!("error: {}", message),
+            },
+            Ok(_) if !session.is_authenticated() => "error: not authenticated".to_string(),
+            Ok(Request::Bind(clients)) => match session.bind(clients) {
+                Ok(()) => "ok".to_string(),
+                Err(message) => format!("error: {}", message),
+            },
+            Ok(Request::Transaction(tx)) if !session.allows(tx.client) => not_bound(tx.client),
+            Ok(Request::Transaction(tx)) => {
+                let result = engine.process_transaction(tx).await;
+                // The client never learns whether the transaction went through
+                #[cfg(feature = "chaos")]
+                if engine.chaos_drops_connection() {
+                    return Ok(());
+                }
+                match result {
+                    Ok(outcome) if outcome.is_applied() => "ok".to_string(),
+                    Ok(outcome) => outcome.to_string(),
+                    Err(e) => format!("error: {}", e),
+                }
+            }
+            Ok(Request::Query(client_id)) if !session.allows(client_id) => not_bound(client_id),
+            Ok(Request::Query(client_id)) => match engine.get_account(client_id).await {
+                Some(account) => protocol::format_account(&account),
+                None => "error: client not found".to_string(),
+            },
+            Ok(Request::Accounts) => {
+                let mut response = String::new();
+                let view = engine.accounts_view().await;
+                for account in view.iter().filter(|a| session.allows(a.client_id)) {
+                    response.push_str(&protocol::format_account(account));
+                    response.push('\n');
+                }
+                response.push_str("end");
+                response
+            }
+            Ok(Request::Funds) if !session.allows_all() => {
+                "error: session not authorized for all clients".to_string()
+            }
+            Ok(Request::Funds) => protocol::format_funds(&engine.funds().await),
+            Err(message) => format!("error: {}", message),
+        };
+
+        writer.write_all(response.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+    }
+
+    writer.flush().await
+}
@@ -0,0 +1,85 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use serde::Serialize;
+
+use crate::concurrent_engine::ShardedEngine;
+use crate::models::TxId;
+use crate::server::http::{AppState, Caller, TransactionAck};
+use crate::server::protocol::{self, Request};
+
+/// Acknowledgement sent back over the socket for each submitted transaction
+#[derive(Debug, Serialize)]
+pub struct StreamAck {
+    pub tx: TxId,
+    #[serde(flatten)]
+    pub ack: TransactionAck,
+}
+
+/// Error reply for a message that couldn't be processed
+#[derive(Debug, Serialize)]
+struct StreamError {
+    error: String,
+}
+
+/// `GET /ws` - upgrade to a WebSocket transaction stream
+///
+/// Each text message carries one transaction, as JSON or a CSV row (see
+/// `protocol::Request`). Every transaction is answered with a JSON message:
+/// `{"tx":1,"status":"applied"}`, `{"tx":1,"status":"rejected","reason":...}`
+/// or `{"error":...}` if it couldn't be parsed or persisted, or is for a
+/// client the caller's API key doesn't cover.
+pub(crate) async fn upgrade(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, state.engine, caller))
+}
+
+/// Process transactions from one socket until it closes
+async fn handle_socket(mut socket: WebSocket, engine: ShardedEngine, caller: Caller) {
+    while let Some(Ok(message)) = socket.recv().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            // Pings are answered by axum; binary frames aren't part of the protocol
+            _ => continue,
+        };
+
+        let reply = match protocol::parse_line(&text) {
+            Ok(Request::Ignore) => continue,
+            Ok(Request::Transaction(tx)) if !caller.0.allows(tx.client) => {
+                error_reply(format!("API key not authorized for client {}", tx.client))
+            }
+            Ok(Request::Transaction(tx)) => {
+                let tx_id = tx.tx;
+                let metadata = tx.metadata.clone();
+                let result = engine.process_transaction(tx).await;
+                // The client never learns whether the transaction went through
+                #[cfg(feature = "chaos")]
+                if engine.chaos_drops_connection() {
+                    return;
+                }
+                match result {
+                    Ok(outcome) => serde_json::to_string(&StreamAck {
+                        tx: tx_id,
+                        ack: TransactionAck::new(&outcome, metadata),
+                    }),
+                    Err(e) => error_reply(e.to_string()),
+                }
+            }
+            Ok(_) => error_reply("only transactions are accepted on this stream".to_string()),
+            Err(message) => error_reply(message),
+        };
+
+        let Ok(reply) = reply else { break };
+        if socket.send(Message::Text(reply.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn error_reply(error: String) -> serde_json::Result<String> {
+    serde_json::to_string(&StreamError { error })
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::amount::Amount;
+use crate::history::History;
+use crate::models::{ClientId, TransactionType};
+
+/// Per-client activity totals for one settlement period
+///
+/// Amounts only include applied transactions; rejected ones never reach the
+/// history and so never affect settlement.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SettlementSummary {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    /// Inclusive start of the period
+    pub period_start: u64,
+    pub deposits: Amount,
+    pub withdrawals: Amount,
+    /// Amount moved into held by disputes opened during the period
+    pub disputes_opened: Amount,
+    /// Amount released back to available by resolves during the period
+    pub disputes_resolved: Amount,
+    pub chargebacks: Amount,
+}
+
+/// Aggregate history into per-client totals per period
+///
+/// Periods are fixed-width windows of `period_length` timestamp units
+/// aligned to zero, e.g. 86400 buckets Unix-second timestamps by UTC day.
+/// Summaries are sorted by client, then period.
+pub(crate) fn summarize(history: &History, period_length: u64) -> Vec<SettlementSummary> {
+    assert!(period_length > 0, "period_length must be at least 1");
+
+    let mut summaries: BTreeMap<(ClientId, u64), SettlementSummary> = BTreeMap::new();
+
+    for (client_id, entries) in history.iter() {
+        for entry in entries {
+            let period_start = entry.timestamp - entry.timestamp % period_length;
+            let summary = summaries
+                .entry((client_id, period_start))
+                .or_insert_with(|| SettlementSummary {
+                    client_id,
+                    period_start,
+                    ..Default::default()
+                });
+
+            let total = match entry.tx_type {
+                TransactionType::Deposit => &mut summary.deposits,
+                TransactionType::Withdrawal => &mut summary.withdrawals,
+                TransactionType::Dispute => &mut summary.disputes_opened,
+                TransactionType::Resolve => &mut summary.disputes_resolved,
+                TransactionType::Chargeback => &mut summary.chargebacks,
+            };
+            *total += entry.amount;
+        }
+    }
+
+    summaries.into_values().collect()
+}
@@ -0,0 +1,318 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "chaos")]
+use std::sync::Mutex;
+
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::account_view::AccountPages;
+use crate::archive::FileArchive;
+#[cfg(feature = "chaos")]
+use crate::chaos::{self, Chaos};
+use crate::config::EngineConfig;
+use crate::engine::{EngineStats, FundsSummary, Simulation};
+use crate::error::Result;
+use crate::events::AccountEvent;
+use crate::hooks::Hooks;
+use crate::models::{Account, ClientId, Hold, StoredTransaction, Transaction};
+use crate::outcome::{Outcome, RejectReason};
+use crate::persistence::PersistenceBackend;
+use crate::persistent_engine::PersistentEngine;
+use crate::state::EngineState;
+
+/// Commands accepted by a shard task, each carrying a channel for the reply
+pub(crate) enum Command {
+    Process {
+        tx: Transaction,
+        /// Only process if the account is at this `Account::version`
+        expected_version: Option<u64>,
+        reply: oneshot::Sender<Result<Outcome>>,
+    },
+    /// Erase a client, e.g. for a redaction shipped from a primary
+    #[cfg(feature = "cluster")]
+    Erase {
+        client_id: ClientId,
+        reply: oneshot::Sender<Result<Outcome>>,
+    },
+    GetAccount {
+        client_id: ClientId,
+        reply: oneshot::Sender<Option<Account>>,
+    },
+    Simulate {
+        tx: Transaction,
+        reply: oneshot::Sender<Simulation>,
+    },
+    /// Take a copy-on-write view of the shard's accounts
+    View {
+        reply: oneshot::Sender<AccountPages>,
+    },
+    OpenDisputes {
+        reply: oneshot::Sender<Vec<StoredTransaction>>,
+    },
+    Holds {
+        client_id: ClientId,
+        reply: oneshot::Sender<Vec<Hold>>,
+    },
+    Stats {
+        reply: oneshot::Sender<EngineStats>,
+    },
+    Funds {
+        reply: oneshot::Sender<FundsSummary>,
+    },
+    SetConfig {
+        config: EngineConfig,
+        reply: oneshot::Sender<()>,
+    },
+    SetHooks {
+        hooks: Hooks,
+        reply: oneshot::Sender<()>,
+    },
+    SetArchive {
+        archive: FileArchive,
+        reply: oneshot::Sender<()>,
+    },
+    /// Flush persistence and capture the shard's state
+    Checkpoint {
+        reply: oneshot::Sender<Result<EngineState>>,
+    },
+    /// Flush persistence, hand back every account and stop the task
+    Finish {
+        reply: oneshot::Sender<Result<Vec<Account>>>,
+    },
+}
+
+/// Sending side of a shard task
+///
+/// The task owns its engine outright and handles one command at a time, so
+/// commands for the same shard are applied in the order they were queued.
+/// It exits once every handle has been dropped.
+#[derive(Clone)]
+pub(crate) struct ShardHandle {
+    commands: mpsc::Sender<Command>,
+    counters: Arc<ShardCounters>,
+}
+
+/// Running totals kept by a shard task, readable without going through its queue
+#[derive(Default)]
+pub(crate) struct ShardCounters {
+    /// Transactions applied
+    pub(crate) applied: AtomicU64,
+    /// Transactions rejected by the engine
+    pub(crate) rejected: AtomicU64,
+    /// Transactions that failed with an error, e.g. from persistence
+    pub(crate) failed: AtomicU64,
+    /// Rejected transactions by reason, indexed like `RejectReason::ALL`
+    pub(crate) rejections: [AtomicU64; RejectReason::ALL.len()],
+}
+
+impl ShardCounters {
+    /// Count a finished transaction; `None` if it failed with an error
+    fn count(&self, outcome: Option<Outcome>) {
+        let counter = match outcome {
+            Some(Outcome::Applied) => &self.applied,
+            Some(Outcome::Rejected(reason)) => {
+                self.rejections[reason as usize].fetch_add(1, Ordering::Relaxed);
+                &self.rejected
+            }
+            None => &self.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl ShardHandle {
+    /// Spawn a task owning `engine` on the current tokio runtime
+    ///
+    /// At most `capacity` commands wait in the queue; senders beyond that wait
+    /// for room, which pushes back on producers when the shard falls behind.
+    pub(crate) fn spawn<P: PersistenceBackend + 'static>(
+        engine: PersistentEngine<P>,
+        capacity: usize,
+        events: broadcast::Sender<AccountEvent>,
+        #[cfg(feature = "chaos")] chaos: Arc<Mutex<Chaos>>,
+    ) -> Self {
+        let (commands, receiver) = mpsc::channel(capacity);
+        let counters = Arc::new(ShardCounters::default());
+        tokio::spawn(run(
+            engine,
+            receiver,
+            events,
+            counters.clone(),
+            #[cfg(feature = "chaos")]
+            chaos,
+        ));
+        Self { commands, counters }
+    }
+
+    /// Totals recorded by the shard task so far
+    pub(crate) fn counters(&self) -> &ShardCounters {
+        &self.counters
+    }
+
+    /// Number of commands waiting in the queue
+    pub(crate) fn queue_depth(&self) -> usize {
+        self.commands.max_capacity() - self.commands.capacity()
+    }
+
+    /// Send a command built around a reply channel and wait for the reply
+    ///
+    /// Returns `None` if the shard task has stopped.
+    pub(crate) async fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> Command,
+    ) -> Option<T> {
+        let (reply, response) = oneshot::channel();
+        self.commands.send(command(reply)).await.ok()?;
+        response.await.ok()
+    }
+}
+
+/// Shard task: apply commands to the engine until every handle is gone or
+/// it is told to finish
+///
+/// Replies are dropped silently if the requester stopped waiting.
+async fn run<P: PersistenceBackend>(
+    mut engine: PersistentEngine<P>,
+    mut commands: mpsc::Receiver<Command>,
+    events: broadcast::Sender<AccountEvent>,
+    counters: Arc<ShardCounters>,
+    #[cfg(feature = "chaos")] chaos: Arc<Mutex<Chaos>>,
+) {
+    // Kept in step with the engine's accounts for views
+    let mut pages = AccountPages::new(engine.engine().accounts_iter());
+
+    while let Some(command) = commands.recv().await {
+        match command {
+            Command::Process {
+                tx,
+                expected_version,
+                reply,
+            } => {
+                let (client_id, tx_id, tx_type) = (tx.client, tx.tx, tx.tx_type);
+
+                // A slow shard holds up everything queued behind it
+                #[cfg(feature = "chaos")]
+                {
+                    let latency = chaos.lock().expect("chaos lock poisoned").shard_latency();
+                    if let Some(latency) = latency {
+                        tokio::time::sleep(latency).await;
+                    }
+                }
+
+                // A stale compare-and-swap never reaches the WAL
+                let current_version = engine.engine().account_version(client_id);
+                if expected_version.is_some_and(|version| version != current_version) {
+                    let outcome = Outcome::Rejected(RejectReason::VersionMismatch);
+                    counters.count(Some(outcome));
+                    let _ = reply.send(Ok(outcome));
+                    continue;
+                }
+
+                // Fail the way a WAL append would: before anything is applied
+                #[cfg(feature = "chaos")]
+                if chaos
+                    .lock()
+                    .expect("chaos lock poisoned")
+                    .fails_persistence()
+                {
+                    counters.count(None);
+                    let _ = reply.send(Err(chaos::injected_persistence_failure()));
+                    continue;
+                }
+
+                // Process with persistence (WAL pattern); the entry may still
+                // be on its way to disk
+                let result = engine.process_transaction_pipelined(tx);
+
+                counters.count(result.as_ref().ok().map(|(outcome, _)| *outcome));
+
+                if let (Ok((outcome, _)), Some(account)) =
+                    (&result, engine.engine().get_account(client_id))
+                {
+                    // Even rejected transactions can open an empty account
+                    pages.update(account);
+
+                    if outcome.is_applied() {
+                        // Sending only fails when nobody is subscribed
+                        let _ = events.send(AccountEvent {
+                            tx: tx_id,
+                            tx_type,
+                            account: account.clone(),
+                        });
+                    }
+                }
+
+                match result {
+                    Ok((outcome, commit)) if !commit.is_completed() => {
+                        // Wait for durability off the shard task, so the next
+                        // command is applied while this entry is synced. The
+                        // transaction already counts as applied or rejected.
+                        tokio::spawn(async move {
+                            let _ = reply.send(commit.wait().await.map(|()| outcome));
+                        });
+                    }
+                    result => {
+                        let _ = reply.send(result.map(|(outcome, _)| outcome));
+                    }
+                }
+            }
+            #[cfg(feature = "cluster")]
+            Command::Erase { client_id, reply } => {
+                let result = engine.erase_client(client_id);
+                if matches!(result, Ok(Outcome::Applied)) {
+                    pages.remove(client_id);
+                }
+                let _ = reply.send(result);
+            }
+            Command::GetAccount { client_id, reply } => {
+                let _ = reply.send(engine.engine().get_account(client_id).cloned());
+            }
+            Command::Simulate { tx, reply } => {
+                let _ = reply.send(engine.engine().simulate(tx));
+            }
+            Command::View { reply } => {
+                let _ = reply.send(pages.clone());
+            }
+            Command::OpenDisputes { reply } => {
+                let _ = reply.send(engine.engine().open_disputes().cloned().collect());
+            }
+            Command::Holds { client_id, reply } => {
+                let _ = reply.send(engine.engine().holds(client_id));
+            }
+            Command::Stats { reply } => {
+                let _ = reply.send(engine.engine().stats());
+            }
+            Command::Funds { reply } => {
+                let _ = reply.send(engine.engine().funds());
+            }
+            Command::SetConfig { config, reply } => {
+                engine.set_config(config);
+                let _ = reply.send(());
+            }
+            Command::SetHooks { hooks, reply } => {
+                engine.set_hooks(hooks);
+                let _ = reply.send(());
+            }
+            Command::SetArchive { archive, reply } => {
+                engine.set_archive(archive);
+                let _ = reply.send(());
+            }
+            Command::Checkpoint { reply } => {
+                // Marks the end of the log for recovery's integrity check
+                let result = engine
+                    .record_digest()
+                    .and_then(|()| engine.flush())
+                    .map(|()| engine.engine().to_state());
+                let _ = reply.send(result);
+            }
+            Command::Finish { reply } => {
+                let result = match engine.record_digest().and_then(|()| engine.flush()) {
+                    Ok(()) => Ok(engine.into_engine().into_accounts()),
+                    Err(e) => Err(e),
+                };
+                let _ = reply.send(result);
+                return;
+            }
+        }
+    }
+}
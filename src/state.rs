@@ -0,0 +1,275 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::amount::Amount;
+use crate::error::{EngineError, Result};
+use crate::models::{Account, ClientId, Hold, StoredTransaction, TxId};
+
+mod legacy;
+
+/// Engine state carried between runs
+///
+/// Captures everything needed to continue processing where a previous run
+/// stopped: balances, disputable deposits (with their dispute flags) and the
+/// set of processed transaction IDs used for duplicate detection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EngineState {
+    pub accounts: Vec<AccountState>,
+    pub disputable_transactions: Vec<StoredTransaction>,
+    pub processed_tx_ids: Vec<TxId>,
+    /// Funds held for each open dispute; state saved without them gets one
+    /// per disputed deposit when restored
+    #[serde(default)]
+    pub holds: Vec<Hold>,
+    /// Held balances seeded from another ledger, which have no backing dispute
+    #[serde(default)]
+    pub seeded_held: Vec<(ClientId, Amount)>,
+    /// Balances of clients erased with `PaymentsEngine::erase_client`
+    #[serde(default)]
+    pub erased: Vec<Tombstone>,
+    /// Amount charged back per client
+    #[serde(default)]
+    pub charged_back: Vec<(ClientId, Amount)>,
+    /// How far each input applied on top of this state was read; engines
+    /// ignore it, `resume::ResumableRows` reads and updates it
+    #[serde(default)]
+    pub sources: Vec<SourceOffset>,
+}
+
+/// How far a run read an input (see `resume`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceOffset {
+    /// Hash of the input's first bytes, up to `position` or
+    /// `resume::FINGERPRINT_BYTES`, identifying the input
+    pub fingerprint: u64,
+    /// Bytes read, ending at the end of a row
+    pub position: u64,
+}
+
+/// Persisted account balances
+///
+/// Unlike the CSV output, `total` is not stored since it is derived.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountState {
+    pub client: ClientId,
+    pub available: Amount,
+    pub held: Amount,
+    pub locked: bool,
+    /// `Account::version`; state written before versions existed starts at 0
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl From<&Account> for AccountState {
+    fn from(account: &Account) -> Self {
+        Self {
+            client: account.client_id,
+            available: account.available,
+            held: account.held,
+            locked: account.locked,
+            version: account.version,
+        }
+    }
+}
+
+impl From<AccountState> for Account {
+    fn from(state: AccountState) -> Self {
+        Self {
+            client_id: state.client,
+            available: state.available,
+            held: state.held,
+            locked: state.locked,
+            version: state.version,
+        }
+    }
+}
+
+/// What is left of a client erased with `PaymentsEngine::erase_client`
+///
+/// The balances stay so the ledger's totals still add up, and the client ID
+/// stays taken so it can't be reused for someone else.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub client: ClientId,
+    pub available: Amount,
+    pub held: Amount,
+    pub locked: bool,
+}
+
+impl From<&Account> for Tombstone {
+    fn from(account: &Account) -> Self {
+        Self {
+            client: account.client_id,
+            available: account.available,
+            held: account.held,
+            locked: account.locked,
+        }
+    }
+}
+
+impl Tombstone {
+    /// Get the total balance (available + held)
+    pub fn total(&self) -> Amount {
+        self.available + self.held
+    }
+}
+
+/// Magic bytes identifying a binary engine snapshot
+const SNAPSHOT_MAGIC: &[u8; 4] = b"PESN";
+
+/// Binary snapshot format version, bumped whenever `EngineState` changes shape
+///
+/// The top bit is set in builds with 64-bit transaction IDs (`wide-tx-ids`)
+/// and the next one in builds with 64-bit client IDs (`wide-client-ids`),
+/// whose snapshots encode IDs differently, so no build misreads another's.
+pub const SNAPSHOT_VERSION: u16 =
+    7 | if cfg!(feature = "wide-tx-ids") {
+        0x8000
+    } else {
+        0
+    } | if cfg!(feature = "wide-client-ids") {
+        0x4000
+    } else {
+        0
+    };
+
+impl EngineState {
+    /// Add the state of an engine holding other clients, e.g. another shard
+    pub fn merge(&mut self, other: EngineState) {
+        self.accounts.extend(other.accounts);
+        self.disputable_transactions
+            .extend(other.disputable_transactions);
+        self.processed_tx_ids.extend(other.processed_tx_ids);
+        self.holds.extend(other.holds);
+        self.seeded_held.extend(other.seeded_held);
+        self.erased.extend(other.erased);
+        self.charged_back.extend(other.charged_back);
+        self.sources.extend(other.sources);
+    }
+
+    /// Encode state as a compact binary snapshot (bincode)
+    ///
+    /// The snapshot starts with a magic/version header so stale or foreign
+    /// files are rejected on import instead of being misread.
+    pub fn to_snapshot_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(SNAPSHOT_MAGIC.len() + 2);
+        bytes.extend_from_slice(SNAPSHOT_MAGIC);
+        bytes.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    /// Decode state from a binary snapshot produced by `to_snapshot_bytes`
+    pub fn from_snapshot_bytes(bytes: &[u8]) -> Result<Self> {
+        let (version, body) = split_snapshot(bytes)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(unsupported_version(version));
+        }
+        Ok(bincode::deserialize(body)?)
+    }
+
+    /// Decode state from a binary snapshot written by this version or an
+    /// earlier one, along with the format version it was written in
+    ///
+    /// Earlier versions lack fields that were added since, which are filled
+    /// in the way JSON state files without them are: accounts start at
+    /// version 0, deposits have no timestamp, and there are no tombstones,
+    /// chargeback totals or input offsets. Holds are placed for disputed
+    /// deposits when the state is restored. Re-encoding the result with
+    /// `to_snapshot_bytes` upgrades the snapshot.
+    pub fn from_any_snapshot_bytes(bytes: &[u8]) -> Result<(u16, Self)> {
+        let (version, body) = split_snapshot(bytes)?;
+        let state = if version == SNAPSHOT_VERSION {
+            bincode::deserialize(body)?
+        } else {
+            legacy::decode(version, body)?.ok_or_else(|| unsupported_version(version))?
+        };
+        Ok((version, state))
+    }
+
+    /// Format version of the binary snapshot `bytes`, from its header
+    pub fn snapshot_version(bytes: &[u8]) -> Result<u16> {
+        Ok(split_snapshot(bytes)?.0)
+    }
+
+    /// Read state as JSON from a reader
+    pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Write state as JSON to a writer
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Load state from a file, returning empty state if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => Self::read_from(BufReader::new(file)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Save state to a file
+    ///
+    /// Writes to a temporary sibling file first and renames it into place,
+    /// so a crash mid-write never leaves a truncated state file behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        replace_file(path, |writer| self.write_to(writer))
+    }
+
+    /// Load state from a binary snapshot file, returning empty state if the
+    /// file doesn't exist yet
+    pub fn load_snapshot(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Self::from_snapshot_bytes(&bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Save state to a file as a binary snapshot, replacing it the same way
+    /// as `save`
+    ///
+    /// Much faster than JSON for large states, e.g. for checkpoints taken
+    /// every few minutes of a long batch.
+    pub fn save_snapshot(&self, path: &Path) -> Result<()> {
+        let bytes = self.to_snapshot_bytes()?;
+        replace_file(path, |writer| Ok(writer.write_all(&bytes)?))
+    }
+}
+
+/// Split a binary snapshot into its format version and encoded state
+fn split_snapshot(bytes: &[u8]) -> Result<(u16, &[u8])> {
+    let header_len = SNAPSHOT_MAGIC.len() + 2;
+    if bytes.len() < header_len || &bytes[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+        return Err(EngineError::InvalidSnapshot(
+            "missing snapshot header".to_string(),
+        ));
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    Ok((version, &bytes[header_len..]))
+}
+
+fn unsupported_version(version: u16) -> EngineError {
+    EngineError::InvalidSnapshot(format!("unsupported snapshot version {}", version))
+}
+
+/// Write `path` through `write` into a temporary sibling file, then rename
+/// it into place
+fn replace_file(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! Binary snapshots in the formats of earlier versions
+//!
+//! Bincode writes neither field names nor defaults, so each earlier layout
+//! is read field by field in the order it was written:
+//!
+//! | Version | Added |
+//! |---------|-------|
+//! | 1 | accounts, deposits, processed IDs, then seeded held balances |
+//! | 2 | account versions |
+//! | 3 | tombstones, after seeded held balances |
+//! | 4 | deposit timestamps |
+//! | 5 | input offsets, after tombstones |
+//! | 6 | chargeback totals, before input offsets |
+//!
+//! Version 7 added holds after the processed IDs. Every earlier version
+//! predates wide IDs, so clients are `u16` and transaction IDs `u32`.
+
+use serde::de::DeserializeOwned;
+
+use super::{AccountState, EngineState, SourceOffset, Tombstone};
+use crate::amount::Amount;
+use crate::error::{EngineError, Result};
+use crate::models::{ClientId, StoredTransaction, TransactionType};
+
+/// A deposit as versions 1 to 3 stored it
+type DepositV1 = (u32, u16, Amount, TransactionType, bool);
+
+/// A deposit as versions 4 to 6 stored it, with its timestamp
+type DepositV4 = (u32, u16, Amount, TransactionType, bool, Option<u64>);
+
+/// Decode the state of a snapshot in version `version`'s format, `None` if
+/// that isn't an earlier version
+#[allow(clippy::useless_conversion)] // IDs are only widened in wide builds
+pub(super) fn decode(version: u16, mut body: &[u8]) -> Result<Option<EngineState>> {
+    if !(1..=6).contains(&version) {
+        return Ok(None);
+    }
+    let body = &mut body;
+
+    let accounts = if version >= 2 {
+        next::<Vec<(u16, Amount, Amount, bool, u64)>>(body)?
+    } else {
+        next::<Vec<(u16, Amount, Amount, bool)>>(body)?
+            .into_iter()
+            .map(|(client, available, held, locked)| (client, available, held, locked, 0))
+            .collect()
+    };
+    let disputable_transactions = if version >= 4 {
+        next::<Vec<DepositV4>>(body)?
+    } else {
+        next::<Vec<DepositV1>>(body)?
+            .into_iter()
+            .map(|(tx_id, client_id, amount, tx_type, disputed)| {
+                (tx_id, client_id, amount, tx_type, disputed, None)
+            })
+            .collect()
+    };
+    let processed_tx_ids = next::<Vec<u32>>(body)?;
+    // The first version 1 snapshots ended before seeded held balances
+    let seeded_held = if version == 1 && body.is_empty() {
+        Vec::new()
+    } else {
+        next::<Vec<(u16, Amount)>>(body)?
+    };
+    let erased = if version >= 3 {
+        next::<Vec<(u16, Amount, Amount, bool)>>(body)?
+    } else {
+        Vec::new()
+    };
+    let charged_back = if version >= 6 {
+        next::<Vec<(u16, Amount)>>(body)?
+    } else {
+        Vec::new()
+    };
+    let sources = if version >= 5 {
+        next::<Vec<SourceOffset>>(body)?
+    } else {
+        Vec::new()
+    };
+    if !body.is_empty() {
+        return Err(EngineError::InvalidSnapshot(format!(
+            "{} bytes past the end of a version {} snapshot",
+            body.len(),
+            version
+        )));
+    }
+
+    Ok(Some(EngineState {
+        accounts: accounts
+            .into_iter()
+            .map(|(client, available, held, locked, version)| AccountState {
+                client: client.into(),
+                available,
+                held,
+                locked,
+                version,
+            })
+            .collect(),
+        disputable_transactions: disputable_transactions
+            .into_iter()
+            .map(
+                |(tx_id, client_id, amount, tx_type, disputed, timestamp)| StoredTransaction {
+                    tx_id: tx_id.into(),
+                    client_id: client_id.into(),
+                    amount,
+                    tx_type,
+                    disputed,
+                    timestamp,
+                },
+            )
+            .collect(),
+        processed_tx_ids: processed_tx_ids.into_iter().map(Into::into).collect(),
+        holds: Vec::new(),
+        seeded_held: widen_clients(seeded_held),
+        erased: erased
+            .into_iter()
+            .map(|(client, available, held, locked)| Tombstone {
+                client: client.into(),
+                available,
+                held,
+                locked,
+            })
+            .collect(),
+        charged_back: widen_clients(charged_back),
+        sources,
+    }))
+}
+
+/// Decode the next field of a snapshot, consuming its bytes
+fn next<T: DeserializeOwned>(body: &mut &[u8]) -> Result<T> {
+    Ok(bincode::deserialize_from(body)?)
+}
+
+#[allow(clippy::useless_conversion)]
+fn widen_clients(balances: Vec<(u16, Amount)>) -> Vec<(ClientId, Amount)> {
+    balances
+        .into_iter()
+        .map(|(client, amount)| (client.into(), amount))
+        .collect()
+}
@@ -0,0 +1,178 @@
+//! ISO 20022 camt.053 bank-to-customer statements
+//!
+//! Written as version `camt.053.001.02`, the one most banks and
+//! reconciliation tools accept.
+
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+
+use crate::amount::Amount;
+use crate::error::Result;
+use crate::models::TransactionType;
+use crate::statement::{escape, Statement, StatementOptions, UtcDateTime};
+
+const NAMESPACE: &str = "urn:iso:std:iso:20022:tech:xsd:camt.053.001.02";
+
+/// Write `statements` as one camt.053 document, one `Stmt` per client
+///
+/// Each statement identifies the account by client ID (`Acct/Id/Othr/Id`)
+/// and reports three balances: the opening and closing booked balances
+/// (`OPBD`, `CLBD`, available plus held) and the closing available balance
+/// (`CLAV`). Deposits, withdrawals and chargebacks are booked entries with
+/// the transaction ID as `AcctSvcrRef` and the transaction type as a
+/// proprietary bank transaction code; disputes and resolves don't change
+/// the booked balance and aren't listed. Message and statement IDs are
+/// derived from the client ID and creation time.
+pub fn write<W: Write>(
+    statements: &[Statement],
+    options: &StatementOptions,
+    mut writer: W,
+) -> Result<()> {
+    let created = UtcDateTime::from_system_time(options.created_at);
+    let created_secs = options
+        .created_at
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let currency = escape(&options.currency);
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<Document xmlns="{}">"#, NAMESPACE)?;
+    writeln!(writer, "  <BkToCstmrStmt>")?;
+    writeln!(writer, "    <GrpHdr>")?;
+    writeln!(writer, "      <MsgId>STMT-{}</MsgId>", created_secs)?;
+    writeln!(
+        writer,
+        "      <CreDtTm>{}</CreDtTm>",
+        created.iso_date_time()
+    )?;
+    writeln!(writer, "    </GrpHdr>")?;
+
+    for statement in statements {
+        writeln!(writer, "    <Stmt>")?;
+        writeln!(
+            writer,
+            "      <Id>STMT-{}-{}</Id>",
+            created_secs, statement.client_id
+        )?;
+        writeln!(
+            writer,
+            "      <CreDtTm>{}</CreDtTm>",
+            created.iso_date_time()
+        )?;
+        writeln!(writer, "      <Acct>")?;
+        writeln!(
+            writer,
+            "        <Id><Othr><Id>{}</Id></Othr></Id>",
+            statement.client_id
+        )?;
+        writeln!(writer, "        <Ccy>{}</Ccy>", currency)?;
+        writeln!(writer, "      </Acct>")?;
+
+        let date = created.iso_date();
+        let closing = &statement.closing;
+        for (code, amount) in [
+            ("OPBD", statement.opening),
+            ("CLBD", closing.total()),
+            ("CLAV", closing.available),
+        ] {
+            writeln!(writer, "      <Bal>")?;
+            writeln!(
+                writer,
+                "        <Tp><CdOrPrtry><Cd>{}</Cd></CdOrPrtry></Tp>",
+                code
+            )?;
+            write_amount(&mut writer, "        ", &currency, amount)?;
+            writeln!(writer, "        <Dt><Dt>{}</Dt></Dt>", date)?;
+            writeln!(writer, "      </Bal>")?;
+        }
+
+        let (mut credits, mut debits) = (Summary::default(), Summary::default());
+        for (_, amount) in statement.bookings() {
+            if amount < Amount::ZERO {
+                debits.add(-amount);
+            } else {
+                credits.add(amount);
+            }
+        }
+        writeln!(writer, "      <TxsSummry>")?;
+        writeln!(
+            writer,
+            "        <TtlNtries><NbOfNtries>{}</NbOfNtries></TtlNtries>",
+            credits.count + debits.count
+        )?;
+        for (element, summary) in [("TtlCdtNtries", &credits), ("TtlDbtNtries", &debits)] {
+            writeln!(
+                writer,
+                "        <{0}><NbOfNtries>{1}</NbOfNtries><Sum>{2}</Sum></{0}>",
+                element, summary.count, summary.sum
+            )?;
+        }
+        writeln!(writer, "      </TxsSummry>")?;
+
+        for (entry, amount) in statement.bookings() {
+            writeln!(writer, "      <Ntry>")?;
+            writeln!(writer, "        <NtryRef>{}</NtryRef>", entry.timestamp)?;
+            write_amount(&mut writer, "        ", &currency, amount)?;
+            writeln!(writer, "        <Sts>BOOK</Sts>")?;
+            writeln!(writer, "        <BookgDt><Dt>{}</Dt></BookgDt>", date)?;
+            writeln!(writer, "        <AcctSvcrRef>{}</AcctSvcrRef>", entry.tx_id)?;
+            writeln!(
+                writer,
+                "        <BkTxCd><Prtry><Cd>{}</Cd></Prtry></BkTxCd>",
+                transaction_code(entry.tx_type)
+            )?;
+            writeln!(writer, "      </Ntry>")?;
+        }
+        writeln!(writer, "    </Stmt>")?;
+    }
+
+    writeln!(writer, "  </BkToCstmrStmt>")?;
+    writeln!(writer, "</Document>")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Number and sum of entries on one side
+#[derive(Default)]
+struct Summary {
+    count: usize,
+    sum: Amount,
+}
+
+impl Summary {
+    fn add(&mut self, amount: Amount) {
+        self.count += 1;
+        self.sum += amount;
+    }
+}
+
+/// `Amt` and `CdtDbtInd` for a signed amount; camt amounts are never negative
+fn write_amount<W: Write>(
+    writer: &mut W,
+    indent: &str,
+    currency: &str,
+    amount: Amount,
+) -> Result<()> {
+    let (indicator, amount) = if amount < Amount::ZERO {
+        ("DBIT", -amount)
+    } else {
+        ("CRDT", amount)
+    };
+    writeln!(
+        writer,
+        r#"{}<Amt Ccy="{}">{}</Amt>"#,
+        indent, currency, amount
+    )?;
+    writeln!(writer, "{}<CdtDbtInd>{}</CdtDbtInd>", indent, indicator)?;
+    Ok(())
+}
+
+fn transaction_code(tx_type: TransactionType) -> &'static str {
+    match tx_type {
+        TransactionType::Deposit => "DEPOSIT",
+        TransactionType::Withdrawal => "WITHDRAWAL",
+        TransactionType::Dispute => "DISPUTE",
+        TransactionType::Resolve => "RESOLVE",
+        TransactionType::Chargeback => "CHARGEBACK",
+    }
+}
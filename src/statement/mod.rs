@@ -0,0 +1,169 @@
+//! Per-client account statements built from the retained history
+//!
+//! A `Statement` holds a client's applied transactions along with the
+//! balances before and after them. Exporters render statements in the
+//! formats banks and partners exchange:
+//!
+//! - `camt053`: ISO 20022 bank-to-customer statement XML
+//! - `mt940`: SWIFT MT940 customer statements, for treasury systems
+//! - `ofx`: Open Financial Exchange, for personal finance tools
+//! - `qif`: Quicken Interchange Format, for older finance tools
+
+pub mod camt053;
+pub mod mt940;
+pub mod ofx;
+pub mod qif;
+
+use std::borrow::Cow;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::amount::Amount;
+use crate::history::{Balance, HistoryEntry};
+use crate::models::{ClientId, TransactionType};
+
+/// A client's applied transactions and the balances around them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement<'a> {
+    pub client_id: ClientId,
+    /// Total balance before the first entry
+    pub opening: Amount,
+    /// Balances after the last entry
+    pub closing: Balance,
+    /// Applied transactions in the order they happened
+    pub entries: &'a [HistoryEntry],
+}
+
+impl<'a> Statement<'a> {
+    /// Statement over `entries`, or `None` if there are none
+    pub fn new(client_id: ClientId, entries: &'a [HistoryEntry]) -> Option<Self> {
+        let (first, last) = (entries.first()?, entries.last()?);
+        let after_first = Balance::from(first).total();
+        Some(Self {
+            client_id,
+            opening: after_first - booked_amount(first).unwrap_or_default(),
+            closing: Balance::from(last),
+            entries,
+        })
+    }
+
+    /// Entries that change the total balance, with the signed amount booked
+    ///
+    /// Deposits are credits; withdrawals and chargebacks are debits.
+    /// Disputes and resolves only move funds between available and held, so
+    /// they show in the closing available balance but aren't bookings.
+    pub fn bookings(&self) -> impl Iterator<Item = (&'a HistoryEntry, Amount)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| Some((entry, booked_amount(entry)?)))
+    }
+}
+
+/// Change to the total balance made by an entry, if any
+fn booked_amount(entry: &HistoryEntry) -> Option<Amount> {
+    match entry.tx_type {
+        TransactionType::Deposit => Some(entry.amount),
+        TransactionType::Withdrawal | TransactionType::Chargeback => Some(-entry.amount),
+        TransactionType::Dispute | TransactionType::Resolve => None,
+    }
+}
+
+/// Settings shared by the statement exporters
+#[derive(Debug, Clone)]
+pub struct StatementOptions {
+    /// ISO 4217 code of the currency amounts are in, e.g. `EUR`; the engine
+    /// itself doesn't track currencies
+    pub currency: String,
+    /// When the statement is created. Input transactions carry no time, so
+    /// this is also the date entries are reported as booked on.
+    pub created_at: SystemTime,
+}
+
+impl StatementOptions {
+    /// Statements in `currency` created now
+    pub fn new(currency: impl Into<String>) -> Self {
+        Self {
+            currency: currency.into(),
+            created_at: SystemTime::now(),
+        }
+    }
+}
+
+/// A point in time broken down into UTC calendar fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UtcDateTime {
+    pub(crate) year: i64,
+    pub(crate) month: u32,
+    pub(crate) day: u32,
+    pub(crate) hour: u32,
+    pub(crate) minute: u32,
+    pub(crate) second: u32,
+}
+
+impl UtcDateTime {
+    /// Times before the Unix epoch are taken as the epoch
+    pub(crate) fn from_system_time(time: SystemTime) -> Self {
+        let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let (days, of_day) = ((seconds / 86_400) as i64, (seconds % 86_400) as u32);
+
+        // Days since the epoch to a proleptic Gregorian date, counting
+        // 400-year eras from 0000-03-01 so leap days end each year
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        } as u32;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: of_day / 3600,
+            minute: of_day % 3600 / 60,
+            second: of_day % 60,
+        }
+    }
+
+    /// ISO 8601 date, e.g. `2024-03-01`
+    pub(crate) fn iso_date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// ISO 8601 date and time in UTC, e.g. `2024-03-01T09:30:00Z`
+    pub(crate) fn iso_date_time(&self) -> String {
+        format!(
+            "{}T{:02}:{:02}:{:02}Z",
+            self.iso_date(),
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+/// Escape text for use in XML content and attribute values
+pub(crate) fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
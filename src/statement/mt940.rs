@@ -0,0 +1,110 @@
+//! SWIFT MT940 customer statements
+//!
+//! Written as the text block of the message (field tags `:20:` to `:64:`)
+//! without the SWIFT envelope, which is how treasury systems usually take
+//! MT940 files. Lines end in CRLF as SWIFT requires.
+
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+
+use crate::amount::Amount;
+use crate::error::Result;
+use crate::models::TransactionType;
+use crate::statement::{Statement, StatementOptions, UtcDateTime};
+
+/// Write `statements` as MT940 messages, one per client, each ending in `-`
+///
+/// The account (`:25:`) is the client ID and the transaction reference
+/// (`:20:`) is derived from the creation time and client ID. Balances are
+/// the opening and closing totals (`:60F:`, `:62F:`) and the closing
+/// available balance (`:64:`). Each deposit, withdrawal and chargeback is a
+/// `:61:` line with the transaction ID as customer reference, followed by a
+/// `:86:` line naming the transaction type. Disputes and resolves don't
+/// change the booked balance and aren't listed.
+pub fn write<W: Write>(
+    statements: &[Statement],
+    options: &StatementOptions,
+    mut writer: W,
+) -> Result<()> {
+    let created = UtcDateTime::from_system_time(options.created_at);
+    let created_secs = options
+        .created_at
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let date = format!(
+        "{:02}{:02}{:02}",
+        created.year.rem_euclid(100),
+        created.month,
+        created.day
+    );
+    let currency = &options.currency;
+
+    for statement in statements {
+        write!(writer, ":20:{}-{}\r\n", created_secs, statement.client_id)?;
+        write!(writer, ":25:{}\r\n", statement.client_id)?;
+        write!(writer, ":28C:1/1\r\n")?;
+        write!(
+            writer,
+            ":60F:{}\r\n",
+            balance(&date, currency, statement.opening)
+        )?;
+
+        for (entry, amount) in statement.bookings() {
+            let (mark, amount) = mark_and_amount(amount);
+            write!(
+                writer,
+                ":61:{}{}{}NMSC{}\r\n",
+                date,
+                mark,
+                swift_amount(amount),
+                entry.tx_id
+            )?;
+            let description = match entry.tx_type {
+                TransactionType::Deposit => "Deposit",
+                TransactionType::Chargeback => "Chargeback",
+                _ => "Withdrawal",
+            };
+            write!(writer, ":86:{} {}\r\n", description, entry.tx_id)?;
+        }
+
+        let closing = &statement.closing;
+        write!(
+            writer,
+            ":62F:{}\r\n",
+            balance(&date, currency, closing.total())
+        )?;
+        write!(
+            writer,
+            ":64:{}\r\n",
+            balance(&date, currency, closing.available)
+        )?;
+        write!(writer, "-\r\n")?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Balance field value, e.g. `C240301EUR123,45`
+fn balance(date: &str, currency: &str, amount: Amount) -> String {
+    let (mark, amount) = mark_and_amount(amount);
+    format!("{}{}{}{}", mark, date, currency, swift_amount(amount))
+}
+
+/// Credit or debit mark and the amount without sign
+fn mark_and_amount(amount: Amount) -> (char, Amount) {
+    if amount < Amount::ZERO {
+        ('D', -amount)
+    } else {
+        ('C', amount)
+    }
+}
+
+/// SWIFT amount: a comma as decimal separator, always present
+fn swift_amount(amount: Amount) -> String {
+    let text = amount.to_string();
+    match text.split_once('.') {
+        Some((whole, fraction)) => format!("{},{}", whole, fraction),
+        None => format!("{},", text),
+    }
+}
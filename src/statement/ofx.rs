@@ -0,0 +1,120 @@
+//! Open Financial Exchange (OFX) bank statements
+//!
+//! Written as OFX 2.2, the XML flavour, which GnuCash, Quicken, Moneydance
+//! and most other personal finance tools import.
+
+use std::io::Write;
+
+use crate::error::Result;
+use crate::models::TransactionType;
+use crate::statement::{escape, Statement, StatementOptions, UtcDateTime};
+
+/// Stands in for the routing number OFX requires on every bank account
+pub const BANK_ID: &str = "PAYMENTSENGINE";
+
+/// Write `statements` as one OFX document, one statement response per client
+///
+/// Each client is a checking account identified by its client ID under
+/// `BANK_ID`. Deposits are `CREDIT`s and withdrawals and chargebacks
+/// `DEBIT`s, with signed amounts; disputes and resolves don't change the
+/// ledger balance and aren't listed. `FITID`s are the transaction IDs, with
+/// a `-chargeback` suffix for chargebacks, so importing a later statement
+/// of the same client skips what was already imported. The ledger balance is
+/// the total and the available balance excludes held funds.
+pub fn write<W: Write>(
+    statements: &[Statement],
+    options: &StatementOptions,
+    mut writer: W,
+) -> Result<()> {
+    let created = ofx_date_time(&UtcDateTime::from_system_time(options.created_at));
+    let currency = escape(&options.currency);
+
+    writeln!(
+        writer,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#
+    )?;
+    writeln!(
+        writer,
+        r#"<?OFX OFXHEADER="200" VERSION="220" SECURITY="NONE" OLDFILEUID="NONE" NEWFILEUID="NONE"?>"#
+    )?;
+    writeln!(writer, "<OFX>")?;
+    writeln!(writer, "  <SIGNONMSGSRSV1>")?;
+    writeln!(writer, "    <SONRS>")?;
+    write_status(&mut writer, "      ")?;
+    writeln!(writer, "      <DTSERVER>{}</DTSERVER>", created)?;
+    writeln!(writer, "      <LANGUAGE>ENG</LANGUAGE>")?;
+    writeln!(writer, "    </SONRS>")?;
+    writeln!(writer, "  </SIGNONMSGSRSV1>")?;
+    writeln!(writer, "  <BANKMSGSRSV1>")?;
+
+    for statement in statements {
+        writeln!(writer, "    <STMTTRNRS>")?;
+        writeln!(writer, "      <TRNUID>{}</TRNUID>", statement.client_id)?;
+        write_status(&mut writer, "      ")?;
+        writeln!(writer, "      <STMTRS>")?;
+        writeln!(writer, "        <CURDEF>{}</CURDEF>", currency)?;
+        writeln!(writer, "        <BANKACCTFROM>")?;
+        writeln!(writer, "          <BANKID>{}</BANKID>", BANK_ID)?;
+        writeln!(writer, "          <ACCTID>{}</ACCTID>", statement.client_id)?;
+        writeln!(writer, "          <ACCTTYPE>CHECKING</ACCTTYPE>")?;
+        writeln!(writer, "        </BANKACCTFROM>")?;
+
+        writeln!(writer, "        <BANKTRANLIST>")?;
+        writeln!(writer, "          <DTSTART>{}</DTSTART>", created)?;
+        writeln!(writer, "          <DTEND>{}</DTEND>", created)?;
+        for (entry, amount) in statement.bookings() {
+            let (kind, name, fitid_suffix) = match entry.tx_type {
+                TransactionType::Deposit => ("CREDIT", "Deposit", ""),
+                TransactionType::Chargeback => ("DEBIT", "Chargeback", "-chargeback"),
+                _ => ("DEBIT", "Withdrawal", ""),
+            };
+            writeln!(writer, "          <STMTTRN>")?;
+            writeln!(writer, "            <TRNTYPE>{}</TRNTYPE>", kind)?;
+            writeln!(writer, "            <DTPOSTED>{}</DTPOSTED>", created)?;
+            writeln!(writer, "            <TRNAMT>{}</TRNAMT>", amount)?;
+            writeln!(
+                writer,
+                "            <FITID>{}{}</FITID>",
+                entry.tx_id, fitid_suffix
+            )?;
+            writeln!(writer, "            <NAME>{}</NAME>", name)?;
+            writeln!(writer, "          </STMTTRN>")?;
+        }
+        writeln!(writer, "        </BANKTRANLIST>")?;
+
+        let closing = &statement.closing;
+        for (element, amount) in [
+            ("LEDGERBAL", closing.total()),
+            ("AVAILBAL", closing.available),
+        ] {
+            writeln!(writer, "        <{}>", element)?;
+            writeln!(writer, "          <BALAMT>{}</BALAMT>", amount)?;
+            writeln!(writer, "          <DTASOF>{}</DTASOF>", created)?;
+            writeln!(writer, "        </{}>", element)?;
+        }
+        writeln!(writer, "      </STMTRS>")?;
+        writeln!(writer, "    </STMTTRNRS>")?;
+    }
+
+    writeln!(writer, "  </BANKMSGSRSV1>")?;
+    writeln!(writer, "</OFX>")?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_status<W: Write>(writer: &mut W, indent: &str) -> Result<()> {
+    writeln!(
+        writer,
+        "{0}<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>",
+        indent
+    )?;
+    Ok(())
+}
+
+/// OFX date and time in UTC, e.g. `20240301093000.000[0:GMT]`
+fn ofx_date_time(time: &UtcDateTime) -> String {
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}.000[0:GMT]",
+        time.year, time.month, time.day, time.hour, time.minute, time.second
+    )
+}
@@ -0,0 +1,64 @@
+//! Quicken Interchange Format (QIF) bank statements
+//!
+//! QIF is plain text and predates OFX; some finance tools only import this.
+
+use std::io::Write;
+
+use crate::amount::Amount;
+use crate::error::Result;
+use crate::models::TransactionType;
+use crate::statement::{Statement, StatementOptions, UtcDateTime};
+
+/// Write `statements` as QIF, one bank account per client
+///
+/// Each client starts a `!Account` block named `Client <id>`. A non-zero
+/// opening balance comes first as an `Opening Balance` transaction, the way
+/// Quicken writes them, followed by a record per deposit, withdrawal and
+/// chargeback with its signed amount and the transaction ID as check number.
+/// Disputes and resolves don't change the balance and aren't listed. Dates
+/// are US-style `MM/DD/YYYY`. QIF has no currencies, so
+/// `StatementOptions::currency` isn't written.
+pub fn write<W: Write>(
+    statements: &[Statement],
+    options: &StatementOptions,
+    mut writer: W,
+) -> Result<()> {
+    let created = UtcDateTime::from_system_time(options.created_at);
+    let date = format!(
+        "{:02}/{:02}/{:04}",
+        created.month, created.day, created.year
+    );
+
+    for statement in statements {
+        let account = format!("Client {}", statement.client_id);
+        writeln!(writer, "!Account")?;
+        writeln!(writer, "N{}", account)?;
+        writeln!(writer, "TBank")?;
+        writeln!(writer, "^")?;
+        writeln!(writer, "!Type:Bank")?;
+
+        if statement.opening != Amount::ZERO {
+            writeln!(writer, "D{}", date)?;
+            writeln!(writer, "T{}", statement.opening)?;
+            writeln!(writer, "POpening Balance")?;
+            writeln!(writer, "L[{}]", account)?;
+            writeln!(writer, "^")?;
+        }
+
+        for (entry, amount) in statement.bookings() {
+            let payee = match entry.tx_type {
+                TransactionType::Deposit => "Deposit",
+                TransactionType::Chargeback => "Chargeback",
+                _ => "Withdrawal",
+            };
+            writeln!(writer, "D{}", date)?;
+            writeln!(writer, "T{}", amount)?;
+            writeln!(writer, "N{}", entry.tx_id)?;
+            writeln!(writer, "P{}", payee)?;
+            writeln!(writer, "^")?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
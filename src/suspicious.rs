@@ -0,0 +1,391 @@
+//! Suspicious-activity reports built from the retained history
+//!
+//! `PaymentsEngine::suspicious_activity` looks through each client's history
+//! for patterns compliance teams review and file reports on:
+//!
+//! - `Pattern::ChargebackCycle`: deposits disputed and charged back soon
+//!   after being made, the mark of funding an account with stolen cards
+//! - `Pattern::FailedWithdrawals`: bursts of rejected withdrawals, probing
+//!   for what an account lets out
+//! - `Pattern::Structuring`: deposits each kept just under the reporting
+//!   threshold that add up to more than it
+//!
+//! Windows are measured in the transactions' own timestamps (Unix seconds)
+//! where the input has them, and otherwise in the engine's logical clock (see
+//! `HistoryEntry::timestamp`), where a window of 100 means within 100
+//! applied transactions. `write_csv` and `write_json` render the result as a
+//! SAR-style report, one row or object per client and pattern.
+//!
+//! ```
+//! use payments_engine::engine::PaymentsEngine;
+//! use payments_engine::models::Transaction;
+//! use payments_engine::suspicious::{Pattern, ReportRules};
+//!
+//! let mut engine = PaymentsEngine::new().retain_history();
+//! engine.process_transaction(Transaction::deposit(1, 1, "500".parse().unwrap()));
+//! engine.process_transaction(Transaction::dispute(1, 1));
+//! engine.process_transaction(Transaction::chargeback(1, 1));
+//!
+//! let report = engine.suspicious_activity(&ReportRules::default());
+//! assert_eq!(report[0].pattern, Pattern::ChargebackCycle);
+//! assert_eq!(report[0].transactions, [1]);
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::io::Write;
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::amount::Amount;
+use crate::error::Result;
+use crate::history::History;
+use crate::models::{ClientId, TransactionType, TxId};
+use crate::statement::UtcDateTime;
+
+/// Which activity gets reported
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportRules {
+    /// Longest time from a deposit to its chargeback that is reported
+    pub cycle_window: u64,
+    /// Rejected withdrawals within `failed_withdrawal_window` of each other
+    /// that are reported
+    pub failed_withdrawals: usize,
+    pub failed_withdrawal_window: u64,
+    /// Amount a single deposit would be reported at anyway
+    pub reporting_threshold: Amount,
+    /// Smallest deposit counted as kept under `reporting_threshold`
+    pub structuring_floor: Amount,
+    /// Longest time over which deposits counted towards structuring add up
+    /// to `reporting_threshold`
+    pub structuring_window: u64,
+}
+
+impl Default for ReportRules {
+    /// Cycles within a week, 3 failed withdrawals within an hour and
+    /// deposits from 8000 up to a 10000 threshold within a day
+    fn default() -> Self {
+        Self {
+            cycle_window: 7 * 86_400,
+            failed_withdrawals: 3,
+            failed_withdrawal_window: 3600,
+            reporting_threshold: "10000".parse().expect("valid amount"),
+            structuring_floor: "8000".parse().expect("valid amount"),
+            structuring_window: 86_400,
+        }
+    }
+}
+
+/// A pattern of suspicious activity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    /// Deposits disputed and charged back within `ReportRules::cycle_window`
+    ChargebackCycle,
+    /// `ReportRules::failed_withdrawals` or more rejected withdrawals within
+    /// `ReportRules::failed_withdrawal_window`
+    FailedWithdrawals,
+    /// Deposits under `ReportRules::reporting_threshold` adding up to it
+    /// within `ReportRules::structuring_window`
+    Structuring,
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ChargebackCycle => "chargeback_cycle",
+            Self::FailedWithdrawals => "failed_withdrawals",
+            Self::Structuring => "structuring",
+        })
+    }
+}
+
+/// One client's activity matching one pattern
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SuspiciousActivity {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    pub pattern: Pattern,
+    /// Time of the earliest transaction involved
+    pub first_seen: u64,
+    /// Time of the latest transaction involved
+    pub last_seen: u64,
+    /// IDs of the transactions involved: the charged-back deposits, the
+    /// rejected withdrawals or the structured deposits
+    pub transactions: Vec<TxId>,
+    /// Total amount of those transactions
+    pub amount: Amount,
+    /// What was seen, for the report's reviewer
+    pub narrative: String,
+}
+
+/// Find the activity matching `rules` in `history`, sorted by client, then
+/// pattern
+pub(crate) fn detect(history: &History, rules: &ReportRules) -> Vec<SuspiciousActivity> {
+    let clients: BTreeSet<ClientId> = history.clients().collect();
+    let mut report = Vec::new();
+
+    for client_id in clients {
+        report.extend(chargeback_cycles(history, client_id, rules));
+        report.extend(failed_withdrawals(history, client_id, rules));
+        report.extend(structuring(history, client_id, rules));
+    }
+
+    report
+}
+
+/// When a transaction happened: its own timestamp, or the logical clock
+fn time(tx_timestamp: Option<u64>, timestamp: u64) -> u64 {
+    tx_timestamp.unwrap_or(timestamp)
+}
+
+/// A transaction a pattern could involve
+struct Candidate {
+    time: u64,
+    tx_id: TxId,
+    amount: Amount,
+}
+
+fn chargeback_cycles(
+    history: &History,
+    client_id: ClientId,
+    rules: &ReportRules,
+) -> Option<SuspiciousActivity> {
+    let entries = history.client_entries(client_id);
+    let deposited: HashMap<TxId, u64> = entries
+        .iter()
+        .filter(|entry| entry.tx_type == TransactionType::Deposit)
+        .map(|entry| (entry.tx_id, time(entry.tx_timestamp, entry.timestamp)))
+        .collect();
+
+    // Each cycle spans from the deposit to its chargeback
+    let mut cycles = Vec::new();
+    let mut last_seen = 0;
+    for entry in entries {
+        if entry.tx_type != TransactionType::Chargeback {
+            continue;
+        }
+        let Some(&deposit_time) = deposited.get(&entry.tx_id) else {
+            continue;
+        };
+        let charged_back = time(entry.tx_timestamp, entry.timestamp);
+        if charged_back.saturating_sub(deposit_time) <= rules.cycle_window {
+            cycles.push(Candidate {
+                time: deposit_time,
+                tx_id: entry.tx_id,
+                amount: entry.amount,
+            });
+            last_seen = last_seen.max(charged_back);
+        }
+    }
+
+    let mut activity = activity(client_id, Pattern::ChargebackCycle, &cycles)?;
+    activity.last_seen = activity.last_seen.max(last_seen);
+    activity.narrative = format!(
+        "{} deposit(s) totalling {} disputed and charged back within {} of being made",
+        cycles.len(),
+        activity.amount,
+        rules.cycle_window
+    );
+    Some(activity)
+}
+
+fn failed_withdrawals(
+    history: &History,
+    client_id: ClientId,
+    rules: &ReportRules,
+) -> Option<SuspiciousActivity> {
+    let mut rejected: Vec<_> = history
+        .client_rejected_withdrawals(client_id)
+        .iter()
+        .map(|withdrawal| {
+            let attempt = Candidate {
+                time: time(withdrawal.tx_timestamp, withdrawal.timestamp),
+                tx_id: withdrawal.tx_id,
+                amount: withdrawal.amount.unwrap_or_default(),
+            };
+            (attempt, withdrawal.reason)
+        })
+        .collect();
+    rejected.sort_by_key(|(attempt, _)| attempt.time);
+    let (attempts, reasons): (Vec<_>, Vec<_>) = rejected.into_iter().unzip();
+    let bursts = in_bursts(&attempts, rules.failed_withdrawal_window, |count, _| {
+        count >= rules.failed_withdrawals.max(1)
+    });
+
+    let reasons: BTreeSet<String> = reasons
+        .iter()
+        .zip(&bursts)
+        .filter(|(_, &in_burst)| in_burst)
+        .map(|(reason, _)| reason.to_string())
+        .collect();
+    let flagged = keep(attempts, bursts);
+    let mut activity = activity(client_id, Pattern::FailedWithdrawals, &flagged)?;
+    activity.narrative = format!(
+        "{} withdrawal(s) totalling {} rejected, {} or more within {} ({})",
+        flagged.len(),
+        activity.amount,
+        rules.failed_withdrawals,
+        rules.failed_withdrawal_window,
+        reasons.into_iter().collect::<Vec<_>>().join(", ")
+    );
+    Some(activity)
+}
+
+fn structuring(
+    history: &History,
+    client_id: ClientId,
+    rules: &ReportRules,
+) -> Option<SuspiciousActivity> {
+    let mut deposits: Vec<_> = history
+        .client_entries(client_id)
+        .iter()
+        .filter(|entry| {
+            entry.tx_type == TransactionType::Deposit
+                && entry.amount >= rules.structuring_floor
+                && entry.amount < rules.reporting_threshold
+        })
+        .map(|entry| Candidate {
+            time: time(entry.tx_timestamp, entry.timestamp),
+            tx_id: entry.tx_id,
+            amount: entry.amount,
+        })
+        .collect();
+    deposits.sort_by_key(|deposit| deposit.time);
+    let bursts = in_bursts(&deposits, rules.structuring_window, |_, total| {
+        total >= rules.reporting_threshold
+    });
+    let flagged = keep(deposits, bursts);
+
+    let mut activity = activity(client_id, Pattern::Structuring, &flagged)?;
+    activity.narrative = format!(
+        "{} deposit(s) of {} to under {} adding up to {} within {}",
+        flagged.len(),
+        rules.structuring_floor,
+        rules.reporting_threshold,
+        activity.amount,
+        rules.structuring_window
+    );
+    Some(activity)
+}
+
+/// Which candidates belong to a run within `window` of each other that
+/// `qualifies`, given its count and total amount
+///
+/// Candidates must be in time order. Amounts aren't negative, so the longest
+/// run ending at a candidate is the one most likely to qualify.
+fn in_bursts(
+    candidates: &[Candidate],
+    window: u64,
+    qualifies: impl Fn(usize, Amount) -> bool,
+) -> Vec<bool> {
+    let mut flagged = vec![false; candidates.len()];
+    let mut start = 0;
+    let mut total = Amount::ZERO;
+
+    for (end, candidate) in candidates.iter().enumerate() {
+        total += candidate.amount;
+        while candidate.time.saturating_sub(candidates[start].time) > window {
+            total -= candidates[start].amount;
+            start += 1;
+        }
+        if qualifies(end - start + 1, total) {
+            flagged[start..=end].fill(true);
+        }
+    }
+
+    flagged
+}
+
+/// The candidates `flags` marks
+fn keep(candidates: Vec<Candidate>, flags: Vec<bool>) -> Vec<Candidate> {
+    candidates
+        .into_iter()
+        .zip(flags)
+        .filter_map(|(candidate, flagged)| flagged.then_some(candidate))
+        .collect()
+}
+
+/// Activity made of `candidates`, `None` if there are none; the narrative
+/// is left for the caller
+fn activity(
+    client_id: ClientId,
+    pattern: Pattern,
+    candidates: &[Candidate],
+) -> Option<SuspiciousActivity> {
+    let mut activity = SuspiciousActivity {
+        client_id,
+        pattern,
+        first_seen: u64::MAX,
+        last_seen: 0,
+        transactions: Vec::new(),
+        amount: Amount::ZERO,
+        narrative: String::new(),
+    };
+    for candidate in candidates {
+        activity.first_seen = activity.first_seen.min(candidate.time);
+        activity.last_seen = activity.last_seen.max(candidate.time);
+        activity.transactions.push(candidate.tx_id);
+        activity.amount += candidate.amount;
+    }
+    (!activity.transactions.is_empty()).then_some(activity)
+}
+
+/// Write `report` as CSV, one row per client and pattern
+///
+/// Transaction IDs are separated by spaces. Writes a header row even if
+/// nothing was found.
+pub fn write_csv<W: Write>(report: &[SuspiciousActivity], writer: W) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "client",
+        "pattern",
+        "first_seen",
+        "last_seen",
+        "transactions",
+        "amount",
+        "narrative",
+    ])?;
+    for activity in report {
+        let transactions: Vec<_> = activity.transactions.iter().map(TxId::to_string).collect();
+        writer.write_record([
+            activity.client_id.to_string(),
+            activity.pattern.to_string(),
+            activity.first_seen.to_string(),
+            activity.last_seen.to_string(),
+            transactions.join(" "),
+            activity.amount.to_string(),
+            activity.narrative.clone(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A report as written by `write_json`
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    generated_at: String,
+    rules: &'a ReportRules,
+    activity: &'a [SuspiciousActivity],
+}
+
+/// Write `report` as one JSON object, along with when it was generated and
+/// the rules it was generated with
+pub fn write_json<W: Write>(
+    report: &[SuspiciousActivity],
+    rules: &ReportRules,
+    generated_at: SystemTime,
+    mut writer: W,
+) -> Result<()> {
+    let report = JsonReport {
+        generated_at: UtcDateTime::from_system_time(generated_at).iso_date_time(),
+        rules,
+        activity: report,
+    };
+    serde_json::to_writer_pretty(&mut writer, &report)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
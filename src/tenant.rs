@@ -0,0 +1,149 @@
+//! Processing for several tenants in one engine
+//!
+//! Each tenant, e.g. a partner the engine processes payments for, gets its
+//! own `PaymentsEngine`, so accounts are keyed by tenant and client ID and
+//! every tenant has its own transaction ID namespace: two tenants can both
+//! use client 1 and transaction 1 without seeing each other. Tenants are
+//! created on their first transaction.
+//!
+//! Tenant IDs are free-form strings. The empty ID is the default tenant,
+//! which input without a `tenant` column goes to.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use crate::config::EngineConfig;
+use crate::engine::{AccountOrdering, PaymentsEngine};
+use crate::error::Result;
+use crate::models::{Account, ClientId, Transaction};
+use crate::outcome::Outcome;
+use crate::CsvRows;
+
+/// Engines for several tenants, keyed by tenant ID
+pub struct MultiTenantEngine {
+    /// Configuration every tenant's engine is created with
+    config: EngineConfig,
+    /// Kept sorted so output is grouped by tenant in a stable order
+    tenants: BTreeMap<String, PaymentsEngine>,
+}
+
+impl MultiTenantEngine {
+    /// Create an engine without tenants
+    ///
+    /// Tenants store accounts by client ID, so `accounts` yields them in
+    /// order without sorting.
+    pub fn new() -> Self {
+        Self::with_config(EngineConfig {
+            account_ordering: AccountOrdering::ByClientId,
+            ..EngineConfig::default()
+        })
+    }
+
+    /// Create an engine whose tenants all use `config`
+    pub fn with_config(config: EngineConfig) -> Self {
+        Self {
+            config,
+            tenants: BTreeMap::new(),
+        }
+    }
+
+    /// Process a transaction for `tenant`, creating the tenant if it is new
+    pub fn process_transaction(&mut self, tenant: &str, tx: Transaction) -> Outcome {
+        if !self.tenants.contains_key(tenant) {
+            let engine = PaymentsEngine::with_config(self.config.clone());
+            self.tenants.insert(tenant.to_string(), engine);
+        }
+        self.tenants
+            .get_mut(tenant)
+            .expect("tenant was just created")
+            .process_transaction(tx)
+    }
+
+    /// The engine holding `tenant`'s accounts, if it has any transactions
+    pub fn tenant(&self, tenant: &str) -> Option<&PaymentsEngine> {
+        self.tenants.get(tenant)
+    }
+
+    /// IDs of all tenants, sorted
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.tenants.keys().map(String::as_str)
+    }
+
+    /// Get a client's account within `tenant`
+    pub fn get_account(&self, tenant: &str, client_id: ClientId) -> Option<&Account> {
+        self.tenant(tenant)?.get_account(client_id)
+    }
+
+    /// All accounts with their tenant, sorted by tenant and then client ID
+    ///
+    /// With an `AccountOrdering::Unordered` configuration, accounts are
+    /// sorted by tenant only.
+    pub fn accounts(&self) -> impl Iterator<Item = (&str, &Account)> {
+        self.tenants.iter().flat_map(|(tenant, engine)| {
+            engine
+                .accounts_iter()
+                .map(move |account| (tenant.as_str(), account))
+        })
+    }
+
+    /// Consume the engine and return each tenant's engine
+    pub fn into_tenants(self) -> BTreeMap<String, PaymentsEngine> {
+        self.tenants
+    }
+}
+
+impl Default for MultiTenantEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Transactions with their tenant from CSV input, skipping malformed rows
+///
+/// The input is the usual `type,client,tx,amount` CSV plus a `tenant`
+/// column in any position. Without that column, or with it left empty,
+/// transactions belong to the default tenant `""`.
+pub fn read_tenant_transactions<R: Read>(reader: R) -> impl Iterator<Item = (String, Transaction)> {
+    let mut rows = CsvRows::new(reader);
+    let tenant_column = rows.headers().iter().position(|name| name == b"tenant");
+    std::iter::from_fn(move || {
+        let mut transaction = rows.next()?;
+        transaction.metadata.remove("tenant");
+        let tenant = tenant_column
+            .and_then(|column| rows.record().get(column))
+            .map(|tenant| String::from_utf8_lossy(tenant).into_owned())
+            .unwrap_or_default();
+        Some((tenant, transaction))
+    })
+}
+
+/// Apply every transaction from a CSV reader with a `tenant` column
+///
+/// See `read_tenant_transactions` for the input format.
+pub fn apply_tenant_transactions<R: Read>(engine: &mut MultiTenantEngine, reader: R) {
+    for (tenant, transaction) in read_tenant_transactions(reader) {
+        engine.process_transaction(&tenant, transaction);
+    }
+}
+
+/// Write accounts as CSV with a `tenant,client,available,held,total,locked`
+/// header, limited to `only` if any tenants are given there
+pub fn write_tenant_accounts<W: Write>(
+    engine: &MultiTenantEngine,
+    only: &[String],
+    writer: W,
+) -> Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    writer.write_record(["tenant", "client", "available", "held", "total", "locked"])?;
+    for (tenant, account) in engine.accounts() {
+        if !only.is_empty() && !only.iter().any(|wanted| wanted == tenant) {
+            continue;
+        }
+        writer.write_field(tenant)?;
+        writer.serialize(account.csv_row())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,275 @@
+//! Support for testing the engine and code built on it
+//!
+//! Built with the `testing` feature. `ValidSequence` and
+//! `AdversarialSequence` implement `arbitrary::Arbitrary`, so they plug into
+//! cargo-fuzz, and through `Unstructured` into proptest or quickcheck.
+//! `generate` turns a plain seed into either, for ordinary `#[test]` loops:
+//!
+//! ```
+//! use payments_engine::engine::PaymentsEngine;
+//! use payments_engine::testing::{generate, AdversarialSequence};
+//!
+//! for seed in 0..100 {
+//!     let AdversarialSequence(transactions) = generate(seed);
+//!     let mut engine = PaymentsEngine::new();
+//!     for tx in transactions {
+//!         engine.process_transaction(tx);
+//!     }
+//!     assert!(engine.check_invariants().is_ok(), "seed {}", seed);
+//! }
+//! ```
+//!
+//! Sequences spread over a few clients so that transactions on the same
+//! account interact. Amounts are positive with at most four decimal places
+//! unless noted.
+//!
+//! `golden` compares the engine's output for fixture files with stored
+//! golden files.
+
+pub mod golden;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::amount::{Amount, FixedAmount};
+use crate::models::{ClientId, Metadata, Transaction, TransactionType, TxId};
+use crate::workload::SplitMix64;
+
+/// Most clients a sequence spreads over
+const MAX_CLIENTS: ClientId = 8;
+/// Most transactions in a sequence
+const MAX_LENGTH: u32 = 256;
+/// Bytes of input `generate` draws from its seed, enough for the longest
+/// sequences
+const GENERATED_BYTES: usize = 16 * 1024;
+
+/// Transactions a well-behaved client could send
+///
+/// Deposits and withdrawals have unique transaction IDs and positive
+/// amounts. Disputes are raised by the deposit's owner on a deposit not
+/// already under dispute, and only open disputes are resolved or charged
+/// back. The engine may still reject some, e.g. withdrawals beyond the
+/// balance or transactions on a locked account, and then later steps that
+/// depend on them, but never as duplicates, for the wrong client or for
+/// their amount.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidSequence(pub Vec<Transaction>);
+
+/// Valid transactions mixed with the mistakes and attacks the engine must
+/// reject without corrupting any account
+///
+/// Besides everything in `ValidSequence`, these contain:
+///
+/// - repeated transactions, and new transactions reusing an earlier ID
+/// - disputes, resolves and chargebacks naming another client's deposit
+/// - lifecycle steps out of order: resolving or charging back a deposit
+///   that isn't disputed, disputing one twice, disputing a withdrawal or a
+///   transaction that never existed
+/// - deposits and withdrawals with a zero, negative or missing amount
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdversarialSequence(pub Vec<Transaction>);
+
+impl<'a> Arbitrary<'a> for ValidSequence {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut sequence = Sequence::new(u)?;
+        let length = u.int_in_range(1..=MAX_LENGTH)?;
+        for _ in 0..length {
+            sequence.valid_step(u)?;
+        }
+        Ok(Self(sequence.transactions))
+    }
+}
+
+impl<'a> Arbitrary<'a> for AdversarialSequence {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut sequence = Sequence::new(u)?;
+        let length = u.int_in_range(1..=MAX_LENGTH)?;
+        for _ in 0..length {
+            // Enough valid traffic that the attacks have something to aim at
+            if u.ratio(1u8, 3)? {
+                sequence.adversarial_step(u)?;
+            } else {
+                sequence.valid_step(u)?;
+            }
+        }
+        Ok(Self(sequence.transactions))
+    }
+}
+
+/// Generate a `T` from a seed; the same seed always gives the same value
+pub fn generate<T: for<'a> Arbitrary<'a>>(seed: u64) -> T {
+    let mut rng = SplitMix64::new(seed);
+    let bytes: Vec<u8> = (0..GENERATED_BYTES / 8)
+        .flat_map(|_| rng.next_u64().to_le_bytes())
+        .collect();
+    T::arbitrary(&mut Unstructured::new(&bytes)).expect("generators can't run out of input")
+}
+
+/// A sequence being built, with what later steps may refer to
+struct Sequence {
+    clients: ClientId,
+    next_tx: TxId,
+    transactions: Vec<Transaction>,
+    /// Deposits not under dispute, as (client, tx)
+    deposits: Vec<(ClientId, TxId)>,
+    /// Deposits under dispute, as (client, tx)
+    disputed: Vec<(ClientId, TxId)>,
+    /// Withdrawals, as (client, tx)
+    withdrawals: Vec<(ClientId, TxId)>,
+}
+
+impl Sequence {
+    fn new(u: &mut Unstructured) -> Result<Self> {
+        Ok(Self {
+            clients: u.int_in_range(1..=MAX_CLIENTS)?,
+            next_tx: 1,
+            transactions: Vec::new(),
+            deposits: Vec::new(),
+            disputed: Vec::new(),
+            withdrawals: Vec::new(),
+        })
+    }
+
+    fn client(&self, u: &mut Unstructured) -> Result<ClientId> {
+        u.int_in_range(1..=self.clients)
+    }
+
+    fn take_tx_id(&mut self) -> TxId {
+        let tx = self.next_tx;
+        self.next_tx += 1;
+        tx
+    }
+
+    /// Append a transaction a well-behaved client could send
+    fn valid_step(&mut self, u: &mut Unstructured) -> Result<()> {
+        let transaction = match u.int_in_range(0..=9u8)? {
+            // Settle an open dispute
+            0..=1 if !self.disputed.is_empty() => {
+                let index = u.choose_index(self.disputed.len())?;
+                let (client, tx) = self.disputed.swap_remove(index);
+                if u.ratio(1u8, 4)? {
+                    Transaction::chargeback(client, tx)
+                } else {
+                    // A resolved deposit can be disputed again
+                    self.deposits.push((client, tx));
+                    Transaction::resolve(client, tx)
+                }
+            }
+            2..=3 if !self.deposits.is_empty() => {
+                let index = u.choose_index(self.deposits.len())?;
+                let (client, tx) = self.deposits.swap_remove(index);
+                self.disputed.push((client, tx));
+                Transaction::dispute(client, tx)
+            }
+            4..=5 => {
+                let (client, tx) = (self.client(u)?, self.take_tx_id());
+                self.withdrawals.push((client, tx));
+                Transaction::withdrawal(client, tx, amount(u)?)
+            }
+            _ => {
+                let (client, tx) = (self.client(u)?, self.take_tx_id());
+                self.deposits.push((client, tx));
+                Transaction::deposit(client, tx, amount(u)?)
+            }
+        };
+        self.transactions.push(transaction);
+        Ok(())
+    }
+
+    /// Append a transaction the engine has to reject, or at least survive
+    fn adversarial_step(&mut self, u: &mut Unstructured) -> Result<()> {
+        let transaction = match u.int_in_range(0..=7u8)? {
+            // Exact repeat, as a retrying client would send
+            0 if !self.transactions.is_empty() => u.choose(&self.transactions)?.clone(),
+            // A new transaction reusing an earlier ID
+            1 if self.next_tx > 1 => {
+                let tx = u.int_in_range(1..=self.next_tx - 1)?;
+                Transaction::deposit(self.client(u)?, tx, amount(u)?)
+            }
+            // Someone else's deposit, under dispute or not
+            2 => {
+                let (owner, tx) =
+                    choose_either(u, &self.deposits, &self.disputed)?.unwrap_or((0, self.next_tx));
+                let mut client = self.client(u)?;
+                if client == owner && self.clients > 1 {
+                    client = client % self.clients + 1;
+                }
+                let step = [
+                    Transaction::dispute,
+                    Transaction::resolve,
+                    Transaction::chargeback,
+                ];
+                u.choose(&step)?(client, tx)
+            }
+            // Settling a deposit that isn't disputed
+            3 if !self.deposits.is_empty() => {
+                let &(client, tx) = u.choose(&self.deposits)?;
+                if u.arbitrary()? {
+                    Transaction::resolve(client, tx)
+                } else {
+                    Transaction::chargeback(client, tx)
+                }
+            }
+            // Disputing what is already disputed, or a withdrawal
+            4 if !self.disputed.is_empty() || !self.withdrawals.is_empty() => {
+                let (client, tx) = choose_either(u, &self.disputed, &self.withdrawals)?
+                    .expect("checked by the match guard");
+                Transaction::dispute(client, tx)
+            }
+            // A transaction that never existed
+            5 => Transaction::dispute(self.client(u)?, u.int_in_range(self.next_tx..=TxId::MAX)?),
+            // Bad amounts, under a fresh ID so they aren't also duplicates
+            _ => Transaction {
+                tx_type: *u.choose(&[TransactionType::Deposit, TransactionType::Withdrawal])?,
+                client: self.client(u)?,
+                tx: self.take_tx_id(),
+                amount: match u.int_in_range(0..=2u8)? {
+                    0 => None,
+                    1 => Some(Amount::ZERO),
+                    _ => Some(-amount(u)?),
+                },
+                timestamp: None,
+                metadata: Metadata::new(),
+            },
+        };
+        self.transactions.push(transaction);
+        Ok(())
+    }
+}
+
+/// An entry of `first` or `second`, all equally likely; `None` if both are empty
+fn choose_either(
+    u: &mut Unstructured,
+    first: &[(ClientId, TxId)],
+    second: &[(ClientId, TxId)],
+) -> Result<Option<(ClientId, TxId)>> {
+    if first.is_empty() && second.is_empty() {
+        return Ok(None);
+    }
+    let index = u.choose_index(first.len() + second.len())?;
+    Ok(Some(
+        first
+            .get(index)
+            .copied()
+            .unwrap_or_else(|| second[index - first.len()]),
+    ))
+}
+
+/// A positive amount of at most four decimal places, mostly small
+fn amount(u: &mut Unstructured) -> Result<Amount> {
+    let units = if u.ratio(1u8, 10)? {
+        u.int_in_range(1..=1_000_000_000_000i64)?
+    } else {
+        u.int_in_range(1..=10_000_000i64)?
+    };
+    Ok(from_minor_units(units))
+}
+
+#[cfg(not(feature = "fixed-point"))]
+fn from_minor_units(units: i64) -> Amount {
+    FixedAmount::from_minor_units(units).into()
+}
+
+#[cfg(feature = "fixed-point")]
+fn from_minor_units(units: i64) -> Amount {
+    FixedAmount::from_minor_units(units)
+}
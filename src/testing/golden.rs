@@ -0,0 +1,202 @@
+//! Golden-file regression checks
+//!
+//! A fixture is a transactions CSV; its golden file holds the accounts the
+//! engine should end up with. Both sides are canonicalized before comparing,
+//! so a golden file is free to be written by hand: rows are sorted by
+//! client and every amount has exactly four decimal places. A mismatch
+//! panics with a line diff of the two.
+//!
+//! Set `UPDATE_GOLDEN=1` to write the actual output to the golden files
+//! instead, then review the change like any other diff.
+//!
+//! ```no_run
+//! use payments_engine::testing::golden::assert_golden;
+//!
+//! assert_golden("tests/fixtures/disputes.csv", "tests/golden/disputes.csv");
+//! ```
+
+use std::fs::{self, File};
+use std::path::Path;
+
+use crate::amount::{Amount, FixedAmount};
+use crate::engine::{AccountOrdering, PaymentsEngine};
+use crate::error::Result;
+use crate::{process_transactions_with_engine, read_accounts};
+
+/// Environment variable that makes the assertions rewrite golden files
+pub const UPDATE_ENV: &str = "UPDATE_GOLDEN";
+
+/// Header of canonical output
+const HEADER: &str = "client,available,held,total,locked";
+
+/// Run the transactions in `input` through a new engine and return the
+/// resulting accounts, canonicalized
+pub fn run_fixture(input: impl AsRef<Path>) -> Result<String> {
+    run_fixture_with_engine(
+        PaymentsEngine::with_account_ordering(AccountOrdering::ByClientId),
+        input,
+    )
+}
+
+/// `run_fixture` on top of an existing engine, e.g. one with limits
+/// configured or initial balances loaded
+pub fn run_fixture_with_engine(engine: PaymentsEngine, input: impl AsRef<Path>) -> Result<String> {
+    let mut output = Vec::new();
+    process_transactions_with_engine(engine, File::open(input)?, &mut output)?;
+    canonicalize(&output)
+}
+
+/// Accounts CSV in canonical form: the output columns, rows sorted by
+/// client, amounts with four decimal places
+///
+/// Reads anything `read_accounts` does, so `total` may be left out.
+pub fn canonicalize(accounts: &[u8]) -> Result<String> {
+    let mut accounts = read_accounts(accounts)?;
+    accounts.sort_by_key(|account| account.client_id);
+
+    let mut canonical = format!("{}\n", HEADER);
+    for account in accounts {
+        canonical.push_str(&format!(
+            "{},{},{},{},{}\n",
+            account.client_id,
+            fixed_scale(account.available),
+            fixed_scale(account.held),
+            fixed_scale(account.total()),
+            account.locked
+        ));
+    }
+    Ok(canonical)
+}
+
+/// Run `input` and compare the result with the golden file `golden`
+///
+/// # Panics
+///
+/// Panics with a diff if they differ, if the golden file is missing, or if
+/// either file can't be read.
+pub fn assert_golden(input: impl AsRef<Path>, golden: impl AsRef<Path>) {
+    let input = input.as_ref();
+    let actual = run_fixture(input)
+        .unwrap_or_else(|e| panic!("failed to run fixture '{}': {}", input.display(), e));
+    assert_matches_golden(&actual, golden);
+}
+
+/// Compare accounts CSV produced some other way with the golden file `golden`
+///
+/// # Panics
+///
+/// As `assert_golden`.
+pub fn assert_matches_golden(actual: &str, golden: impl AsRef<Path>) {
+    let golden = golden.as_ref();
+    let actual = canonicalize(actual.as_bytes()).unwrap_or_else(|e| {
+        panic!(
+            "output for '{}' is not accounts CSV: {}",
+            golden.display(),
+            e
+        )
+    });
+
+    if updating() {
+        if let Some(dir) = golden.parent() {
+            fs::create_dir_all(dir).expect("failed to create golden file directory");
+        }
+        fs::write(golden, &actual)
+            .unwrap_or_else(|e| panic!("failed to write '{}': {}", golden.display(), e));
+        return;
+    }
+
+    let expected = match fs::read(golden) {
+        Ok(expected) => expected,
+        Err(e) => panic!(
+            "failed to read golden file '{}': {}; run with {}=1 to create it",
+            golden.display(),
+            e,
+            UPDATE_ENV
+        ),
+    };
+    let expected = canonicalize(&expected)
+        .unwrap_or_else(|e| panic!("golden file '{}' is invalid: {}", golden.display(), e));
+
+    if let Some(diff) = diff(&expected, &actual) {
+        panic!(
+            "output differs from golden file '{}' (-golden +actual); run with {}=1 to accept it:\n{}",
+            golden.display(),
+            UPDATE_ENV,
+            diff
+        );
+    }
+}
+
+/// Line diff of two texts, `None` if they are equal
+///
+/// Lines only in `expected` start with `-`, lines only in `actual` with `+`
+/// and lines in both with a space.
+pub fn diff(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    // Longest common subsequence of every pair of suffixes
+    let mut common = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        let line = if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+            format!(" {}", actual[j - 1])
+        } else if j == actual.len() || (i < expected.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            i += 1;
+            format!("-{}", expected[i - 1])
+        } else {
+            j += 1;
+            format!("+{}", actual[j - 1])
+        };
+        diff.push_str(&line);
+        diff.push('\n');
+    }
+    Some(diff)
+}
+
+fn updating() -> bool {
+    std::env::var(UPDATE_ENV).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+/// An amount with exactly four decimal places
+///
+/// Amounts with more places, which only a `max_decimal_places` above four
+/// lets through, are written as they are.
+fn fixed_scale(amount: Amount) -> String {
+    let Some(units) = minor_units(amount) else {
+        return amount.to_string();
+    };
+    let sign = if units < 0 { "-" } else { "" };
+    let units = units.unsigned_abs();
+    let unit = 10u64.pow(FixedAmount::SCALE);
+    format!("{}{}.{:04}", sign, units / unit, units % unit)
+}
+
+#[cfg(not(feature = "fixed-point"))]
+fn minor_units(amount: Amount) -> Option<i64> {
+    FixedAmount::try_from(amount)
+        .ok()
+        .map(FixedAmount::minor_units)
+}
+
+#[cfg(feature = "fixed-point")]
+fn minor_units(amount: Amount) -> Option<i64> {
+    Some(amount.minor_units())
+}
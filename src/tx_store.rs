@@ -0,0 +1,542 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::slice;
+
+#[cfg(not(feature = "wide-tx-ids"))]
+use roaring::RoaringBitmap;
+#[cfg(feature = "wide-tx-ids")]
+use roaring::RoaringTreemap;
+
+use crate::amount;
+use crate::archive::ArchiveBackend;
+use crate::config::RetentionPolicy;
+use crate::error::Result;
+use crate::memory;
+use crate::models::{ClientId, StoredTransaction, TransactionType, TxId};
+
+/// Bytes of the client ID at the start of a record: 2, or 8 with the
+/// `wide-client-ids` feature
+const CLIENT_SIZE: usize = std::mem::size_of::<ClientId>();
+
+/// Bytes per spilled or archived entry: client ID, amount, transaction type,
+/// whether there is a timestamp and the timestamp
+pub(crate) const RECORD_SIZE: u64 = (CLIENT_SIZE + 16 + 1 + 1 + 8) as u64;
+
+/// Compressed set of transaction IDs
+#[cfg(not(feature = "wide-tx-ids"))]
+pub(crate) type TxIdSet = RoaringBitmap;
+
+/// Compressed set of transaction IDs; a treemap of bitmaps, since those only
+/// take 32-bit values
+#[cfg(feature = "wide-tx-ids")]
+pub(crate) type TxIdSet = RoaringTreemap;
+
+/// Disputable transaction storage backing `PaymentsEngine`
+///
+/// Entries live in memory until a spill file is attached. After that, once
+/// more than `max_in_memory` entries are held, the oldest undisputed ones are
+/// written out and dropped from memory, and brought back the next time a
+/// dispute, resolve or chargeback references them. Entries under dispute are
+/// never spilled, so open disputes can always be listed from memory.
+///
+/// Independently, a `RetentionPolicy` passed to `tick` drops old entries for
+/// good, wherever they are stored, writing them to the archive first if one
+/// is attached.
+#[derive(Default)]
+pub(crate) struct TransactionStore {
+    hot: Slab,
+    spill: Option<SpillFile>,
+    archive: Option<Box<dyn ArchiveBackend>>,
+    /// IDs of entries dropped under the retention policy
+    evicted: TxIdSet,
+    /// Every entry's ID with the time it was stored, oldest first; only kept
+    /// while a retention policy is in force
+    timeline: Option<VecDeque<(TxId, u64)>>,
+    /// Applied transactions counted by `tick`, the clock entry ages are measured on
+    clock: u64,
+}
+
+/// In-memory tier: entries packed side by side in one `Vec`
+///
+/// The index only maps IDs to positions, so it stays small, and scans such
+/// as listing open disputes walk contiguous memory. Removal moves the last
+/// entry into the freed slot, keeping the entries dense.
+#[derive(Default)]
+struct Slab {
+    entries: Vec<StoredTransaction>,
+    /// Position of each entry in `entries`, by transaction ID
+    index: HashMap<TxId, usize>,
+}
+
+/// On-disk tier: a sparse file with one fixed-size record per transaction ID
+///
+/// A record's offset is derived from its transaction ID, so the file is its
+/// own index and spilled entries cost no memory beyond one bit each.
+struct SpillFile {
+    file: File,
+    /// IDs with a record in the file
+    spilled: TxIdSet,
+    /// IDs in the order they entered memory, oldest first; may name entries
+    /// that have since left memory
+    order: VecDeque<TxId>,
+    max_in_memory: usize,
+}
+
+impl TransactionStore {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spill entries beyond `max_in_memory` to a new file at `path`
+    ///
+    /// Any existing file at `path` is truncated. Entries already in memory
+    /// are spilled lowest ID first.
+    pub(crate) fn attach_spill_file(
+        &mut self,
+        path: &Path,
+        max_in_memory: usize,
+    ) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        let mut order: Vec<TxId> = self.hot.keys().copied().collect();
+        order.sort_unstable();
+        self.spill = Some(SpillFile {
+            file,
+            spilled: TxIdSet::new(),
+            order: order.into(),
+            max_in_memory,
+        });
+        self.spill_excess();
+        Ok(())
+    }
+
+    /// Archive entries to `archive` when the retention policy drops them
+    pub(crate) fn attach_archive(&mut self, archive: Box<dyn ArchiveBackend>) {
+        self.archive = Some(archive);
+    }
+
+    /// An entry the archive holds, `None` if it holds none or there is no
+    /// archive
+    pub(crate) fn archived(&self, tx_id: TxId) -> Result<Option<StoredTransaction>> {
+        match &self.archive {
+            Some(archive) => archive.fetch(tx_id),
+            None => Ok(None),
+        }
+    }
+
+    /// Make the archived entries durable
+    pub(crate) fn sync_archive(&mut self) -> Result<()> {
+        match &mut self.archive {
+            Some(archive) => archive.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of entries, in memory and spilled
+    pub(crate) fn len(&self) -> usize {
+        let spilled = self.spill.as_ref().map_or(0, |s| s.spilled.len());
+        self.hot.len() + spilled as usize
+    }
+
+    /// Look up an entry held in memory; spilled entries are not loaded
+    pub(crate) fn get(&self, tx_id: &TxId) -> Option<&StoredTransaction> {
+        self.hot.get(tx_id)
+    }
+
+    /// Copy of an entry, read from the spill file if it was spilled but left
+    /// there
+    pub(crate) fn peek(&self, tx_id: TxId) -> io::Result<Option<StoredTransaction>> {
+        if let Some(stored_tx) = self.hot.get(&tx_id) {
+            return Ok(Some(stored_tx.clone()));
+        }
+        match &self.spill {
+            Some(spill) if spill.spilled.contains(tx_id) => spill.read(tx_id).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Look up an entry, loading it back into memory if it was spilled
+    ///
+    /// A loaded entry stays in memory until a later insert makes room.
+    pub(crate) fn get_mut(&mut self, tx_id: &TxId) -> io::Result<Option<&mut StoredTransaction>> {
+        if !self.hot.contains_key(tx_id) {
+            let Some(stored_tx) = self.load(*tx_id)? else {
+                return Ok(None);
+            };
+            self.hot.insert(stored_tx);
+        }
+        Ok(self.hot.get_mut(tx_id))
+    }
+
+    /// Make room for `additional` more entries in memory without reallocating
+    ///
+    /// With a spill file attached, memory never holds more than its limit,
+    /// so the reservation is capped there.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        let additional = match &self.spill {
+            Some(spill) => additional.min(spill.max_in_memory.saturating_sub(self.hot.len())),
+            None => additional,
+        };
+        self.hot.entries.reserve(additional);
+        self.hot.index.reserve(additional);
+    }
+
+    /// Whether `tx_id` was dropped under the retention policy
+    pub(crate) fn is_evicted(&self, tx_id: TxId) -> bool {
+        self.evicted.contains(tx_id)
+    }
+
+    /// Store a new entry, stamped with the time of the transaction being applied
+    pub(crate) fn insert(&mut self, stored_tx: StoredTransaction) {
+        let tx_id = stored_tx.tx_id;
+        self.hot.insert(stored_tx);
+        if let Some(timeline) = &mut self.timeline {
+            timeline.push_back((tx_id, self.clock + 1));
+        }
+        if let Some(spill) = &mut self.spill {
+            spill.order.push_back(tx_id);
+        }
+        self.spill_excess();
+    }
+
+    /// Estimated heap bytes held by entries in memory and by the bookkeeping
+    /// for spilled and evicted ones
+    pub(crate) fn memory_footprint(&self) -> usize {
+        let spill = self.spill.as_ref().map_or(0, |spill| {
+            memory::bitmap_bytes(&spill.spilled) + memory::vec_deque_bytes(&spill.order)
+        });
+        let timeline = self.timeline.as_ref().map_or(0, memory::vec_deque_bytes);
+        memory::vec_bytes(&self.hot.entries)
+            + memory::hash_map_bytes(&self.hot.index)
+            + spill
+            + memory::bitmap_bytes(&self.evicted)
+            + timeline
+    }
+
+    /// Iterate over the entries held in memory, which include every open dispute
+    pub(crate) fn in_memory_values(&self) -> slice::Iter<'_, StoredTransaction> {
+        self.hot.values()
+    }
+
+    /// Every entry, reading spilled ones back from disk
+    pub(crate) fn all(&self) -> io::Result<Vec<StoredTransaction>> {
+        let mut entries: Vec<_> = self.hot.values().cloned().collect();
+        if let Some(spill) = &self.spill {
+            entries.reserve(spill.spilled.len() as usize);
+            for tx_id in &spill.spilled {
+                entries.push(spill.read(tx_id)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Drop every entry of `client_id`, returning how many there were
+    ///
+    /// Spilled records are overwritten with zeros so the file doesn't keep
+    /// them either. Finding them means reading every spilled record back.
+    pub(crate) fn remove_client(&mut self, client_id: ClientId) -> io::Result<usize> {
+        let in_memory: Vec<TxId> = self
+            .hot
+            .values()
+            .filter(|stored_tx| stored_tx.client_id == client_id)
+            .map(|stored_tx| stored_tx.tx_id)
+            .collect();
+        for tx_id in &in_memory {
+            self.hot.remove(tx_id);
+        }
+
+        let mut removed = in_memory.len();
+        if let Some(spill) = &mut self.spill {
+            let mut spilled = Vec::new();
+            for tx_id in &spill.spilled {
+                if spill.read(tx_id)?.client_id == client_id {
+                    spilled.push(tx_id);
+                }
+            }
+            for tx_id in spilled {
+                spill.clear(tx_id)?;
+                spill.spilled.remove(tx_id);
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Advance the clock by one applied transaction and drop the entries
+    /// `retention` no longer allows
+    ///
+    /// Entries under dispute are kept and restamped with the current time.
+    pub(crate) fn tick(&mut self, retention: &RetentionPolicy) {
+        self.clock += 1;
+        if !retention.is_limited() {
+            self.timeline = None;
+            return;
+        }
+
+        let mut timeline = match self.timeline.take() {
+            Some(timeline) => timeline,
+            None => self.ids().into_iter().map(|id| (id, self.clock)).collect(),
+        };
+
+        // Disputed entries are requeued, so give up after one full pass
+        let mut remaining = timeline.len();
+        while let Some(&(tx_id, stored_at)) = timeline.front() {
+            let over_capacity = retention.max_entries.is_some_and(|max| self.len() > max);
+            let expired = retention
+                .max_age
+                .is_some_and(|max| self.clock - stored_at > max);
+            if remaining == 0 || !(over_capacity || expired) {
+                break;
+            }
+            remaining -= 1;
+
+            timeline.pop_front();
+            if self.hot.get(&tx_id).is_some_and(|t| t.disputed) || !self.archive_entry(tx_id) {
+                timeline.push_back((tx_id, self.clock));
+                continue;
+            }
+            self.remove(tx_id);
+            self.evicted.insert(tx_id);
+        }
+
+        self.timeline = Some(timeline);
+    }
+
+    /// Write an entry about to be dropped to the archive, if there is one
+    ///
+    /// Returns false if it couldn't be archived; it is then kept, trading the
+    /// retention bound for not losing it.
+    fn archive_entry(&mut self, tx_id: TxId) -> bool {
+        if self.archive.is_none() {
+            return true;
+        }
+        let Ok(Some(stored_tx)) = self.peek(tx_id) else {
+            return false;
+        };
+        self.archive
+            .as_mut()
+            .is_some_and(|archive| archive.store(&stored_tx).is_ok())
+    }
+
+    /// IDs of every entry, in memory and spilled, in ascending order
+    fn ids(&self) -> Vec<TxId> {
+        let mut ids: Vec<TxId> = self.hot.keys().copied().collect();
+        if let Some(spill) = &self.spill {
+            ids.extend(&spill.spilled);
+        }
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Drop an entry from whichever tier holds it
+    fn remove(&mut self, tx_id: TxId) {
+        if self.hot.remove(&tx_id).is_none() {
+            if let Some(spill) = &mut self.spill {
+                spill.spilled.remove(tx_id);
+            }
+        }
+    }
+
+    /// Take a spilled entry back out of the file, if there is one
+    fn load(&mut self, tx_id: TxId) -> io::Result<Option<StoredTransaction>> {
+        match &mut self.spill {
+            Some(spill) if spill.spilled.contains(tx_id) => {
+                let stored_tx = spill.read(tx_id)?;
+                spill.spilled.remove(tx_id);
+                spill.order.push_back(tx_id);
+                Ok(Some(stored_tx))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Move the oldest undisputed entries to disk until memory is back under
+    /// the limit
+    ///
+    /// An entry that can't be written stays in memory, trading the bound for
+    /// correctness.
+    fn spill_excess(&mut self) {
+        let Some(spill) = &mut self.spill else {
+            return;
+        };
+
+        // Disputed entries are requeued, so give up after one full pass
+        let mut remaining = spill.order.len();
+        while self.hot.len() > spill.max_in_memory && remaining > 0 {
+            remaining -= 1;
+            let Some(tx_id) = spill.order.pop_front() else {
+                break;
+            };
+            let Some(stored_tx) = self.hot.get(&tx_id) else {
+                continue;
+            };
+            if stored_tx.disputed || spill.write(stored_tx).is_err() {
+                spill.order.push_back(tx_id);
+                continue;
+            }
+            spill.spilled.insert(tx_id);
+            self.hot.remove(&tx_id);
+        }
+    }
+}
+
+impl Slab {
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn contains_key(&self, tx_id: &TxId) -> bool {
+        self.index.contains_key(tx_id)
+    }
+
+    fn get(&self, tx_id: &TxId) -> Option<&StoredTransaction> {
+        self.index.get(tx_id).map(|&slot| &self.entries[slot])
+    }
+
+    fn get_mut(&mut self, tx_id: &TxId) -> Option<&mut StoredTransaction> {
+        self.index.get(tx_id).map(|&slot| &mut self.entries[slot])
+    }
+
+    /// Store an entry, replacing and returning any entry with the same ID
+    fn insert(&mut self, stored_tx: StoredTransaction) -> Option<StoredTransaction> {
+        match self.index.get(&stored_tx.tx_id) {
+            Some(&slot) => Some(std::mem::replace(&mut self.entries[slot], stored_tx)),
+            None => {
+                self.index.insert(stored_tx.tx_id, self.entries.len());
+                self.entries.push(stored_tx);
+                None
+            }
+        }
+    }
+
+    fn remove(&mut self, tx_id: &TxId) -> Option<StoredTransaction> {
+        let slot = self.index.remove(tx_id)?;
+        let removed = self.entries.swap_remove(slot);
+        if let Some(moved) = self.entries.get(slot) {
+            self.index.insert(moved.tx_id, slot);
+        }
+        Some(removed)
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &TxId> {
+        self.index.keys()
+    }
+
+    fn values(&self) -> slice::Iter<'_, StoredTransaction> {
+        self.entries.iter()
+    }
+}
+
+impl FromIterator<StoredTransaction> for TransactionStore {
+    fn from_iter<I: IntoIterator<Item = StoredTransaction>>(iter: I) -> Self {
+        let mut store = Self::new();
+        for stored_tx in iter {
+            store.insert(stored_tx);
+        }
+        store
+    }
+}
+
+impl SpillFile {
+    fn write(&mut self, stored_tx: &StoredTransaction) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset(stored_tx.tx_id)?))?;
+        self.file.write_all(&encode_record(stored_tx))
+    }
+
+    /// Overwrite the record for `tx_id` with zeros, which never decode
+    fn clear(&mut self, tx_id: TxId) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset(tx_id)?))?;
+        self.file.write_all(&[0; RECORD_SIZE as usize])
+    }
+
+    /// Read the record for `tx_id`; spilled entries are never disputed
+    fn read(&self, tx_id: TxId) -> io::Result<StoredTransaction> {
+        let mut record = [0u8; RECORD_SIZE as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset(tx_id)?))?;
+        file.read_exact(&mut record)?;
+
+        decode_record(tx_id, &record).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("corrupt spill record for transaction {}", tx_id),
+            )
+        })
+    }
+}
+
+/// Position of `tx_id`'s record in a file indexed by transaction ID
+///
+/// Only 64-bit IDs (`wide-tx-ids`) can be too large to have one. Large ones
+/// that do may still be past what the filesystem allows, in which case
+/// writing the record fails.
+pub(crate) fn offset(tx_id: TxId) -> io::Result<u64> {
+    // A no-op unless IDs are 32 bits
+    #[allow(clippy::useless_conversion)]
+    u64::from(tx_id).checked_mul(RECORD_SIZE).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("transaction {} is too large to index a file by", tx_id),
+        )
+    })
+}
+
+/// Fixed-size record for `stored_tx`; the dispute flag isn't kept
+pub(crate) fn encode_record(stored_tx: &StoredTransaction) -> [u8; RECORD_SIZE as usize] {
+    let mut record = [0u8; RECORD_SIZE as usize];
+    let (client_id, rest) = record.split_at_mut(CLIENT_SIZE);
+    client_id.copy_from_slice(&stored_tx.client_id.to_le_bytes());
+    rest[..16].copy_from_slice(&amount::to_bytes(stored_tx.amount));
+    rest[16] = encode_type(stored_tx.tx_type);
+    if let Some(timestamp) = stored_tx.timestamp {
+        rest[17] = 1;
+        rest[18..26].copy_from_slice(&timestamp.to_le_bytes());
+    }
+    record
+}
+
+/// Entry `tx_id` from its record, `None` if the record is zeroed or corrupt
+pub(crate) fn decode_record(
+    tx_id: TxId,
+    record: &[u8; RECORD_SIZE as usize],
+) -> Option<StoredTransaction> {
+    let (client_id, rest) = record.split_at(CLIENT_SIZE);
+    let client_id = ClientId::from_le_bytes(client_id.try_into().expect("client ID slice"));
+    let amount = amount::from_bytes(rest[..16].try_into().expect("16-byte slice"));
+    let tx_type = decode_type(rest[16])?;
+    let timestamp =
+        (rest[17] == 1).then(|| u64::from_le_bytes(rest[18..26].try_into().expect("8-byte slice")));
+    Some(StoredTransaction {
+        timestamp,
+        ..StoredTransaction::new(tx_id, client_id, amount, tx_type)
+    })
+}
+
+/// Zero is left unused so a hole in the sparse file never decodes
+fn encode_type(tx_type: TransactionType) -> u8 {
+    match tx_type {
+        TransactionType::Deposit => 1,
+        TransactionType::Withdrawal => 2,
+        TransactionType::Dispute => 3,
+        TransactionType::Resolve => 4,
+        TransactionType::Chargeback => 5,
+    }
+}
+
+fn decode_type(byte: u8) -> Option<TransactionType> {
+    match byte {
+        1 => Some(TransactionType::Deposit),
+        2 => Some(TransactionType::Withdrawal),
+        3 => Some(TransactionType::Dispute),
+        4 => Some(TransactionType::Resolve),
+        5 => Some(TransactionType::Chargeback),
+        _ => None,
+    }
+}
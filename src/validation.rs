@@ -0,0 +1,221 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::amount::{self, Amount};
+use crate::config::EngineConfig;
+use crate::models::{Transaction, TransactionType};
+use crate::outcome::RejectReason;
+
+/// A problem with a transaction that shows without any engine state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A deposit or withdrawal without an amount
+    MissingAmount,
+    /// A dispute, resolve or chargeback with an amount; the engine ignores
+    /// it and moves the referenced deposit's amount instead
+    UnexpectedAmount,
+    /// A deposit or withdrawal amount of zero or less
+    NonPositiveAmount,
+    /// A deposit or withdrawal above `EngineConfig::max_amount`
+    AmountAboveLimit { max: Amount },
+    /// An amount with more decimal places than
+    /// `EngineConfig::max_decimal_places`
+    TooManyDecimalPlaces { max: u32 },
+}
+
+impl ValidationError {
+    /// What the engine would reject the transaction with, `None` for
+    /// problems it lets through
+    pub fn reject_reason(&self) -> Option<RejectReason> {
+        match self {
+            Self::MissingAmount => Some(RejectReason::MissingAmount),
+            Self::UnexpectedAmount => None,
+            Self::NonPositiveAmount => Some(RejectReason::NonPositiveAmount),
+            Self::AmountAboveLimit { .. } => Some(RejectReason::AmountAboveLimit),
+            Self::TooManyDecimalPlaces { .. } => Some(RejectReason::TooManyDecimalPlaces),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingAmount => f.write_str("deposits and withdrawals need an amount"),
+            Self::UnexpectedAmount => {
+                f.write_str("disputes, resolves and chargebacks take no amount")
+            }
+            Self::NonPositiveAmount => f.write_str("amount must be positive"),
+            Self::AmountAboveLimit { max } => write!(f, "amount above limit of {}", max),
+            Self::TooManyDecimalPlaces { max } => {
+                write!(f, "amount has more than {} decimal places", max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Check a transaction on its own, before it reaches an engine
+///
+/// Reports every structural problem and every breach of `config`'s amount
+/// limits, in the order listed on `ValidationError`. Whatever needs engine
+/// state, such as duplicate IDs, balances or the deposit a dispute refers
+/// to, is left to the engine. A transaction that passes is never rejected
+/// for its shape or amount. A deposit or withdrawal that fails is rejected
+/// with the `reject_reason` of its first error, unless the engine finds
+/// another reason first.
+///
+/// # Example
+///
+/// ```
+/// use payments_engine::config::EngineConfig;
+/// use payments_engine::models::Transaction;
+/// use payments_engine::validation::{validate_transaction, ValidationError};
+///
+/// let config = EngineConfig {
+///     max_amount: Some("1000".parse().unwrap()),
+///     max_decimal_places: Some(2),
+///     ..EngineConfig::default()
+/// };
+/// let deposit = Transaction::deposit(1, 1, "2500.125".parse().unwrap());
+/// assert_eq!(
+///     validate_transaction(&deposit, &config),
+///     Err(vec![
+///         ValidationError::AmountAboveLimit { max: "1000".parse().unwrap() },
+///         ValidationError::TooManyDecimalPlaces { max: 2 },
+///     ])
+/// );
+/// ```
+pub fn validate_transaction(
+    tx: &Transaction,
+    config: &EngineConfig,
+) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    match (tx.tx_type, tx.amount) {
+        (TransactionType::Deposit | TransactionType::Withdrawal, None) => {
+            errors.push(ValidationError::MissingAmount);
+        }
+        (TransactionType::Deposit | TransactionType::Withdrawal, Some(amount)) => {
+            if amount <= Amount::ZERO {
+                errors.push(ValidationError::NonPositiveAmount);
+            }
+            if let Some(max) = config.max_amount.filter(|&max| amount > max) {
+                errors.push(ValidationError::AmountAboveLimit { max });
+            }
+            if let Some(max) = config
+                .max_decimal_places
+                .filter(|&max| amount::decimal_places(amount) > max)
+            {
+                errors.push(ValidationError::TooManyDecimalPlaces { max });
+            }
+        }
+        (_, Some(_)) => errors.push(ValidationError::UnexpectedAmount),
+        (_, None) => {}
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Columns a transactions CSV header must have; `timestamp` may be left out
+pub const REQUIRED_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+/// What a transactions CSV header row lacks or adds
+///
+/// Extra columns aren't an error: they are passed through as each
+/// transaction's `metadata`. They are listed so that a misspelt column, e.g.
+/// `amout`, doesn't go unnoticed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderReport {
+    /// Required columns missing from the header, in `REQUIRED_COLUMNS` order
+    pub missing: Vec<&'static str>,
+    /// Columns `Transaction` doesn't read, in header order
+    pub unknown: Vec<String>,
+}
+
+impl HeaderReport {
+    /// Whether every required column is there
+    pub fn is_valid(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for HeaderReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.missing.is_empty() {
+            parts.push(format!("missing columns: {}", self.missing.join(", ")));
+        }
+        if !self.unknown.is_empty() {
+            parts.push(format!("unknown columns: {}", self.unknown.join(", ")));
+        }
+        if parts.is_empty() {
+            return f.write_str("header is complete");
+        }
+        f.write_str(&parts.join("; "))
+    }
+}
+
+/// Check a transactions CSV header row against the input format
+///
+/// Column names are compared as they are, after the trimming the CSV reader
+/// does; empty names are ignored.
+///
+/// # Example
+///
+/// ```
+/// use payments_engine::validation::check_header;
+///
+/// let report = check_header(["type", "client", "amout", "tx"]);
+/// assert!(!report.is_valid());
+/// assert_eq!(report.missing, ["amount"]);
+/// assert_eq!(report.unknown, ["amout"]);
+/// ```
+pub fn check_header<'a>(columns: impl IntoIterator<Item = &'a str>) -> HeaderReport {
+    let columns: Vec<&str> = columns.into_iter().collect();
+    HeaderReport {
+        missing: REQUIRED_COLUMNS
+            .into_iter()
+            .filter(|required| !columns.contains(required))
+            .collect(),
+        unknown: columns
+            .into_iter()
+            .filter(|column| !column.is_empty() && !Transaction::FIELDS.contains(column))
+            .map(String::from)
+            .collect(),
+    }
+}
+
+/// A row of a transactions CSV that isn't a valid transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RowError {
+    /// Line of the input the row ends on, the header being line 1; the one
+    /// it is on unless a quoted value spans lines
+    pub line: u64,
+    /// Column whose value couldn't be read, `None` when the row as a whole
+    /// is at fault, e.g. it has the wrong number of fields
+    pub column: Option<String>,
+    /// That column's value as written
+    pub value: Option<String>,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(column) = &self.column {
+            write!(f, ", column {}", column)?;
+        }
+        if let Some(value) = &self.value {
+            write!(f, " ('{}')", value)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for RowError {}
@@ -0,0 +1,249 @@
+//! Checking accounts files written by earlier runs
+//!
+//! `verify_accounts` reads a CSV in the output format and checks it the way a
+//! pipeline would before handing it on: every row is a well-formed account,
+//! `total` is `available + held`, no balance is negative and no client is
+//! listed twice. It only sees the file, so it can't tell whether the
+//! balances are right, only that they are consistent.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::amount::Amount;
+use crate::error::Result;
+use crate::models::ClientId;
+use crate::validation::RowError;
+
+/// Columns of an accounts file, in order
+pub const ACCOUNT_COLUMNS: [&str; 5] = ["client", "available", "held", "total", "locked"];
+
+/// One row of an accounts file as written: client, available, held, total
+/// and locked
+type AccountRow = (ClientId, Amount, Amount, Amount, bool);
+
+/// Something wrong with an accounts file
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccountsProblem {
+    /// The header isn't `client,available,held,total,locked`; the rows
+    /// aren't checked
+    Header { found: String },
+    /// A row that isn't an account
+    Malformed(RowError),
+    /// `total` isn't `available + held`
+    TotalMismatch {
+        line: u64,
+        client_id: ClientId,
+        available: Amount,
+        held: Amount,
+        total: Amount,
+    },
+    /// A negative balance in `column`
+    Negative {
+        line: u64,
+        client_id: ClientId,
+        column: &'static str,
+        value: Amount,
+    },
+    /// A client already listed on `first_line`
+    DuplicateClient {
+        line: u64,
+        client_id: ClientId,
+        first_line: u64,
+    },
+}
+
+impl AccountsProblem {
+    /// True for problems with the file's structure rather than its balances
+    pub fn is_structural(&self) -> bool {
+        matches!(self, Self::Header { .. } | Self::Malformed(_))
+    }
+}
+
+impl fmt::Display for AccountsProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Header { found } => write!(
+                f,
+                "header is '{}', not '{}'",
+                found,
+                ACCOUNT_COLUMNS.join(",")
+            ),
+            Self::Malformed(error) => error.fmt(f),
+            Self::TotalMismatch {
+                line,
+                client_id,
+                available,
+                held,
+                total,
+            } => write!(
+                f,
+                "line {}: client {}: total {} is not available {} + held {}",
+                line, client_id, total, available, held
+            ),
+            Self::Negative {
+                line,
+                client_id,
+                column,
+                value,
+            } => write!(
+                f,
+                "line {}: client {}: negative {} {}",
+                line, client_id, column, value
+            ),
+            Self::DuplicateClient {
+                line,
+                client_id,
+                first_line,
+            } => write!(
+                f,
+                "line {}: client {} already listed on line {}",
+                line, client_id, first_line
+            ),
+        }
+    }
+}
+
+/// Result of `verify_accounts`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountsReport {
+    /// Number of well-formed rows checked
+    pub accounts_checked: usize,
+    /// Problems found, in file order
+    pub problems: Vec<AccountsProblem>,
+}
+
+impl AccountsReport {
+    /// True if the file has no problems
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// True if any problem is with the file's structure
+    pub fn is_malformed(&self) -> bool {
+        self.problems.iter().any(AccountsProblem::is_structural)
+    }
+}
+
+impl fmt::Display for AccountsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} problem(s) across {} account(s)",
+            self.problems.len(),
+            self.accounts_checked
+        )?;
+        for problem in &self.problems {
+            write!(f, "\n  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check an accounts CSV, as written by a batch run, for structural and
+/// accounting problems
+///
+/// Values may be padded with spaces. Every problem is reported, not just
+/// the first; a wrong header stops the check, since the rows can't be read
+/// without it. Fails only if reading fails.
+pub fn verify_accounts<R: Read>(reader: R) -> Result<AccountsReport> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(reader);
+    let mut report = AccountsReport::default();
+
+    let headers = reader.headers()?.clone();
+    if headers.iter().ne(ACCOUNT_COLUMNS) {
+        let found = headers.iter().collect::<Vec<_>>().join(",");
+        report.problems.push(AccountsProblem::Header { found });
+        return Ok(report);
+    }
+
+    let mut lines: HashMap<ClientId, u64> = HashMap::new();
+    let mut record = csv::StringRecord::new();
+    loop {
+        let line = reader.position().line();
+        if !reader.read_record(&mut record)? {
+            break;
+        }
+        let (client_id, available, held, total, _) = match row(&record, line) {
+            Ok(row) => row,
+            Err(error) => {
+                report.problems.push(AccountsProblem::Malformed(error));
+                continue;
+            }
+        };
+        report.accounts_checked += 1;
+
+        if let Some(&first_line) = lines.get(&client_id) {
+            report.problems.push(AccountsProblem::DuplicateClient {
+                line,
+                client_id,
+                first_line,
+            });
+        } else {
+            lines.insert(client_id, line);
+        }
+        for (column, value) in [("available", available), ("held", held), ("total", total)] {
+            if value < Amount::ZERO {
+                report.problems.push(AccountsProblem::Negative {
+                    line,
+                    client_id,
+                    column,
+                    value,
+                });
+            }
+        }
+        if available.checked_add(held) != Some(total) {
+            report.problems.push(AccountsProblem::TotalMismatch {
+                line,
+                client_id,
+                available,
+                held,
+                total,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Read `record`, found on `line`, as an account row
+fn row(record: &csv::StringRecord, line: u64) -> std::result::Result<AccountRow, RowError> {
+    if record.len() != ACCOUNT_COLUMNS.len() {
+        return Err(RowError {
+            line,
+            column: None,
+            value: None,
+            message: format!(
+                "expected {} fields, found {}",
+                ACCOUNT_COLUMNS.len(),
+                record.len()
+            ),
+        });
+    }
+    Ok((
+        field(record, line, 0)?,
+        field(record, line, 1)?,
+        field(record, line, 2)?,
+        field(record, line, 3)?,
+        field(record, line, 4)?,
+    ))
+}
+
+/// Parse field `index` of `record`, found on `line`
+fn field<T>(record: &csv::StringRecord, line: u64, index: usize) -> std::result::Result<T, RowError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let value = &record[index];
+    value.parse().map_err(|e: T::Err| RowError {
+        line,
+        column: Some(ACCOUNT_COLUMNS[index].to_string()),
+        value: Some(value.to_string()),
+        message: e.to_string(),
+    })
+}
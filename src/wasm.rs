@@ -0,0 +1,76 @@
+//! JavaScript bindings for running the engine in the browser
+//!
+//! Built with the `wasm` feature and without the default ones, e.g.
+//! `wasm-pack build --target web --no-default-features --features wasm`.
+//! Both entry points drive the same `PaymentsEngine` the CLI and server use,
+//! so a simulation gives exactly the results production would.
+
+use wasm_bindgen::prelude::*;
+
+use crate::engine::{AccountOrdering, PaymentsEngine};
+use crate::models::{ClientId, Transaction};
+use crate::output::{CsvSink, OutputSink};
+
+/// Process a CSV of transactions and return the resulting accounts as CSV
+///
+/// The same as the CLI's batch mode: malformed rows are skipped and
+/// accounts come out sorted by client ID.
+#[wasm_bindgen]
+pub fn process_csv(input: &str) -> Result<String, JsError> {
+    let mut output = Vec::new();
+    crate::process_transactions(input.as_bytes(), &mut output)?;
+    Ok(String::from_utf8(output)?)
+}
+
+/// An engine kept between calls, fed one transaction at a time
+#[wasm_bindgen]
+pub struct WasmEngine {
+    engine: PaymentsEngine,
+}
+
+impl Default for WasmEngine {
+    fn default() -> Self {
+        Self {
+            engine: PaymentsEngine::with_account_ordering(AccountOrdering::ByClientId),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl WasmEngine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a transaction given as JSON, in the shape the HTTP API takes
+    ///
+    /// Returns the outcome, `applied` or `rejected: <reason>`; only JSON
+    /// that isn't a transaction is an error.
+    pub fn push_transaction(&mut self, json: &str) -> Result<String, JsError> {
+        let transaction: Transaction = serde_json::from_str(json)?;
+        Ok(self.engine.process_transaction(transaction).to_string())
+    }
+
+    /// A client's account as JSON, or `undefined` if it has none
+    pub fn account(&self, client_id: ClientId) -> Result<Option<String>, JsError> {
+        self.engine
+            .get_account(client_id)
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(JsError::from)
+    }
+
+    /// All accounts as CSV sorted by client ID, like `process_csv` returns
+    pub fn accounts_csv(&self) -> Result<String, JsError> {
+        // Accounts are stored by client ID, so no sort is needed
+        let mut output = Vec::new();
+        let mut sink = CsvSink::new(&mut output);
+        for account in self.engine.accounts_iter() {
+            sink.write_account(account)?;
+        }
+        sink.finish()?;
+        drop(sink);
+        Ok(String::from_utf8(output)?)
+    }
+}
@@ -0,0 +1,305 @@
+//! Synthetic transaction streams for benchmarks and load tests
+//!
+//! Workloads are generated from a seed, so the same options always produce
+//! the same transactions and benchmark runs stay comparable.
+
+use std::io::Write;
+
+use crate::amount::Amount;
+use crate::error::Result;
+use crate::models::{ClientId, Metadata, Transaction, TransactionType, TxId};
+
+/// Shape of a generated workload
+///
+/// Ratios are fractions of all generated transactions; whatever they leave
+/// over is deposits. Disputes pick a deposit made earlier in the stream, and
+/// a dispute slot with disputes already open may resolve or charge back one
+/// of them instead.
+#[derive(Debug, Clone, Copy)]
+pub struct WorkloadOptions {
+    /// Number of transactions to generate
+    pub transactions: usize,
+    /// Clients the transactions are spread over, IDs `1..=clients`; must be at least 1
+    pub clients: ClientId,
+    /// Fraction of transactions that are withdrawals
+    pub withdrawal_ratio: f64,
+    /// Fraction of transactions that are disputes, resolves or chargebacks
+    pub dispute_ratio: f64,
+    /// Fraction of settled disputes that end in a chargeback rather than a
+    /// resolve; chargebacks lock the account
+    pub chargeback_rate: f64,
+    /// Fraction of transactions that repeat an earlier deposit or withdrawal,
+    /// transaction ID included, as a retrying client would
+    pub duplicate_ratio: f64,
+    /// How deposit and withdrawal amounts are drawn
+    pub amounts: AmountDistribution,
+    /// Seed for the generator
+    pub seed: u64,
+}
+
+impl Default for WorkloadOptions {
+    /// 100,000 transactions over 1,000 clients: 70% deposits, 25%
+    /// withdrawals, 5% disputes and their follow-ups, no duplicates
+    fn default() -> Self {
+        Self {
+            transactions: 100_000,
+            clients: 1_000,
+            withdrawal_ratio: 0.25,
+            dispute_ratio: 0.05,
+            chargeback_rate: 0.1,
+            duplicate_ratio: 0.0,
+            amounts: AmountDistribution::default(),
+            seed: 0x5eed,
+        }
+    }
+}
+
+impl WorkloadOptions {
+    /// Deposits only, the cheapest transactions to apply
+    pub fn deposits(transactions: usize, clients: ClientId) -> Self {
+        Self {
+            transactions,
+            clients,
+            withdrawal_ratio: 0.0,
+            dispute_ratio: 0.0,
+            ..Self::default()
+        }
+    }
+
+    /// A third of the transactions are disputes, resolves or chargebacks,
+    /// keeping many stored deposits under dispute at once
+    pub fn dispute_heavy(transactions: usize, clients: ClientId) -> Self {
+        Self {
+            transactions,
+            clients,
+            withdrawal_ratio: 0.1,
+            dispute_ratio: 0.33,
+            ..Self::default()
+        }
+    }
+}
+
+/// Distribution of generated amounts
+///
+/// Amounts are rounded to four decimal places and are at least 0.0001.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AmountDistribution {
+    /// Every amount up to `max` equally likely
+    Uniform { max: f64 },
+    /// Log-normal around `median`: mostly small amounts with a long tail of
+    /// large ones, as in real payment traffic. `sigma` is the spread of the
+    /// underlying normal distribution; amounts are capped at `max`
+    LogNormal { median: f64, sigma: f64, max: f64 },
+}
+
+impl Default for AmountDistribution {
+    /// Uniform up to 1000
+    fn default() -> Self {
+        Self::Uniform { max: 1000.0 }
+    }
+}
+
+/// Generate the transactions described by `options`
+///
+/// Collects a `Workload`; iterate one directly to stream a large workload
+/// without holding it in memory.
+///
+/// # Example
+///
+/// ```
+/// use payments_engine::engine::PaymentsEngine;
+/// use payments_engine::workload::{generate, WorkloadOptions};
+///
+/// let mut engine = PaymentsEngine::new();
+/// for tx in generate(&WorkloadOptions::deposits(1_000, 10)) {
+///     engine.process_transaction(tx);
+/// }
+/// assert_eq!(engine.get_accounts().len(), 10);
+/// ```
+pub fn generate(options: &WorkloadOptions) -> Vec<Transaction> {
+    Workload::new(*options).collect()
+}
+
+/// Generator yielding the transactions described by a `WorkloadOptions`
+///
+/// Deposits and withdrawals get consecutive transaction IDs starting at 1;
+/// duplicates reuse the ID they repeat. Memory grows with the number of
+/// deposits still open to dispute and of transactions that may be
+/// duplicated, a few bytes each, not with the transactions themselves.
+pub struct Workload {
+    options: WorkloadOptions,
+    rng: SplitMix64,
+    generated: usize,
+    next_tx: TxId,
+    /// Deposits that may still be disputed
+    deposits: Vec<(ClientId, TxId)>,
+    open_disputes: Vec<(ClientId, TxId)>,
+    /// Deposits and withdrawals a duplicate may repeat, only kept when
+    /// duplicates are wanted
+    repeatable: Vec<Transaction>,
+}
+
+impl Workload {
+    /// # Panics
+    ///
+    /// Panics if `options.clients` is zero.
+    pub fn new(options: WorkloadOptions) -> Self {
+        assert!(options.clients > 0, "clients must be at least 1");
+        Self {
+            options,
+            rng: SplitMix64::new(options.seed),
+            generated: 0,
+            next_tx: 1,
+            deposits: Vec::new(),
+            open_disputes: Vec::new(),
+            repeatable: Vec::new(),
+        }
+    }
+
+    /// Dispute an earlier deposit, or settle an open dispute
+    fn dispute_step(&mut self) -> Transaction {
+        let settle = !self.open_disputes.is_empty()
+            && (self.deposits.is_empty() || self.rng.next_f64() < 0.5);
+        let (tx_type, (client, tx)) = if settle {
+            let index = self.rng.below(self.open_disputes.len());
+            let settled = self.open_disputes.swap_remove(index);
+            let tx_type = if self.rng.next_f64() < self.options.chargeback_rate {
+                TransactionType::Chargeback
+            } else {
+                TransactionType::Resolve
+            };
+            (tx_type, settled)
+        } else {
+            let index = self.rng.below(self.deposits.len());
+            let disputed = self.deposits.swap_remove(index);
+            self.open_disputes.push(disputed);
+            (TransactionType::Dispute, disputed)
+        };
+        Transaction {
+            tx_type,
+            client,
+            tx,
+            amount: None,
+            timestamp: None,
+            metadata: Metadata::new(),
+        }
+    }
+}
+
+impl Iterator for Workload {
+    type Item = Transaction;
+
+    fn next(&mut self) -> Option<Transaction> {
+        if self.generated == self.options.transactions {
+            return None;
+        }
+        self.generated += 1;
+
+        let options = &self.options;
+        let roll = self.rng.next_f64();
+        let disputable = !self.deposits.is_empty() || !self.open_disputes.is_empty();
+        if roll < options.dispute_ratio && disputable {
+            return Some(self.dispute_step());
+        }
+        let roll = roll - options.dispute_ratio;
+        if (0.0..options.duplicate_ratio).contains(&roll) && !self.repeatable.is_empty() {
+            let index = self.rng.below(self.repeatable.len());
+            return Some(self.repeatable[index].clone());
+        }
+
+        let tx_type = if roll - options.duplicate_ratio < options.withdrawal_ratio {
+            TransactionType::Withdrawal
+        } else {
+            TransactionType::Deposit
+        };
+        let transaction = Transaction {
+            tx_type,
+            client: self.rng.below(options.clients as usize) as ClientId + 1,
+            tx: self.next_tx,
+            amount: Some(random_amount(&mut self.rng, options.amounts)),
+            timestamp: None,
+            metadata: Metadata::new(),
+        };
+        self.next_tx += 1;
+
+        if tx_type == TransactionType::Deposit {
+            self.deposits.push((transaction.client, transaction.tx));
+        }
+        if options.duplicate_ratio > 0.0 {
+            self.repeatable.push(transaction.clone());
+        }
+        Some(transaction)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.options.transactions - self.generated;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Write transactions as CSV input for the engine, header included; the
+/// same as `write_transactions`
+pub fn write_csv<W: Write>(
+    transactions: impl IntoIterator<Item = Transaction>,
+    writer: W,
+) -> Result<()> {
+    crate::write_transactions(transactions, writer)
+}
+
+/// Generate a workload and write it as CSV in memory
+pub fn generate_csv(options: &WorkloadOptions) -> Vec<u8> {
+    let mut csv = Vec::new();
+    write_csv(Workload::new(*options), &mut csv).expect("writing to memory can't fail");
+    csv
+}
+
+/// Draw an amount from `distribution`
+fn random_amount(rng: &mut SplitMix64, distribution: AmountDistribution) -> Amount {
+    let value = match distribution {
+        AmountDistribution::Uniform { max } => rng.next_f64() * max,
+        AmountDistribution::LogNormal { median, sigma, max } => {
+            (median * (sigma * rng.next_normal()).exp()).min(max)
+        }
+    };
+    // Whole 1/10000ths, at least one
+    let minor_units = ((value * 10_000.0).round() as u64).max(1);
+    format!("{}.{:04}", minor_units / 10_000, minor_units % 10_000)
+        .parse()
+        .expect("generated amounts are valid")
+}
+
+/// Small, fast generator; workloads only need to be reproducible, not random
+/// in any stronger sense
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, by the Box-Muller transform
+    fn next_normal(&mut self) -> f64 {
+        // 1 - u keeps the logarithm's argument in (0, 1]
+        let u = 1.0 - self.next_f64();
+        let v = self.next_f64();
+        (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+    }
+
+    /// Uniform in `0..bound`, with negligible bias for the bounds used here
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
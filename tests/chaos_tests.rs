@@ -0,0 +1,110 @@
+#![cfg(all(feature = "chaos", not(feature = "fixed-point")))]
+
+use std::time::Duration;
+
+use payments_engine::chaos::ChaosConfig;
+use payments_engine::concurrent_engine::ShardedEngine;
+use payments_engine::error::EngineError;
+use payments_engine::models::Transaction;
+use payments_engine::server::config::ServerConfig;
+use payments_engine::server::tcp;
+use rust_decimal_macros::dec;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::time::Instant;
+
+fn chaos(config: ChaosConfig) -> Option<ChaosConfig> {
+    Some(ChaosConfig {
+        seed: Some(7),
+        ..config
+    })
+}
+
+#[test]
+fn test_chaos_section_parses_and_validates() {
+    let config = ServerConfig::parse(
+        r#"
+        [chaos]
+        persistence_failure_rate = 0.25
+        max_shard_latency_ms = 20
+        seed = 42
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.chaos,
+        Some(ChaosConfig {
+            persistence_failure_rate: 0.25,
+            max_shard_latency_ms: 20,
+            dropped_connection_rate: 0.0,
+            seed: Some(42),
+        })
+    );
+    assert_eq!(ServerConfig::parse("").unwrap().chaos, None);
+
+    let error = ServerConfig::parse("[chaos]\ndropped_connection_rate = 1.5").unwrap_err();
+    assert!(error.to_string().contains("dropped_connection_rate"));
+}
+
+#[tokio::test]
+async fn test_injected_persistence_failures_apply_nothing() {
+    let engine = ShardedEngine::new(2);
+    engine.set_chaos(chaos(ChaosConfig {
+        persistence_failure_rate: 1.0,
+        ..ChaosConfig::default()
+    }));
+
+    let result = engine
+        .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+        .await;
+    assert!(matches!(result, Err(EngineError::Io(_))));
+    assert!(engine.get_account(1).await.is_none());
+    let failed: u64 = engine.shard_metrics().await.iter().map(|m| m.failed).sum();
+    assert_eq!(failed, 1);
+
+    // Turning chaos off lets the retry through
+    engine.set_chaos(None);
+    let outcome = engine
+        .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+        .await
+        .unwrap();
+    assert!(outcome.is_applied());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_shard_latency_delays_transactions() {
+    let engine = ShardedEngine::new(1);
+    engine.set_chaos(chaos(ChaosConfig {
+        max_shard_latency_ms: 50,
+        ..ChaosConfig::default()
+    }));
+
+    let start = Instant::now();
+    for tx in 1..=10 {
+        engine
+            .process_transaction(Transaction::deposit(1, tx, dec!(1)))
+            .await
+            .unwrap();
+    }
+    let elapsed = start.elapsed();
+    assert!(elapsed > Duration::ZERO);
+    assert!(elapsed <= Duration::from_millis(500));
+}
+
+#[tokio::test]
+async fn test_dropped_connection_after_processing() {
+    let engine = ShardedEngine::new(2);
+    engine.set_chaos(chaos(ChaosConfig {
+        dropped_connection_rate: 1.0,
+        ..ChaosConfig::default()
+    }));
+    let (client, server) = tokio::io::duplex(4096);
+    tokio::spawn(tcp::handle_connection(server, engine.clone_handle(), None));
+
+    let (reader, mut writer) = tokio::io::split(client);
+    let mut lines = BufReader::new(reader).lines();
+    writer.write_all(b"deposit,1,1,5.0\n").await.unwrap();
+
+    // Closed without an answer, although the deposit was applied
+    assert_eq!(lines.next_line().await.unwrap(), None);
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(5.0));
+}
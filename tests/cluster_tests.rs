@@ -0,0 +1,267 @@
+#![cfg(all(feature = "cluster", not(feature = "fixed-point")))]
+
+use std::net::TcpListener;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use payments_engine::concurrent_engine::{ShardOptions, ShardedEngine};
+use payments_engine::error::EngineError;
+use payments_engine::models::{ClientId, Transaction, TxId};
+use payments_engine::outcome::Outcome;
+use payments_engine::persistence::read_log;
+use payments_engine::persistence::replicated::Follower;
+use payments_engine::persistence::shipping::{request_promotion, Replica, ReplicaExit, WalShipper};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+fn deposit(client: ClientId, tx: TxId) -> Transaction {
+    Transaction::deposit(client, tx, dec!(1.0))
+}
+
+/// Start a follower keeping its logs in `dir`, returning its address
+fn start_follower(addr: &str, dir: &Path) -> String {
+    let follower = Follower::bind(addr, dir).unwrap();
+    let addr = follower.local_addr().unwrap().to_string();
+    thread::spawn(move || follower.run());
+    addr
+}
+
+/// An address nothing listens on, for now
+fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+async fn balances(engine: &ShardedEngine) -> Vec<(ClientId, Decimal, Decimal, bool)> {
+    let mut balances: Vec<_> = engine
+        .get_all_accounts()
+        .await
+        .into_iter()
+        .map(|account| {
+            (
+                account.client_id,
+                account.available,
+                account.held,
+                account.locked,
+            )
+        })
+        .collect();
+    balances.sort_by_key(|balance| balance.0);
+    balances
+}
+
+fn options(num_shards: usize) -> ShardOptions {
+    ShardOptions {
+        num_shards,
+        ..ShardOptions::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_follower_can_take_over() {
+    let leader_dir = tempfile::tempdir().unwrap();
+    let follower_dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+    let followers: Vec<_> = follower_dirs
+        .iter()
+        .map(|dir| start_follower("127.0.0.1:0", dir.path()))
+        .collect();
+
+    let engine =
+        ShardedEngine::recover_replicated(leader_dir.path(), options(2), &followers).unwrap();
+    for (tx, client) in (1..=6).zip([1, 2, 3, 1, 2, 3]) {
+        engine
+            .process_transaction(deposit(client, tx))
+            .await
+            .unwrap();
+    }
+    engine
+        .process_transaction(Transaction::dispute(2, 2))
+        .await
+        .unwrap();
+    let accounts = balances(&engine).await;
+    drop(engine);
+
+    for dir in &follower_dirs {
+        let promoted = ShardedEngine::recover(dir.path(), 2).unwrap();
+        assert_eq!(balances(&promoted).await, accounts);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_commits_with_a_majority() {
+    let leader_dir = tempfile::tempdir().unwrap();
+    let follower_dir = tempfile::tempdir().unwrap();
+    let followers = vec![
+        start_follower("127.0.0.1:0", follower_dir.path()),
+        free_addr(),
+    ];
+
+    let engine =
+        ShardedEngine::recover_replicated(leader_dir.path(), options(1), &followers).unwrap();
+    assert_eq!(
+        engine.process_transaction(deposit(1, 1)).await.unwrap(),
+        Outcome::Applied
+    );
+    assert_eq!(
+        read_log(follower_dir.path().join("shard-0/wal.log"))
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_refuses_transactions_without_a_majority() {
+    let leader_dir = tempfile::tempdir().unwrap();
+    let followers = vec![free_addr(), free_addr()];
+
+    let engine =
+        ShardedEngine::recover_replicated(leader_dir.path(), options(1), &followers).unwrap();
+    assert!(matches!(
+        engine.process_transaction(deposit(1, 1)).await,
+        Err(EngineError::Replication(_))
+    ));
+    assert!(engine.get_account(1).await.is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_late_follower_catches_up() {
+    let leader_dir = tempfile::tempdir().unwrap();
+    let early_dir = tempfile::tempdir().unwrap();
+    let late_dir = tempfile::tempdir().unwrap();
+    let late = free_addr();
+    let followers = vec![
+        start_follower("127.0.0.1:0", early_dir.path()),
+        late.clone(),
+    ];
+
+    let engine =
+        ShardedEngine::recover_replicated(leader_dir.path(), options(1), &followers).unwrap();
+    for tx in 1..=3 {
+        engine.process_transaction(deposit(1, tx)).await.unwrap();
+    }
+    assert!(!late_dir.path().join("shard-0").exists());
+
+    start_follower(&late, late_dir.path());
+    engine.process_transaction(deposit(2, 4)).await.unwrap();
+
+    let late_log = read_log(late_dir.path().join("shard-0/wal.log")).unwrap();
+    assert_eq!(late_log.len(), 4);
+    assert_eq!(
+        late_log,
+        read_log(leader_dir.path().join("shard-0/wal.log")).unwrap()
+    );
+}
+
+/// Start shipping the logs in `dir`, returning the shipper's address
+async fn start_shipper(dir: &Path) -> String {
+    let shipper = WalShipper::bind("127.0.0.1:0", dir).await.unwrap();
+    let addr = shipper.local_addr().unwrap().to_string();
+    tokio::spawn(shipper.run());
+    addr
+}
+
+/// Wait until the replica's balances match the primary's
+async fn wait_for_replica(primary: &ShardedEngine, replica: &ShardedEngine) {
+    let expected = balances(primary).await;
+    for _ in 0..500 {
+        if balances(replica).await == expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("replica never caught up");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_replica_follows_until_promoted() {
+    let primary_dir = tempfile::tempdir().unwrap();
+    let replica_dir = tempfile::tempdir().unwrap();
+    let primary = ShardedEngine::recover(primary_dir.path(), 2).unwrap();
+    primary.process_transaction(deposit(1, 1)).await.unwrap();
+    let shipper = start_shipper(primary_dir.path()).await;
+
+    let replica = ShardedEngine::recover(replica_dir.path(), 2).unwrap();
+    let following = tokio::spawn(
+        Replica::new(&replica, shipper, replica_dir.path()).run(std::future::pending()),
+    );
+    for (tx, client) in (2..=5).zip([2, 3, 1, 2]) {
+        primary
+            .process_transaction(deposit(client, tx))
+            .await
+            .unwrap();
+    }
+    primary
+        .process_transaction(Transaction::dispute(2, 2))
+        .await
+        .unwrap();
+    wait_for_replica(&primary, &replica).await;
+
+    assert!(matches!(
+        replica.process_transaction(deposit(4, 6)).await,
+        Err(EngineError::ReadOnly)
+    ));
+
+    request_promotion(replica_dir.path()).unwrap();
+    assert_eq!(following.await.unwrap().unwrap(), ReplicaExit::Promoted);
+    assert_eq!(
+        replica.process_transaction(deposit(4, 6)).await.unwrap(),
+        Outcome::Applied
+    );
+    for shard in 0..2 {
+        let path = format!("shard-{}/wal.log", shard);
+        let replica_log = read_log(replica_dir.path().join(&path)).unwrap();
+        let primary_log = read_log(primary_dir.path().join(&path)).unwrap();
+        assert!(replica_log.starts_with(&primary_log));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_replica_stops_read_only() {
+    let primary_dir = tempfile::tempdir().unwrap();
+    let replica_dir = tempfile::tempdir().unwrap();
+    let _primary = ShardedEngine::recover(primary_dir.path(), 1).unwrap();
+    let shipper = start_shipper(primary_dir.path()).await;
+
+    let replica = ShardedEngine::recover(replica_dir.path(), 1).unwrap();
+    let exit = Replica::new(&replica, shipper, replica_dir.path())
+        .run(tokio::time::sleep(Duration::from_millis(50)))
+        .await
+        .unwrap();
+    assert_eq!(exit, ReplicaExit::Stopped);
+    assert!(replica.is_read_only().await);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_diverged_replica_is_refused() {
+    let primary_dir = tempfile::tempdir().unwrap();
+    let replica_dir = tempfile::tempdir().unwrap();
+    let _primary = ShardedEngine::recover(primary_dir.path(), 1).unwrap();
+    let shipper = start_shipper(primary_dir.path()).await;
+
+    let replica = ShardedEngine::recover(replica_dir.path(), 1).unwrap();
+    replica.process_transaction(deposit(1, 1)).await.unwrap();
+    assert!(matches!(
+        Replica::new(&replica, shipper, replica_dir.path())
+            .run(std::future::pending())
+            .await,
+        Err(EngineError::Replication(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_replica_with_other_shard_count_is_refused() {
+    let primary_dir = tempfile::tempdir().unwrap();
+    let replica_dir = tempfile::tempdir().unwrap();
+    let _primary = ShardedEngine::recover(primary_dir.path(), 2).unwrap();
+    let shipper = start_shipper(primary_dir.path()).await;
+
+    let replica = ShardedEngine::recover(replica_dir.path(), 3).unwrap();
+    assert!(matches!(
+        Replica::new(&replica, shipper, replica_dir.path())
+            .run(std::future::pending())
+            .await,
+        Err(EngineError::Replication(_))
+    ));
+}
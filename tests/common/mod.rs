@@ -1,11 +1,13 @@
-use payments_engine::models::{Transaction, TransactionType};
+#![allow(dead_code)]
+
+use payments_engine::models::{ClientId, Metadata, Transaction, TransactionType, TxId};
 use rust_decimal::Decimal;
 
 /// Helper to create a transaction with all fields
 pub fn make_transaction(
     tx_type: TransactionType,
-    client: u16,
-    tx: u32,
+    client: ClientId,
+    tx: TxId,
     amount: Option<Decimal>,
 ) -> Transaction {
     Transaction {
@@ -13,17 +15,19 @@ pub fn make_transaction(
         client,
         tx,
         amount,
+        timestamp: None,
+        metadata: Metadata::new(),
     }
 }
 
 /// Helper to create a deposit transaction
-pub fn make_deposit(client: u16, tx: u32, amount: Decimal) -> Transaction {
-    make_transaction(TransactionType::Deposit, client, tx, Some(amount))
+pub fn make_deposit(client: ClientId, tx: TxId, amount: Decimal) -> Transaction {
+    Transaction::deposit(client, tx, amount)
 }
 
 /// Helper to create a dispute transaction
-pub fn make_dispute(client: u16, tx: u32) -> Transaction {
-    make_transaction(TransactionType::Dispute, client, tx, None)
+pub fn make_dispute(client: ClientId, tx: TxId) -> Transaction {
+    Transaction::dispute(client, tx)
 }
 
 /// Process a CSV string through the engine and return the output
@@ -37,7 +41,7 @@ pub fn process_csv_string(csv_input: &str) -> Result<String, Box<dyn std::error:
 /// Handles both "0" and "0.0" formats flexibly
 pub fn assert_client_balance(
     output: &str,
-    client_id: u16,
+    client_id: ClientId,
     available: &str,
     held: &str,
     total: &str,
@@ -87,7 +91,7 @@ pub fn assert_client_balance(
 }
 
 /// Create a test CSV from a list of transaction descriptions
-pub fn build_csv(transactions: &[(&str, u16, u32, &str)]) -> String {
+pub fn build_csv(transactions: &[(&str, ClientId, TxId, &str)]) -> String {
     let mut csv = String::from("type,client,tx,amount\n");
 
     for (tx_type, client, tx, amount) in transactions {
@@ -1,5 +1,18 @@
-use payments_engine::concurrent_engine::ShardedEngine;
-use payments_engine::models::{Transaction, TransactionType};
+#![cfg(all(feature = "async", not(feature = "fixed-point")))]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use payments_engine::concurrent_engine::{
+    hashed_shard_key, modulo_shard_key, ShardKey, ShardOptions, ShardedEngine,
+};
+use payments_engine::config::{EngineConfig, RetentionPolicy};
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::error::EngineError;
+use payments_engine::hooks::Hooks;
+use payments_engine::models::{Account, ClientId, Metadata, Transaction, TransactionType, TxId};
+use payments_engine::outcome::{Outcome, RejectReason};
+use payments_engine::rate_limit::RateLimit;
 use rust_decimal_macros::dec;
 
 /// Test concurrent deposits to the same client
@@ -17,6 +30,8 @@ async fn test_concurrent_deposits_same_client() {
             client: 1,
             tx: i,
             amount: Some(dec!(10.0)),
+            timestamp: None,
+            metadata: Metadata::new(),
         };
 
         let engine = engine.clone_handle();
@@ -53,8 +68,10 @@ async fn test_concurrent_deposits_different_clients() {
         let tx = Transaction {
             tx_type: TransactionType::Deposit,
             client: client_id,
-            tx: client_id as u32,
+            tx: client_id as TxId,
             amount: Some(dec!(100.0)),
+            timestamp: None,
+            metadata: Metadata::new(),
         };
 
         let engine = engine.clone_handle();
@@ -92,6 +109,8 @@ async fn test_concurrent_mixed_operations() {
         client: 1,
         tx: 1,
         amount: Some(dec!(1000.0)),
+        timestamp: None,
+        metadata: Metadata::new(),
     };
     engine.process_transaction(tx).await.unwrap();
 
@@ -105,6 +124,8 @@ async fn test_concurrent_mixed_operations() {
             client: 1,
             tx: 100 + i,
             amount: Some(dec!(10.0)),
+            timestamp: None,
+            metadata: Metadata::new(),
         };
 
         let engine = engine.clone_handle();
@@ -123,6 +144,8 @@ async fn test_concurrent_mixed_operations() {
             client: 2,
             tx: 200 + i,
             amount: Some(dec!(20.0)),
+            timestamp: None,
+            metadata: Metadata::new(),
         };
 
         let engine = engine.clone_handle();
@@ -156,8 +179,10 @@ async fn test_concurrent_dispute_workflow() {
         let tx = Transaction {
             tx_type: TransactionType::Deposit,
             client: client_id,
-            tx: client_id as u32,
+            tx: client_id as TxId,
             amount: Some(dec!(200.0)),
+            timestamp: None,
+            metadata: Metadata::new(),
         };
         engine.process_transaction(tx).await.unwrap();
     }
@@ -169,8 +194,10 @@ async fn test_concurrent_dispute_workflow() {
         let tx = Transaction {
             tx_type: TransactionType::Dispute,
             client: client_id,
-            tx: client_id as u32,
+            tx: client_id as TxId,
             amount: None,
+            timestamp: None,
+            metadata: Metadata::new(),
         };
 
         let engine = engine.clone_handle();
@@ -215,8 +242,10 @@ async fn test_high_concurrency() {
         let tx = Transaction {
             tx_type: TransactionType::Deposit,
             client: client_id,
-            tx: i as u32,
+            tx: i as TxId,
             amount: Some(dec!(1.0)),
+            timestamp: None,
+            metadata: Metadata::new(),
         };
 
         let engine = engine.clone_handle();
@@ -251,6 +280,8 @@ async fn test_transaction_ordering_per_client() {
         client: 1,
         tx: 1,
         amount: Some(dec!(100.0)),
+        timestamp: None,
+        metadata: Metadata::new(),
     };
 
     // Withdrawal
@@ -259,6 +290,8 @@ async fn test_transaction_ordering_per_client() {
         client: 1,
         tx: 2,
         amount: Some(dec!(30.0)),
+        timestamp: None,
+        metadata: Metadata::new(),
     };
 
     // Dispute
@@ -267,6 +300,8 @@ async fn test_transaction_ordering_per_client() {
         client: 1,
         tx: 1,
         amount: None,
+        timestamp: None,
+        metadata: Metadata::new(),
     };
 
     // Process concurrently (but all go to same shard, so serialized)
@@ -314,8 +349,10 @@ async fn test_throughput_demonstration() {
         let tx = Transaction {
             tx_type: TransactionType::Deposit,
             client: client_id,
-            tx: i as u32,
+            tx: i as TxId,
             amount: Some(dec!(1.0)),
+            timestamp: None,
+            metadata: Metadata::new(),
         };
 
         let engine = engine.clone_handle();
@@ -342,3 +379,799 @@ async fn test_throughput_demonstration() {
     // Should be very fast (tens of thousands per second)
     assert!(throughput > 1000.0, "Throughput too low: {}", throughput);
 }
+
+/// Test that every applied transaction is published to subscribers
+#[tokio::test]
+async fn test_subscribers_receive_account_events() {
+    let engine = ShardedEngine::new(4);
+    let mut events = engine.subscribe();
+
+    let handle = engine.clone_handle();
+    for (tx, amount) in [(1, dec!(5.0)), (2, dec!(50.0))] {
+        handle
+            .process_transaction(Transaction {
+                tx_type: TransactionType::Withdrawal,
+                client: 3,
+                tx,
+                amount: Some(amount),
+                timestamp: None,
+                metadata: Metadata::new(),
+            })
+            .await
+            .unwrap();
+    }
+    handle
+        .process_transaction(Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 3,
+            tx: 3,
+            amount: Some(dec!(7.5)),
+            timestamp: None,
+            metadata: Metadata::new(),
+        })
+        .await
+        .unwrap();
+
+    // Only the deposit was applied; the withdrawals had no funds to draw on
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.tx, 3);
+    assert_eq!(event.account.client_id, 3);
+    assert_eq!(event.account.available, dec!(7.5));
+    assert!(events.try_recv().is_err());
+}
+
+/// Test that a replica rebuilt from the event stream matches the engine
+#[tokio::test]
+async fn test_replica_rebuilds_from_account_events() {
+    let engine = ShardedEngine::new(4);
+    let mut events = engine.subscribe();
+
+    let handle = engine.clone_handle();
+    for client in 1..=8 {
+        for tx in [
+            deposit(client, (client as TxId) * 10),
+            deposit(client, (client as TxId) * 10 + 1),
+        ] {
+            handle.process_transaction(tx).await.unwrap();
+        }
+    }
+    for (tx_type, client, tx) in [
+        (TransactionType::Dispute, 2, 20),
+        (TransactionType::Dispute, 5, 51),
+        (TransactionType::Chargeback, 5, 51),
+    ] {
+        handle
+            .process_transaction(Transaction {
+                tx_type,
+                client,
+                tx,
+                amount: None,
+                timestamp: None,
+                metadata: Metadata::new(),
+            })
+            .await
+            .unwrap();
+    }
+
+    let mut received = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    let replica = PaymentsEngine::from_events(received);
+
+    let mut expected = engine.get_all_accounts().await;
+    expected.sort_by_key(|account| account.client_id);
+    let mut rebuilt: Vec<Account> = replica.into_accounts();
+    rebuilt.sort_by_key(|account| account.client_id);
+    assert_eq!(rebuilt.len(), expected.len());
+    for (rebuilt, expected) in rebuilt.iter().zip(&expected) {
+        assert_eq!(
+            (
+                rebuilt.available,
+                rebuilt.held,
+                rebuilt.locked,
+                rebuilt.version
+            ),
+            (
+                expected.available,
+                expected.held,
+                expected.locked,
+                expected.version
+            )
+        );
+    }
+}
+
+fn deposit(client: ClientId, tx: TxId) -> Transaction {
+    Transaction {
+        tx_type: TransactionType::Deposit,
+        client,
+        tx,
+        amount: Some(dec!(1.0)),
+        timestamp: None,
+        metadata: Metadata::new(),
+    }
+}
+
+/// Test that shutdown drains in-flight work and refuses new transactions
+#[tokio::test]
+async fn test_shutdown_drains_and_stops_intake() {
+    let engine = ShardedEngine::new(4);
+
+    let handles: Vec<_> = (0..200)
+        .map(|i| {
+            let engine = engine.clone_handle();
+            tokio::spawn(
+                async move { engine.process_transaction(deposit(i % 10, i as TxId)).await },
+            )
+        })
+        .collect();
+
+    let state = engine.shutdown().await.unwrap();
+
+    // Every transaction either made it into the final state or was refused
+    let mut applied = 0;
+    for handle in handles {
+        match handle.await.unwrap() {
+            Ok(outcome) => {
+                assert!(outcome.is_applied());
+                applied += 1;
+            }
+            Err(EngineError::ShuttingDown) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+    assert_eq!(state.processed_tx_ids.len(), applied);
+
+    let late = engine.process_transaction(deposit(1, 1000)).await;
+    assert!(matches!(late, Err(EngineError::ShuttingDown)));
+
+    // Queries still work after shutdown
+    let total: rust_decimal::Decimal = engine
+        .get_all_accounts()
+        .await
+        .iter()
+        .map(|a| a.total())
+        .sum();
+    assert_eq!(total, rust_decimal::Decimal::from(applied));
+}
+
+/// Test that a shutdown snapshot restores into a differently sized engine
+#[tokio::test]
+async fn test_state_round_trip_across_shard_counts() {
+    let engine = ShardedEngine::new(2);
+    for (client, tx) in [(1, 1), (2, 2), (3, 3)] {
+        engine
+            .process_transaction(deposit(client, tx))
+            .await
+            .unwrap();
+    }
+    engine
+        .process_transaction(Transaction {
+            tx_type: TransactionType::Dispute,
+            client: 3,
+            tx: 3,
+            amount: None,
+            timestamp: None,
+            metadata: Metadata::new(),
+        })
+        .await
+        .unwrap();
+    let state = engine.shutdown().await.unwrap();
+
+    let restored = ShardedEngine::from_state(3, state);
+    assert_eq!(restored.get_all_accounts().await.len(), 3);
+    assert_eq!(restored.get_account(3).await.unwrap().held, dec!(1.0));
+    assert_eq!(restored.get_open_disputes().await.len(), 1);
+
+    // Duplicates are still detected, whichever shard the client lands on
+    let outcome = restored.process_transaction(deposit(5, 2)).await.unwrap();
+    assert!(!outcome.is_applied());
+
+    // Resolving the restored dispute works on the new shard layout
+    let outcome = restored
+        .process_transaction(Transaction {
+            tx_type: TransactionType::Resolve,
+            client: 3,
+            tx: 3,
+            amount: None,
+            timestamp: None,
+            metadata: Metadata::new(),
+        })
+        .await
+        .unwrap();
+    assert!(outcome.is_applied());
+}
+
+/// Test that rate limits and config changes apply to a running engine
+#[tokio::test]
+async fn test_rate_limit_and_config_reload() {
+    let engine = ShardedEngine::new(2);
+    engine.set_rate_limit(Some(RateLimit {
+        per_second: 1,
+        burst: 2,
+    }));
+
+    let mut outcomes = Vec::new();
+    for tx in 0..3 {
+        outcomes.push(engine.process_transaction(deposit(1, tx)).await.unwrap());
+    }
+    assert!(outcomes[0].is_applied() && outcomes[1].is_applied());
+    assert_eq!(outcomes[2], Outcome::Rejected(RejectReason::RateLimited));
+
+    // Other clients have their own bucket
+    assert!(engine
+        .process_transaction(deposit(2, 10))
+        .await
+        .unwrap()
+        .is_applied());
+
+    engine.set_rate_limit(None);
+    engine
+        .set_config(EngineConfig {
+            max_amount: Some(dec!(0.5)),
+            ..EngineConfig::default()
+        })
+        .await;
+    assert_eq!(
+        engine.process_transaction(deposit(1, 11)).await.unwrap(),
+        Outcome::Rejected(RejectReason::AmountAboveLimit)
+    );
+}
+
+/// Test that producers outrunning a shard's queue wait rather than fail
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_full_shard_queue_applies_backpressure() {
+    let engine = ShardedEngine::new(1);
+
+    // Several times the queue capacity, all aimed at the same shard
+    let handles: Vec<_> = (0..5000)
+        .map(|tx| {
+            let engine = engine.clone_handle();
+            tokio::spawn(async move { engine.process_transaction(deposit(1, tx)).await })
+        })
+        .collect();
+
+    for handle in handles {
+        assert!(handle.await.unwrap().unwrap().is_applied());
+    }
+
+    let account = engine.get_account(1).await.unwrap();
+    assert_eq!(account.available, dec!(5000));
+}
+
+#[test]
+fn test_shard_keys_stay_in_range() {
+    for num_shards in [1, 3, 8] {
+        for client in [0, 1, 7, 1000, ClientId::MAX] {
+            assert!(modulo_shard_key(client, num_shards) < num_shards);
+            assert!(hashed_shard_key(client, num_shards) < num_shards);
+        }
+    }
+    assert_eq!(modulo_shard_key(10, 4), 2);
+
+    // Even client IDs only reach half the shards under modulo
+    let shards_used = |key: ShardKey| {
+        let used: std::collections::HashSet<_> = (0..100).map(|c| key(c * 2, 4)).collect();
+        used.len()
+    };
+    assert_eq!(shards_used(modulo_shard_key), 2);
+    assert_eq!(shards_used(hashed_shard_key), 4);
+}
+
+/// Test that a custom shard key routes consistently, including from saved state
+#[tokio::test]
+async fn test_custom_shard_key() {
+    let options = ShardOptions {
+        num_shards: 3,
+        shard_key: hashed_shard_key,
+        ..ShardOptions::default()
+    };
+    let engine = ShardedEngine::with_options(options);
+    for (client, tx) in [(1, 1), (2, 2), (1, 3)] {
+        engine
+            .process_transaction(deposit(client, tx))
+            .await
+            .unwrap();
+    }
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(2.0));
+
+    let state = engine.shutdown().await.unwrap();
+    let restored = ShardedEngine::from_state_with_options(options, state);
+    assert_eq!(restored.get_account(1).await.unwrap().available, dec!(2.0));
+    assert_eq!(
+        restored.process_transaction(deposit(2, 2)).await.unwrap(),
+        Outcome::Rejected(RejectReason::DuplicateTransaction)
+    );
+}
+
+/// Test that resizing a running engine keeps balances, disputes and
+/// duplicate detection, and is visible through every handle
+#[tokio::test]
+async fn test_resize_running_engine() {
+    let engine = ShardedEngine::new(2);
+    let other_handle = engine.clone_handle();
+    engine
+        .set_config(EngineConfig {
+            max_amount: Some(dec!(5)),
+            ..EngineConfig::default()
+        })
+        .await;
+
+    for client in 1..=6 {
+        engine
+            .process_transaction(deposit(client, client as TxId))
+            .await
+            .unwrap();
+    }
+    let dispute = Transaction {
+        tx_type: TransactionType::Dispute,
+        client: 3,
+        tx: 3,
+        amount: None,
+        timestamp: None,
+        metadata: Metadata::new(),
+    };
+    engine.process_transaction(dispute).await.unwrap();
+
+    // Writers keep going while the engine grows
+    let writer = tokio::spawn(async move {
+        for tx in 100..300 {
+            other_handle
+                .process_transaction(deposit(1, tx))
+                .await
+                .unwrap();
+        }
+        other_handle
+    });
+    engine.resize(5).await.unwrap();
+    let other_handle = writer.await.unwrap();
+
+    assert_eq!(other_handle.num_shards().await, 5);
+    assert_eq!(engine.get_account(1).await.unwrap().available, dec!(201));
+    assert_eq!(engine.get_account(3).await.unwrap().held, dec!(1.0));
+    assert_eq!(engine.get_open_disputes().await.len(), 1);
+    assert_eq!(engine.get_all_accounts().await.len(), 6);
+
+    // Processed IDs and config survive the move
+    assert_eq!(
+        engine.process_transaction(deposit(4, 4)).await.unwrap(),
+        Outcome::Rejected(RejectReason::DuplicateTransaction)
+    );
+    let large = Transaction {
+        amount: Some(dec!(10)),
+        ..deposit(2, 500)
+    };
+    assert_eq!(
+        engine.process_transaction(large).await.unwrap(),
+        Outcome::Rejected(RejectReason::AmountAboveLimit)
+    );
+
+    engine.resize(1).await.unwrap();
+    assert_eq!(engine.get_account(6).await.unwrap().available, dec!(1.0));
+
+    assert!(matches!(
+        engine.resize(0).await,
+        Err(EngineError::InvalidConfig(_))
+    ));
+    engine.shutdown().await.unwrap();
+    assert!(matches!(
+        engine.resize(2).await,
+        Err(EngineError::ShuttingDown)
+    ));
+}
+
+/// Test that into_accounts moves every account out and stops other handles
+#[tokio::test]
+async fn test_into_accounts_finishes_engine() {
+    let engine = ShardedEngine::new(3);
+    let other_handle = engine.clone_handle();
+    for (client, tx) in [(5, 1), (2, 2), (9, 3), (2, 4)] {
+        engine
+            .process_transaction(deposit(client, tx))
+            .await
+            .unwrap();
+    }
+
+    let accounts = engine.into_accounts().await.unwrap();
+    let clients: Vec<_> = accounts.iter().map(|a| a.client_id).collect();
+    assert_eq!(clients, vec![2, 5, 9]);
+    assert_eq!(accounts[0].available, dec!(2.0));
+
+    assert!(matches!(
+        other_handle.process_transaction(deposit(2, 5)).await,
+        Err(EngineError::ShuttingDown)
+    ));
+    assert!(other_handle.get_account(2).await.is_none());
+    assert!(other_handle.get_all_accounts().await.is_empty());
+}
+
+/// Test that shard metrics attribute transactions to the shard that ran them
+#[tokio::test]
+async fn test_shard_metrics() {
+    let engine = ShardedEngine::new(2);
+    for (client, tx) in [(1, 1), (3, 2), (2, 3)] {
+        engine
+            .process_transaction(deposit(client, tx))
+            .await
+            .unwrap();
+    }
+    // Duplicate on the odd shard
+    engine.process_transaction(deposit(1, 1)).await.unwrap();
+
+    let metrics = engine.shard_metrics().await;
+    assert_eq!(metrics.len(), 2);
+    assert_eq!((metrics[0].applied, metrics[0].rejected), (1, 0));
+    assert_eq!((metrics[1].applied, metrics[1].rejected), (2, 1));
+    assert_eq!(metrics[1].processed(), 3);
+    assert!(metrics.iter().all(|m| m.failed == 0 && m.queue_depth == 0));
+}
+
+/// Test that rejections are counted by reason across shards and the rate limiter
+#[tokio::test]
+async fn test_rejection_counts() {
+    let engine = ShardedEngine::new(2);
+    assert!(engine.rejection_counts().await.is_empty());
+
+    for (client, tx) in [(1, 1), (2, 2)] {
+        engine
+            .process_transaction(deposit(client, tx))
+            .await
+            .unwrap();
+    }
+    // Duplicates on both shards, one version mismatch
+    engine.process_transaction(deposit(1, 1)).await.unwrap();
+    engine.process_transaction(deposit(2, 2)).await.unwrap();
+    engine
+        .process_transaction_if_version(deposit(1, 3), 7)
+        .await
+        .unwrap();
+
+    engine.set_rate_limit(Some(RateLimit {
+        per_second: 1,
+        burst: 1,
+    }));
+    engine.process_transaction(deposit(3, 4)).await.unwrap();
+    engine.process_transaction(deposit(3, 5)).await.unwrap();
+
+    assert_eq!(
+        engine.rejection_counts().await,
+        [
+            (RejectReason::DuplicateTransaction, 2),
+            (RejectReason::RateLimited, 1),
+            (RejectReason::VersionMismatch, 1),
+        ]
+    );
+}
+
+/// Test that per-shard WALs rebuild the engine after a restart
+#[tokio::test]
+async fn test_recover_from_shard_wals() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let engine = ShardedEngine::recover(dir.path(), 3).unwrap();
+    for (client, tx) in [(1, 1), (2, 2), (3, 3), (1, 4)] {
+        engine
+            .process_transaction(deposit(client, tx))
+            .await
+            .unwrap();
+    }
+    let dispute = Transaction {
+        tx_type: TransactionType::Dispute,
+        client: 2,
+        tx: 2,
+        amount: None,
+        timestamp: None,
+        metadata: Metadata::new(),
+    };
+    engine.process_transaction(dispute).await.unwrap();
+    assert!(matches!(
+        engine.resize(4).await,
+        Err(EngineError::InvalidConfig(_))
+    ));
+    engine.shutdown().await.unwrap();
+    drop(engine);
+
+    for shard in 0..3 {
+        assert!(dir
+            .path()
+            .join(format!("shard-{}/wal.log", shard))
+            .is_file());
+    }
+
+    let recovered = ShardedEngine::recover(dir.path(), 3).unwrap();
+    assert_eq!(recovered.get_account(1).await.unwrap().available, dec!(2.0));
+    assert_eq!(recovered.get_account(2).await.unwrap().held, dec!(1.0));
+    assert_eq!(
+        recovered.process_transaction(deposit(3, 3)).await.unwrap(),
+        Outcome::Rejected(RejectReason::DuplicateTransaction)
+    );
+    drop(recovered);
+
+    assert!(matches!(
+        ShardedEngine::recover(dir.path(), 2),
+        Err(EngineError::InvalidConfig(_))
+    ));
+}
+
+/// Test that snapshots never show a later transaction without an earlier one
+/// from another shard
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_snapshot_is_consistent_across_shards() {
+    let engine = ShardedEngine::new(2);
+    let writer_engine = engine.clone_handle();
+
+    // Client 1 (shard 1) is always credited before client 2 (shard 0)
+    let writer = tokio::spawn(async move {
+        for tx in 0..500 {
+            for client in [1, 2] {
+                writer_engine
+                    .process_transaction(deposit(client, tx * 2 + client as TxId))
+                    .await
+                    .unwrap();
+            }
+        }
+    });
+
+    while !writer.is_finished() {
+        let accounts = engine.snapshot().await;
+        let balance = |client| {
+            accounts
+                .iter()
+                .find(|a| a.client_id == client)
+                .map_or(dec!(0), |a| a.available)
+        };
+        let lead = balance(1) - balance(2);
+        assert!(
+            lead == dec!(0) || lead == dec!(1),
+            "torn snapshot: {}",
+            lead
+        );
+    }
+    writer.await.unwrap();
+
+    let accounts = engine.snapshot().await;
+    assert_eq!(accounts.len(), 2);
+    assert!(accounts.iter().all(|a| a.available == dec!(500)));
+}
+
+/// Test that an accounts view stays frozen while later transactions apply
+#[tokio::test]
+async fn test_accounts_view_is_unaffected_by_later_transactions() {
+    let engine = ShardedEngine::new(4);
+    for client in [300, 7, 1] {
+        engine
+            .process_transaction(deposit(client, client as TxId))
+            .await
+            .unwrap();
+    }
+
+    let view = engine.accounts_view().await;
+
+    // Update an existing account on each shard and open new ones
+    for client in [300, 7, 1, 2, 1000] {
+        engine
+            .process_transaction(deposit(client, 10_000 + client as TxId))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(view.len(), 3);
+    let ids: Vec<ClientId> = view.iter().map(|a| a.client_id).collect();
+    assert_eq!(ids, vec![1, 7, 300]);
+    assert!(view.iter().all(|a| a.available == dec!(1)));
+    assert_eq!(view.get(7).unwrap().available, dec!(1));
+    assert!(view.get(2).is_none());
+
+    // A fresh view sees everything
+    let latest = engine.accounts_view().await;
+    assert_eq!(latest.len(), 5);
+    assert_eq!(latest.get(7).unwrap().available, dec!(2));
+    let ids: Vec<ClientId> = latest.iter().map(|a| a.client_id).collect();
+    assert_eq!(ids, vec![1, 2, 7, 300, 1000]);
+}
+
+/// Test compare-and-swap processing against account versions
+#[tokio::test]
+async fn test_process_transaction_if_version() {
+    let engine = ShardedEngine::new(4);
+    engine.process_transaction(deposit(5, 1)).await.unwrap();
+
+    let reviewed = engine.get_account(5).await.unwrap().version;
+    assert_eq!(reviewed, 1);
+
+    engine.process_transaction(deposit(5, 2)).await.unwrap();
+    assert_eq!(
+        engine
+            .process_transaction_if_version(deposit(5, 3), reviewed)
+            .await
+            .unwrap(),
+        Outcome::Rejected(RejectReason::VersionMismatch)
+    );
+    assert_eq!(engine.get_account(5).await.unwrap().available, dec!(2));
+
+    let current = engine.get_account(5).await.unwrap().version;
+    assert_eq!(
+        engine
+            .process_transaction_if_version(deposit(5, 3), current)
+            .await
+            .unwrap(),
+        Outcome::Applied
+    );
+
+    // A client without an account is at version 0
+    assert_eq!(
+        engine
+            .process_transaction_if_version(deposit(6, 4), 0)
+            .await
+            .unwrap(),
+        Outcome::Applied
+    );
+    assert_eq!(engine.get_account(5).await.unwrap().version, 3);
+}
+
+/// Test dry runs on the client's shard
+#[tokio::test]
+async fn test_simulate() {
+    let engine = ShardedEngine::new(4);
+    engine.set_rate_limit(Some(RateLimit {
+        per_second: 1,
+        burst: 2,
+    }));
+    engine.process_transaction(deposit(5, 1)).await.unwrap();
+
+    let withdrawal = |amount| Transaction {
+        tx_type: TransactionType::Withdrawal,
+        client: 5,
+        tx: 2,
+        amount: Some(amount),
+        timestamp: None,
+        metadata: Metadata::new(),
+    };
+    let simulation = engine.simulate(withdrawal(dec!(3))).await.unwrap();
+    assert_eq!(
+        simulation.outcome,
+        Outcome::Rejected(RejectReason::InsufficientFunds)
+    );
+    assert_eq!(simulation.account.unwrap().available, dec!(1));
+
+    let simulation = engine.simulate(withdrawal(dec!(0.25))).await.unwrap();
+    assert_eq!(simulation.outcome, Outcome::Applied);
+    assert_eq!(simulation.account.unwrap().available, dec!(0.75));
+    assert_eq!(engine.get_account(5).await.unwrap().available, dec!(1));
+
+    // Simulations don't spend the rate limit, so the last token is still there
+    assert_eq!(
+        engine
+            .process_transaction(withdrawal(dec!(0.25)))
+            .await
+            .unwrap(),
+        Outcome::Applied
+    );
+}
+
+/// Test hooks on every shard, including shards created by a resize
+#[tokio::test]
+async fn test_set_hooks_reaches_every_shard() {
+    let committed = Arc::new(AtomicUsize::new(0));
+    let count = committed.clone();
+    let engine = ShardedEngine::new(2);
+    engine
+        .set_hooks(
+            Hooks::new()
+                .pre_commit(|tx, _| {
+                    if tx.client % 2 == 0 {
+                        Err(RejectReason::Vetoed)
+                    } else {
+                        Ok(())
+                    }
+                })
+                .post_commit(move |_, _| {
+                    count.fetch_add(1, Ordering::Relaxed);
+                }),
+        )
+        .await;
+
+    let mut tx = 0;
+    for num_shards in [2, 5] {
+        engine.resize(num_shards).await.unwrap();
+        for client in 1..=10 {
+            tx += 1;
+            let outcome = engine
+                .process_transaction(deposit(client, tx))
+                .await
+                .unwrap();
+            assert_eq!(outcome.is_applied(), client % 2 == 1, "client {}", client);
+        }
+    }
+    assert_eq!(committed.load(Ordering::Relaxed), 10);
+    assert_eq!(engine.stats().await.accounts, 5);
+}
+
+#[tokio::test]
+async fn test_funds_sum_across_shards_and_resizes() {
+    let engine = ShardedEngine::new(3);
+    for client in 1..=6 {
+        engine
+            .process_transaction(deposit(client, client as TxId))
+            .await
+            .unwrap();
+    }
+    for (tx_type, client) in [
+        (TransactionType::Dispute, 2),
+        (TransactionType::Dispute, 5),
+        (TransactionType::Chargeback, 5),
+    ] {
+        let tx = Transaction {
+            tx_type,
+            client,
+            tx: client as TxId,
+            amount: None,
+            timestamp: None,
+            metadata: Metadata::new(),
+        };
+        assert!(engine.process_transaction(tx).await.unwrap().is_applied());
+    }
+
+    let funds = engine.funds().await;
+    assert_eq!((funds.available, funds.held), (dec!(4.0), dec!(1.0)));
+    assert_eq!((funds.charged_back, funds.open_disputes), (dec!(1.0), 1));
+    assert_eq!(engine.holds(2).await[0].tx_id, 2);
+    assert!(engine.holds(5).await.is_empty());
+
+    // Chargebacks and holds move with their clients
+    engine.resize(2).await.unwrap();
+    assert_eq!(engine.funds().await, funds);
+    assert_eq!(engine.holds(2).await[0].amount, dec!(1.0));
+}
+
+#[tokio::test]
+async fn test_shards_share_an_archive_across_resizes() {
+    let dir = tempfile::tempdir().unwrap();
+    let engine = ShardedEngine::new(2);
+    assert!(engine.archived_transaction(1).await.unwrap().is_none());
+    engine.archive_to(dir.path().join("archive")).await.unwrap();
+    engine
+        .set_config(EngineConfig {
+            retention: RetentionPolicy {
+                max_entries: None,
+                max_age: Some(1),
+            },
+            ..EngineConfig::default()
+        })
+        .await;
+
+    let mut tx = 0;
+    for (num_shards, per_client) in [(2, 2), (3, 3)] {
+        engine.resize(num_shards).await.unwrap();
+        for client in 1..=3 {
+            for _ in 0..per_client {
+                tx += 1;
+                engine
+                    .process_transaction(deposit(client, tx))
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    let mut archived = Vec::new();
+    for tx in 1..=15 {
+        if let Some(stored_tx) = engine.archived_transaction(tx).await.unwrap() {
+            archived.push(stored_tx.tx_id);
+        }
+    }
+    // Deposits older than one transaction on their shard: 1 and 2 before the
+    // resize, then the first of each client's three after it, along with
+    // the deposits the resize moved to other shards
+    assert_eq!(archived, [1, 2, 3, 4, 5, 6, 7, 10, 13]);
+    assert_eq!(
+        engine
+            .archived_transaction(3)
+            .await
+            .unwrap()
+            .unwrap()
+            .client_id,
+        2
+    );
+}
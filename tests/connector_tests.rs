@@ -0,0 +1,443 @@
+#![cfg(not(feature = "fixed-point"))]
+
+mod common;
+
+#[cfg(feature = "async")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "async")]
+use std::sync::{Arc, Mutex};
+
+use common::{make_deposit, make_dispute};
+#[cfg(feature = "file-tail")]
+use payments_engine::amount::Amount;
+#[cfg(feature = "async")]
+use payments_engine::concurrent_engine::ShardedEngine;
+use payments_engine::connectors::format::MessageFormat;
+use payments_engine::connectors::iso8583::{
+    self, client_for_pan, BitmapEncoding, ClientMapping, Iso8583Format,
+};
+use payments_engine::connectors::offsets::OffsetTracker;
+#[cfg(feature = "async")]
+use payments_engine::connectors::publish::{publish_events, EventSink, PublishStats};
+use payments_engine::error::EngineError;
+#[cfg(feature = "async")]
+use payments_engine::error::Result;
+#[cfg(feature = "async")]
+use payments_engine::events::AccountEvent;
+use payments_engine::models::{ClientId, Transaction, TransactionType, TxId};
+use rust_decimal_macros::dec;
+#[cfg(feature = "async")]
+use tokio::sync::oneshot;
+
+/// Avro encoding of a transaction record, without framing
+fn avro_record(tx_type: &str, client: u8, tx: u8, amount: Option<&str>) -> Vec<u8> {
+    // Every value here fits a single zigzag varint byte
+    let mut bytes = vec![tx_type.len() as u8 * 2];
+    bytes.extend_from_slice(tx_type.as_bytes());
+    bytes.extend_from_slice(&[client * 2, tx * 2]);
+    match amount {
+        Some(amount) => {
+            bytes.extend_from_slice(&[2, amount.len() as u8 * 2]);
+            bytes.extend_from_slice(amount.as_bytes());
+        }
+        None => bytes.push(0),
+    }
+    bytes
+}
+
+/// Protocol Buffers encoding of a transaction message
+fn protobuf_message(tx_type: &str, client: u8, tx: u8, amount: &str) -> Vec<u8> {
+    let mut bytes = vec![0x0a, tx_type.len() as u8];
+    bytes.extend_from_slice(tx_type.as_bytes());
+    bytes.extend_from_slice(&[0x10, client, 0x18, tx]);
+    if !amount.is_empty() {
+        bytes.extend_from_slice(&[0x22, amount.len() as u8]);
+        bytes.extend_from_slice(amount.as_bytes());
+    }
+    bytes
+}
+
+fn assert_transaction(tx: &Transaction, tx_type: TransactionType, client: ClientId, id: TxId) {
+    assert_eq!(tx.tx_type, tx_type);
+    assert_eq!(tx.client, client);
+    assert_eq!(tx.tx, id);
+}
+
+#[test]
+fn test_decode_json_message() {
+    let tx = MessageFormat::Json
+        .decode(br#"{"type":"deposit","client":1,"tx":7,"amount":"2.5"}"#)
+        .unwrap();
+    assert_transaction(&tx, TransactionType::Deposit, 1, 7);
+    assert_eq!(tx.amount, Some(dec!(2.5)));
+
+    assert!(matches!(
+        MessageFormat::Json.decode(b"deposit,1,7,2.5"),
+        Err(EngineError::InvalidMessage(_))
+    ));
+}
+
+#[test]
+fn test_decode_avro_message_with_and_without_framing() {
+    let record = avro_record("deposit", 1, 7, Some("2.5"));
+    let tx = MessageFormat::Avro.decode(&record).unwrap();
+    assert_transaction(&tx, TransactionType::Deposit, 1, 7);
+    assert_eq!(tx.amount, Some(dec!(2.5)));
+
+    // Schema registry framing: magic byte and schema ID
+    let mut framed = vec![0, 0, 0, 0, 42];
+    framed.extend_from_slice(&avro_record("dispute", 3, 7, None));
+    let tx = MessageFormat::Avro.decode(&framed).unwrap();
+    assert_transaction(&tx, TransactionType::Dispute, 3, 7);
+    assert_eq!(tx.amount, None);
+
+    assert!(MessageFormat::Avro
+        .decode(&record[..record.len() - 1])
+        .is_err());
+    assert!(MessageFormat::Avro
+        .decode(&avro_record("refund", 1, 7, Some("1")))
+        .is_err());
+}
+
+#[test]
+fn test_decode_protobuf_message() {
+    let tx = MessageFormat::Protobuf
+        .decode(&protobuf_message("withdrawal", 2, 9, "1.25"))
+        .unwrap();
+    assert_transaction(&tx, TransactionType::Withdrawal, 2, 9);
+    assert_eq!(tx.amount, Some(dec!(1.25)));
+
+    // Unknown fields are skipped and a missing amount means none
+    let mut message = protobuf_message("resolve", 2, 9, "");
+    message.extend_from_slice(&[0x48, 0x96, 0x01]);
+    let tx = MessageFormat::Protobuf.decode(&message).unwrap();
+    assert_transaction(&tx, TransactionType::Resolve, 2, 9);
+    assert_eq!(tx.amount, None);
+
+    // Client IDs beyond u16 are rejected like in CSV input
+    #[cfg(not(feature = "wide-client-ids"))]
+    {
+        let mut message = vec![0x0a, 7];
+        message.extend_from_slice(b"deposit");
+        message.extend_from_slice(&[0x10, 0xf0, 0xa2, 0x04, 0x18, 1, 0x22, 1, b'1']);
+        assert!(MessageFormat::Protobuf.decode(&message).is_err());
+    }
+}
+
+#[test]
+fn test_message_format_from_str() {
+    assert_eq!(
+        "avro".parse::<MessageFormat>().unwrap(),
+        MessageFormat::Avro
+    );
+    assert_eq!(
+        "protobuf".parse::<MessageFormat>().unwrap(),
+        MessageFormat::Protobuf
+    );
+    assert!("xml".parse::<MessageFormat>().is_err());
+}
+
+#[test]
+fn test_encode_round_trips_through_decode() {
+    let transactions = [
+        make_deposit(1, 1, dec!(2.5000)),
+        make_deposit(0, 300, dec!(0.0001)),
+        // The largest 32-bit ID, which every format carries in both ID widths
+        make_dispute(65535, 4_294_967_295),
+    ];
+    for format in [
+        MessageFormat::Json,
+        MessageFormat::Avro,
+        MessageFormat::Protobuf,
+    ] {
+        for tx in &transactions {
+            let decoded = format.decode(&format.encode(tx)).unwrap();
+            assert_eq!(&decoded, tx, "{:?}", format);
+        }
+    }
+
+    // Same bytes as the hand-built messages the decoder is tested with
+    let deposit = make_deposit(3, 7, dec!(1.5));
+    assert_eq!(
+        MessageFormat::Avro.encode(&deposit),
+        avro_record("deposit", 3, 7, Some("1.5"))
+    );
+    assert_eq!(
+        MessageFormat::Protobuf.encode(&deposit),
+        protobuf_message("deposit", 3, 7, "1.5")
+    );
+    assert_eq!(
+        MessageFormat::Json.encode(&make_dispute(3, 7)),
+        br#"{"type":"dispute","client":3,"tx":7,"amount":null}"#
+    );
+}
+
+/// ISO 8583 message with a hex bitmap; `fields` must be in order
+fn iso8583_message(mti: &str, fields: &[(u8, &str)]) -> Vec<u8> {
+    let bitmap = fields
+        .iter()
+        .fold(0u64, |bitmap, (field, _)| bitmap | 1 << (64 - field));
+    let mut message = format!("{}{:016X}", mti, bitmap);
+    for (field, value) in fields {
+        // The PAN is the only variable-length field used here
+        if *field == 2 {
+            message.push_str(&format!("{:02}", value.len()));
+        }
+        message.push_str(value);
+    }
+    message.into_bytes()
+}
+
+const PAN: &str = "4111111111111111";
+
+#[test]
+fn test_decode_iso8583_deposit_and_withdrawal() {
+    let format = Iso8583Format::default();
+
+    let deposit = iso8583_message(
+        "0200",
+        &[(2, PAN), (3, "210000"), (4, "000000012345"), (11, "000042")],
+    );
+    let tx = format.decode(&deposit).unwrap();
+    assert_transaction(&tx, TransactionType::Deposit, client_for_pan(PAN), 42);
+    assert_eq!(tx.amount, Some(dec!(123.45)));
+
+    // Fields between the ones read are skipped, here the transmission time
+    let withdrawal = iso8583_message(
+        "0200",
+        &[
+            (2, PAN),
+            (3, "000000"),
+            (4, "000000000500"),
+            (7, "0229123456"),
+            (11, "000043"),
+        ],
+    );
+    let tx = format.decode(&withdrawal).unwrap();
+    assert_transaction(&tx, TransactionType::Withdrawal, client_for_pan(PAN), 43);
+    assert_eq!(tx.amount, Some(dec!(5)));
+
+    // Unmapped MTIs and truncated messages are invalid
+    let reversal = iso8583_message("0400", &[(2, PAN), (4, "000000000500"), (11, "000044")]);
+    assert!(matches!(
+        format.decode(&reversal),
+        Err(EngineError::InvalidMessage(_))
+    ));
+    assert!(matches!(
+        format.decode(&deposit[..deposit.len() - 1]),
+        Err(EngineError::InvalidMessage(_))
+    ));
+}
+
+#[test]
+fn test_decode_iso8583_with_custom_mapping() {
+    let mut format = Iso8583Format::default();
+    format
+        .types
+        .insert("0422".to_string(), TransactionType::Dispute);
+    format.client_mapping = ClientMapping::Numeric;
+    format.client_field = 41;
+    format.bitmap = BitmapEncoding::Binary;
+
+    // Binary bitmap with fields 11 and 41: the terminal ID holds the client
+    let mut message = b"0422".to_vec();
+    message.extend_from_slice(&(1u64 << 53 | 1 << 23).to_be_bytes());
+    message.extend_from_slice(b"00004200000007");
+
+    // Disputes carry no amount
+    let tx = format.decode(&message).unwrap();
+    assert_transaction(&tx, TransactionType::Dispute, 7, 42);
+    assert_eq!(tx.amount, None);
+}
+
+#[test]
+fn test_read_iso8583_transactions_skips_invalid_messages() {
+    let mut input = Vec::new();
+    for message in [
+        iso8583_message("0200", &[(2, PAN), (4, "000000000100"), (11, "000001")]),
+        b"garbage".to_vec(),
+        iso8583_message("0200", &[(2, PAN), (4, "000000000200"), (11, "000002")]),
+    ] {
+        input.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        input.extend_from_slice(&message);
+    }
+    // A message cut short at the end of the input
+    input.extend_from_slice(&[0, 50, b'0']);
+
+    let format = Iso8583Format::default();
+    let results: Vec<_> = iso8583::read_transactions(input.as_slice(), &format).collect();
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].as_ref().unwrap().tx, 1);
+    assert!(matches!(results[1], Err(EngineError::InvalidMessage(_))));
+    assert_eq!(results[2].as_ref().unwrap().amount, Some(dec!(2)));
+    assert!(matches!(results[3], Err(EngineError::Io(_))));
+}
+
+#[test]
+fn test_offset_tracker_commits_only_contiguous_offsets() {
+    let mut tracker = OffsetTracker::new();
+    assert_eq!(tracker.committable(0), None);
+
+    for offset in 10..14 {
+        tracker.start(0, offset);
+    }
+    tracker.start(1, 5);
+
+    // Later offsets finishing first don't move the watermark
+    assert_eq!(tracker.finish(0, 12), Some(10));
+    assert_eq!(tracker.finish(0, 11), Some(10));
+    assert_eq!(tracker.finish(0, 10), Some(13));
+    assert!(tracker.has_in_flight());
+
+    assert_eq!(tracker.finish(0, 13), Some(14));
+    assert_eq!(tracker.finish(1, 5), Some(6));
+    assert!(!tracker.has_in_flight());
+
+    let mut watermarks: Vec<_> = tracker.watermarks().collect();
+    watermarks.sort();
+    assert_eq!(watermarks, vec![(0, 14), (1, 6)]);
+}
+
+#[cfg(feature = "async")]
+/// Sink collecting events in memory
+#[derive(Default)]
+struct VecSink {
+    events: Arc<Mutex<Vec<AccountEvent>>>,
+    flushed: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "async")]
+impl EventSink for VecSink {
+    async fn publish(&mut self, event: &AccountEvent) -> Result<()> {
+        self.events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.flushed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_publish_events_forwards_in_order_and_drains_on_shutdown() {
+    let engine = ShardedEngine::new(2);
+    let sink = VecSink::default();
+    let (events, flushed) = (sink.events.clone(), sink.flushed.clone());
+    let (stop, stopped) = oneshot::channel::<()>();
+    let publisher = tokio::spawn(publish_events(engine.subscribe(), sink, async {
+        let _ = stopped.await;
+    }));
+
+    engine
+        .process_transaction(make_deposit(1, 1, dec!(5)))
+        .await
+        .unwrap();
+    engine
+        .process_transaction(make_deposit(2, 2, dec!(1)))
+        .await
+        .unwrap();
+    engine
+        .process_transaction(make_dispute(1, 1))
+        .await
+        .unwrap();
+    // Rejected transactions produce no events
+    engine
+        .process_transaction(make_dispute(1, 9))
+        .await
+        .unwrap();
+
+    // Events already emitted are published even when stopped right away
+    stop.send(()).unwrap();
+    let stats = publisher.await.unwrap().unwrap();
+    assert_eq!(
+        stats,
+        PublishStats {
+            published: 3,
+            missed: 0
+        }
+    );
+    assert!(flushed.load(Ordering::SeqCst));
+
+    let events = events.lock().unwrap();
+    let summary: Vec<_> = events
+        .iter()
+        .map(|e| (e.tx, e.tx_type, e.account.client_id, e.account.version))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (1, TransactionType::Deposit, 1, 1),
+            (2, TransactionType::Deposit, 2, 1),
+            (1, TransactionType::Dispute, 1, 2),
+        ]
+    );
+}
+
+/// Poll `engine` until client `client` has `available`, failing after a second
+#[cfg(feature = "file-tail")]
+async fn wait_for_available(engine: &ShardedEngine, client: ClientId, available: Amount) {
+    for _ in 0..100 {
+        if let Some(account) = engine.get_account(client).await {
+            if account.available == available {
+                return;
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("client {} never reached {}", client, available);
+}
+
+#[cfg(feature = "file-tail")]
+#[tokio::test]
+async fn test_file_tail_applies_appended_lines_and_resumes_from_its_offset() {
+    use std::io::Write;
+
+    use payments_engine::connectors::file_tail::{self, TailSourceOptions};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("transactions.csv");
+    let mut options = TailSourceOptions::new(&path);
+    options.offset_file = Some(dir.path().join("offset"));
+    options.poll_ms = 10;
+    let follow = |engine: ShardedEngine| {
+        let (stop, stopped) = oneshot::channel::<()>();
+        let options = options.clone();
+        let following = tokio::spawn(async move {
+            file_tail::consume(&engine, options, async {
+                let _ = stopped.await;
+            })
+            .await
+        });
+        (stop, following)
+    };
+
+    // Followed before it exists; the second row arrives in two writes
+    let engine = ShardedEngine::new(2);
+    let (stop, following) = follow(engine.clone_handle());
+    let mut file = std::fs::File::create(&path).unwrap();
+    file.write_all(b"type,client,tx,amount\ndeposit,1,1,5.0\ndeposit,2,2,1")
+        .unwrap();
+    wait_for_available(&engine, 1, dec!(5)).await;
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(engine.get_account(2).await.is_none());
+
+    file.write_all(b".5\nnot,a,row\nwithdrawal,1,3,2.0\n")
+        .unwrap();
+    wait_for_available(&engine, 2, dec!(1.5)).await;
+    wait_for_available(&engine, 1, dec!(3)).await;
+    stop.send(()).unwrap();
+    let stats = following.await.unwrap().unwrap();
+    assert_eq!((stats.processed, stats.malformed), (3, 1));
+
+    // A restart only reads what was appended since
+    file.write_all(b"deposit,3,4,1.0\n").unwrap();
+    let engine = ShardedEngine::new(2);
+    let (stop, following) = follow(engine.clone_handle());
+    wait_for_available(&engine, 3, dec!(1)).await;
+    stop.send(()).unwrap();
+    let stats = following.await.unwrap().unwrap();
+    assert_eq!(stats.processed, 1);
+    assert!(engine.get_account(1).await.is_none());
+}
@@ -0,0 +1,149 @@
+#![cfg(all(feature = "tui", not(feature = "fixed-point")))]
+
+use std::time::{Duration, Instant};
+
+use payments_engine::concurrent_engine::ShardedEngine;
+use payments_engine::dashboard::{top_held, Dashboard, Sample};
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::models::{ClientId, Transaction};
+use payments_engine::outcome::{Outcome, RejectReason};
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+use rust_decimal_macros::dec;
+
+/// Render `dashboard` on a terminal of the given size and return its lines
+fn render(dashboard: &Dashboard, width: u16, height: u16) -> Vec<String> {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(|frame| dashboard.render(frame)).unwrap();
+    let buffer = terminal.backend().buffer();
+    (0..height)
+        .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect())
+        .collect()
+}
+
+fn engine_with_disputes() -> PaymentsEngine {
+    let mut engine = PaymentsEngine::new();
+    for (client, tx, amount) in [
+        (1, 1, dec!(5)),
+        (2, 2, dec!(50)),
+        (3, 3, dec!(50)),
+        (4, 4, dec!(1)),
+    ] {
+        engine.process_transaction(Transaction::deposit(client, tx, amount));
+    }
+    for (client, tx) in [(3, 3), (1, 1), (2, 2)] {
+        engine.process_transaction(Transaction::dispute(client, tx));
+    }
+    engine
+}
+
+#[test]
+fn test_top_held() {
+    let engine = engine_with_disputes();
+    let clients: Vec<ClientId> = top_held(engine.accounts_iter(), 10)
+        .iter()
+        .map(|account| account.client_id)
+        .collect();
+    // Client 4 holds nothing; ties go to the lower client ID
+    assert_eq!(clients, [2, 3, 1]);
+    assert_eq!(top_held(engine.accounts_iter(), 1)[0].client_id, 2);
+}
+
+#[test]
+fn test_sample_records_outcomes() {
+    let mut sample = Sample::default();
+    sample.record(Outcome::Applied);
+    sample.record(Outcome::Rejected(RejectReason::AccountLocked));
+    sample.record(Outcome::Rejected(RejectReason::NotDisputed));
+    sample.record(Outcome::Rejected(RejectReason::AccountLocked));
+
+    assert_eq!(
+        (sample.applied, sample.rejected, sample.processed()),
+        (1, 3, 4)
+    );
+    assert_eq!(
+        sample.rejections,
+        [
+            (RejectReason::AccountLocked, 2),
+            (RejectReason::NotDisputed, 1)
+        ]
+    );
+}
+
+#[test]
+fn test_throughput_between_samples() {
+    let started = Instant::now();
+    let mut dashboard = Dashboard::new("test", started);
+    assert_eq!(dashboard.throughput(), 0);
+
+    let mut sample = Sample {
+        applied: 500,
+        ..Sample::default()
+    };
+    dashboard.update(sample.clone(), started + Duration::from_millis(250));
+    assert_eq!(dashboard.throughput(), 2000);
+
+    sample.rejected = 100;
+    dashboard.update(sample, started + Duration::from_secs(1));
+    assert_eq!(dashboard.throughput(), 133);
+    assert_eq!(dashboard.latest().processed(), 600);
+}
+
+#[test]
+fn test_batch_dashboard_renders_panels() {
+    let started = Instant::now();
+    let mut dashboard = Dashboard::new("batch run", started);
+    let mut sample = Sample::default();
+    sample.record(Outcome::Applied);
+    sample.record(Outcome::Rejected(RejectReason::InsufficientFunds));
+    sample.top_held = top_held(engine_with_disputes().accounts_iter(), 10);
+    dashboard.update(sample, started + Duration::from_secs(1));
+
+    let screen = render(&dashboard, 120, 24).join("\n");
+    for text in [
+        "batch run",
+        "2 tx/s | processed 2 | applied 1 | rejected 1 | failed 0",
+        "Throughput (tx/s)",
+        "insufficient available funds",
+        "Top held",
+        "q: quit",
+    ] {
+        assert!(screen.contains(text), "'{}' missing from\n{}", text, screen);
+    }
+    // Batch runs have no shards to show
+    assert!(!screen.contains("Shards"));
+}
+
+#[tokio::test]
+async fn test_server_sample_and_shard_panel() {
+    let engine = ShardedEngine::new(2);
+    for tx in [
+        Transaction::deposit(1, 1, dec!(10)),
+        Transaction::deposit(2, 2, dec!(20)),
+        Transaction::dispute(2, 2),
+        Transaction::deposit(1, 1, dec!(10)),
+    ] {
+        engine.process_transaction(tx).await.unwrap();
+    }
+
+    let sample = Sample::of(&engine).await;
+    assert_eq!((sample.applied, sample.rejected, sample.failed), (3, 1, 0));
+    assert_eq!(sample.shards.len(), 2);
+    assert_eq!(sample.rejections, [(RejectReason::DuplicateTransaction, 1)]);
+    assert_eq!(sample.top_held.len(), 1);
+    assert_eq!(sample.top_held[0].held, dec!(20));
+
+    let started = Instant::now();
+    let mut dashboard = Dashboard::new("serve", started);
+    dashboard.update(sample, started + Duration::from_secs(2));
+    let lines = render(&dashboard, 150, 24);
+    let screen = lines.join("\n");
+    assert!(screen.contains("Shards"), "{}", screen);
+    assert!(screen.contains("duplicate transaction id"), "{}", screen);
+    let header = lines.iter().find(|line| line.contains("queue")).unwrap();
+    assert!(
+        header.contains("shard") && header.contains("processed"),
+        "{}",
+        screen
+    );
+}
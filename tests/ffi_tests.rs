@@ -0,0 +1,98 @@
+#![cfg(feature = "ffi")]
+
+// Drives the C interface from Rust the way a C caller would, through raw
+// pointers and status codes.
+
+use std::ffi::CStr;
+use std::ptr;
+
+use payments_engine::ffi::*;
+
+#[test]
+fn test_submit_and_fetch_account() {
+    unsafe {
+        let engine = pe_engine_new();
+        assert_eq!(pe_engine_submit(engine, PE_DEPOSIT, 1, 1, 125_000), PE_OK);
+        assert_eq!(pe_engine_submit(engine, PE_DEPOSIT, 1, 2, 25_000), PE_OK);
+        assert_eq!(pe_engine_submit(engine, PE_DISPUTE, 1, 1, 0), PE_OK);
+
+        let mut account = PeAccount::default();
+        assert_eq!(pe_engine_get_account(engine, 1, &mut account), PE_OK);
+        assert_eq!(
+            account,
+            PeAccount {
+                client: 1,
+                available: 25_000,
+                held: 125_000,
+                total: 150_000,
+                locked: false,
+            }
+        );
+
+        assert_eq!(pe_engine_submit(engine, PE_CHARGEBACK, 1, 1, 0), PE_OK);
+        assert_eq!(pe_engine_get_account(engine, 1, &mut account), PE_OK);
+        assert!(account.locked);
+        assert_eq!(account.total, 25_000);
+
+        pe_engine_free(engine);
+    }
+}
+
+#[test]
+fn test_rejections_and_errors_have_messages() {
+    unsafe {
+        let engine = pe_engine_new();
+        assert_eq!(pe_engine_submit(engine, PE_DEPOSIT, 1, 1, 10_000), PE_OK);
+
+        let status = pe_engine_submit(engine, PE_WITHDRAWAL, 1, 2, 20_000);
+        assert_eq!(status, 8);
+        let message = CStr::from_ptr(pe_status_message(status));
+        assert_eq!(message.to_str().unwrap(), "insufficient available funds");
+
+        assert_eq!(pe_engine_submit(engine, 9, 1, 3, 0), PE_INVALID_ARGUMENT);
+        assert_eq!(
+            pe_engine_submit(ptr::null_mut(), PE_DEPOSIT, 1, 3, 1),
+            PE_INVALID_ARGUMENT
+        );
+
+        let mut account = PeAccount::default();
+        assert_eq!(pe_engine_get_account(engine, 2, &mut account), PE_NOT_FOUND);
+        assert_eq!(
+            pe_engine_get_account(engine, 1, ptr::null_mut()),
+            PE_INVALID_ARGUMENT
+        );
+        assert_eq!(account, PeAccount::default());
+
+        for status in [PE_NOT_FOUND, i32::MIN, i32::MAX, 20] {
+            assert!(!pe_status_message(status).is_null());
+        }
+        let unknown = CStr::from_ptr(pe_status_message(20));
+        assert_eq!(unknown.to_str().unwrap(), "unknown status");
+
+        pe_engine_free(engine);
+        pe_engine_free(ptr::null_mut());
+    }
+}
+
+#[test]
+fn test_accounts_csv_is_sorted() {
+    unsafe {
+        let engine = pe_engine_new();
+        pe_engine_submit(engine, PE_DEPOSIT, 2, 1, 30_000);
+        pe_engine_submit(engine, PE_DEPOSIT, 1, 2, 15_000);
+
+        let csv = pe_engine_accounts_csv(engine);
+        assert!(!csv.is_null());
+        let text = CStr::from_ptr(csv).to_str().unwrap().to_string();
+        pe_string_free(csv);
+
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows[0], "client,available,held,total,locked");
+        assert!(rows[1].starts_with("1,1.5"));
+        assert!(rows[2].starts_with("2,3"));
+        assert_eq!(rows.len(), 3);
+
+        assert!(pe_engine_accounts_csv(ptr::null()).is_null());
+        pe_engine_free(engine);
+    }
+}
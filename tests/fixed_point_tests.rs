@@ -0,0 +1,103 @@
+use payments_engine::amount::FixedAmount;
+use rust_decimal_macros::dec;
+
+fn amount(s: &str) -> FixedAmount {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_fixed_amount_parse_and_display() {
+    assert_eq!(amount("1.5").minor_units(), 15_000);
+    assert_eq!(amount("-0.0001").minor_units(), -1);
+    assert_eq!(amount("+12").minor_units(), 120_000);
+    assert_eq!(amount(".25").minor_units(), 2_500);
+    assert_eq!(amount("3.").minor_units(), 30_000);
+
+    // Trailing zeros beyond four places are harmless, other digits are not
+    assert_eq!(amount("2.500000").minor_units(), 25_000);
+    assert!("2.00001".parse::<FixedAmount>().is_err());
+
+    for invalid in [
+        "",
+        ".",
+        "-",
+        "1.2.3",
+        "1e5",
+        "abc",
+        " 1",
+        "99999999999999999",
+    ] {
+        assert!(invalid.parse::<FixedAmount>().is_err(), "{:?}", invalid);
+    }
+
+    assert_eq!(amount("1.5000").to_string(), "1.5");
+    assert_eq!(amount("-0.25").to_string(), "-0.25");
+    assert_eq!(amount("100").to_string(), "100");
+    assert_eq!(amount("0.0007").to_string(), "0.0007");
+    assert_eq!(FixedAmount::ZERO.to_string(), "0");
+}
+
+#[test]
+fn test_fixed_amount_arithmetic() {
+    let mut balance = amount("10.25");
+    balance += amount("0.0001");
+    balance -= amount("5");
+    assert_eq!(balance, amount("5.2501"));
+    assert_eq!(-balance + balance, FixedAmount::ZERO);
+    assert!(amount("0.0001") > FixedAmount::ZERO);
+
+    assert_eq!(amount("1.5").decimal_places(), 1);
+    assert_eq!(amount("-2.0625").decimal_places(), 4);
+    assert_eq!(amount("7").decimal_places(), 0);
+
+    let max = FixedAmount::from_minor_units(i64::MAX);
+    assert_eq!(max.checked_add(amount("0.0001")), None);
+}
+
+#[test]
+#[should_panic(expected = "amount overflow")]
+fn test_fixed_amount_overflow_panics() {
+    let _ = FixedAmount::from_minor_units(i64::MAX) + amount("1");
+}
+
+#[test]
+fn test_fixed_amount_decimal_conversion() {
+    assert_eq!(
+        rust_decimal::Decimal::from(amount("12.3456")),
+        dec!(12.3456)
+    );
+    assert_eq!(
+        FixedAmount::try_from(dec!(12.3400)).unwrap(),
+        amount("12.34")
+    );
+    assert!(FixedAmount::try_from(dec!(0.00001)).is_err());
+
+    let json = serde_json::to_string(&amount("42.5")).unwrap();
+    assert_eq!(json, "\"42.5\"");
+    assert_eq!(
+        serde_json::from_str::<FixedAmount>(&json).unwrap(),
+        amount("42.5")
+    );
+}
+
+#[cfg(feature = "fixed-point")]
+#[test]
+fn test_engine_runs_on_fixed_point_amounts() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.5\n\
+                 deposit,1,2,0.00001\n\
+                 withdrawal,1,3,0.2500\n\
+                 deposit,2,4,3\n\
+                 dispute,2,4,\n";
+
+    let mut output = Vec::new();
+    payments_engine::process_transactions(input.as_bytes(), &mut output).unwrap();
+
+    // The amount with five decimal places isn't representable and is skipped
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked\n\
+         1,10.25,0,10.25,false\n\
+         2,0,3,3,false\n"
+    );
+}
@@ -0,0 +1,64 @@
+#![cfg(all(feature = "testing", not(feature = "fixed-point")))]
+
+use payments_engine::testing::golden::{assert_golden, assert_matches_golden, canonicalize, diff};
+
+#[test]
+fn test_fixtures_match_golden_files() {
+    for fixture in [
+        "basic",
+        "chargebacks",
+        "comprehensive_test",
+        "disputes",
+        "edge_cases",
+    ] {
+        assert_golden(
+            format!("tests/fixtures/{}.csv", fixture),
+            format!("tests/golden/{}.csv", fixture),
+        );
+    }
+}
+
+#[test]
+fn test_canonicalize_sorts_rows_and_fixes_scale() {
+    let output = "client,available,held,total,locked\n\
+                  2,1.5,0,1.5,false\n\
+                  1,-0.25,10.0001,9.7501,true\n";
+    assert_eq!(
+        canonicalize(output.as_bytes()).unwrap(),
+        "client,available,held,total,locked\n\
+         1,-0.2500,10.0001,9.7501,true\n\
+         2,1.5000,0.0000,1.5000,false\n"
+    );
+
+    // Hand-written golden files may leave out the total
+    let golden = "client,available,held,locked\n2,1.5,0,false\n";
+    assert_matches_golden(
+        "client,available,held,total,locked\n2,1.50,0.0,1.50,false\n",
+        tempfile_with(golden).path(),
+    );
+}
+
+#[test]
+#[should_panic(expected = "-2,1.5000,0.0000,1.5000,false\n+2,1.0000,0.0000,1.0000,false")]
+fn test_mismatch_panics_with_diff() {
+    let golden = tempfile_with("client,available,held,total,locked\n2,1.5,0,1.5,false\n");
+    assert_matches_golden(
+        "client,available,held,total,locked\n2,1,0,1,false\n",
+        golden.path(),
+    );
+}
+
+#[test]
+fn test_diff_marks_changed_lines() {
+    assert_eq!(diff("a\nb\nc\n", "a\nb\nc\n"), None);
+    assert_eq!(
+        diff("a\nb\nc\n", "a\nx\nc\nd\n").unwrap(),
+        " a\n-b\n+x\n c\n+d\n"
+    );
+}
+
+fn tempfile_with(contents: &str) -> tempfile::NamedTempFile {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), contents).unwrap();
+    file
+}
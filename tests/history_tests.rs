@@ -0,0 +1,199 @@
+#![cfg(not(feature = "fixed-point"))]
+
+mod common;
+
+use common::{make_deposit, make_dispute, make_transaction};
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::models::{Transaction, TransactionType};
+use rust_decimal_macros::dec;
+
+#[test]
+fn test_balance_at_reconstructs_past_balances() {
+    let mut engine = PaymentsEngine::new().retain_history();
+
+    engine.process_transaction(make_deposit(1, 1, dec!(100))); // t=1
+    engine.process_transaction(make_deposit(2, 2, dec!(7))); // t=2
+    engine.process_transaction(make_dispute(1, 1)); // t=3
+    engine.process_transaction(make_transaction(TransactionType::Resolve, 1, 1, None)); // t=4
+
+    let at_deposit = engine.balance_at(1, 1).unwrap();
+    assert_eq!(at_deposit.available, dec!(100));
+    assert_eq!(at_deposit.held, dec!(0));
+
+    // Client 2's deposit doesn't change client 1's balance
+    assert_eq!(engine.balance_at(1, 2).unwrap(), at_deposit);
+
+    let during_dispute = engine.balance_at(1, 3).unwrap();
+    assert_eq!(during_dispute.available, dec!(0));
+    assert_eq!(during_dispute.held, dec!(100));
+    assert_eq!(during_dispute.total(), dec!(100));
+
+    let resolved = engine.balance_at(1, u64::MAX).unwrap();
+    assert_eq!(resolved.available, dec!(100));
+}
+
+#[test]
+fn test_history_keeps_metadata() {
+    let mut engine = PaymentsEngine::new().retain_history();
+    let deposit = Transaction::builder()
+        .deposit()
+        .client(1)
+        .tx(1)
+        .amount(dec!(10))
+        .metadata("reference", "INV-0042")
+        .build()
+        .unwrap();
+    engine.process_transaction(deposit);
+    engine.process_transaction(make_dispute(1, 1));
+
+    let history = engine.client_history(1);
+    assert_eq!(history[0].metadata["reference"], "INV-0042");
+    assert!(history[1].metadata.is_empty());
+}
+
+#[test]
+fn test_history_keeps_transaction_timestamps() {
+    let mut engine = PaymentsEngine::new().retain_history();
+    let csv = "type,client,tx,amount,timestamp\n\
+               deposit,1,1,10,1709251200\n\
+               dispute,1,1,,\n";
+    for transaction in payments_engine::read_transactions(csv.as_bytes()) {
+        engine.process_transaction(transaction);
+    }
+
+    let history = engine.client_history(1);
+    assert_eq!(history[0].tx_timestamp, Some(1_709_251_200));
+    assert_eq!(history[1].tx_timestamp, None);
+    // The engine's own clock still orders entries
+    assert_eq!(history[1].timestamp, 2);
+    assert_eq!(
+        engine.open_disputes().next().unwrap().timestamp,
+        Some(1_709_251_200)
+    );
+}
+
+#[test]
+fn test_balance_at_before_first_transaction() {
+    let mut engine = PaymentsEngine::new().retain_history();
+
+    engine.process_transaction(make_deposit(2, 1, dec!(5)));
+    engine.process_transaction(make_deposit(1, 2, dec!(5)));
+
+    assert!(engine.balance_at(1, 1).is_none());
+    assert!(engine.balance_at(1, 2).is_some());
+}
+
+#[test]
+fn test_rejected_transactions_not_recorded() {
+    let mut engine = PaymentsEngine::new().retain_history();
+
+    engine.process_transaction(make_deposit(1, 1, dec!(10)));
+    // Insufficient funds
+    engine.process_transaction(make_transaction(
+        TransactionType::Withdrawal,
+        1,
+        2,
+        Some(dec!(50)),
+    ));
+    engine.process_transaction(make_dispute(1, 1));
+
+    let history = engine.client_history(1);
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1].tx_type, TransactionType::Dispute);
+    assert_eq!(history[1].amount, dec!(10));
+    assert_eq!(history[1].timestamp, 2);
+}
+
+#[test]
+fn test_history_disabled_by_default() {
+    let mut engine = PaymentsEngine::new();
+    engine.process_transaction(make_deposit(1, 1, dec!(10)));
+
+    assert!(engine.balance_at(1, 1).is_none());
+    assert!(engine.client_history(1).is_empty());
+}
+
+#[test]
+fn test_settlement_summaries_per_client_and_period() {
+    let mut engine = PaymentsEngine::new().retain_history();
+
+    engine.process_transaction(make_deposit(1, 1, dec!(100))); // t=1
+    engine.process_transaction(make_deposit(1, 2, dec!(50))); // t=2
+    engine.process_transaction(make_transaction(
+        TransactionType::Withdrawal,
+        1,
+        3,
+        Some(dec!(30)),
+    )); // t=3
+    engine.process_transaction(make_dispute(1, 2)); // t=4
+    engine.process_transaction(make_deposit(2, 4, dec!(5))); // t=5
+    engine.process_transaction(make_transaction(TransactionType::Chargeback, 1, 2, None)); // t=6
+
+    // Periods of 4 ticks: [0, 4) and [4, 8)
+    let summaries = engine.settlement_summaries(4);
+    assert_eq!(summaries.len(), 3);
+
+    let first = &summaries[0];
+    assert_eq!((first.client_id, first.period_start), (1, 0));
+    assert_eq!(first.deposits, dec!(150));
+    assert_eq!(first.withdrawals, dec!(30));
+    assert_eq!(first.disputes_opened, dec!(0));
+
+    let second = &summaries[1];
+    assert_eq!((second.client_id, second.period_start), (1, 4));
+    assert_eq!(second.disputes_opened, dec!(50));
+    assert_eq!(second.chargebacks, dec!(50));
+    assert_eq!(second.deposits, dec!(0));
+
+    let third = &summaries[2];
+    assert_eq!((third.client_id, third.period_start), (2, 4));
+    assert_eq!(third.deposits, dec!(5));
+}
+
+#[test]
+fn test_settlement_summaries_serialize_to_csv() {
+    let mut engine = PaymentsEngine::new().retain_history();
+    engine.process_transaction(make_deposit(3, 1, dec!(1.5)));
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for summary in engine.settlement_summaries(86400) {
+        writer.serialize(summary).unwrap();
+    }
+    let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+    assert!(output.starts_with(
+        "client,period_start,deposits,withdrawals,disputes_opened,disputes_resolved,chargebacks"
+    ));
+    assert!(output.contains("3,0,1.5,0,0,0,0"));
+}
+
+#[test]
+fn test_erase_client_anonymizes_history() {
+    let mut engine = PaymentsEngine::new().retain_history();
+    engine.process_transaction(make_deposit(1, 1, dec!(10))); // t=1
+    engine.process_transaction(make_deposit(2, 2, dec!(3))); // t=2
+    engine.process_transaction(make_transaction(
+        TransactionType::Withdrawal,
+        1,
+        3,
+        Some(dec!(4)),
+    )); // t=3
+
+    engine.erase_client(1);
+
+    assert!(engine.client_history(1).is_empty());
+    assert!(engine.balance_at(1, 3).is_none());
+    assert_eq!(engine.client_history(2).len(), 1);
+    let anonymized: Vec<_> = engine
+        .anonymized_history()
+        .iter()
+        .map(|entry| (entry.timestamp, entry.tx_type, entry.amount))
+        .collect();
+    assert_eq!(
+        anonymized,
+        [
+            (1, TransactionType::Deposit, dec!(10)),
+            (3, TransactionType::Withdrawal, dec!(4)),
+        ]
+    );
+}
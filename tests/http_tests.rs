@@ -0,0 +1,649 @@
+#![cfg(feature = "server")]
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use futures::{SinkExt, StreamExt};
+use http_body_util::BodyExt;
+use payments_engine::concurrent_engine::ShardedEngine;
+use payments_engine::server::auth::{ApiKeys, ClientScope};
+use payments_engine::server::http;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use tower::ServiceExt;
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, body)
+}
+
+async fn post_transaction(app: &Router, tx: Value) -> (StatusCode, Value) {
+    let request = Request::post("/transactions")
+        .header("content-type", "application/json")
+        .body(Body::from(tx.to_string()))
+        .unwrap();
+    send(app, request).await
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, Value) {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+#[tokio::test]
+async fn test_submit_transaction_acks() {
+    let app = http::router(ShardedEngine::new(2), None);
+
+    let (status, body) = post_transaction(
+        &app,
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "10.0"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"status": "applied"}));
+
+    let (status, body) = post_transaction(
+        &app,
+        json!({"type": "withdrawal", "client": 1, "tx": 2, "amount": "99"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body,
+        json!({"status": "rejected", "reason": "insufficient available funds"})
+    );
+}
+
+#[tokio::test]
+async fn test_read_only_engine_refuses_transactions() {
+    let engine = ShardedEngine::new(2);
+    engine.set_read_only(true).await;
+    let app = http::router(engine.clone_handle(), None);
+
+    let (status, body) = post_transaction(
+        &app,
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "10.0"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body, json!({"error": "Engine is read-only"}));
+
+    let (status, _) = get(&app, "/accounts").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_rejection_echoes_metadata() {
+    let app = http::router(ShardedEngine::new(2), None);
+
+    let (status, body) = post_transaction(
+        &app,
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "10", "merchant": "m-1"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"status": "applied"}));
+
+    let (status, body) = post_transaction(
+        &app,
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "10", "merchant": "m-1", "attempt": 2}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body,
+        json!({
+            "status": "rejected",
+            "reason": "duplicate transaction id",
+            "metadata": {"attempt": "2", "merchant": "m-1"}
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_malformed_transaction_is_client_error() {
+    let app = http::router(ShardedEngine::new(2), None);
+
+    let (status, _) = post_transaction(&app, json!({"type": "deposit", "client": 1})).await;
+    assert!(status.is_client_error());
+}
+
+#[tokio::test]
+async fn test_account_queries() {
+    let app = http::router(ShardedEngine::new(4), None);
+    for (client, tx) in [(2, 1), (1, 2)] {
+        post_transaction(
+            &app,
+            json!({"type": "deposit", "client": client, "tx": tx, "amount": "1.5"}),
+        )
+        .await;
+    }
+
+    let (status, body) = get(&app, "/accounts/1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({"client": 1, "available": "1.5", "held": "0", "total": "1.5", "locked": false, "version": 1})
+    );
+
+    let (status, body) = get(&app, "/accounts/9").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, json!({"error": "client not found"}));
+
+    let (status, body) = get(&app, "/accounts").await;
+    assert_eq!(status, StatusCode::OK);
+    let clients: Vec<_> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|account| account["client"].clone())
+        .collect();
+    assert_eq!(clients, vec![json!(1), json!(2)]);
+}
+
+#[tokio::test]
+async fn test_if_match_applies_only_at_the_expected_version() {
+    let app = http::router(ShardedEngine::new(2), None);
+    post_transaction(
+        &app,
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "10"}),
+    )
+    .await;
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/accounts/1").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    assert_eq!(etag, "\"1\"");
+
+    let conditional = |tx: Value, version: &str| {
+        Request::post("/transactions")
+            .header("content-type", "application/json")
+            .header("if-match", version)
+            .body(Body::from(tx.to_string()))
+            .unwrap()
+    };
+    let withdrawal = |tx| json!({"type": "withdrawal", "client": 1, "tx": tx, "amount": "1"});
+
+    let (status, body) = send(&app, conditional(withdrawal(2), &etag)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"status": "applied"}));
+
+    // The account moved on to version 2
+    let (status, body) = send(&app, conditional(withdrawal(3), &etag)).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(
+        body,
+        json!({"status": "rejected", "reason": "account version changed"})
+    );
+
+    let (status, _) = send(&app, conditional(withdrawal(4), "2")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, conditional(withdrawal(5), "latest")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, body) = get(&app, "/accounts/1").await;
+    assert_eq!(body["version"], json!(3));
+}
+
+#[tokio::test]
+async fn test_simulate_transaction_applies_nothing() {
+    let app = http::router(ShardedEngine::new(2), None);
+    post_transaction(
+        &app,
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "10"}),
+    )
+    .await;
+
+    let simulate = |tx: Value| {
+        Request::post("/transactions/simulate")
+            .header("content-type", "application/json")
+            .body(Body::from(tx.to_string()))
+            .unwrap()
+    };
+    let (status, body) = send(
+        &app,
+        simulate(json!({"type": "withdrawal", "client": 1, "tx": 2, "amount": "99"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], json!("rejected"));
+    assert_eq!(body["reason"], json!("insufficient available funds"));
+    assert_eq!(body["account"]["version"], json!(1));
+
+    let (status, body) = send(
+        &app,
+        simulate(json!({"type": "withdrawal", "client": 1, "tx": 2, "amount": "4"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], json!("applied"));
+    assert!(body.get("reason").is_none());
+    assert_eq!(body["account"]["version"], json!(2));
+
+    // No account to show for a client without one
+    let (_, body) = send(
+        &app,
+        simulate(json!({"type": "dispute", "client": 2, "tx": 7})),
+    )
+    .await;
+    assert_eq!(
+        body,
+        json!({"status": "rejected", "reason": "referenced transaction not found"})
+    );
+
+    let (_, account) = get(&app, "/accounts/1").await;
+    assert_eq!(account["version"], json!(1));
+}
+
+// Expects amounts formatted the way `Decimal` writes them
+#[cfg(not(feature = "fixed-point"))]
+#[tokio::test]
+async fn test_open_disputes_listing() {
+    let app = http::router(ShardedEngine::new(2), None);
+    for tx in [
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "5.0"}),
+        json!({"type": "deposit", "client": 2, "tx": 2, "amount": "7.0"}),
+        json!({"type": "dispute", "client": 2, "tx": 2}),
+        json!({"type": "dispute", "client": 1, "tx": 1}),
+        json!({"type": "resolve", "client": 1, "tx": 1}),
+    ] {
+        post_transaction(&app, tx).await;
+    }
+
+    let (status, body) = get(&app, "/disputes").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([{"client": 2, "tx": 2, "amount": "7.0"}]));
+}
+
+#[cfg(not(feature = "fixed-point"))]
+#[tokio::test]
+async fn test_client_holds() {
+    let app = http::router(ShardedEngine::new(2), Some(partner_keys()));
+    for tx in [
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "5.0"}),
+        json!({"type": "deposit", "client": 1, "tx": 2, "amount": "2.5"}),
+        json!({"type": "deposit", "client": 3, "tx": 3, "amount": "1.0"}),
+        json!({"type": "dispute", "client": 1, "tx": 2, "timestamp": 1700000000}),
+        json!({"type": "dispute", "client": 1, "tx": 1}),
+        json!({"type": "dispute", "client": 3, "tx": 3}),
+    ] {
+        let request = with_key(Request::post("/transactions"), "operator")
+            .header("content-type", "application/json")
+            .body(Body::from(tx.to_string()))
+            .unwrap();
+        send(&app, request).await;
+    }
+
+    let holds = |client: u16| {
+        with_key(
+            Request::get(format!("/accounts/{}/holds", client)),
+            "partner-a",
+        )
+        .body(Body::empty())
+        .unwrap()
+    };
+    let (status, body) = send(&app, holds(1)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!([
+            {"tx": 1, "amount": "5.0"},
+            {"tx": 2, "amount": "2.5", "placed_at": 1700000000}
+        ])
+    );
+    let (status, _) = send(&app, holds(3)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[cfg(not(feature = "fixed-point"))]
+#[tokio::test]
+async fn test_funds_totals() {
+    let app = http::router(ShardedEngine::new(2), Some(partner_keys()));
+    for tx in [
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "5.0"}),
+        json!({"type": "deposit", "client": 2, "tx": 2, "amount": "7.0"}),
+        json!({"type": "deposit", "client": 3, "tx": 3, "amount": "1.5"}),
+        json!({"type": "dispute", "client": 2, "tx": 2}),
+        json!({"type": "dispute", "client": 3, "tx": 3}),
+        json!({"type": "chargeback", "client": 3, "tx": 3}),
+    ] {
+        let request = with_key(Request::post("/transactions"), "operator")
+            .header("content-type", "application/json")
+            .body(Body::from(tx.to_string()))
+            .unwrap();
+        send(&app, request).await;
+    }
+
+    let funds = |key: &str| {
+        with_key(Request::get("/funds"), key)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let (status, body) = send(&app, funds("operator")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({
+            "available": "5.0",
+            "held": "7.0",
+            "total": "12.0",
+            "charged_back": "1.5",
+            "open_disputes": 1
+        })
+    );
+
+    // Totals cover every client, so scoped keys can't see them
+    let (status, _) = send(&app, funds("partner-a")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_admin_snapshot() {
+    use payments_engine::server::snapshots::{SnapshotOptions, Snapshotter};
+
+    let (status, _) = send(
+        &http::router(ShardedEngine::new(1), None),
+        Request::post("/admin/snapshot")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let dir = tempfile::tempdir().unwrap();
+    let engine = ShardedEngine::new(2);
+    let snapshots = Snapshotter::new(&engine, SnapshotOptions::new(dir.path())).unwrap();
+    let app = http::router_with_snapshots(engine, Some(partner_keys()), Some(snapshots));
+    let request = with_key(Request::post("/transactions"), "operator")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({"type": "deposit", "client": 1, "tx": 1, "amount": "5.0"}).to_string(),
+        ))
+        .unwrap();
+    send(&app, request).await;
+
+    let snapshot = |key: &str| {
+        with_key(Request::post("/admin/snapshot"), key)
+            .body(Body::empty())
+            .unwrap()
+    };
+    // The state covers every client
+    let (status, _) = send(&app, snapshot("partner-a")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = send(&app, snapshot("operator")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["accounts"], json!(1));
+    let path = std::path::Path::new(body["path"].as_str().unwrap());
+    assert_eq!(path.parent(), Some(dir.path()));
+    assert!(path.exists());
+}
+
+#[cfg(not(feature = "fixed-point"))]
+#[tokio::test]
+async fn test_archived_deposits() {
+    use payments_engine::config::{EngineConfig, RetentionPolicy};
+
+    let dir = tempfile::tempdir().unwrap();
+    let engine = ShardedEngine::new(1);
+    engine.archive_to(dir.path().join("archive")).await.unwrap();
+    engine
+        .set_config(EngineConfig {
+            retention: RetentionPolicy {
+                max_entries: Some(1),
+                max_age: None,
+            },
+            ..EngineConfig::default()
+        })
+        .await;
+    let app = http::router(engine, Some(partner_keys()));
+    for tx in [
+        json!({"type": "deposit", "client": 3, "tx": 1, "amount": "5.0"}),
+        json!({"type": "deposit", "client": 3, "tx": 2, "amount": "7.0"}),
+    ] {
+        let request = with_key(Request::post("/transactions"), "operator")
+            .header("content-type", "application/json")
+            .body(Body::from(tx.to_string()))
+            .unwrap();
+        send(&app, request).await;
+    }
+
+    let archived = |key: &str, tx: u32| {
+        with_key(Request::get(format!("/archive/{}", tx)), key)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let (status, body) = send(&app, archived("operator", 1)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({"client": 3, "tx": 1, "amount": "5.0"}));
+
+    let (status, _) = send(&app, archived("operator", 2)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, archived("partner-a", 1)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_websocket_stream_acks_each_transaction() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(http::serve(listener, ShardedEngine::new(2), None, None));
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+
+    for message in [
+        r#"{"type":"deposit","client":1,"tx":1,"amount":"4.0"}"#,
+        "withdrawal,1,2,5.0",
+        "not a transaction",
+    ] {
+        socket.send(Message::text(message)).await.unwrap();
+    }
+
+    let mut replies = Vec::new();
+    while replies.len() < 3 {
+        if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+            replies.push(serde_json::from_str::<Value>(&text).unwrap());
+        }
+    }
+
+    assert_eq!(replies[0], json!({"tx": 1, "status": "applied"}));
+    assert_eq!(
+        replies[1],
+        json!({"tx": 2, "status": "rejected", "reason": "insufficient available funds"})
+    );
+    assert!(replies[2]["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("invalid transaction"));
+}
+
+// Expects amounts formatted the way `Decimal` writes them
+#[cfg(not(feature = "fixed-point"))]
+#[tokio::test]
+async fn test_sse_streams_filtered_account_changes() {
+    let app = http::router(ShardedEngine::new(2), None);
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/events?client=2")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+    for tx in [
+        json!({"type": "deposit", "client": 1, "tx": 1, "amount": "1.0"}),
+        json!({"type": "deposit", "client": 2, "tx": 2, "amount": "2.0"}),
+        json!({"type": "withdrawal", "client": 2, "tx": 3, "amount": "9.0"}),
+        json!({"type": "dispute", "client": 2, "tx": 2}),
+    ] {
+        post_transaction(&app, tx).await;
+    }
+
+    let mut body = response.into_body();
+    let mut events = Vec::new();
+    while events.len() < 2 {
+        let frame = body.frame().await.unwrap().unwrap();
+        let text = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+        if let Some(data) = text.lines().find_map(|line| line.strip_prefix("data: ")) {
+            assert!(text.starts_with("event: account"));
+            events.push(serde_json::from_str::<Value>(data).unwrap());
+        }
+    }
+
+    // Client 1's deposit and the rejected withdrawal produce no events here
+    assert_eq!(
+        events[0],
+        json!({"tx": 2, "type": "deposit", "client": 2, "available": "2.0", "held": "0", "total": "2.0", "locked": false, "version": 1})
+    );
+    assert_eq!(
+        events[1],
+        json!({"tx": 2, "type": "dispute", "client": 2, "available": "0.0", "held": "2.0", "total": "2.0", "locked": false, "version": 2})
+    );
+}
+
+#[tokio::test]
+async fn test_openapi_document_describes_routes() {
+    let app = http::router(ShardedEngine::new(1), None);
+
+    let (status, spec) = get(&app, "/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    for path in [
+        "/transactions",
+        "/transactions/simulate",
+        "/accounts",
+        "/accounts/{client}",
+        "/disputes",
+        "/accounts/{client}/holds",
+        "/funds",
+        "/archive/{tx}",
+        "/events",
+    ] {
+        assert!(spec["paths"].get(path).is_some(), "missing {}", path);
+    }
+    let account = &spec["components"]["schemas"]["Account"]["properties"];
+    assert!(account.get("total").is_some());
+    // Transaction IDs are plain integers, whatever their width
+    let tx = &spec["components"]["schemas"]["Transaction"]["properties"]["tx"];
+    assert_eq!(tx["type"], "integer");
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/docs/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+fn partner_keys() -> ApiKeys {
+    ApiKeys::parse(
+        "# key      clients\n\
+         partner-a  1, 2\n\
+         \n\
+         operator   *\n",
+    )
+    .unwrap()
+}
+
+fn with_key(builder: axum::http::request::Builder, key: &str) -> axum::http::request::Builder {
+    builder.header(http::API_KEY_HEADER, key)
+}
+
+#[test]
+fn test_api_keys_parse() {
+    let keys = partner_keys();
+    assert_eq!(
+        *keys.scope("partner-a").unwrap(),
+        ClientScope::Clients([1, 2].into())
+    );
+    assert_eq!(*keys.scope("operator").unwrap(), ClientScope::All);
+    assert!(keys.scope("unknown").is_none());
+
+    assert!(ApiKeys::parse("lonely-key").is_err());
+    assert!(ApiKeys::parse("key 1,x").is_err());
+}
+
+#[tokio::test]
+async fn test_api_key_required_and_scoped() {
+    let app = http::router(ShardedEngine::new(2), Some(partner_keys()));
+
+    // Without a key nothing but the docs is reachable
+    let (status, _) = get(&app, "/accounts").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = get(&app, "/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let submit = |key: &str, tx: Value| {
+        with_key(Request::post("/transactions"), key)
+            .header("content-type", "application/json")
+            .body(Body::from(tx.to_string()))
+            .unwrap()
+    };
+
+    let (status, _) = send(
+        &app,
+        submit(
+            "operator",
+            json!({"type": "deposit", "client": 3, "tx": 1, "amount": "1.0"}),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(
+        &app,
+        submit(
+            "partner-a",
+            json!({"type": "deposit", "client": 2, "tx": 2, "amount": "2.0"}),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Partner A can't touch client 3
+    let (status, body) = send(
+        &app,
+        submit(
+            "partner-a",
+            json!({"type": "dispute", "client": 3, "tx": 1}),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        body,
+        json!({"error": "API key not authorized for client 3"})
+    );
+
+    let query = |key: &str, uri: &str| {
+        with_key(Request::get(uri), key)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let (status, _) = send(&app, query("partner-a", "/accounts/3")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (_, body) = send(&app, query("partner-a", "/accounts")).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["client"], json!(2));
+
+    let (_, body) = send(&app, query("operator", "/accounts")).await;
+    assert_eq!(body.as_array().unwrap().len(), 2);
+
+    let (status, _) = send(&app, query("stolen", "/disputes")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
@@ -1,9 +1,29 @@
+#![cfg(not(feature = "fixed-point"))]
+
 mod common;
 
 use std::fs::File;
 
 use common::{assert_client_balance, build_csv, process_csv_string};
-use payments_engine::process_transactions;
+#[cfg(feature = "async")]
+use payments_engine::concurrent_engine::ShardedEngine;
+use payments_engine::engine::PaymentsEngine;
+#[cfg(feature = "async")]
+use payments_engine::engine::{Engine, EngineStats};
+use payments_engine::input::Input;
+use payments_engine::models::{Account, ClientId, TransactionType, TxId};
+use payments_engine::parallel::process_transactions_parallel;
+#[cfg(feature = "async")]
+use payments_engine::persistence::StubPersistence;
+#[cfg(feature = "async")]
+use payments_engine::persistent_engine::PersistentEngine;
+use payments_engine::workload::{generate, generate_csv, AmountDistribution, WorkloadOptions};
+use payments_engine::{
+    apply_transactions_as_of, process_transactions, read_transactions, write_transactions,
+};
+#[cfg(feature = "async")]
+use payments_engine::{apply_transactions_async, process_transactions_async};
+use rust_decimal::Decimal;
 
 #[test]
 fn test_comprehensive_scenario() {
@@ -34,6 +54,83 @@ fn test_comprehensive_scenario() {
     assert!(output_str.contains("0.0,true"));
 }
 
+#[test]
+fn test_extra_columns_become_metadata() {
+    let csv = "type,client,merchant,tx,amount,reference\n\
+               deposit,1,007,1,10.0,INV-1\n\
+               withdrawal,1,,2,5.0,\n";
+    let transactions: Vec<_> = read_transactions(csv.as_bytes()).collect();
+
+    assert_eq!(transactions.len(), 2);
+    assert_eq!(transactions[0].amount, Some(Decimal::new(100, 1)));
+    assert_eq!(transactions[0].metadata["merchant"], "007");
+    assert_eq!(transactions[0].metadata["reference"], "INV-1");
+    assert!(transactions[1].metadata.is_empty());
+
+    // CSV output has a fixed set of columns, so metadata is left out
+    let mut written = Vec::new();
+    write_transactions(transactions.clone(), &mut written).unwrap();
+    let read_back: Vec<_> = read_transactions(&written[..]).collect();
+    assert_eq!(read_back[0].tx, 1);
+    assert!(read_back[0].metadata.is_empty());
+}
+
+#[test]
+fn test_timestamp_column_is_optional() {
+    let with = "type,client,tx,amount,timestamp\n\
+                deposit,1,1,10.0,1700000000\n\
+                dispute,1,1,,\n";
+    let transactions: Vec<_> = read_transactions(with.as_bytes()).collect();
+    assert_eq!(transactions[0].timestamp, Some(1_700_000_000));
+    assert_eq!(transactions[1].timestamp, None);
+
+    let without = "type,client,tx,amount\ndeposit,1,1,10.0\ndispute,1,1,\n";
+    let untimed: Vec<_> = read_transactions(without.as_bytes()).collect();
+    assert!(untimed.iter().all(|tx| tx.timestamp.is_none()));
+
+    let mut written = Vec::new();
+    write_transactions(transactions.clone(), &mut written).unwrap();
+    assert_eq!(
+        String::from_utf8(written.clone()).unwrap(),
+        "type,client,tx,amount,timestamp\ndeposit,1,1,10.0,1700000000\ndispute,1,1,,\n"
+    );
+    let read_back: Vec<_> = read_transactions(&written[..]).collect();
+    assert_eq!(read_back, transactions);
+
+    // JSON leaves out a missing timestamp rather than writing null
+    let json = serde_json::to_string(&transactions[1]).unwrap();
+    assert_eq!(
+        json,
+        r#"{"type":"dispute","client":1,"tx":1,"amount":null}"#
+    );
+}
+
+#[test]
+fn test_as_of_skips_later_transactions() {
+    // Two days of activity, a little out of order, with an untimed row
+    let csv = "type,client,tx,amount,timestamp\n\
+               deposit,1,1,100.0,1709251200\n\
+               deposit,2,2,40.0,1709337600\n\
+               deposit,2,4,5.0,\n\
+               dispute,1,1,,1709337601\n\
+               withdrawal,1,3,30.0,1709290000\n";
+    let balance = |engine: &PaymentsEngine, client| {
+        let account = engine.get_account(client).unwrap();
+        (account.available, account.held)
+    };
+
+    // End of the first day
+    let mut engine = PaymentsEngine::new();
+    apply_transactions_as_of(&mut engine, csv.as_bytes(), 1_709_337_599);
+    assert_eq!(balance(&engine, 1), (Decimal::new(70, 0), Decimal::ZERO));
+    assert_eq!(balance(&engine, 2), (Decimal::new(5, 0), Decimal::ZERO));
+
+    let mut engine = PaymentsEngine::new();
+    apply_transactions_as_of(&mut engine, csv.as_bytes(), 1_709_337_601);
+    assert_eq!(balance(&engine, 1), (Decimal::ZERO, Decimal::new(100, 0)));
+    assert_eq!(balance(&engine, 2), (Decimal::new(45, 0), Decimal::ZERO));
+}
+
 #[test]
 fn test_basic_transactions() {
     let input = File::open("tests/fixtures/basic.csv").unwrap();
@@ -497,7 +594,8 @@ fn test_invalid_amounts_table_driven() {
 
     for case in test_cases {
         let csv = format!("type,client,tx,amount\n{}", case.transactions);
-        let output = process_csv_string(&csv).expect(&format!("Failed test: {}", case.name));
+        let output = process_csv_string(&csv)
+            .unwrap_or_else(|e| panic!("Failed test: {}: {}", case.name, e));
 
         if case.should_have_account {
             let balance = case.expected_balance.unwrap();
@@ -550,7 +648,8 @@ fn test_insufficient_funds_table_driven() {
             ("withdrawal", 1, 2, case.withdrawal),
         ]);
 
-        let output = process_csv_string(&csv).expect(&format!("Failed test: {}", case.name));
+        let output = process_csv_string(&csv)
+            .unwrap_or_else(|e| panic!("Failed test: {}: {}", case.name, e));
 
         assert_client_balance(
             &output,
@@ -568,7 +667,7 @@ fn test_insufficient_funds_table_driven() {
 fn test_dispute_workflows_table_driven() {
     struct TestCase {
         name: &'static str,
-        transactions: Vec<(&'static str, u16, u32, &'static str)>,
+        transactions: Vec<(&'static str, ClientId, TxId, &'static str)>,
         expected_available: &'static str,
         expected_held: &'static str,
         expected_locked: bool,
@@ -620,7 +719,8 @@ fn test_dispute_workflows_table_driven() {
 
     for case in test_cases {
         let csv = build_csv(&case.transactions);
-        let output = process_csv_string(&csv).expect(&format!("Failed test: {}", case.name));
+        let output = process_csv_string(&csv)
+            .unwrap_or_else(|e| panic!("Failed test: {}: {}", case.name, e));
 
         let expected_total = format!(
             "{}",
@@ -682,7 +782,8 @@ fn test_precision_scenarios_table_driven() {
             ("deposit", 1, 2, case.amount2),
         ]);
 
-        let output = process_csv_string(&csv).expect(&format!("Failed test: {}", case.name));
+        let output = process_csv_string(&csv)
+            .unwrap_or_else(|e| panic!("Failed test: {}: {}", case.name, e));
 
         assert!(
             output.contains(&format!("1,{}", case.expected_total)),
@@ -729,7 +830,8 @@ fn test_locked_account_operations_table_driven() {
         transactions.push((op_type, 1, 2, amount));
 
         let csv = build_csv(&transactions);
-        let output = process_csv_string(&csv).expect(&format!("Failed test: {}", case.name));
+        let output = process_csv_string(&csv)
+            .unwrap_or_else(|e| panic!("Failed test: {}: {}", case.name, e));
 
         assert_client_balance(
             &output,
@@ -746,14 +848,14 @@ fn test_locked_account_operations_table_driven() {
 #[test]
 fn test_multi_client_isolation_table_driven() {
     struct ClientExpectation {
-        client_id: u16,
+        client_id: ClientId,
         available: &'static str,
         held: &'static str,
     }
 
     struct TestCase {
         name: &'static str,
-        transactions: Vec<(&'static str, u16, u32, &'static str)>,
+        transactions: Vec<(&'static str, ClientId, TxId, &'static str)>,
         expectations: Vec<ClientExpectation>,
     }
 
@@ -829,7 +931,8 @@ fn test_multi_client_isolation_table_driven() {
 
     for case in test_cases {
         let csv = build_csv(&case.transactions);
-        let output = process_csv_string(&csv).expect(&format!("Failed test: {}", case.name));
+        let output = process_csv_string(&csv)
+            .unwrap_or_else(|e| panic!("Failed test: {}: {}", case.name, e));
 
         for expectation in case.expectations {
             let total = format!(
@@ -855,7 +958,7 @@ fn test_multi_client_isolation_table_driven() {
 fn test_duplicate_detection_table_driven() {
     struct TestCase {
         name: &'static str,
-        transactions: Vec<(&'static str, u16, u32, &'static str)>,
+        transactions: Vec<(&'static str, ClientId, TxId, &'static str)>,
         expected_balance: &'static str,
     }
 
@@ -888,7 +991,8 @@ fn test_duplicate_detection_table_driven() {
 
     for case in test_cases {
         let csv = build_csv(&case.transactions);
-        let output = process_csv_string(&csv).expect(&format!("Failed test: {}", case.name));
+        let output = process_csv_string(&csv)
+            .unwrap_or_else(|e| panic!("Failed test: {}: {}", case.name, e));
 
         assert_client_balance(
             &output,
@@ -900,3 +1004,297 @@ fn test_duplicate_detection_table_driven() {
         );
     }
 }
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_async_pipeline_matches_sync_output() {
+    for fixture in [
+        "basic.csv",
+        "disputes.csv",
+        "chargebacks.csv",
+        "edge_cases.csv",
+        "comprehensive_test.csv",
+    ] {
+        let input = std::fs::read(format!("tests/fixtures/{}", fixture)).unwrap();
+
+        let mut sync_output = Vec::new();
+        process_transactions(input.as_slice(), &mut sync_output).unwrap();
+
+        let mut async_output = Vec::new();
+        process_transactions_async(input.as_slice(), &mut async_output)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(async_output).unwrap(),
+            String::from_utf8(sync_output).unwrap(),
+            "{}",
+            fixture
+        );
+    }
+}
+
+#[cfg(feature = "async")]
+/// Feed a fixture through any engine and report what it ended up with
+async fn run_fixture<E: Engine>(mut engine: E, fixture: &str) -> (Vec<Account>, EngineStats) {
+    let input = std::fs::read(format!("tests/fixtures/{}", fixture)).unwrap();
+    apply_transactions_async(&mut engine, input.as_slice())
+        .await
+        .unwrap();
+    (engine.accounts().await, engine.stats().await)
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_engine_trait_agrees_across_execution_models() {
+    let fixture = "comprehensive_test.csv";
+    let (accounts, stats) = run_fixture(PaymentsEngine::new(), fixture).await;
+
+    assert_eq!(
+        stats,
+        EngineStats {
+            accounts: 3,
+            locked_accounts: 1,
+            open_disputes: 0,
+        }
+    );
+    let clients: Vec<_> = accounts.iter().map(|a| a.client_id).collect();
+    assert_eq!(clients, vec![1, 2, 3]);
+
+    let persistent = PersistentEngine::new(StubPersistence::new());
+    let sharded = ShardedEngine::new(2);
+    for (other_accounts, other_stats) in [
+        run_fixture(persistent, fixture).await,
+        run_fixture(sharded, fixture).await,
+    ] {
+        assert_eq!(other_stats, stats);
+        assert_eq!(format!("{:?}", other_accounts), format!("{:?}", accounts));
+    }
+}
+
+#[test]
+fn test_parallel_batch_matches_sequential_output() {
+    for fixture in [
+        "basic.csv",
+        "disputes.csv",
+        "chargebacks.csv",
+        "edge_cases.csv",
+        "comprehensive_test.csv",
+    ] {
+        let input = std::fs::read(format!("tests/fixtures/{}", fixture)).unwrap();
+
+        let mut sequential = Vec::new();
+        process_transactions(input.as_slice(), &mut sequential).unwrap();
+
+        for partitions in [1, 2, 7] {
+            let mut parallel = Vec::new();
+            process_transactions_parallel(input.as_slice(), &mut parallel, partitions).unwrap();
+            assert_eq!(
+                String::from_utf8(parallel).unwrap(),
+                String::from_utf8(sequential.clone()).unwrap(),
+                "{} with {} partitions",
+                fixture,
+                partitions
+            );
+        }
+    }
+}
+
+#[test]
+fn test_parallel_batch_rejects_ids_reused_across_partitions() {
+    // Clients 1 and 2 land in different partitions; tx 1 is only valid once.
+    // The invalid deposit doesn't use up tx 3, so client 2's deposit counts.
+    let input = build_csv(&[
+        ("deposit", 1, 1, "10.0"),
+        ("deposit", 2, 1, "20.0"),
+        ("deposit", 1, 3, "0"),
+        ("deposit", 2, 3, "5.0"),
+    ]);
+
+    let mut output = Vec::new();
+    process_transactions_parallel(input.as_bytes(), &mut output, 2).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.contains("1,10.0,0,10.0,false"), "{}", output);
+    assert!(output.contains("2,5.0,0,5.0,false"), "{}", output);
+}
+
+#[test]
+fn test_memory_mapped_input_matches_buffered() {
+    let path = std::path::Path::new("tests/fixtures/comprehensive_test.csv");
+
+    let mapped = Input::open_mapped(path).unwrap();
+    assert!(mapped.is_mapped());
+    let mut mapped_output = Vec::new();
+    process_transactions(mapped, &mut mapped_output).unwrap();
+
+    let buffered = Input::open(path).unwrap();
+    assert!(!buffered.is_mapped());
+    let mut buffered_output = Vec::new();
+    process_transactions(buffered, &mut buffered_output).unwrap();
+
+    assert_eq!(mapped_output, buffered_output);
+
+    // Empty files can't always be mapped and are read normally instead
+    let empty = tempfile::NamedTempFile::new().unwrap();
+    let input = Input::open_mapped(empty.path()).unwrap();
+    assert!(!input.is_mapped());
+    let mut output = Vec::new();
+    process_transactions(input, &mut output).unwrap();
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_workload_is_reproducible_and_round_trips_through_csv() {
+    let options = WorkloadOptions::dispute_heavy(5_000, 50);
+    let transactions = generate(&options);
+    assert_eq!(transactions.len(), 5_000);
+
+    let csv = generate_csv(&options);
+    assert_eq!(csv, generate_csv(&options));
+    let parsed: Vec<_> = read_transactions(&csv[..]).collect();
+    assert_eq!(parsed, transactions);
+
+    let mut rewritten = Vec::new();
+    write_transactions(parsed, &mut rewritten).unwrap();
+    assert_eq!(rewritten, csv);
+
+    // Disputes only reference deposits made earlier by the same client
+    let disputes = transactions
+        .iter()
+        .filter(|t| t.tx_type == TransactionType::Dispute)
+        .count();
+    assert!(disputes > 500, "only {} disputes", disputes);
+    let mut engine = PaymentsEngine::new();
+    for tx in transactions {
+        engine.process_transaction(tx);
+    }
+    assert!(engine.stats().open_disputes > 0);
+}
+
+#[test]
+fn test_workload_duplicates_chargebacks_and_amounts() {
+    let options = WorkloadOptions {
+        transactions: 10_000,
+        clients: 20,
+        dispute_ratio: 0.2,
+        chargeback_rate: 1.0,
+        duplicate_ratio: 0.1,
+        amounts: AmountDistribution::LogNormal {
+            median: 10.0,
+            sigma: 1.0,
+            max: 500.0,
+        },
+        ..WorkloadOptions::default()
+    };
+    let transactions = generate(&options);
+
+    let mut seen = std::collections::HashSet::new();
+    let mut duplicates = 0;
+    for tx in &transactions {
+        assert_ne!(tx.tx_type, TransactionType::Resolve);
+        if let Some(amount) = tx.amount {
+            assert!(
+                amount > Decimal::ZERO && amount <= Decimal::from(500),
+                "{}",
+                amount
+            );
+            if !seen.insert(tx.tx) {
+                duplicates += 1;
+            }
+        }
+    }
+    assert!(
+        (800..1200).contains(&duplicates),
+        "{} duplicates",
+        duplicates
+    );
+
+    // Every settled dispute is a chargeback, so some clients end up locked
+    let mut engine = PaymentsEngine::new();
+    for tx in transactions {
+        engine.process_transaction(tx);
+    }
+    assert!(engine.stats().locked_accounts > 0);
+}
+
+/// Apply rows one at a time, the reference for the chunked pipeline
+fn apply_one_by_one(input: impl std::io::Read) -> Vec<Account> {
+    let mut engine = PaymentsEngine::new();
+    for tx in read_transactions(input) {
+        engine.process_transaction(tx);
+    }
+    engine.into_sorted_accounts().collect()
+}
+
+fn apply_chunked(input: impl std::io::Read) -> Vec<Account> {
+    let mut engine = PaymentsEngine::new();
+    payments_engine::apply_transactions(&mut engine, input);
+    engine.into_sorted_accounts().collect()
+}
+
+fn account_rows(accounts: &[Account]) -> Vec<String> {
+    accounts
+        .iter()
+        .map(|a| format!("{} {} {} {}", a.client_id, a.available, a.held, a.locked))
+        .collect()
+}
+
+#[test]
+fn test_chunked_apply_matches_row_by_row() {
+    // Several megabytes, so the input spans many chunks, with malformed rows
+    // and a row of the wrong length mixed in
+    let mut input = generate_csv(&WorkloadOptions {
+        transactions: 250_000,
+        clients: 300,
+        duplicate_ratio: 0.01,
+        ..WorkloadOptions::default()
+    });
+    for (i, line) in [(1_000, "deposit,1,1\n"), (2_000_000, "bogus,1,2,3\n")] {
+        let at = i + input[i..].iter().position(|&b| b == b'\n').unwrap() + 1;
+        input.splice(at..at, line.bytes());
+    }
+    assert!(input.len() > 4 << 20);
+
+    let expected = apply_one_by_one(&input[..]);
+    assert_eq!(
+        account_rows(&apply_chunked(&input[..])),
+        account_rows(&expected)
+    );
+}
+
+/// Reader that fails after yielding `limit` bytes
+struct FailingReader<'a> {
+    data: &'a [u8],
+    limit: usize,
+}
+
+impl std::io::Read for FailingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.limit == 0 {
+            return Err(std::io::Error::other("disk on fire"));
+        }
+        let n = buf.len().min(self.limit).min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        self.limit -= n;
+        Ok(n)
+    }
+}
+
+#[test]
+fn test_chunked_apply_stops_at_read_error_like_row_by_row() {
+    let input = generate_csv(&WorkloadOptions::deposits(200_000, 50));
+    // Fail partway through a line in the third chunk
+    let limit = (5 << 19) + 7;
+    let reader = || FailingReader {
+        data: &input,
+        limit,
+    };
+
+    let chunked = apply_chunked(reader());
+    let expected = apply_one_by_one(reader());
+    assert_eq!(account_rows(&chunked), account_rows(&expected));
+    let total: Decimal = chunked.iter().map(|a| a.available).sum();
+    assert!(total > Decimal::ZERO);
+}
@@ -0,0 +1,170 @@
+#![cfg(not(feature = "fixed-point"))]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{make_deposit, make_dispute, make_transaction};
+use payments_engine::config::EngineConfig;
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::hooks::Hooks;
+use payments_engine::invariants::{InvariantReport, InvariantViolation};
+use payments_engine::models::{Account, ClientId, TransactionType};
+use payments_engine::state::{AccountState, EngineState};
+use rust_decimal_macros::dec;
+
+fn account(
+    client_id: ClientId,
+    available: rust_decimal::Decimal,
+    held: rust_decimal::Decimal,
+) -> Account {
+    Account {
+        client_id,
+        available,
+        held,
+        locked: false,
+        version: 0,
+    }
+}
+
+#[test]
+fn test_invariants_hold_through_dispute_lifecycle() {
+    let mut engine = PaymentsEngine::new();
+
+    engine.process_transaction(make_deposit(1, 1, dec!(100)));
+    engine.process_transaction(make_deposit(1, 2, dec!(40)));
+    engine.process_transaction(make_dispute(1, 1));
+    engine.process_transaction(make_dispute(1, 2));
+    engine.process_transaction(make_transaction(TransactionType::Resolve, 1, 2, None));
+    engine.process_transaction(make_deposit(2, 3, dec!(5)));
+    engine.process_transaction(make_dispute(2, 3));
+    engine.process_transaction(make_transaction(TransactionType::Chargeback, 2, 3, None));
+
+    let report = engine.check_invariants();
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(report.accounts_checked, 2);
+}
+
+#[test]
+fn test_seeded_held_balance_is_not_a_violation() {
+    let engine = PaymentsEngine::with_initial_accounts(vec![account(1, dec!(10), dec!(5))]);
+
+    assert!(engine.check_invariants().is_ok());
+}
+
+#[test]
+fn test_held_without_dispute_is_reported() {
+    // A state file where held funds have no backing dispute
+    let state = EngineState {
+        accounts: vec![AccountState {
+            client: 3,
+            available: dec!(1),
+            held: dec!(2),
+            locked: false,
+            version: 0,
+        }],
+        ..EngineState::default()
+    };
+    let engine = PaymentsEngine::from_state(state);
+
+    let report = engine.check_invariants();
+    assert_eq!(
+        report.violations,
+        vec![InvariantViolation::HeldMismatch {
+            client_id: 3,
+            held: dec!(2),
+            expected: dec!(0),
+        }]
+    );
+    assert!(report.to_string().contains("client 3"));
+}
+
+#[test]
+fn test_negative_balances_respect_config() {
+    let overdrawn = vec![account(1, dec!(-5), dec!(0))];
+
+    let engine = PaymentsEngine::with_initial_accounts(overdrawn.clone());
+    assert_eq!(
+        engine.check_invariants().violations,
+        vec![InvariantViolation::NegativeAvailable {
+            client_id: 1,
+            available: dec!(-5),
+        }]
+    );
+
+    let mut permissive = PaymentsEngine::with_config(EngineConfig {
+        allow_negative_balances: true,
+        ..EngineConfig::default()
+    });
+    permissive.seed_accounts(overdrawn);
+    assert!(permissive.check_invariants().is_ok());
+}
+
+/// Engine checking its invariants every `every` transactions, and the
+/// reports its alert hook received
+fn watched(
+    mut engine: PaymentsEngine,
+    every: u64,
+) -> (PaymentsEngine, Arc<Mutex<Vec<InvariantReport>>>) {
+    engine.set_config(EngineConfig {
+        invariant_check_every: Some(every),
+        ..EngineConfig::default()
+    });
+    let alerts = Arc::new(Mutex::new(Vec::new()));
+    let raised = alerts.clone();
+    engine.set_hooks(Hooks::new().alert(move |report| {
+        raised.lock().unwrap().push(report.clone());
+    }));
+    (engine, alerts)
+}
+
+#[test]
+fn test_watchdog_raises_alerts_every_n_transactions() {
+    let overdrawn = PaymentsEngine::with_initial_accounts(vec![account(1, dec!(-5), dec!(0))]);
+    let (mut engine, alerts) = watched(overdrawn, 2);
+
+    engine.process_transaction(make_deposit(2, 1, dec!(10)));
+    assert!(alerts.lock().unwrap().is_empty());
+    engine.process_transaction(make_deposit(2, 2, dec!(10)));
+    // Rejected transactions don't count towards the interval
+    engine.process_transaction(make_deposit(2, 2, dec!(10)));
+    engine.process_transaction(make_deposit(2, 3, dec!(10)));
+    engine.process_transaction(make_deposit(2, 4, dec!(10)));
+
+    let alerts = alerts.lock().unwrap();
+    assert_eq!(alerts.len(), 2);
+    assert_eq!(
+        alerts[0].violations,
+        vec![InvariantViolation::NegativeAvailable {
+            client_id: 1,
+            available: dec!(-5),
+        }]
+    );
+    assert_eq!(alerts[1].accounts_checked, 2);
+}
+
+#[test]
+fn test_watchdog_is_quiet_while_the_ledger_holds() {
+    let (mut engine, alerts) = watched(PaymentsEngine::new(), 1);
+
+    engine.process_transaction(make_deposit(1, 1, dec!(100)));
+    engine.process_transaction(make_dispute(1, 1));
+    engine.process_transaction(make_transaction(TransactionType::Chargeback, 1, 1, None));
+    assert!(alerts.lock().unwrap().is_empty());
+
+    // Off without an interval, even with an alert hook
+    let state = EngineState {
+        accounts: vec![AccountState {
+            client: 3,
+            available: dec!(1),
+            held: dec!(2),
+            locked: false,
+            version: 0,
+        }],
+        ..EngineState::default()
+    };
+    let (mut engine, alerts) = watched(PaymentsEngine::from_state(state), 1);
+    engine.set_config(EngineConfig::default());
+    engine.process_transaction(make_deposit(3, 1, dec!(1)));
+    assert!(alerts.lock().unwrap().is_empty());
+}
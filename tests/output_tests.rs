@@ -0,0 +1,109 @@
+#![cfg(not(feature = "fixed-point"))]
+
+mod common;
+
+use common::{make_deposit, make_dispute};
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::error::Result;
+use payments_engine::models::{Account, ClientId};
+use payments_engine::output::{CsvSink, JsonLinesSink, OutputSink};
+use payments_engine::{apply_transactions_streaming, write_accounts_to};
+use rust_decimal_macros::dec;
+
+fn sample_engine() -> PaymentsEngine {
+    let mut engine = PaymentsEngine::new();
+    engine.process_transaction(make_deposit(2, 1, dec!(5.5)));
+    engine.process_transaction(make_deposit(1, 2, dec!(10)));
+    engine.process_transaction(make_dispute(1, 2));
+    engine
+}
+
+#[test]
+fn test_csv_sink_matches_default_output() {
+    let mut buffer = Vec::new();
+    write_accounts_to(sample_engine(), &mut CsvSink::new(&mut buffer)).unwrap();
+
+    let output = String::from_utf8(buffer).unwrap();
+    assert_eq!(
+        output,
+        "client,available,held,total,locked\n1,0,10,10,false\n2,5.5,0,5.5,false\n"
+    );
+}
+
+#[test]
+fn test_json_lines_sink() {
+    let mut buffer = Vec::new();
+    write_accounts_to(sample_engine(), &mut JsonLinesSink::new(&mut buffer)).unwrap();
+
+    let output = String::from_utf8(buffer).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[1],
+        r#"{"client":2,"available":"5.5","held":"0","total":"5.5","locked":false,"version":1}"#
+    );
+}
+
+#[test]
+fn test_vec_sink_collects_sorted_accounts() {
+    let mut accounts: Vec<Account> = Vec::new();
+    write_accounts_to(sample_engine(), &mut accounts).unwrap();
+
+    let clients: Vec<ClientId> = accounts.iter().map(|a| a.client_id).collect();
+    assert_eq!(clients, vec![1, 2]);
+}
+
+/// Custom sink counting accounts and tracking whether it was finished
+#[derive(Default)]
+struct CountingSink {
+    count: usize,
+    finished: bool,
+}
+
+impl OutputSink for CountingSink {
+    fn write_account(&mut self, _account: &Account) -> Result<()> {
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.finished = true;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_custom_sink_is_finished() {
+    let mut sink = CountingSink::default();
+    write_accounts_to(sample_engine(), &mut sink).unwrap();
+
+    assert_eq!(sink.count, 2);
+    assert!(sink.finished);
+}
+
+#[test]
+fn test_streaming_emits_each_applied_update() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 deposit,2,2,3.0\n\
+                 withdrawal,1,3,50.0\n\
+                 dispute,1,1,\n";
+
+    let mut engine = PaymentsEngine::new();
+    let mut updates: Vec<Account> = Vec::new();
+    apply_transactions_streaming(&mut engine, input.as_bytes(), &mut updates).unwrap();
+
+    // The failed withdrawal produces no update
+    let summary: Vec<(ClientId, String, String)> = updates
+        .iter()
+        .map(|a| (a.client_id, a.available.to_string(), a.held.to_string()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (1, "10.0".to_string(), "0".to_string()),
+            (2, "3.0".to_string(), "0".to_string()),
+            (1, "0.0".to_string(), "10.0".to_string()),
+        ]
+    );
+}
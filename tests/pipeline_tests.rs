@@ -0,0 +1,95 @@
+#![cfg(all(feature = "async", not(feature = "fixed-point")))]
+
+use payments_engine::concurrent_engine::ShardedEngine;
+use payments_engine::error::EngineError;
+use payments_engine::pipeline::{self, IngestStats, OverflowPolicy, PipelineOptions};
+use rust_decimal_macros::dec;
+
+/// CSV with `count` deposits of 1.0, spread over clients 1-10
+fn deposits_csv(count: u32) -> String {
+    let mut csv = String::from("type,client,tx,amount\n");
+    for tx in 1..=count {
+        csv.push_str(&format!("deposit,{},{},1.0\n", tx % 10 + 1, tx));
+    }
+    csv
+}
+
+#[tokio::test]
+async fn test_ingest_preserves_per_client_order() {
+    let engine = ShardedEngine::new(3);
+    let input = "type, client, tx, amount\n\
+                 deposit, 1, 1, 10.0\n\
+                 withdrawal, 1, 2, 4.0\n\
+                 deposit, 2, 3, 3.0\n\
+                 not,a,row\n\
+                 dispute, 2, 3,\n\
+                 withdrawal, 1, 4, 6.0\n";
+
+    let stats = pipeline::ingest(&engine, input.as_bytes(), PipelineOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        stats,
+        IngestStats {
+            processed: 5,
+            dropped: 0,
+            malformed: 1,
+        }
+    );
+
+    let client1 = engine.get_account(1).await.unwrap();
+    assert_eq!(client1.available, dec!(0.0));
+    let client2 = engine.get_account(2).await.unwrap();
+    assert_eq!(client2.held, dec!(3.0));
+}
+
+#[tokio::test]
+async fn test_ingest_blocks_on_full_lanes_without_losing_transactions() {
+    let engine = ShardedEngine::new(2);
+    let options = PipelineOptions {
+        lane_capacity: 1,
+        overflow: OverflowPolicy::Block,
+    };
+
+    let stats = pipeline::ingest(&engine, deposits_csv(2000).as_bytes(), options)
+        .await
+        .unwrap();
+    assert_eq!(stats.processed, 2000);
+
+    let total: rust_decimal::Decimal = engine
+        .get_all_accounts()
+        .await
+        .iter()
+        .map(|account| account.available)
+        .sum();
+    assert_eq!(total, dec!(2000));
+}
+
+#[tokio::test]
+async fn test_ingest_drops_when_lanes_overflow() {
+    let engine = ShardedEngine::new(1);
+    let options = PipelineOptions {
+        lane_capacity: 1,
+        overflow: OverflowPolicy::Drop,
+    };
+
+    let stats = pipeline::ingest(&engine, deposits_csv(2000).as_bytes(), options)
+        .await
+        .unwrap();
+    assert!(stats.dropped > 0);
+    assert_eq!(stats.processed + stats.dropped, 2000);
+}
+
+#[tokio::test]
+async fn test_ingest_reports_engine_errors() {
+    let engine = ShardedEngine::new(2);
+    engine.shutdown().await.unwrap();
+
+    let result = pipeline::ingest(
+        &engine,
+        deposits_csv(10).as_bytes(),
+        PipelineOptions::default(),
+    )
+    .await;
+    assert!(matches!(result, Err(EngineError::ShuttingDown)));
+}
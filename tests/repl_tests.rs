@@ -0,0 +1,118 @@
+#![cfg(not(feature = "fixed-point"))]
+
+use payments_engine::repl::{Repl, Reply};
+
+/// Run `line` and return its output
+fn run(repl: &mut Repl, line: &str) -> String {
+    match repl.execute(line) {
+        Reply::Output(output) => output,
+        Reply::Quit => panic!("'{}' quit the session", line),
+    }
+}
+
+#[test]
+fn test_transactions_and_inspection() {
+    let mut repl = Repl::new();
+    assert_eq!(run(&mut repl, "deposit,1,1,100.0"), "applied");
+    assert_eq!(
+        run(
+            &mut repl,
+            r#"{"type":"deposit","client":2,"tx":2,"amount":"5.0"}"#
+        ),
+        "applied"
+    );
+    assert_eq!(run(&mut repl, "dispute,1,1"), "applied");
+    assert_eq!(
+        run(&mut repl, "withdrawal,1,3,1.0"),
+        "rejected: insufficient available funds"
+    );
+
+    assert_eq!(
+        run(&mut repl, "accounts"),
+        "client,available,held,total,locked\n1,0.0,100.0,100.0,false\n2,5.0,0,5.0,false"
+    );
+    assert_eq!(
+        run(&mut repl, "account 2"),
+        "client,available,held,total,locked\n2,5.0,0,5.0,false"
+    );
+    assert_eq!(run(&mut repl, "disputes"), "client,tx,amount\n1,1,100.0");
+    assert_eq!(
+        run(&mut repl, "history 1"),
+        "time,type,tx,amount,available,held,locked\n\
+         1,deposit,1,100.0,100.0,0,false\n\
+         3,dispute,1,100.0,0.0,100.0,false"
+    );
+    assert!(run(&mut repl, "check").starts_with("0 violation(s)"));
+    assert_eq!(run(&mut repl, "erase 2"), "applied");
+    assert_eq!(
+        run(&mut repl, "account 2"),
+        "error: no account for client 2"
+    );
+}
+
+#[test]
+fn test_undo_to_snapshots() {
+    let mut repl = Repl::new();
+    run(&mut repl, "deposit,1,1,10");
+    assert_eq!(run(&mut repl, "snapshot funded"), "saved snapshot 'funded'");
+    run(&mut repl, "dispute,1,1");
+    assert_eq!(run(&mut repl, "snapshot"), "saved snapshot '1'");
+    run(&mut repl, "chargeback,1,1");
+    run(&mut repl, "erase 1");
+    assert_eq!(
+        run(&mut repl, "snapshots"),
+        "start (0 entries)\nfunded (1 entries)\n1 (2 entries)"
+    );
+
+    assert_eq!(
+        run(&mut repl, "undo"),
+        "back at snapshot '1', undid 2 entries"
+    );
+    assert_eq!(run(&mut repl, "disputes"), "client,tx,amount\n1,1,10");
+
+    assert_eq!(
+        run(&mut repl, "undo funded"),
+        "back at snapshot 'funded', undid 1 entries"
+    );
+    assert_eq!(
+        run(&mut repl, "snapshots"),
+        "start (0 entries)\nfunded (1 entries)"
+    );
+    assert_eq!(repl.engine().client_history(1).len(), 1);
+    // The transaction ID is free again
+    assert_eq!(run(&mut repl, "dispute,1,1"), "applied");
+
+    assert_eq!(
+        run(&mut repl, "undo start"),
+        "back at snapshot 'start', undid 2 entries"
+    );
+    assert_eq!(
+        run(&mut repl, "accounts"),
+        "client,available,held,total,locked"
+    );
+    assert_eq!(run(&mut repl, "deposit,1,1,3"), "applied");
+}
+
+#[test]
+fn test_errors_keep_the_session_going() {
+    let mut repl = Repl::new();
+    assert_eq!(run(&mut repl, ""), "");
+    assert_eq!(
+        run(&mut repl, "account"),
+        "error: wrong arguments for 'account'; try 'help'"
+    );
+    assert_eq!(run(&mut repl, "history x"), "error: invalid client ID 'x'");
+    assert!(run(&mut repl, "deposit,one").starts_with("error: unknown command or transaction"));
+    assert!(run(&mut repl, "{").starts_with("error: invalid JSON transaction"));
+    assert_eq!(
+        run(&mut repl, "undo nope"),
+        "error: no snapshot named 'nope'"
+    );
+    assert_eq!(
+        run(&mut repl, "snapshot start"),
+        "error: snapshot 'start' already exists"
+    );
+    assert!(run(&mut repl, "help").contains("undo [<name>]"));
+    assert_eq!(repl.execute("quit"), Reply::Quit);
+    assert_eq!(repl.execute(" exit "), Reply::Quit);
+}
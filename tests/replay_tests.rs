@@ -0,0 +1,232 @@
+#![cfg(all(feature = "async", not(feature = "fixed-point")))]
+
+use payments_engine::concurrent_engine::ShardedEngine;
+use payments_engine::history::Balance;
+use payments_engine::models::Transaction;
+use payments_engine::persistence::{FilePersistence, LogEntry};
+use payments_engine::persistent_engine::PersistentEngine;
+use payments_engine::replay::{diff, AccountChange, Breakpoint, Replay};
+use rust_decimal_macros::dec;
+use tempfile::TempDir;
+
+/// Write a log in which client 1 is charged back and locked and client 2 is
+/// erased; the withdrawal of tx 4 is rejected
+fn write_log(dir: &TempDir) -> std::path::PathBuf {
+    let path = dir.path().join("wal.log");
+    let mut engine = PersistentEngine::new(FilePersistence::open(&path).unwrap());
+    for tx in [
+        Transaction::deposit(1, 1, dec!(10)),
+        Transaction::deposit(2, 2, dec!(3)),
+        Transaction::dispute(1, 1),
+        Transaction::withdrawal(2, 4, dec!(5)),
+        Transaction::chargeback(1, 1),
+    ] {
+        engine.process_transaction(tx).unwrap();
+    }
+    engine.erase_client(2).unwrap();
+    engine.flush().unwrap();
+    path
+}
+
+fn balance(available: rust_decimal::Decimal, held: rust_decimal::Decimal, locked: bool) -> Balance {
+    Balance {
+        available,
+        held,
+        locked,
+    }
+}
+
+#[test]
+fn test_breakpoint_parses_and_displays() {
+    for point in ["tx:7", "time:3", "lsn:4", "at:1700000000", "locked:12"] {
+        assert_eq!(point.parse::<Breakpoint>().unwrap().to_string(), point);
+    }
+    assert_eq!("tx:7".parse(), Ok(Breakpoint::Tx(7)));
+    for invalid in [
+        "",
+        "tx",
+        "tx:",
+        "tx:-1",
+        "client:1",
+        "at:yesterday",
+        "locked:18446744073709551616",
+    ] {
+        assert!(invalid.parse::<Breakpoint>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_replay_stops_at_breakpoints() {
+    let dir = TempDir::new().unwrap();
+    let mut replay = Replay::open(write_log(&dir)).unwrap();
+    assert_eq!(replay.len(), 6);
+
+    assert!(replay.run_to(Breakpoint::Tx(2), |_| {}));
+    assert_eq!((replay.position(), replay.time()), (2, 2));
+    assert_eq!(replay.snapshot()[&2], balance(dec!(3), dec!(0), false));
+
+    // The rejected withdrawal doesn't advance time
+    let mut steps = Vec::new();
+    assert!(replay.run_to(Breakpoint::Locked(1), |step| steps.push(step.position)));
+    assert_eq!(steps, [3, 4, 5]);
+    assert_eq!(replay.time(), 4);
+    assert!(replay.engine().get_account(1).unwrap().locked);
+
+    // Already reached
+    assert!(replay.run_to(Breakpoint::Time(1), |_| panic!("stepped")));
+
+    replay.rewind();
+    assert_eq!(replay.position(), 0);
+    assert!(replay.snapshot().is_empty());
+    assert!(replay.run_to(Breakpoint::Time(3), |_| {}));
+    assert_eq!(replay.position(), 3);
+    assert_eq!(replay.snapshot()[&1], balance(dec!(0), dec!(10), false));
+
+    assert!(!replay.run_to(Breakpoint::Tx(99), |_| {}));
+    assert_eq!(replay.position(), replay.len());
+    assert!(!replay.snapshot().contains_key(&2));
+}
+
+#[test]
+fn test_step_reports_entry_and_account() {
+    let dir = TempDir::new().unwrap();
+    let mut replay = Replay::open(write_log(&dir)).unwrap();
+    replay.run_to(Breakpoint::Tx(2), |_| {});
+
+    let step = replay.step().unwrap();
+    assert!(matches!(&step.entry, LogEntry::Transaction(tx) if tx.tx == 1));
+    assert_eq!(step.client(), 1);
+    assert_eq!(step.account.as_ref().unwrap().held, dec!(10));
+    assert!(step
+        .to_string()
+        .starts_with("#3 time 3: dispute client 1 tx 1"));
+
+    replay.run_to(Breakpoint::Locked(1), |_| {});
+    let step = replay.step().unwrap();
+    assert!(matches!(step.entry, LogEntry::Redaction(2)));
+    assert!(step.account.is_none());
+    assert!(replay.step().is_none());
+}
+
+#[test]
+fn test_diff_between_points() {
+    let dir = TempDir::new().unwrap();
+    let mut replay = Replay::open(write_log(&dir)).unwrap();
+    let start = replay.snapshot();
+    replay.run_to(Breakpoint::Tx(2), |_| {});
+    let funded = replay.snapshot();
+    replay.run_to_end(|_| {});
+    let end = replay.snapshot();
+
+    assert!(diff(&funded, &funded).is_empty());
+    assert_eq!(diff(&start, &funded).len(), 2);
+
+    let changes = diff(&funded, &end);
+    assert_eq!(
+        changes,
+        [
+            AccountChange {
+                client: 1,
+                before: Some(balance(dec!(10), dec!(0), false)),
+                after: Some(balance(dec!(0), dec!(0), true)),
+            },
+            AccountChange {
+                client: 2,
+                before: Some(balance(dec!(3), dec!(0), false)),
+                after: None,
+            },
+        ]
+    );
+    assert_eq!(
+        changes[0].to_string(),
+        "client 1: available 10 -> 0, locked false -> true"
+    );
+    assert_eq!(changes[1].to_string(), "client 2: erased");
+    assert_eq!(
+        diff(&start, &funded)[1].to_string(),
+        "client 2: opened with available 3, held 0, locked false"
+    );
+}
+
+#[test]
+fn test_replay_ignores_torn_tail() {
+    let dir = TempDir::new().unwrap();
+    let path = write_log(&dir);
+    let mut contents = std::fs::read(&path).unwrap();
+    contents.extend_from_slice(b"{\"type\":\"deposit\",\"cli");
+    std::fs::write(&path, &contents).unwrap();
+
+    assert_eq!(Replay::open(&path).unwrap().len(), 6);
+    // Read-only: the torn tail is left for recovery to deal with
+    assert_eq!(std::fs::read(&path).unwrap(), contents);
+}
+
+#[test]
+fn test_replay_starts_from_checkpoint() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("wal.log");
+    let mut engine = PersistentEngine::new(FilePersistence::open(&path).unwrap());
+    engine
+        .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+        .unwrap();
+    engine.checkpoint().unwrap();
+    engine
+        .process_transaction(Transaction::dispute(1, 1))
+        .unwrap();
+    engine.flush().unwrap();
+
+    // Only the dispute is left in the log; the deposit comes from the snapshot
+    let mut replay = Replay::open(&path).unwrap();
+    assert_eq!(replay.len(), 1);
+    assert_eq!(replay.snapshot()[&1], balance(dec!(10), dec!(0), false));
+    replay.run_to_end(|_| {});
+    assert_eq!(replay.snapshot()[&1], balance(dec!(0), dec!(10), false));
+
+    replay.rewind();
+    assert_eq!(replay.snapshot()[&1], balance(dec!(10), dec!(0), false));
+}
+
+#[tokio::test]
+async fn test_wal_directory_replays_in_timestamp_order() {
+    let dir = TempDir::new().unwrap();
+    let engine = ShardedEngine::recover(dir.path(), 2).unwrap();
+    // Clients 1 and 2 are on different shards
+    for tx in [
+        Transaction {
+            timestamp: Some(100),
+            ..Transaction::deposit(2, 1, dec!(5))
+        },
+        Transaction {
+            timestamp: Some(200),
+            ..Transaction::deposit(1, 2, dec!(7))
+        },
+        Transaction {
+            timestamp: Some(300),
+            ..Transaction::deposit(2, 3, dec!(1))
+        },
+        Transaction {
+            timestamp: Some(400),
+            ..Transaction::withdrawal(1, 4, dec!(2))
+        },
+    ] {
+        engine.process_transaction(tx).await.unwrap();
+    }
+    drop(engine);
+
+    let mut replay = Replay::open(dir.path()).unwrap();
+    let mut applied = Vec::new();
+    assert!(replay.run_to(Breakpoint::At(250), |step| applied.push(step.time)));
+    assert_eq!(applied, [1, 2]);
+    assert_eq!(replay.snapshot()[&1], balance(dec!(7), dec!(0), false));
+    assert_eq!(replay.snapshot()[&2], balance(dec!(5), dec!(0), false));
+
+    // Past the last timestamp, the replay stops at the end of the log
+    assert!(replay.run_to(Breakpoint::At(1_000), |_| {}));
+    assert_eq!(replay.position(), 4);
+
+    replay.rewind();
+    assert!(replay.run_to(Breakpoint::Lsn(3), |_| {}));
+    assert_eq!(replay.snapshot()[&2], balance(dec!(6), dec!(0), false));
+    assert!(replay.run_to(Breakpoint::Lsn(2), |_| panic!("stepped")));
+    assert!(!replay.run_to(Breakpoint::Lsn(5), |_| {}));
+}
@@ -0,0 +1,330 @@
+#![cfg(all(feature = "server", not(feature = "fixed-point")))]
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use payments_engine::concurrent_engine::ShardedEngine;
+use payments_engine::error::EngineError;
+use payments_engine::models::{Transaction, TransactionType};
+use payments_engine::server::auth::ApiKeys;
+use payments_engine::server::config::ServerConfig;
+use payments_engine::server::protocol::{parse_line, Request};
+use payments_engine::server::snapshots::{Schedule, SnapshotOptions, Snapshotter};
+use payments_engine::server::tcp;
+use payments_engine::state::EngineState;
+use rust_decimal_macros::dec;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+#[test]
+fn test_parse_csv_and_json_transactions() {
+    match parse_line("deposit, 1, 7, 2.5").unwrap() {
+        Request::Transaction(tx) => {
+            assert_eq!(tx.tx_type, TransactionType::Deposit);
+            assert_eq!((tx.client, tx.tx, tx.amount), (1, 7, Some(dec!(2.5))));
+        }
+        other => panic!("unexpected request {:?}", other),
+    }
+
+    // Disputes may omit the trailing amount column
+    match parse_line("dispute,1,7").unwrap() {
+        Request::Transaction(tx) => assert_eq!(tx.amount, None),
+        other => panic!("unexpected request {:?}", other),
+    }
+
+    match parse_line(r#"{"type":"resolve","client":3,"tx":9}"#).unwrap() {
+        Request::Transaction(tx) => {
+            assert_eq!(tx.tx_type, TransactionType::Resolve);
+            assert_eq!(tx.amount, None);
+        }
+        other => panic!("unexpected request {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_commands_and_noise() {
+    assert!(matches!(parse_line("query 42"), Ok(Request::Query(42))));
+    assert!(matches!(parse_line("accounts"), Ok(Request::Accounts)));
+    assert!(matches!(parse_line("funds"), Ok(Request::Funds)));
+    assert!(matches!(parse_line(""), Ok(Request::Ignore)));
+    assert!(matches!(
+        parse_line("type, client, tx, amount"),
+        Ok(Request::Ignore)
+    ));
+    assert!(matches!(parse_line("auth secret"), Ok(Request::Auth(key)) if key == "secret"));
+    assert!(matches!(parse_line("bind 1, 2"), Ok(Request::Bind(c)) if c == vec![1, 2]));
+    assert!(parse_line("bind").is_err());
+    assert!(parse_line("query abc").is_err());
+    assert!(parse_line("bogus,1,2,3").is_err());
+}
+
+#[tokio::test]
+async fn test_connection_acks_and_queries() {
+    let engine = ShardedEngine::new(2);
+    let (client, server) = tokio::io::duplex(4096);
+    tokio::spawn(tcp::handle_connection(server, engine.clone_handle(), None));
+
+    let (reader, mut writer) = tokio::io::split(client);
+    let mut lines = BufReader::new(reader).lines();
+
+    writer
+        .write_all(
+            b"type,client,tx,amount\n\
+              deposit,1,1,10.0\n\
+              withdrawal,1,2,99\n\
+              garbage\n\
+              query 1\n\
+              query 2\n",
+        )
+        .await
+        .unwrap();
+
+    let mut responses = Vec::new();
+    for _ in 0..5 {
+        responses.push(lines.next_line().await.unwrap().unwrap());
+    }
+
+    assert_eq!(responses[0], "ok");
+    assert_eq!(responses[1], "rejected: insufficient available funds");
+    assert!(responses[2].starts_with("error: invalid transaction"));
+    assert_eq!(responses[3], "1,10.0,0,10.0,false");
+    assert_eq!(responses[4], "error: client not found");
+}
+
+#[tokio::test]
+async fn test_tcp_server_shares_engine_across_connections() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(tcp::serve(listener, ShardedEngine::new(4), None));
+
+    // First connection deposits
+    let mut first = TcpStream::connect(addr).await.unwrap();
+    first.write_all(b"deposit,5,1,3.0\n").await.unwrap();
+    let mut first_lines = BufReader::new(first).lines();
+    assert_eq!(first_lines.next_line().await.unwrap().unwrap(), "ok");
+
+    // Second connection sees the balance
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    second.write_all(b"accounts\n").await.unwrap();
+    let mut second_lines = BufReader::new(second).lines();
+    assert_eq!(
+        second_lines.next_line().await.unwrap().unwrap(),
+        "5,3.0,0,3.0,false"
+    );
+    assert_eq!(second_lines.next_line().await.unwrap().unwrap(), "end");
+}
+
+/// Send `input` over a fresh in-memory connection and collect `count` response lines
+async fn exchange(api_keys: Option<ApiKeys>, input: &[u8], count: usize) -> Vec<String> {
+    let engine = ShardedEngine::new(2);
+    let (client, server) = tokio::io::duplex(4096);
+    tokio::spawn(tcp::handle_connection(
+        server,
+        engine,
+        api_keys.map(std::sync::Arc::new),
+    ));
+
+    let (reader, mut writer) = tokio::io::split(client);
+    writer.write_all(input).await.unwrap();
+
+    let mut lines = BufReader::new(reader).lines();
+    let mut responses = Vec::new();
+    for _ in 0..count {
+        responses.push(lines.next_line().await.unwrap().unwrap());
+    }
+    responses
+}
+
+#[tokio::test]
+async fn test_bound_session_rejects_other_clients() {
+    let responses = exchange(
+        None,
+        b"bind 1,2\n\
+          deposit,1,1,5.0\n\
+          dispute,3,1\n\
+          query 3\n\
+          bind 3\n\
+          accounts\n",
+        6,
+    )
+    .await;
+
+    assert_eq!(responses[0], "ok");
+    assert_eq!(responses[1], "ok");
+    assert_eq!(responses[2], "error: client 3 not bound to this session");
+    assert_eq!(responses[3], "error: client 3 not bound to this session");
+    assert_eq!(responses[4], "error: session already bound");
+    assert_eq!(responses[5], "1,5.0,0,5.0,false");
+}
+
+#[tokio::test]
+async fn test_funds_need_every_client() {
+    let responses = exchange(
+        None,
+        b"deposit,1,1,5.0
+          deposit,2,2,2.5
+          dispute,2,2
+          funds
+          bind 1
+          funds
+",
+        6,
+    )
+    .await;
+
+    assert_eq!(responses[3], "5.0,2.5,7.5,0,1");
+    assert_eq!(
+        responses[5],
+        "error: session not authorized for all clients"
+    );
+}
+
+#[tokio::test]
+async fn test_session_authenticates_with_api_key() {
+    let keys = ApiKeys::parse("feed-a 1,2\n").unwrap();
+    let responses = exchange(
+        Some(keys),
+        b"deposit,1,1,5.0\n\
+          auth wrong\n\
+          auth feed-a\n\
+          bind 2,3\n\
+          deposit,3,2,1.0\n\
+          deposit,2,3,1.0\n",
+        6,
+    )
+    .await;
+
+    assert_eq!(responses[0], "error: not authenticated");
+    assert_eq!(responses[1], "error: unknown API key");
+    assert_eq!(responses[2], "ok");
+    assert_eq!(responses[3], "error: API key not authorized for client 3");
+    assert_eq!(responses[4], "error: client 3 not bound to this session");
+    assert_eq!(responses[5], "ok");
+}
+
+#[test]
+fn test_server_config_parse() {
+    let config = ServerConfig::parse(
+        r#"
+        [limits]
+        max_amount = "250.5"
+
+        [rate_limit]
+        per_second = 10
+        burst = 20
+
+        [validation]
+        max_decimal_places = 4
+
+        [retention]
+        max_entries = 1000
+
+        [watchdog]
+        check_every = 50
+        "#,
+    )
+    .unwrap();
+
+    let engine_config = config.engine_config();
+    assert_eq!(engine_config.max_amount, Some(dec!(250.5)));
+    assert_eq!(engine_config.max_decimal_places, Some(4));
+    assert_eq!(engine_config.retention.max_entries, Some(1000));
+    assert_eq!(engine_config.retention.max_age, None);
+    assert_eq!(engine_config.invariant_check_every, Some(50));
+    assert_eq!(config.rate_limit.unwrap().burst, 20);
+
+    // Every section is optional, but unknown settings are mistakes
+    assert_eq!(ServerConfig::parse("").unwrap(), ServerConfig::default());
+    assert!(ServerConfig::parse("[limits]\nmax_amout = \"1\"").is_err());
+}
+
+/// Unix seconds to a time, for schedule checks
+fn at(seconds: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+#[test]
+fn test_snapshot_schedule_next_after() {
+    // Friday 2024-03-01
+    let day = 1_709_251_200;
+
+    let working_hours: Schedule = "*/15 9-17 * * 1-5".parse().unwrap();
+    assert_eq!(
+        working_hours.next_after(at(day + 9 * 3600 + 7 * 60)),
+        Some(at(day + 9 * 3600 + 15 * 60))
+    );
+    // After the last slot on Friday comes Monday morning
+    assert_eq!(
+        working_hours.next_after(at(day + 17 * 3600 + 50 * 60)),
+        Some(at(day + 3 * 86_400 + 9 * 3600))
+    );
+
+    // Both day fields restricted: the 13th or any Friday
+    let friday_or_13th: Schedule = "0 0 13 * 5".parse().unwrap();
+    assert_eq!(
+        friday_or_13th.next_after(at(day)),
+        Some(at(day + 7 * 86_400))
+    );
+    // 7 is Sunday too
+    let sunday: Schedule = "0 12 * * 7".parse().unwrap();
+    assert_eq!(
+        sunday.next_after(at(day)),
+        Some(at(day + 2 * 86_400 + 12 * 3600))
+    );
+    let daily: Schedule = "@daily".parse().unwrap();
+    assert_eq!(daily.next_after(at(day + 60)), Some(at(day + 86_400)));
+    assert_eq!(daily.to_string(), "@daily");
+
+    let never: Schedule = "0 0 31 2 *".parse().unwrap();
+    assert_eq!(never.next_after(at(day)), None);
+
+    for invalid in [
+        "61 * * * *",
+        "* * * *",
+        "*/0 * * * *",
+        "5-1 * * * *",
+        "0 0 0 * *",
+    ] {
+        assert!(invalid.parse::<Schedule>().is_err(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn test_snapshotter_saves_and_prunes() {
+    let dir = tempfile::tempdir().unwrap();
+    let engine = ShardedEngine::new(2);
+    let snapshots = Snapshotter::new(
+        &engine,
+        SnapshotOptions {
+            dir: dir.path().join("snapshots"),
+            keep: Some(2),
+        },
+    )
+    .unwrap();
+
+    let mut saved = Vec::new();
+    for (client, tx) in [(1, 1), (2, 2), (3, 3)] {
+        engine
+            .process_transaction(Transaction::deposit(client, tx, dec!(1.5)))
+            .await
+            .unwrap();
+        saved.push(snapshots.take().await.unwrap());
+    }
+    assert_eq!(saved[2].accounts, 3);
+
+    // Only the newest two are kept
+    let mut files: Vec<_> = std::fs::read_dir(snapshots.dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    assert_eq!(files, [saved[1].path.clone(), saved[2].path.clone()]);
+    let state = EngineState::load(&saved[2].path).unwrap();
+    assert_eq!(state.accounts.len(), 3);
+    assert_eq!(state.processed_tx_ids, [1, 2, 3]);
+
+    engine.shutdown().await.unwrap();
+    assert!(matches!(
+        snapshots.take().await,
+        Err(EngineError::ShuttingDown)
+    ));
+}
@@ -0,0 +1,500 @@
+//! Deterministic simulation of a `ShardedEngine` under faults
+//!
+//! Everything runs on one thread with tokio's clock paused, so the only
+//! source of variation is the seeded `SimRng`: the same seed always replays
+//! the same interleaving of requests, network delays, dropped requests,
+//! failed WAL appends and crashes. After every step the harness checks the
+//! engine against a replica rebuilt from its event stream and the ledger
+//! invariants; a failure names the seed and step to replay it.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use payments_engine::concurrent_engine::{modulo_shard_key, ShardedEngine};
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::error::Result;
+use payments_engine::events::AccountEvent;
+use payments_engine::models::{ClientId, Transaction, TransactionType, TxId};
+use payments_engine::outcome::Outcome;
+use payments_engine::persistence::PersistenceBackend;
+use payments_engine::persistent_engine::PersistentEngine;
+use rust_decimal::Decimal;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+/// Seeded pseudo-random numbers (SplitMix64), so runs don't depend on a
+/// system RNG
+pub struct SimRng(u64);
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// True `percent` times out of 100
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// How often each kind of fault is injected, in percent per opportunity
+#[derive(Debug, Clone, Copy)]
+pub struct FaultRates {
+    /// A WAL append fails, so the transaction is refused
+    pub append_failure: u64,
+    /// An in-flight request is dropped, as if the connection went away
+    pub dropped_request: u64,
+    /// The process crashes and the engine is recovered from its WALs
+    pub crash: u64,
+}
+
+impl FaultRates {
+    /// No faults, only random interleavings
+    pub const NONE: Self = Self {
+        append_failure: 0,
+        dropped_request: 0,
+        crash: 0,
+    };
+
+    /// A bit of everything
+    pub const CHAOTIC: Self = Self {
+        append_failure: 5,
+        dropped_request: 5,
+        crash: 1,
+    };
+}
+
+/// Settings of one simulation run
+#[derive(Debug, Clone, Copy)]
+pub struct SimConfig {
+    pub seed: u64,
+    pub steps: usize,
+    pub num_shards: usize,
+    pub clients: u16,
+    pub faults: FaultRates,
+}
+
+/// Shard WAL kept in memory, so it outlives a simulated crash
+///
+/// Appends fail when the shared fault script says so.
+struct MemoryPersistence {
+    log: Arc<Mutex<Vec<Transaction>>>,
+    faults: Arc<Mutex<AppendFaults>>,
+}
+
+/// Which appends fail, decided up front from the seed
+struct AppendFaults {
+    rng: SimRng,
+    percent: u64,
+    failed: usize,
+}
+
+impl PersistenceBackend for MemoryPersistence {
+    fn append(&mut self, tx: &Transaction) -> Result<()> {
+        let mut faults = self.faults.lock().unwrap();
+        let percent = faults.percent;
+        if faults.rng.chance(percent) {
+            faults.failed += 1;
+            return Err(io::Error::other("injected WAL failure").into());
+        }
+        self.log.lock().unwrap().push(tx.clone());
+        Ok(())
+    }
+
+    fn replay(&self) -> Result<Vec<Transaction>> {
+        Ok(self.log.lock().unwrap().clone())
+    }
+}
+
+/// A request on its way through the simulated network
+struct Request {
+    tx: Transaction,
+    task: JoinHandle<Result<Outcome>>,
+}
+
+/// What a run did, for checking that a seed replays identically
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SimReport {
+    /// One line per acknowledged, failed or dropped request, in order
+    pub trace: Vec<String>,
+    pub applied: usize,
+    pub rejected: usize,
+    pub failed_appends: usize,
+    pub dropped: usize,
+    pub crashes: usize,
+}
+
+/// One simulated deployment: the engine, its WALs and its clients
+pub struct Simulation {
+    config: SimConfig,
+    rng: SimRng,
+    engine: ShardedEngine,
+    logs: Vec<Arc<Mutex<Vec<Transaction>>>>,
+    faults: Arc<Mutex<AppendFaults>>,
+    events: broadcast::Receiver<AccountEvent>,
+    /// Rebuilt from the event stream alone
+    replica: PaymentsEngine,
+    in_flight: Vec<Request>,
+    /// Deposits submitted so far, as (client, tx) pairs to dispute
+    deposits: Vec<(ClientId, TxId)>,
+    /// Every transaction submitted, for retries
+    submitted: Vec<Transaction>,
+    /// Submitted transactions, as `describe`d
+    submitted_keys: HashSet<String>,
+    /// Applied acknowledgements and events seen, by transaction
+    acked_applied: HashMap<String, usize>,
+    events_seen: HashMap<String, usize>,
+    next_tx: TxId,
+    step: usize,
+    report: SimReport,
+}
+
+impl Simulation {
+    /// Start a fresh engine; must run inside a runtime with paused time
+    pub fn new(config: SimConfig) -> Self {
+        let mut rng = SimRng::new(config.seed);
+        let faults = Arc::new(Mutex::new(AppendFaults {
+            rng: SimRng::new(rng.next_u64()),
+            percent: config.faults.append_failure,
+            failed: 0,
+        }));
+        let logs: Vec<_> = (0..config.num_shards)
+            .map(|_| Arc::new(Mutex::new(Vec::new())))
+            .collect();
+        let engine = start_engine(&logs, &faults, false);
+        let events = engine.subscribe();
+
+        Self {
+            config,
+            rng,
+            engine,
+            logs,
+            faults,
+            events,
+            replica: PaymentsEngine::new(),
+            in_flight: Vec::new(),
+            deposits: Vec::new(),
+            submitted: Vec::new(),
+            submitted_keys: HashSet::new(),
+            acked_applied: HashMap::new(),
+            events_seen: HashMap::new(),
+            next_tx: 1,
+            step: 0,
+            report: SimReport::default(),
+        }
+    }
+
+    /// Run every step, checking invariants after each, then let the
+    /// remaining requests finish and check once more
+    pub async fn run(mut self) -> SimReport {
+        for step in 0..self.config.steps {
+            self.step = step;
+            self.act().await;
+            self.collect_finished().await;
+            self.check().await;
+        }
+
+        while !self.in_flight.is_empty() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            self.collect_finished().await;
+        }
+        self.check().await;
+
+        self.report.failed_appends = self.faults.lock().unwrap().failed;
+        self.report
+    }
+
+    /// Take one random action
+    async fn act(&mut self) {
+        let faults = self.config.faults;
+        match self.rng.below(100) {
+            roll if roll < faults.crash => self.crash_and_recover().await,
+            roll if roll < faults.crash + faults.dropped_request => self.drop_request(),
+            roll if roll < 15 && !self.submitted.is_empty() => {
+                let index = self.rng.below(self.submitted.len() as u64) as usize;
+                self.submit(self.submitted[index].clone());
+            }
+            roll if roll < 80 => {
+                let tx = self.next_transaction();
+                self.submit(tx);
+            }
+            _ => {
+                let millis = self.rng.below(5);
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+            }
+        }
+    }
+
+    /// A random transaction over a few clients, including adversarial ones:
+    /// disputes of other clients' deposits, of unknown transactions, and
+    /// lifecycle steps out of order
+    fn next_transaction(&mut self) -> Transaction {
+        let client = 1 + self.rng.below(u64::from(self.config.clients)) as ClientId;
+        let amount = Decimal::new(1 + self.rng.below(10_000) as i64, 2);
+        let referenced = |rng: &mut SimRng, deposits: &[(ClientId, TxId)]| {
+            if deposits.is_empty() || rng.chance(10) {
+                // Sometimes a transaction that never existed
+                return (client, TxId::MAX - rng.below(100) as TxId);
+            }
+            let (owner, tx) = deposits[rng.below(deposits.len() as u64) as usize];
+            // Usually the owner disputes, sometimes someone else tries to
+            (if rng.chance(90) { owner } else { client }, tx)
+        };
+
+        match self.rng.below(10) {
+            0..=3 => {
+                let tx = self.take_tx_id();
+                self.deposits.push((client, tx));
+                Transaction::deposit(client, tx, amount)
+            }
+            4..=5 => Transaction::withdrawal(client, self.take_tx_id(), amount),
+            6..=7 => {
+                let (client, tx) = referenced(&mut self.rng, &self.deposits);
+                Transaction::dispute(client, tx)
+            }
+            8 => {
+                let (client, tx) = referenced(&mut self.rng, &self.deposits);
+                Transaction::resolve(client, tx)
+            }
+            _ => {
+                let (client, tx) = referenced(&mut self.rng, &self.deposits);
+                Transaction::chargeback(client, tx)
+            }
+        }
+    }
+
+    /// Transaction IDs are unique across clients: shards only catch
+    /// duplicates among their own clients
+    fn take_tx_id(&mut self) -> TxId {
+        let tx = self.next_tx;
+        self.next_tx += 1;
+        tx
+    }
+
+    /// Send a transaction after a random network delay
+    fn submit(&mut self, tx: Transaction) {
+        self.submitted_keys.insert(describe(&tx));
+        self.submitted.push(tx.clone());
+
+        let delay = Duration::from_millis(self.rng.below(10));
+        let engine = self.engine.clone_handle();
+        let request = tx.clone();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            engine.process_transaction(request).await
+        });
+        self.in_flight.push(Request { tx, task });
+    }
+
+    /// Drop a random request; it may or may not have reached the engine
+    fn drop_request(&mut self) {
+        if self.in_flight.is_empty() {
+            return;
+        }
+        let index = self.rng.below(self.in_flight.len() as u64) as usize;
+        let request = self.in_flight.swap_remove(index);
+        request.task.abort();
+        self.report.dropped += 1;
+        self.report
+            .trace
+            .push(format!("dropped {}", describe(&request.tx)));
+    }
+
+    /// Lose the process, then recover from the WALs as they were
+    ///
+    /// The logs and event stream are cut at the same instant: a shard appends
+    /// and publishes within one poll, so they always agree.
+    async fn crash_and_recover(&mut self) {
+        self.drain_events();
+        let logs: Vec<_> = self
+            .logs
+            .iter()
+            .map(|log| Arc::new(Mutex::new(log.lock().unwrap().clone())))
+            .collect();
+
+        // Requests to the dead process never get an answer
+        for request in self.in_flight.drain(..) {
+            request.task.abort();
+            self.report
+                .trace
+                .push(format!("lost {}", describe(&request.tx)));
+        }
+
+        self.logs = logs;
+        self.engine = start_engine(&self.logs, &self.faults, true);
+        self.events = self.engine.subscribe();
+        self.report.crashes += 1;
+        self.report
+            .trace
+            .push(format!("crash at step {}", self.step));
+    }
+
+    /// Record the answers to requests that completed
+    async fn collect_finished(&mut self) {
+        // Let shards and requests run up to the current virtual time
+        tokio::task::yield_now().await;
+
+        let mut index = 0;
+        while index < self.in_flight.len() {
+            if !self.in_flight[index].task.is_finished() {
+                index += 1;
+                continue;
+            }
+            let request = self.in_flight.swap_remove(index);
+            let result = request.task.await.expect("request task panicked");
+            let line = match &result {
+                Ok(Outcome::Applied) => {
+                    self.report.applied += 1;
+                    *self.acked_applied.entry(describe(&request.tx)).or_default() += 1;
+                    "applied".to_string()
+                }
+                Ok(outcome) => {
+                    self.report.rejected += 1;
+                    outcome.to_string()
+                }
+                Err(e) => format!("failed: {}", e),
+            };
+            self.report
+                .trace
+                .push(format!("{} {}", describe(&request.tx), line));
+        }
+    }
+
+    /// Feed every published event to the replica, which must accept it
+    fn drain_events(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => {
+                    let event_key =
+                        describe_parts(event.tx_type, event.account.client_id, event.tx);
+                    assert!(
+                        self.submitted_keys.contains(&event_key),
+                        "{}: event for a transaction never submitted: {:?}",
+                        self.context(),
+                        event
+                    );
+                    *self.events_seen.entry(event_key).or_default() += 1;
+                    let outcome = self.replica.apply_event(event.clone());
+                    assert_eq!(
+                        outcome,
+                        Outcome::Applied,
+                        "{}: replica refused {:?}",
+                        self.context(),
+                        event
+                    );
+                }
+                Err(broadcast::error::TryRecvError::Empty) => return,
+                Err(e) => panic!("{}: event stream broke: {}", self.context(), e),
+            }
+        }
+    }
+
+    /// Check the engine against its replica and the ledger invariants
+    async fn check(&mut self) {
+        // Nothing is in process while the snapshot is taken, and nothing
+        // runs between it and draining the events, so both show one moment
+        let snapshot = self.engine.snapshot().await;
+        self.drain_events();
+
+        for account in &snapshot {
+            let (available, held, locked, version) = self
+                .replica
+                .get_account(account.client_id)
+                .map_or((Decimal::ZERO, Decimal::ZERO, false, 0), |replica| {
+                    (
+                        replica.available,
+                        replica.held,
+                        replica.locked,
+                        replica.version,
+                    )
+                });
+            assert_eq!(
+                (
+                    account.available,
+                    account.held,
+                    account.locked,
+                    account.version
+                ),
+                (available, held, locked, version),
+                "{}: client {} differs from the event replica",
+                self.context(),
+                account.client_id
+            );
+        }
+        assert_eq!(
+            snapshot
+                .iter()
+                .filter(|account| account.version > 0)
+                .count(),
+            self.replica.get_accounts().len(),
+            "{}: replica has accounts the engine doesn't",
+            self.context()
+        );
+
+        let report = self.replica.check_invariants();
+        assert!(report.is_ok(), "{}: {}", self.context(), report);
+
+        // Every acknowledged application was published
+        for (event_key, acked) in &self.acked_applied {
+            let published = self.events_seen.get(event_key).copied().unwrap_or_default();
+            assert!(
+                published >= *acked,
+                "{}: {} acknowledged {} times but published {} times",
+                self.context(),
+                event_key,
+                acked,
+                published
+            );
+        }
+    }
+
+    fn context(&self) -> String {
+        format!("seed {} step {}", self.config.seed, self.step)
+    }
+}
+
+/// Start an engine over `logs`, replaying them when recovering
+fn start_engine(
+    logs: &[Arc<Mutex<Vec<Transaction>>>],
+    faults: &Arc<Mutex<AppendFaults>>,
+    recover: bool,
+) -> ShardedEngine {
+    let engines = logs
+        .iter()
+        .map(|log| {
+            let persistence = MemoryPersistence {
+                log: log.clone(),
+                faults: faults.clone(),
+            };
+            if recover {
+                PersistentEngine::recover(persistence).expect("memory WAL replays")
+            } else {
+                PersistentEngine::new(persistence)
+            }
+        })
+        .collect();
+    ShardedEngine::from_shards(engines, modulo_shard_key)
+}
+
+fn describe(tx: &Transaction) -> String {
+    describe_parts(tx.tx_type, tx.client, tx.tx)
+}
+
+fn describe_parts(tx_type: TransactionType, client: ClientId, tx: TxId) -> String {
+    format!("{} client {} tx {}", tx_type, client, tx)
+}
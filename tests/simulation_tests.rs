@@ -0,0 +1,55 @@
+#![cfg(all(feature = "async", not(feature = "fixed-point")))]
+
+mod simulation;
+
+use simulation::{FaultRates, SimConfig, SimReport, Simulation};
+
+/// Seeds every run covers; `SIM_SEED` runs just that one, to replay a failure
+fn seeds() -> Vec<u64> {
+    match std::env::var("SIM_SEED") {
+        Ok(seed) => vec![seed.parse().expect("SIM_SEED is a u64")],
+        Err(_) => (1..=8).collect(),
+    }
+}
+
+fn config(seed: u64, faults: FaultRates) -> SimConfig {
+    SimConfig {
+        seed,
+        steps: 400,
+        num_shards: 3,
+        clients: 6,
+        faults,
+    }
+}
+
+async fn simulate(config: SimConfig) -> SimReport {
+    Simulation::new(config).run().await
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_simulated_interleavings_keep_invariants() {
+    for seed in seeds() {
+        let report = simulate(config(seed, FaultRates::NONE)).await;
+        assert!(report.applied > 0, "seed {}: nothing applied", seed);
+        assert_eq!(report.crashes + report.dropped + report.failed_appends, 0);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_simulated_faults_keep_invariants() {
+    for seed in seeds() {
+        let report = simulate(config(seed, FaultRates::CHAOTIC)).await;
+        assert!(report.applied > 0, "seed {}: nothing applied", seed);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_simulation_replays_from_its_seed() {
+    let first = simulate(config(7, FaultRates::CHAOTIC)).await;
+    let second = simulate(config(7, FaultRates::CHAOTIC)).await;
+    assert_eq!(first, second);
+    assert!(first.crashes > 0 && first.dropped > 0 && first.failed_appends > 0);
+
+    let other = simulate(config(8, FaultRates::CHAOTIC)).await;
+    assert_ne!(first.trace, other.trace);
+}
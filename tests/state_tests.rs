@@ -0,0 +1,754 @@
+#![cfg(not(feature = "fixed-point"))]
+
+mod common;
+
+use common::assert_client_balance;
+use payments_engine::engine::PaymentsEngine;
+#[cfg(feature = "async")]
+use payments_engine::error::EngineError;
+use payments_engine::models::Account;
+#[cfg(feature = "async")]
+use payments_engine::models::{ClientId, Metadata, Transaction, TransactionType};
+#[cfg(feature = "async")]
+use payments_engine::persistence::{
+    decode_log_line, migrate_log, read_checkpointed_log, BackgroundPersistence, FilePersistence,
+    LogEntry, Migration, PersistenceBackend,
+};
+#[cfg(feature = "async")]
+use payments_engine::persistent_engine::{CheckpointPolicy, IntegrityCheck, PersistentEngine};
+use payments_engine::resume::ResumableRows;
+use payments_engine::state::EngineState;
+use payments_engine::{
+    process_transactions_with_engine, process_transactions_with_state, read_accounts,
+};
+use rust_decimal_macros::dec;
+use tempfile::TempDir;
+
+/// Run a CSV batch against a state file and return the output
+fn run_batch(csv_input: &str, state_path: &std::path::Path) -> String {
+    let mut output = Vec::new();
+    process_transactions_with_state(csv_input.as_bytes(), &mut output, state_path).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_missing_state_file_starts_fresh() {
+    let dir = TempDir::new().unwrap();
+    let state_path = dir.path().join("state.json");
+
+    let output = run_batch("type,client,tx,amount\ndeposit,1,1,10.0\n", &state_path);
+
+    assert_client_balance(&output, 1, "10.0", "0", "10.0", false);
+    assert!(state_path.exists());
+}
+
+#[test]
+fn test_balances_carry_over_between_runs() {
+    let dir = TempDir::new().unwrap();
+    let state_path = dir.path().join("state.json");
+
+    run_batch("type,client,tx,amount\ndeposit,1,1,100.0\n", &state_path);
+    let output = run_batch(
+        "type,client,tx,amount\nwithdrawal,1,2,40.0\ndeposit,2,3,5.0\n",
+        &state_path,
+    );
+
+    assert_client_balance(&output, 1, "60.0", "0", "60.0", false);
+    assert_client_balance(&output, 2, "5.0", "0", "5.0", false);
+}
+
+#[test]
+fn test_duplicate_from_previous_run_rejected() {
+    let dir = TempDir::new().unwrap();
+    let state_path = dir.path().join("state.json");
+
+    run_batch("type,client,tx,amount\ndeposit,1,1,100.0\n", &state_path);
+    // Re-running the same batch must not double-count the deposit
+    let output = run_batch("type,client,tx,amount\ndeposit,1,1,100.0\n", &state_path);
+
+    assert_client_balance(&output, 1, "100.0", "0", "100.0", false);
+}
+
+#[test]
+fn test_dispute_flags_carry_over_between_runs() {
+    let dir = TempDir::new().unwrap();
+    let state_path = dir.path().join("state.json");
+
+    run_batch(
+        "type,client,tx,amount\ndeposit,1,1,100.0\ndispute,1,1,\n",
+        &state_path,
+    );
+    let output = run_batch("type,client,tx,amount\nchargeback,1,1,\n", &state_path);
+
+    assert_client_balance(&output, 1, "0", "0", "0", true);
+}
+
+#[test]
+fn test_rerun_of_an_input_applies_nothing() {
+    let dir = TempDir::new().unwrap();
+    let state_path = dir.path().join("state.json");
+    let first = "type,client,tx,amount\ndeposit,1,1,100.0\ndispute,1,1,\n";
+
+    run_batch(first, &state_path);
+    run_batch("type,client,tx,amount\nresolve,1,1,\n", &state_path);
+    // The dispute must not be opened again
+    let output = run_batch(first, &state_path);
+
+    assert_client_balance(&output, 1, "100.0", "0", "100.0", false);
+}
+
+#[test]
+fn test_grown_input_resumes_where_the_last_run_stopped() {
+    let dir = TempDir::new().unwrap();
+    let state_path = dir.path().join("state.json");
+    let first = "type,client,tx,amount\ndeposit,1,1,100.0\ndispute,1,1,\n";
+
+    run_batch(first, &state_path);
+    run_batch("type,client,tx,amount\nresolve,1,1,\n", &state_path);
+    let output = run_batch(&format!("{}deposit,1,2,5.0\n", first), &state_path);
+
+    assert_client_balance(&output, 1, "105.0", "0", "105.0", false);
+}
+
+#[test]
+fn test_resumable_rows_pick_up_after_a_checkpoint() {
+    let input = "type,client,tx,amount\ndeposit,1,1,10\ndeposit,1,2,20\ndeposit,1,3,30\n";
+    let mut sources = Vec::new();
+
+    // A run that saved a checkpoint after its first transaction, then crashed
+    let mut rows = ResumableRows::new(input.as_bytes(), &sources).unwrap();
+    assert_eq!(rows.next().unwrap().tx, 1);
+    rows.record(&mut sources);
+
+    let rows = ResumableRows::new(input.as_bytes(), &sources).unwrap();
+    assert_eq!(rows.resume_from(), 37);
+    assert_eq!(rows.map(|tx| tx.tx).collect::<Vec<_>>(), vec![2, 3]);
+
+    // Other input is read from the start
+    let other = "type,client,tx,amount\ndeposit,2,4,10\n";
+    let rows = ResumableRows::new(other.as_bytes(), &sources).unwrap();
+    assert_eq!(rows.resume_from(), 0);
+    assert_eq!(rows.count(), 1);
+}
+
+#[test]
+fn test_long_input_resumes_from_a_snapshot_checkpoint() {
+    let dir = TempDir::new().unwrap();
+    let checkpoint = dir.path().join("checkpoint.bin");
+    let mut input = String::from("type,client,tx,amount\n");
+    for tx in 1..=1000 {
+        input.push_str(&format!("deposit,{},{},1.0\n", tx % 7, tx));
+    }
+    input.push_str("dispute,3,500,\nresolve,3,500,\n");
+
+    // A run that checkpointed after 600 transactions, well past the
+    // fingerprinted start, then crashed
+    let mut engine = PaymentsEngine::new();
+    let mut sources = Vec::new();
+    let mut rows = ResumableRows::new(input.as_bytes(), &sources).unwrap();
+    for tx in rows.by_ref().take(600) {
+        engine.process_transaction(tx);
+    }
+    rows.record(&mut sources);
+    let mut state = engine.to_state();
+    state.sources = sources;
+    state.save_snapshot(&checkpoint).unwrap();
+
+    let mut state = EngineState::load_snapshot(&checkpoint).unwrap();
+    let sources = std::mem::take(&mut state.sources);
+    let mut engine = PaymentsEngine::from_state(state);
+    let rows = ResumableRows::new(input.as_bytes(), &sources).unwrap();
+    assert!(rows.resume_from() > 4096);
+    let applied: Vec<_> = rows
+        .map(|tx| {
+            let id = tx.tx;
+            engine.process_transaction(tx);
+            id
+        })
+        .collect();
+    assert_eq!(applied.len(), 402);
+    assert_eq!(applied[0], 601);
+    assert_eq!(engine.get_account(3).unwrap().available, dec!(143));
+    assert_eq!(engine.get_account(3).unwrap().held, dec!(0));
+
+    assert!(EngineState::load_snapshot(&dir.path().join("missing.bin"))
+        .unwrap()
+        .sources
+        .is_empty());
+}
+
+#[test]
+fn test_state_round_trip() {
+    let mut engine = PaymentsEngine::new();
+    engine.process_transaction(common::make_deposit(1, 1, dec!(2.5)));
+    engine.process_transaction(common::make_dispute(1, 1));
+
+    let mut buffer = Vec::new();
+    engine.to_state().write_to(&mut buffer).unwrap();
+    let restored = PaymentsEngine::from_state(EngineState::read_from(buffer.as_slice()).unwrap());
+
+    let state = restored.to_state();
+    assert_eq!(state.accounts.len(), 1);
+    assert_eq!(state.accounts[0].held, dec!(2.5));
+    assert_eq!(state.processed_tx_ids, vec![1]);
+    assert!(state.disputable_transactions[0].disputed);
+}
+
+#[test]
+fn test_initial_accounts_seed_balances() {
+    let accounts_csv =
+        "client,available,held,total,locked\n1,50.0,10.0,60.0,false\n2,5.0,0.0,5.0,true\n";
+    let accounts = read_accounts(accounts_csv.as_bytes()).unwrap();
+    let engine = PaymentsEngine::with_initial_accounts(accounts);
+
+    let input = "type,client,tx,amount\ndeposit,1,1,25.0\ndeposit,2,2,1.0\n";
+    let mut output = Vec::new();
+    process_transactions_with_engine(engine, input.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert_client_balance(&output, 1, "75.0", "10.0", "85.0", false);
+    // Locked status is seeded too, so the deposit is ignored
+    assert_client_balance(&output, 2, "5.0", "0.0", "5.0", true);
+}
+
+#[test]
+fn test_read_accounts_rejects_malformed_rows() {
+    let accounts_csv = "client,available,held,total,locked\n1,abc,0,0,false\n";
+    assert!(read_accounts(accounts_csv.as_bytes()).is_err());
+}
+
+#[test]
+fn test_read_accounts_checks_total_when_given() {
+    let accounts_csv = "client,available,held,total,locked\n1,50.0,10.0,70.0,false\n";
+    let error = read_accounts(accounts_csv.as_bytes()).unwrap_err();
+    assert!(error.to_string().contains("total"), "{}", error);
+
+    // The total column is optional
+    let accounts_csv = "client,available,held,locked\n1,50.0,10.0,false\n";
+    let accounts = read_accounts(accounts_csv.as_bytes()).unwrap();
+    assert_eq!(accounts[0].total(), dec!(60.0));
+}
+
+#[test]
+fn test_account_round_trips_through_json() {
+    let account = Account::with_balances(7, dec!(1.2345), dec!(3), true);
+    let json = serde_json::to_string(&account).unwrap();
+    let parsed: Account = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        (
+            parsed.client_id,
+            parsed.available,
+            parsed.held,
+            parsed.locked
+        ),
+        (7, dec!(1.2345), dec!(3), true)
+    );
+    assert_eq!(parsed.version, 0);
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let mut engine = PaymentsEngine::new();
+    engine.process_transaction(common::make_deposit(1, 1, dec!(10.1234)));
+    engine.process_transaction(common::make_deposit(2, 2, dec!(3)));
+    engine.process_transaction(common::make_dispute(2, 2));
+
+    let snapshot = engine.export_snapshot().unwrap();
+    let mut restored = PaymentsEngine::import_snapshot(&snapshot).unwrap();
+
+    let account = restored.to_state().accounts[1].clone();
+    assert_eq!(account.held, dec!(3));
+
+    // Processed IDs survive, so a replayed deposit is still a duplicate
+    restored.process_transaction(common::make_deposit(1, 1, dec!(10.1234)));
+    let state = restored.to_state();
+    assert_eq!(state.accounts[0].available, dec!(10.1234));
+    assert!(state.disputable_transactions[1].disputed);
+}
+
+#[test]
+fn test_erased_client_carries_over_in_snapshot() {
+    let mut engine = PaymentsEngine::new();
+    engine.process_transaction(common::make_deposit(1, 1, dec!(10)));
+    engine.process_transaction(common::make_deposit(2, 2, dec!(3)));
+    engine.erase_client(1);
+
+    let state = engine.to_state();
+    assert_eq!(state.accounts.len(), 1);
+    assert!(state
+        .disputable_transactions
+        .iter()
+        .all(|stored_tx| stored_tx.client_id != 1));
+
+    let mut restored = PaymentsEngine::import_snapshot(&engine.export_snapshot().unwrap()).unwrap();
+    let tombstone = *restored.erased_clients().next().unwrap();
+    assert_eq!((tombstone.client, tombstone.available), (1, dec!(10)));
+    assert!(!restored
+        .process_transaction(common::make_deposit(1, 3, dec!(1)))
+        .is_applied());
+}
+
+#[test]
+fn test_snapshot_rejects_foreign_bytes() {
+    assert!(PaymentsEngine::import_snapshot(b"not a snapshot").is_err());
+    assert!(PaymentsEngine::import_snapshot(&[]).is_err());
+}
+
+/// Snapshots saved by versions 1 and 6: client 1 has 8.25 available and a
+/// disputed deposit of 1.0 (tx 4), client 2 was charged back 3.0. In version
+/// 6, client 3 was also erased with 4.0, client 5 was seeded with 7
+/// available and 2 held, and the snapshot records input offset 99.
+#[test]
+fn test_snapshots_of_earlier_versions_upgrade() {
+    for (fixture, version) in [("engine_v1", 1), ("engine_v6", 6)] {
+        let bytes = std::fs::read(format!("tests/fixtures/{}.snapshot", fixture)).unwrap();
+        assert!(EngineState::from_snapshot_bytes(&bytes).is_err());
+        assert_eq!(EngineState::snapshot_version(&bytes).unwrap(), version);
+
+        let (read_version, state) = EngineState::from_any_snapshot_bytes(&bytes).unwrap();
+        assert_eq!(read_version, version);
+        let upgraded = state.to_snapshot_bytes().unwrap();
+        let (upgraded_version, _) = EngineState::from_any_snapshot_bytes(&upgraded).unwrap();
+        assert_eq!(upgraded_version, payments_engine::state::SNAPSHOT_VERSION);
+
+        let mut engine = PaymentsEngine::import_snapshot(&upgraded).unwrap();
+        let balance = |engine: &PaymentsEngine, client| {
+            let account = engine.get_account(client).unwrap();
+            (account.available, account.held, account.locked)
+        };
+        assert_eq!(balance(&engine, 1), (dec!(8.25), dec!(1), false));
+        assert_eq!(balance(&engine, 2), (dec!(0), dec!(0), true));
+        let holds = engine.holds(1);
+        assert_eq!(
+            (holds.len(), holds[0].tx_id, holds[0].amount),
+            (1, 4, dec!(1))
+        );
+        // Processed IDs carry over, and the disputed deposit still resolves
+        assert!(!engine
+            .process_transaction(common::make_deposit(1, 3, dec!(1)))
+            .is_applied());
+        assert!(engine
+            .process_transaction(payments_engine::models::Transaction::resolve(1, 4))
+            .is_applied());
+        assert_eq!(balance(&engine, 1), (dec!(9.25), dec!(0), false));
+
+        if version == 6 {
+            assert_eq!(
+                state.disputable_transactions[0].timestamp,
+                Some(1_700_000_000)
+            );
+            assert_eq!(state.charged_back, [(2, dec!(3))]);
+            assert_eq!(state.sources[0].position, 99);
+            assert_eq!(engine.erased_clients().next().unwrap().available, dec!(4));
+            assert_eq!(balance(&engine, 5), (dec!(7), dec!(2), false));
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_file_wal_replays_and_drops_torn_tail() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+
+    let mut engine = PersistentEngine::new(FilePersistence::open(&wal_path).unwrap());
+    for (tx, amount) in [(1, dec!(5.0)), (2, dec!(2.5))] {
+        let deposit = Transaction {
+            tx_type: TransactionType::Deposit,
+            client: 1,
+            tx,
+            amount: Some(amount),
+            timestamp: None,
+            metadata: Metadata::new(),
+        };
+        engine.process_transaction(deposit).unwrap();
+    }
+    engine.flush().unwrap();
+    drop(engine);
+
+    // Simulate a crash halfway through writing a third entry
+    let mut contents = std::fs::read(&wal_path).unwrap();
+    contents.extend_from_slice(b"{\"type\":\"withdrawal\",\"cli");
+    std::fs::write(&wal_path, contents).unwrap();
+
+    let persistence = FilePersistence::open(&wal_path).unwrap();
+    assert_eq!(persistence.replay().unwrap().len(), 2);
+
+    let engine = PersistentEngine::recover(persistence).unwrap();
+    let account = engine.engine().get_account(1).unwrap();
+    assert_eq!(account.available, dec!(7.5));
+    assert_eq!(engine.engine().get_accounts().len(), 1);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_file_wal_keeps_metadata() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+
+    let deposit: Transaction = serde_json::from_str(
+        r#"{"type":"deposit","client":1,"tx":1,"amount":"5.0","channel":"pos","terminal":42}"#,
+    )
+    .unwrap();
+    let mut engine = PersistentEngine::new(FilePersistence::open(&wal_path).unwrap());
+    engine.process_transaction(deposit.clone()).unwrap();
+    engine.flush().unwrap();
+    drop(engine);
+
+    let replayed = FilePersistence::open(&wal_path).unwrap().replay().unwrap();
+    assert_eq!(replayed, vec![deposit]);
+    assert_eq!(replayed[0].metadata["terminal"], "42");
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_wal_redaction_erases_client_on_recovery() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+
+    let mut engine = PersistentEngine::new(BackgroundPersistence::open(&wal_path).unwrap());
+    engine
+        .process_transaction(common::make_deposit(1, 1, dec!(5)))
+        .unwrap();
+    engine
+        .process_transaction(common::make_deposit(2, 2, dec!(1)))
+        .unwrap();
+    assert!(engine.erase_client(1).unwrap().is_applied());
+    engine.flush().unwrap();
+    drop(engine);
+
+    let persistence = FilePersistence::open(&wal_path).unwrap();
+    assert_eq!(persistence.replay().unwrap().len(), 2);
+    let recovered = PersistentEngine::recover(persistence).unwrap();
+    assert!(recovered.engine().get_account(1).is_none());
+    assert_eq!(recovered.engine().erased_clients().count(), 1);
+    assert_eq!(recovered.engine().get_accounts().len(), 1);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_decode_log_line() {
+    assert_eq!(
+        decode_log_line(r#"{"type":"deposit","client":1,"tx":2,"amount":"1.5"}"#).unwrap(),
+        LogEntry::Transaction(common::make_deposit(1, 2, dec!(1.5)))
+    );
+    assert_eq!(
+        decode_log_line(r#"{"redact":7}"#).unwrap(),
+        LogEntry::Redaction(7)
+    );
+    assert_eq!(
+        decode_log_line(r#"{"digest":42}"#).unwrap(),
+        LogEntry::Digest(42)
+    );
+    // A line torn by a crash mid-write
+    assert!(decode_log_line(r#"{"type":"deposit","client":1,"tx""#).is_err());
+    assert!(decode_log_line(r#"{"redact":7,"extra":1}"#).is_err());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_background_wal_acknowledges_after_sync() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+
+    let mut engine = PersistentEngine::new(BackgroundPersistence::open(&wal_path).unwrap());
+    let commits: Vec<_> = (1..=50)
+        .map(|tx| {
+            let deposit = common::make_deposit(1, tx, dec!(1.5));
+            engine.process_transaction_pipelined(deposit).unwrap().1
+        })
+        .collect();
+
+    // Processed as soon as queued, durable once the handles resolve
+    assert_eq!(
+        engine.engine().get_account(1).unwrap().available,
+        dec!(75.0)
+    );
+    for commit in commits {
+        commit.wait_blocking().unwrap();
+    }
+    assert_eq!(engine.persistence_mut().replay().unwrap().len(), 50);
+
+    // Plain appends aren't waited on, but flush covers them
+    engine
+        .process_transaction(common::make_deposit(1, 51, dec!(1.5)))
+        .unwrap();
+    engine.flush().unwrap();
+    drop(engine);
+
+    let recovered = PersistentEngine::recover(FilePersistence::open(&wal_path).unwrap()).unwrap();
+    let account = recovered.engine().get_account(1).unwrap();
+    assert_eq!(account.available, dec!(76.5));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_migrate_log_upgrades_checkpoint_and_lines() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+    // A log checkpointed by version 6, with one line written in another
+    // field order and a torn last line
+    let mut snapshot = 1u64.to_le_bytes().to_vec();
+    snapshot.extend(std::fs::read("tests/fixtures/engine_v6.snapshot").unwrap());
+    std::fs::write(dir.path().join("wal.log.snapshot"), snapshot).unwrap();
+    std::fs::write(
+        &wal_path,
+        "{\"type\":\"deposit\",\"client\":1,\"tx\":10,\"amount\":\"1.75\"}\n\
+         {\"tx\":11,\"client\":6,\"amount\":\"5\",\"type\":\"deposit\"}\n\
+         {\"redact\":6}\n\
+         {\"type\":\"dep",
+    )
+    .unwrap();
+    assert!(read_checkpointed_log(&wal_path).is_err());
+
+    let migration = migrate_log(&wal_path).unwrap();
+    assert_eq!(
+        migration,
+        Migration {
+            logs: 1,
+            snapshots: 1,
+            lines: 1,
+        }
+    );
+    assert_eq!(
+        std::fs::read_to_string(&wal_path).unwrap(),
+        "{\"type\":\"deposit\",\"client\":1,\"tx\":10,\"amount\":\"1.75\"}\n\
+         {\"type\":\"deposit\",\"client\":6,\"tx\":11,\"amount\":\"5\"}\n\
+         {\"redact\":6}\n"
+    );
+    assert_eq!(file_names(dir.path()), ["wal.log", "wal.log.snapshot"]);
+
+    let recovered = PersistentEngine::recover(FilePersistence::open(&wal_path).unwrap()).unwrap();
+    let account = recovered.engine().get_account(1).unwrap();
+    assert_eq!((account.available, account.held), (dec!(10), dec!(1)));
+    assert_eq!(recovered.engine().erased_clients().count(), 2);
+
+    // Migrating again finds nothing to do
+    assert_eq!(
+        migrate_log(&wal_path).unwrap(),
+        Migration {
+            logs: 1,
+            ..Migration::default()
+        }
+    );
+}
+
+/// Names of the files in `dir`, sorted
+#[cfg(feature = "async")]
+fn file_names(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_checkpoint_by_entries_compacts_wal() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+
+    let mut engine = PersistentEngine::new(FilePersistence::open(&wal_path).unwrap());
+    engine.set_checkpoint_policy(CheckpointPolicy {
+        max_entries: Some(3),
+        ..CheckpointPolicy::default()
+    });
+    for tx in 1..=7 {
+        engine
+            .process_transaction(common::make_deposit(1, tx, dec!(2)))
+            .unwrap();
+    }
+    engine
+        .process_transaction(Transaction::dispute(1, 7))
+        .unwrap();
+    engine.flush().unwrap();
+    drop(engine);
+
+    // Checkpointed before the 4th and 7th entries; the sealed logs are gone
+    assert_eq!(file_names(dir.path()), ["wal.log", "wal.log.snapshot"]);
+    let persistence = FilePersistence::open(&wal_path).unwrap();
+    assert_eq!(persistence.replay().unwrap().len(), 2);
+
+    let recovered = PersistentEngine::recover(persistence).unwrap();
+    let account = recovered.engine().get_account(1).unwrap();
+    assert_eq!(account.available, dec!(12));
+    assert_eq!(account.held, dec!(2));
+    // Transactions in the snapshot still count as processed
+    let mut recovered = recovered;
+    assert!(!recovered
+        .process_transaction(common::make_deposit(1, 2, dec!(2)))
+        .unwrap()
+        .is_applied());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_checkpoint_recovers_from_crash_before_snapshot() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+
+    let mut engine = PersistentEngine::new(FilePersistence::open(&wal_path).unwrap());
+    engine
+        .process_transaction(common::make_deposit(1, 1, dec!(1)))
+        .unwrap();
+    engine.checkpoint().unwrap();
+    engine
+        .process_transaction(common::make_deposit(1, 2, dec!(2)))
+        .unwrap();
+    drop(engine);
+
+    // A crash after sealing the log as segment 2 but before the snapshot
+    // covering it was saved
+    std::fs::rename(&wal_path, dir.path().join("wal.log.2")).unwrap();
+    let mut engine = PersistentEngine::recover(FilePersistence::open(&wal_path).unwrap()).unwrap();
+    engine
+        .process_transaction(common::make_deposit(1, 3, dec!(4)))
+        .unwrap();
+    assert_eq!(engine.engine().get_account(1).unwrap().available, dec!(7));
+
+    // The next checkpoint covers the leftover segment too
+    engine.checkpoint().unwrap();
+    drop(engine);
+    assert_eq!(file_names(dir.path()), ["wal.log", "wal.log.snapshot"]);
+    let recovered = PersistentEngine::recover(FilePersistence::open(&wal_path).unwrap()).unwrap();
+    assert_eq!(
+        recovered.engine().get_account(1).unwrap().available,
+        dec!(7)
+    );
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_recovery_checks_logged_digests() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+
+    let mut engine = PersistentEngine::new(FilePersistence::open(&wal_path).unwrap());
+    engine
+        .process_transaction(common::make_deposit(1, 1, dec!(1)))
+        .unwrap();
+    engine.checkpoint().unwrap();
+    engine
+        .process_transaction(common::make_deposit(2, 2, dec!(2.5)))
+        .unwrap();
+    engine.record_digest().unwrap();
+    let digest = engine.engine().state_digest();
+    drop(engine);
+
+    let entries = FilePersistence::open(&wal_path)
+        .unwrap()
+        .replay_entries()
+        .unwrap();
+    assert_eq!(entries.len(), 3);
+    assert!(matches!(entries[0], LogEntry::Digest(_)));
+    assert_eq!(entries[2], LogEntry::Digest(digest));
+
+    let recovered = PersistentEngine::recover_checked(
+        FilePersistence::open(&wal_path).unwrap(),
+        IntegrityCheck::Refuse,
+    )
+    .unwrap();
+    assert_eq!(recovered.engine().state_digest(), digest);
+    drop(recovered);
+
+    // Edit the deposit logged after the checkpoint
+    let log = std::fs::read_to_string(&wal_path).unwrap();
+    std::fs::write(&wal_path, log.replace("2.5", "3.5")).unwrap();
+    let refused = PersistentEngine::recover_checked(
+        FilePersistence::open(&wal_path).unwrap(),
+        IntegrityCheck::Refuse,
+    );
+    assert!(matches!(
+        refused,
+        Err(EngineError::StateMismatch { expected, .. }) if expected == digest
+    ));
+    let warned = PersistentEngine::recover_checked(
+        FilePersistence::open(&wal_path).unwrap(),
+        IntegrityCheck::Warn,
+    )
+    .unwrap();
+    assert_eq!(warned.engine().get_account(2).unwrap().available, dec!(3.5));
+    // Unchecked recovery ignores digests
+    assert!(PersistentEngine::recover(FilePersistence::open(&wal_path).unwrap()).is_ok());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_background_wal_checkpoints_by_size_and_time() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+
+    let mut engine = PersistentEngine::new(BackgroundPersistence::open(&wal_path).unwrap());
+    engine.set_checkpoint_policy(CheckpointPolicy {
+        max_log_bytes: Some(200),
+        ..CheckpointPolicy::default()
+    });
+    let commits: Vec<_> = (1..=20)
+        .map(|tx| {
+            let deposit = common::make_deposit(tx as ClientId % 3, tx, dec!(1.5));
+            engine.process_transaction_pipelined(deposit).unwrap().1
+        })
+        .collect();
+    for commit in commits {
+        commit.wait_blocking().unwrap();
+    }
+    // Only the entry that reached the limit goes past it
+    assert!(engine.persistence_mut().log_size().unwrap() < 300);
+    assert!(engine.persistence_mut().replay().unwrap().len() < 20);
+
+    // A zero interval checkpoints before every entry
+    engine.set_checkpoint_policy(CheckpointPolicy {
+        max_interval: Some(std::time::Duration::ZERO),
+        ..CheckpointPolicy::default()
+    });
+    assert!(engine.erase_client(2).unwrap().is_applied());
+    engine
+        .process_transaction(common::make_deposit(1, 21, dec!(1.5)))
+        .unwrap();
+    engine.flush().unwrap();
+    // The checkpoint's digest, then the deposit
+    let entries = engine.persistence_mut().replay_entries().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(matches!(entries[0], LogEntry::Digest(_)));
+    drop(engine);
+
+    let recovered = PersistentEngine::recover(FilePersistence::open(&wal_path).unwrap()).unwrap();
+    assert_eq!(
+        recovered.engine().get_account(1).unwrap().available,
+        dec!(12)
+    );
+    assert!(recovered.engine().get_account(2).is_none());
+    assert_eq!(recovered.engine().erased_clients().count(), 1);
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[test]
+fn test_background_wal_over_io_uring() {
+    let dir = TempDir::new().unwrap();
+    let wal_path = dir.path().join("wal.log");
+
+    let persistence = BackgroundPersistence::open(&wal_path).unwrap();
+    if !persistence.uses_io_uring() {
+        eprintln!("io_uring unavailable, skipping");
+        return;
+    }
+
+    // More entries than fit in one batch
+    let mut engine = PersistentEngine::new(persistence);
+    let commits: Vec<_> = (1..=3000)
+        .map(|tx| {
+            let deposit = common::make_deposit(1, tx, dec!(0.5));
+            engine.process_transaction_pipelined(deposit).unwrap().1
+        })
+        .collect();
+    for commit in commits {
+        commit.wait_blocking().unwrap();
+    }
+    drop(engine);
+
+    let recovered = PersistentEngine::recover(FilePersistence::open(&wal_path).unwrap()).unwrap();
+    let account = recovered.engine().get_account(1).unwrap();
+    assert_eq!(account.available, dec!(1500.0));
+}
@@ -0,0 +1,210 @@
+#![cfg(not(feature = "fixed-point"))]
+
+mod common;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::{make_deposit, make_dispute, make_transaction};
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::models::{Account, TransactionType};
+use payments_engine::statement::{camt053, mt940, ofx, qif, StatementOptions};
+use rust_decimal_macros::dec;
+
+/// Client 1 starts from a seeded balance, then deposits, withdraws and disputes
+fn engine_with_activity() -> PaymentsEngine {
+    let mut engine = PaymentsEngine::with_initial_accounts([Account {
+        client_id: 1,
+        available: dec!(50),
+        held: dec!(0),
+        locked: false,
+        version: 0,
+    }])
+    .retain_history();
+
+    engine.process_transaction(make_deposit(1, 1, dec!(10)));
+    engine.process_transaction(make_deposit(2, 2, dec!(3)));
+    engine.process_transaction(make_transaction(
+        TransactionType::Withdrawal,
+        1,
+        3,
+        Some(dec!(5.25)),
+    ));
+    engine.process_transaction(make_dispute(1, 1));
+    engine
+}
+
+/// Options with a fixed creation time of 2000-02-29T12:34:56Z
+fn options() -> StatementOptions {
+    StatementOptions {
+        currency: "EUR".to_string(),
+        created_at: UNIX_EPOCH + Duration::from_secs(951_827_696),
+    }
+}
+
+#[test]
+fn test_statement_balances_and_bookings() {
+    let engine = engine_with_activity();
+    let statement = engine.statement(1).unwrap();
+
+    assert_eq!(statement.opening, dec!(50));
+    assert_eq!(statement.closing.total(), dec!(54.75));
+    assert_eq!(statement.closing.available, dec!(44.75));
+    assert_eq!(statement.closing.held, dec!(10));
+    assert_eq!(statement.entries.len(), 3);
+
+    // The dispute moves funds into held but books nothing
+    let bookings: Vec<_> = statement
+        .bookings()
+        .map(|(entry, amount)| (entry.tx_id, amount))
+        .collect();
+    assert_eq!(bookings, vec![(1, dec!(10)), (3, dec!(-5.25))]);
+}
+
+#[test]
+fn test_statements_need_history() {
+    let engine = engine_with_activity();
+    let clients: Vec<_> = engine.statements().iter().map(|s| s.client_id).collect();
+    assert_eq!(clients, vec![1, 2]);
+    assert!(engine.statement(3).is_none());
+
+    let mut engine = PaymentsEngine::new();
+    engine.process_transaction(make_deposit(1, 1, dec!(10)));
+    assert!(engine.statement(1).is_none());
+    assert!(engine.statements().is_empty());
+}
+
+#[test]
+fn test_camt053_statement() {
+    let engine = engine_with_activity();
+    let mut output = Vec::new();
+    camt053::write(&engine.statements(), &options(), &mut output).unwrap();
+    let xml = String::from_utf8(output).unwrap();
+
+    assert!(xml.contains(r#"<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">"#));
+    assert!(xml.contains("<CreDtTm>2000-02-29T12:34:56Z</CreDtTm>"));
+    assert_eq!(xml.matches("<Stmt>").count(), 2);
+    assert!(xml.contains("<Id><Othr><Id>1</Id></Othr></Id>"));
+
+    // Opening and closing booked balances, then closing available
+    let balances = [("OPBD", "50"), ("CLBD", "54.75"), ("CLAV", "44.75")];
+    for (code, amount) in balances {
+        assert!(xml.contains(&format!(
+            "<Tp><CdOrPrtry><Cd>{}</Cd></CdOrPrtry></Tp>\n        <Amt Ccy=\"EUR\">{}</Amt>",
+            code, amount
+        )));
+    }
+    assert!(xml.contains("<TtlDbtNtries><NbOfNtries>1</NbOfNtries><Sum>5.25</Sum></TtlDbtNtries>"));
+
+    // The withdrawal is a debit entry referencing its transaction
+    let withdrawal = xml
+        .split("<Ntry>")
+        .find(|entry| entry.contains("<AcctSvcrRef>3</AcctSvcrRef>"))
+        .unwrap();
+    assert!(withdrawal.contains(r#"<Amt Ccy="EUR">5.25</Amt>"#));
+    assert!(withdrawal.contains("<CdtDbtInd>DBIT</CdtDbtInd>"));
+    assert!(withdrawal.contains("<BookgDt><Dt>2000-02-29</Dt></BookgDt>"));
+    assert!(withdrawal.contains("<Cd>WITHDRAWAL</Cd>"));
+    assert!(!xml.contains("DISPUTE"));
+}
+
+#[test]
+fn test_ofx_statement() {
+    let engine = engine_with_activity();
+    let mut output = Vec::new();
+    ofx::write(&engine.statements(), &options(), &mut output).unwrap();
+    let ofx = String::from_utf8(output).unwrap();
+
+    assert!(ofx.contains(r#"<?OFX OFXHEADER="200" VERSION="220""#));
+    assert!(ofx.contains("<DTSERVER>20000229123456.000[0:GMT]</DTSERVER>"));
+    assert_eq!(ofx.matches("<STMTTRNRS>").count(), 2);
+    assert!(ofx.contains("<CURDEF>EUR</CURDEF>"));
+    assert!(ofx.contains("<ACCTID>1</ACCTID>"));
+
+    let withdrawal = ofx
+        .split("<STMTTRN>")
+        .find(|entry| entry.contains("<FITID>3</FITID>"))
+        .unwrap();
+    assert!(withdrawal.contains("<TRNTYPE>DEBIT</TRNTYPE>"));
+    assert!(withdrawal.contains("<TRNAMT>-5.25</TRNAMT>"));
+
+    let client_1 = ofx.split("<STMTTRNRS>").nth(1).unwrap();
+    assert!(client_1.contains("<LEDGERBAL>\n          <BALAMT>54.75</BALAMT>"));
+    assert!(client_1.contains("<AVAILBAL>\n          <BALAMT>44.75</BALAMT>"));
+}
+
+#[test]
+fn test_ofx_chargeback_has_its_own_fitid() {
+    let mut engine = PaymentsEngine::new().retain_history();
+    engine.process_transaction(make_deposit(1, 1, dec!(10)));
+    engine.process_transaction(make_dispute(1, 1));
+    engine.process_transaction(make_transaction(TransactionType::Chargeback, 1, 1, None));
+
+    let mut output = Vec::new();
+    ofx::write(&engine.statements(), &options(), &mut output).unwrap();
+    let ofx = String::from_utf8(output).unwrap();
+
+    assert!(ofx.contains("<FITID>1</FITID>"));
+    assert!(ofx.contains("<FITID>1-chargeback</FITID>"));
+    assert!(ofx.contains("<TRNAMT>-10</TRNAMT>"));
+}
+
+#[test]
+fn test_qif_statement() {
+    let engine = engine_with_activity();
+    let mut output = Vec::new();
+    qif::write(&engine.statements(), &options(), &mut output).unwrap();
+    let qif = String::from_utf8(output).unwrap();
+
+    let expected_client_1 = "\
+!Account
+NClient 1
+TBank
+^
+!Type:Bank
+D02/29/2000
+T50
+POpening Balance
+L[Client 1]
+^
+D02/29/2000
+T10
+N1
+PDeposit
+^
+D02/29/2000
+T-5.25
+N3
+PWithdrawal
+^
+";
+    assert!(qif.starts_with(expected_client_1));
+
+    // Client 2 starts from zero, so has no opening balance record
+    let client_2 = &qif[expected_client_1.len()..];
+    assert!(client_2.starts_with("!Account\nNClient 2\n"));
+    assert!(!client_2.contains("Opening Balance"));
+}
+
+#[test]
+fn test_mt940_statement() {
+    let engine = engine_with_activity();
+    let mut output = Vec::new();
+    mt940::write(&engine.statements(), &options(), &mut output).unwrap();
+    let mt940 = String::from_utf8(output).unwrap();
+
+    let expected_client_1 = "\
+:20:951827696-1\r
+:25:1\r
+:28C:1/1\r
+:60F:C000229EUR50,\r
+:61:000229C10,NMSC1\r
+:86:Deposit 1\r
+:61:000229D5,25NMSC3\r
+:86:Withdrawal 3\r
+:62F:C000229EUR54,75\r
+:64:C000229EUR44,75\r
+-\r
+";
+    assert!(mt940.starts_with(expected_client_1));
+    assert!(mt940[expected_client_1.len()..].starts_with(":20:951827696-2\r\n"));
+}
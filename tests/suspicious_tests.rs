@@ -0,0 +1,211 @@
+#![cfg(not(feature = "fixed-point"))]
+
+mod common;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use common::{make_deposit, make_dispute, make_transaction};
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::models::{ClientId, TransactionType};
+use payments_engine::outcome::RejectReason;
+use payments_engine::read_transactions;
+use payments_engine::suspicious::{self, Pattern, ReportRules, SuspiciousActivity};
+use rust_decimal_macros::dec;
+
+/// Engine with history that has applied `csv`
+fn engine_with(csv: &str) -> PaymentsEngine {
+    let mut engine = PaymentsEngine::new().retain_history();
+    for transaction in read_transactions(csv.as_bytes()) {
+        engine.process_transaction(transaction);
+    }
+    engine
+}
+
+fn patterns(report: &[SuspiciousActivity]) -> Vec<(ClientId, Pattern)> {
+    report
+        .iter()
+        .map(|activity| (activity.client_id, activity.pattern))
+        .collect()
+}
+
+#[test]
+fn test_chargeback_cycles_within_window() {
+    let engine = engine_with(
+        "type,client,tx,amount,timestamp\n\
+         deposit,1,1,100,1000\n\
+         deposit,1,2,50,1000\n\
+         dispute,1,1,,2000\n\
+         chargeback,1,1,,3000\n\
+         dispute,1,2,,5000\n\
+         chargeback,1,2,,900000\n",
+    );
+
+    let report = engine.suspicious_activity(&ReportRules::default());
+    assert_eq!(patterns(&report), [(1, Pattern::ChargebackCycle)]);
+    // The second deposit took longer than a week to be charged back
+    assert_eq!(report[0].transactions, [1]);
+    assert_eq!(report[0].amount, dec!(100));
+    assert_eq!((report[0].first_seen, report[0].last_seen), (1000, 3000));
+
+    let rules = ReportRules {
+        cycle_window: 1_000_000,
+        ..ReportRules::default()
+    };
+    let report = engine.suspicious_activity(&rules);
+    assert_eq!(report[0].transactions, [1, 2]);
+    assert_eq!(report[0].last_seen, 900_000);
+}
+
+#[test]
+fn test_failed_withdrawals_in_bursts() {
+    let engine = engine_with(
+        "type,client,tx,amount,timestamp\n\
+         deposit,1,1,10,0\n\
+         withdrawal,1,2,50,100\n\
+         withdrawal,1,3,40,200\n\
+         withdrawal,1,4,30,10000\n\
+         withdrawal,1,5,20,10100\n\
+         withdrawal,1,6,15,10200\n\
+         withdrawal,1,7,5,10300\n\
+         withdrawal,2,8,5,10300\n\
+         withdrawal,2,9,5,10301\n\
+         withdrawal,2,10,5,10302\n",
+    );
+
+    assert_eq!(engine.rejected_withdrawals(1).len(), 5);
+    assert_eq!(
+        engine.rejected_withdrawals(1)[0].reason,
+        RejectReason::InsufficientFunds
+    );
+
+    let report = engine.suspicious_activity(&ReportRules::default());
+    assert_eq!(
+        patterns(&report),
+        [
+            (1, Pattern::FailedWithdrawals),
+            (2, Pattern::FailedWithdrawals)
+        ]
+    );
+    // The first two were too far from the rest; the applied one doesn't count
+    assert_eq!(report[0].transactions, [4, 5, 6]);
+    assert_eq!(report[0].amount, dec!(65));
+    assert!(report[0].narrative.contains("insufficient available funds"));
+    assert!(report[1].narrative.contains("account not found"));
+}
+
+#[test]
+fn test_resubmitted_withdrawals_are_not_failures() {
+    let mut engine = PaymentsEngine::new().retain_history();
+    engine.process_transaction(make_deposit(1, 1, dec!(10)));
+    let withdrawal = make_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(50)));
+    for _ in 0..3 {
+        engine.process_transaction(withdrawal.clone());
+    }
+
+    assert_eq!(engine.rejected_withdrawals(1).len(), 1);
+    assert!(engine
+        .suspicious_activity(&ReportRules::default())
+        .is_empty());
+}
+
+#[test]
+fn test_structuring_below_threshold() {
+    let engine = engine_with(
+        "type,client,tx,amount,timestamp\n\
+         deposit,1,1,9500,0\n\
+         deposit,1,2,9000,3600\n\
+         deposit,1,3,500,3700\n\
+         deposit,2,4,9500,0\n\
+         deposit,2,5,9500,200000\n\
+         deposit,3,6,12000,0\n\
+         deposit,3,7,9999,10\n",
+    );
+
+    let report = engine.suspicious_activity(&ReportRules::default());
+    // Client 2's deposits are days apart, client 3 only made one under the
+    // threshold
+    assert_eq!(patterns(&report), [(1, Pattern::Structuring)]);
+    assert_eq!(report[0].transactions, [1, 2]);
+    assert_eq!(report[0].amount, dec!(18500));
+}
+
+#[test]
+fn test_untimed_inputs_use_the_logical_clock() {
+    let mut engine = PaymentsEngine::new().retain_history();
+    engine.process_transaction(make_deposit(1, 1, dec!(5))); // t=1
+    for tx in 2..=10 {
+        engine.process_transaction(make_deposit(2, tx, dec!(1)));
+    }
+    engine.process_transaction(make_dispute(1, 1)); // t=11
+    engine.process_transaction(make_transaction(TransactionType::Chargeback, 1, 1, None)); // t=12
+
+    let rules = ReportRules {
+        cycle_window: 10,
+        ..ReportRules::default()
+    };
+    assert!(engine.suspicious_activity(&rules).is_empty());
+    let rules = ReportRules {
+        cycle_window: 11,
+        ..ReportRules::default()
+    };
+    let report = engine.suspicious_activity(&rules);
+    assert_eq!((report[0].first_seen, report[0].last_seen), (1, 12));
+}
+
+#[test]
+fn test_report_needs_history_and_forgets_erased_clients() {
+    let csv = "type,client,tx,amount\n\
+               deposit,1,1,1\n\
+               withdrawal,1,4,5\n\
+               withdrawal,1,2,5\n\
+               withdrawal,1,3,5\n";
+    let mut engine = PaymentsEngine::new();
+    for transaction in read_transactions(csv.as_bytes()) {
+        engine.process_transaction(transaction);
+    }
+    assert!(engine
+        .suspicious_activity(&ReportRules::default())
+        .is_empty());
+
+    let mut engine = engine_with(csv);
+    assert_eq!(engine.suspicious_activity(&ReportRules::default()).len(), 1);
+    engine.erase_client(1);
+    assert!(engine.rejected_withdrawals(1).is_empty());
+    assert!(engine
+        .suspicious_activity(&ReportRules::default())
+        .is_empty());
+}
+
+#[test]
+fn test_report_csv_and_json() {
+    let engine = engine_with(
+        "type,client,tx,amount\n\
+         deposit,7,1,9000\n\
+         deposit,7,2,9000\n",
+    );
+    let rules = ReportRules::default();
+    let report = engine.suspicious_activity(&rules);
+
+    let mut csv = Vec::new();
+    suspicious::write_csv(&report, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "client,pattern,first_seen,last_seen,transactions,amount,narrative"
+    );
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("7,structuring,1,2,1 2,18000,"));
+
+    let mut json = Vec::new();
+    let generated_at = UNIX_EPOCH + Duration::from_secs(1_709_251_200);
+    suspicious::write_json(&report, &rules, generated_at, &mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["generated_at"], "2024-03-01T00:00:00Z");
+    assert_eq!(json["rules"]["failed_withdrawals"], 3);
+    assert_eq!(json["activity"][0]["client"], 7);
+    assert_eq!(json["activity"][0]["pattern"], "structuring");
+    assert_eq!(json["activity"][0]["transactions"][1], 2);
+}
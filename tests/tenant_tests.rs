@@ -0,0 +1,80 @@
+#![cfg(not(feature = "fixed-point"))]
+
+mod common;
+
+use common::{make_deposit, make_dispute};
+use payments_engine::outcome::{Outcome, RejectReason};
+use payments_engine::tenant::{
+    apply_tenant_transactions, read_tenant_transactions, write_tenant_accounts, MultiTenantEngine,
+};
+use rust_decimal_macros::dec;
+
+#[test]
+fn test_tenants_have_separate_accounts_and_tx_ids() {
+    let mut engine = MultiTenantEngine::new();
+
+    assert!(engine
+        .process_transaction("acme", make_deposit(1, 1, dec!(10)))
+        .is_applied());
+    // Same client and transaction ID in another tenant is not a duplicate
+    assert!(engine
+        .process_transaction("beta", make_deposit(1, 1, dec!(4)))
+        .is_applied());
+    assert_eq!(
+        engine.process_transaction("acme", make_deposit(1, 1, dec!(10))),
+        Outcome::Rejected(RejectReason::DuplicateTransaction)
+    );
+
+    // A dispute only sees its own tenant's deposit
+    engine.process_transaction("beta", make_dispute(1, 1));
+    assert_eq!(engine.get_account("acme", 1).unwrap().held, dec!(0));
+    assert_eq!(engine.get_account("beta", 1).unwrap().held, dec!(4));
+    assert!(engine.get_account("gamma", 1).is_none());
+    assert_eq!(engine.tenants().collect::<Vec<_>>(), ["acme", "beta"]);
+}
+
+#[test]
+fn test_read_tenant_transactions_defaults_missing_tenant() {
+    let input = "type,client,tx,amount,tenant\n\
+                 deposit,1,1,1.0,acme\n\
+                 deposit,1,2,2.0,\n\
+                 bogus,1,3,1.0,acme\n";
+    let tenants: Vec<String> = read_tenant_transactions(input.as_bytes())
+        .map(|(tenant, _)| tenant)
+        .collect();
+    assert_eq!(tenants, ["acme", ""]);
+
+    // Without a tenant column everything goes to the default tenant
+    let input = "type,client,tx,amount\ndeposit,1,1,1.0\n";
+    let (tenant, transaction) = read_tenant_transactions(input.as_bytes()).next().unwrap();
+    assert_eq!(tenant, "");
+    assert_eq!(transaction, make_deposit(1, 1, dec!(1.0)));
+}
+
+#[test]
+fn test_write_tenant_accounts_sorts_and_filters() {
+    let input = "tenant,type,client,tx,amount\n\
+                 beta,deposit,2,1,5.0\n\
+                 acme,deposit,3,1,1.5\n\
+                 beta,deposit,1,2,2.0\n\
+                 acme,withdrawal,3,2,0.5\n";
+    let mut engine = MultiTenantEngine::new();
+    apply_tenant_transactions(&mut engine, input.as_bytes());
+
+    let mut output = Vec::new();
+    write_tenant_accounts(&engine, &[], &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "tenant,client,available,held,total,locked\n\
+         acme,3,1.0,0,1.0,false\n\
+         beta,1,2.0,0,2.0,false\n\
+         beta,2,5.0,0,5.0,false\n"
+    );
+
+    let mut output = Vec::new();
+    write_tenant_accounts(&engine, &["acme".to_string()], &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "tenant,client,available,held,total,locked\nacme,3,1.0,0,1.0,false\n"
+    );
+}
@@ -0,0 +1,80 @@
+#![cfg(feature = "testing")]
+
+use std::collections::HashSet;
+
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::models::TransactionType;
+use payments_engine::outcome::{Outcome, RejectReason};
+use payments_engine::testing::{generate, AdversarialSequence, ValidSequence};
+
+const SEEDS: u64 = 200;
+
+#[test]
+fn test_generated_sequences_are_reproducible() {
+    let first: AdversarialSequence = generate(3);
+    assert_eq!(first, generate(3));
+    assert_ne!(first, generate(4));
+}
+
+#[test]
+fn test_valid_sequences_are_never_rejected_as_malformed() {
+    for seed in 0..SEEDS {
+        let ValidSequence(transactions) = generate(seed);
+        let mut engine = PaymentsEngine::new();
+        let mut ids = HashSet::new();
+        for tx in transactions {
+            if matches!(
+                tx.tx_type,
+                TransactionType::Deposit | TransactionType::Withdrawal
+            ) {
+                assert!(ids.insert(tx.tx), "seed {}: reused tx {}", seed, tx.tx);
+            }
+            let outcome = engine.process_transaction(tx.clone());
+            assert!(
+                !matches!(
+                    outcome,
+                    Outcome::Rejected(
+                        RejectReason::DuplicateTransaction
+                            | RejectReason::ClientMismatch
+                            | RejectReason::MissingAmount
+                            | RejectReason::NonPositiveAmount
+                    )
+                ),
+                "seed {}: {:?} was {}",
+                seed,
+                tx,
+                outcome
+            );
+        }
+        assert!(engine.check_invariants().is_ok(), "seed {}", seed);
+    }
+}
+
+#[test]
+fn test_adversarial_sequences_keep_invariants() {
+    let mut rejections = HashSet::new();
+    for seed in 0..SEEDS {
+        let AdversarialSequence(transactions) = generate(seed);
+        let mut engine = PaymentsEngine::new();
+        for tx in transactions {
+            if let Outcome::Rejected(reason) = engine.process_transaction(tx) {
+                rejections.insert(reason.to_string());
+            }
+        }
+        let report = engine.check_invariants();
+        assert!(report.is_ok(), "seed {}: {}", seed, report);
+    }
+
+    // Every kind of attack shows up somewhere
+    for reason in [
+        RejectReason::DuplicateTransaction,
+        RejectReason::ClientMismatch,
+        RejectReason::NotDisputed,
+        RejectReason::AlreadyDisputed,
+        RejectReason::TransactionNotFound,
+        RejectReason::MissingAmount,
+        RejectReason::NonPositiveAmount,
+    ] {
+        assert!(rejections.contains(&reason.to_string()), "no {}", reason);
+    }
+}
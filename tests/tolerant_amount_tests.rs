@@ -0,0 +1,76 @@
+#![cfg(feature = "tolerant-amounts")]
+
+use payments_engine::amount::{normalize, Amount};
+use payments_engine::models::Transaction;
+use payments_engine::{parse_csv_row, process_transactions, read_transactions_checked};
+
+fn amount(text: &str) -> Amount {
+    text.parse().unwrap()
+}
+
+#[test]
+fn test_normalize_rewrites_separators_and_exponents() {
+    let cases = [
+        ("1,234", "1234"),
+        ("+12,345.678", "12345.678"),
+        ("-999,999,999.5", "-999999999.5"),
+        ("1.5e3", "1500"),
+        ("1.5E+3", "1500"),
+        ("+2.5e-3", "0.0025"),
+        ("125e-1", "12.5"),
+        (".5e1", "5"),
+        ("1,000e-3", "1.000"),
+    ];
+    for (text, plain) in cases {
+        assert_eq!(normalize(text).as_deref(), Some(plain), "{text}");
+    }
+}
+
+#[test]
+fn test_normalize_leaves_plain_and_malformed_amounts() {
+    for text in [
+        "12.5", "+12.5", "-0.0001", "1,00", "1,0000", ",100", "1,,000", "1.000,5", "1e", "e5",
+        "1e2.5", "1e29", "1,5e", "abc", "1_000",
+    ] {
+        assert_eq!(normalize(text), None, "{text}");
+    }
+}
+
+#[test]
+fn test_csv_accepts_tolerant_amounts() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,\"1,250.50\"\n\
+                 deposit,1,2,\" +2.5e1 \"\n\
+                 withdrawal,1,3,1e-2\n\
+                 deposit,2,4,\"12,34\"\n\
+                 deposit,2,5,1e99\n";
+    let mut output = Vec::new();
+    process_transactions(input.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let balances: Vec<&str> = output.lines().skip(1).collect();
+    assert_eq!(balances.len(), 1, "{output}");
+    assert!(balances[0].starts_with("1,1275.49,"), "{output}");
+
+    let errors: Vec<_> = read_transactions_checked(input.as_bytes())
+        .filter_map(Result::err)
+        .map(|error| (error.line, error.value))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            (5, Some("12,34".to_string())),
+            (6, Some("1e99".to_string()))
+        ]
+    );
+}
+
+#[test]
+fn test_tolerant_amounts_in_rows_and_json() {
+    assert_eq!(
+        parse_csv_row("deposit,3,1,\"5,000\"").unwrap().amount,
+        Some(amount("5000"))
+    );
+    let transaction: Transaction =
+        serde_json::from_str(r#"{"type":"deposit","client":3,"tx":2,"amount":" 7.5e2 "}"#).unwrap();
+    assert_eq!(transaction.amount, Some(amount("750")));
+}
@@ -1,3 +1,5 @@
+#![cfg(not(feature = "fixed-point"))]
+
 use payments_engine::models::Account;
 use rust_decimal_macros::dec;
 
@@ -1,12 +1,21 @@
-use payments_engine::engine::PaymentsEngine;
-use payments_engine::models::{Transaction, TransactionType};
+#![cfg(not(feature = "fixed-point"))]
+
+use std::sync::{Arc, Mutex};
+
+use payments_engine::archive::{ArchiveBackend, FileArchive};
+use payments_engine::config::{EngineConfig, RetentionPolicy};
+use payments_engine::engine::{AccountOrdering, FundsSummary, PaymentsEngine};
+use payments_engine::events::AccountEvent;
+use payments_engine::hooks::Hooks;
+use payments_engine::models::{ClientId, Metadata, Transaction, TransactionType, TxId};
+use payments_engine::outcome::{Outcome, RejectReason};
 use rust_decimal_macros::dec;
 
 // Helper to create a transaction
 fn make_transaction(
     tx_type: TransactionType,
-    client: u16,
-    tx: u32,
+    client: ClientId,
+    tx: TxId,
     amount: Option<rust_decimal::Decimal>,
 ) -> Transaction {
     Transaction {
@@ -14,6 +23,8 @@ fn make_transaction(
         client,
         tx,
         amount,
+        timestamp: None,
+        metadata: Metadata::new(),
     }
 }
 
@@ -75,6 +86,29 @@ fn test_duplicate_withdrawal_rejected() {
     assert_eq!(accounts[0].available, dec!(150));
 }
 
+#[test]
+fn test_duplicates_detected_across_full_id_range() {
+    let mut engine = PaymentsEngine::new();
+
+    for tx in [0, 1, 65_535, 65_536, TxId::MAX] {
+        let deposit = make_transaction(TransactionType::Deposit, 1, tx, Some(dec!(1)));
+        assert!(engine.process_transaction(deposit).is_applied());
+    }
+    for tx in [0, 65_536, TxId::MAX] {
+        let duplicate = make_transaction(TransactionType::Withdrawal, 1, tx, Some(dec!(1)));
+        assert_eq!(
+            engine.process_transaction(duplicate),
+            Outcome::Rejected(RejectReason::DuplicateTransaction)
+        );
+    }
+
+    let restored = PaymentsEngine::from_state(engine.to_state());
+    assert_eq!(
+        restored.to_state().processed_tx_ids,
+        vec![0, 1, 65_535, 65_536, TxId::MAX]
+    );
+}
+
 #[test]
 fn test_negative_deposit_rejected() {
     let mut engine = PaymentsEngine::new();
@@ -414,3 +448,953 @@ fn test_different_transaction_ids_across_clients() {
     assert_eq!(client1.available, dec!(100));
     assert_eq!(client2.available, dec!(200));
 }
+
+#[test]
+fn test_get_account_lookup() {
+    let mut engine = PaymentsEngine::new();
+
+    engine.process_transaction(make_transaction(
+        TransactionType::Deposit,
+        1,
+        1,
+        Some(dec!(10)),
+    ));
+    engine.process_transaction(make_transaction(
+        TransactionType::Deposit,
+        2,
+        2,
+        Some(dec!(20)),
+    ));
+
+    assert_eq!(engine.get_account(2).unwrap().available, dec!(20));
+    assert!(engine.get_account(3).is_none());
+}
+
+#[test]
+fn test_accounts_iter_visits_every_account() {
+    let mut engine = PaymentsEngine::new();
+
+    for client in 1..=3 {
+        engine.process_transaction(make_transaction(
+            TransactionType::Deposit,
+            client,
+            client as TxId,
+            Some(dec!(1)),
+        ));
+    }
+
+    let mut clients: Vec<ClientId> = engine.accounts_iter().map(|a| a.client_id).collect();
+    clients.sort();
+    assert_eq!(clients, vec![1, 2, 3]);
+}
+
+#[test]
+fn test_into_sorted_accounts_for_both_orderings() {
+    for ordering in [AccountOrdering::Unordered, AccountOrdering::ByClientId] {
+        let mut engine = PaymentsEngine::with_account_ordering(ordering);
+
+        for (tx, client) in [5, 1, 3, 2, 4].into_iter().enumerate() {
+            engine.process_transaction(make_transaction(
+                TransactionType::Deposit,
+                client,
+                tx as TxId,
+                Some(dec!(1)),
+            ));
+        }
+
+        let clients: Vec<ClientId> = engine.into_sorted_accounts().map(|a| a.client_id).collect();
+        assert_eq!(clients, vec![1, 2, 3, 4, 5], "ordering {:?}", ordering);
+    }
+}
+
+#[test]
+fn test_ordered_engine_lookup() {
+    let mut engine = PaymentsEngine::with_account_ordering(AccountOrdering::ByClientId);
+
+    engine.process_transaction(make_transaction(
+        TransactionType::Deposit,
+        7,
+        1,
+        Some(dec!(3)),
+    ));
+    engine.process_transaction(make_transaction(
+        TransactionType::Withdrawal,
+        7,
+        2,
+        Some(dec!(1)),
+    ));
+
+    assert_eq!(engine.get_account(7).unwrap().available, dec!(2));
+    assert_eq!(engine.into_accounts_iter().count(), 1);
+}
+
+#[test]
+fn test_outcome_reports_rejection_reasons() {
+    let mut engine = PaymentsEngine::new();
+
+    let deposit = make_transaction(TransactionType::Deposit, 1, 1, Some(dec!(10)));
+    assert_eq!(
+        engine.process_transaction(deposit.clone()),
+        Outcome::Applied
+    );
+    assert_eq!(
+        engine.process_transaction(deposit),
+        Outcome::Rejected(RejectReason::DuplicateTransaction)
+    );
+    assert_eq!(
+        engine.process_transaction(make_transaction(TransactionType::Deposit, 1, 2, None)),
+        Outcome::Rejected(RejectReason::MissingAmount)
+    );
+    assert_eq!(
+        engine.process_transaction(make_transaction(
+            TransactionType::Withdrawal,
+            1,
+            3,
+            Some(dec!(-1))
+        )),
+        Outcome::Rejected(RejectReason::NonPositiveAmount)
+    );
+    assert_eq!(
+        engine.process_transaction(make_transaction(
+            TransactionType::Withdrawal,
+            1,
+            4,
+            Some(dec!(50))
+        )),
+        Outcome::Rejected(RejectReason::InsufficientFunds)
+    );
+    assert_eq!(
+        engine.process_transaction(make_transaction(
+            TransactionType::Withdrawal,
+            9,
+            5,
+            Some(dec!(1))
+        )),
+        Outcome::Rejected(RejectReason::AccountNotFound)
+    );
+    assert_eq!(
+        engine.process_transaction(make_transaction(TransactionType::Dispute, 2, 1, None)),
+        Outcome::Rejected(RejectReason::ClientMismatch)
+    );
+    assert_eq!(
+        engine.process_transaction(make_transaction(TransactionType::Resolve, 1, 1, None)),
+        Outcome::Rejected(RejectReason::NotDisputed)
+    );
+    assert_eq!(
+        engine.process_transaction(make_transaction(TransactionType::Dispute, 1, 99, None)),
+        Outcome::Rejected(RejectReason::TransactionNotFound)
+    );
+}
+
+#[test]
+fn test_outcome_after_chargeback_lock() {
+    let mut engine = PaymentsEngine::new();
+
+    engine.process_transaction(make_transaction(
+        TransactionType::Deposit,
+        1,
+        1,
+        Some(dec!(10)),
+    ));
+    engine.process_transaction(make_transaction(TransactionType::Dispute, 1, 1, None));
+    assert_eq!(
+        engine.process_transaction(make_transaction(TransactionType::Dispute, 1, 1, None)),
+        Outcome::Rejected(RejectReason::AlreadyDisputed)
+    );
+    assert!(engine
+        .process_transaction(make_transaction(TransactionType::Chargeback, 1, 1, None))
+        .is_applied());
+
+    let outcome = engine.process_transaction(make_transaction(
+        TransactionType::Deposit,
+        1,
+        2,
+        Some(dec!(1)),
+    ));
+    assert_eq!(outcome, Outcome::Rejected(RejectReason::AccountLocked));
+    assert_eq!(outcome.to_string(), "rejected: account locked");
+}
+
+#[test]
+fn test_amount_limits_and_decimal_places() {
+    let mut engine = PaymentsEngine::with_config(EngineConfig {
+        max_amount: Some(dec!(100)),
+        max_decimal_places: Some(2),
+        ..EngineConfig::default()
+    });
+
+    let deposit = |tx, amount| make_transaction(TransactionType::Deposit, 1, tx, Some(amount));
+
+    assert!(engine
+        .process_transaction(deposit(1, dec!(100)))
+        .is_applied());
+    assert_eq!(
+        engine.process_transaction(deposit(2, dec!(100.01))),
+        Outcome::Rejected(RejectReason::AmountAboveLimit)
+    );
+    assert_eq!(
+        engine.process_transaction(deposit(3, dec!(1.001))),
+        Outcome::Rejected(RejectReason::TooManyDecimalPlaces)
+    );
+    // Trailing zeros don't count as decimal places
+    assert!(engine
+        .process_transaction(deposit(4, dec!(1.5000)))
+        .is_applied());
+
+    // Loosening the config takes effect for the next transaction
+    engine.set_config(EngineConfig::default());
+    assert!(engine
+        .process_transaction(deposit(5, dec!(500.001)))
+        .is_applied());
+    assert_eq!(engine.get_account(1).unwrap().available, dec!(601.501));
+}
+
+#[test]
+fn test_spilled_deposits_can_still_be_disputed() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut engine = PaymentsEngine::new()
+        .spill_transactions(dir.path().join("spill"), 2)
+        .unwrap();
+
+    for tx in 1..=10 {
+        let deposit = make_transaction(TransactionType::Deposit, 1, tx, Some(dec!(1.5)));
+        assert!(engine.process_transaction(deposit).is_applied());
+    }
+
+    // Deposit 1 was spilled long ago and is loaded back for the dispute
+    let dispute = make_transaction(TransactionType::Dispute, 1, 1, None);
+    assert!(engine.process_transaction(dispute).is_applied());
+    assert_eq!(engine.open_disputes().count(), 1);
+
+    // Disputed deposits stay in memory while new deposits push others out
+    for tx in 11..=20 {
+        let deposit = make_transaction(TransactionType::Deposit, 1, tx, Some(dec!(1.5)));
+        assert!(engine.process_transaction(deposit).is_applied());
+    }
+    let chargeback = make_transaction(TransactionType::Chargeback, 1, 1, None);
+    assert!(engine.process_transaction(chargeback).is_applied());
+
+    let account = engine.get_account(1).unwrap();
+    assert_eq!(account.available, dec!(28.5));
+    assert_eq!(account.held, dec!(0));
+    assert!(account.locked);
+
+    // Client checks still apply to spilled deposits
+    let dispute = make_transaction(TransactionType::Dispute, 2, 5, None);
+    assert_eq!(
+        engine.process_transaction(dispute),
+        Outcome::Rejected(RejectReason::ClientMismatch)
+    );
+
+    // State still carries every deposit, in ID order
+    let state = engine.to_state();
+    let ids: Vec<TxId> = state
+        .disputable_transactions
+        .iter()
+        .map(|t| t.tx_id)
+        .collect();
+    assert_eq!(ids, (1..=20).collect::<Vec<_>>());
+    assert!(engine.check_invariants().is_ok());
+}
+
+#[test]
+fn test_timestamps_survive_spilling() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut engine = PaymentsEngine::new()
+        .spill_transactions(dir.path().join("spill"), 2)
+        .unwrap();
+
+    for tx in 1..=5u16 {
+        let deposit = Transaction {
+            timestamp: (tx % 2 == 1).then_some(1_700_000_000 + u64::from(tx)),
+            ..make_transaction(TransactionType::Deposit, 1, TxId::from(tx), Some(dec!(1)))
+        };
+        engine.process_transaction(deposit);
+    }
+    for tx in [1, 2] {
+        engine.process_transaction(make_transaction(TransactionType::Dispute, 1, tx, None));
+    }
+
+    let mut timestamps: Vec<_> = engine
+        .open_disputes()
+        .map(|stored_tx| (stored_tx.tx_id, stored_tx.timestamp))
+        .collect();
+    timestamps.sort_unstable();
+    assert_eq!(timestamps, [(1, Some(1_700_000_001)), (2, None)]);
+}
+
+#[test]
+fn test_retention_evicts_old_deposits() {
+    let mut engine = PaymentsEngine::with_config(EngineConfig {
+        retention: RetentionPolicy {
+            max_entries: Some(3),
+            max_age: None,
+        },
+        ..EngineConfig::default()
+    });
+    let deposit = |tx| make_transaction(TransactionType::Deposit, 1, tx, Some(dec!(10)));
+    let dispute = |tx| make_transaction(TransactionType::Dispute, 1, tx, None);
+
+    for tx in 1..=3 {
+        assert!(engine.process_transaction(deposit(tx)).is_applied());
+    }
+    assert!(engine.process_transaction(dispute(1)).is_applied());
+
+    // Deposit 1 is under dispute, so deposit 2 is dropped in its place
+    assert!(engine.process_transaction(deposit(4)).is_applied());
+    assert_eq!(
+        engine.process_transaction(dispute(2)),
+        Outcome::Rejected(RejectReason::TransactionExpired)
+    );
+    assert_eq!(
+        engine.process_transaction(dispute(99)),
+        Outcome::Rejected(RejectReason::TransactionNotFound)
+    );
+
+    // Deposit 1 was kept with a fresh retention period, so deposit 3 goes next
+    let resolve = make_transaction(TransactionType::Resolve, 1, 1, None);
+    assert!(engine.process_transaction(resolve).is_applied());
+    assert!(engine.process_transaction(deposit(5)).is_applied());
+    assert_eq!(
+        engine.process_transaction(dispute(3)),
+        Outcome::Rejected(RejectReason::TransactionExpired)
+    );
+    assert!(engine.process_transaction(dispute(1)).is_applied());
+    assert_eq!(engine.to_state().disputable_transactions.len(), 3);
+    assert!(engine.check_invariants().is_ok());
+}
+
+#[test]
+fn test_retention_max_age_counts_applied_transactions() {
+    let mut engine = PaymentsEngine::with_config(EngineConfig {
+        retention: RetentionPolicy {
+            max_entries: None,
+            max_age: Some(2),
+        },
+        ..EngineConfig::default()
+    });
+    let deposit = |tx| make_transaction(TransactionType::Deposit, 1, tx, Some(dec!(10)));
+    let dispute = |tx| make_transaction(TransactionType::Dispute, 1, tx, None);
+
+    assert!(engine.process_transaction(deposit(1)).is_applied());
+    assert!(engine.process_transaction(deposit(2)).is_applied());
+    // Rejected transactions don't advance the clock
+    assert!(!engine.process_transaction(deposit(2)).is_applied());
+    assert!(engine.process_transaction(deposit(3)).is_applied());
+    assert!(engine.process_transaction(deposit(4)).is_applied());
+
+    assert_eq!(
+        engine.process_transaction(dispute(1)),
+        Outcome::Rejected(RejectReason::TransactionExpired)
+    );
+    assert!(engine.process_transaction(dispute(2)).is_applied());
+    assert_eq!(engine.get_account(1).unwrap().held, dec!(10));
+}
+
+#[test]
+fn test_evicted_deposits_are_archived() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("archive");
+    let mut engine = PaymentsEngine::with_config(EngineConfig {
+        retention: RetentionPolicy {
+            max_entries: Some(2),
+            max_age: None,
+        },
+        ..EngineConfig::default()
+    })
+    .spill_transactions(dir.path().join("spill"), 1)
+    .unwrap();
+    engine.set_archive(FileArchive::open(&path).unwrap());
+    let deposit = |tx, amount| make_transaction(TransactionType::Deposit, 1, tx, Some(amount));
+
+    assert!(engine
+        .process_transaction(deposit(1, dec!(10)))
+        .is_applied());
+    assert!(engine
+        .process_transaction(deposit(2, dec!(20)))
+        .is_applied());
+    let dispute = make_transaction(TransactionType::Dispute, 1, 2, None);
+    assert!(engine.process_transaction(dispute).is_applied());
+    assert!(engine.archived_transaction(1).unwrap().is_none());
+
+    // Deposit 1 was spilled, deposit 2 is under dispute
+    assert!(engine
+        .process_transaction(deposit(3, dec!(30)))
+        .is_applied());
+    let archived = engine.archived_transaction(1).unwrap().unwrap();
+    assert_eq!((archived.client_id, archived.amount), (1, dec!(10)));
+    assert!(engine.archived_transaction(2).unwrap().is_none());
+    assert_eq!(
+        engine.process_transaction(make_transaction(TransactionType::Dispute, 1, 1, None)),
+        Outcome::Rejected(RejectReason::TransactionExpired)
+    );
+    engine.sync_archive().unwrap();
+
+    // The archive outlives the engine
+    let archive = FileArchive::open(&path).unwrap();
+    let reopened = archive.fetch(1).unwrap().unwrap();
+    assert_eq!((reopened.client_id, reopened.amount), (1, dec!(10)));
+    assert_eq!(reopened.tx_type, archived.tx_type);
+    assert!(archive.fetch(3).unwrap().is_none());
+    assert!(archive.fetch(1_000_000).unwrap().is_none());
+}
+
+#[test]
+fn test_stored_deposits_stay_reachable_after_evictions() {
+    let mut engine = PaymentsEngine::with_config(EngineConfig {
+        retention: RetentionPolicy {
+            max_entries: Some(50),
+            max_age: None,
+        },
+        ..EngineConfig::default()
+    });
+
+    // Disputing every third deposit pins it while its neighbours are evicted
+    // around it, so stored entries keep changing places
+    for tx in 1..=200 {
+        let client = (tx % 4) as ClientId;
+        let deposit = make_transaction(TransactionType::Deposit, client, tx, Some(dec!(1)));
+        assert!(engine.process_transaction(deposit).is_applied());
+        if tx % 3 == 0 && tx > 150 {
+            let dispute = make_transaction(TransactionType::Dispute, client, tx, None);
+            assert!(engine.process_transaction(dispute).is_applied());
+        }
+    }
+
+    let mut disputed: Vec<TxId> = engine.open_disputes().map(|t| t.tx_id).collect();
+    disputed.sort_unstable();
+    assert_eq!(
+        disputed,
+        (151..=200).filter(|tx| tx % 3 == 0).collect::<Vec<_>>()
+    );
+
+    for tx in disputed {
+        let resolve = make_transaction(TransactionType::Resolve, (tx % 4) as ClientId, tx, None);
+        assert!(engine.process_transaction(resolve).is_applied());
+    }
+    assert_eq!(engine.open_disputes().count(), 0);
+    assert!(engine.check_invariants().is_ok());
+
+    let stored: Vec<TxId> = engine
+        .to_state()
+        .disputable_transactions
+        .iter()
+        .map(|t| t.tx_id)
+        .collect();
+    assert_eq!(stored.len(), 50);
+    assert!(stored.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn test_memory_footprint_tracks_state_and_spilling() {
+    let deposits = |engine: &mut PaymentsEngine| {
+        for tx in 1..=5_000 {
+            let client = (tx % 50) as ClientId;
+            let deposit = make_transaction(TransactionType::Deposit, client, tx, Some(dec!(1.5)));
+            assert!(engine.process_transaction(deposit).is_applied());
+        }
+    };
+
+    assert_eq!(PaymentsEngine::new().memory_footprint().total(), 0);
+
+    let mut in_memory = PaymentsEngine::new();
+    deposits(&mut in_memory);
+    let footprint = in_memory.memory_footprint();
+    assert!(footprint.accounts >= 50 * std::mem::size_of::<payments_engine::models::Account>());
+    // Each stored deposit costs at least its own size
+    assert!(footprint.stored_transactions > 5_000 * 16);
+    assert!(footprint.stored_transactions > footprint.processed_tx_ids);
+    assert_eq!(footprint.history, 0);
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut spilling = PaymentsEngine::new()
+        .retain_history()
+        .spill_transactions(dir.path().join("spill"), 100)
+        .unwrap();
+    deposits(&mut spilling);
+    let spilled = spilling.memory_footprint();
+    assert!(spilled.stored_transactions < footprint.stored_transactions / 2);
+    assert_eq!(spilled.accounts, footprint.accounts);
+    assert!(spilled.history > 0);
+}
+
+#[test]
+fn test_with_capacity_preallocates_without_changing_behavior() {
+    let mut engine = PaymentsEngine::with_capacity(100, 10_000);
+    let reserved = engine.memory_footprint();
+    assert!(reserved.accounts > 0);
+    assert!(reserved.stored_transactions > 10_000 * 16);
+
+    for tx in 1..=1_000 {
+        let client = (tx % 100) as ClientId;
+        let deposit = make_transaction(TransactionType::Deposit, client, tx, Some(dec!(2)));
+        assert!(engine.process_transaction(deposit).is_applied());
+    }
+    // Nothing grew: everything fit in the reserved space
+    let filled = engine.memory_footprint();
+    assert_eq!(filled.accounts, reserved.accounts);
+    assert_eq!(filled.stored_transactions, reserved.stored_transactions);
+    assert_eq!(engine.get_account(7).unwrap().available, dec!(20));
+
+    // Spilling engines only reserve up to their in-memory limit
+    let dir = tempfile::tempdir().unwrap();
+    let mut spilling = PaymentsEngine::new()
+        .spill_transactions(dir.path().join("spill"), 10)
+        .unwrap();
+    spilling.reserve(0, 10_000);
+    assert!(spilling.memory_footprint().stored_transactions < 10_000);
+}
+
+#[test]
+fn test_account_version_counts_applied_changes() {
+    let mut engine = PaymentsEngine::new();
+    let version = |engine: &PaymentsEngine| engine.get_account(1).unwrap().version;
+
+    engine.process_transaction(make_transaction(
+        TransactionType::Deposit,
+        1,
+        1,
+        Some(dec!(10)),
+    ));
+    assert_eq!(version(&engine), 1);
+
+    // Rejections leave the version alone
+    engine.process_transaction(make_transaction(
+        TransactionType::Withdrawal,
+        1,
+        2,
+        Some(dec!(50)),
+    ));
+    engine.process_transaction(make_transaction(TransactionType::Resolve, 1, 1, None));
+    assert_eq!(version(&engine), 1);
+
+    for tx_type in [
+        TransactionType::Dispute,
+        TransactionType::Resolve,
+        TransactionType::Dispute,
+        TransactionType::Chargeback,
+    ] {
+        engine.process_transaction(make_transaction(tx_type, 1, 1, None));
+    }
+    assert_eq!(version(&engine), 5);
+
+    // Versions survive a round trip through saved state
+    let restored = PaymentsEngine::from_state(engine.to_state());
+    assert_eq!(restored.account_version(1), 5);
+    assert_eq!(restored.account_version(2), 0);
+}
+
+#[test]
+fn test_process_transaction_if_version() {
+    let mut engine = PaymentsEngine::new();
+    let deposit = |tx| make_transaction(TransactionType::Deposit, 1, tx, Some(dec!(1)));
+
+    assert_eq!(
+        engine.process_transaction_if_version(deposit(1), 1),
+        Outcome::Rejected(RejectReason::VersionMismatch)
+    );
+    assert!(engine.get_account(1).is_none());
+
+    assert_eq!(
+        engine.process_transaction_if_version(deposit(1), 0),
+        Outcome::Applied
+    );
+    assert_eq!(
+        engine.process_transaction_if_version(deposit(2), 0),
+        Outcome::Rejected(RejectReason::VersionMismatch)
+    );
+    assert_eq!(
+        engine.process_transaction_if_version(deposit(2), 1),
+        Outcome::Applied
+    );
+    assert_eq!(engine.get_account(1).unwrap().available, dec!(2));
+}
+
+#[test]
+fn test_simulate_predicts_without_changing_anything() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut engine = PaymentsEngine::new()
+        .spill_transactions(dir.path().join("spill"), 4)
+        .unwrap();
+    for tx in 1..=10 {
+        let deposit = make_transaction(TransactionType::Deposit, 1, tx, Some(dec!(10)));
+        engine.process_transaction(deposit);
+    }
+
+    let steps = [
+        make_transaction(TransactionType::Withdrawal, 1, 11, Some(dec!(500))),
+        make_transaction(TransactionType::Withdrawal, 1, 11, Some(dec!(30))),
+        make_transaction(TransactionType::Deposit, 1, 11, Some(dec!(1))),
+        make_transaction(TransactionType::Deposit, 2, 12, Some(dec!(0))),
+        make_transaction(TransactionType::Deposit, 2, 12, Some(dec!(7))),
+        // Deposit 1 was spilled to disk long ago
+        make_transaction(TransactionType::Dispute, 2, 1, None),
+        make_transaction(TransactionType::Dispute, 1, 1, None),
+        make_transaction(TransactionType::Dispute, 1, 1, None),
+        make_transaction(TransactionType::Resolve, 1, 99, None),
+        make_transaction(TransactionType::Chargeback, 1, 1, None),
+        make_transaction(TransactionType::Deposit, 1, 13, Some(dec!(1))),
+        make_transaction(TransactionType::Withdrawal, 3, 14, Some(dec!(1))),
+    ];
+    for tx in steps {
+        let state = |engine: &PaymentsEngine| serde_json::to_value(engine.to_state()).unwrap();
+        let before = state(&engine);
+        let simulation = engine.simulate(tx.clone());
+        assert_eq!(state(&engine), before, "{:?} changed the engine", tx);
+
+        let outcome = engine.process_transaction(tx.clone());
+        assert_eq!(simulation.outcome, outcome, "{:?}", tx);
+        let account = engine.get_account(tx.client);
+        match (&simulation.account, account) {
+            (Some(simulated), Some(account)) => {
+                assert_eq!(simulated.available, account.available, "{:?}", tx);
+                assert_eq!(simulated.held, account.held, "{:?}", tx);
+                assert_eq!(simulated.locked, account.locked, "{:?}", tx);
+                assert_eq!(simulated.version, account.version, "{:?}", tx);
+            }
+            (None, None) => {}
+            _ => panic!("{:?} simulated account {:?}", tx, simulation.account),
+        }
+    }
+    assert!(engine.get_account(1).unwrap().locked);
+    assert!(engine.get_account(3).is_none());
+}
+
+#[test]
+fn test_hooks_veto_and_observe_transactions() {
+    let committed = Arc::new(Mutex::new(Vec::new()));
+    let seen = committed.clone();
+    let mut engine = PaymentsEngine::new();
+    engine.set_hooks(
+        Hooks::new()
+            .pre_commit(|tx, account| match account {
+                // Nothing above 50 may leave an account in one go
+                Some(_)
+                    if tx.tx_type == TransactionType::Withdrawal && tx.amount > Some(dec!(50)) =>
+                {
+                    Err(RejectReason::Vetoed)
+                }
+                _ => Ok(()),
+            })
+            .pre_commit(|tx, _| {
+                if tx.client == 9 {
+                    Err(RejectReason::AccountLocked)
+                } else {
+                    Ok(())
+                }
+            })
+            .post_commit(move |tx, account| {
+                seen.lock()
+                    .unwrap()
+                    .push((tx.tx, account.available, account.held));
+            }),
+    );
+
+    let deposit = make_transaction(TransactionType::Deposit, 1, 1, Some(dec!(100)));
+    assert!(engine.process_transaction(deposit).is_applied());
+    let large = make_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(60)));
+    assert_eq!(
+        engine.process_transaction(large.clone()),
+        Outcome::Rejected(RejectReason::Vetoed)
+    );
+    assert_eq!(
+        engine.simulate(large).outcome,
+        Outcome::Rejected(RejectReason::Vetoed)
+    );
+    // The vetoed withdrawal's ID is still free
+    let small = make_transaction(TransactionType::Withdrawal, 1, 2, Some(dec!(40)));
+    assert!(engine.process_transaction(small).is_applied());
+    let deposit = make_transaction(TransactionType::Deposit, 1, 4, Some(dec!(30)));
+    assert!(engine.process_transaction(deposit).is_applied());
+    let dispute = make_transaction(TransactionType::Dispute, 1, 4, None);
+    assert!(engine.process_transaction(dispute).is_applied());
+    // Checks of the engine's own come before the hooks
+    let duplicate = make_transaction(TransactionType::Deposit, 9, 1, Some(dec!(1)));
+    assert_eq!(
+        engine.process_transaction(duplicate),
+        Outcome::Rejected(RejectReason::DuplicateTransaction)
+    );
+    let vetoed = make_transaction(TransactionType::Deposit, 9, 3, Some(dec!(1)));
+    assert_eq!(
+        engine.process_transaction(vetoed),
+        Outcome::Rejected(RejectReason::AccountLocked)
+    );
+    assert!(engine.get_account(9).is_none());
+
+    assert_eq!(
+        *committed.lock().unwrap(),
+        vec![
+            (1, dec!(100), dec!(0)),
+            (2, dec!(60), dec!(0)),
+            (4, dec!(90), dec!(0)),
+            (4, dec!(60), dec!(30)),
+        ]
+    );
+}
+
+#[test]
+fn test_erase_client_leaves_tombstone() {
+    let mut engine = PaymentsEngine::new();
+    let deposit =
+        |client, tx, amount| make_transaction(TransactionType::Deposit, client, tx, Some(amount));
+    engine.process_transaction(deposit(1, 1, dec!(10)));
+    engine.process_transaction(deposit(2, 2, dec!(4)));
+
+    assert_eq!(engine.erase_client(1), Outcome::Applied);
+    assert!(engine.get_account(1).is_none());
+    let tombstones: Vec<_> = engine.erased_clients().collect();
+    assert_eq!(tombstones.len(), 1);
+    assert_eq!((tombstones[0].client, tombstones[0].total()), (1, dec!(10)));
+
+    // The client takes nothing more, and its deposits can't be disputed
+    assert_eq!(
+        engine.process_transaction(deposit(1, 3, dec!(1))),
+        Outcome::Rejected(RejectReason::ClientErased)
+    );
+    assert_eq!(
+        engine.process_transaction(make_transaction(TransactionType::Dispute, 2, 1, None)),
+        Outcome::Rejected(RejectReason::TransactionNotFound)
+    );
+    // Its transaction IDs still count as processed
+    assert_eq!(
+        engine.process_transaction(deposit(2, 1, dec!(1))),
+        Outcome::Rejected(RejectReason::DuplicateTransaction)
+    );
+    assert_eq!(
+        engine.erase_client(1),
+        Outcome::Rejected(RejectReason::ClientErased)
+    );
+    assert_eq!(
+        engine.erase_client(3),
+        Outcome::Rejected(RejectReason::AccountNotFound)
+    );
+}
+
+#[test]
+fn test_erase_client_waits_for_open_disputes() {
+    let mut engine = PaymentsEngine::new();
+    engine.process_transaction(make_transaction(
+        TransactionType::Deposit,
+        1,
+        1,
+        Some(dec!(5)),
+    ));
+    engine.process_transaction(make_transaction(TransactionType::Dispute, 1, 1, None));
+
+    assert_eq!(
+        engine.erase_client(1),
+        Outcome::Rejected(RejectReason::AlreadyDisputed)
+    );
+
+    engine.process_transaction(make_transaction(TransactionType::Resolve, 1, 1, None));
+    assert_eq!(engine.erase_client(1), Outcome::Applied);
+    assert_eq!(engine.stats().open_disputes, 0);
+}
+
+/// Process `tx` and return the event the engine's account change amounts to
+fn apply_and_capture(engine: &mut PaymentsEngine, tx: Transaction) -> AccountEvent {
+    let (tx_id, tx_type, client) = (tx.tx, tx.tx_type, tx.client);
+    assert!(engine.process_transaction(tx).is_applied());
+    AccountEvent {
+        tx: tx_id,
+        tx_type,
+        account: engine.get_account(client).unwrap().clone(),
+    }
+}
+
+#[test]
+fn test_from_events_rebuilds_deposits_and_disputes() {
+    let mut source = PaymentsEngine::new();
+    let deposit =
+        |client, tx, amount| make_transaction(TransactionType::Deposit, client, tx, Some(amount));
+    let events = [
+        apply_and_capture(&mut source, deposit(1, 1, dec!(10))),
+        apply_and_capture(&mut source, deposit(2, 2, dec!(3))),
+        apply_and_capture(
+            &mut source,
+            make_transaction(TransactionType::Withdrawal, 1, 3, Some(dec!(2.5))),
+        ),
+        apply_and_capture(
+            &mut source,
+            make_transaction(TransactionType::Dispute, 2, 2, None),
+        ),
+    ];
+
+    // Events survive a trip through their JSON form
+    let events: Vec<AccountEvent> = events
+        .iter()
+        .map(|event| serde_json::from_str(&serde_json::to_string(event).unwrap()).unwrap())
+        .collect();
+    let mut replica = PaymentsEngine::from_events(events.clone());
+
+    assert_eq!(replica.get_account(1).unwrap().available, dec!(7.5));
+    assert_eq!(replica.get_account(2).unwrap().held, dec!(3));
+    assert_eq!(replica.stats().open_disputes, 1);
+    assert!(replica.check_invariants().is_ok());
+
+    // Replaying the stream again changes nothing
+    for event in events {
+        assert_eq!(
+            replica.apply_event(event),
+            Outcome::Rejected(RejectReason::DuplicateTransaction)
+        );
+    }
+
+    // The rebuilt deposit store and processed IDs work as the source's do
+    assert_eq!(
+        replica.process_transaction(deposit(1, 3, dec!(1))),
+        Outcome::Rejected(RejectReason::DuplicateTransaction)
+    );
+    replica.process_transaction(make_transaction(TransactionType::Chargeback, 2, 2, None));
+    assert!(replica.get_account(2).unwrap().locked);
+}
+
+#[test]
+fn test_apply_event_rejects_gaps() {
+    let mut source = PaymentsEngine::new();
+    let first = apply_and_capture(
+        &mut source,
+        make_transaction(TransactionType::Deposit, 1, 1, Some(dec!(1))),
+    );
+    let second = apply_and_capture(
+        &mut source,
+        make_transaction(TransactionType::Deposit, 1, 2, Some(dec!(1))),
+    );
+
+    let mut replica = PaymentsEngine::new();
+    assert_eq!(
+        replica.apply_event(second.clone()),
+        Outcome::Rejected(RejectReason::VersionMismatch)
+    );
+    assert_eq!(replica.apply_event(first), Outcome::Applied);
+    assert_eq!(replica.apply_event(second), Outcome::Applied);
+    assert_eq!(replica.get_account(1).unwrap().available, dec!(2));
+}
+
+#[test]
+fn test_funds_across_clients() {
+    let mut engine = PaymentsEngine::new();
+    for tx in [
+        make_transaction(TransactionType::Deposit, 1, 1, Some(dec!(100))),
+        make_transaction(TransactionType::Deposit, 1, 2, Some(dec!(30))),
+        make_transaction(TransactionType::Deposit, 2, 3, Some(dec!(20))),
+        make_transaction(TransactionType::Deposit, 3, 4, Some(dec!(7))),
+        make_transaction(TransactionType::Deposit, 2, 6, Some(dec!(5))),
+        make_transaction(TransactionType::Withdrawal, 2, 5, Some(dec!(5))),
+        make_transaction(TransactionType::Dispute, 1, 1, None),
+        make_transaction(TransactionType::Dispute, 1, 2, None),
+        make_transaction(TransactionType::Chargeback, 1, 2, None),
+        make_transaction(TransactionType::Dispute, 2, 3, None),
+    ] {
+        engine.process_transaction(tx);
+    }
+    assert_eq!(engine.erase_client(3), Outcome::Applied);
+
+    let funds = engine.funds();
+    assert_eq!(
+        funds,
+        FundsSummary {
+            available: dec!(7),
+            held: dec!(120),
+            charged_back: dec!(30),
+            open_disputes: 2,
+        }
+    );
+    // What the bank should hold: deposits less withdrawals and chargebacks
+    assert_eq!(funds.total(), dec!(162) - dec!(5) - dec!(30));
+
+    // Chargebacks survive a state round trip and an event replay
+    assert_eq!(
+        PaymentsEngine::from_state(engine.to_state())
+            .funds()
+            .charged_back,
+        dec!(30)
+    );
+    let mut source = PaymentsEngine::new();
+    let mut events = Vec::new();
+    for tx in [
+        make_transaction(TransactionType::Deposit, 1, 1, Some(dec!(4))),
+        make_transaction(TransactionType::Dispute, 1, 1, None),
+        make_transaction(TransactionType::Chargeback, 1, 1, None),
+    ] {
+        events.push(apply_and_capture(&mut source, tx));
+    }
+    assert_eq!(PaymentsEngine::from_events(events).funds(), source.funds());
+}
+
+#[test]
+fn test_holds_track_each_open_dispute() {
+    let mut engine = PaymentsEngine::new();
+    for tx in [
+        make_transaction(TransactionType::Deposit, 1, 1, Some(dec!(10))),
+        make_transaction(TransactionType::Deposit, 1, 2, Some(dec!(5))),
+        make_transaction(TransactionType::Deposit, 1, 3, Some(dec!(2))),
+        make_transaction(TransactionType::Deposit, 2, 4, Some(dec!(7))),
+        make_transaction(TransactionType::Dispute, 2, 4, None),
+        make_transaction(TransactionType::Dispute, 1, 3, None),
+        Transaction {
+            timestamp: Some(1_700_000_000),
+            ..make_transaction(TransactionType::Dispute, 1, 1, None)
+        },
+    ] {
+        engine.process_transaction(tx);
+    }
+
+    let holds = engine.holds(1);
+    assert_eq!(
+        holds
+            .iter()
+            .map(|hold| (hold.tx_id, hold.amount, hold.placed_at))
+            .collect::<Vec<_>>(),
+        [(1, dec!(10), Some(1_700_000_000)), (3, dec!(2), None)]
+    );
+    assert_eq!(engine.get_account(1).unwrap().held, dec!(12));
+
+    // Settling a dispute releases its own hold and leaves the others
+    engine.process_transaction(make_transaction(TransactionType::Resolve, 1, 1, None));
+    engine.process_transaction(make_transaction(TransactionType::Chargeback, 2, 4, None));
+    assert_eq!(engine.holds(1)[0].tx_id, 3);
+    assert_eq!(engine.holds(1).len(), 1);
+    assert!(engine.holds(2).is_empty());
+    assert_eq!(engine.get_account(1).unwrap().held, dec!(2));
+    assert!(engine.check_invariants().is_ok());
+
+    // Holds survive a state round trip, and state saved without them gets
+    // them back from the disputed deposits
+    let state = engine.to_state();
+    assert_eq!(state.holds.len(), 1);
+    assert_eq!(
+        PaymentsEngine::from_state(state.clone()).holds(1),
+        holds[1..]
+    );
+    let mut old_state = state;
+    old_state.holds.clear();
+    assert_eq!(PaymentsEngine::from_state(old_state).holds(1), holds[1..]);
+}
+
+#[test]
+fn test_settling_releases_exactly_the_hold() {
+    let mut engine = PaymentsEngine::new();
+    engine.process_transaction(make_transaction(
+        TransactionType::Deposit,
+        1,
+        1,
+        Some(dec!(10)),
+    ));
+    engine.process_transaction(make_transaction(TransactionType::Dispute, 1, 1, None));
+
+    // A hold for less than the deposit, e.g. from a ledger that held part of it
+    let mut state = engine.to_state();
+    state.holds[0].amount = dec!(4);
+    state.accounts[0].held = dec!(4);
+    state.accounts[0].available = dec!(6);
+    let mut engine = PaymentsEngine::from_state(state);
+    assert!(engine.check_invariants().is_ok());
+
+    let outcome =
+        engine.process_transaction(make_transaction(TransactionType::Chargeback, 1, 1, None));
+    assert_eq!(outcome, Outcome::Applied);
+    let account = engine.get_account(1).unwrap();
+    assert_eq!((account.available, account.held), (dec!(6), dec!(0)));
+    assert_eq!(engine.funds().charged_back, dec!(4));
+}
@@ -0,0 +1,173 @@
+#![cfg(not(feature = "fixed-point"))]
+
+use payments_engine::config::EngineConfig;
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::models::{Metadata, Transaction, TransactionType};
+use payments_engine::outcome::Outcome;
+use payments_engine::validation::{check_header, validate_transaction, RowError, ValidationError};
+use payments_engine::{read_transactions, read_transactions_checked};
+use rust_decimal_macros::dec;
+
+fn transaction(tx_type: TransactionType, amount: Option<rust_decimal::Decimal>) -> Transaction {
+    Transaction {
+        tx_type,
+        client: 1,
+        tx: 1,
+        amount,
+        timestamp: None,
+        metadata: Metadata::new(),
+    }
+}
+
+fn limited() -> EngineConfig {
+    EngineConfig {
+        max_amount: Some(dec!(1000)),
+        max_decimal_places: Some(2),
+        ..EngineConfig::default()
+    }
+}
+
+#[test]
+fn test_validation_reports_every_problem() {
+    let config = limited();
+    let check = |tx_type, amount| validate_transaction(&transaction(tx_type, amount), &config);
+
+    assert_eq!(check(TransactionType::Deposit, Some(dec!(999.99))), Ok(()));
+    assert_eq!(check(TransactionType::Dispute, None), Ok(()));
+    // Trailing zeros don't count as decimal places
+    assert_eq!(
+        check(TransactionType::Withdrawal, Some(dec!(1.500))),
+        Ok(())
+    );
+
+    assert_eq!(
+        check(TransactionType::Withdrawal, None),
+        Err(vec![ValidationError::MissingAmount])
+    );
+    assert_eq!(
+        check(TransactionType::Chargeback, Some(dec!(1))),
+        Err(vec![ValidationError::UnexpectedAmount])
+    );
+    assert_eq!(
+        check(TransactionType::Deposit, Some(dec!(-0.001))),
+        Err(vec![
+            ValidationError::NonPositiveAmount,
+            ValidationError::TooManyDecimalPlaces { max: 2 },
+        ])
+    );
+    assert_eq!(
+        check(TransactionType::Deposit, Some(dec!(1000.01))),
+        Err(vec![ValidationError::AmountAboveLimit { max: dec!(1000) }])
+    );
+
+    // Without limits only the shape and sign matter
+    let unlimited = EngineConfig::default();
+    let huge = transaction(TransactionType::Deposit, Some(dec!(1e20)));
+    assert_eq!(validate_transaction(&huge, &unlimited), Ok(()));
+}
+
+#[test]
+fn test_validation_agrees_with_the_engine() {
+    let config = limited();
+    let amounts = [
+        None,
+        Some(dec!(0)),
+        Some(dec!(-5)),
+        Some(dec!(0.001)),
+        Some(dec!(12.5)),
+        Some(dec!(1000)),
+        Some(dec!(1000.001)),
+        Some(dec!(5000)),
+    ];
+    for tx_type in [TransactionType::Deposit, TransactionType::Withdrawal] {
+        for amount in amounts {
+            let tx = transaction(tx_type, amount);
+            let mut engine = PaymentsEngine::with_config(config.clone());
+            // Funds for the withdrawals, under a different ID
+            engine.process_transaction(Transaction::deposit(1, 2, dec!(1000)));
+
+            let outcome = engine.process_transaction(tx.clone());
+            match validate_transaction(&tx, &config) {
+                Ok(()) => assert!(outcome.is_applied(), "{:?}: {}", tx, outcome),
+                Err(errors) => assert_eq!(
+                    outcome,
+                    Outcome::Rejected(errors[0].reject_reason().unwrap()),
+                    "{:?}",
+                    tx
+                ),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_header_reports_missing_and_unknown_columns() {
+    let report = check_header(["type", "client", "tx", "amount", "timestamp", "merchant"]);
+    assert!(report.is_valid());
+    assert_eq!(report.unknown, ["merchant"]);
+
+    let report = read_transactions_checked("tx,type,cliant,amount,\n".as_bytes())
+        .header()
+        .clone();
+    assert!(!report.is_valid());
+    assert_eq!(report.missing, ["client"]);
+    assert_eq!(report.unknown, ["cliant"]);
+    assert_eq!(
+        report.to_string(),
+        "missing columns: client; unknown columns: cliant"
+    );
+}
+
+#[test]
+fn test_row_errors_locate_the_offending_value() {
+    let input = "type,client,tx,amount,ref\n\
+                 deposit,1,1,10,a\n\
+                 refund,1,2,5,b\n\
+                 deposit,-1,3,5,c\n\
+                 \n\
+                 deposit,1,4,ten,d\n\
+                 deposit,1,5\n\
+                 withdrawal,1,6,2.5,\n";
+    let results: Vec<_> = read_transactions_checked(input.as_bytes()).collect();
+    assert_eq!(results.len(), 6);
+    assert_eq!(results[0].as_ref().unwrap().metadata["ref"], "a");
+    assert!(results[5].is_ok());
+
+    let errors: Vec<RowError> = results.into_iter().filter_map(Result::err).collect();
+    let located: Vec<_> = errors
+        .iter()
+        .map(|e| (e.line, e.column.as_deref(), e.value.as_deref()))
+        .collect();
+    assert_eq!(
+        located,
+        [
+            (3, Some("type"), Some("refund")),
+            (4, Some("client"), Some("-1")),
+            (6, Some("amount"), Some("ten")),
+            (7, None, None),
+        ]
+    );
+    assert!(errors[0].message.contains("unknown variant"));
+    assert_eq!(errors[3].to_string(), "line 7: expected 5 fields, found 3");
+    assert!(errors[2]
+        .to_string()
+        .starts_with("line 6, column amount ('ten'): "));
+}
+
+#[test]
+fn test_checked_and_unchecked_reads_agree() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,1\n\
+                 deposit,x,2,1\n\
+                 dispute,1,1,\n";
+    let checked: Vec<Transaction> = read_transactions_checked(input.as_bytes())
+        .filter_map(Result::ok)
+        .collect();
+    let unchecked: Vec<Transaction> = read_transactions(input.as_bytes()).collect();
+    assert_eq!(checked, unchecked);
+    assert_eq!(checked.len(), 2);
+
+    // A last row without a line break is on its own line all the same
+    let mut transactions = read_transactions_checked("type,client,tx,amount\ndeposit".as_bytes());
+    assert_eq!(transactions.next().unwrap().unwrap_err().line, 2);
+}
@@ -0,0 +1,105 @@
+#![cfg(not(feature = "fixed-point"))]
+
+use payments_engine::process_transactions;
+use payments_engine::validation::RowError;
+use payments_engine::verify::{verify_accounts, AccountsProblem};
+use rust_decimal_macros::dec;
+
+#[test]
+fn test_engine_output_verifies() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 deposit,2,2,4.5\n\
+                 dispute,1,1,\n\
+                 deposit,3,3,1.0\n\
+                 dispute,3,3,\n\
+                 chargeback,3,3,\n";
+    let mut output = Vec::new();
+    process_transactions(input.as_bytes(), &mut output).unwrap();
+
+    let report = verify_accounts(&output[..]).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    assert_eq!(report.accounts_checked, 3);
+}
+
+#[test]
+fn test_every_problem_is_reported_with_its_line() {
+    let accounts = "client,available,held,total,locked\n\
+                    1, 10.0, 0.0, 10.0, false\n\
+                    2,5.0,1.0,5.0,false\n\
+                    3,-1.0,0,-1.0,true\n\
+                    1,0,0,0,false\n\
+                    4,abc,0,0,false\n\
+                    5,1,1\n";
+    let report = verify_accounts(accounts.as_bytes()).unwrap();
+    assert_eq!(report.accounts_checked, 4);
+    assert!(report.is_malformed());
+    assert_eq!(report.problems.len(), 6);
+    assert_eq!(
+        report.problems[..4],
+        [
+            AccountsProblem::TotalMismatch {
+                line: 3,
+                client_id: 2,
+                available: dec!(5.0),
+                held: dec!(1.0),
+                total: dec!(5.0),
+            },
+            AccountsProblem::Negative {
+                line: 4,
+                client_id: 3,
+                column: "available",
+                value: dec!(-1.0),
+            },
+            AccountsProblem::Negative {
+                line: 4,
+                client_id: 3,
+                column: "total",
+                value: dec!(-1.0),
+            },
+            AccountsProblem::DuplicateClient {
+                line: 5,
+                client_id: 1,
+                first_line: 2,
+            },
+        ]
+    );
+    assert!(matches!(
+        &report.problems[4],
+        AccountsProblem::Malformed(RowError { line: 6, column: Some(column), value: Some(value), .. })
+            if column == "available" && value == "abc"
+    ));
+    assert_eq!(
+        report.problems[5],
+        AccountsProblem::Malformed(RowError {
+            line: 7,
+            column: None,
+            value: None,
+            message: "expected 5 fields, found 3".to_string(),
+        })
+    );
+    assert_eq!(
+        report.problems[0].to_string(),
+        "line 3: client 2: total 5.0 is not available 5.0 + held 1.0"
+    );
+    assert_eq!(
+        report.problems[3].to_string(),
+        "line 5: client 1 already listed on line 2"
+    );
+}
+
+#[test]
+fn test_wrong_header_stops_the_check() {
+    let report = verify_accounts("client,available,total\n1,1,1\n".as_bytes()).unwrap();
+    assert_eq!(report.accounts_checked, 0);
+    assert_eq!(
+        report.problems,
+        [AccountsProblem::Header {
+            found: "client,available,total".to_string()
+        }]
+    );
+    assert_eq!(
+        report.problems[0].to_string(),
+        "header is 'client,available,total', not 'client,available,held,total,locked'"
+    );
+}
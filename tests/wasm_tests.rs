@@ -0,0 +1,52 @@
+#![cfg(all(feature = "wasm", not(feature = "fixed-point")))]
+
+// Run on the host with `cargo test --features wasm`; only the success paths
+// are exercised since building a `JsError` needs a JavaScript host.
+
+use payments_engine::process_transactions;
+use payments_engine::wasm::{process_csv, WasmEngine};
+
+#[test]
+fn test_process_csv_matches_batch_processing() {
+    let input = "type,client,tx,amount\n\
+                 deposit,1,1,10.0\n\
+                 deposit,2,2,3.0\n\
+                 withdrawal,1,3,4.5\n\
+                 dispute,2,2,\n";
+
+    let mut expected = Vec::new();
+    process_transactions(input.as_bytes(), &mut expected).unwrap();
+
+    let output = process_csv(input).unwrap();
+    assert_eq!(output, String::from_utf8(expected).unwrap());
+    assert!(output.contains("1,5.5,0,5.5,false"));
+    assert!(output.contains("2,0.0,3.0,3.0,false"));
+}
+
+#[test]
+fn test_push_transaction_reports_outcomes() {
+    let mut engine = WasmEngine::new();
+
+    let outcome = engine
+        .push_transaction(r#"{"type":"deposit","client":1,"tx":1,"amount":"5.0"}"#)
+        .unwrap();
+    assert_eq!(outcome, "applied");
+
+    let outcome = engine
+        .push_transaction(r#"{"type":"withdrawal","client":1,"tx":2,"amount":"9.0"}"#)
+        .unwrap();
+    assert_eq!(outcome, "rejected: insufficient available funds");
+
+    let outcome = engine
+        .push_transaction(r#"{"type":"dispute","client":1,"tx":1}"#)
+        .unwrap();
+    assert_eq!(outcome, "applied");
+
+    let account = engine.account(1).unwrap().unwrap();
+    assert!(account.contains(r#""held":"5.0""#));
+    assert_eq!(engine.account(2).unwrap(), None);
+    assert_eq!(
+        engine.accounts_csv().unwrap(),
+        "client,available,held,total,locked\n1,0.0,5.0,5.0,false\n"
+    );
+}
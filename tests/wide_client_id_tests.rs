@@ -0,0 +1,109 @@
+#![cfg(feature = "wide-client-ids")]
+
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::models::{ClientId, Transaction};
+use payments_engine::outcome::Outcome;
+use payments_engine::state::EngineState;
+use payments_engine::{apply_transactions, process_transactions};
+
+/// Client IDs from a user table keyed by 64-bit IDs
+const ALICE: ClientId = 4_398_046_511_104;
+const BOB: ClientId = 4_398_046_511_105;
+
+fn amount(text: &str) -> payments_engine::amount::Amount {
+    text.parse().unwrap()
+}
+
+#[test]
+fn test_csv_with_clients_past_u16() {
+    let input = format!(
+        "type,client,tx,amount\n\
+         deposit,70000,1,10.0\n\
+         deposit,{ALICE},2,5.0\n\
+         withdrawal,{ALICE},3,2.0\n\
+         dispute,70000,1,\n\
+         deposit,{},4,1.0\n",
+        ClientId::MAX
+    );
+    let mut output = Vec::new();
+    process_transactions(input.as_bytes(), &mut output).unwrap();
+    let mut lines: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().collect();
+    lines.sort_unstable();
+    assert_eq!(
+        lines,
+        [
+            "18446744073709551615,1.0,0,1.0,false",
+            "4398046511104,3.0,0,3.0,false",
+            "70000,0.0,10.0,10.0,false",
+            "client,available,held,total,locked",
+        ]
+    );
+}
+
+#[test]
+fn test_state_round_trips_wide_clients() {
+    let mut engine = PaymentsEngine::new();
+    apply_transactions(
+        &mut engine,
+        format!(
+            "type,client,tx,amount\n\
+             deposit,{ALICE},1,4.0\n\
+             deposit,{BOB},2,1.0\n\
+             dispute,{ALICE},1,\n"
+        )
+        .as_bytes(),
+    );
+    assert_eq!(engine.holds(ALICE)[0].client_id, ALICE);
+
+    let snapshot = engine.export_snapshot().unwrap();
+    let restored = PaymentsEngine::import_snapshot(&snapshot).unwrap();
+    assert_eq!(restored.get_account(BOB).unwrap().available, amount("1"));
+
+    let mut json = Vec::new();
+    engine.to_state().write_to(&mut json).unwrap();
+    let mut restored = PaymentsEngine::from_state(EngineState::read_from(&json[..]).unwrap());
+    assert_eq!(
+        restored.process_transaction(Transaction::resolve(ALICE, 1)),
+        Outcome::Applied
+    );
+    assert_eq!(restored.get_account(ALICE).unwrap().available, amount("4"));
+}
+
+#[test]
+fn test_spilled_deposits_keep_wide_clients() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut engine = PaymentsEngine::new()
+        .spill_transactions(dir.path().join("spill"), 1)
+        .unwrap();
+    for (client, tx) in [(ALICE, 1), (BOB, 2), (ClientId::MAX, 3)] {
+        engine.process_transaction(Transaction::deposit(client, tx, amount("1")));
+    }
+    assert_eq!(
+        engine.process_transaction(Transaction::dispute(ALICE, 1)),
+        Outcome::Applied
+    );
+    assert_eq!(engine.get_account(ALICE).unwrap().held, amount("1"));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_accounts_view_with_sparse_clients() {
+    use payments_engine::concurrent_engine::ShardedEngine;
+
+    let engine = ShardedEngine::new(3);
+    let clients = [ClientId::MAX, 7, ALICE, 70_000, BOB, 1 << 40];
+    for (tx, client) in (1..).zip(clients) {
+        engine
+            .process_transaction(Transaction::deposit(client, tx, amount("1")))
+            .await
+            .unwrap();
+    }
+
+    let view = engine.accounts_view().await;
+    let ids: Vec<ClientId> = view.iter().map(|account| account.client_id).collect();
+    let mut sorted = clients;
+    sorted.sort_unstable();
+    assert_eq!(ids, sorted);
+    assert_eq!(view.get(BOB).unwrap().available, amount("1"));
+    assert!(view.get(BOB + 1).is_none());
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "wide-tx-ids")]
+
+use payments_engine::engine::PaymentsEngine;
+use payments_engine::models::{Transaction, TxId};
+use payments_engine::outcome::{Outcome, RejectReason};
+use payments_engine::state::EngineState;
+use payments_engine::{apply_transactions, process_transactions};
+
+/// Snowflake-style IDs: a millisecond timestamp shifted past 32 bits
+const FIRST: TxId = 1_794_558_190_387_200_001;
+const SECOND: TxId = 1_794_558_190_387_200_002;
+
+fn amount(text: &str) -> payments_engine::amount::Amount {
+    text.parse().unwrap()
+}
+
+#[test]
+fn test_csv_with_snowflake_ids() {
+    let input = format!(
+        "type,client,tx,amount\n\
+         deposit,1,{FIRST},10.0\n\
+         deposit,1,{SECOND},5.0\n\
+         deposit,1,{FIRST},99.0\n\
+         dispute,1,{SECOND},\n\
+         chargeback,1,{SECOND},\n"
+    );
+    let mut output = Vec::new();
+    process_transactions(input.as_bytes(), &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "client,available,held,total,locked\n1,10.0,0.0,10.0,true\n"
+    );
+}
+
+#[test]
+fn test_ids_differing_above_32_bits_are_distinct() {
+    let mut engine = PaymentsEngine::new();
+    let low = 7;
+    let high = (1 << 32) + 7;
+    for tx in [low, high, TxId::MAX] {
+        let outcome = engine.process_transaction(Transaction::deposit(1, tx, amount("1")));
+        assert_eq!(outcome, Outcome::Applied);
+    }
+    assert_eq!(
+        engine.process_transaction(Transaction::withdrawal(1, high, amount("1"))),
+        Outcome::Rejected(RejectReason::DuplicateTransaction)
+    );
+    assert_eq!(
+        engine.process_transaction(Transaction::dispute(1, (2 << 32) + 7)),
+        Outcome::Rejected(RejectReason::TransactionNotFound)
+    );
+    assert_eq!(engine.get_account(1).unwrap().available, amount("3"));
+}
+
+#[test]
+fn test_state_round_trips_wide_ids() {
+    let mut engine = PaymentsEngine::new();
+    apply_transactions(
+        &mut engine,
+        format!(
+            "type,client,tx,amount\n\
+             deposit,2,{FIRST},4.0\n\
+             dispute,2,{FIRST},\n"
+        )
+        .as_bytes(),
+    );
+    assert_eq!(engine.holds(2)[0].tx_id, FIRST);
+
+    let snapshot = engine.export_snapshot().unwrap();
+    let restored = PaymentsEngine::import_snapshot(&snapshot).unwrap();
+    assert_eq!(restored.to_state().processed_tx_ids, [FIRST]);
+
+    let mut json = Vec::new();
+    engine.to_state().write_to(&mut json).unwrap();
+    let mut restored = PaymentsEngine::from_state(EngineState::read_from(&json[..]).unwrap());
+    assert_eq!(
+        restored.process_transaction(Transaction::resolve(2, FIRST)),
+        Outcome::Applied
+    );
+    assert_eq!(restored.get_account(2).unwrap().available, amount("4"));
+}
+
+#[test]
+fn test_spill_file_keeps_ids_it_cannot_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut engine = PaymentsEngine::new()
+        .spill_transactions(dir.path().join("spill"), 1)
+        .unwrap();
+    // Small IDs spill as usual; huge ones can't be placed and stay in memory
+    for tx in [1, 2, TxId::MAX - 1, TxId::MAX] {
+        engine.process_transaction(Transaction::deposit(1, tx, amount("1")));
+    }
+    for tx in [1, TxId::MAX - 1] {
+        assert_eq!(
+            engine.process_transaction(Transaction::dispute(1, tx)),
+            Outcome::Applied
+        );
+    }
+    assert_eq!(engine.get_account(1).unwrap().held, amount("2"));
+}